@@ -0,0 +1,71 @@
+// Embed build metadata for the `version` command. Everything here runs on the
+// build host, so std is available.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let commit = git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| String::from("unknown"));
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|status| !status.is_empty());
+    println!("cargo:rustc-env=UEFIPXE_GIT_COMMIT={}{}", commit, if dirty { "-dirty" } else { "" });
+
+    // SOURCE_DATE_EPOCH keeps reproducible builds byte-identical
+    let epoch = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+    println!("cargo:rustc-env=UEFIPXE_BUILD_DATE={}", format_utc(epoch));
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|name| name.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+    let features = if features.is_empty() { String::from("none") } else { features.join(",") };
+    println!("cargo:rustc-env=UEFIPXE_FEATURES={}", features);
+
+    println!("cargo:rustc-env=UEFIPXE_TARGET={}", env::var("TARGET").unwrap_or_default());
+    println!("cargo:rustc-env=UEFIPXE_PROFILE={}", env::var("PROFILE").unwrap_or_default());
+
+    // Run again when the checkout moves to another commit or gets edited;
+    // a missing path would make cargo rerun this on every build
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        for file in ["HEAD", "index", "packed-refs", "refs"] {
+            let path = Path::new(&git_dir).join(file);
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
+
+/// Output of a git command, trimmed; None outside a checkout
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `YYYY-MM-DD HH:MM UTC` for seconds since the Unix epoch
+fn format_utc(epoch: u64) -> String {
+    let days = (epoch / 86400) as i64;
+    let minutes = (epoch % 86400) / 60;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minutes / 60, minutes % 60)
+}
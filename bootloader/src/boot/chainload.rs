@@ -1,50 +1,186 @@
-use crate::util::{Error, Result};
+use crate::boot::initrd::Initrd;
+use crate::boot::secure::{self, Assessment};
+use crate::util::metrics::{Phase, Timer};
+use crate::util::{ucs2, Error, Result};
+use crate::{detail, say};
+use alloc::format;
+use alloc::string::String;
 use uefi::boot;
-use uefi::println;
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::{println, CString16, Handle};
+
+/// Watchdog code for timers we arm (0 to 0xFFFF are the firmware's)
+const WATCHDOG_CODE: u64 = 0x1_0000;
+
+/// What an image is started with besides its own data
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImageArgs<'a> {
+    /// LoadOptions text, e.g. a Linux kernel command line
+    pub cmdline: Option<&'a str>,
+    /// Initrd for a Linux EFI stub kernel
+    pub initrd: Option<&'a [u8]>,
+}
 
 /// Chainload image directly from memory buffer
 ///
 /// This is simpler than writing to a file and loading from disk.
-/// UEFI LoadImage supports loading directly from memory.
-pub fn chainload_image(image_data: &[u8]) -> Result<()> {
-    println!("Preparing to chainload image ({} bytes)...", image_data.len());
+/// UEFI LoadImage supports loading directly from memory. `source` is the
+/// device path the image was read from, when it came from a disk the image
+/// needs to find again (an iSCSI loader looking for its config).
+pub fn chainload_image(image_data: &[u8], source: Option<&DevicePath>, args: &ImageArgs) -> Result<()> {
+    say!("Preparing to chainload image ({} bytes)...", image_data.len());
+
+    // Nothing of our network setup may outlive us into the next stage
+    crate::network::manager::teardown();
+    crate::storage::secrets::clear();
+    if crate::storage::with_config(|config| config.reconnect_nic).unwrap_or(false) {
+        reconnect_nics();
+    }
+
+    // The menu the image was picked from may have been edited since startup
+    if let Some(Err(e)) = crate::storage::with_config(crate::storage::integrity::measure) {
+        println!("  Warning: Could not measure configuration: {}", e);
+    }
+
+    // With Secure Boot on, an unsigned image would only fail with a bare
+    // SECURITY_VIOLATION from LoadImage
+    let assessment = Assessment::of(image_data);
+    if assessment.enforced {
+        say!("  Secure Boot: on, {}", assessment.summary());
+    }
 
     // Load the image directly from memory buffer
-    println!("  Loading image from memory...");
-    let image_handle = unsafe {
+    detail!("  Loading image from memory...");
+    let timer = Timer::start();
+    let loaded = unsafe {
         boot::load_image(
             boot::image_handle(),
             boot::LoadImageSource::FromBuffer {
                 buffer: image_data,
-                file_path: None,
+                file_path: source,
             },
         )
-        .map_err(|e| {
+    };
+    let shim = config.and_then(|config| config.shim.as_deref());
+    let (image_handle, staged) = match loaded {
+        Ok(image_handle) => (image_handle, None),
+        Err(e) if assessment.enforced && secure::refused(e.status()) => {
+            println!("    Secure Boot refused the image: {:?}", e.status());
+            let Some(shim) = shim else {
+                println!("    {}", assessment.advice(false));
+                return Err(Error::SecurityViolation);
+            };
+            say!("  Starting it through shim ({})", shim);
+            let (image_handle, staged) = secure::load_via_shim(shim, image_data).map_err(|e| {
+                println!("    {}", assessment.advice(true));
+                e
+            })?;
+            (image_handle, Some(staged))
+        }
+        Err(e) => {
             println!("    Failed to load image: {:?}", e.status());
-            Error::Uefi(e.status())
-        })?
+            return Err(Error::Uefi(e.status()));
+        }
     };
 
-    println!("  Image loaded successfully");
-    println!();
+    timer.stop(Phase::Load);
+    detail!("  Image loaded successfully");
+
+    // Shim starts the file named first in its options, with the rest as
+    // that image's own options
+    let shim_options = staged.as_ref().map(|staged| match args.cmdline {
+        Some(cmdline) => format!("{} {}", staged.path(), cmdline),
+        None => String::from(staged.path()),
+    });
+    let shim_args = ImageArgs { cmdline: shim_options.as_deref(), ..*args };
+    let args = if staged.is_some() { &shim_args } else { args };
+
+    // Both have to be in place before the image runs, and stay until it returns
+    let _prepared = match prepare(image_handle, args) {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            let _ = boot::unload_image(image_handle);
+            return Err(e);
+        }
+    };
+    say!();
+
+    // The image may never return, so the report gets the load time now
+    crate::boot::report::refresh();
+    crate::util::logger::flush();
 
     // Start the image (this should not return for Linux kernel)
-    println!("===========================================");
-    println!("Chainloading to boot image...");
-    println!("===========================================");
-    println!();
+    if config.is_some_and(|config| config.clear_screen) {
+        let _ = uefi::system::with_stdout(|stdout| stdout.clear());
+    } else if !crate::util::splash::is_shown() {
+        crate::util::ui::banner("Chainloading to boot image...");
+        println!();
+    }
 
-    unsafe {
-        boot::start_image(image_handle).map_err(|e| {
-            println!();
-            println!("Failed to start image: {:?}", e.status());
-            Error::Uefi(e.status())
-        })?;
+    let watchdog = config.and_then(|config| config.watchdog);
+    if let Some(seconds) = watchdog {
+        if let Err(e) = boot::set_watchdog_timer(seconds as usize, WATCHDOG_CODE, None) {
+            println!("  Warning: Could not set the watchdog: {:?}", e.status());
+        }
     }
 
+    let started = unsafe { boot::start_image(image_handle) };
+
+    // Back with us: the image's watchdog must not reset the machine at the prompt
+    if watchdog.is_some() {
+        let _ = boot::set_watchdog_timer(0, WATCHDOG_CODE, None);
+    }
+    started.map_err(|e| {
+        println!();
+        println!("Failed to start image: {:?}", e.status());
+        Error::Uefi(e.status())
+    })?;
+
     // If we get here, the image returned (shouldn't happen for Linux kernel)
     println!();
     println!("Warning: Image returned control to bootloader");
     Ok(())
 }
 
+/// Connect every NIC to its drivers again, so the image finds the network
+/// stack the way the firmware set it up rather than as we left it
+fn reconnect_nics() {
+    for handle in crate::util::caps::nics() {
+        let _ = boot::connect_controller(handle, None, None, true);
+    }
+}
+
+/// Set the command line and offer the initrd to a loaded image
+///
+/// Returns what the image points into, which must be kept until it returns.
+fn prepare<'a>(image_handle: Handle, args: &ImageArgs<'a>) -> Result<(Option<CString16>, Option<Initrd<'a>>)> {
+    let options = match args.cmdline {
+        Some(cmdline) => {
+            // Linux reads its command line from the LoadOptions as UCS-2
+            let options = ucs2::to_cstring16(cmdline).map_err(|e| {
+                println!("  Command line is not valid UCS-2");
+                e
+            })?;
+            let mut image =
+                boot::open_protocol_exclusive::<LoadedImage>(image_handle).map_err(|e| Error::Uefi(e.status()))?;
+            unsafe { image.set_load_options(options.as_ptr().cast(), options.num_bytes() as u32) };
+            say!("  Command line: {}", cmdline);
+            Some(options)
+        }
+        None => None,
+    };
+
+    let initrd = match args.initrd {
+        Some(data) => {
+            let initrd = Initrd::install(data).map_err(|e| {
+                println!("  Could not offer the initrd: {}", e);
+                e
+            })?;
+            say!("  Initrd: {} bytes (LINUX_EFI_INITRD_MEDIA)", data.len());
+            Some(initrd)
+        }
+        None => None,
+    };
+    Ok((options, initrd))
+}
@@ -0,0 +1,182 @@
+use crate::network::url::{self, Scheme};
+use crate::storage::config::EntryMeta;
+use crate::storage::path;
+use crate::util::{Error, Result};
+use alloc::format;
+use alloc::string::String;
+
+/// What an entry's `url=` points at, and so how it is booted (`type=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntryType {
+    /// An EFI application, started as it is with `cmdline=` as its load
+    /// options
+    #[default]
+    Efi,
+    /// A Linux kernel with an EFI stub, started with `cmdline=` and
+    /// `initrd=`
+    Linux,
+    /// A Multiboot2 kernel; recognized so the configuration can say that
+    /// this build cannot start it
+    Multiboot2,
+    /// A bootable ISO image, attached as a RAM disk and started from its
+    /// removable-media loader
+    Iso,
+    /// A loader on a local volume (`file://<volume>/[path]`), started from
+    /// that volume so it finds the OS next to it
+    LocalDisk,
+    /// CLI commands, run like `run` runs a script
+    Script,
+}
+
+impl EntryType {
+    pub const ALL: [EntryType; 6] = [
+        EntryType::Efi,
+        EntryType::Linux,
+        EntryType::Multiboot2,
+        EntryType::Iso,
+        EntryType::LocalDisk,
+        EntryType::Script,
+    ];
+
+    /// Parse a `type=` value (case-insensitive)
+    pub fn parse(value: &str) -> Result<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str().eq_ignore_ascii_case(value)).ok_or(Error::Parse)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryType::Efi => "efi",
+            EntryType::Linux => "linux",
+            EntryType::Multiboot2 => "multiboot2",
+            EntryType::Iso => "iso",
+            EntryType::LocalDisk => "localdisk",
+            EntryType::Script => "script",
+        }
+    }
+
+    /// The type of an entry without `type=`, as entries were booted before
+    /// there were types: with an initrd a Linux kernel, otherwise an EFI
+    /// application
+    pub fn implied(meta: &EntryMeta) -> Self {
+        if meta.initrd.is_empty() {
+            EntryType::Efi
+        } else {
+            EntryType::Linux
+        }
+    }
+
+    /// Why `url` and `meta` do not make an entry of this type, if they do
+    /// not
+    ///
+    /// Relative URLs are network files here; they cannot be `iscsi://` or
+    /// `file://` once resolved.
+    pub fn check(self, url: &str, meta: &EntryMeta) -> core::result::Result<(), &'static str> {
+        if self == EntryType::Multiboot2 {
+            return Err("type=multiboot2 kernels cannot be started by this build");
+        }
+        let url = match self {
+            EntryType::LocalDisk => local_disk_url(url, path::removable_loader()),
+            _ => String::from(url),
+        };
+        let scheme = url::parse(&url).ok().map(|parsed| (parsed.scheme, parsed.host));
+        if !meta.initrd.is_empty() && self != EntryType::Linux {
+            return Err("initrd= needs type=linux");
+        }
+        if !meta.initrd_sha256.is_empty() && meta.initrd.is_empty() {
+            return Err("initrd_sha256= without initrd=");
+        }
+        let takes_cmdline = matches!(self, EntryType::Efi | EntryType::Linux);
+        if !meta.cmdline.is_empty() && !takes_cmdline {
+            return Err("cmdline= is only passed to efi and linux entries");
+        }
+        match (self, scheme) {
+            // A SAN disk stands for its removable-media loader
+            (EntryType::Efi, _) => Ok(()),
+            (EntryType::LocalDisk, Some((Scheme::File, volume))) if !volume.is_empty() => Ok(()),
+            (EntryType::LocalDisk, _) => Err("type=localdisk needs url=file://<volume>/[path]"),
+            (_, Some((Scheme::Iscsi, _))) => Err("iscsi:// is a disk, use type=efi"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl core::fmt::Display for EntryType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The file a `localdisk` entry's `url` starts: the one named, or the
+/// volume's removable-media `loader` when the URL ends at the volume
+pub fn local_disk_url(url: &str, loader: &str) -> String {
+    let at_volume =
+        url.split_once("://").is_some_and(|(_, rest)| rest.len() > 1 && rest.find('/') == Some(rest.len() - 1));
+    if !at_volume {
+        return String::from(url);
+    }
+    format!("{}{}", url, loader.trim_start_matches('\\').replace('\\', "/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(cmdline: &str, initrd: &str) -> EntryMeta {
+        let mut meta = EntryMeta::default();
+        meta.cmdline.push_str(cmdline).unwrap();
+        meta.initrd.push_str(initrd).unwrap();
+        meta
+    }
+
+    #[test]
+    fn test_parse() {
+        for kind in EntryType::ALL {
+            assert_eq!(EntryType::parse(kind.as_str()), Ok(kind));
+        }
+        assert_eq!(EntryType::parse("LocalDisk"), Ok(EntryType::LocalDisk));
+        assert_eq!(EntryType::parse("pe"), Err(Error::Parse));
+        assert_eq!(EntryType::parse(""), Err(Error::Parse));
+    }
+
+    #[test]
+    fn test_implied() {
+        assert_eq!(EntryType::implied(&meta("", "")), EntryType::Efi);
+        assert_eq!(EntryType::implied(&meta("quiet", "")), EntryType::Efi);
+        assert_eq!(EntryType::implied(&meta("", "initrd.img")), EntryType::Linux);
+    }
+
+    #[test]
+    fn test_check() {
+        let kernel = meta("console=ttyS0", "initrd.img");
+        assert_eq!(EntryType::Linux.check("http://10.0.0.5/vmlinuz", &kernel), Ok(()));
+        assert!(EntryType::Efi.check("http://10.0.0.5/vmlinuz", &kernel).is_err());
+        assert!(EntryType::Multiboot2.check("http://10.0.0.5/kernel", &meta("quiet", "")).is_err());
+        assert!(EntryType::Multiboot2.check("http://10.0.0.5/kernel", &EntryMeta::default()).is_err());
+
+        let plain = EntryMeta::default();
+        assert_eq!(EntryType::Efi.check("iscsi://10.0.0.5/iqn.2010-04.org.example:node1", &plain), Ok(()));
+        assert!(EntryType::Iso.check("iscsi://10.0.0.5/iqn.2010-04.org.example:node1", &plain).is_err());
+        assert_eq!(EntryType::Iso.check("images/live.iso", &plain), Ok(()));
+        assert!(EntryType::Iso.check("http://10.0.0.5/live.iso", &meta("quiet", "")).is_err());
+        assert!(EntryType::Script.check("http://10.0.0.5/site.txt", &meta("quiet", "")).is_err());
+
+        assert_eq!(EntryType::LocalDisk.check("file://WINDOWS/", &plain), Ok(()));
+        assert_eq!(EntryType::LocalDisk.check("file://WINDOWS/EFI/Microsoft/Boot/bootmgfw.efi", &plain), Ok(()));
+        assert!(EntryType::LocalDisk.check("file:///EFI/BOOT/BOOTX64.EFI", &plain).is_err());
+        assert!(EntryType::LocalDisk.check("http://10.0.0.5/x.efi", &plain).is_err());
+
+        let mut unused_hash = EntryMeta::default();
+        unused_hash.initrd_sha256.push_str("00").unwrap();
+        assert!(EntryType::Linux.check("http://10.0.0.5/vmlinuz", &unused_hash).is_err());
+    }
+
+    #[test]
+    fn test_local_disk_url() {
+        let loader = "\\EFI\\BOOT\\BOOTX64.EFI";
+        assert_eq!(local_disk_url("file://WINDOWS/", loader), "file://WINDOWS/EFI/BOOT/BOOTX64.EFI");
+        assert_eq!(local_disk_url("file://WINDOWS/EFI/x.efi", loader), "file://WINDOWS/EFI/x.efi");
+        assert_eq!(local_disk_url("file://WINDOWS/EFI/", loader), "file://WINDOWS/EFI/");
+        assert_eq!(local_disk_url("file:///", loader), "file:///");
+        assert_eq!(local_disk_url("file://", loader), "file://");
+    }
+}
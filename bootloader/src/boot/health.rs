@@ -0,0 +1,175 @@
+use crate::storage::config::MAX_URLS;
+use crate::storage::vars;
+use crate::util::sha256;
+use crate::util::Result;
+use heapless::Vec;
+use uefi::cstr16;
+
+/// Marker set right before handing off to an image
+///
+/// This is the OS handshake: the marker is visible to the OS, which deletes
+/// it once it has booted successfully (scripts/uefipxe-boot-ok.sh does this
+/// from a late-boot service). If it is still present when we start again,
+/// the last boot never got that far and the entry is marked failed.
+pub const MARKER_VAR: &uefi::CStr16 = cstr16!("BootInProgress");
+
+/// Marker contents, bumped if the handshake ever changes meaning
+const HANDSHAKE_VERSION: u8 = 1;
+
+/// Which entry the marker belongs to (boot-services-only, so it survives
+/// the OS clearing the marker)
+const PENDING_VAR: &uefi::CStr16 = cstr16!("BootPending");
+
+/// Known outcome of every entry that has been booted
+const TABLE_VAR: &uefi::CStr16 = cstr16!("EntryHealth");
+
+/// Bytes of the URL hash used to identify an entry
+const KEY_LEN: usize = 8;
+
+/// Record layout in the table: key followed by a state byte
+const RECORD_LEN: usize = KEY_LEN + 1;
+
+type Key = [u8; KEY_LEN];
+
+/// What we know about an entry from previous boots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    /// Never booted (or the outcome was never learned)
+    Unknown,
+    /// Last boot was confirmed by the OS (last-known-good)
+    Good,
+    /// Last boot failed or was never confirmed
+    Failed,
+}
+
+impl Health {
+    fn from_byte(b: u8) -> Self {
+        match b {
+            1 => Health::Good,
+            2 => Health::Failed,
+            _ => Health::Unknown,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Health::Unknown => 0,
+            Health::Good => 1,
+            Health::Failed => 2,
+        }
+    }
+
+    /// Short label for listings
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Health::Unknown => "unknown",
+            Health::Good => "last-known-good",
+            Health::Failed => "failed",
+        }
+    }
+}
+
+/// Entries are identified by their URL so that reordering the config
+/// does not move the health state to a different image
+fn key(url: &str) -> Key {
+    let digest = sha256::digest(url.as_bytes());
+    let mut key = [0u8; KEY_LEN];
+    key.copy_from_slice(&digest[..KEY_LEN]);
+    key
+}
+
+/// Load the health table
+fn load_table() -> Vec<(Key, Health), MAX_URLS> {
+    let mut buf = [0u8; RECORD_LEN * MAX_URLS];
+    let mut table = Vec::new();
+
+    if let Ok(len) = vars::read(TABLE_VAR, &mut buf) {
+        for record in buf[..len].chunks_exact(RECORD_LEN) {
+            let mut key = [0u8; KEY_LEN];
+            key.copy_from_slice(&record[..KEY_LEN]);
+            let _ = table.push((key, Health::from_byte(record[KEY_LEN])));
+        }
+    }
+
+    table
+}
+
+/// Store the health table
+fn store_table(table: &[(Key, Health)]) -> Result<()> {
+    let mut buf: Vec<u8, { RECORD_LEN * MAX_URLS }> = Vec::new();
+    for (key, health) in table.iter() {
+        let _ = buf.extend_from_slice(key);
+        let _ = buf.push(health.to_byte());
+    }
+    vars::write(TABLE_VAR, vars::BOOT_ONLY, &buf)
+}
+
+/// Health of the entry with this URL
+pub fn health(url: &str) -> Health {
+    let key = key(url);
+    load_table()
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map_or(Health::Unknown, |(_, h)| *h)
+}
+
+/// Remember the outcome of booting the entry with this URL
+pub fn record(url: &str, health: Health) -> Result<()> {
+    record_key(key(url), health)
+}
+
+fn record_key(key: Key, health: Health) -> Result<()> {
+    let mut table = load_table();
+
+    match table.iter_mut().find(|(k, _)| *k == key) {
+        Some(entry) => entry.1 = health,
+        None => {
+            // Forget the oldest entry when full
+            if table.is_full() {
+                table.remove(0);
+            }
+            let _ = table.push((key, health));
+        }
+    }
+
+    store_table(&table)
+}
+
+/// Set the boot-in-progress marker right before starting an image
+pub fn mark_in_progress(url: &str) -> Result<()> {
+    vars::write(PENDING_VAR, vars::BOOT_ONLY, &key(url))?;
+    vars::write(MARKER_VAR, vars::OS_VISIBLE, &[HANDSHAKE_VERSION])
+}
+
+/// The image came back to us, so no confirmation will follow
+pub fn clear_in_progress() {
+    let _ = vars::delete(MARKER_VAR);
+    let _ = vars::delete(PENDING_VAR);
+}
+
+/// Learn the outcome of the previous boot from the marker
+///
+/// Call once at startup. Returns the outcome if a boot was pending.
+pub fn resolve_pending() -> Option<Health> {
+    let mut pending: Key = [0; KEY_LEN];
+    match vars::read(PENDING_VAR, &mut pending) {
+        Ok(KEY_LEN) => {}
+        _ => {
+            // Without an entry to blame, a leftover marker means nothing
+            clear_in_progress();
+            return None;
+        }
+    }
+
+    // The OS deletes the marker once it is up
+    let mut marker = [0u8; 1];
+    let outcome = if vars::read(MARKER_VAR, &mut marker).is_ok() {
+        Health::Failed
+    } else {
+        Health::Good
+    };
+
+    let _ = record_key(pending, outcome);
+    clear_in_progress();
+    Some(outcome)
+}
@@ -0,0 +1,151 @@
+use crate::util::{Error, Result};
+use alloc::boxed::Box;
+use core::ffi::c_void;
+use core::ptr;
+use uefi::boot;
+use uefi::proto::device_path::DevicePath;
+use uefi::{Guid, Handle, Identify, Status};
+use uefi_raw::Boolean;
+
+/// Vendor media node GUID the Linux EFI stub looks its initrd up under
+/// {5568E427-68FC-4F3D-AC74-CA555231CC68}
+const LINUX_EFI_INITRD_MEDIA_GUID: [u8; 16] = [
+    0x27, 0xe4, 0x68, 0x55, 0xfc, 0x68, 0x3d, 0x4f,
+    0xac, 0x74, 0xca, 0x55, 0x52, 0x31, 0xcc, 0x68,
+];
+
+/// Load File 2 Protocol GUID
+/// {4006C0C1-FCB3-403E-996D-4A6C8724E06D}
+const LOAD_FILE2_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0xc1, 0xc0, 0x06, 0x40, 0xb3, 0xfc, 0x3e, 0x40,
+    0x99, 0x6d, 0x4a, 0x6c, 0x87, 0x24, 0xe0, 0x6d,
+]);
+
+#[repr(C)]
+struct LoadFile2Protocol {
+    load_file: unsafe extern "efiapi" fn(
+        this: *mut LoadFile2Protocol,
+        file_path: *const c_void,
+        boot_policy: Boolean,
+        buffer_size: *mut usize,
+        buffer: *mut c_void,
+    ) -> Status,
+}
+
+/// The protocol followed by the data it serves, so `load_file` finds the
+/// initrd from its `this` pointer
+#[repr(C)]
+struct Loader {
+    protocol: LoadFile2Protocol,
+    data: *const u8,
+    len: usize,
+}
+
+/// VenMedia(LINUX_EFI_INITRD_MEDIA_GUID)/End
+const DEVICE_PATH: [u8; 24] = {
+    let mut path = [0u8; 24];
+    // Media device path, vendor subtype, 20 bytes long
+    path[0] = 0x04;
+    path[1] = 0x03;
+    path[2] = 20;
+    let mut i = 0;
+    while i < 16 {
+        path[4 + i] = LINUX_EFI_INITRD_MEDIA_GUID[i];
+        i += 1;
+    }
+    // End of the entire device path
+    path[20] = 0x7f;
+    path[21] = 0xff;
+    path[22] = 4;
+    path
+};
+
+unsafe extern "efiapi" fn load_file(
+    this: *mut LoadFile2Protocol,
+    _file_path: *const c_void,
+    boot_policy: Boolean,
+    buffer_size: *mut usize,
+    buffer: *mut c_void,
+) -> Status {
+    // LoadFile2 is never a boot selection
+    if bool::from(boot_policy) {
+        return Status::UNSUPPORTED;
+    }
+    if buffer_size.is_null() {
+        return Status::INVALID_PARAMETER;
+    }
+
+    let loader = unsafe { &*(this as *const Loader) };
+    unsafe {
+        if buffer.is_null() || *buffer_size < loader.len {
+            *buffer_size = loader.len;
+            return Status::BUFFER_TOO_SMALL;
+        }
+        ptr::copy_nonoverlapping(loader.data, buffer as *mut u8, loader.len);
+        *buffer_size = loader.len;
+    }
+    Status::SUCCESS
+}
+
+/// An initrd offered to the next image through LINUX_EFI_INITRD_MEDIA,
+/// withdrawn again on drop
+///
+/// The kernel's EFI stub locates the LoadFile2 protocol installed under
+/// the vendor device path and reads the initrd through it, so the data
+/// must stay in place until the image has started (or returned).
+pub struct Initrd<'a> {
+    handle: Handle,
+    loader: Box<Loader>,
+    path: Box<[u8; 24]>,
+    _data: &'a [u8],
+}
+
+impl<'a> Initrd<'a> {
+    /// Install the protocols serving `data`
+    pub fn install(data: &'a [u8]) -> Result<Self> {
+        let loader = Box::new(Loader {
+            protocol: LoadFile2Protocol { load_file },
+            data: data.as_ptr(),
+            len: data.len(),
+        });
+        let path = Box::new(DEVICE_PATH);
+
+        let handle = unsafe {
+            boot::install_protocol_interface(None, &DevicePath::GUID, path.as_ptr() as *const c_void)
+        }
+        .map_err(|e| Error::Uefi(e.status()))?;
+        let installed = unsafe {
+            boot::install_protocol_interface(
+                Some(handle),
+                &LOAD_FILE2_PROTOCOL_GUID,
+                &*loader as *const Loader as *const c_void,
+            )
+        };
+        if let Err(e) = installed {
+            unsafe {
+                let _ = boot::uninstall_protocol_interface(handle, &DevicePath::GUID, path.as_ptr() as *const c_void);
+            }
+            return Err(Error::Uefi(e.status()));
+        }
+
+        Ok(Initrd { handle, loader, path, _data: data })
+    }
+}
+
+impl Drop for Initrd<'_> {
+    fn drop(&mut self) {
+        // Removing the last protocol also frees the handle
+        unsafe {
+            let _ = boot::uninstall_protocol_interface(
+                self.handle,
+                &LOAD_FILE2_PROTOCOL_GUID,
+                &*self.loader as *const Loader as *const c_void,
+            );
+            let _ = boot::uninstall_protocol_interface(
+                self.handle,
+                &DevicePath::GUID,
+                self.path.as_ptr() as *const c_void,
+            );
+        }
+    }
+}
@@ -1,3 +1,15 @@
 pub mod chainload;
+pub mod entry_type;
+pub mod health;
+pub mod initrd;
+pub mod pipeline;
+pub mod prefetch;
+pub mod ramdisk;
+pub mod report;
+pub mod rescue;
+pub mod rollback;
+pub mod safemode;
+pub mod secure;
+pub mod staging;
 
-pub use chainload::chainload_image;
+pub use chainload::{chainload_image, ImageArgs};
@@ -0,0 +1,71 @@
+use crate::boot::health::Health;
+use crate::storage::config::MAX_URLS;
+use heapless::Vec;
+
+/// Order in which entries are tried when booting without an explicit index
+///
+/// The default entry goes first unless its last boot failed. After it come
+/// last-known-good entries, then entries never booted, then failed ones,
+/// each group in config order. Disabled entries are left out entirely.
+pub fn fallback_order(
+    count: usize,
+    default: Option<usize>,
+    health: impl Fn(usize) -> Health,
+    disabled: impl Fn(usize) -> bool,
+) -> Vec<usize, MAX_URLS> {
+    let mut order = Vec::new();
+    let count = count.min(MAX_URLS);
+
+    if let Some(d) = default.filter(|&d| d < count && !disabled(d) && health(d) != Health::Failed) {
+        let _ = order.push(d);
+    }
+
+    for group in [Health::Good, Health::Unknown, Health::Failed] {
+        for i in 0..count {
+            if !order.contains(&i) && !disabled(i) && health(i) == group {
+                let _ = order.push(i);
+            }
+        }
+    }
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn states(i: usize) -> Health {
+        match i {
+            1 => Health::Failed,
+            3 => Health::Good,
+            _ => Health::Unknown,
+        }
+    }
+
+    #[test]
+    fn test_default_first() {
+        let order = fallback_order(4, Some(2), states, |_| false);
+        assert_eq!(order.as_slice(), &[2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn test_failed_default_demoted() {
+        let order = fallback_order(4, Some(1), states, |_| false);
+        assert_eq!(order.as_slice(), &[3, 0, 2, 1]);
+    }
+
+    #[test]
+    fn test_no_default() {
+        let order = fallback_order(4, None, states, |_| false);
+        assert_eq!(order.as_slice(), &[3, 0, 2, 1]);
+        assert!(fallback_order(0, Some(0), states, |_| false).is_empty());
+    }
+
+    #[test]
+    fn test_disabled_left_out() {
+        let order = fallback_order(4, Some(2), states, |i| i == 2 || i == 3);
+        assert_eq!(order.as_slice(), &[0, 1]);
+        assert!(fallback_order(4, Some(2), states, |_| true).is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::network::client::{is_closed, next_chunk_size, HttpClient, Receive, MIN_CHUNK};
+use crate::storage::{self, Config};
+use crate::network::verify::{self, Hasher};
+use crate::util::global::Global;
+use crate::util::platform::Platform;
+use crate::util::wipe::Wiped;
+use crate::util::{Error, Result};
+use crate::say;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+use uefi::boot;
+use uefi::println;
+use uefi_raw::protocol::network::http::HttpStatusCode;
+
+/// Pause between driver polls while working through an idle slice
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Where a background download stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Receive calls are still running
+    Receiving,
+    /// Whole body received and, if signed, verified
+    Done,
+    /// Failed or did not match its signature; boot downloads again
+    Failed,
+}
+
+/// Background download of the default entry
+struct Prefetch {
+    // Declared first so the HTTP child is destroyed, ending any receive
+    // call, before the buffers that call writes to are wiped and freed
+    client: HttpClient,
+    receive: Option<Receive>,
+    url: String,
+    signature: String,
+    data: Wiped,
+    content_length: Option<usize>,
+    delimited: bool,
+    keep_alive: bool,
+    chunk: usize,
+    hasher: Option<Hasher>,
+    hashed: usize,
+    state: State,
+}
+
+/// Download started at the prompt, if any
+static PREFETCH: Global<Prefetch> = Global::new();
+
+/// Start downloading the default entry in the background
+///
+/// Only `http(s)://` entries with `prefetch=true` in the config are
+/// fetched. The download then progresses whenever the CLI waits for a key,
+/// so by the time the technician types `boot` it is often complete and
+/// already verified.
+pub fn start() {
+    let Some((index, url, signature)) = storage::with_config(prefetched_entry).flatten() else {
+        return;
+    };
+
+    say!("Prefetching default entry [{}] in the background", index);
+    match begin(&url, &signature) {
+        Ok(prefetch) => PREFETCH.set(prefetch),
+        Err(e) => {
+            println!("  Prefetch not started: {}", e);
+            log(log::Level::Warn, format_args!("Prefetch of {} not started: {}", url, e));
+        }
+    }
+}
+
+/// The default entry with its URL and signature, if `config` has it
+/// prefetched
+fn prefetched_entry(config: &Config) -> Option<(usize, String, String)> {
+    let index = config.default_index.filter(|&index| config.prefetch && !config.is_disabled(index))?;
+    let url = config.urls.get(index)?;
+    let absolute = crate::storage::entry_url(url);
+    if !absolute.starts_with("http://") && !absolute.starts_with("https://") {
+        return None;
+    }
+    // Booting it would be refused anyway
+    let requires = config.meta.get(index).map(|meta| meta.requires).unwrap_or_default();
+    if requires.unmet(&Platform::current()).is_some() {
+        return None;
+    }
+    let signature = config.signatures.get(index).map_or("", |s| s.as_str());
+    Some((index, String::from(url.as_str()), String::from(signature)))
+}
+
+fn begin(url: &str, signature: &str) -> Result<Prefetch> {
+    // Kept under the URL as configured, which is what boot asks for
+    let mut client = crate::network::http::connect(&crate::storage::entry_url(url))?;
+    let mut data = Wiped::default();
+    let receive = client.begin_receive(&mut data, MIN_CHUNK, true)?;
+
+    Ok(Prefetch {
+        client,
+        receive: Some(receive),
+        url: String::from(url),
+        signature: String::from(signature),
+        data,
+        content_length: None,
+        delimited: false,
+        keep_alive: false,
+        chunk: MIN_CHUNK,
+        hasher: verify::hasher(signature),
+        hashed: 0,
+        state: State::Receiving,
+    })
+}
+
+/// Spend up to `budget` on the background download
+///
+/// Called while waiting for input. Returns false when there is nothing to
+/// do, so the caller can idle as usual.
+pub fn run_for(budget: Duration) -> bool {
+    PREFETCH
+        .with_mut(|prefetch| {
+            if prefetch.state != State::Receiving {
+                return false;
+            }
+
+            let polls = (budget.as_micros() / POLL_INTERVAL.as_micros()).max(1);
+            for _ in 0..polls {
+                if prefetch.state != State::Receiving {
+                    break;
+                }
+                let done = match prefetch.receive.as_ref() {
+                    Some(rx) => prefetch.client.is_done(rx),
+                    None => true,
+                };
+                if done {
+                    prefetch.advance();
+                } else {
+                    boot::stall(POLL_INTERVAL);
+                }
+            }
+            true
+        })
+        .unwrap_or(false)
+}
+
+/// Hand over the prefetched image for `url`
+///
+/// Finishes a download still in progress (printing progress as a normal
+/// download would). Any prefetch of a different URL is abandoned.
+pub fn take(url: &str) -> Option<Vec<u8>> {
+    if PREFETCH.with(|prefetch| prefetch.url != url)? {
+        cancel();
+        return None;
+    }
+
+    PREFETCH.with_mut(|prefetch| {
+        if prefetch.state == State::Receiving {
+            say!("Finishing background download: {}", url);
+        }
+        while prefetch.state == State::Receiving {
+            let previous = prefetch.data.len();
+            let waited = match prefetch.receive.as_ref() {
+                Some(rx) => prefetch.client.wait_receive(rx),
+                None => Ok(()),
+            };
+            match waited {
+                Ok(()) => prefetch.advance(),
+                Err(e) => prefetch.fail(e),
+            }
+            crate::util::ui::progress("Progress", prefetch.data.len(), previous, prefetch.content_length);
+        }
+        crate::util::ui::progress_done();
+    });
+
+    let prefetch = PREFETCH.take()?;
+    match prefetch.state {
+        State::Done => {
+            say!("Using prefetched image ({} bytes)", prefetch.data.len());
+            let Prefetch { client, data, keep_alive, .. } = prefetch;
+            if keep_alive {
+                crate::network::http::release(url, client);
+            }
+            Some(data.into_inner())
+        }
+        _ => {
+            println!("Background download failed, downloading again");
+            None
+        }
+    }
+}
+
+/// Abandon the background download
+pub fn cancel() {
+    PREFETCH.clear();
+}
+
+impl Prefetch {
+    /// Collect a completed receive call and start the next one
+    fn advance(&mut self) {
+        let Some(rx) = self.receive.take() else {
+            return self.finish();
+        };
+
+        let chunk = match self.client.end_receive(rx, &mut self.data) {
+            Ok(chunk) => chunk,
+            // Without a length or chunks, only the server closing the
+            // connection ends the body; a timeout or reset means it was cut short
+            Err(e) if !self.delimited && self.hashed > 0 && is_closed(e) => return self.finish(),
+            Err(e) => return self.fail(e),
+        };
+
+        if let Some(head) = chunk.head {
+            if head.status != HttpStatusCode::STATUS_200_OK {
+                return self.fail(Error::Io);
+            }
+            self.content_length = head.content_length;
+            self.delimited = head.is_delimited();
+            self.keep_alive = head.keeps_alive();
+            if let Some(total) = head.content_length {
+                if self.data.try_reserve_exact(total.saturating_sub(self.data.len())).is_err() {
+                    return self.fail(Error::OutOfMemory);
+                }
+            }
+        } else if chunk.received == 0 {
+            return self.finish();
+        }
+
+        // Hash as we go so that verification costs nothing at the end
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&self.data[self.hashed..]);
+        }
+        self.hashed = self.data.len();
+        self.chunk = next_chunk_size(self.chunk, chunk.received);
+
+        let want = match self.content_length {
+            Some(total) if self.data.len() >= total => return self.finish(),
+            Some(total) => self.chunk.min(total - self.data.len()),
+            None => self.chunk,
+        };
+        match self.client.begin_receive(&mut self.data, want, false) {
+            Ok(rx) => self.receive = Some(rx),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Whole body is in: check length and signature
+    fn finish(&mut self) {
+        if self.content_length.is_some_and(|total| total != self.data.len()) {
+            return self.fail(Error::Io);
+        }
+
+        if !self.signature.is_empty() {
+            // Verifiers that need the whole image run now
+            let verified = match self.hasher.take() {
+                Some(hasher) => verify::digest_matches(&self.signature, &hasher.finalize()),
+                None => verify::check(&self.data, &self.signature).is_ok(),
+            };
+            if !verified {
+                return self.fail(Error::SecurityViolation);
+            }
+        }
+
+        self.state = State::Done;
+        log(
+            log::Level::Info,
+            format_args!("Prefetched {} ({} bytes)", self.url, self.data.len()),
+        );
+    }
+
+    fn fail(&mut self, error: Error) {
+        // The driver must be done with the buffer before it is freed
+        if let Some(rx) = self.receive.take() {
+            self.client.cancel_receive(rx, &mut self.data);
+        }
+
+        // Wipe and free the memory now; boot will download again
+        self.data = Wiped::default();
+        self.state = State::Failed;
+        log(log::Level::Warn, format_args!("Prefetch of {} failed: {}", self.url, error));
+    }
+}
+
+/// Output would land in the middle of the prompt, so only the log hears
+/// about background progress
+fn log(level: log::Level, args: core::fmt::Arguments) {
+    crate::util::logger::log_entry(level, &alloc::format!("{}", args));
+}
@@ -0,0 +1,122 @@
+use crate::network::child::RawProtocol;
+use crate::storage::{file, path};
+use crate::util::caps::{self, Capability, RAM_DISK_PROTOCOL_GUID};
+use crate::util::metrics::{Phase, Timer};
+use crate::util::{Error, Result};
+use crate::say;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ptr::{self, NonNull};
+use uefi::boot::{self, AllocateType, MemoryType, OpenProtocolAttributes, OpenProtocolParams, SearchType};
+use uefi::proto::device_path::{DevicePath, DevicePathNode, FfiDevicePath};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::{println, Guid, Handle, Identify, Status};
+
+/// Disk type of an ISO image on a RAM disk
+/// {3D5ABD30-4175-87CE-6D64-D2ADE523C4BB}
+const VIRTUAL_CD_GUID: Guid = Guid::from_bytes([
+    0x30, 0xbd, 0x5a, 0x3d, 0x75, 0x41, 0xce, 0x87,
+    0x6d, 0x64, 0xd2, 0xad, 0xe5, 0x23, 0xc4, 0xbb,
+]);
+
+const PAGE_SIZE: usize = 4096;
+
+#[repr(C)]
+struct RamDiskProtocol {
+    register: unsafe extern "efiapi" fn(
+        ram_disk_base: u64,
+        ram_disk_size: u64,
+        ram_disk_type: *const Guid,
+        parent_device_path: *const FfiDevicePath,
+        device_path: *mut *const FfiDevicePath,
+    ) -> Status,
+    unregister: unsafe extern "efiapi" fn(device_path: *const FfiDevicePath) -> Status,
+}
+
+/// Attach an ISO image as a RAM disk and read its removable-media loader
+///
+/// Returns the loader with its device path, which it needs to find the
+/// rest of the ISO. The disk is kept in reserved memory, so an installer
+/// or live system can still find it after ExitBootServices.
+pub fn attach(iso: &[u8]) -> Result<(Vec<u8>, Box<DevicePath>)> {
+    let handle = *caps::handles(Capability::RamDisk).first().ok_or(Error::Unsupported)?;
+    let ram_disk = RawProtocol::<RamDiskProtocol>::open(handle, &RAM_DISK_PROTOCOL_GUID)?;
+    let ram_disk_ptr = ram_disk.as_ptr();
+
+    let timer = Timer::start();
+    let pages = iso.len().div_ceil(PAGE_SIZE);
+    let region =
+        boot::allocate_pages(AllocateType::AnyPages, MemoryType::RESERVED, pages).map_err(|_| Error::OutOfMemory)?;
+    unsafe { ptr::copy_nonoverlapping(iso.as_ptr(), region.as_ptr(), iso.len()) };
+
+    let mut disk: *const FfiDevicePath = ptr::null();
+    let status = unsafe {
+        ((*ram_disk_ptr).register)(region.as_ptr() as u64, iso.len() as u64, &VIRTUAL_CD_GUID, ptr::null(), &mut disk)
+    };
+    if status.is_error() || disk.is_null() {
+        free(region, pages);
+        return Err(Error::Uefi(status));
+    }
+    timer.stop(Phase::Connect);
+    say!("  ISO attached as a RAM disk ({} bytes)", iso.len());
+
+    let loader = path::removable_loader();
+    let found = volumes_on(unsafe { DevicePath::from_ffi_ptr(disk) }).into_iter().find_map(|(handle, device_path)| {
+        let data = file::read_image_on(handle, loader).ok()?;
+        Some((data, file::with_file(&device_path, loader).ok()?))
+    });
+    match found {
+        Some((data, device_path)) => {
+            say!("  Read {} ({} bytes)", loader, data.len());
+            Ok((data, device_path))
+        }
+        None => {
+            println!("  No filesystem on the ISO has {}", loader);
+            unsafe {
+                let _ = ((*ram_disk_ptr).unregister)(disk);
+            }
+            free(region, pages);
+            Err(Error::NotFound)
+        }
+    }
+}
+
+fn free(region: NonNull<u8>, pages: usize) {
+    unsafe {
+        let _ = boot::free_pages(region, pages);
+    }
+}
+
+/// Filesystems on the RAM disk at `disk`, with their device paths
+///
+/// The firmware connects a registered disk itself; its El Torito
+/// partitions show up as device paths below the disk's own.
+fn volumes_on(disk: &DevicePath) -> Vec<(Handle, Box<DevicePath>)> {
+    let Ok(handles) = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID)) else {
+        return Vec::new();
+    };
+    let disk_nodes: Vec<&DevicePathNode> = disk.node_iter().collect();
+
+    handles
+        .iter()
+        .filter_map(|&handle| {
+            let device_path = unsafe {
+                boot::open_protocol::<DevicePath>(
+                    OpenProtocolParams {
+                        handle,
+                        agent: boot::image_handle(),
+                        controller: None,
+                    },
+                    OpenProtocolAttributes::GetProtocol,
+                )
+                .ok()?
+            };
+            let nodes: Vec<&DevicePathNode> = device_path.node_iter().collect();
+            let below = nodes.len() > disk_nodes.len()
+                && disk_nodes.iter().zip(&nodes).all(|(a, b)| {
+                    a.device_type() == b.device_type() && a.sub_type() == b.sub_type() && a.data() == b.data()
+                });
+            below.then(|| (handle, device_path.to_boxed()))
+        })
+        .collect()
+}
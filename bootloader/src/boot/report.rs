@@ -0,0 +1,145 @@
+use crate::storage::vars;
+use crate::util::beep::{self, Signal};
+use crate::util::metrics;
+use crate::util::Result;
+use core::fmt::Write;
+use heapless::String;
+use uefi::cstr16;
+
+/// Variable holding the outcome of the most recent boot attempt
+///
+/// Kept in NVRAM so the report survives a reset caused by the booted image.
+const REPORT_VAR: &uefi::CStr16 = cstr16!("LastBoot");
+
+/// Maximum length of a rendered report
+pub const MAX_REPORT_LEN: usize = 512;
+
+/// Which entry a boot attempt is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+    /// Configured entry by index
+    Index(usize),
+    /// Rescue image compiled into the bootloader
+    Rescue,
+}
+
+impl core::fmt::Display for Entry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Entry::Index(index) => write!(f, "[{}]", index),
+            Entry::Rescue => write!(f, "{}", crate::boot::rescue::NAME),
+        }
+    }
+}
+
+/// How far a boot attempt got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Fetching the image
+    Download,
+    /// Checking the image signature
+    Verify,
+    /// Handing control to the image
+    Start,
+    /// The image returned to the bootloader
+    Returned,
+}
+
+impl Stage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Download => "download",
+            Stage::Verify => "verify",
+            Stage::Start => "start",
+            Stage::Returned => "returned",
+        }
+    }
+}
+
+/// Arguments of the last `record`, so `refresh` can store it again
+struct Recorded {
+    entry: Entry,
+    url: alloc::string::String,
+    stage: Stage,
+    size: usize,
+    result: Result<()>,
+}
+
+static mut RECORDED: Option<Recorded> = None;
+
+/// Record the state of a boot attempt
+///
+/// `result` is the outcome of `stage`; pass `Ok(())` for a stage that is
+/// about to run (e.g. right before starting the image). The phase timings
+/// measured so far go along. Failures are also sounded (see `beep=`).
+pub fn record(entry: Entry, url: &str, stage: Stage, size: usize, result: Result<()>) {
+    if result.is_err() {
+        beep::signal(if stage == Stage::Verify { Signal::VerifyFailed } else { Signal::BootFailed });
+    }
+    let recorded = Recorded { entry, url: alloc::string::String::from(url), stage, size, result };
+    store(&recorded);
+    unsafe {
+        RECORDED = Some(recorded);
+    }
+}
+
+/// Store the last report again with the timings measured since
+///
+/// Called right before starting the image, which may never return.
+pub fn refresh() {
+    if let Some(recorded) = unsafe { RECORDED.as_ref() } {
+        store(recorded);
+    }
+}
+
+fn store(recorded: &Recorded) {
+    let mut report: String<MAX_REPORT_LEN> = String::new();
+
+    if let Ok(t) = uefi::runtime::get_time() {
+        let _ = writeln!(
+            report,
+            "time={:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            t.year(),
+            t.month(),
+            t.day(),
+            t.hour(),
+            t.minute(),
+            t.second()
+        );
+    }
+    let _ = writeln!(report, "image={} {}", recorded.entry, recorded.url);
+    let _ = writeln!(report, "stage={}", recorded.stage.as_str());
+    let _ = writeln!(report, "size={}", recorded.size);
+    let _ = match recorded.result {
+        Ok(()) => writeln!(report, "result=ok"),
+        Err(e) => writeln!(report, "result={}", e),
+    };
+    if let Some(timings) = metrics::summary(&metrics::timings()) {
+        let _ = writeln!(report, "timings={}", timings);
+    }
+
+    if let Err(e) = vars::write(REPORT_VAR, vars::BOOT_ONLY, report.as_bytes()) {
+        crate::util::logger::log_entry(
+            log::Level::Warn,
+            &alloc::format!("Could not store boot report: {}", e),
+        );
+    }
+}
+
+/// Read the report of the most recent boot attempt
+pub fn last() -> Option<String<MAX_REPORT_LEN>> {
+    let mut buf = [0u8; MAX_REPORT_LEN];
+    let len = vars::read(REPORT_VAR, &mut buf).ok()?;
+    let text = core::str::from_utf8(&buf[..len]).ok()?;
+
+    let mut report = String::new();
+    report.push_str(text).ok()?;
+    Some(report)
+}
+
+/// Timings line of the most recent report, e.g. `dhcp=1.20s connect=85ms`
+pub fn last_timings() -> Option<alloc::string::String> {
+    let report = last()?;
+    let line = report.lines().find_map(|line| line.strip_prefix("timings="))?;
+    Some(alloc::string::String::from(line))
+}
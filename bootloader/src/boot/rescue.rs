@@ -0,0 +1,16 @@
+/// Name of the hidden rescue entry (`boot @builtin-rescue`)
+pub const NAME: &str = "@builtin-rescue";
+
+/// Rescue image URL, compiled in from `UEFIPXE_RESCUE_URL`
+///
+/// Does not depend on config.txt, so it can still be booted when the config
+/// is corrupt, empty or refused by the integrity check.
+pub const URL: Option<&str> = option_env!("UEFIPXE_RESCUE_URL");
+
+/// Expected SHA256 of the rescue image, from `UEFIPXE_RESCUE_SHA256`
+pub const SHA256: Option<&str> = option_env!("UEFIPXE_RESCUE_SHA256");
+
+/// Whether a rescue image was built in
+pub fn available() -> bool {
+    URL.is_some_and(|url| !url.is_empty())
+}
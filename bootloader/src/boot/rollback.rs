@@ -0,0 +1,103 @@
+use crate::storage::vars;
+use crate::util::{Error, Result};
+use uefi::cstr16;
+
+/// Lowest image version that may still be booted
+///
+/// Boot-services-only, so an OS cannot lower it again. Firmware without
+/// authenticated variable support for our own keys gives us nothing
+/// stronger; together with the policy pin this keeps a compromised OS from
+/// reopening a downgrade path.
+const FLOOR_VAR: &uefi::CStr16 = cstr16!("MinImageVersion");
+
+/// Version of the image being started, until the OS confirms the boot
+const PENDING_VAR: &uefi::CStr16 = cstr16!("PendingVersion");
+
+fn read_u32(name: &uefi::CStr16) -> Option<u32> {
+    let mut buf = [0u8; 4];
+    match vars::read(name, &mut buf) {
+        Ok(4) => Some(u32::from_le_bytes(buf)),
+        _ => None,
+    }
+}
+
+/// Current floor (0 when none has been set)
+pub fn floor() -> u32 {
+    read_u32(FLOOR_VAR).unwrap_or(0)
+}
+
+/// Whether an entry declaring `version` may boot with this floor
+///
+/// Entries without `version=` count as version 0, so once a floor exists
+/// they are refused like any other old image.
+pub fn is_allowed(version: Option<u32>, floor: u32) -> bool {
+    version.unwrap_or(0) >= floor
+}
+
+/// Refuse an entry whose declared version is below the floor
+pub fn check(version: Option<u32>) -> Result<()> {
+    let floor = floor();
+    if is_allowed(version, floor) {
+        return Ok(());
+    }
+
+    match version {
+        Some(version) => uefi::println!("Refusing image version {}: minimum is {}", version, floor),
+        None => uefi::println!("Refusing image without version=: minimum is {}", floor),
+    }
+    Err(Error::SecurityViolation)
+}
+
+/// Raise the floor; it never goes down
+pub fn raise(version: u32) -> Result<u32> {
+    let floor = floor();
+    if version < floor {
+        return Err(Error::InvalidArgument);
+    }
+    if version > floor {
+        vars::write(FLOOR_VAR, vars::BOOT_ONLY, &version.to_le_bytes())?;
+    }
+    Ok(version)
+}
+
+/// Remember the version of the image about to be started
+///
+/// The floor only moves once the OS has confirmed the boot (see
+/// `health::resolve_pending`), so a broken release can still fall back to
+/// the previous one.
+pub fn mark_pending(version: Option<u32>) -> Result<()> {
+    match version {
+        Some(version) => vars::write(PENDING_VAR, vars::BOOT_ONLY, &version.to_le_bytes()),
+        None => vars::delete(PENDING_VAR),
+    }
+}
+
+/// Forget the pending version without raising the floor
+pub fn clear_pending() {
+    let _ = vars::delete(PENDING_VAR);
+}
+
+/// The previous boot was confirmed: raise the floor to its version
+///
+/// Returns the new floor if it moved.
+pub fn commit_pending() -> Option<u32> {
+    let version = read_u32(PENDING_VAR);
+    clear_pending();
+
+    let version = version.filter(|&v| v > floor())?;
+    raise(version).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_allowed() {
+        assert!(is_allowed(None, 0));
+        assert!(is_allowed(Some(3), 3));
+        assert!(is_allowed(Some(4), 3));
+        assert!(!is_allowed(Some(2), 3));
+        assert!(!is_allowed(None, 1));
+    }
+}
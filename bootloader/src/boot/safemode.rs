@@ -0,0 +1,139 @@
+use crate::storage::vars;
+use crate::util::global::Global;
+use crate::util::rtc;
+use uefi::cstr16;
+
+/// Starts that have not got anywhere yet
+///
+/// Written at every start and deleted once that start reaches the prompt,
+/// hands off to an image or exits, so it only grows while something kills
+/// us before then: a panic, a hang the watchdog resets, a script or entry
+/// that takes the machine down. Boot-services-only, like the health table.
+const STARTS_VAR: &uefi::CStr16 = cstr16!("UnsettledStarts");
+
+/// Unsettled starts in a row after which the next one is a safe one
+pub const CRASHES: u8 = 3;
+
+/// Starts further apart than this are not a loop
+const WINDOW_SECS: u64 = 10 * 60;
+
+/// Record layout: count, then the time of the last start (u64 LE, or
+/// all ones when the clock could not be read)
+const RECORD_LEN: usize = 1 + 8;
+
+const NO_TIME: u64 = u64::MAX;
+
+/// Unsettled starts seen so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Starts {
+    count: u8,
+    last: Option<u64>,
+}
+
+impl Starts {
+    fn from_bytes(record: &[u8; RECORD_LEN]) -> Self {
+        let mut last = [0u8; 8];
+        last.copy_from_slice(&record[1..]);
+        let last = u64::from_le_bytes(last);
+        Starts { count: record[0], last: Some(last).filter(|&last| last != NO_TIME) }
+    }
+
+    fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let mut record = [0u8; RECORD_LEN];
+        record[0] = self.count;
+        record[1..].copy_from_slice(&self.last.unwrap_or(NO_TIME).to_le_bytes());
+        record
+    }
+
+    /// The record after a start at `now`
+    ///
+    /// Without a clock on either side the starts are taken to be close: a
+    /// machine stuck in a loop is worse off than one sent to the prompt
+    /// once too often.
+    fn next(previous: Option<Starts>, now: Option<u64>) -> Starts {
+        let close = |last: Option<u64>| match (last, now) {
+            (Some(last), Some(now)) => now.saturating_sub(last) <= WINDOW_SECS,
+            _ => true,
+        };
+        let count = match previous {
+            Some(previous) if close(previous.last) => previous.count.saturating_add(1),
+            _ => 1,
+        };
+        Starts { count, last: now }
+    }
+
+    /// Whether this start comes after enough crashes for safe mode
+    fn tripped(&self) -> bool {
+        self.count > CRASHES
+    }
+}
+
+/// Whether this start is in safe mode
+static ACTIVE: Global<bool> = Global::new();
+
+/// Count this start and decide whether it runs in safe mode
+///
+/// Call once, before anything that could take the machine down.
+pub fn check() -> bool {
+    let mut record = [0u8; RECORD_LEN];
+    let previous = match vars::read(STARTS_VAR, &mut record) {
+        Ok(RECORD_LEN) => Some(Starts::from_bytes(&record)),
+        _ => None,
+    };
+    let now = rtc::now().ok().map(|time| rtc::seconds(&time));
+
+    let starts = Starts::next(previous, now);
+    // Without the variable a loop cannot be seen, but nothing else changes
+    if let Err(e) = vars::write(STARTS_VAR, vars::BOOT_ONLY, &starts.to_bytes()) {
+        crate::util::logger::log_entry(log::Level::Warn, &alloc::format!("Could not count start: {}", e));
+    }
+
+    let tripped = starts.tripped();
+    ACTIVE.set(tripped);
+    tripped
+}
+
+/// Whether this start is in safe mode
+pub fn active() -> bool {
+    ACTIVE.with(|active| *active).unwrap_or(false)
+}
+
+/// This start got somewhere: the next one is a normal one again
+pub fn settle() {
+    let _ = vars::delete(STARTS_VAR);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        for starts in [Starts { count: 2, last: Some(845_000_000) }, Starts { count: 1, last: None }] {
+            assert_eq!(Starts::from_bytes(&starts.to_bytes()), starts);
+        }
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut starts = Starts::next(None, Some(1000));
+        assert_eq!(starts.count, 1);
+        for _ in 0..CRASHES - 1 {
+            starts = Starts::next(Some(starts), starts.last.map(|last| last + 30));
+            assert!(!starts.tripped());
+        }
+        starts = Starts::next(Some(starts), starts.last.map(|last| last + 30));
+        assert!(starts.tripped());
+    }
+
+    #[test]
+    fn test_window() {
+        let previous = Starts { count: CRASHES, last: Some(1000) };
+        assert_eq!(Starts::next(Some(previous), Some(1000 + WINDOW_SECS)).count, CRASHES + 1);
+        assert_eq!(Starts::next(Some(previous), Some(1001 + WINDOW_SECS)).count, 1);
+        // No clock, or one set back: still counted
+        assert_eq!(Starts::next(Some(previous), None).count, CRASHES + 1);
+        assert_eq!(Starts::next(Some(previous), Some(10)).count, CRASHES + 1);
+        assert_eq!(Starts::next(Some(Starts { count: 1, last: None }), Some(1000)).count, 2);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::network::child::RawProtocol;
+use crate::storage::file;
+use crate::util::{authenticode, platform};
+use crate::util::{Error, Result};
+use alloc::format;
+use alloc::string::String;
+use core::ffi::c_void;
+use uefi::boot::{self, SearchType};
+use uefi::{println, Guid, Handle, Status};
+
+/// Shim Lock Protocol GUID, installed by shim for the loader it starts
+/// {605DAB50-E046-4300-ABB6-3DD810DD8B23}
+const SHIM_LOCK_GUID: Guid = Guid::from_bytes([
+    0x50, 0xab, 0x5d, 0x60, 0x46, 0xe0, 0x00, 0x43,
+    0xab, 0xb6, 0x3d, 0xd8, 0x10, 0xdd, 0x8b, 0x23,
+]);
+
+/// Name of the copy of the image written next to shim for it to start
+const STAGED_NAME: &str = "uefipxe-next.efi";
+
+#[repr(C)]
+struct ShimLockProtocol {
+    verify: unsafe extern "efiapi" fn(buffer: *const c_void, size: u32) -> Status,
+    _hash: unsafe extern "efiapi" fn() -> Status,
+    _context: unsafe extern "efiapi" fn() -> Status,
+}
+
+/// What Secure Boot will make of an image, worked out before loading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Assessment {
+    /// Whether the firmware enforces Secure Boot
+    pub enforced: bool,
+    /// Whether the image carries an Authenticode signature (None = not a
+    /// PE/COFF image)
+    pub signed: Option<bool>,
+    /// Whether shim accepts the image with its keys, db and MOK (None =
+    /// we were not started by shim)
+    pub shim: Option<bool>,
+}
+
+impl Assessment {
+    /// Look at `image` with the firmware's Secure Boot state
+    pub fn of(image: &[u8]) -> Self {
+        let enforced = platform::secure_boot_enabled();
+        Assessment {
+            enforced,
+            signed: authenticode::is_signed(image).ok(),
+            shim: if enforced { shim_verify(image) } else { None },
+        }
+    }
+
+    /// One-line summary for the boot output
+    pub fn summary(&self) -> String {
+        let signed = match self.signed {
+            Some(true) => "signed",
+            Some(false) => "not signed",
+            None => "not a PE/COFF image",
+        };
+        match self.shim {
+            Some(true) => format!("image {}, trusted by shim", signed),
+            Some(false) => format!("image {}, not trusted by shim", signed),
+            None => format!("image {}", signed),
+        }
+    }
+
+    /// What to do about the firmware refusing the image
+    pub fn advice(&self, shim_configured: bool) -> &'static str {
+        match (self.signed, self.shim) {
+            (None, _) => "Secure Boot only starts signed EFI executables; check that the entry points at one",
+            (Some(false), _) => {
+                "The image is not signed: sign it (e.g. sbsign) with a key in db or enrolled in MOK, or turn Secure Boot off in firmware setup"
+            }
+            (Some(true), Some(true)) if !shim_configured => {
+                "Shim trusts the image but the firmware does not: set shim=<path of shimx64.efi on the ESP> to start it through shim"
+            }
+            (Some(true), Some(true)) => {
+                "Shim trusts the image: check that shim= names a shim on a local volume that the firmware accepts"
+            }
+            (Some(true), _) if shim_configured => {
+                "Neither the firmware nor shim trusts the signing key: enroll it in MOK (mokutil --import) and reboot to confirm"
+            }
+            (Some(true), _) => {
+                "The signing key is not in db: enroll it there, or in MOK (mokutil --import) and set shim=<path of shimx64.efi on the ESP>"
+            }
+        }
+    }
+}
+
+/// Whether a LoadImage failure is Secure Boot refusing the image
+pub fn refused(status: Status) -> bool {
+    matches!(status, Status::SECURITY_VIOLATION | Status::ACCESS_DENIED)
+}
+
+/// Ask shim, when it started us, whether it accepts `image`
+fn shim_verify(image: &[u8]) -> Option<bool> {
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SHIM_LOCK_GUID)).ok()?;
+    let shim = RawProtocol::<ShimLockProtocol>::open(*handles.first()?, &SHIM_LOCK_GUID).ok()?;
+    let size = u32::try_from(image.len()).ok()?;
+    let status = unsafe { ((*shim.as_ptr()).verify)(image.as_ptr().cast(), size) };
+    Some(!status.is_error())
+}
+
+/// The copy of an image written next to shim, deleted again when dropped
+pub struct Staged {
+    volume: Handle,
+    path: String,
+}
+
+impl Staged {
+    /// Path shim is told to start, on its own volume
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        let _ = file::delete_on(self.volume, &self.path);
+    }
+}
+
+/// Load the shim at `shim_path` on a local volume, having written `image`
+/// next to it for shim to verify against MOK and start
+///
+/// Shim starts the file named first in its load options, so the caller
+/// passes `Staged::path` there, ahead of the image's command line.
+pub fn load_via_shim(shim_path: &str, image: &[u8]) -> Result<(Handle, Staged)> {
+    let volume = file::volume_with(shim_path).map_err(|e| {
+        println!("  Shim not found on any volume: {}", shim_path);
+        e
+    })?;
+
+    let staged = Staged { volume, path: staged_path(shim_path) };
+    file::write_on(volume, &staged.path, image).map_err(|e| {
+        println!("  Could not write the image next to shim: {}", e);
+        e
+    })?;
+
+    let shim = file::read_image_on(volume, shim_path)?;
+    let device_path = file::device_path_on(volume, shim_path)?;
+    let handle = unsafe {
+        boot::load_image(
+            boot::image_handle(),
+            boot::LoadImageSource::FromBuffer { buffer: &shim, file_path: Some(&*device_path) },
+        )
+    }
+    .map_err(|e| {
+        println!("  Firmware refused shim as well: {:?}", e.status());
+        Error::Uefi(e.status())
+    })?;
+    Ok((handle, staged))
+}
+
+/// Where the image goes for the shim at `shim_path`: the same directory
+fn staged_path(shim_path: &str) -> String {
+    let directory = &shim_path[..shim_path.rfind('\\').map_or(0, |i| i + 1)];
+    format!("{}{}", directory, STAGED_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_staged_path() {
+        assert_eq!(staged_path("\\EFI\\uefipxe\\shimx64.efi"), "\\EFI\\uefipxe\\uefipxe-next.efi");
+        assert_eq!(staged_path("\\shimx64.efi"), "\\uefipxe-next.efi");
+    }
+
+    #[test]
+    fn test_advice() {
+        let unsigned = Assessment { enforced: true, signed: Some(false), shim: None };
+        assert!(unsigned.advice(false).contains("not signed"));
+        assert_eq!(unsigned.summary(), "image not signed");
+
+        let mok = Assessment { enforced: true, signed: Some(true), shim: Some(true) };
+        assert!(mok.advice(false).contains("set shim="));
+        assert!(mok.advice(true).contains("check that shim="));
+        assert_eq!(mok.summary(), "image signed, trusted by shim");
+
+        let unknown_key = Assessment { enforced: true, signed: Some(true), shim: Some(false) };
+        assert!(unknown_key.advice(true).contains("mokutil"));
+        assert!(unknown_key.advice(false).contains("not in db"));
+
+        let not_pe = Assessment { enforced: true, signed: None, shim: None };
+        assert!(not_pe.advice(false).contains("EFI executables"));
+    }
+
+    #[test]
+    fn test_refused() {
+        assert!(refused(Status::SECURITY_VIOLATION));
+        assert!(refused(Status::ACCESS_DENIED));
+        assert!(!refused(Status::LOAD_ERROR));
+    }
+}
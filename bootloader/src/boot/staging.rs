@@ -0,0 +1,199 @@
+use crate::storage::vars;
+use crate::util::global::Global;
+use crate::util::sha256;
+use crate::util::{Error, Result};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+use uefi::boot::{self, AllocateType, MemoryType};
+use uefi::cstr16;
+
+/// Where the staged image is, so a start after a warm reset can find it
+///
+/// Boot-services-only like the other records, so the OS cannot point us
+/// at memory of its choosing.
+const STAGED_VAR: &uefi::CStr16 = cstr16!("StagedImage");
+
+/// Record layout: address and length (u64 LE each), SHA-256 of the image,
+/// then the entry URL and the signature it was verified with (empty when
+/// unsigned), each preceded by its length as u16 LE
+const HEADER_LEN: usize = 8 + 8 + 32;
+
+/// Largest record read back
+const MAX_RECORD_LEN: usize = 4096;
+
+const PAGE_SIZE: usize = 4096;
+
+/// A staged image: where it lies, what it hashes to and what it was
+/// verified as
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Staged {
+    address: u64,
+    len: u64,
+    sha256: [u8; 32],
+    url: String,
+    signature: String,
+}
+
+impl Staged {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut record = Vec::with_capacity(HEADER_LEN + 4 + self.url.len() + self.signature.len());
+        record.extend_from_slice(&self.address.to_le_bytes());
+        record.extend_from_slice(&self.len.to_le_bytes());
+        record.extend_from_slice(&self.sha256);
+        for text in [&self.url, &self.signature] {
+            record.extend_from_slice(&(text.len() as u16).to_le_bytes());
+            record.extend_from_slice(text.as_bytes());
+        }
+        record
+    }
+
+    fn from_bytes(record: &[u8]) -> Option<Self> {
+        let u64_at = |at: usize| record.get(at..at + 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()));
+        let mut sha256 = [0u8; 32];
+        sha256.copy_from_slice(record.get(16..HEADER_LEN)?);
+
+        let mut rest = &record[HEADER_LEN..];
+        let mut text = || {
+            let len = usize::from(u16::from_le_bytes(rest.get(..2)?.try_into().unwrap()));
+            let text = core::str::from_utf8(rest.get(2..2 + len)?).ok()?;
+            rest = &rest[2 + len..];
+            Some(String::from(text))
+        };
+        let (url, signature) = (text()?, text()?);
+        Some(Staged { address: u64_at(0)?, len: u64_at(8)?, sha256, url, signature })
+    }
+
+    fn pages(&self) -> usize {
+        (self.len as usize).div_ceil(PAGE_SIZE)
+    }
+
+    /// Whether this is the image for `url` verified with `signature`
+    fn is_for(&self, url: &str, signature: Option<&str>) -> bool {
+        self.url == url && self.signature == signature.unwrap_or("")
+    }
+}
+
+/// The staged image this start holds the pages of
+static HELD: Global<Staged> = Global::new();
+
+/// Whether `stage_image=true`
+pub fn enabled() -> bool {
+    crate::storage::with_config(|config| config.stage_image).unwrap_or(false)
+}
+
+/// Keep `data`, verified as `url` with `signature`, in pages of its own
+/// and note where in a variable
+///
+/// The pages are loader data: the OS gets them back, and a warm reset
+/// leaves them as they are on most firmware, which the hash check on the
+/// way back in relies on rather than trusts.
+pub fn stage(url: &str, signature: Option<&str>, data: &[u8]) -> Result<()> {
+    let sha256 = sha256::digest(data);
+    if HELD.with(|held| held.is_for(url, signature) && held.sha256 == sha256) == Some(true) {
+        return Ok(());
+    }
+    release();
+
+    let pages = data.len().div_ceil(PAGE_SIZE);
+    let region =
+        boot::allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, pages).map_err(|_| Error::OutOfMemory)?;
+    unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), region.as_ptr(), data.len()) };
+
+    let staged = Staged {
+        address: region.as_ptr() as u64,
+        len: data.len() as u64,
+        sha256,
+        url: String::from(url),
+        signature: String::from(signature.unwrap_or("")),
+    };
+    let record = staged.to_bytes();
+    if record.len() > MAX_RECORD_LEN {
+        unsafe {
+            let _ = boot::free_pages(region, pages);
+        }
+        return Err(Error::BufferTooSmall);
+    }
+    HELD.set(staged);
+    vars::write(STAGED_VAR, vars::BOOT_ONLY, &record)
+}
+
+/// A copy of the image staged for `url` and `signature`, if one is held
+/// or survived the reset and is intact
+pub fn take(url: &str, signature: Option<&str>) -> Option<Vec<u8>> {
+    if HELD.with(|_| ()).is_none() {
+        reclaim();
+    }
+    HELD.with(|held| {
+        if !held.is_for(url, signature) {
+            return None;
+        }
+        let image = unsafe { core::slice::from_raw_parts(held.address as *const u8, held.len as usize) };
+        Some(image.to_vec()).filter(|image| sha256::digest(image) == held.sha256)
+    })
+    .flatten()
+}
+
+/// Take back the pages a previous start staged its image in
+fn reclaim() {
+    let mut record = alloc::vec![0u8; MAX_RECORD_LEN];
+    let Some(staged) = vars::read(STAGED_VAR, &mut record).ok().and_then(|len| Staged::from_bytes(&record[..len]))
+    else {
+        return;
+    };
+
+    // Firmware that used the memory since has it allocated; then the
+    // image is gone
+    if let Ok(region) =
+        boot::allocate_pages(AllocateType::Address(staged.address), MemoryType::LOADER_DATA, staged.pages())
+    {
+        let image = unsafe { core::slice::from_raw_parts(region.as_ptr(), staged.len as usize) };
+        if sha256::digest(image) == staged.sha256 {
+            HELD.set(staged);
+            return;
+        }
+        unsafe {
+            let _ = boot::free_pages(region, staged.pages());
+        }
+    }
+    let _ = vars::delete(STAGED_VAR);
+}
+
+/// Give up the staged image, if any
+pub fn release() {
+    if let Some(held) = HELD.with(|held| held.clone()) {
+        if let Some(region) = NonNull::new(held.address as *mut u8) {
+            unsafe {
+                let _ = boot::free_pages(region, held.pages());
+            }
+        }
+        HELD.clear();
+    }
+    let _ = vars::delete(STAGED_VAR);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let staged = Staged {
+            address: 0x7e00_0000,
+            len: 8_388_609,
+            sha256: [0xab; 32],
+            url: String::from("http://boot.example.com/vmlinuz.efi"),
+            signature: String::from("sha256:00ff"),
+        };
+        assert_eq!(Staged::from_bytes(&staged.to_bytes()), Some(staged.clone()));
+        assert_eq!(staged.pages(), 2049);
+        assert!(staged.is_for("http://boot.example.com/vmlinuz.efi", Some("sha256:00ff")));
+        assert!(!staged.is_for("http://boot.example.com/vmlinuz.efi", None));
+
+        let unsigned = Staged { signature: String::new(), ..staged };
+        assert!(unsigned.is_for("http://boot.example.com/vmlinuz.efi", None));
+        let bytes = unsigned.to_bytes();
+        assert_eq!(Staged::from_bytes(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(Staged::from_bytes(&bytes[..HEADER_LEN - 1]), None);
+    }
+}
@@ -0,0 +1,140 @@
+use super::commands::Command;
+use super::repl;
+use crate::storage;
+use crate::util::global::Global;
+use crate::util::password::PasswordHash;
+use core::time::Duration;
+use uefi::println;
+
+/// Wrong passwords in a row before `login` gives up
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Pause after a wrong password, to slow down guessing
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// What the person at the console may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Every command, including changes to the configuration and keys
+    Admin,
+    /// Reports such as `list` and `status`, `boot` and the menu
+    Operator,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Operator => "operator",
+        }
+    }
+}
+
+/// Role the console was logged in as (unset until someone logged in)
+static SESSION: Global<Role> = Global::new();
+
+/// Password hashes from the configuration, admin first
+fn hashes() -> (Option<PasswordHash>, Option<PasswordHash>) {
+    storage::with_config(|config| {
+        let parse = |value: &Option<_>| value.as_deref().and_then(|value| PasswordHash::parse(value).ok());
+        (parse(&config.admin_password), parse(&config.operator_password))
+    })
+    .unwrap_or_default()
+}
+
+/// Whether the CLI asks for a password (`admin_password=` is set)
+pub fn required() -> bool {
+    storage::with_config(|config| config.admin_password.is_some()).unwrap_or(false)
+}
+
+/// The role the console has: admin without passwords, None while nobody
+/// has logged in
+pub fn role() -> Option<Role> {
+    if !required() {
+        return Some(Role::Admin);
+    }
+    SESSION.with(|role| *role)
+}
+
+/// Whether the console may run `command` typed at the prompt or picked in
+/// the menu
+///
+/// Before anyone logged in, the menu may still boot what it shows.
+/// Scripts and hooks come from the configuration and are not checked.
+pub fn permits(command: &Command) -> bool {
+    role() == Some(Role::Admin) || command.is_operator()
+}
+
+/// The role `password` logs in as, if any
+fn role_of(password: &[u8], admin: Option<&PasswordHash>, operator: Option<&PasswordHash>) -> Option<Role> {
+    if admin.is_some_and(|admin| admin.matches(password)) {
+        Some(Role::Admin)
+    } else if admin.is_some() && operator.is_some_and(|operator| operator.matches(password)) {
+        Some(Role::Operator)
+    } else {
+        None
+    }
+}
+
+/// Ask for a password and log in with the role it belongs to
+///
+/// Gives up after `MAX_ATTEMPTS` wrong ones, leaving the role as it was.
+pub fn login() -> Option<Role> {
+    let (admin, operator) = hashes();
+    for attempt in 1..=MAX_ATTEMPTS {
+        // Wiped once this attempt is over
+        let password = match repl::secret("Password: ") {
+            Ok(password) => password,
+            Err(_) => break,
+        };
+
+        if let Some(role) = role_of(&password, admin.as_ref(), operator.as_ref()) {
+            SESSION.set(role);
+            println!("Logged in as {}", role.as_str());
+            crate::util::logger::log_entry(log::Level::Info, &alloc::format!("Logged in as {}", role.as_str()));
+            return Some(role);
+        }
+        crate::util::logger::log_entry(log::Level::Warn, "Wrong CLI password");
+        if attempt < MAX_ATTEMPTS {
+            uefi::boot::stall(RETRY_DELAY);
+            println!("Wrong password, try again");
+        }
+    }
+    println!("Wrong password");
+    None
+}
+
+/// Make sure the console has the admin role, asking for the admin
+/// password when it does not
+pub fn admin() -> bool {
+    if role() == Some(Role::Admin) {
+        return true;
+    }
+    println!("This needs the admin password");
+    login() == Some(Role::Admin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_of() {
+        let admin = PasswordHash::parse(
+            "pbkdf2-sha256$10000$a1b2c3d4e5f60718$9af95e08f8b55543e29ad7bcfdcec9cdcbc80e54fcf7ece830b268f51eb53e33",
+        )
+        .unwrap();
+        let operator = PasswordHash::parse(
+            "pbkdf2-sha256$10000$0f1e2d3c4b5a6978$94b265f1108c5bd14e294ba9054b6acaec889fe2560375d1838a2de828515015",
+        )
+        .unwrap();
+        let (admin, operator) = (Some(&admin), Some(&operator));
+        assert_eq!(role_of(b"admin", admin, operator), Some(Role::Admin));
+        assert_eq!(role_of(b"operator", admin, operator), Some(Role::Operator));
+        assert_eq!(role_of(b"guess", admin, operator), None);
+        assert_eq!(role_of(b"ADMIN", admin, None), None);
+
+        // An operator password alone does not open anything
+        assert_eq!(role_of(b"operator", None, operator), None);
+    }
+}
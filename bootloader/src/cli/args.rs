@@ -0,0 +1,164 @@
+use super::commands::ControlFlow;
+use super::parser::parse_words;
+use crate::util::{ucs2, Error, Result};
+use heapless::{String, Vec};
+use uefi::boot;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::{println, Status};
+
+/// Maximum number of command line arguments
+pub const MAX_ARGS: usize = 16;
+
+/// Maximum length of a single argument
+pub const MAX_ARG_LEN: usize = 256;
+
+pub type Args = Vec<String<MAX_ARG_LEN>, MAX_ARGS>;
+
+/// Split a command line the way the UEFI Shell does
+///
+/// Arguments are separated by spaces or tabs, double quotes group text
+/// containing spaces, and `^` escapes the following character.
+pub fn split_args(cmdline: &str) -> Result<Args> {
+    let mut args = Vec::new();
+    let mut current: String<MAX_ARG_LEN> = String::new();
+    let mut in_arg = false;
+    let mut in_quotes = false;
+    let mut chars = cmdline.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '^' => {
+                let escaped = chars.next().ok_or(Error::Parse)?;
+                current.push(escaped).map_err(|_| Error::BufferTooSmall)?;
+                in_arg = true;
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                in_arg = true;
+            }
+            ' ' | '\t' if !in_quotes => {
+                if in_arg {
+                    args.push(core::mem::take(&mut current)).map_err(|_| Error::OutOfMemory)?;
+                    in_arg = false;
+                }
+            }
+            '\0' => break,
+            c => {
+                current.push(c).map_err(|_| Error::BufferTooSmall)?;
+                in_arg = true;
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err(Error::Parse);
+    }
+    if in_arg {
+        args.push(current).map_err(|_| Error::OutOfMemory)?;
+    }
+
+    Ok(args)
+}
+
+/// Drop the program name the shell passes as the first argument
+///
+/// Boot manager entries usually pass only the arguments, so the first
+/// argument is only treated as the program name if it looks like one.
+pub fn strip_program_name(args: &mut Args) {
+    let is_program = args.first().is_some_and(|first| {
+        let lower = first.to_ascii_lowercase();
+        lower.ends_with(".efi") || lower == "uefipxe"
+    });
+
+    if is_program {
+        args.remove(0);
+    }
+}
+
+/// Read our LoadOptions as text, if they are a UCS-2 string
+pub fn load_options() -> Option<alloc::string::String> {
+    let image = boot::open_protocol_exclusive::<LoadedImage>(boot::image_handle()).ok()?;
+    ucs2::decode_le_bytes(image.load_options_as_bytes()?).ok()
+}
+
+/// Command line arguments we were started with (program name removed)
+pub fn command_line() -> Result<Args> {
+    match load_options() {
+        Some(options) => {
+            let mut args = split_args(&options)?;
+            strip_program_name(&mut args);
+            Ok(args)
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Run a single command given on the command line, without the REPL
+///
+/// The arguments go to the parser as they were split, so a quoted one
+/// (e.g. an entry name with spaces) stays one argument. The returned
+/// status is what a calling startup.nsh sees in %lasterror%.
+pub fn execute(args: &Args) -> Status {
+    let words: alloc::vec::Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    crate::util::logger::log_entry(
+        log::Level::Info,
+        &alloc::format!("Command line: {}", words.join(" ")),
+    );
+
+    let result = match parse_words(words.into_iter()) {
+        Ok(cmd) => cmd.execute(),
+        Err(e) => {
+            println!("Invalid command line. Type 'help' for usage information.");
+            Err(e)
+        }
+    };
+
+    match result {
+        Ok(ControlFlow::Continue) => Status::SUCCESS,
+        Ok(ControlFlow::Exit(status)) => status,
+        Err(e) => {
+            println!("Error: {}", e);
+            crate::util::logger::log_entry(
+                log::Level::Error,
+                &alloc::format!("Command line error: {}", e),
+            );
+            e.status()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_args() {
+        let args = split_args("uefipxe.efi boot  0").unwrap();
+        assert_eq!(args.len(), 3);
+        assert_eq!(args[0].as_str(), "uefipxe.efi");
+        assert_eq!(args[1].as_str(), "boot");
+        assert_eq!(args[2].as_str(), "0");
+    }
+
+    #[test]
+    fn test_split_quotes_and_escapes() {
+        let args = split_args("add \"http://host/a b.efi\" ^\"x^^").unwrap();
+        assert_eq!(args.len(), 3);
+        assert_eq!(args[1].as_str(), "http://host/a b.efi");
+        assert_eq!(args[2].as_str(), "\"x^");
+        assert!(split_args("add \"unterminated").is_err());
+        assert!(split_args("trailing^").is_err());
+    }
+
+    #[test]
+    fn test_strip_program_name() {
+        let mut args = split_args("FS0:\\EFI\\uefipxe\\UEFIPXE.EFI boot 0").unwrap();
+        strip_program_name(&mut args);
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[0].as_str(), "boot");
+
+        let mut args = split_args("boot 0").unwrap();
+        strip_program_name(&mut args);
+        assert_eq!(args.len(), 2);
+    }
+}
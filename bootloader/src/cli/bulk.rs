@@ -0,0 +1,137 @@
+use super::commands::Command;
+use crate::network::{url, verify};
+use crate::storage::config::{MAX_NAME_LEN, MAX_URL_LEN};
+use crate::storage::{self, file, policy};
+use crate::util::{Error, Result};
+use uefi::println;
+
+/// One `url[,sha256][,name]` line of an image list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub url: &'a str,
+    pub sha256: Option<&'a str>,
+    pub name: Option<&'a str>,
+}
+
+/// Parse a list line; None for blank lines and `#` comments
+///
+/// Only the first two commas split fields, so names may contain commas.
+pub fn parse_record(line: &str) -> core::result::Result<Option<Record<'_>>, &'static str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut fields = line.splitn(3, ',').map(str::trim);
+    let url = fields.next().unwrap_or("");
+    let sha256 = fields.next().filter(|hash| !hash.is_empty());
+    let name = fields.next().filter(|name| !name.is_empty());
+
+    if url.len() > MAX_URL_LEN || url::parse(url).is_err() {
+        return Err("invalid URL");
+    }
+    if sha256.is_some_and(|hash| !verify::find(verify::DEFAULT).is_some_and(|sha256| sha256.accepts(hash))) {
+        return Err("SHA256 must be 64 hex characters");
+    }
+    if name.is_some_and(|name| name.len() > MAX_NAME_LEN) {
+        return Err("name too long");
+    }
+
+    Ok(Some(Record { url, sha256, name }))
+}
+
+/// Add every entry of a list on the ESP, a local volume or the network
+///
+/// Entries already configured (same URL or name) are skipped, so the same
+/// list can be applied again after it grew. Invalid lines are reported and
+/// skipped; the command fails if there were any.
+pub fn add_from(source: &str) -> Result<()> {
+    let data = if url::parse(source).is_ok() { Command::fetch(source)? } else { file::read_file(source)? };
+    let text = core::str::from_utf8(&data).map_err(|_| Error::Parse)?;
+    let (added, skipped, invalid, total) = storage::with_config_mut(|config| -> Result<_> {
+        let (mut added, mut skipped, mut invalid) = (0, 0, 0);
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+            let record = match parse_record(line) {
+                Ok(Some(record)) => record,
+                Ok(None) => continue,
+                Err(reason) => {
+                    println!("  Line {}: {}", number, reason);
+                    invalid += 1;
+                    continue;
+                }
+            };
+            if let Err(violation) = policy::check_url(record.url) {
+                println!("  Line {}: refused by boot policy: {}", number, violation.as_str());
+                invalid += 1;
+                continue;
+            }
+            if let Some(index) = config.find_duplicate(record.url, record.name) {
+                println!("  Line {}: already configured as [{}]", number, index);
+                skipped += 1;
+                continue;
+            }
+            if config.is_full() {
+                println!("  Line {}: configuration is full, stopping here", number);
+                invalid += 1;
+                break;
+            }
+
+            config.add_url_with_signature(record.url, record.sha256.unwrap_or(""))?;
+            if let Some(name) = record.name {
+                config.set_name(config.urls.len() - 1, name)?;
+            }
+            added += 1;
+        }
+        Ok((added, skipped, invalid, config.urls.len()))
+    })
+    .ok_or(Error::Unknown)??;
+
+    let summary = alloc::format!(
+        "Added {} entries from {} ({} already configured, {} invalid)",
+        added,
+        source,
+        skipped,
+        invalid
+    );
+    println!("{}", summary);
+    crate::util::logger::log_entry(log::Level::Info, &summary);
+    if added > 0 {
+        println!("Total images: {}", total);
+        println!("Remember to run 'save' to persist changes to ESP");
+    }
+
+    if invalid > 0 {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_record() {
+        let hash = "a3b2c1d4e5f6abcd1234567890abcdef1234567890abcdef1234567890abcdef";
+
+        assert_eq!(parse_record("   "), Ok(None));
+        assert_eq!(parse_record("# seed list"), Ok(None));
+        assert_eq!(
+            parse_record("http://a/x.efi"),
+            Ok(Some(Record { url: "http://a/x.efi", sha256: None, name: None }))
+        );
+        assert_eq!(
+            parse_record(&alloc::format!("http://a/x.efi, {} ,Ubuntu 24.04, LTS", hash)),
+            Ok(Some(Record { url: "http://a/x.efi", sha256: Some(hash), name: Some("Ubuntu 24.04, LTS") }))
+        );
+        assert_eq!(
+            parse_record("http://a/x.efi,,Rescue"),
+            Ok(Some(Record { url: "http://a/x.efi", sha256: None, name: Some("Rescue") }))
+        );
+
+        assert_eq!(parse_record("a/x.efi,,Rescue"), Err("invalid URL"));
+        assert_eq!(parse_record("http://a/x.efi,abcd"), Err("SHA256 must be 64 hex characters"));
+        let long_name = alloc::format!("http://a/x.efi,,{}", "n".repeat(MAX_NAME_LEN + 1));
+        assert_eq!(parse_record(&long_name), Err("name too long"));
+    }
+}
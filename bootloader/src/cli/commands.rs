@@ -1,155 +1,927 @@
+use super::access;
+use super::keymap::{self, Layout};
+use crate::boot::entry_type::{self, EntryType};
+use crate::boot::health::{self, Health};
+use crate::boot::ImageArgs;
+use crate::boot::report::{self, Entry, Stage};
+use crate::boot::rescue;
+use crate::boot::rollback;
+use crate::boot::staging;
+use crate::network::addr::Mac;
+use crate::network::http::{Body, UploadMethod};
+use crate::network::url::Scheme;
 use crate::storage;
+use crate::storage::config::{MAX_CMDLINE_LEN, MAX_NAME_LEN};
+use crate::storage::Store;
+use crate::util::beep::{self, BeepMode, Signal};
+use crate::util::capture;
+use crate::util::logger::{self, Echo};
+use crate::util::metrics::{self, Phase, Timer};
+use crate::util::platform::Platform;
+use crate::util::rtc::{self, DateTime};
+use crate::util::ui::{self, UiMode};
+use crate::util::wipe::Wiped;
 use crate::util::{Error, Result};
+use crate::{detail, say};
 use heapless::String;
+use uefi::Status;
 
 const MAX_URL_LEN: usize = 256;
 
+/// Maximum length of a host name given to `auth`
+pub const MAX_HOST_LEN: usize = 128;
+
+/// Maximum length of a `list` filter
+pub const MAX_FILTER_LEN: usize = 64;
+
+/// Entries shown per page by `list`
+const LIST_PAGE_SIZE: usize = 20;
+
+/// What an entry starts its image with, besides the image itself
+#[derive(Debug, Clone, Copy, Default)]
+struct BootExtras<'a> {
+    /// What the image is, and so how it is started
+    entry_type: EntryType,
+    /// Load options (a Linux kernel command line)
+    cmdline: Option<&'a str>,
+    /// Absolute URL of the initrd to download alongside
+    initrd: Option<&'a str>,
+    /// SHA256 the initrd must have
+    initrd_sha256: Option<&'a str>,
+    /// Absolute URL of a detached Ed25519 signature of the image
+    sig: Option<&'a str>,
+}
+
+/// What the CLI should do after a command has run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlFlow {
+    /// Keep reading commands
+    Continue,
+    /// Leave the CLI and return this status to the firmware
+    Exit(Status),
+}
+
+/// Settings that can be changed with `set`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    /// Console keyboard layout
+    Keymap(Layout),
+    /// Console output style
+    Ui(UiMode),
+    /// How much progress output reaches the console
+    Echo(Echo),
+    /// How finished downloads and failed boots are sounded
+    Beep(BeepMode),
+}
+
+/// What `policy` should do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyAction {
+    /// Show the policy in force
+    Show,
+    /// Pin the current policy.txt
+    Trust,
+    /// Remove the pin
+    Forget,
+}
+
+/// What `auth` should do with a server's sealed credential
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthAction {
+    /// Prompt for an Authorization value and seal it to these PCRs
+    Seal(String<MAX_HOST_LEN>, u32),
+    /// Check that the credential still unseals
+    Test(String<MAX_HOST_LEN>),
+    /// Delete the credential
+    Forget(String<MAX_HOST_LEN>),
+}
+
+/// What `net` should do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetAction {
+    /// List the network interfaces
+    List,
+    /// Run DHCP and downloads on this interface (index in `net list`)
+    Use(usize),
+    /// Ask the PXE servers for a boot file and add it as an entry
+    Pxe,
+}
+
 /// Available CLI commands
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
     /// Display help
     Help,
-    /// List all configured image URLs
-    List,
-    /// Add a new image URL
-    Add(String<MAX_URL_LEN>),
+    /// List configured image URLs, optionally filtered
+    List(Option<String<MAX_FILTER_LEN>>),
+    /// Add a new image URL with an optional name; with `--replace`, update
+    /// the entry with the same URL or name instead
+    Add(String<MAX_URL_LEN>, Option<String<MAX_NAME_LEN>>, bool),
+    /// Add every entry of a `url[,sha256][,name]` list (ESP path or URL)
+    AddFrom(String<MAX_URL_LEN>),
     /// Remove an image URL by index
     Remove(usize),
-    /// Boot an image by index
-    Boot(usize),
+    /// Take an entry out of the fallback rotation
+    Disable(usize),
+    /// Put a disabled entry back into the fallback rotation
+    Enable(usize),
+    /// Show the details of one entry
+    Show(usize),
+    /// Boot an image by index, or try entries in fallback order
+    Boot(Option<usize>),
+    /// Boot a linux entry once with its kernel command line edited first
+    BootEdited(usize),
+    /// Boot the rescue image compiled into the bootloader
+    BootRescue,
     /// Set default boot image
     Default(usize),
-    /// Save configuration to ESP
+    /// Save configuration to its backend (ESP or NVRAM)
     Save,
+    /// Upload the serialized configuration to a URL
+    ConfigPush(String<MAX_URL_LEN>, UploadMethod),
+    /// Upload a file from the ESP to a URL
+    Upload(String<MAX_URL_LEN>, String<MAX_URL_LEN>, UploadMethod),
+    /// Show or change strict config integrity checking
+    ConfigStrict(Option<bool>),
+    /// Show or change where the configuration is saved
+    ConfigStorage(Option<Store>),
+    /// Show, pin or unpin the verified boot policy
+    Policy(PolicyAction),
+    /// Show the minimum image version, or raise it
+    Rollback(Option<u32>),
+    /// Manage TPM-sealed HTTP credentials
+    Auth(AuthAction),
+    /// Export a diagnostics bundle to the ESP or a URL
+    DiagExport(Option<String<MAX_URL_LEN>>),
+    /// Interactive network setup wizard
+    NetSetup,
+    /// List the network interfaces, or pick one
+    Net(NetAction),
+    /// Look up the IPv4 address of a host name
+    Resolve(String<MAX_HOST_LEN>),
+    /// Full-screen boot menu
+    Menu,
+    /// Ask for a password and take the role it belongs to
+    Login,
+    /// Run a script from the ESP
+    Run(String<MAX_URL_LEN>),
+    /// Change a setting
+    Set(Setting),
+    /// Show bootloader and firmware status
+    Status,
+    /// Show size and free space of every filesystem
+    Df,
+    /// Show the firmware clock, or set it
+    Date(Option<DateTime>),
+    /// Run the built-in self-test
+    SelfTest,
+    /// Show version and build metadata
+    Version,
     /// Test network connectivity
     TestNetwork,
     /// Display log messages
     Logs,
+    /// Append buffered log messages to the boot log on the ESP
+    LogsSave,
+    /// Delete the boot log from the ESP
+    LogsClearFile,
+    /// List the commands typed at the prompt
+    History,
+    /// Forget them, including the history file on the ESP
+    HistoryClear,
+    /// List the images in the cache
+    CacheList,
     /// Exit to firmware
     Exit,
 }
 
 impl Command {
+    /// Whether the command only prints a report, so its output may be
+    /// redirected to a file
+    pub fn is_report(&self) -> bool {
+        matches!(
+            self,
+            Command::Help
+                | Command::List(_)
+                | Command::Show(_)
+                | Command::Policy(PolicyAction::Show)
+                | Command::Rollback(None)
+                | Command::Net(NetAction::List)
+                | Command::Resolve(_)
+                | Command::Status
+                | Command::Df
+                | Command::Date(None)
+                | Command::SelfTest
+                | Command::Version
+                | Command::TestNetwork
+                | Command::Logs
+                | Command::History
+                | Command::CacheList
+        )
+    }
+
+    /// Whether an operator may run the command: reports, booting as
+    /// configured, the menu and logging in
+    pub fn is_operator(&self) -> bool {
+        self.is_report() || matches!(self, Command::Boot(_) | Command::BootRescue | Command::Menu | Command::Login)
+    }
+
+    /// Whether the command boots an image or leaves the CLI, which an
+    /// entry's hook must not do
+    pub fn starts_boot(&self) -> bool {
+        matches!(self, Command::Boot(_) | Command::BootEdited(_) | Command::BootRescue | Command::Menu | Command::Exit)
+    }
+
     /// Execute the command
-    pub fn execute(&self) -> Result<()> {
-        match self {
+    pub fn execute(&self) -> Result<ControlFlow> {
+        let result = match self {
             Command::Help => {
                 Self::print_help();
                 Ok(())
             }
-            Command::List => Self::exec_list(),
-            Command::Add(url) => Self::exec_add(url),
+            Command::List(filter) => Self::exec_list(filter.as_deref()),
+            Command::Add(url, name, replace) => Self::exec_add(url, name.as_deref(), *replace),
+            Command::AddFrom(source) => super::bulk::add_from(source),
             Command::Remove(index) => Self::exec_remove(*index),
-            Command::Boot(index) => Self::exec_boot(*index),
+            Command::Disable(index) => Self::exec_set_disabled(*index, true),
+            Command::Enable(index) => Self::exec_set_disabled(*index, false),
+            Command::Show(index) => Self::exec_show(*index),
+            Command::Boot(Some(index)) => Self::exec_boot(*index, None),
+            Command::BootEdited(index) => Self::exec_boot_edited(*index),
+            Command::Boot(None) => Self::exec_boot_fallback(),
+            Command::BootRescue => Self::exec_boot_rescue(),
             Command::Default(index) => Self::exec_default(*index),
             Command::Save => Self::exec_save(),
+            Command::ConfigPush(url, method) => Self::exec_config_push(url, *method),
+            Command::Upload(path, url, method) => Self::exec_upload(path, url, *method),
+            Command::ConfigStrict(strict) => Self::exec_config_strict(*strict),
+            Command::ConfigStorage(store) => Self::exec_config_storage(*store),
+            Command::Policy(action) => Self::exec_policy(*action),
+            Command::Rollback(version) => Self::exec_rollback(*version),
+            Command::Auth(action) => Self::exec_auth(action),
+            Command::DiagExport(target) => Self::exec_diag_export(target.as_deref()),
+            Command::NetSetup => super::netsetup::run(),
+            Command::Net(action) => Self::exec_net(*action),
+            Command::Resolve(host) => Self::exec_resolve(host),
+            Command::Menu => super::menu::run(None),
+            Command::Login => Self::exec_login(),
+            Command::Run(path) => return super::script::run_file(path),
+            Command::Set(setting) => Self::exec_set(*setting),
+            Command::Status => Self::exec_status(),
+            Command::Df => Self::exec_df(),
+            Command::Date(set) => Self::exec_date(set.as_ref()),
+            Command::SelfTest => Self::exec_selftest(),
+            Command::Version => {
+                Self::print_version();
+                Ok(())
+            }
             Command::TestNetwork => Self::exec_test_network(),
             Command::Logs => Self::exec_logs(),
-            Command::Exit => Self::exec_exit(),
-        }
+            Command::LogsSave => Self::exec_logs_save(),
+            Command::LogsClearFile => Self::exec_logs_clear_file(),
+            Command::History => Self::exec_history(),
+            Command::HistoryClear => Self::exec_history_clear(),
+            Command::CacheList => Self::exec_cache_list(),
+            Command::Exit => return Self::exec_exit(),
+        };
+
+        result.map(|_| ControlFlow::Continue)
     }
 
     fn print_help() {
         uefi::println!();
-        uefi::println!("Available Commands:");
-        uefi::println!("==================");
+        ui::heading("Available Commands:");
         uefi::println!("  help                 - Display this help message");
-        uefi::println!("  list                 - List all configured image URLs");
-        uefi::println!("  add <url>            - Add a new image URL");
+        uefi::println!("  list [filter]        - List image URLs (filter on name, URL or tag)");
+        uefi::println!("  add [--replace] <url> [name] - Add a new image URL (--replace updates");
+        uefi::println!("                         the entry with the same URL or name)");
+        uefi::println!("  add --from <path|url> - Add every url[,sha256][,name] line of a list");
         uefi::println!("  remove <index>       - Remove image URL by index");
-        uefi::println!("  boot <index>         - Download and boot image");
+        uefi::println!("  disable <index>      - Leave an entry out of booting without an index");
+        uefi::println!("  enable <index>       - Put a disabled entry back");
+        uefi::println!("  show <index>         - Show entry details and boot health");
+        uefi::println!("  boot [index]         - Download and boot image (no index: fallback chain)");
+        uefi::println!("  boot --edit <index>  - Edit a linux entry's cmdline, then boot it once with it");
         uefi::println!("  default <index>      - Set default boot image");
-        uefi::println!("  save                 - Save configuration to ESP");
+        uefi::println!("  save                 - Save configuration (ESP or NVRAM)");
+        uefi::println!("  config push <url> [--post]");
+        uefi::println!("                       - Upload configuration (HTTP PUT or POST)");
+        uefi::println!("  upload <path> <url> [--post]");
+        uefi::println!("                       - Upload a file from the ESP (HTTP PUT or POST)");
+        uefi::println!("  config strict [on|off]");
+        uefi::println!("                       - Refuse configs modified outside uefipxe");
+        uefi::println!("  config storage [esp|nvram]");
+        uefi::println!("                       - Show or change where 'save' keeps the configuration");
+        uefi::println!("  policy [trust|forget]");
+        uefi::println!("                       - Show the boot policy, pin policy.txt or unpin it");
+        uefi::println!("  rollback [raise <n>] - Show or raise the minimum image version");
+        uefi::println!("  auth seal <host> [pcrs]");
+        uefi::println!("                       - Seal an Authorization header for a server to the TPM");
+        uefi::println!("  auth test|forget <host>");
+        uefi::println!("                       - Check or delete a sealed credential");
+        uefi::println!("  diag export [path|url]");
+        uefi::println!("                       - Write diagnostics bundle to ESP or POST it");
+        uefi::println!("  netsetup             - Configure network interface (DHCP or static)");
+        uefi::println!("  net list             - List network interfaces with MAC, link and driver");
+        uefi::println!("  net use <index>      - Run DHCP and downloads on that interface");
+        uefi::println!("  net pxe              - Ask PXE/ProxyDHCP servers for a boot file, add it as an entry");
+        uefi::println!("  resolve <host>       - Look up a host name with the configured DNS servers");
+        uefi::println!("  menu                 - Pick an entry to boot with the arrow keys");
+        uefi::println!("  login                - Enter a password to change role (admin or operator)");
+        uefi::println!("  run <path>           - Run a script from the ESP");
+        uefi::println!("  set keymap <layout>  - Keyboard layout: firmware, us, uk, de, fr");
+        uefi::println!("  set ui <plain|fancy> - Append-only output for serial/screen readers");
+        uefi::println!("  set echo <level>     - Progress output: quiet, normal, verbose, debug");
+        uefi::println!("  set beep <mode>      - Sound downloads and failures: off, console, speaker");
+        uefi::println!("  status               - Show configuration and firmware capabilities");
+        uefi::println!("  df                   - Show size and free space of each filesystem");
+        uefi::println!("  date [set DATE TIME] - Show the clock, or set it (YYYY-MM-DD HH:MM)");
+        uefi::println!("  selftest             - Check hashing, config, UCS-2, ESP access and protocols");
+        uefi::println!("  version              - Show version, commit, build date, features and target");
         uefi::println!("  test-network         - Test network connectivity");
         uefi::println!("  logs                 - Display buffered log messages");
+        uefi::println!("  logs save|clear-file - Append them to the boot log on the ESP, or delete it");
+        uefi::println!("  history [clear]      - List earlier commands (Up/Down recall them), or forget them");
+        uefi::println!("  cache list           - List cached images with size, hits and last use");
         uefi::println!("  exit                 - Exit to firmware setup");
         uefi::println!();
+        uefi::println!("Append '> PATH' to list, show, status, df, logs and similar reports");
+        uefi::println!("to write their output to a file on the ESP instead.");
+        uefi::println!();
     }
 
-    fn exec_list() -> Result<()> {
-        let config = storage::get_config().ok_or(Error::Unknown)?;
+    fn exec_login() -> Result<()> {
+        if !access::required() {
+            uefi::println!("No admin_password= is set; every command is allowed");
+            return Ok(());
+        }
+        access::login().map(|_| ()).ok_or(Error::Uefi(Status::ACCESS_DENIED))
+    }
 
-        uefi::println!();
-        uefi::println!("Configured Images:");
-        uefi::println!("==================");
+    fn exec_list(filter: Option<&str>) -> Result<()> {
+        storage::with_config(|config| {
+            uefi::println!();
+            ui::heading("Configured Images:");
 
-        if config.urls.is_empty() {
-            uefi::println!("  (no images configured)");
-        } else {
-            for (i, url) in config.urls.iter().enumerate() {
-                let default_marker = if config.default_index == Some(i) {
-                    " [DEFAULT]"
-                } else {
-                    ""
+            if config.urls.is_empty() {
+                uefi::println!("  (no images configured)");
+                uefi::println!();
+                return Ok(());
+            }
+
+            let matches: alloc::vec::Vec<usize> =
+                (0..config.urls.len()).filter(|&i| filter.is_none_or(|f| config.entry_matches(i, f))).collect();
+
+            for (shown, &i) in matches.iter().enumerate() {
+                // No one is there to page through output going to a file
+                let page_full = shown > 0 && shown % LIST_PAGE_SIZE == 0 && !capture::active();
+                if page_full && !super::repl::more(matches.len() - shown)? {
+                    break;
+                }
+
+                let default_marker = if config.default_index == Some(i) { " [DEFAULT]" } else { "" };
+                let health_marker = match health::health(&config.urls[i]) {
+                    _ if config.is_disabled(i) => " [DISABLED]",
+                    Health::Good => " [LKG]",
+                    Health::Failed => " [FAILED]",
+                    Health::Unknown => "",
                 };
-                uefi::println!("  [{}] {}{}", i, url, default_marker);
+                match config.meta.get(i).filter(|meta| !meta.name.is_empty()) {
+                    Some(meta) => uefi::println!(
+                        "  [{}] {} - {}{}{}",
+                        i,
+                        meta.name,
+                        config.urls[i],
+                        default_marker,
+                        health_marker
+                    ),
+                    None => uefi::println!("  [{}] {}{}{}", i, config.urls[i], default_marker, health_marker),
+                }
+                if let Some(meta) = config.meta.get(i).filter(|meta| !meta.tags.is_empty()) {
+                    uefi::print!("       tags:");
+                    for tag in meta.tags.iter() {
+                        uefi::print!(" {}", tag);
+                    }
+                    uefi::println!();
+                }
+            }
+
+            uefi::println!();
+            match filter {
+                Some(f) => uefi::println!("{} of {} entries match '{}'", matches.len(), config.urls.len(), f),
+                None => uefi::println!("{} entries", config.urls.len()),
             }
+            uefi::println!();
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
+    }
+
+    fn exec_add(url: &str, name: Option<&str>, replace: bool) -> Result<()> {
+        // Relative URLs are checked as what they point to
+        let absolute = crate::storage::entry_url(url);
+        let valid = match crate::network::url::parse(&absolute) {
+            Ok(parsed) if parsed.scheme == Scheme::Iscsi => crate::network::iscsi::Target::parse(url).is_ok(),
+            Ok(parsed) if parsed.scheme == Scheme::Nfs => crate::network::nfs::Location::parse(url).is_ok(),
+            Ok(parsed) if parsed.scheme == Scheme::Tftp => crate::network::tftp::Location::parse(url).is_ok(),
+            parsed => parsed.is_ok(),
+        };
+        if !valid {
+            uefi::println!("Error: Invalid URL: {}", url);
+            uefi::println!("Expected scheme://host[:port]/path (http or https)");
+            uefi::println!("or tftp://<server ip>[:port]/<path> for a file on a TFTP server");
+            uefi::println!("or file://[volume]/path for a file on a local volume");
+            uefi::println!("or iscsi://<target ip>[:port]/<target name>[/<lun>] for a SAN disk");
+            uefi::println!("or nfs://<server ip>[:port]/<export>/<path> for a file on an NFS export");
+            return Err(Error::InvalidArgument);
         }
 
-        uefi::println!();
-        Ok(())
+        if let Err(violation) = storage::policy::check_url(&absolute) {
+            uefi::println!("Error: Refused by boot policy: {}", violation.as_str());
+            return Err(Error::SecurityViolation);
+        }
+
+        // Scripted adds must not pile up copies of the same entry
+        storage::with_config_mut(|config| {
+            match config.find_duplicate(url, name) {
+                Some(index) if !replace => {
+                    let what = if config.urls[index] == url { "URL" } else { "name" };
+                    uefi::println!("Error: Entry [{}] already has this {}", index, what);
+                    uefi::println!("Use 'add --replace' to update it, or 'remove {}' first", index);
+                    return Err(Error::InvalidArgument);
+                }
+                Some(index) => {
+                    let had_signature = !config.signatures[index].is_empty();
+                    config.replace_url(index, url)?;
+                    if let Some(name) = name {
+                        config.set_name(index, name)?;
+                    }
+                    uefi::println!("Updated entry [{}]: {}", index, url);
+                    if had_signature && config.signatures[index].is_empty() {
+                        uefi::println!("Its hash no longer applies and was removed");
+                    }
+                }
+                None => {
+                    config.add_url(url)?;
+                    if let Some(name) = name {
+                        config.set_name(config.urls.len() - 1, name)?;
+                    }
+                    uefi::println!("Added: {}", url);
+                    uefi::println!("Total images: {}", config.urls.len());
+                }
+            }
+            uefi::println!("Remember to run 'save' to persist changes to ESP");
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
     }
 
-    fn exec_add(url: &str) -> Result<()> {
-        let config = storage::get_config_mut().ok_or(Error::Unknown)?;
+    fn exec_set_disabled(index: usize, disabled: bool) -> Result<()> {
+        storage::with_config_mut(|config| {
+            if index >= config.urls.len() {
+                uefi::println!("Error: Index {} out of range (max: {})", index, config.urls.len().saturating_sub(1));
+                return Err(Error::NotFound);
+            }
 
-        config.add_url(url)?;
+            config.set_disabled(index, disabled)?;
 
-        uefi::println!("Added: {}", url);
-        uefi::println!("Total images: {}", config.urls.len());
-        uefi::println!("Remember to run 'save' to persist changes to ESP");
+            if disabled {
+                uefi::println!("Disabled: [{}] {}", index, config.urls[index]);
+                uefi::println!("It is skipped when booting without an index; 'boot {}' still works", index);
+            } else {
+                uefi::println!("Enabled: [{}] {}", index, config.urls[index]);
+            }
+            uefi::println!("Remember to run 'save' to persist changes to ESP");
 
-        Ok(())
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
     }
 
     fn exec_remove(index: usize) -> Result<()> {
-        let config = storage::get_config_mut().ok_or(Error::Unknown)?;
+        storage::with_config_mut(|config| {
+            if index >= config.urls.len() {
+                uefi::println!("Error: Index {} out of range (max: {})", index, config.urls.len() - 1);
+                return Err(Error::NotFound);
+            }
+
+            let url = config.urls[index].clone();
+            config.remove_url(index)?;
+
+            uefi::println!("Removed: {}", url);
+            uefi::println!("Total images: {}", config.urls.len());
+            uefi::println!("Remember to run 'save' to persist changes to ESP");
 
-        if index >= config.urls.len() {
-            uefi::println!("Error: Index {} out of range (max: {})", index, config.urls.len() - 1);
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
+    }
+
+    fn exec_show(index: usize) -> Result<()> {
+        storage::with_config(|config| {
+            if index >= config.urls.len() {
+                uefi::println!("Error: Index {} out of range (max: {})", index, config.urls.len().saturating_sub(1));
+                return Err(Error::NotFound);
+            }
+
+            let url = &config.urls[index];
+            let meta = config.meta.get(index);
+
+            uefi::println!();
+            ui::heading("Entry Details:");
+            uefi::println!("  Index:     {}", index);
+            match meta.filter(|meta| !meta.name.is_empty()) {
+                Some(meta) => uefi::println!("  Name:      {}", meta.name),
+                None => uefi::println!("  Name:      (none)"),
+            }
+            uefi::println!("  URL:       {}", url);
+            let resolved = storage::entry_url(url);
+            if *resolved != **url {
+                uefi::println!("  Resolves:  {}", resolved);
+            }
+            if let Some(name) = config.host_override(&resolved) {
+                uefi::println!("  Host:      {}", name);
+            }
+            if let Some(meta) = meta {
+                let implied = if meta.entry_type.is_none() { " (implied)" } else { "" };
+                uefi::println!("  Type:      {}{}", meta.effective_type(), implied);
+            }
+            if let Some(meta) = meta.filter(|meta| !meta.cmdline.is_empty()) {
+                uefi::println!("  Cmdline:   {}", meta.cmdline);
+            }
+            if let Some(meta) = meta.filter(|meta| !meta.initrd.is_empty()) {
+                let verified = if meta.initrd_sha256.is_empty() { " (not verified)" } else { "" };
+                uefi::println!("  Initrd:    {}{}", meta.initrd, verified);
+            }
+            match config.signatures.get(index).filter(|sig| !sig.is_empty()) {
+                Some(sig) => {
+                    let (verifier, value) = crate::network::verify::split(sig);
+                    uefi::println!("  Verify:    {} {}", verifier.key, value);
+                }
+                None if meta.is_some_and(|meta| !meta.sig.is_empty()) => {}
+                None => uefi::println!("  Verify:    (not configured)"),
+            }
+            if let Some(meta) = meta.filter(|meta| !meta.sig.is_empty()) {
+                uefi::println!("  Verify:    ed25519 detached {}", meta.sig);
+            }
+            if let Some(meta) = meta {
+                for line in meta.pre.lines() {
+                    uefi::println!("  Pre:       {}", line);
+                }
+                for line in meta.on_fail.lines() {
+                    uefi::println!("  On fail:   {}", line);
+                }
+            }
+            if let Some(meta) = meta.filter(|meta| !meta.tags.is_empty()) {
+                uefi::print!("  Tags:     ");
+                for tag in meta.tags.iter() {
+                    uefi::print!(" {}", tag);
+                }
+                uefi::println!();
+            }
+            uefi::println!("  Default:   {}", if config.default_index == Some(index) { "yes" } else { "no" });
+            uefi::println!("  Enabled:   {}", if config.is_disabled(index) { "no" } else { "yes" });
+            if let Some(requires) = meta.map(|meta| meta.requires).filter(|requires| !requires.is_empty()) {
+                uefi::print!("  Requires: ");
+                if let Some(arch) = requires.arch {
+                    uefi::print!(" arch={}", arch.as_str());
+                }
+                if let Some(min_fw) = requires.min_fw {
+                    uefi::print!(" min_fw={}", min_fw);
+                }
+                if requires.secure_boot {
+                    uefi::print!(" secureboot=required");
+                }
+                match requires.unmet(&Platform::current()) {
+                    Some(reason) => uefi::println!(" (NOT MET: {})", reason),
+                    None => uefi::println!(" (met)"),
+                }
+            }
+            match meta.and_then(|meta| meta.version) {
+                Some(version) => uefi::println!("  Version:   {}", version),
+                None => uefi::println!("  Version:   (none)"),
+            }
+            uefi::println!("  Health:    {}", health::health(url).as_str());
+            uefi::println!();
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
+    }
+
+    /// Try entries one after another until one boots
+    fn exec_boot_fallback() -> Result<()> {
+        let (order, count) = storage::with_config(|config| {
+            let order = crate::boot::pipeline::fallback_order(
+                config.urls.len(),
+                config.default_index,
+                |i| health::health(&config.urls[i]),
+                |i| config.is_disabled(i),
+            );
+            (order, config.urls.len())
+        })
+        .ok_or(Error::Unknown)?;
+        if order.is_empty() && !rescue::available() {
+            if count == 0 {
+                uefi::println!("Error: No images configured");
+            } else {
+                uefi::println!("Error: Every entry is disabled (use 'enable <index>')");
+            }
             return Err(Error::NotFound);
         }
 
-        let url = config.urls[index].clone();
-        config.remove_url(index)?;
+        let mut last_error = Error::NotFound;
+        for &index in order.iter() {
+            match Self::exec_boot(index, None) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    uefi::println!("Entry [{}] failed: {}, trying next", index, e);
+                    last_error = e;
+                }
+            }
+        }
 
-        uefi::println!("Removed: {}", url);
-        uefi::println!("Total images: {}", config.urls.len());
-        uefi::println!("Remember to run 'save' to persist changes to ESP");
+        // Last resort when the config is broken or every entry failed
+        if rescue::available() {
+            uefi::println!("Trying built-in rescue image");
+            return Self::exec_boot_rescue();
+        }
 
-        Ok(())
+        uefi::println!("All entries failed");
+        Err(last_error)
     }
 
-    fn exec_boot(index: usize) -> Result<()> {
-        let config = storage::get_config().ok_or(Error::Unknown)?;
+    /// Show a linux entry's kernel command line for editing and boot the
+    /// entry once with the result; the configuration keeps the original
+    fn exec_boot_edited(index: usize) -> Result<()> {
+        let entry = storage::with_config(|config| {
+            let meta = config.meta.get(index).cloned().unwrap_or_default();
+            (index < config.urls.len()).then_some((meta.effective_type(), meta.cmdline))
+        })
+        .ok_or(Error::Unknown)?;
+        let Some((entry_type, current)) = entry else {
+            uefi::println!("Error: No entry {}", index);
+            return Err(Error::NotFound);
+        };
+        if entry_type != EntryType::Linux {
+            uefi::println!("Error: Entry [{}] is type={}, only linux entries have a kernel cmdline", index, entry_type);
+            return Err(Error::Unsupported);
+        }
 
-        if index >= config.urls.len() {
-            uefi::println!("Error: Index {} out of range (max: {})", index, config.urls.len() - 1);
+        uefi::println!("Editing the cmdline of entry [{}] for this boot only (Enter boots, Escape cancels)", index);
+        let edited = match super::repl::edit::<MAX_CMDLINE_LEN>("cmdline: ", &current) {
+            Ok(edited) => edited,
+            Err(Error::Uefi(Status::ABORTED)) => {
+                uefi::println!();
+                uefi::println!("Cancelled");
+                return Err(Error::Uefi(Status::ABORTED));
+            }
+            Err(e) => return Err(e),
+        };
+        if edited != current {
+            logger::log_entry(
+                log::Level::Info,
+                &alloc::format!("Entry [{}] booted with an edited cmdline: {}", index, edited.as_str()),
+            );
+        }
+        Self::exec_boot(index, Some(edited.trim()))
+    }
+
+    fn exec_boot_rescue() -> Result<()> {
+        let url = match rescue::URL.filter(|_| rescue::available()) {
+            Some(url) => url,
+            None => {
+                uefi::println!("Error: No rescue image was built in (set UEFIPXE_RESCUE_URL when building)");
+                return Err(Error::NotFound);
+            }
+        };
+
+        let signature = rescue::SHA256.filter(|sig| !sig.is_empty());
+        Self::boot_image(Entry::Rescue, url, signature, None, &BootExtras::default())
+    }
+
+    /// Boot entry `index`, with `cmdline` in place of its own for this
+    /// boot only when given
+    fn exec_boot(index: usize, cmdline: Option<&str>) -> Result<()> {
+        // Copied, as the hooks may change the configuration they come from
+        let (count, disabled, meta) = storage::with_config(|config| {
+            (config.urls.len(), config.is_disabled(index), config.meta.get(index).cloned())
+        })
+        .ok_or(Error::Unknown)?;
+
+        if index >= count {
+            uefi::println!("Error: Index {} out of range (max: {})", index, count - 1);
             return Err(Error::NotFound);
         }
 
-        let url = &config.urls[index];
-        uefi::println!();
-        uefi::println!("Booting image [{}]: {}", index, url);
-        uefi::println!();
+        if disabled {
+            uefi::println!("Note: Entry [{}] is disabled, booting it because it was asked for", index);
+        }
 
-        // Download the image
-        let image_data = crate::network::http::download(url)?;
-        uefi::println!();
-        uefi::println!("Download successful: {} bytes", image_data.len());
+        // Checked before downloading, which can take minutes for big images
+        let requires = meta.as_ref().map(|meta| meta.requires).unwrap_or_default();
+        if let Some(reason) = requires.unmet(&Platform::current()) {
+            uefi::println!("Error: Entry [{}] cannot run on this machine: {}", index, reason);
+            return Err(Error::Unsupported);
+        }
 
-        // Verify SHA256 signature if present
-        if index < config.signatures.len() && !config.signatures[index].is_empty() {
-            let signature = &config.signatures[index];
-            uefi::println!();
-            match crate::network::verify::verify_signature(&image_data, signature) {
+        let (pre, on_fail) = meta.map(|meta| (meta.pre, meta.on_fail)).unzip();
+        if let Some(pre) = pre.filter(|pre| !pre.is_empty()) {
+            say!("Running pre-boot hook of entry [{}]", index);
+            if let Err(e) = super::script::run_hook(&pre) {
+                uefi::println!("Error: Pre-boot hook of entry [{}] failed, not booting it", index);
+                return Err(e);
+            }
+        }
+
+        let result = Self::boot_entry(index, cmdline);
+        if let (Err(_), Some(on_fail)) = (result, on_fail.filter(|on_fail| !on_fail.is_empty())) {
+            say!("Running failure hook of entry [{}]", index);
+            if let Err(e) = super::script::run_hook(&on_fail) {
+                uefi::println!("Warning: Failure hook of entry [{}] failed: {}", index, e);
+            }
+        }
+        result
+    }
+
+    /// Boot entry `index` as the configuration has it now
+    fn boot_entry(index: usize, cmdline: Option<&str>) -> Result<()> {
+        // Copied, so nothing holds the configuration through the boot
+        let entry = storage::with_config(|config| {
+            let url = config.urls.get(index)?.clone();
+            Some((url, config.signatures.get(index).cloned(), config.meta.get(index).cloned()))
+        })
+        .ok_or(Error::Unknown)?;
+        let Some((url, signature, meta)) = entry else {
+            uefi::println!("Error: Entry [{}] was removed by its hook", index);
+            return Err(Error::NotFound);
+        };
+
+        let signature = signature.as_ref().map(|sig| sig.as_str()).filter(|sig| !sig.is_empty());
+        let meta = meta.as_ref();
+        let version = meta.and_then(|meta| meta.version);
+        let entry_type = meta.map_or(EntryType::default(), |meta| meta.effective_type());
+
+        // A local disk entry naming just the volume starts its own loader
+        let url = match entry_type {
+            EntryType::LocalDisk => entry_type::local_disk_url(&url, storage::path::removable_loader()),
+            _ => alloc::string::String::from(url.as_str()),
+        };
+
+        // A relative initrd= sits next to the kernel
+        let absolute = crate::storage::entry_url(&url);
+        let initrd = match meta.map(|meta| meta.initrd.as_str()).filter(|initrd| !initrd.is_empty()) {
+            Some(initrd) => {
+                Some(crate::network::url::resolve(&absolute, &storage::with_arch(initrd)).map_err(|e| {
+                    uefi::println!("Error: Entry [{}] has an invalid initrd: {}", index, initrd);
+                    e
+                })?)
+            }
+            None => None,
+        };
+        // So does a relative sig=
+        let sig = match meta.map(|meta| meta.sig.as_str()).filter(|sig| !sig.is_empty()) {
+            Some(sig) => Some(crate::network::url::resolve(&absolute, &storage::with_arch(sig)).map_err(|e| {
+                uefi::println!("Error: Entry [{}] has an invalid sig: {}", index, sig);
+                e
+            })?),
+            None => None,
+        };
+        let extras = BootExtras {
+            entry_type,
+            cmdline: cmdline.or(meta.map(|meta| meta.cmdline.as_str())).filter(|cmdline| !cmdline.is_empty()),
+            initrd: initrd.as_deref(),
+            initrd_sha256: meta.map(|meta| meta.initrd_sha256.as_str()).filter(|hash| !hash.is_empty()),
+            sig: sig.as_deref(),
+        };
+        Self::boot_image(Entry::Index(index), &url, signature, version, &extras)
+    }
+
+    /// Download, verify and start an image
+    fn boot_image(
+        entry: Entry,
+        url: &str,
+        signature: Option<&str>,
+        version: Option<u32>,
+        extras: &BootExtras,
+    ) -> Result<()> {
+        say!();
+        say!("Booting image {}: {}", entry, url);
+        say!();
+        metrics::begin_attempt();
+
+        // Unattended boots may draw a progress bar instead of the text above
+        let name: Option<alloc::string::String> = match entry {
+            Entry::Index(index) => {
+                storage::with_config(|config| config.meta.get(index).map(|meta| meta.name.as_str().into())).flatten()
+            }
+            Entry::Rescue => Some(crate::boot::rescue::NAME.into()),
+        };
+        let name = name.filter(|name| !name.is_empty());
+        let _splash = crate::util::splash::show(name.as_deref().unwrap_or(url));
+
+        // A detached signature is checked as well as the configured one,
+        // never instead of it
+        let detached = match extras.sig {
+            Some(sig_url) => Some(Self::fetch_detached(entry, url, sig_url)?),
+            None => None,
+        };
+        let signatures: heapless::Vec<&str, 2> = signature.into_iter().chain(detached.as_deref()).collect();
+        let signature = signature.or(detached.as_deref());
+
+        // The rescue image's URL and hash are part of the build, and it has
+        // to stay bootable when the policy itself is broken
+        if entry != Entry::Rescue {
+            let absolute = crate::storage::entry_url(url);
+            let checked = match signatures.as_slice() {
+                [] => storage::policy::check_entry(&absolute, None),
+                signatures => signatures.iter().try_for_each(|sig| storage::policy::check_entry(&absolute, Some(*sig))),
+            };
+            if let Err(violation) = checked {
+                uefi::println!("Refused by boot policy: {}", violation.as_str());
+                report::record(entry, url, Stage::Verify, 0, Err(Error::SecurityViolation));
+                return Err(Error::SecurityViolation);
+            }
+            // The initrd is as much a part of the boot as the kernel
+            if let Some(initrd) = extras.initrd {
+                if let Err(violation) = storage::policy::check_entry(initrd, extras.initrd_sha256) {
+                    uefi::println!("Initrd refused by boot policy: {}", violation.as_str());
+                    report::record(entry, url, Stage::Verify, 0, Err(Error::SecurityViolation));
+                    return Err(Error::SecurityViolation);
+                }
+            }
+
+            // Refuse old releases before spending time on the download
+            if let Err(e) = rollback::check(version) {
+                report::record(entry, url, Stage::Verify, 0, Err(e));
+                return Err(e);
+            }
+        }
+
+        // What the firmware cannot start is not worth downloading
+        if extras.entry_type == EntryType::Iso {
+            if let Err(e) = crate::util::caps::require(crate::util::caps::Capability::RamDisk) {
+                report::record(entry, url, Stage::Start, 0, Err(e));
+                return Err(e);
+            }
+        }
+
+        // Download the image; it is zeroed on every way out of here, so a
+        // rejected image never lingers in memory the next one is given
+        let stage = staging::enabled();
+        let staged = if stage { staging::take(url, signature) } else { None };
+        let from_stage = staged.is_some();
+        let cache = Self::uses_cache(url, signature.is_some());
+        let etag = if cache && !from_stage { Self::current_etag(url) } else { None };
+        let current = staged.or_else(|| etag.as_deref().and_then(|etag| Self::current_copy(url, etag)));
+        let (image_data, cached) = match current {
+            Some(data) => (Wiped::new(data), true),
+            None => match Self::fetch(url) {
+                Ok(data) => (Wiped::new(data), false),
+                Err(e) => match cache.then(|| Self::cached_copy(url, e)).flatten() {
+                    Some(data) => (Wiped::new(data), true),
+                    None => {
+                        storage::secrets::clear();
+                        report::record(entry, url, Stage::Download, 0, Err(e));
+                        return Err(e);
+                    }
+                },
+            },
+        };
+        say!();
+        if from_stage {
+            say!("Using the copy staged in RAM by an earlier boot: {} bytes", image_data.len());
+        } else if cached {
+            say!("Using cached copy: {} bytes", image_data.len());
+        } else {
+            say!("Download successful: {} bytes", image_data.len());
+            beep::signal(Signal::Downloaded);
+        }
+
+        // Verify the signatures if present
+        if !signatures.is_empty() {
+            say!();
+            crate::util::splash::stage("Verifying");
+            let timer = Timer::start();
+            let verified = signatures
+                .iter()
+                .try_for_each(|signature| crate::network::verify::verify_signature(&image_data, signature));
+            timer.stop(Phase::Verify);
+            match verified {
                 Ok(_) => {
-                    uefi::println!();
+                    say!();
                 }
                 Err(e) => {
                     uefi::println!();
                     uefi::println!("SECURITY WARNING: Signature verification failed!");
+                    if from_stage {
+                        uefi::println!("The staged copy is not the image configured now.");
+                        staging::release();
+                    } else if cached {
+                        uefi::println!("The cached copy is not the image configured now.");
+                    }
                     uefi::println!("Refusing to boot unsigned/mismatched image.");
+                    storage::secrets::clear();
+                    report::record(entry, url, Stage::Verify, image_data.len(), Err(e));
                     return Err(e);
                 }
             }
@@ -159,33 +931,289 @@ impl Command {
             uefi::println!("Skipping verification (not recommended for production)");
         }
 
+        // Only verified images are kept for booting offline
+        if cache && !cached {
+            match storage::cache::store(url, &image_data, etag.as_deref()) {
+                Ok(()) => say!("Image cached on the ESP for offline boots"),
+                Err(e) => uefi::println!("Warning: Could not cache image: {}", e),
+            }
+        }
+        // ... or again without a download
+        if stage && !from_stage {
+            match staging::stage(url, signature, &image_data) {
+                Ok(()) => say!("Image staged in RAM for booting it again"),
+                Err(e) => uefi::println!("Warning: Could not stage image: {}", e),
+            }
+        }
+
+        // A script is run here rather than started
+        if extras.entry_type == EntryType::Script {
+            return Self::run_script_entry(entry, url, &image_data);
+        }
+
+        let initrd = match extras.initrd {
+            Some(initrd_url) => Some(Self::fetch_initrd(entry, url, initrd_url, extras.initrd_sha256)?),
+            None => None,
+        };
+
+        // Loaders are started from their own device, so they find the OS
+        let (image_data, source) = match Self::loader_of(extras.entry_type, url, image_data) {
+            Ok(loader) => loader,
+            Err(e) => {
+                report::record(entry, url, Stage::Start, 0, Err(e));
+                return Err(e);
+            }
+        };
+
         // Chainload the verified image
+        say!();
+        crate::util::splash::stage("Starting");
+        report::record(entry, url, Stage::Start, image_data.len(), Ok(()));
+        crate::boot::safemode::settle();
+        if let Err(e) = health::mark_in_progress(url) {
+            uefi::println!("Warning: Could not set boot marker: {}", e);
+        }
+        if let Err(e) = rollback::mark_pending(version) {
+            uefi::println!("Warning: Could not record image version: {}", e);
+        }
+        let args = ImageArgs { cmdline: extras.cmdline, initrd: initrd.as_deref() };
+        let result = crate::boot::chainload_image(&image_data, source.as_deref(), &args);
+
+        // The image came back, so the OS will never confirm this boot;
+        // only an OS that confirms it makes the entry good
+        health::clear_in_progress();
+        rollback::clear_pending();
+        if result.is_err() {
+            let _ = health::record(url, Health::Failed);
+        }
+        report::record(entry, url, Stage::Returned, image_data.len(), result);
+        result
+    }
+
+    /// What to start for a verified image of `entry_type`, with the device
+    /// path it is started from
+    ///
+    /// An ISO is attached and its loader started; a local disk's or SAN
+    /// disk's loader is started from the disk.
+    fn loader_of(
+        entry_type: EntryType,
+        url: &str,
+        image_data: Wiped,
+    ) -> Result<(Wiped, Option<alloc::boxed::Box<uefi::proto::device_path::DevicePath>>)> {
+        match entry_type {
+            EntryType::Iso => {
+                say!();
+                say!("Attaching ISO image");
+                let (loader, source) = crate::boot::ramdisk::attach(&image_data)?;
+                Ok((Wiped::new(loader), Some(source)))
+            }
+            EntryType::LocalDisk => {
+                let absolute = crate::storage::entry_url(url);
+                let parsed = crate::network::url::parse(&absolute)?;
+                let volume = storage::file::find_volume(parsed.host)?;
+                let source = storage::file::device_path_on(volume, &storage::path::normalize(parsed.path)?)?;
+                Ok((image_data, Some(source)))
+            }
+            _ => Ok((image_data, crate::network::iscsi::take_device_path(url))),
+        }
+    }
+
+    /// Run the verified script of a `type=script` entry
+    ///
+    /// `exit` in it ends the script, not the CLI.
+    fn run_script_entry(entry: Entry, url: &str, data: &[u8]) -> Result<()> {
+        let Ok(script) = core::str::from_utf8(data) else {
+            uefi::println!("Error: {} is not a text script", url);
+            report::record(entry, url, Stage::Start, data.len(), Err(Error::Parse));
+            return Err(Error::Parse);
+        };
+        say!();
+        say!("Running script {}", url);
+        report::record(entry, url, Stage::Start, data.len(), Ok(()));
+        let result = super::script::run(script).map(|_| ());
+        report::record(entry, url, Stage::Returned, data.len(), result);
+        result
+    }
+
+    /// Whether `url` is booted through the image cache: `image_cache=true`,
+    /// a network URL, and a signature to check a cached copy against
+    /// (`signed`)
+    fn uses_cache(url: &str, signed: bool) -> bool {
+        let network = crate::network::url::parse(&crate::storage::entry_url(url))
+            .is_ok_and(|parsed| !matches!(parsed.scheme, Scheme::File | Scheme::Iscsi));
+        signed && network && storage::with_config(|c| c.image_cache).unwrap_or(false)
+    }
+
+    /// The server's ETag for `url`, to tell whether the cached copy is
+    /// still current; only HTTP servers have them, and a failed HEAD just
+    /// means there is none
+    fn current_etag(url: &str) -> Option<alloc::string::String> {
+        let absolute = crate::storage::entry_url(url);
+        let parsed = crate::network::url::parse(&absolute).ok()?;
+        if !matches!(parsed.scheme, Scheme::Http | Scheme::Https) {
+            return None;
+        }
+        let head = crate::network::http::head(&absolute).ok()?;
+        head.etag.filter(|_| crate::network::client::status_number(head.status) == 200)
+    }
+
+    /// The cached copy of `url`, when the server's ETag for it is still
+    /// the one it was cached with
+    fn current_copy(url: &str, etag: &str) -> Option<alloc::vec::Vec<u8>> {
+        if storage::cache::etag(url).as_deref() != Some(etag) {
+            return None;
+        }
+        let data = storage::cache::load(url).ok()?;
+        say!("Cached copy is current (ETag {}), not downloading", etag);
+        Some(data)
+    }
+
+    /// The cached copy of `url`, after its download failed with `error`
+    fn cached_copy(url: &str, error: Error) -> Option<alloc::vec::Vec<u8>> {
+        let data = storage::cache::load(url).ok()?;
         uefi::println!();
-        crate::boot::chainload_image(&image_data)
+        uefi::println!("Download failed ({}), falling back to the cached copy", error);
+        crate::util::logger::log_entry(
+            log::Level::Warn,
+            &alloc::format!("Download of {} failed ({}), booting cached copy", url, error),
+        );
+        Some(data)
     }
 
-    fn exec_default(index: usize) -> Result<()> {
-        let config = storage::get_config_mut().ok_or(Error::Unknown)?;
+    /// Download the detached signature of the entry at `url`, as an
+    /// `ed25519:` signature to verify the image with
+    ///
+    /// An entry whose signature cannot be had is not booted at all.
+    fn fetch_detached(entry: Entry, url: &str, sig_url: &str) -> Result<alloc::string::String> {
+        if let Err(violation) = storage::policy::check_url(sig_url) {
+            uefi::println!("Signature refused by boot policy: {}", violation.as_str());
+            report::record(entry, url, Stage::Verify, 0, Err(Error::SecurityViolation));
+            return Err(Error::SecurityViolation);
+        }
 
-        if index >= config.urls.len() {
-            uefi::println!("Error: Index {} out of range (max: {})", index, config.urls.len() - 1);
-            return Err(Error::NotFound);
+        // Cached too, or a cached image could not be checked offline
+        let cache = Self::uses_cache(sig_url, true);
+        let (data, cached) = match Self::fetch(sig_url) {
+            Ok(data) => (data, false),
+            Err(e) => match cache.then(|| Self::cached_copy(sig_url, e)).flatten() {
+                Some(data) => (data, true),
+                None => {
+                    uefi::println!("Signature download failed, refusing to boot");
+                    report::record(entry, url, Stage::Download, 0, Err(e));
+                    return Err(e);
+                }
+            },
+        };
+
+        let text = match storage::signed::parse_detached(&data) {
+            Ok(text) => text,
+            Err(_) => {
+                uefi::println!("SECURITY WARNING: {} is not an Ed25519 signature", sig_url);
+                uefi::println!("Refusing to boot unsigned/mismatched image.");
+                report::record(entry, url, Stage::Verify, 0, Err(Error::SecurityViolation));
+                return Err(Error::SecurityViolation);
+            }
+        };
+        // Safe to keep unverified: it only ever vouches for an image
+        // through a trusted key
+        if cache && !cached {
+            if let Err(e) = storage::cache::store(sig_url, &data, None) {
+                uefi::println!("Warning: Could not cache signature: {}", e);
+            }
+        }
+        Ok(alloc::format!("ed25519:{}", text))
+    }
+
+    /// Download and verify the initrd of the entry at `url`
+    fn fetch_initrd(entry: Entry, url: &str, initrd_url: &str, sha256: Option<&str>) -> Result<Wiped> {
+        say!();
+        let cache = Self::uses_cache(initrd_url, sha256.is_some());
+        let (data, cached) = match Self::fetch(initrd_url) {
+            Ok(data) => (Wiped::new(data), false),
+            Err(e) => match cache.then(|| Self::cached_copy(initrd_url, e)).flatten() {
+                Some(data) => (Wiped::new(data), true),
+                None => {
+                    uefi::println!("Initrd download failed");
+                    storage::secrets::clear();
+                    report::record(entry, url, Stage::Download, 0, Err(e));
+                    return Err(e);
+                }
+            },
+        };
+
+        match sha256 {
+            Some(sha256) => {
+                say!();
+                if let Err(e) = crate::network::verify::verify_signature(&data, sha256) {
+                    uefi::println!("SECURITY WARNING: Initrd verification failed!");
+                    storage::secrets::clear();
+                    report::record(entry, url, Stage::Verify, data.len(), Err(e));
+                    return Err(e);
+                }
+                if cache && !cached {
+                    if let Err(e) = storage::cache::store(initrd_url, &data, None) {
+                        uefi::println!("Warning: Could not cache initrd: {}", e);
+                    }
+                }
+            }
+            None => uefi::println!("WARNING: No initrd_sha256 configured, the initrd is not verified"),
         }
+        Ok(data)
+    }
 
-        config.set_default(index)?;
+    /// Get an image from the network or, for `file://` URLs, a local volume
+    ///
+    /// `iscsi://` URLs attach a SAN disk and read its removable-media loader.
+    pub(crate) fn fetch(url: &str) -> Result<alloc::vec::Vec<u8>> {
+        // The default entry may already have been downloaded at the prompt
+        if let Some(data) = crate::boot::prefetch::take(url) {
+            return Ok(data);
+        }
 
-        uefi::println!("Default image set to: [{}] {}", index, config.urls[index]);
-        uefi::println!("Remember to run 'save' to persist changes to ESP");
+        // Relative entries, against base_url= or where HTTP Boot got us
+        let absolute = crate::storage::entry_url(url);
+        let url = absolute.as_ref();
+        let parsed = crate::network::url::parse(url)?;
+        match parsed.scheme {
+            Scheme::File => {}
+            Scheme::Iscsi => return crate::network::iscsi::read_loader(url),
+            Scheme::Nfs => return crate::network::nfs::download(url),
+            Scheme::Tftp => return crate::network::tftp::download(url),
+            _ => return crate::network::http::download(url),
+        }
 
-        Ok(())
+        // Without a volume in the URL, prefer the configured one
+        let configured = storage::with_config(|c| c.volume.clone()).flatten();
+        let volume = Some(parsed.host).filter(|host| !host.is_empty()).or(configured.as_deref());
+
+        say!("Reading: {}", url);
+        let data = storage::file::read_image(volume, parsed.path)?;
+        detail!("  Read {} bytes", data.len());
+        Ok(data)
     }
 
-    fn exec_save() -> Result<()> {
-        let config = storage::get_config().ok_or(Error::Unknown)?;
+    fn exec_default(index: usize) -> Result<()> {
+        storage::with_config_mut(|config| {
+            if index >= config.urls.len() {
+                uefi::println!("Error: Index {} out of range (max: {})", index, config.urls.len() - 1);
+                return Err(Error::NotFound);
+            }
+
+            config.set_default(index)?;
 
-        uefi::println!("Saving configuration to ESP...");
+            uefi::println!("Default image set to: [{}] {}", index, config.urls[index]);
+            uefi::println!("Remember to run 'save' to persist changes to ESP");
 
-        match storage::save_config(config) {
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
+    }
+
+    fn exec_save() -> Result<()> {
+        let store = storage::with_config(|config| config.storage).ok_or(Error::Unknown)?;
+        uefi::println!("Saving configuration to {}...", store.backend().name());
+
+        match storage::with_config(storage::save_config).ok_or(Error::Unknown)? {
             Ok(_) => {
                 uefi::println!("Configuration saved successfully!");
                 Ok(())
@@ -197,6 +1225,504 @@ impl Command {
         }
     }
 
+    fn exec_config_push(url: &str, method: UploadMethod) -> Result<()> {
+        let content = storage::with_config(|config| config.serialize()).ok_or(Error::Unknown)??;
+
+        match crate::network::http::upload(url, method, Body::Text(content.as_bytes())) {
+            Ok(_) => {
+                uefi::println!("Configuration pushed to {}", url);
+                Ok(())
+            }
+            Err(e) => {
+                uefi::println!("Error pushing configuration: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    fn exec_upload(path: &str, url: &str, method: UploadMethod) -> Result<()> {
+        match crate::network::http::upload(url, method, Body::File(path)) {
+            Ok(_) => {
+                uefi::println!("{} uploaded to {}", path, url);
+                Ok(())
+            }
+            Err(e) => {
+                uefi::println!("Error uploading {}: {}", path, e);
+                Err(e)
+            }
+        }
+    }
+
+    fn exec_config_strict(strict: Option<bool>) -> Result<()> {
+        if let Some(strict) = strict {
+            storage::integrity::set_strict(strict)?;
+        }
+
+        let enabled = storage::integrity::is_strict();
+        uefi::println!("Strict config integrity: {}", if enabled { "on" } else { "off" });
+        if strict == Some(false) && enabled {
+            uefi::println!("Strict mode is enforced by {}", storage::policy::POLICY_PATH);
+        }
+
+        let content = storage::with_config(|config| config.serialize()).ok_or(Error::Unknown)??;
+        if enabled && storage::integrity::check(content.as_bytes()) != storage::integrity::Check::Match {
+            uefi::println!("Run 'save' so the current configuration is recorded as trusted");
+        }
+
+        Ok(())
+    }
+
+    fn exec_config_storage(store: Option<Store>) -> Result<()> {
+        storage::with_config_mut(|config| {
+            let Some(store) = store else {
+                uefi::println!("Config storage: {}", config.storage.backend().name());
+                return Ok(());
+            };
+
+            config.storage = store;
+            uefi::println!("Config storage set to {}", store.backend().name());
+            match store {
+                Store::Nvram => uefi::println!("Use 'save' to move the configuration into NVRAM"),
+                Store::Esp => uefi::println!("Use 'save' to write it to the ESP and remove the copy in NVRAM"),
+            }
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
+    }
+
+    fn exec_policy(action: PolicyAction) -> Result<()> {
+        use storage::policy::{self, State};
+
+        let state = match action {
+            PolicyAction::Show => policy::state(),
+            PolicyAction::Trust => {
+                let state = policy::trust()?;
+                uefi::println!("Pinned {}", policy::POLICY_PATH);
+                state
+            }
+            PolicyAction::Forget => {
+                let state = policy::forget()?;
+                uefi::println!("Removed the pin of {}", policy::POLICY_PATH);
+                state
+            }
+        };
+
+        match state {
+            State::None => uefi::println!("No boot policy ({} not found)", policy::POLICY_PATH),
+            State::Broken => {
+                uefi::println!("Boot policy: BROKEN, every boot is refused");
+                uefi::println!("Review {} and run 'policy trust', or 'policy forget'", policy::POLICY_PATH);
+            }
+            State::Active { policy, pinned } => {
+                uefi::println!("Boot policy: {}", if *pinned { "pinned" } else { "NOT pinned (run 'policy trust')" });
+                uefi::println!("  Require signature: {}", if policy.require_signature { "yes" } else { "no" });
+                if policy.algorithms.is_empty() {
+                    uefi::println!("  Algorithms: any supported");
+                }
+                for algorithm in policy.algorithms.iter() {
+                    uefi::println!("  Algorithm: {}", algorithm.as_str());
+                }
+                uefi::println!("  Strict config integrity: {}", if policy.strict { "yes" } else { "no" });
+                uefi::println!("  Signed volume configs: {}", if policy.signed_config { "required" } else { "no" });
+                if policy.allow.is_empty() {
+                    uefi::println!("  Allowed URLs: any");
+                }
+                for pattern in policy.allow.iter() {
+                    uefi::println!("  Allow: {}", pattern);
+                }
+                if policy.allow_host.is_empty() {
+                    uefi::println!("  Allowed hosts: any");
+                }
+                for rule in policy.allow_host.iter() {
+                    uefi::println!("  Allow host: {}", rule);
+                }
+                for key in policy.keys.iter() {
+                    uefi::println!("  Key: {} ({} bytes)", key.name, key.key.len());
+                }
+            }
+        }
+        if let Some(key) = policy::embedded_key() {
+            uefi::println!("Built-in signing key: {} ({} bytes)", key.name, key.key.len());
+        }
+
+        Ok(())
+    }
+
+    fn exec_rollback(raise: Option<u32>) -> Result<()> {
+        if let Some(version) = raise {
+            if rollback::raise(version).is_err() {
+                uefi::println!("Error: The minimum version can only go up (currently {})", rollback::floor());
+                return Err(Error::InvalidArgument);
+            }
+        }
+
+        match rollback::floor() {
+            0 => uefi::println!("Minimum image version: none"),
+            floor => uefi::println!("Minimum image version: {}", floor),
+        }
+        uefi::println!("Raised automatically when the OS confirms a boot of a newer version");
+        Ok(())
+    }
+
+    fn exec_auth(action: &AuthAction) -> Result<()> {
+        use storage::secrets;
+
+        match action {
+            AuthAction::Seal(host, pcrs) => {
+                uefi::println!("The value is sent as the Authorization header to {} over HTTPS", host);
+                uefi::println!("(e.g. 'Bearer <token>'). Escape aborts.");
+                // Wiped once sealed
+                let value = super::repl::secret("Value: ")?;
+                let result = core::str::from_utf8(&value)
+                    .map_err(|_| Error::InvalidArgument)
+                    .and_then(|value| secrets::seal(host, value.trim(), *pcrs));
+                match result {
+                    Ok(()) => {
+                        uefi::print!("Sealed to PCRs");
+                        for pcr in (0..=crate::util::tpm::MAX_PCR).filter(|pcr| pcrs & (1 << pcr) != 0) {
+                            uefi::print!(" {}", pcr);
+                        }
+                        uefi::println!(" and stored in {}", secrets::SECRETS_DIR);
+                        Ok(())
+                    }
+                    Err(Error::InvalidArgument) => {
+                        uefi::println!("Error: Value must be 1-{} printable ASCII characters", crate::util::tpm::MAX_SECRET_LEN);
+                        Err(Error::InvalidArgument)
+                    }
+                    Err(e) => {
+                        uefi::println!("Error: Could not seal credential: {}", e);
+                        Err(e)
+                    }
+                }
+            }
+            AuthAction::Test(host) => match secrets::authorization(host) {
+                Some(value) => {
+                    uefi::println!("Credential for {} unseals ({} characters)", host, value.len());
+                    Ok(())
+                }
+                None => {
+                    uefi::println!("No usable credential for {}", host);
+                    Err(Error::NotFound)
+                }
+            },
+            AuthAction::Forget(host) => {
+                if secrets::forget(host)? {
+                    uefi::println!("Deleted the credential for {}", host);
+                } else {
+                    uefi::println!("No credential stored for {}", host);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn exec_diag_export(target: Option<&str>) -> Result<()> {
+        uefi::println!("Collecting diagnostics...");
+
+        match crate::util::diag::export(target) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                uefi::println!("Error exporting diagnostics: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    fn exec_net(action: NetAction) -> Result<()> {
+        use crate::network::init;
+
+        let interfaces = init::list_interfaces()?;
+        match action {
+            NetAction::List => {
+                uefi::println!();
+                ui::heading("Network Interfaces:");
+                if interfaces.is_empty() {
+                    uefi::println!("  (no network interfaces found)");
+                }
+                let selected = init::select_interface().ok();
+                for (i, nic) in interfaces.iter().enumerate() {
+                    let link = match init::link_state(nic.handle) {
+                        Some(true) => "up",
+                        Some(false) => "down",
+                        None => "?",
+                    };
+                    let driver = init::driver_name(nic.handle);
+                    let marker = if Some(nic.handle) == selected { "*" } else { " " };
+                    uefi::println!(
+                        " {}[{}] {}  link {:<4}  {}",
+                        marker,
+                        i,
+                        Mac(nic.mac),
+                        link,
+                        driver.as_deref().unwrap_or("(driver not named)")
+                    );
+                }
+                uefi::println!();
+                uefi::println!("* = in use; pick another with 'net use <index>'");
+                Ok(())
+            }
+            NetAction::Use(index) => {
+                let Some(nic) = interfaces.get(index) else {
+                    uefi::println!("Error: No interface {} ({} available, see 'net list')", index, interfaces.len());
+                    return Err(Error::NotFound);
+                };
+                // By MAC, so the choice holds when the firmware reorders them
+                storage::with_config_mut(|config| {
+                    config.nic_mac = Some(Mac(nic.mac));
+                    config.nic_index = None;
+                })
+                .ok_or(Error::Unknown)?;
+                // Bring the network up afresh on it with the next transfer
+                crate::network::session::close();
+                uefi::println!("Using interface {} ({})", index, Mac(nic.mac));
+                uefi::println!("Remember to run 'save' to persist changes to ESP");
+                Ok(())
+            }
+            NetAction::Pxe => {
+                use crate::network::addr::Ipv4;
+                use crate::network::pxe;
+
+                let lease = pxe::discover(init::select_interface()?).map_err(|e| {
+                    uefi::println!("Error: PXE discovery failed: {}", e);
+                    e
+                })?;
+                say!("PXE address: {}", Ipv4(lease.client_ip));
+                say!("Boot server: {}", Ipv4(lease.server_ip));
+                let url = pxe::boot_url(&lease);
+                crate::network::dhcp::record_lease(lease);
+                let Some(url) = url else {
+                    uefi::println!("Error: The PXE servers named no boot file");
+                    return Err(Error::NotFound);
+                };
+                // One entry, kept up to date with what the servers hand out.
+                // One of the user's own with the same name or URL stays as it is.
+                let theirs = storage::with_config(|config| {
+                    let index = config.find_duplicate(&url, Some(pxe::ENTRY_NAME))?;
+                    let ours = config.meta[index].tags.iter().any(|tag| tag == pxe::ENTRY_TAG);
+                    (!ours).then(|| (index, config.urls[index] == url))
+                })
+                .flatten();
+                match theirs {
+                    Some((index, true)) => {
+                        uefi::println!("The boot file is already entry [{}]", index);
+                        return Ok(());
+                    }
+                    Some((index, false)) => {
+                        uefi::println!("Error: Entry [{}] was not added by PXE discovery, not replacing it", index);
+                        return Err(Error::InvalidArgument);
+                    }
+                    None => {}
+                }
+                Self::exec_add(&url, Some(pxe::ENTRY_NAME), true)?;
+                storage::with_config_mut(|config| {
+                    let index = config.find_duplicate(&url, None).ok_or(Error::NotFound)?;
+                    config.meta[index].set_tags(pxe::ENTRY_TAG)
+                })
+                .ok_or(Error::Unknown)?
+            }
+        }
+    }
+
+    fn exec_resolve(host: &str) -> Result<()> {
+        use crate::network::dns;
+
+        let (servers, source) = dns::servers(crate::network::session::nic()?)?;
+        if servers.is_empty() {
+            uefi::println!("No DNS servers known ({}); set dns= in the config", source.as_str());
+        } else {
+            uefi::print!("DNS servers ({}):", source.as_str());
+            for server in servers.iter() {
+                uefi::print!(" {}", server);
+            }
+            uefi::println!();
+        }
+
+        match dns::resolve(host, dns::LOOKUP_TIMEOUT) {
+            Ok(address) => {
+                uefi::println!("{} is {}", host, address);
+                Ok(())
+            }
+            Err(Error::NotFound) => {
+                uefi::println!("Error: No server knows {}", host);
+                Err(Error::NotFound)
+            }
+            Err(Error::Uefi(Status::TIMEOUT)) => {
+                uefi::println!("Error: No DNS server answered");
+                Err(Error::Uefi(Status::TIMEOUT))
+            }
+            Err(e) => {
+                uefi::println!("Error: Lookup failed: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    fn exec_set(setting: Setting) -> Result<()> {
+        storage::with_config_mut(|config| {
+            match setting {
+                Setting::Keymap(layout) => {
+                    config.keymap = layout;
+                    keymap::set_layout(layout);
+                    uefi::println!("Keyboard layout: {}", layout.as_str());
+                }
+                Setting::Ui(mode) => {
+                    config.ui = mode;
+                    ui::set_mode(mode);
+                    uefi::println!("Output style: {}", mode.as_str());
+                }
+                Setting::Echo(echo) => {
+                    config.echo = echo;
+                    logger::set_echo(echo);
+                    uefi::println!("Console echo level: {}", echo.as_str());
+                }
+                Setting::Beep(mode) => {
+                    config.beep = mode;
+                    beep::set_mode(mode);
+                    uefi::println!("Beep: {}", mode.as_str());
+                    beep::signal(Signal::Downloaded);
+                }
+            }
+
+            uefi::println!("Remember to run 'save' to persist changes to ESP");
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
+    }
+
+    fn exec_status() -> Result<()> {
+        storage::with_config(|config| {
+            uefi::println!();
+            uefi::println!("UEFI PXE Bootloader v{} ({})", crate::util::version::VERSION, crate::util::version::COMMIT);
+            uefi::println!("  Images configured: {}", config.urls.len());
+            match config.default_index {
+                Some(index) => uefi::println!("  Default image: [{}]", index),
+                None => uefi::println!("  Default image: (none)"),
+            }
+            uefi::println!("  Config storage: {}", config.storage.backend().name());
+            if crate::boot::safemode::active() {
+                uefi::println!("  Safe mode: on (startup script, remote config and automatic boots skipped)");
+            }
+            match config.static_ip {
+                Some(ref ip) => uefi::println!("  Addressing: static {}", ip.address),
+                None => uefi::println!("  Addressing: DHCP"),
+            }
+            match rescue::URL.filter(|_| rescue::available()) {
+                Some(url) => uefi::println!("  Rescue image: {} ({})", url, rescue::NAME),
+                None => uefi::println!("  Rescue image: (not built in)"),
+            }
+            let platform = Platform::current();
+            uefi::println!(
+                "  Platform: {}, UEFI {}, Secure Boot {}",
+                platform.arch.map_or("unknown", |arch| arch.as_str()),
+                platform.uefi,
+                if platform.secure_boot { "on" } else { "off" }
+            );
+            uefi::println!("  SHA256: {}", crate::util::sha256::backend());
+            uefi::println!("  Open HTTP connections: {}", crate::network::session::idle_count());
+            uefi::println!("  Network child instances: {}", crate::network::manager::live_count());
+            uefi::println!("  Session cookies: {}", crate::network::session::cookie_count());
+            uefi::println!("  User-Agent: {}", crate::network::session::user_agent());
+            match storage::integrity::measured() {
+                Some(digest) => {
+                    uefi::print!("  Config measured into PCR {}: ", crate::util::tpm::CONFIG_PCR);
+                    for byte in digest.iter() {
+                        uefi::print!("{:02x}", byte);
+                    }
+                    uefi::println!();
+                }
+                None => uefi::println!("  Config measured: no"),
+            }
+            if let Some(timings) = metrics::summary(&metrics::timings()) {
+                uefi::println!("  Timings this session: {}", timings);
+            }
+            if let Some(timings) = report::last_timings() {
+                uefi::println!("  Timings of last boot: {}", timings);
+            }
+            uefi::println!();
+
+            crate::util::caps::print_matrix();
+            uefi::println!();
+            Ok(())
+        })
+        .ok_or(Error::Unknown)?
+    }
+
+    fn exec_selftest() -> Result<()> {
+        // A failure shows in %lasterror% when run from a script
+        match crate::util::selftest::run() {
+            0 => Ok(()),
+            _ => Err(Error::Unknown),
+        }
+    }
+
+    fn print_version() {
+        use crate::util::version;
+
+        uefi::println!();
+        uefi::println!("UEFI PXE Bootloader v{}", version::VERSION);
+        uefi::println!("  Commit:   {}", version::COMMIT);
+        uefi::println!("  Built:    {} ({})", version::BUILD_DATE, version::PROFILE);
+        uefi::println!("  Features: {}", version::FEATURES);
+        uefi::println!("  Target:   {}", version::TARGET);
+        uefi::println!();
+    }
+
+    fn exec_date(set: Option<&DateTime>) -> Result<()> {
+        if let Some(to) = set {
+            let before = rtc::now().map(|time| rtc::format(&time));
+            if let Err(e) = rtc::set(to) {
+                uefi::println!("Error: Could not set the clock: {}", e);
+                return Err(e);
+            }
+            crate::util::logger::log_entry(
+                log::Level::Info,
+                &alloc::format!("Clock set to {} (was {})", to, before.as_deref().unwrap_or("unknown")),
+            );
+        }
+
+        match rtc::now() {
+            Ok(time) => {
+                uefi::println!("{}", rtc::format(&time));
+                Ok(())
+            }
+            Err(e) => {
+                uefi::println!("Error: Could not read the clock: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    fn exec_df() -> Result<()> {
+        let filesystems = storage::file::filesystems()?;
+
+        uefi::println!();
+        ui::heading("Filesystems:");
+
+        if filesystems.is_empty() {
+            uefi::println!("  (no filesystems found)");
+        }
+
+        for (i, fs) in filesystems.iter().enumerate() {
+            let percent = if fs.total > 0 { fs.free * 100 / fs.total } else { 0 };
+            uefi::println!(
+                "  [{}] {:12} {:>8} KiB total {:>8} KiB free ({}%){}{}",
+                i,
+                if fs.label.is_empty() { "(no label)" } else { fs.label.as_str() },
+                fs.total / 1024,
+                fs.free / 1024,
+                percent,
+                if fs.esp { " ESP" } else { "" },
+                if fs.read_only { " read-only" } else { "" }
+            );
+            if fs.esp && !fs.fits(storage::file::MAX_FILE_SIZE) {
+                uefi::println!("      WARNING: too full to save the configuration safely");
+            }
+        }
+
+        uefi::println!();
+        Ok(())
+    }
+
     fn exec_test_network() -> Result<()> {
         uefi::println!("Testing network connectivity...");
         uefi::println!();
@@ -206,8 +1732,8 @@ impl Command {
 
         uefi::println!();
 
-        // Test basic network detection
-        crate::network::http::test_network()
+        // Then walk the way to the default entry's server, layer by layer
+        crate::network::diagnose::run()
     }
 
     fn exec_logs() -> Result<()> {
@@ -217,8 +1743,7 @@ impl Command {
             uefi::println!("No log entries.");
         } else {
             uefi::println!();
-            uefi::println!("Log entries:");
-            uefi::println!("============");
+            ui::heading("Log entries:");
             for entry in logs.iter() {
                 uefi::println!("[{:5}] {}", entry.level, entry.message);
             }
@@ -228,8 +1753,61 @@ impl Command {
         Ok(())
     }
 
-    fn exec_exit() -> Result<()> {
+    fn exec_logs_save() -> Result<()> {
+        crate::util::logger::save()?;
+        uefi::println!("Log entries appended to {}", crate::util::logger::LOG_FILE_PATH);
+        Ok(())
+    }
+
+    fn exec_logs_clear_file() -> Result<()> {
+        match crate::util::logger::clear_file()? {
+            0 => uefi::println!("No boot log to delete"),
+            count => uefi::println!("Deleted {} boot log file(s)", count),
+        }
+        Ok(())
+    }
+
+    fn exec_history() -> Result<()> {
+        let entries = super::history::entries();
+        if entries.is_empty() {
+            uefi::println!("No commands in the history.");
+        }
+        for (i, line) in entries.iter().enumerate() {
+            uefi::println!("{:4}  {}", i + 1, line);
+        }
+        Ok(())
+    }
+
+    fn exec_cache_list() -> Result<()> {
+        let entries = storage::cache::entries();
+        if entries.is_empty() {
+            uefi::println!("No images in the cache.");
+            return Ok(());
+        }
+        uefi::println!("{:<12}  {:>10}  {:>4}  {:<15}  URL", "SHA256", "Size", "Hits", "Last used");
+        for entry in entries.iter() {
+            uefi::println!(
+                "{:<12}  {:>10}  {:>4}  {:<15}  {}",
+                &entry.hash[..12],
+                entry.size,
+                entry.hits,
+                entry.used.as_deref().unwrap_or("never"),
+                entry.url
+            );
+        }
+        Ok(())
+    }
+
+    fn exec_history_clear() -> Result<()> {
+        match super::history::clear()? {
+            0 => uefi::println!("History cleared"),
+            _ => uefi::println!("History cleared, {} deleted", super::history::HISTORY_PATH),
+        }
+        Ok(())
+    }
+
+    fn exec_exit() -> Result<ControlFlow> {
         uefi::println!("Exiting to firmware...");
-        Err(Error::Unknown) // This will cause the REPL to exit
+        Ok(ControlFlow::Exit(Status::SUCCESS))
     }
 }
@@ -0,0 +1,156 @@
+use super::parser::{self, ArgKind};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// What Tab does to the line being typed
+#[derive(Debug, PartialEq, Eq)]
+pub enum Completion {
+    /// Nothing matches
+    None,
+    /// The line, completed as far as the matches agree
+    Line(String),
+    /// Several matches with nothing more in common, to be listed
+    Choices(Vec<String>),
+}
+
+/// One way to complete the last word
+struct Candidate {
+    /// What replaces the word
+    text: String,
+    /// How it is listed
+    label: String,
+}
+
+impl Candidate {
+    fn word(word: &str) -> Self {
+        Candidate { text: word.to_string(), label: word.to_string() }
+    }
+}
+
+/// Complete the last word of `line`: a command, a subcommand, or an entry
+/// for the commands that take one
+///
+/// `entries` identify the configured entries by index (their names, or
+/// their URLs when unnamed); a word matching one completes to its index.
+pub fn complete(line: &str, entries: &[&str]) -> Completion {
+    let (head, word) = match line.rfind(' ') {
+        Some(i) => line.split_at(i + 1),
+        None => ("", line),
+    };
+
+    let mut args = head.split_whitespace();
+    let candidates: Vec<Candidate> = match (args.next(), args.next()) {
+        (None, _) => parser::COMMANDS
+            .iter()
+            .filter(|spec| starts_with(spec.name, word))
+            .map(|spec| Candidate::word(spec.name))
+            .collect(),
+        (Some(command), None) => match parser::lookup(command).map(|spec| spec.arg) {
+            Some(ArgKind::Words(words)) => {
+                words.iter().filter(|w| starts_with(w, word)).map(|w| Candidate::word(w)).collect()
+            }
+            Some(ArgKind::Entry) => entry_candidates(word, entries),
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+
+    match candidates.as_slice() {
+        [] => Completion::None,
+        [only] => Completion::Line(format!("{}{} ", head, only.text)),
+        _ => {
+            let common = common_prefix(candidates.iter().map(|c| c.text.as_str()));
+            if common.len() > word.len() && starts_with(common, word) {
+                Completion::Line(format!("{}{}", head, common))
+            } else {
+                Completion::Choices(candidates.into_iter().map(|c| c.label).collect())
+            }
+        }
+    }
+}
+
+/// Entries whose index or name starts with `word`
+fn entry_candidates(word: &str, entries: &[&str]) -> Vec<Candidate> {
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (i.to_string(), name))
+        .filter(|(index, name)| index.starts_with(word) || starts_with(name, word))
+        .map(|(index, name)| Candidate { label: format!("[{}] {}", index, name), text: index })
+        .collect()
+}
+
+/// Whether `text` starts with `word`, ignoring ASCII case
+fn starts_with(text: &str, word: &str) -> bool {
+    text.get(..word.len()).is_some_and(|start| start.eq_ignore_ascii_case(word))
+}
+
+/// Longest start all of `texts` share
+fn common_prefix<'a>(mut texts: impl Iterator<Item = &'a str>) -> &'a str {
+    let Some(first) = texts.next() else {
+        return "";
+    };
+    texts.fold(first, |common, text| {
+        let len = common.char_indices().zip(text.chars()).take_while(|((_, a), b)| a == b).count();
+        match common.char_indices().nth(len) {
+            Some((end, _)) => &common[..end],
+            None => common,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> Completion {
+        Completion::Line(text.to_string())
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(complete("sel", &[]), line("selftest "));
+        assert_eq!(complete("SEL", &[]), line("selftest "));
+        // net and netsetup: only as far as they agree
+        assert_eq!(complete("ne", &[]), line("net"));
+        assert_eq!(complete("dis", &[]), line("disable "));
+        assert_eq!(complete("xyz", &[]), Completion::None);
+        let Completion::Choices(choices) = complete("s", &[]) else {
+            panic!("several commands start with s");
+        };
+        assert!(choices.iter().any(|c| c == "status") && choices.iter().any(|c| c == "set"));
+    }
+
+    #[test]
+    fn test_subcommands() {
+        assert_eq!(complete("config st", &[]), Completion::Choices(["strict", "storage"].map(String::from).to_vec()));
+        assert_eq!(complete("config sto", &[]), line("config storage "));
+        assert_eq!(complete("logs cl", &[]), line("logs clear-file "));
+        assert_eq!(complete("ls h", &[]), Completion::None);
+        // Only the word after the command is completed
+        assert_eq!(complete("config storage e", &[]), Completion::None);
+    }
+
+    #[test]
+    fn test_entries() {
+        let entries = ["ubuntu", "rescue", "http://10.0.0.5/memtest.efi"];
+        assert_eq!(complete("boot ubu", &entries), line("boot 0 "));
+        assert_eq!(complete("show R", &entries), line("show 1 "));
+        assert_eq!(complete("rm 2", &entries), line("rm 2 "));
+        let Completion::Choices(choices) = complete("boot ", &entries) else {
+            panic!("every entry matches an empty word");
+        };
+        assert_eq!(choices, ["[0] ubuntu", "[1] rescue", "[2] http://10.0.0.5/memtest.efi"]);
+        assert_eq!(complete("boot x", &entries), Completion::None);
+        assert_eq!(complete("add ub", &entries), Completion::None);
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix(["status", "set", "save"].into_iter()), "s");
+        assert_eq!(common_prefix(["über", "übel"].into_iter()), "übe");
+        assert_eq!(common_prefix(["10", "11"].into_iter()), "1");
+        assert_eq!(common_prefix(core::iter::empty()), "");
+    }
+}
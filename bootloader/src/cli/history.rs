@@ -0,0 +1,213 @@
+use crate::storage::file;
+use crate::util::{Error, Result};
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+
+/// Where commands typed at the prompt are kept between visits
+pub const HISTORY_PATH: &str = "\\EFI\\uefipxe\\history.txt";
+
+/// Most commands remembered, and written with `history=` unset
+pub const MAX_ENTRIES: usize = 100;
+
+/// Largest history file written; the oldest commands are left out first
+const MAX_FILE_LEN: usize = 8 * 1024;
+
+/// Commands entered at the prompt, oldest first
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<String>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History { entries: VecDeque::new() }
+    }
+
+    /// Parse the file form: one command per line, oldest first
+    pub fn parse(text: &str) -> Self {
+        let mut history = History::new();
+        for line in text.lines() {
+            history.push(line);
+        }
+        history
+    }
+
+    /// Remember a command; false if it was blank or repeats the last one
+    pub fn push(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        if line.is_empty() || self.entries.back().is_some_and(|last| last == line) {
+            return false;
+        }
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(String::from(line));
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The command `back` steps up from the prompt (1 is the last one)
+    pub fn recall(&self, back: usize) -> Option<&str> {
+        let index = self.entries.len().checked_sub(back)?;
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// Commands, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    /// File form of the newest `keep` commands, as many as fit in
+    /// `MAX_FILE_LEN`
+    pub fn serialize(&self, keep: usize) -> String {
+        let mut len = 0;
+        let kept = self
+            .entries
+            .iter()
+            .rev()
+            .take(keep)
+            .take_while(|entry| {
+                len += entry.len() + 1;
+                len <= MAX_FILE_LEN
+            })
+            .count();
+
+        let mut text = String::with_capacity(len);
+        for entry in self.entries.iter().skip(self.entries.len() - kept) {
+            text.push_str(entry);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// History of this session, loaded from the ESP by `load`
+static mut HISTORY: Option<History> = None;
+
+/// Whether a failed save was already reported
+static mut SAVE_FAILED: bool = false;
+
+/// Commands written to the ESP, as `history=` asks (0: none)
+fn kept() -> usize {
+    crate::storage::with_config(|config| config.history).flatten().map_or(MAX_ENTRIES, |count| count as usize)
+}
+
+/// Pick up the commands of earlier visits
+///
+/// A missing or unreadable file just means an empty history, as does
+/// `history=0`.
+pub fn load() {
+    let saved = if kept() > 0 { file::read_file(HISTORY_PATH) } else { Err(Error::NotFound) };
+    let history = match saved {
+        Ok(data) => History::parse(&String::from_utf8_lossy(&data)),
+        Err(Error::NotFound) => History::new(),
+        Err(e) => {
+            crate::util::logger::log_entry(log::Level::Warn, &format!("Could not load history: {}", e));
+            History::new()
+        }
+    };
+    unsafe {
+        HISTORY = Some(history);
+    }
+}
+
+/// The command `back` steps up from the prompt (1 is the last one)
+pub fn recall(back: usize) -> Option<String> {
+    unsafe { HISTORY.as_ref() }?.recall(back).map(String::from)
+}
+
+/// Commands of this session and the kept ones of earlier visits, oldest
+/// first
+pub fn entries() -> alloc::vec::Vec<String> {
+    unsafe { HISTORY.as_ref() }.map_or_else(alloc::vec::Vec::new, |history| history.iter().map(String::from).collect())
+}
+
+/// Forget every command, here and on the ESP
+///
+/// Returns how many history files were deleted.
+pub fn clear() -> Result<usize> {
+    if let Some(history) = unsafe { HISTORY.as_mut() } {
+        *history = History::new();
+    }
+    file::delete(HISTORY_PATH)
+}
+
+/// Remember a command and write the history back to the ESP
+pub fn record(line: &str) {
+    let Some(history) = (unsafe { HISTORY.as_mut() }) else {
+        return;
+    };
+    let keep = kept();
+    if !history.push(line) || keep == 0 {
+        return;
+    }
+    if let Err(e) = save(history, keep) {
+        // A read-only ESP would otherwise log this after every command
+        if !unsafe { SAVE_FAILED } {
+            crate::util::logger::log_entry(log::Level::Warn, &format!("Could not save history: {}", e));
+            unsafe { SAVE_FAILED = true };
+        }
+    }
+}
+
+fn save(history: &History, keep: usize) -> Result<()> {
+    file::atomic_write(HISTORY_PATH, history.serialize(keep).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_and_recall() {
+        let mut history = History::parse("list\n\nboot 0\nboot 0\n  status  \n");
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.recall(1), Some("status"));
+        assert_eq!(history.recall(3), Some("list"));
+        assert_eq!(history.recall(4), None);
+        assert_eq!(history.recall(0), None);
+
+        assert!(!history.push("status"));
+        assert!(!history.push("   "));
+        assert!(history.push("list"));
+        assert_eq!(history.recall(1), Some("list"));
+    }
+
+    #[test]
+    fn test_caps() {
+        let mut history = History::new();
+        for i in 0..MAX_ENTRIES + 5 {
+            history.push(&format!("show {}", i));
+        }
+        assert_eq!(history.len(), MAX_ENTRIES);
+        assert_eq!(history.recall(MAX_ENTRIES), Some("show 5"));
+
+        // Long commands: only the newest that fit are written
+        let mut history = History::new();
+        let long = "x".repeat(1000);
+        for i in 0..20 {
+            history.push(&format!("add http://{}/{}", i, long));
+        }
+        let text = history.serialize(MAX_ENTRIES);
+        assert!(text.len() <= MAX_FILE_LEN);
+        let reloaded = History::parse(&text);
+        assert!(reloaded.len() < 20);
+        assert_eq!(reloaded.recall(1), history.recall(1));
+    }
+
+    #[test]
+    fn test_keep() {
+        let history = History::parse("list\nshow 0\nboot 0\n");
+        assert_eq!(history.serialize(2), "show 0\nboot 0\n");
+        assert_eq!(history.serialize(0), "");
+        assert_eq!(history.iter().collect::<alloc::vec::Vec<_>>(), ["list", "show 0", "boot 0"]);
+    }
+}
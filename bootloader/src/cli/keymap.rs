@@ -0,0 +1,146 @@
+use crate::util::{Error, Result};
+
+/// Console keyboard layouts
+///
+/// Many firmware implementations only know the US layout, so on a German or
+/// French keyboard the key labelled ':' arrives as something else. The
+/// tables below map what US-layout firmware reports to what the key is
+/// labelled on the selected layout. AltGr combinations cannot be recovered
+/// (the modifier is not reported), but every character needed for URLs is
+/// reachable with Shift on these layouts. Dead keys (^ ` ´ ¨) are emitted as
+/// literal characters right away, since URLs need the literal characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// Trust the characters the firmware reports (it may already honor the layout)
+    #[default]
+    Firmware,
+    /// US QWERTY
+    Us,
+    /// UK QWERTY
+    Uk,
+    /// German QWERTZ
+    De,
+    /// French AZERTY
+    Fr,
+}
+
+/// UK keys that differ from US
+const UK: &[(char, char)] = &[
+    ('@', '"'), ('#', '£'), ('"', '@'), ('\\', '#'), ('|', '~'), ('~', '¬'),
+];
+
+/// German keys that differ from US
+const DE: &[(char, char)] = &[
+    ('y', 'z'), ('z', 'y'), ('Y', 'Z'), ('Z', 'Y'),
+    ('-', 'ß'), ('=', '´'), ('`', '^'), ('~', '°'),
+    ('@', '"'), ('#', '§'), ('^', '&'), ('&', '/'), ('*', '('),
+    ('(', ')'), (')', '='), ('_', '?'), ('+', '`'),
+    ('[', 'ü'), ('{', 'Ü'), (']', '+'), ('}', '*'),
+    (';', 'ö'), (':', 'Ö'), ('\'', 'ä'), ('"', 'Ä'), ('\\', '#'), ('|', '\''),
+    ('<', ';'), ('>', ':'), ('/', '-'), ('?', '_'),
+];
+
+/// French keys that differ from US
+const FR: &[(char, char)] = &[
+    ('q', 'a'), ('a', 'q'), ('w', 'z'), ('z', 'w'),
+    ('Q', 'A'), ('A', 'Q'), ('W', 'Z'), ('Z', 'W'),
+    (';', 'm'), (':', 'M'), ('m', ','), ('M', '?'),
+    ('1', '&'), ('2', 'é'), ('3', '"'), ('4', '\''), ('5', '('),
+    ('6', '-'), ('7', 'è'), ('8', '_'), ('9', 'ç'), ('0', 'à'),
+    ('!', '1'), ('@', '2'), ('#', '3'), ('$', '4'), ('%', '5'),
+    ('^', '6'), ('&', '7'), ('*', '8'), ('(', '9'), (')', '0'),
+    ('-', ')'), ('_', '°'), ('`', '²'),
+    ('[', '^'), ('{', '¨'), (']', '$'), ('}', '£'),
+    ('\'', 'ù'), ('"', '%'), ('\\', '*'), ('|', 'µ'),
+    (',', ';'), ('<', '.'), ('.', ':'), ('>', '/'), ('/', '!'), ('?', '§'),
+];
+
+impl Layout {
+    /// Parse a layout name as used in config.txt and `set keymap`
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "firmware" | "auto" => Ok(Layout::Firmware),
+            "us" => Ok(Layout::Us),
+            "uk" | "gb" => Ok(Layout::Uk),
+            "de" => Ok(Layout::De),
+            "fr" => Ok(Layout::Fr),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Layout name as written in config.txt
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layout::Firmware => "firmware",
+            Layout::Us => "us",
+            Layout::Uk => "uk",
+            Layout::De => "de",
+            Layout::Fr => "fr",
+        }
+    }
+
+    fn table(&self) -> &'static [(char, char)] {
+        match self {
+            Layout::Firmware | Layout::Us => &[],
+            Layout::Uk => UK,
+            Layout::De => DE,
+            Layout::Fr => FR,
+        }
+    }
+
+    /// Translate a character reported by US-layout firmware
+    pub fn translate(&self, c: char) -> char {
+        self.table()
+            .iter()
+            .find(|(us, _)| *us == c)
+            .map_or(c, |(_, mapped)| *mapped)
+    }
+}
+
+/// Layout applied to console input
+static mut ACTIVE: Layout = Layout::Firmware;
+
+/// Select the layout used for console input
+pub fn set_layout(layout: Layout) {
+    unsafe {
+        ACTIVE = layout;
+    }
+}
+
+/// Layout currently used for console input
+pub fn layout() -> Layout {
+    unsafe { ACTIVE }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_layout() {
+        assert_eq!(Layout::parse("DE").unwrap(), Layout::De);
+        assert_eq!(Layout::parse("auto").unwrap(), Layout::Firmware);
+        assert!(Layout::parse("dvorak").is_err());
+        assert_eq!(Layout::parse(Layout::Fr.as_str()).unwrap(), Layout::Fr);
+    }
+
+    #[test]
+    fn test_url_characters() {
+        // "http://" typed on a German keyboard, as seen by US firmware
+        let typed = "http>&&";
+        let translated: heapless::String<16> = typed.chars().map(|c| Layout::De.translate(c)).collect();
+        assert_eq!(translated.as_str(), "http://");
+
+        // Same on a French keyboard
+        let typed = "http.>>";
+        let translated: heapless::String<16> = typed.chars().map(|c| Layout::Fr.translate(c)).collect();
+        assert_eq!(translated.as_str(), "http://");
+    }
+
+    #[test]
+    fn test_passthrough() {
+        assert_eq!(Layout::Firmware.translate('y'), 'y');
+        assert_eq!(Layout::Us.translate(':'), ':');
+        assert_eq!(Layout::De.translate('a'), 'a');
+    }
+}
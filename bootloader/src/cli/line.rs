@@ -0,0 +1,278 @@
+use heapless::String;
+
+/// Control characters the firmware reports for Ctrl+letter
+pub const CTRL_A: char = '\x01';
+pub const CTRL_E: char = '\x05';
+pub const CTRL_K: char = '\x0b';
+pub const CTRL_L: char = '\x0c';
+pub const CTRL_U: char = '\x15';
+pub const CTRL_W: char = '\x17';
+pub const CTRL_Y: char = '\x19';
+
+/// A line being typed at the prompt: text, cursor and the last killed text
+///
+/// Editing follows the shell shortcuts serial-console users expect. The
+/// cursor is a byte offset that always sits on a character boundary.
+pub struct LineEditor<const N: usize> {
+    text: String<N>,
+    cursor: usize,
+    killed: String<N>,
+}
+
+impl<const N: usize> Default for LineEditor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> LineEditor<N> {
+    pub fn new() -> Self {
+        LineEditor { text: String::new(), cursor: 0, killed: String::new() }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Finish editing, keeping the text
+    pub fn into_string(self) -> String<N> {
+        self.text
+    }
+
+    /// Whether the cursor is after the last character
+    pub fn at_end(&self) -> bool {
+        self.cursor == self.text.len()
+    }
+
+    /// Screen columns before the cursor
+    pub fn cursor_column(&self) -> usize {
+        self.text[..self.cursor].chars().count()
+    }
+
+    /// Screen columns the whole line takes
+    pub fn columns(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    /// Insert a character at the cursor; false when the line is full
+    ///
+    /// One byte is kept free, as the prompt always has.
+    pub fn insert(&mut self, c: char) -> bool {
+        if self.text.len() + c.len_utf8() >= N {
+            return false;
+        }
+        let mut tail: String<N> = String::new();
+        let _ = tail.push_str(&self.text[self.cursor..]);
+        self.text.truncate(self.cursor);
+        let _ = self.text.push(c);
+        let _ = self.text.push_str(&tail);
+        self.cursor += c.len_utf8();
+        true
+    }
+
+    /// Delete the character before the cursor
+    pub fn backspace(&mut self) -> bool {
+        match self.text[..self.cursor].chars().next_back() {
+            Some(c) => {
+                let start = self.cursor - c.len_utf8();
+                self.remove(start, self.cursor);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace the whole line, e.g. with a recalled command; the cursor
+    /// goes to the end and text that does not fit is cut off
+    pub fn replace(&mut self, text: &str) {
+        self.text.clear();
+        self.cursor = 0;
+        for c in text.chars() {
+            if !self.insert(c) {
+                break;
+            }
+        }
+    }
+
+    /// Delete the character under the cursor (Delete)
+    pub fn delete(&mut self) -> bool {
+        match self.text[self.cursor..].chars().next() {
+            Some(c) => {
+                self.remove(self.cursor, self.cursor + c.len_utf8());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// One character to the left; false at the start of the line
+    pub fn left(&mut self) -> bool {
+        match self.text[..self.cursor].chars().next_back() {
+            Some(c) => {
+                self.cursor -= c.len_utf8();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// One character to the right; false at the end of the line
+    pub fn right(&mut self) -> bool {
+        match self.text[self.cursor..].chars().next() {
+            Some(c) => {
+                self.cursor += c.len_utf8();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ctrl+A or Home
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Ctrl+E or End
+    pub fn end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Ctrl+K: kill from the cursor to the end of the line
+    pub fn kill_to_end(&mut self) -> bool {
+        self.kill(self.cursor, self.text.len())
+    }
+
+    /// Ctrl+U: kill from the start of the line to the cursor
+    pub fn kill_to_start(&mut self) -> bool {
+        self.kill(0, self.cursor)
+    }
+
+    /// Ctrl+W: kill the word before the cursor and the spaces after it
+    pub fn kill_word(&mut self) -> bool {
+        let before = &self.text[..self.cursor];
+        let word_end = before.trim_end_matches(' ').len();
+        let start = before[..word_end].rfind(' ').map_or(0, |i| i + 1);
+        self.kill(start, self.cursor)
+    }
+
+    /// Ctrl+Y: insert the last killed text at the cursor
+    pub fn yank(&mut self) -> bool {
+        let killed = self.killed.clone();
+        let mut changed = false;
+        for c in killed.chars() {
+            if !self.insert(c) {
+                break;
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    fn kill(&mut self, start: usize, end: usize) -> bool {
+        if start == end {
+            return false;
+        }
+        self.killed.clear();
+        let _ = self.killed.push_str(&self.text[start..end]);
+        self.remove(start, end);
+        true
+    }
+
+    fn remove(&mut self, start: usize, end: usize) {
+        let mut tail: String<N> = String::new();
+        let _ = tail.push_str(&self.text[end..]);
+        self.text.truncate(start);
+        let _ = self.text.push_str(&tail);
+        self.cursor = start;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> LineEditor<64> {
+        let mut line = LineEditor::new();
+        for c in text.chars() {
+            assert!(line.insert(c));
+        }
+        line
+    }
+
+    #[test]
+    fn test_insert_and_move() {
+        let mut line = editor("boot 1");
+        line.home();
+        assert_eq!(line.cursor_column(), 0);
+        line.insert('#');
+        assert_eq!(line.as_str(), "#boot 1");
+        line.end();
+        assert!(line.at_end());
+        line.backspace();
+        line.insert('ü');
+        assert_eq!(line.as_str(), "#boot ü");
+        assert_eq!((line.cursor_column(), line.columns()), (7, 7));
+        assert!(line.backspace());
+        assert_eq!(line.as_str(), "#boot ");
+    }
+
+    #[test]
+    fn test_cursor_keys() {
+        let mut line = editor("böot");
+        assert!(!line.right());
+        assert!(line.left() && line.left() && line.left());
+        assert_eq!(line.cursor_column(), 1);
+        assert!(line.delete());
+        assert_eq!(line.as_str(), "bot");
+        line.insert('o');
+        assert_eq!((line.as_str(), line.cursor_column()), ("boot", 2));
+        assert!(line.right() && line.right());
+        assert!(!line.right() && !line.delete());
+        line.home();
+        assert!(!line.left());
+        assert!(line.delete());
+        assert_eq!((line.as_str(), line.cursor_column()), ("oot", 0));
+    }
+
+    #[test]
+    fn test_kill_and_yank() {
+        let mut line = editor("add http://a/x.efi");
+        line.cursor = 4;
+        assert!(line.kill_to_end());
+        assert_eq!(line.as_str(), "add ");
+        assert!(line.yank());
+        assert_eq!(line.as_str(), "add http://a/x.efi");
+
+        line.cursor = 4;
+        assert!(line.kill_to_start());
+        assert_eq!((line.as_str(), line.cursor_column()), ("http://a/x.efi", 0));
+        assert!(!line.kill_to_start());
+        line.end();
+        assert!(line.yank());
+        assert_eq!(line.as_str(), "http://a/x.efiadd ");
+    }
+
+    #[test]
+    fn test_kill_word() {
+        let mut line = editor("boot  fallback  ");
+        assert!(line.kill_word());
+        assert_eq!(line.as_str(), "boot  ");
+        assert!(line.kill_word());
+        assert_eq!(line.as_str(), "");
+        assert!(!line.kill_word());
+        line.yank();
+        assert_eq!(line.as_str(), "boot  ");
+    }
+
+    #[test]
+    fn test_full_line() {
+        let mut line: LineEditor<4> = LineEditor::new();
+        assert!(line.insert('a'));
+        assert!(line.insert('b'));
+        assert!(line.insert('c'));
+        assert!(!line.insert('d'));
+        line.replace("wxyz");
+        assert_eq!((line.as_str(), line.at_end()), ("wxy", true));
+        assert_eq!(line.into_string().as_str(), "wxy");
+    }
+}
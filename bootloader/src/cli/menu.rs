@@ -0,0 +1,396 @@
+use super::access;
+use super::commands::Command;
+use super::repl::{self, prompt};
+use crate::storage;
+use crate::util::ui;
+use crate::util::{Error, Result};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::time::Duration;
+use uefi::proto::console::text::{Color, Key, ScanCode};
+use uefi::println;
+
+/// Screen size assumed when the firmware does not report its text mode
+const DEFAULT_COLUMNS: usize = 80;
+const DEFAULT_ROWS: usize = 25;
+
+/// Lines used by the title above and the key help below the entries
+const TITLE_ROWS: usize = 3;
+const FOOTER_ROWS: usize = 4;
+
+/// Highlighted entry and the part of the list that fits on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Selection {
+    index: usize,
+    count: usize,
+    /// First entry shown
+    top: usize,
+}
+
+impl Selection {
+    fn new(count: usize, index: usize) -> Self {
+        Selection { index: index.min(count.saturating_sub(1)), count, top: 0 }
+    }
+
+    /// Move by `delta` entries, stopping at either end
+    fn step(&mut self, delta: isize) {
+        let last = self.count.saturating_sub(1) as isize;
+        self.index = (self.index as isize + delta).clamp(0, last) as usize;
+    }
+
+    fn first(&mut self) {
+        self.index = 0;
+    }
+
+    fn last(&mut self) {
+        self.index = self.count.saturating_sub(1);
+    }
+
+    /// Scroll so the highlighted entry is among the `visible` shown
+    fn scroll(&mut self, visible: usize) {
+        let visible = visible.max(1);
+        if self.index < self.top {
+            self.top = self.index;
+        } else if self.index >= self.top + visible {
+            self.top = self.index + 1 - visible;
+        }
+    }
+}
+
+/// What the technician chose
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    /// Boot this entry
+    Entry(usize),
+    /// Edit this entry's kernel command line, then boot it
+    Edit(usize),
+    /// Nobody pressed a key before the countdown ran out
+    Timeout,
+    /// Go to the command line
+    Cli,
+}
+
+/// Full-screen boot menu of the configured entries
+///
+/// Up/Down, Page Up/Down and Home/End pick an entry and Enter boots it;
+/// `e` shows a linux entry's kernel command line to edit for that one
+/// boot, and Escape or `c` leaves for the command line. With `timeout`
+/// (seconds, as given by `menu_timeout=`), the default entry boots through
+/// the fallback chain unless a key is pressed first. A failed boot comes
+/// back to the menu. In plain mode the entries are listed and a number is
+/// asked for.
+///
+/// With `config_url=` and `poll_config_interval=`, the full-screen menu
+/// checks the server for a new configuration while it waits and shows its
+/// entries as soon as they change.
+pub fn run(timeout: Option<u32>) -> Result<()> {
+    let (count, default) =
+        storage::with_config(|config| (config.urls.len(), config.default_index)).ok_or(Error::Unknown)?;
+    if count == 0 {
+        println!("No images configured; add one with 'add <url>'");
+        return Ok(());
+    }
+
+    let mut countdown = timeout.filter(|&seconds| seconds > 0);
+    let mut selection = Selection::new(count, default.unwrap_or(0));
+    loop {
+        let choice = if ui::is_plain() {
+            choose_plain(countdown)?
+        } else {
+            choose(&mut selection, countdown)?
+        };
+        // The countdown only runs once, before anyone touched the keyboard
+        countdown = None;
+
+        // Nobody picked an entry, so nobody may be watching either
+        let unattended = matches!(choice, Choice::Timeout);
+        let command = match choice {
+            Choice::Entry(index) => Command::Boot(Some(index)),
+            Choice::Edit(index) => Command::BootEdited(index),
+            Choice::Timeout => Command::Boot(None),
+            Choice::Cli => return Ok(()),
+        };
+        // With passwords set, only the admin may edit a cmdline
+        if !access::permits(&command) && !access::admin() {
+            println!("Press any key to return to the menu");
+            repl::wait_for_key()?;
+            continue;
+        }
+        crate::util::splash::set_unattended(unattended);
+        let result = command.execute();
+        crate::util::splash::set_unattended(false);
+        match result {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                println!("Error: {}", e);
+                println!("Press any key to return to the menu");
+                repl::wait_for_key()?;
+            }
+        }
+    }
+}
+
+/// Draw the menu and follow the keys until something is chosen
+fn choose(selection: &mut Selection, mut countdown: Option<u32>) -> Result<Choice> {
+    let (columns, rows) = screen_size();
+    let visible = rows.saturating_sub(TITLE_ROWS + FOOTER_ROWS).max(1);
+
+    let interval = storage::remote::interval();
+    let mut idle = 0;
+
+    let _ = uefi::system::with_stdout(|stdout| stdout.enable_cursor(false));
+    let choice = loop {
+        selection.scroll(visible);
+        draw(selection, columns, rows, visible, countdown);
+        if countdown == Some(0) {
+            break Choice::Timeout;
+        }
+
+        // Tick once a second while counting down or watching the server
+        let key = if countdown.is_some() || interval.is_some() {
+            match repl::wait_for_key_until(Some(Duration::from_secs(1)))? {
+                Some(key) => {
+                    countdown = None;
+                    key
+                }
+                None => {
+                    countdown = countdown.map(|seconds| seconds - 1);
+                    idle += 1;
+                    if interval.is_some_and(|interval| idle >= interval) {
+                        idle = 0;
+                        refresh(selection);
+                    }
+                    continue;
+                }
+            }
+        } else {
+            repl::wait_for_key()?
+        };
+
+        let page = visible as isize;
+        match key {
+            Key::Special(ScanCode::UP) => selection.step(-1),
+            Key::Special(ScanCode::DOWN) => selection.step(1),
+            Key::Special(ScanCode::PAGE_UP) => selection.step(-page),
+            Key::Special(ScanCode::PAGE_DOWN) => selection.step(page),
+            Key::Special(ScanCode::HOME) => selection.first(),
+            Key::Special(ScanCode::END) => selection.last(),
+            Key::Special(ScanCode::ESCAPE) => break Choice::Cli,
+            Key::Printable(c) => match char::from(c) {
+                '\r' | '\n' => break Choice::Entry(selection.index),
+                'e' | 'E' => break Choice::Edit(selection.index),
+                'c' | 'C' => break Choice::Cli,
+                _ => {}
+            },
+            Key::Special(_) => {}
+        }
+    };
+
+    // Leave a clean screen for the boot output or the prompt
+    uefi::system::with_stdout(|stdout| {
+        let _ = stdout.set_color(Color::LightGray, Color::Black);
+        let _ = stdout.clear();
+        let _ = stdout.enable_cursor(true);
+    });
+    Ok(choice)
+}
+
+/// Check `config_url` for new entries, keeping the highlight in range
+///
+/// The screen is redrawn afterwards either way, which also clears what
+/// the download printed.
+fn refresh(selection: &mut Selection) {
+    match storage::remote::poll() {
+        Ok(true) => {
+            let count = storage::with_config(|config| config.urls.len()).unwrap_or(0);
+            *selection = Selection::new(count, selection.index);
+        }
+        Ok(false) => {}
+        Err(e) => {
+            crate::util::logger::log_entry(log::Level::Warn, &alloc::format!("Could not check config_url: {}", e))
+        }
+    }
+}
+
+/// Text mode size, as columns and rows
+fn screen_size() -> (usize, usize) {
+    uefi::system::with_stdout(|stdout| match stdout.current_mode() {
+        Ok(Some(mode)) => (mode.columns(), mode.rows()),
+        _ => (DEFAULT_COLUMNS, DEFAULT_ROWS),
+    })
+}
+
+/// Redraw the whole screen
+fn draw(selection: &Selection, columns: usize, rows: usize, visible: usize, countdown: Option<u32>) {
+    let width = columns.saturating_sub(4);
+    let Some(lines) = storage::with_config(|config| {
+        let mut lines: Vec<String> = Vec::new();
+        for i in selection.top..(selection.top + visible).min(selection.count) {
+            let mut line = match config.meta.get(i).filter(|meta| !meta.name.is_empty()) {
+                Some(meta) => alloc::format!("[{}] {}", i, meta.name),
+                None => alloc::format!("[{}] {}", i, config.urls[i]),
+            };
+            if config.default_index == Some(i) {
+                line.push_str(" (default)");
+            }
+            if config.is_disabled(i) {
+                line.push_str(" (disabled)");
+            }
+            lines.push(fit(&line, width));
+        }
+        lines
+    }) else {
+        return;
+    };
+
+    uefi::system::with_stdout(|stdout| {
+        let _ = stdout.set_color(Color::LightGray, Color::Black);
+        let _ = stdout.clear();
+        let _ = stdout.set_cursor_position(2, 0);
+        let _ = write!(stdout, "UEFI PXE Bootloader v{}", env!("CARGO_PKG_VERSION"));
+
+        for (row, line) in lines.iter().enumerate() {
+            let selected = selection.top + row == selection.index;
+            let _ = stdout.set_cursor_position(1, TITLE_ROWS - 1 + row);
+            if selected {
+                let _ = stdout.set_color(Color::Black, Color::LightGray);
+            }
+            // Pad so the highlight runs the width of the menu
+            let _ = write!(stdout, " {:<width$} ", line, width = width);
+            if selected {
+                let _ = stdout.set_color(Color::LightGray, Color::Black);
+            }
+        }
+
+        let footer = rows.saturating_sub(FOOTER_ROWS) + 1;
+        if selection.top > 0 || selection.top + visible < selection.count {
+            let _ = stdout.set_cursor_position(2, footer - 1);
+            let _ = write!(stdout, "({} of {})", selection.index + 1, selection.count);
+        }
+        let _ = stdout.set_cursor_position(2, footer);
+        let _ = write!(stdout, "Use Up and Down to select an entry, Enter to boot it.");
+        let _ = stdout.set_cursor_position(2, footer + 1);
+        let _ = write!(stdout, "Press 'e' to edit the cmdline, 'c' or Escape for the command line.");
+        if let Some(seconds) = countdown {
+            let _ = stdout.set_cursor_position(2, footer + 2);
+            let _ = write!(stdout, "The default entry boots in {} seconds.", seconds);
+        }
+    });
+}
+
+/// Cut `text` down to `width` characters, marking the cut
+fn fit(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return String::from(text);
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(3)).collect();
+    cut.push_str("...");
+    cut
+}
+
+/// Append-only menu: list the entries and ask for a number
+fn choose_plain(countdown: Option<u32>) -> Result<Choice> {
+    println!();
+    ui::heading("Boot Menu");
+    let count = storage::with_config(|config| {
+        for (i, url) in config.urls.iter().enumerate() {
+            let name = config.meta.get(i).map(|meta| meta.name.as_str()).filter(|name| !name.is_empty());
+            let default_marker = if config.default_index == Some(i) { " (default)" } else { "" };
+            let disabled_marker = if config.is_disabled(i) { " (disabled)" } else { "" };
+            println!("  [{}] {}{}{}", i, name.unwrap_or(url.as_str()), default_marker, disabled_marker);
+        }
+        config.urls.len()
+    })
+    .ok_or(Error::Unknown)?;
+    println!();
+
+    if let Some(seconds) = countdown {
+        println!("The default entry boots in {} seconds, press any key to choose", seconds);
+        if repl::wait_for_key_until(Some(Duration::from_secs(u64::from(seconds))))?.is_none() {
+            return Ok(Choice::Timeout);
+        }
+    }
+
+    loop {
+        let answer = match prompt("Entry to boot (e<n> to edit its cmdline, empty for the command line): ") {
+            Ok(answer) => answer,
+            Err(Error::Uefi(uefi::Status::ABORTED)) => return Ok(Choice::Cli),
+            Err(e) => return Err(e),
+        };
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(Choice::Cli);
+        }
+        match parse_answer(answer, count) {
+            Some(choice) => return Ok(choice),
+            None => println!("No entry {}", answer),
+        }
+    }
+}
+
+/// The plain menu's answer: an entry number, or `e` and the number of the
+/// entry whose cmdline to edit
+fn parse_answer(answer: &str, count: usize) -> Option<Choice> {
+    let (edit, number) = match answer.strip_prefix(['e', 'E']) {
+        Some(number) => (true, number.trim_start()),
+        None => (false, answer),
+    };
+    let index = number.parse::<usize>().ok().filter(|&index| index < count)?;
+    Some(if edit { Choice::Edit(index) } else { Choice::Entry(index) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_moves() {
+        let mut selection = Selection::new(5, 3);
+        assert_eq!(selection.index, 3);
+        selection.step(1);
+        selection.step(1);
+        assert_eq!(selection.index, 4);
+        selection.step(-10);
+        assert_eq!(selection.index, 0);
+        selection.last();
+        assert_eq!(selection.index, 4);
+        selection.first();
+        assert_eq!(selection.index, 0);
+
+        // A default past the end lands on the last entry
+        assert_eq!(Selection::new(2, 7).index, 1);
+    }
+
+    #[test]
+    fn test_selection_scrolls() {
+        let mut selection = Selection::new(30, 0);
+        selection.scroll(10);
+        assert_eq!(selection.top, 0);
+        selection.step(12);
+        selection.scroll(10);
+        assert_eq!(selection.top, 3);
+        selection.step(-5);
+        selection.scroll(10);
+        assert_eq!(selection.top, 3);
+        selection.first();
+        selection.scroll(10);
+        assert_eq!(selection.top, 0);
+    }
+
+    #[test]
+    fn test_parse_answer() {
+        assert_eq!(parse_answer("2", 3), Some(Choice::Entry(2)));
+        assert_eq!(parse_answer("e2", 3), Some(Choice::Edit(2)));
+        assert_eq!(parse_answer("E 0", 3), Some(Choice::Edit(0)));
+        assert_eq!(parse_answer("3", 3), None);
+        assert_eq!(parse_answer("e", 3), None);
+        assert_eq!(parse_answer("edit", 3), None);
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("[0] short", 20), "[0] short");
+        assert_eq!(fit("[0] http://boot.example.com/x.efi", 16), "[0] http://bo...");
+    }
+}
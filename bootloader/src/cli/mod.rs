@@ -1,7 +1,17 @@
+pub mod access;
+pub mod args;
+pub mod bulk;
 pub mod commands;
+pub mod complete;
+pub mod history;
+pub mod keymap;
+pub mod line;
+pub mod menu;
+pub mod netsetup;
 pub mod parser;
 pub mod repl;
+pub mod script;
 
-pub use commands::Command;
+pub use commands::{Command, ControlFlow};
 pub use parser::parse_command;
 pub use repl::run;
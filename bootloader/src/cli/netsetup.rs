@@ -0,0 +1,191 @@
+use super::repl::prompt;
+use crate::network::addr::{self, Ipv4, Mac};
+use crate::network::{init, ip4config};
+use crate::storage::{self, config::StaticIp};
+use crate::util::{Error, Result};
+use heapless::Vec;
+use uefi::println;
+
+/// Interactive network setup
+///
+/// Walks through interface selection, DHCP vs static addressing and a
+/// connectivity test, then stores the result in the configuration.
+/// Escape aborts; nothing is changed until the settings are applied.
+pub fn run() -> Result<()> {
+    println!();
+    crate::util::ui::heading("Network Setup");
+    println!("(press Escape to abort)");
+    println!();
+
+    let interfaces = init::list_interfaces()?;
+    if interfaces.is_empty() {
+        println!("No network interfaces found");
+        return Err(Error::NotFound);
+    }
+
+    for (i, nic) in interfaces.iter().enumerate() {
+        let m = nic.mac;
+        println!(
+            "  [{}] {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            i, m[0], m[1], m[2], m[3], m[4], m[5]
+        );
+    }
+
+    let selected = init::select_interface().ok();
+    let current_nic = interfaces.iter().position(|nic| Some(nic.handle) == selected).unwrap_or(0);
+    let nic_index = ask(&alloc::format!("Interface [{}]: ", current_nic), |answer| {
+        if answer.is_empty() {
+            return Ok(current_nic);
+        }
+        let index = answer.parse::<usize>().map_err(|_| Error::Parse)?;
+        if index >= interfaces.len() {
+            return Err(Error::NotFound);
+        }
+        Ok(index)
+    })?;
+    let nic_handle = interfaces[nic_index].handle;
+
+    let use_dhcp = ask("Addressing (dhcp/static) [dhcp]: ", |answer| {
+        match answer.to_ascii_lowercase().as_str() {
+            "" | "dhcp" | "d" => Ok(true),
+            "static" | "s" => Ok(false),
+            _ => Err(Error::InvalidArgument),
+        }
+    })?;
+
+    let static_ip = if use_dhcp { None } else { Some(ask_static()?) };
+
+    // Apply to the interface so the settings can be tested right away
+    println!();
+    println!("Applying settings...");
+    match static_ip {
+        Some(ref settings) => ip4config::set_static(nic_handle, settings)?,
+        None => ip4config::set_dhcp(nic_handle)?,
+    }
+
+    // Downloads pick the interface and addressing from the config
+    storage::with_config_mut(|config| {
+        // An interface picked by MAC stays picked by MAC
+        if config.nic_mac.is_some() {
+            config.nic_mac = Some(Mac(interfaces[nic_index].mac));
+        } else {
+            config.nic_index = if nic_index == 0 { None } else { Some(nic_index) };
+        }
+        config.static_ip = static_ip.clone();
+    })
+    .ok_or(Error::Unknown)?;
+    // Bring the interface up afresh with these settings on the next transfer
+    crate::network::session::close();
+
+    test_connectivity(nic_handle, static_ip.as_ref())?;
+
+    let save = ask("Save configuration to ESP now? [Y/n]: ", |answer| {
+        match answer.to_ascii_lowercase().as_str() {
+            "" | "y" | "yes" => Ok(true),
+            "n" | "no" => Ok(false),
+            _ => Err(Error::InvalidArgument),
+        }
+    })?;
+
+    if save {
+        storage::with_config(storage::save_config).ok_or(Error::Unknown)??;
+        println!("Network settings saved");
+    } else {
+        println!("Remember to run 'save' to persist changes to ESP");
+    }
+
+    Ok(())
+}
+
+/// Prompt until `parse` accepts the answer
+fn ask<T>(question: &str, parse: impl Fn(&str) -> Result<T>) -> Result<T> {
+    loop {
+        let answer = prompt(question)?;
+        match parse(answer.trim()) {
+            Ok(value) => return Ok(value),
+            Err(e) => println!("  Invalid input ({}), try again", e),
+        }
+    }
+}
+
+/// Collect and validate static IPv4 settings
+fn ask_static() -> Result<StaticIp> {
+    let address = ask("IP address: ", addr::parse_ipv4)?;
+
+    let netmask = ask("Netmask [255.255.255.0]: ", |answer| {
+        let mask = if answer.is_empty() {
+            Ipv4([255, 255, 255, 0])
+        } else {
+            addr::parse_netmask(answer)?
+        };
+        if !addr::is_host_address(address, mask) {
+            println!("  {} is not a usable host address with netmask {}", address, mask);
+            return Err(Error::InvalidArgument);
+        }
+        Ok(mask)
+    })?;
+
+    let gateway = ask("Gateway (blank for none): ", |answer| {
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        let gateway = addr::parse_ipv4(answer)?;
+        if !addr::same_subnet(address, gateway, netmask) || gateway == address {
+            println!("  Gateway must be another address in {}/{}", address, netmask);
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Some(gateway))
+    })?;
+
+    let dns = ask("DNS servers, comma separated (blank for none): ", |answer| {
+        let mut servers = Vec::new();
+        for server in answer.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            servers.push(addr::parse_ipv4(server)?).map_err(|_| Error::OutOfMemory)?;
+        }
+        Ok(servers)
+    })?;
+
+    Ok(StaticIp {
+        address,
+        netmask,
+        gateway,
+        dns,
+    })
+}
+
+/// Check that the interface picked up an address, optionally fetching a URL
+fn test_connectivity(nic_handle: uefi::Handle, static_ip: Option<&StaticIp>) -> Result<()> {
+    println!("Testing connectivity...");
+
+    // The firmware DHCP client runs in the background; give it a few seconds
+    let mut current = ip4config::query(nic_handle)?;
+    for _ in 0..50 {
+        if current.is_configured() {
+            break;
+        }
+        uefi::boot::stall(core::time::Duration::from_millis(100));
+        current = ip4config::query(nic_handle)?;
+    }
+
+    if !current.is_configured() {
+        println!("  Interface has no address (DHCP may still be running)");
+    } else {
+        println!("  Interface address: {}", Ipv4(current.address));
+        if let Some(settings) = static_ip {
+            if current.address != settings.address.0 {
+                println!("  Warning: address differs from the requested {}", settings.address);
+            }
+        }
+    }
+
+    let url = prompt("Test URL to fetch (blank to skip): ")?;
+    let url = url.trim();
+    if !url.is_empty() {
+        match crate::network::http::download(url) {
+            Ok(data) => println!("  Connectivity OK ({} bytes received)", data.len()),
+            Err(e) => println!("  Connectivity test failed: {}", e),
+        }
+    }
+
+    Ok(())
+}
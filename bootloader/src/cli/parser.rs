@@ -1,45 +1,196 @@
-use super::commands::Command;
+use super::commands::{AuthAction, Command, NetAction, PolicyAction, Setting};
+use super::keymap::Layout;
+use crate::util::rtc::DateTime;
+use crate::util::beep::BeepMode;
+use crate::util::logger::Echo;
+use crate::util::ui::UiMode;
+use crate::network::http::UploadMethod;
+use crate::storage::config::MAX_NAME_LEN;
+use crate::storage::Store;
 use crate::util::{Error, Result};
 use heapless::String;
 
 const MAX_URL_LEN: usize = 256;
 
+/// What a command takes after its name, as far as completion knows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Nothing
+    None,
+    /// An entry index; Tab also turns entry names into their index
+    Entry,
+    /// One of these words (a subcommand or a setting)
+    Words(&'static [&'static str]),
+    /// Anything else: URLs, paths, host names
+    Text,
+}
+
+/// A command of the prompt: its name, the shorter ways to type it and
+/// what follows it
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub arg: ArgKind,
+}
+
+const fn spec(name: &'static str, aliases: &'static [&'static str], arg: ArgKind) -> CommandSpec {
+    CommandSpec { name, aliases, arg }
+}
+
+/// Every command `parse_command` knows
+pub const COMMANDS: &[CommandSpec] = &[
+    spec("help", &["h", "?"], ArgKind::None),
+    spec("list", &["ls"], ArgKind::Text),
+    spec("add", &[], ArgKind::Text),
+    spec("remove", &["rm"], ArgKind::Entry),
+    spec("disable", &[], ArgKind::Entry),
+    spec("enable", &[], ArgKind::Entry),
+    spec("show", &[], ArgKind::Entry),
+    spec("boot", &[], ArgKind::Entry),
+    spec("default", &[], ArgKind::Entry),
+    spec("save", &[], ArgKind::None),
+    spec("config", &[], ArgKind::Words(&["push", "strict", "storage"])),
+    spec("upload", &[], ArgKind::Text),
+    spec("policy", &[], ArgKind::Words(&["trust", "forget"])),
+    spec("rollback", &[], ArgKind::Words(&["raise"])),
+    spec("auth", &[], ArgKind::Words(&["seal", "test", "forget"])),
+    spec("diag", &[], ArgKind::Words(&["export"])),
+    spec("netsetup", &[], ArgKind::None),
+    spec("net", &[], ArgKind::Words(&["list", "use", "pxe"])),
+    spec("resolve", &[], ArgKind::Text),
+    spec("menu", &["m"], ArgKind::None),
+    spec("login", &[], ArgKind::None),
+    spec("run", &[], ArgKind::Text),
+    spec("set", &[], ArgKind::Words(&["keymap", "ui", "echo", "beep"])),
+    spec("status", &[], ArgKind::None),
+    spec("df", &[], ArgKind::None),
+    spec("date", &["time"], ArgKind::Words(&["set"])),
+    spec("selftest", &[], ArgKind::None),
+    spec("version", &["ver"], ArgKind::None),
+    spec("test-network", &["test"], ArgKind::None),
+    spec("logs", &[], ArgKind::Words(&["save", "clear-file"])),
+    spec("history", &[], ArgKind::Words(&["clear"])),
+    spec("cache", &[], ArgKind::Words(&["list"])),
+    spec("exit", &["quit", "q"], ArgKind::None),
+];
+
+/// The command typed as `word`, by name or alias in any case
+pub fn lookup(word: &str) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(word) || spec.aliases.iter().any(|a| a.eq_ignore_ascii_case(word)))
+}
+
 /// Parse a command string into a Command
 pub fn parse_command(input: &str) -> Result<Command> {
-    let input = input.trim();
-
-    if input.is_empty() {
-        return Err(Error::Parse);
-    }
+    parse_words(input.split_whitespace())
+}
 
-    // Split into command and arguments
-    let mut parts = input.split_whitespace();
+/// Parse a command already split into words, such as command line
+/// arguments whose quotes kept spaces inside them
+pub fn parse_words<'a>(mut parts: impl Iterator<Item = &'a str>) -> Result<Command> {
     let cmd = parts.next().ok_or(Error::Parse)?;
+    let spec = lookup(cmd).ok_or(Error::InvalidCommand)?;
 
-    match cmd.to_lowercase().as_str() {
-        "help" | "h" | "?" => Ok(Command::Help),
+    match spec.name {
+        "help" => Ok(Command::Help),
 
-        "list" | "ls" => Ok(Command::List),
+        "list" => {
+            let filter = match parts.next() {
+                Some(f) => {
+                    let mut filter = String::new();
+                    filter.push_str(f).map_err(|_| Error::BufferTooSmall)?;
+                    Some(filter)
+                }
+                None => None,
+            };
+            Ok(Command::List(filter))
+        }
 
         "add" => {
-            let url = parts.next().ok_or(Error::InvalidArgument)?;
+            let mut url = parts.next().ok_or(Error::InvalidArgument)?;
+            if url == "--from" {
+                let source = parts.next().ok_or(Error::InvalidArgument)?;
+                let mut source_string = String::new();
+                source_string.push_str(source).map_err(|_| Error::BufferTooSmall)?;
+                return Ok(Command::AddFrom(source_string));
+            }
+            let replace = url == "--replace";
+            if replace {
+                url = parts.next().ok_or(Error::InvalidArgument)?;
+            }
             let mut url_string = String::new();
             url_string.push_str(url).map_err(|_| Error::BufferTooSmall)?;
-            Ok(Command::Add(url_string))
+
+            // The rest of the line names the entry
+            let mut name: String<MAX_NAME_LEN> = String::new();
+            for word in parts {
+                if !name.is_empty() {
+                    name.push(' ').map_err(|_| Error::BufferTooSmall)?;
+                }
+                name.push_str(word).map_err(|_| Error::BufferTooSmall)?;
+            }
+            let name = Some(name).filter(|name| !name.is_empty());
+            Ok(Command::Add(url_string, name, replace))
         }
 
-        "remove" | "rm" => {
+        "remove" => {
             let index_str = parts.next().ok_or(Error::InvalidArgument)?;
             let index = index_str.parse::<usize>().map_err(|_| Error::Parse)?;
             Ok(Command::Remove(index))
         }
 
-        "boot" => {
+        "disable" => {
             let index_str = parts.next().ok_or(Error::InvalidArgument)?;
             let index = index_str.parse::<usize>().map_err(|_| Error::Parse)?;
-            Ok(Command::Boot(index))
+            Ok(Command::Disable(index))
         }
 
+        "enable" => {
+            let index_str = parts.next().ok_or(Error::InvalidArgument)?;
+            let index = index_str.parse::<usize>().map_err(|_| Error::Parse)?;
+            Ok(Command::Enable(index))
+        }
+
+        "upload" => {
+            let path = parts.next().ok_or(Error::InvalidArgument)?;
+            let url = parts.next().ok_or(Error::InvalidArgument)?;
+            let mut path_string = String::new();
+            path_string.push_str(path).map_err(|_| Error::BufferTooSmall)?;
+            let mut url_string = String::new();
+            url_string.push_str(url).map_err(|_| Error::BufferTooSmall)?;
+            let method = upload_method(parts.next())?;
+            Ok(Command::Upload(path_string, url_string, method))
+        }
+
+        "run" => {
+            let path = parts.next().ok_or(Error::InvalidArgument)?;
+            let mut path_string = String::new();
+            path_string.push_str(path).map_err(|_| Error::BufferTooSmall)?;
+            Ok(Command::Run(path_string))
+        }
+
+        "show" => {
+            let index_str = parts.next().ok_or(Error::InvalidArgument)?;
+            let index = index_str.parse::<usize>().map_err(|_| Error::Parse)?;
+            Ok(Command::Show(index))
+        }
+
+        "boot" => match parts.next() {
+            Some(name) if name == crate::boot::rescue::NAME => Ok(Command::BootRescue),
+            Some("--edit") => {
+                let index_str = parts.next().ok_or(Error::InvalidArgument)?;
+                let index = index_str.parse::<usize>().map_err(|_| Error::Parse)?;
+                Ok(Command::BootEdited(index))
+            }
+            Some(index_str) => {
+                let index = index_str.parse::<usize>().map_err(|_| Error::Parse)?;
+                Ok(Command::Boot(Some(index)))
+            }
+            None => Ok(Command::Boot(None)),
+        },
+
         "default" => {
             let index_str = parts.next().ok_or(Error::InvalidArgument)?;
             let index = index_str.parse::<usize>().map_err(|_| Error::Parse)?;
@@ -48,20 +199,203 @@ pub fn parse_command(input: &str) -> Result<Command> {
 
         "save" => Ok(Command::Save),
 
-        "test-network" | "test" => Ok(Command::TestNetwork),
+        "config" => match parts.next().ok_or(Error::InvalidArgument)? {
+            "push" => {
+                let url = parts.next().ok_or(Error::InvalidArgument)?;
+                let mut url_string = String::new();
+                url_string.push_str(url).map_err(|_| Error::BufferTooSmall)?;
+                let method = upload_method(parts.next())?;
+                Ok(Command::ConfigPush(url_string, method))
+            }
+            "strict" => match parts.next() {
+                None => Ok(Command::ConfigStrict(None)),
+                Some(value) => {
+                    let strict = crate::storage::config::parse_bool(value)?;
+                    Ok(Command::ConfigStrict(Some(strict)))
+                }
+            },
+            "storage" => match parts.next() {
+                None => Ok(Command::ConfigStorage(None)),
+                Some(name) => Ok(Command::ConfigStorage(Some(Store::parse(name)?))),
+            },
+            _ => Err(Error::InvalidArgument),
+        },
+
+        "policy" => match parts.next() {
+            None => Ok(Command::Policy(PolicyAction::Show)),
+            Some("trust") => Ok(Command::Policy(PolicyAction::Trust)),
+            Some("forget") => Ok(Command::Policy(PolicyAction::Forget)),
+            Some(_) => Err(Error::InvalidArgument),
+        },
+
+        "rollback" => match parts.next() {
+            None => Ok(Command::Rollback(None)),
+            Some("raise") => {
+                let version_str = parts.next().ok_or(Error::InvalidArgument)?;
+                let version = version_str.parse::<u32>().map_err(|_| Error::Parse)?;
+                Ok(Command::Rollback(Some(version)))
+            }
+            Some(_) => Err(Error::InvalidArgument),
+        },
+
+        "auth" => {
+            let action = parts.next().ok_or(Error::InvalidArgument)?;
+            let host_str = parts.next().ok_or(Error::InvalidArgument)?;
+            let mut host = String::new();
+            host.push_str(host_str).map_err(|_| Error::BufferTooSmall)?;
+            match action {
+                "seal" => {
+                    let pcrs = match parts.next() {
+                        Some(list) => crate::util::tpm::parse_pcrs(list)?,
+                        None => crate::util::tpm::pcr_mask(&crate::storage::secrets::DEFAULT_PCRS)?,
+                    };
+                    Ok(Command::Auth(AuthAction::Seal(host, pcrs)))
+                }
+                "test" => Ok(Command::Auth(AuthAction::Test(host))),
+                "forget" => Ok(Command::Auth(AuthAction::Forget(host))),
+                _ => Err(Error::InvalidArgument),
+            }
+        }
 
-        "logs" => Ok(Command::Logs),
+        "diag" => match parts.next().ok_or(Error::InvalidArgument)? {
+            "export" => {
+                let target = match parts.next() {
+                    Some(target) => {
+                        let mut target_string = String::new();
+                        target_string.push_str(target).map_err(|_| Error::BufferTooSmall)?;
+                        Some(target_string)
+                    }
+                    None => None,
+                };
+                Ok(Command::DiagExport(target))
+            }
+            _ => Err(Error::InvalidArgument),
+        },
 
-        "exit" | "quit" | "q" => Ok(Command::Exit),
+        "netsetup" => Ok(Command::NetSetup),
+
+        "net" => match parts.next().ok_or(Error::InvalidArgument)? {
+            "list" | "ls" => Ok(Command::Net(NetAction::List)),
+            "use" => {
+                let index_str = parts.next().ok_or(Error::InvalidArgument)?;
+                let index = index_str.parse::<usize>().map_err(|_| Error::Parse)?;
+                Ok(Command::Net(NetAction::Use(index)))
+            }
+            "pxe" => Ok(Command::Net(NetAction::Pxe)),
+            _ => Err(Error::InvalidArgument),
+        },
+
+        "resolve" => {
+            let host_str = parts.next().ok_or(Error::InvalidArgument)?;
+            let mut host = String::new();
+            host.push_str(host_str).map_err(|_| Error::BufferTooSmall)?;
+            Ok(Command::Resolve(host))
+        }
+
+        "menu" => Ok(Command::Menu),
+
+        "login" => Ok(Command::Login),
+
+        "set" => {
+            let key = parts.next().ok_or(Error::InvalidArgument)?;
+            let value = parts.next().ok_or(Error::InvalidArgument)?;
+            match key {
+                "keymap" => Ok(Command::Set(Setting::Keymap(Layout::parse(value)?))),
+                "ui" => Ok(Command::Set(Setting::Ui(UiMode::parse(value)?))),
+                "echo" => Ok(Command::Set(Setting::Echo(Echo::parse(value)?))),
+                "beep" => Ok(Command::Set(Setting::Beep(BeepMode::parse(value)?))),
+                _ => Err(Error::InvalidArgument),
+            }
+        }
+
+        "status" => Ok(Command::Status),
+
+        "test-network" => Ok(Command::TestNetwork),
+
+        "logs" => match parts.next() {
+            None => Ok(Command::Logs),
+            Some("save") => Ok(Command::LogsSave),
+            Some("clear-file") => Ok(Command::LogsClearFile),
+            Some(_) => Err(Error::InvalidArgument),
+        },
+
+        "history" => match parts.next() {
+            None => Ok(Command::History),
+            Some("clear") => Ok(Command::HistoryClear),
+            Some(_) => Err(Error::InvalidArgument),
+        },
+
+        "cache" => match parts.next() {
+            Some("list") => Ok(Command::CacheList),
+            _ => Err(Error::InvalidArgument),
+        },
+
+        "df" => Ok(Command::Df),
+
+        "date" => match parts.next() {
+            None => Ok(Command::Date(None)),
+            Some("set") => {
+                let date = parts.next().ok_or(Error::InvalidArgument)?;
+                let time = parts.next().ok_or(Error::InvalidArgument)?;
+                if parts.next().is_some() {
+                    return Err(Error::InvalidArgument);
+                }
+                Ok(Command::Date(Some(DateTime::parse(date, time)?)))
+            }
+            Some(_) => Err(Error::InvalidArgument),
+        },
+
+        "selftest" => Ok(Command::SelfTest),
+
+        "version" => Ok(Command::Version),
+
+        "exit" => Ok(Command::Exit),
 
         _ => Err(Error::InvalidCommand),
     }
 }
 
+/// `--put` (the default) or `--post`
+fn upload_method(flag: Option<&str>) -> Result<UploadMethod> {
+    match flag {
+        None | Some("--put") => Ok(UploadMethod::Put),
+        Some("--post") => Ok(UploadMethod::Post),
+        Some(_) => Err(Error::InvalidArgument),
+    }
+}
+
+/// Split `command > path` into the command and the file its output goes to
+///
+/// Only the last `>` counts, so the path is everything after it.
+pub fn split_redirect(input: &str) -> Result<(&str, Option<&str>)> {
+    match input.rsplit_once('>') {
+        None => Ok((input, None)),
+        Some((command, path)) => {
+            let path = path.trim();
+            if command.trim().is_empty() || path.is_empty() {
+                return Err(Error::InvalidArgument);
+            }
+            Ok((command.trim_end(), Some(path)))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_command_table() {
+        // Every name and alias is one parse_command handles
+        for spec in COMMANDS {
+            for word in core::iter::once(&spec.name).chain(spec.aliases) {
+                assert_ne!(parse_command(word).err(), Some(Error::InvalidCommand), "{}", word);
+                assert_eq!(lookup(&word.to_uppercase()).map(|found| found.name), Some(spec.name));
+            }
+        }
+        assert!(lookup("reboot").is_none());
+    }
+
     #[test]
     fn test_parse_help() {
         assert!(matches!(parse_command("help"), Ok(Command::Help)));
@@ -71,8 +405,46 @@ mod tests {
 
     #[test]
     fn test_parse_list() {
-        assert!(matches!(parse_command("list"), Ok(Command::List)));
-        assert!(matches!(parse_command("ls"), Ok(Command::List)));
+        assert!(matches!(parse_command("list"), Ok(Command::List(None))));
+        assert!(matches!(parse_command("ls"), Ok(Command::List(None))));
+        match parse_command("list ubuntu") {
+            Ok(Command::List(Some(filter))) => assert_eq!(filter.as_str(), "ubuntu"),
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_menu() {
+        assert!(matches!(parse_command("menu"), Ok(Command::Menu)));
+        assert!(matches!(parse_command("m"), Ok(Command::Menu)));
+    }
+
+    #[test]
+    fn test_parse_login() {
+        assert!(matches!(parse_command("login"), Ok(Command::Login)));
+        assert!(matches!(parse_command("LOGIN"), Ok(Command::Login)));
+    }
+
+    #[test]
+    fn test_parse_logs() {
+        assert!(matches!(parse_command("logs"), Ok(Command::Logs)));
+        assert!(matches!(parse_command("logs save"), Ok(Command::LogsSave)));
+        assert!(matches!(parse_command("logs clear-file"), Ok(Command::LogsClearFile)));
+        assert!(parse_command("logs rotate").is_err());
+    }
+
+    #[test]
+    fn test_parse_history() {
+        assert!(matches!(parse_command("history"), Ok(Command::History)));
+        assert!(matches!(parse_command("history clear"), Ok(Command::HistoryClear)));
+        assert!(parse_command("history 5").is_err());
+    }
+
+    #[test]
+    fn test_parse_cache() {
+        assert!(matches!(parse_command("cache list"), Ok(Command::CacheList)));
+        assert!(parse_command("cache").is_err());
+        assert!(parse_command("cache drop").is_err());
     }
 
     #[test]
@@ -85,7 +457,177 @@ mod tests {
     #[test]
     fn test_parse_add() {
         let result = parse_command("add https://example.com/image.efi");
-        assert!(matches!(result, Ok(Command::Add(_))));
+        assert!(matches!(result, Ok(Command::Add(_, None, false))));
+        match parse_command("add --replace https://example.com/image.efi Ubuntu  24.04") {
+            Ok(Command::Add(url, Some(name), true)) => {
+                assert_eq!(url.as_str(), "https://example.com/image.efi");
+                assert_eq!(name.as_str(), "Ubuntu 24.04");
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(parse_command("add --replace").is_err());
+        match parse_command("add --from \\EFI\\uefipxe\\images.txt") {
+            Ok(Command::AddFrom(source)) => assert_eq!(source.as_str(), "\\EFI\\uefipxe\\images.txt"),
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(parse_command("add --from").is_err());
+    }
+
+    #[test]
+    fn test_parse_words() {
+        match parse_words(["add", "file://USB/a b.efi", "My  Entry"].into_iter()) {
+            Ok(Command::Add(url, Some(name), false)) => {
+                assert_eq!(url.as_str(), "file://USB/a b.efi");
+                assert_eq!(name.as_str(), "My  Entry");
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(parse_words(core::iter::empty()).is_err());
+    }
+
+    #[test]
+    fn test_parse_upload() {
+        match parse_command("upload \\EFI\\uefipxe\\logs.txt http://10.0.0.1/logs/node1 --post") {
+            Ok(Command::Upload(path, url, UploadMethod::Post)) => {
+                assert_eq!(path.as_str(), "\\EFI\\uefipxe\\logs.txt");
+                assert_eq!(url.as_str(), "http://10.0.0.1/logs/node1");
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(matches!(parse_command("upload a.txt http://10.0.0.1/a"), Ok(Command::Upload(_, _, UploadMethod::Put))));
+        assert!(parse_command("upload a.txt").is_err());
+    }
+
+    #[test]
+    fn test_parse_config_push() {
+        let result = parse_command("config push http://10.0.0.1/configs/node1");
+        assert!(matches!(result, Ok(Command::ConfigPush(_, UploadMethod::Put))));
+        let result = parse_command("config push http://10.0.0.1/configs --post");
+        assert!(matches!(result, Ok(Command::ConfigPush(_, UploadMethod::Post))));
+        assert!(parse_command("config push").is_err());
+        assert!(parse_command("config push http://10.0.0.1/ --patch").is_err());
+        assert!(parse_command("config pull http://10.0.0.1/").is_err());
+    }
+
+    #[test]
+    fn test_parse_config_strict() {
+        assert!(matches!(parse_command("config strict"), Ok(Command::ConfigStrict(None))));
+        assert!(matches!(parse_command("config strict on"), Ok(Command::ConfigStrict(Some(true)))));
+        assert!(matches!(parse_command("config strict off"), Ok(Command::ConfigStrict(Some(false)))));
+        assert!(parse_command("config strict maybe").is_err());
+    }
+
+    #[test]
+    fn test_parse_config_storage() {
+        assert!(matches!(parse_command("config storage"), Ok(Command::ConfigStorage(None))));
+        assert!(matches!(parse_command("config storage nvram"), Ok(Command::ConfigStorage(Some(Store::Nvram)))));
+        assert!(matches!(parse_command("config storage esp"), Ok(Command::ConfigStorage(Some(Store::Esp)))));
+        assert!(parse_command("config storage usb").is_err());
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert!(matches!(parse_command("policy"), Ok(Command::Policy(PolicyAction::Show))));
+        assert!(matches!(parse_command("policy trust"), Ok(Command::Policy(PolicyAction::Trust))));
+        assert!(matches!(parse_command("policy forget"), Ok(Command::Policy(PolicyAction::Forget))));
+        assert!(parse_command("policy edit").is_err());
+    }
+
+    #[test]
+    fn test_parse_rollback() {
+        assert!(matches!(parse_command("rollback"), Ok(Command::Rollback(None))));
+        assert!(matches!(parse_command("rollback raise 7"), Ok(Command::Rollback(Some(7)))));
+        assert!(parse_command("rollback raise").is_err());
+        assert!(parse_command("rollback lower 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_auth() {
+        let result = parse_command("auth seal images.example.com");
+        assert!(matches!(result, Ok(Command::Auth(AuthAction::Seal(ref host, 0x95))) if host == "images.example.com"));
+        assert!(matches!(parse_command("auth seal a 7"), Ok(Command::Auth(AuthAction::Seal(_, 0x80)))));
+        assert!(matches!(parse_command("auth forget a"), Ok(Command::Auth(AuthAction::Forget(_)))));
+        assert!(parse_command("auth seal a 99").is_err());
+        assert!(parse_command("auth test").is_err());
+    }
+
+    #[test]
+    fn test_parse_net() {
+        assert!(matches!(parse_command("net list"), Ok(Command::Net(NetAction::List))));
+        assert!(matches!(parse_command("net use 2"), Ok(Command::Net(NetAction::Use(2)))));
+        assert!(matches!(parse_command("net pxe"), Ok(Command::Net(NetAction::Pxe))));
+        assert!(parse_command("net use").is_err());
+        assert!(parse_command("net use eth0").is_err());
+        assert!(parse_command("net").is_err());
+    }
+
+    #[test]
+    fn test_parse_resolve() {
+        match parse_command("resolve images.example.com") {
+            Ok(Command::Resolve(host)) => assert_eq!(host.as_str(), "images.example.com"),
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(parse_command("resolve").is_err());
+    }
+
+    #[test]
+    fn test_parse_diag_export() {
+        assert!(matches!(parse_command("diag export"), Ok(Command::DiagExport(None))));
+        let result = parse_command("diag export http://10.0.0.1/support");
+        assert!(matches!(result, Ok(Command::DiagExport(Some(_)))));
+        assert!(parse_command("diag").is_err());
+    }
+
+    #[test]
+    fn test_parse_selftest() {
+        assert!(matches!(parse_command("selftest"), Ok(Command::SelfTest)));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert!(matches!(parse_command("version"), Ok(Command::Version)));
+        assert!(matches!(parse_command("ver"), Ok(Command::Version)));
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert!(matches!(parse_command("date"), Ok(Command::Date(None))));
+        assert!(matches!(parse_command("time"), Ok(Command::Date(None))));
+        let result = parse_command("date set 2026-10-16 14:30");
+        assert!(matches!(result, Ok(Command::Date(Some(DateTime { year: 2026, hour: 14, minute: 30, .. })))));
+        assert!(parse_command("date set 2026-10-16").is_err());
+        assert!(parse_command("date set 2026-02-30 14:30").is_err());
+        assert!(parse_command("date set 2026-10-16 14:30 UTC").is_err());
+        assert!(parse_command("date now").is_err());
+    }
+
+    #[test]
+    fn test_split_redirect() {
+        assert_eq!(split_redirect("status"), Ok(("status", None)));
+        assert_eq!(
+            split_redirect("logs > \\EFI\\logs.txt"),
+            Ok(("logs", Some("\\EFI\\logs.txt")))
+        );
+        assert_eq!(split_redirect("list prod>out.txt"), Ok(("list prod", Some("out.txt"))));
+        assert_eq!(split_redirect("status >"), Err(Error::InvalidArgument));
+        assert_eq!(split_redirect("> out.txt"), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn test_parse_set() {
+        let result = parse_command("set keymap de");
+        assert!(matches!(result, Ok(Command::Set(Setting::Keymap(Layout::De)))));
+        assert!(parse_command("set keymap").is_err());
+        assert!(parse_command("set keymap dvorak").is_err());
+        let result = parse_command("set ui plain");
+        assert!(matches!(result, Ok(Command::Set(Setting::Ui(UiMode::Plain)))));
+        let result = parse_command("set echo quiet");
+        assert!(matches!(result, Ok(Command::Set(Setting::Echo(Echo::Quiet)))));
+        assert!(parse_command("set echo loud").is_err());
+        let result = parse_command("set beep speaker");
+        assert!(matches!(result, Ok(Command::Set(Setting::Beep(BeepMode::Speaker)))));
+        assert!(parse_command("set beep loud").is_err());
+        assert!(parse_command("set colour blue").is_err());
     }
 
     #[test]
@@ -93,4 +635,23 @@ mod tests {
         let result = parse_command("remove 0");
         assert!(matches!(result, Ok(Command::Remove(0))));
     }
+
+    #[test]
+    fn test_parse_disable_enable() {
+        assert_eq!(parse_command("disable 3"), Ok(Command::Disable(3)));
+        assert_eq!(parse_command("enable 3"), Ok(Command::Enable(3)));
+        assert!(parse_command("disable").is_err());
+        assert!(parse_command("enable x").is_err());
+    }
+
+    #[test]
+    fn test_parse_boot() {
+        assert!(matches!(parse_command("boot 2"), Ok(Command::Boot(Some(2)))));
+        assert!(matches!(parse_command("boot"), Ok(Command::Boot(None))));
+        assert!(matches!(parse_command("boot @builtin-rescue"), Ok(Command::BootRescue)));
+        assert!(matches!(parse_command("boot --edit 1"), Ok(Command::BootEdited(1))));
+        assert!(parse_command("boot --edit").is_err());
+        assert!(matches!(parse_command("show 1"), Ok(Command::Show(1))));
+        assert!(parse_command("show").is_err());
+    }
 }
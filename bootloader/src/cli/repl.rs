@@ -1,27 +1,67 @@
-use super::parser::parse_command;
+use super::access;
+use super::commands::{Command, ControlFlow};
+use super::complete::{self, Completion};
+use super::history;
+use super::line::{self, LineEditor};
+use super::parser::{parse_command, split_redirect};
+use crate::util::capture;
+use crate::util::ui;
+use crate::util::wipe::{wipe, Wiped};
 use crate::util::{Error, Result};
 use heapless::String;
-use uefi::{println, proto::console::text::Key};
+use uefi::{println, proto::console::text::Key, Status};
 use alloc::format;
 use core::time::Duration;
 
 const MAX_INPUT_LEN: usize = 256;
 const PROMPT: &str = "uefipxe> ";
 
+/// Countdown shown before an idle prompt resumes the default boot
+const IDLE_COUNTDOWN_SECS: u32 = 10;
+
+/// Step of the key polling loop
+const POLL_INTERVAL: Duration = Duration::from_micros(10_000); // 10ms
+
 /// Main REPL (Read-Eval-Print Loop)
-pub fn run() -> Result<()> {
+///
+/// Returns the status to hand back to the firmware once the user exits.
+pub fn run() -> Status {
     println!();
     println!("Welcome to UEFI PXE Bootloader CLI");
     println!("Type 'help' for available commands");
+    if crate::boot::safemode::active() {
+        println!("Safe mode: fix the configuration and 'save' it; the next start is a normal one");
+    }
     println!();
 
-    loop {
-        // Print prompt
-        print_prompt();
+    // Getting here is no crash, whatever happens next
+    crate::boot::safemode::settle();
+
+    // With passwords set, nobody gets a prompt without one
+    if access::role().is_none() && access::login().is_none() {
+        crate::util::logger::log_entry(log::Level::Warn, "CLI login failed, leaving");
+        return Status::ACCESS_DENIED;
+    }
+
+    // Earlier visits' commands, for the Up arrow
+    history::load();
 
-        // Read line
-        let line = match read_line() {
+    loop {
+        // Prompt and read a line, giving up after `idle_timeout=` minutes
+        // without a key
+        let idle_minutes = crate::storage::with_config(|config| config.idle_timeout)
+            .flatten()
+            .filter(|_| !crate::boot::safemode::active());
+        let idle_limit = idle_minutes.map(|minutes| Duration::from_secs(u64::from(minutes) * 60));
+        let line = match read_line_until::<MAX_INPUT_LEN>(PROMPT, "", idle_limit, true) {
             Ok(line) => line,
+            Err(Error::Uefi(uefi::Status::TIMEOUT)) => {
+                println!();
+                if let Some(status) = idle_boot(idle_minutes.unwrap_or(0)) {
+                    return status;
+                }
+                continue;
+            }
             Err(Error::Uefi(uefi::Status::ABORTED)) => {
                 // User pressed Ctrl+C or similar
                 println!();
@@ -39,25 +79,30 @@ pub fn run() -> Result<()> {
             continue;
         }
 
-        // Log the command
+        // Log the command and keep it for the next visit
         crate::util::logger::log_entry(log::Level::Info, &format!("Command: {}", line));
+        history::record(&line);
 
-        // Parse and execute command
-        match parse_command(&line) {
-            Ok(cmd) => {
-                // Check if it's an exit command
-                if matches!(cmd, super::commands::Command::Exit) {
-                    println!("Goodbye!");
-                    return Ok(());
+        // Parse and execute command, with any output redirection split off
+        let parsed = split_redirect(&line)
+            .and_then(|(command, redirect)| parse_command(command).map(|cmd| (cmd, redirect)));
+        match parsed {
+            // Writing a report to a file is a change like any other
+            Ok((cmd, redirect))
+                if !access::permits(&cmd) || (redirect.is_some() && access::role() != Some(access::Role::Admin)) =>
+            {
+                println!("Operators cannot do that; 'login' with the admin password first");
+            }
+            Ok((cmd, Some(path))) => {
+                if cmd.is_report() {
+                    execute_redirected(&cmd, path);
+                } else {
+                    println!("Only reports such as list, status or logs can be redirected.");
                 }
-
-                // Execute command
-                if let Err(e) = cmd.execute() {
-                    println!("Error executing command: {}", e);
-                    crate::util::logger::log_entry(
-                        log::Level::Error,
-                        &format!("Command error: {}", e),
-                    );
+            }
+            Ok((cmd, None)) => {
+                if let Some(status) = execute(&cmd) {
+                    return status;
                 }
             }
             Err(Error::InvalidCommand) => {
@@ -73,50 +118,267 @@ pub fn run() -> Result<()> {
     }
 }
 
-fn print_prompt() {
-    uefi::print!("{}", PROMPT);
+/// Run a command, returning the exit status if it leaves the CLI
+fn execute(cmd: &Command) -> Option<Status> {
+    let status = match cmd.execute() {
+        Ok(ControlFlow::Continue) => None,
+        Ok(ControlFlow::Exit(status)) => {
+            println!("Goodbye!");
+            Some(status)
+        }
+        Err(e) => {
+            println!("Error executing command: {}", e);
+            crate::util::logger::log_entry(
+                log::Level::Error,
+                &format!("Command error: {}", e),
+            );
+            None
+        }
+    };
+    // Record what the command did on the ESP before anything else happens
+    crate::util::logger::flush();
+    status
+}
+
+/// Run a report command with its console output going to a file on the ESP
+///
+/// Gives a way to collect diagnostics on machines without serial logging.
+fn execute_redirected(cmd: &Command, path: &str) {
+    if let Err(e) = capture::start() {
+        println!("Error redirecting output: {}", e);
+        return;
+    }
+    // Reports never leave the CLI, so there is no status to pass on
+    let _ = execute(cmd);
+    let output = capture::stop();
+
+    match crate::storage::file::atomic_write(path, output.as_bytes()) {
+        Ok(()) => println!("Output ({} bytes) written to {}", output.len(), path),
+        Err(e) => println!("Error writing {}: {}", path, e),
+    }
+}
+
+/// The prompt was left alone for `minutes`: count down, then boot
+///
+/// Keeps a machine left at the prompt after remote troubleshooting from
+/// staying down. Any key cancels; the idle timer then starts over.
+fn idle_boot(minutes: u32) -> Option<Status> {
+    println!("No input for {} minutes", minutes);
+    crate::util::logger::log_entry(
+        log::Level::Info,
+        &format!("Prompt idle for {} minutes, resuming boot", minutes),
+    );
+
+    if ui::is_plain() {
+        println!("Booting in {} seconds, press any key to cancel", IDLE_COUNTDOWN_SECS);
+    }
+    for remaining in (1..=IDLE_COUNTDOWN_SECS).rev() {
+        if !ui::is_plain() {
+            uefi::print!("\rBooting in {:2} seconds, press any key to cancel", remaining);
+        }
+        match wait_for_key_until(Some(Duration::from_secs(1))) {
+            Ok(None) => {}
+            Ok(Some(_)) | Err(_) => {
+                println!();
+                println!("Automatic boot cancelled");
+                return None;
+            }
+        }
+    }
+    println!();
+
+    crate::util::splash::set_unattended(true);
+    let status = execute(&Command::Boot(None));
+    crate::util::splash::set_unattended(false);
+    status
+}
+
+/// Print a question and read the answer (Escape aborts)
+pub fn prompt(question: &str) -> Result<String<MAX_INPUT_LEN>> {
+    read_line_until(question, "", None, false)
+}
+
+/// Print a question with `answer` already typed, to be edited rather than
+/// typed again, and read the edited answer (Escape aborts)
+pub fn edit<const N: usize>(question: &str, answer: &str) -> Result<String<N>> {
+    read_line_until(question, answer, None, false)
+}
+
+/// Print a question and read an answer that is not shown, such as a
+/// password (Escape aborts)
+///
+/// The answer is kept in a buffer that is never regrown and is zeroed
+/// when dropped, so no copy of it is left behind.
+pub fn secret(question: &str) -> Result<Wiped> {
+    uefi::print!("{}", question);
+    let mut answer = Wiped::with_capacity(MAX_INPUT_LEN);
+    loop {
+        match wait_for_key()? {
+            Key::Printable(c) => match char::from(c) {
+                '\r' | '\n' => break,
+                '\x08' | '\x7f' => {
+                    // Back to the start of the last character
+                    let start = answer.iter().rposition(|byte| byte & 0xc0 != 0x80).unwrap_or(0);
+                    answer.truncate(start);
+                }
+                c if c.is_control() => {}
+                c => {
+                    let mut utf8 = [0; 4];
+                    let c = super::keymap::layout().translate(c).encode_utf8(&mut utf8);
+                    if answer.len() + c.len() <= MAX_INPUT_LEN {
+                        answer.extend_from_slice(c.as_bytes());
+                    }
+                    wipe(&mut utf8);
+                }
+            },
+            Key::Special(uefi::proto::console::text::ScanCode::NULL) => break,
+            Key::Special(uefi::proto::console::text::ScanCode::ESCAPE) => {
+                println!();
+                return Err(Error::Uefi(uefi::Status::ABORTED));
+            }
+            Key::Special(_) => {}
+        }
+    }
+    println!();
+    Ok(answer)
+}
+
+/// Pause between pages of output
+///
+/// Returns false when the user asks to stop (q or Escape).
+pub fn more(remaining: usize) -> Result<bool> {
+    uefi::print!("-- {} more: Enter to continue, q to stop --", remaining);
+    let key = wait_for_key()?;
+    println!();
+
+    Ok(match key {
+        Key::Printable(c) => !matches!(char::from(c), 'q' | 'Q'),
+        Key::Special(uefi::proto::console::text::ScanCode::ESCAPE) => false,
+        Key::Special(_) => true,
+    })
 }
 
-/// Read a line of input from the user
-fn read_line() -> Result<String<MAX_INPUT_LEN>> {
-    let mut buffer = String::<MAX_INPUT_LEN>::new();
+/// Print a prompt and read a line, failing with TIMEOUT once no key came
+/// for `idle`
+///
+/// Left, Right, Home and End move the cursor, typing inserts at it, and
+/// Backspace and Delete remove the character before and under it. The
+/// usual shell shortcuts work as well: Ctrl+A/E (start/end), Ctrl+K/U
+/// (kill to end/start), Ctrl+W (kill word), Ctrl+Y (yank killed text) and
+/// Ctrl+L (clear screen). For a `command` line, Up and Down step through
+/// earlier commands and Tab completes the word before the cursor. The line
+/// starts out as `initial`.
+fn read_line_until<const N: usize>(
+    prompt: &str,
+    initial: &str,
+    idle: Option<Duration>,
+    command: bool,
+) -> Result<String<N>> {
+    let mut line = LineEditor::<N>::new();
+    line.replace(initial);
+    uefi::print!("{}{}", prompt, line.as_str());
+    let mut edited = false;
+
+    // Steps up into the history (0: the line being typed, kept in `draft`)
+    let mut recalled = 0;
+    let mut draft: String<N> = String::new();
 
     loop {
-        // Wait for key press
-        let key = wait_for_key()?;
+        // Wait for key press; every key restarts the idle timer
+        let key = wait_for_key_until(idle)?.ok_or(Error::Uefi(uefi::Status::TIMEOUT))?;
 
-        match key {
+        let (old_cursor, old_columns) = (line.cursor_column(), line.columns());
+        let changed = match key {
             Key::Printable(char) => {
                 // Handle printable characters
                 let c: char = char.into();
 
-                // Check for Enter key (carriage return or line feed)
-                if c == '\r' || c == '\n' {
-                    println!();
-                    return Ok(buffer);
-                }
-
-                // Check for backspace
-                if c == '\x08' || c == '\x7f' {
-                    if !buffer.is_empty() {
-                        buffer.pop();
-                        // Move cursor back, print space, move back again
-                        uefi::print!("\x08 \x08");
+                match c {
+                    // Enter key (carriage return or line feed)
+                    '\r' | '\n' => {
+                        println!();
+                        echo_edited_line(line.as_str(), edited);
+                        return Ok(line.into_string());
                     }
-                    continue;
-                }
+                    // Backspace
+                    '\x08' | '\x7f' => {
+                        if line.at_end() {
+                            if line.backspace() {
+                                edited = true;
+                                // Erase the character on screen (fancy mode only)
+                                ui::erase_char();
+                            }
+                            continue;
+                        }
+                        line.backspace()
+                    }
+                    line::CTRL_A => {
+                        line.home();
+                        true
+                    }
+                    line::CTRL_E => {
+                        line.end();
+                        true
+                    }
+                    line::CTRL_K => line.kill_to_end(),
+                    line::CTRL_U => line.kill_to_start(),
+                    line::CTRL_W => line.kill_word(),
+                    line::CTRL_Y => line.yank(),
+                    line::CTRL_L => {
+                        clear_screen(prompt, &line);
+                        continue;
+                    }
+                    '\t' if command => {
+                        if !line.at_end() {
+                            continue;
+                        }
+                        match complete_line(line.as_str()) {
+                            Completion::None => continue,
+                            Completion::Choices(choices) => {
+                                // List them, then start the line over below
+                                println!();
+                                println!("{}", choices.join("  "));
+                                uefi::print!("{}{}", prompt, line.as_str());
+                                edited = false;
+                                continue;
+                            }
+                            Completion::Line(text) => {
+                                let appended = text.strip_prefix(line.as_str()).map(alloc::string::String::from);
+                                line.replace(&text);
+                                if !ui::is_plain() {
+                                    true
+                                } else {
+                                    match appended {
+                                        Some(appended) => uefi::print!("{}", appended),
+                                        // A name that became an index: show the line afresh
+                                        None => {
+                                            println!();
+                                            uefi::print!("{}{}", prompt, line.as_str());
+                                            edited = false;
+                                        }
+                                    }
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    c if c.is_control() && c != '\t' => continue,
+                    c => {
+                        // Map from what the firmware reported to the selected layout
+                        let c = super::keymap::layout().translate(c);
+                        let appending = line.at_end();
 
-                // Check if buffer is full
-                if buffer.len() >= MAX_INPUT_LEN - 1 {
-                    // Buffer full, beep or ignore
-                    continue;
+                        // Buffer full: ignore
+                        if !line.insert(c) {
+                            continue;
+                        }
+                        if appending {
+                            uefi::print!("{}", c);
+                            continue;
+                        }
+                        true
+                    }
                 }
-
-                // Echo character
-                uefi::print!("{}", c);
-
-                // Add to buffer
-                buffer.push(c).map_err(|_| Error::BufferTooSmall)?;
             }
             Key::Special(special) => {
                 use uefi::proto::console::text::ScanCode;
@@ -125,41 +387,156 @@ fn read_line() -> Result<String<MAX_INPUT_LEN>> {
                     // Enter - return the line
                     ScanCode::NULL => {
                         println!();
-                        return Ok(buffer);
+                        echo_edited_line(line.as_str(), edited);
+                        return Ok(line.into_string());
                     }
-                    // Backspace
-                    ScanCode::DELETE => {
-                        if !buffer.is_empty() {
-                            buffer.pop();
-                            // Move cursor back, print space, move back again
-                            uefi::print!("\x08 \x08");
-                        }
+                    ScanCode::DELETE => line.delete(),
+                    ScanCode::LEFT => line.left(),
+                    ScanCode::RIGHT => line.right(),
+                    ScanCode::HOME => {
+                        line.home();
+                        true
+                    }
+                    ScanCode::END => {
+                        line.end();
+                        true
                     }
                     // Escape
                     ScanCode::ESCAPE => {
                         return Err(Error::Uefi(uefi::Status::ABORTED));
                     }
+                    ScanCode::UP | ScanCode::DOWN if command => {
+                        let target = if special == ScanCode::UP {
+                            recalled + 1
+                        } else {
+                            recalled.saturating_sub(1)
+                        };
+                        if target == recalled {
+                            continue;
+                        }
+                        let text = match target {
+                            0 => alloc::string::String::from(draft.as_str()),
+                            n => match history::recall(n) {
+                                Some(text) => text,
+                                None => continue,
+                            },
+                        };
+                        if recalled == 0 {
+                            draft.clear();
+                            let _ = draft.push_str(line.as_str());
+                        }
+                        recalled = target;
+                        line.replace(&text);
+
+                        if ui::is_plain() {
+                            // No redrawing: show the recalled line afresh
+                            println!();
+                            uefi::print!("{}{}", prompt, line.as_str());
+                            edited = false;
+                            continue;
+                        }
+                        true
+                    }
                     // Other special keys - ignore for now
-                    _ => {}
+                    _ => false,
                 }
             }
+        };
+
+        if changed {
+            edited = true;
+            redraw(&line, old_cursor, old_columns);
         }
     }
 }
 
+/// Tab at the end of `line`, with the configured entries to complete
+/// entry arguments from
+fn complete_line(line: &str) -> Completion {
+    let entries = crate::storage::with_config(|config| {
+        let name = |i: usize| config.meta.get(i).map(|meta| meta.name.as_str()).filter(|name| !name.is_empty());
+        (0..config.urls.len())
+            .map(|i| alloc::string::String::from(name(i).unwrap_or(config.urls[i].as_str())))
+            .collect::<alloc::vec::Vec<_>>()
+    })
+    .unwrap_or_default();
+    let entries: alloc::vec::Vec<&str> = entries.iter().map(|entry| entry.as_str()).collect();
+    complete::complete(line, &entries)
+}
+
+/// Bring the screen in line with the editor after an edit
+///
+/// Plain mode never moves the cursor backwards; the final line is echoed
+/// once Enter is pressed instead.
+fn redraw<const N: usize>(line: &LineEditor<N>, old_cursor: usize, old_columns: usize) {
+    if ui::is_plain() {
+        return;
+    }
+
+    // Back to the start of the input, rewrite it, blank what is left of the
+    // old text, then step back to the cursor
+    let columns = line.columns();
+    let leftover = old_columns.saturating_sub(columns);
+    let mut out = alloc::string::String::new();
+    out.extend(core::iter::repeat('\x08').take(old_cursor));
+    out.push_str(line.as_str());
+    out.extend(core::iter::repeat(' ').take(leftover));
+    out.extend(core::iter::repeat('\x08').take(leftover + columns - line.cursor_column()));
+    uefi::print!("{}", out);
+}
+
+/// Ctrl+L: clear the screen and show the prompt and line again
+fn clear_screen<const N: usize>(prompt: &str, line: &LineEditor<N>) {
+    if ui::is_plain() {
+        // Output is append-only; just start a fresh line
+        println!();
+    } else {
+        let _ = uefi::system::with_stdout(|stdout| stdout.clear());
+    }
+    uefi::print!("{}{}", prompt, line.as_str());
+    if !ui::is_plain() {
+        redraw(line, line.columns(), line.columns());
+    }
+}
+
+/// In plain mode, show the final line if backspace was used while typing it
+fn echo_edited_line(buffer: &str, edited: bool) {
+    if edited && ui::is_plain() {
+        println!("(entered: {})", buffer);
+    }
+}
+
 /// Wait for a key press
-fn wait_for_key() -> Result<Key> {
+pub(super) fn wait_for_key() -> Result<Key> {
+    loop {
+        if let Some(key) = wait_for_key_until(None)? {
+            return Ok(key);
+        }
+    }
+}
+
+/// Wait for a key press for at most `limit` (None: forever)
+pub(super) fn wait_for_key_until(limit: Option<Duration>) -> Result<Option<Key>> {
     use uefi::boot;
 
+    let mut waited = Duration::ZERO;
     loop {
         // Check if key is available by accessing stdin within the closure scope
         let key_result = uefi::system::with_stdin(|stdin| stdin.read_key());
 
         match key_result {
-            Ok(Some(key)) => return Ok(key),
+            Ok(Some(key)) => return Ok(Some(key)),
             Ok(None) => {
-                // No key available, wait a bit
-                boot::stall(Duration::from_micros(10_000)); // 10ms
+                if limit.is_some_and(|limit| waited >= limit) {
+                    return Ok(None);
+                }
+
+                // No key available: give a background download the time,
+                // otherwise wait a bit
+                if !crate::boot::prefetch::run_for(POLL_INTERVAL) {
+                    boot::stall(POLL_INTERVAL);
+                }
+                waited += POLL_INTERVAL;
             }
             Err(e) => return Err(Error::Uefi(e.status())),
         }
@@ -0,0 +1,416 @@
+use super::commands::ControlFlow;
+use super::parser::parse_command;
+use crate::storage::file;
+use crate::util::global::Global;
+use crate::util::platform::Arch;
+use crate::util::{Error, Result};
+use heapless::{String, Vec};
+use uefi::println;
+
+/// Script run automatically at startup when present on the ESP
+pub const AUTOEXEC_PATH: &str = "\\EFI\\uefipxe\\autoexec.txt";
+
+/// Maximum length of a script line after variable expansion
+pub const MAX_LINE_LEN: usize = 256;
+
+/// Maximum number of script variables
+const MAX_VARS: usize = 16;
+
+/// Maximum length of a variable name
+const MAX_VAR_NAME_LEN: usize = 32;
+
+/// Maximum length of a variable value
+const MAX_VAR_VALUE_LEN: usize = 128;
+
+/// Lines executed before a script is assumed to loop forever
+const MAX_STEPS: usize = 1000;
+
+/// Scripts running inside each other (`run`, script entries) before one is
+/// assumed to start itself forever
+const MAX_DEPTH: usize = 8;
+
+/// How many scripts are running, innermost included
+static DEPTH: Global<usize> = Global::new();
+
+/// Set while an entry's hook runs
+static IN_HOOK: Global<bool> = Global::new();
+
+/// Variables set by `prompt`, referenced as `${name}`
+pub type Vars = Vec<(String<MAX_VAR_NAME_LEN>, String<MAX_VAR_VALUE_LEN>), MAX_VARS>;
+
+/// One line of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive<'a> {
+    /// Blank line or `#` comment
+    Nothing,
+    /// Jump target (`:name`)
+    Label(&'a str),
+    /// Ask the technician and store the answer (`prompt <var> <text>`)
+    Prompt { var: &'a str, text: &'a str },
+    /// Unconditional jump (`goto <label>`)
+    Goto(&'a str),
+    /// Conditional jump (`if <a> == <b> goto <label>`, also `!=`)
+    If { lhs: &'a str, equal: bool, rhs: &'a str, label: &'a str },
+    /// What a failing line does from here on (`onerror=stop|continue`)
+    OnError(OnError),
+    /// Any CLI command
+    Command(&'a str),
+}
+
+/// What a failing line does to the rest of a script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnError {
+    /// End the script (the default)
+    Stop,
+    /// Report it and go on with the next line
+    Continue,
+}
+
+/// Classify a script line (after variable expansion)
+pub fn parse_line(line: &str) -> Result<Directive<'_>> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(Directive::Nothing);
+    }
+
+    if let Some(label) = line.strip_prefix(':') {
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(Error::Parse);
+        }
+        return Ok(Directive::Label(label));
+    }
+
+    if let Some(mode) = line.strip_prefix("onerror") {
+        return match mode.trim_start().trim_start_matches('=').trim() {
+            "stop" => Ok(Directive::OnError(OnError::Stop)),
+            "continue" => Ok(Directive::OnError(OnError::Continue)),
+            _ => Err(Error::InvalidArgument),
+        };
+    }
+
+    let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+
+    match keyword {
+        "prompt" => {
+            let (var, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if !is_var_name(var) {
+                return Err(Error::InvalidArgument);
+            }
+            Ok(Directive::Prompt { var, text: text.trim() })
+        }
+        "goto" => match rest {
+            "" => Err(Error::InvalidArgument),
+            label => Ok(Directive::Goto(label)),
+        },
+        "if" => {
+            // Sides are split on the operator rather than on whitespace so
+            // that an empty answer still compares correctly
+            let (cond, label) = rest.rsplit_once(" goto ").ok_or(Error::Parse)?;
+            let label = label.trim();
+            let (lhs, equal, rhs) = if let Some((lhs, rhs)) = cond.split_once("==") {
+                (lhs, true, rhs)
+            } else if let Some((lhs, rhs)) = cond.split_once("!=") {
+                (lhs, false, rhs)
+            } else {
+                return Err(Error::Parse);
+            };
+            if label.is_empty() {
+                return Err(Error::InvalidArgument);
+            }
+            Ok(Directive::If { lhs: lhs.trim(), equal, rhs: rhs.trim(), label })
+        }
+        _ => Ok(Directive::Command(line)),
+    }
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_VAR_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace every `${name}` with the variable's value
+///
+/// `${arch}` is this machine's architecture (`x64`, `aa64`, ...) unless a
+/// script sets a variable of that name.
+pub fn expand(line: &str, vars: &Vars) -> Result<String<MAX_LINE_LEN>> {
+    let mut out = String::new();
+    let mut rest = line;
+
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]).map_err(|_| Error::BufferTooSmall)?;
+        let after = &rest[start + 2..];
+        let end = after.find('}').ok_or(Error::Parse)?;
+        let value = lookup(vars, &after[..end]).or_else(|| builtin(&after[..end])).ok_or(Error::NotFound)?;
+        out.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest).map_err(|_| Error::BufferTooSmall)?;
+
+    Ok(out)
+}
+
+fn lookup<'a>(vars: &'a Vars, name: &str) -> Option<&'a str> {
+    vars.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+/// Variables every script has
+fn builtin(name: &str) -> Option<&'static str> {
+    match name {
+        "arch" => Arch::CURRENT.map(|arch| arch.as_str()),
+        _ => None,
+    }
+}
+
+/// Set a variable, replacing any previous value
+pub fn set_var(vars: &mut Vars, name: &str, value: &str) -> Result<()> {
+    let mut stored = String::new();
+    stored.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+
+    if let Some(entry) = vars.iter_mut().find(|(n, _)| n == name) {
+        entry.1 = stored;
+        return Ok(());
+    }
+
+    let mut key = String::new();
+    key.push_str(name).map_err(|_| Error::BufferTooSmall)?;
+    vars.push((key, stored)).map_err(|_| Error::BufferTooSmall)
+}
+
+/// Line number (0-based) of a label
+pub fn find_label(script: &str, label: &str) -> Option<usize> {
+    script
+        .lines()
+        .position(|line| matches!(parse_line(line), Ok(Directive::Label(l)) if l == label))
+}
+
+/// Run a script file from the ESP
+///
+/// Stops at the first failing line unless `onerror=continue` says
+/// otherwise. A command that exits the CLI (e.g. `exit`) ends the script
+/// with the same control flow.
+pub fn run_file(path: &str) -> Result<ControlFlow> {
+    let data = file::read_file(path)?;
+    let script = core::str::from_utf8(&data).map_err(|_| Error::Parse)?;
+    run(script)
+}
+
+/// Run the startup script if there is one
+pub fn run_autoexec() -> Result<ControlFlow> {
+    let data = match file::read_file(AUTOEXEC_PATH) {
+        Ok(data) => data,
+        Err(Error::NotFound) => return Ok(ControlFlow::Continue),
+        Err(e) => return Err(e),
+    };
+
+    println!("Running {}", AUTOEXEC_PATH);
+    run(core::str::from_utf8(&data).map_err(|_| Error::Parse)?)
+}
+
+/// Run an entry's `pre=` or `on_fail=` lines
+///
+/// A hook is a script of its own, so its variables and labels stay within
+/// it. It runs on the way to a boot already: commands that boot, open the
+/// menu or exit are refused, also in scripts it runs.
+pub fn run_hook(lines: &str) -> Result<()> {
+    let outer = in_hook();
+    IN_HOOK.set(true);
+    let result = run(lines);
+    IN_HOOK.set(outer);
+    match result? {
+        ControlFlow::Continue => Ok(()),
+        ControlFlow::Exit(_) => Err(Error::InvalidCommand),
+    }
+}
+
+/// Whether the script running belongs to an entry hook
+fn in_hook() -> bool {
+    IN_HOOK.with(|in_hook| *in_hook).unwrap_or(false)
+}
+
+/// Run a script
+///
+/// With `onerror=continue`, a script that reaches its end after failing
+/// lines still fails, with the first error. A script that would run
+/// deeper than MAX_DEPTH scripts fails before its first line.
+pub fn run(script: &str) -> Result<ControlFlow> {
+    let depth = DEPTH.with(|depth| *depth).unwrap_or(0);
+    if depth == MAX_DEPTH {
+        println!("Scripts nested {} deep, not running another (does one run itself?)", MAX_DEPTH);
+        return Err(Error::Unknown);
+    }
+    DEPTH.set(depth + 1);
+    let result = run_lines(script);
+    DEPTH.set(depth);
+    result
+}
+
+fn run_lines(script: &str) -> Result<ControlFlow> {
+    let lines: alloc::vec::Vec<&str> = script.lines().collect();
+    let mut vars = Vars::new();
+    let mut on_error = OnError::Stop;
+    let mut failed: Option<(usize, Error)> = None;
+    let mut pc = 0;
+
+    for _ in 0..MAX_STEPS {
+        let Some(raw) = lines.get(pc) else {
+            return match failed {
+                Some((count, e)) => {
+                    println!("Script finished with {} failed line(s)", count);
+                    Err(e)
+                }
+                None => Ok(ControlFlow::Continue),
+            };
+        };
+
+        let result = expand(raw, &vars).and_then(|line| step(script, &line, &mut vars, &mut on_error));
+        match result {
+            Ok(Step::Next) => pc += 1,
+            Ok(Step::Jump(target)) => pc = target,
+            Ok(Step::Exit(status)) => return Ok(ControlFlow::Exit(status)),
+            Err(e) => {
+                println!("Script error on line {}: {}", pc + 1, e);
+                crate::util::logger::log_entry(
+                    log::Level::Error,
+                    &alloc::format!("Script failed at line {}: {}", pc + 1, e),
+                );
+                if on_error == OnError::Stop {
+                    return Err(e);
+                }
+                failed = Some(failed.map_or((1, e), |(count, first)| (count + 1, first)));
+                pc += 1;
+            }
+        }
+    }
+
+    println!("Script stopped after {} steps (endless loop?)", MAX_STEPS);
+    Err(Error::Unknown)
+}
+
+/// What to do after a line
+enum Step {
+    Next,
+    Jump(usize),
+    Exit(uefi::Status),
+}
+
+fn step(script: &str, line: &str, vars: &mut Vars, on_error: &mut OnError) -> Result<Step> {
+    match parse_line(line)? {
+        Directive::Nothing | Directive::Label(_) => Ok(Step::Next),
+        Directive::OnError(mode) => {
+            *on_error = mode;
+            Ok(Step::Next)
+        }
+        Directive::Prompt { var, text } => {
+            let answer = super::repl::prompt(if text.is_empty() { "? " } else { text })?;
+            set_var(vars, var, answer.trim())?;
+            Ok(Step::Next)
+        }
+        Directive::Goto(label) => jump(script, label),
+        Directive::If { lhs, equal, rhs, label } => {
+            if (lhs == rhs) == equal {
+                jump(script, label)
+            } else {
+                Ok(Step::Next)
+            }
+        }
+        Directive::Command(command) => {
+            crate::util::logger::log_entry(
+                log::Level::Info,
+                &alloc::format!("Script command: {}", command),
+            );
+            let command = parse_command(command)?;
+            if in_hook() && command.starts_boot() {
+                println!("Not allowed in an entry hook");
+                return Err(Error::InvalidCommand);
+            }
+            match command.execute()? {
+                ControlFlow::Continue => Ok(Step::Next),
+                ControlFlow::Exit(status) => Ok(Step::Exit(status)),
+            }
+        }
+    }
+}
+
+fn jump(script: &str, label: &str) -> Result<Step> {
+    match find_label(script, label) {
+        Some(line) => Ok(Step::Jump(line)),
+        None => {
+            println!("Unknown label: {}", label);
+            Err(Error::NotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directives() {
+        assert_eq!(parse_line("  # comment").unwrap(), Directive::Nothing);
+        assert_eq!(parse_line(":lab").unwrap(), Directive::Label("lab"));
+        assert_eq!(
+            parse_line("prompt site Site code: ").unwrap(),
+            Directive::Prompt { var: "site", text: "Site code:" }
+        );
+        assert_eq!(parse_line("goto done").unwrap(), Directive::Goto("done"));
+        assert_eq!(parse_line("boot 0").unwrap(), Directive::Command("boot 0"));
+        assert!(parse_line("prompt bad-name text").is_err());
+        assert!(parse_line("goto").is_err());
+    }
+
+    #[test]
+    fn test_parse_if() {
+        assert_eq!(
+            parse_line("if lab == lab goto lab").unwrap(),
+            Directive::If { lhs: "lab", equal: true, rhs: "lab", label: "lab" }
+        );
+        // Empty left side after expanding an empty answer
+        assert_eq!(
+            parse_line("if  != x goto retry").unwrap(),
+            Directive::If { lhs: "", equal: false, rhs: "x", label: "retry" }
+        );
+        assert!(parse_line("if a = b goto x").is_err());
+        assert!(parse_line("if a == b").is_err());
+    }
+
+    #[test]
+    fn test_parse_onerror() {
+        assert_eq!(parse_line("onerror=continue").unwrap(), Directive::OnError(OnError::Continue));
+        assert_eq!(parse_line("onerror = stop").unwrap(), Directive::OnError(OnError::Stop));
+        assert_eq!(parse_line("onerror continue").unwrap(), Directive::OnError(OnError::Continue));
+        assert!(parse_line("onerror=ignore").is_err());
+        assert!(parse_line("onerror").is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut vars = Vars::new();
+        set_var(&mut vars, "site", "fra1").unwrap();
+        set_var(&mut vars, "site", "ams2").unwrap();
+        assert_eq!(vars.len(), 1);
+
+        let line = expand("add http://${site}.example.com/${site}.efi", &vars).unwrap();
+        assert_eq!(line.as_str(), "add http://ams2.example.com/ams2.efi");
+        assert!(expand("add ${missing}", &vars).is_err());
+        assert!(expand("add ${site", &vars).is_err());
+
+        // Built in, but a script's own variable of the name wins
+        let arch = Arch::CURRENT.unwrap().as_str();
+        let line = expand("add http://h/${arch}/boot.efi", &vars).unwrap();
+        assert_eq!(line.as_str(), alloc::format!("add http://h/{}/boot.efi", arch));
+        set_var(&mut vars, "arch", "aa64").unwrap();
+        assert_eq!(expand("${arch}", &vars).unwrap().as_str(), "aa64");
+    }
+
+    #[test]
+    fn test_find_label() {
+        let script = "prompt site Site:\nif ${site} == lab goto lab\n:lab\nboot 0\n";
+        assert_eq!(find_label(script, "lab"), Some(2));
+        assert_eq!(find_label(script, "other"), None);
+    }
+}
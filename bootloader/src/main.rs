@@ -15,45 +15,176 @@ mod util;
 #[entry]
 fn main() -> Status {
     // Initialize UEFI services (heap allocator, logger, panic handler)
-    uefi::helpers::init().expect("Failed to initialize UEFI");
+    if uefi::helpers::init().is_err() {
+        return Status::ABORTED;
+    }
 
     // Initialize logger
     util::logger::init();
 
     // Print welcome message
     println!();
-    println!("UEFI PXE Bootloader v{}", env!("CARGO_PKG_VERSION"));
-    println!("=====================================");
+    util::ui::heading(concat!("UEFI PXE Bootloader v", env!("CARGO_PKG_VERSION")));
 
     // Log startup
     util::logger::log_entry(log::Level::Info, "Bootloader started");
 
-    // Load configuration
-    let config = storage::load_config().unwrap_or_else(|e| {
-        println!("Warning: Could not load config: {}", e);
-        util::logger::log_entry(
-            log::Level::Warn,
-            &alloc::format!("Config load failed: {}, using empty config", e),
+    // Several starts in a row that died before getting anywhere: leave out
+    // everything that runs by itself, so a bad config cannot lock us out
+    let safe_mode = boot::safemode::check();
+    if safe_mode {
+        println!(
+            "Safe mode: the last {} starts did not finish, skipping the startup script, remote config and automatic boots",
+            boot::safemode::CRASHES
         );
-        storage::Config::new()
-    });
-    storage::init_config(config);
-    util::logger::log_entry(log::Level::Info, "Configuration loaded");
+        util::logger::log_entry(log::Level::Warn, "Starting in safe mode after repeated crashes");
+    }
 
-    // Run CLI REPL
-    match cli::run() {
-        Ok(_) => {
-            println!("Exiting normally");
-            util::logger::log_entry(log::Level::Info, "Bootloader exiting normally");
+    // Find out what the firmware supports before anything depends on it
+    util::caps::probe();
+    util::caps::warn_missing();
+
+    // The policy decides how strictly the config itself is checked
+    storage::policy::load();
+
+    // Load configuration
+    let config = match storage::load_config() {
+        Ok(config) => config,
+        Err(util::Error::SecurityViolation) => {
+            // Strict integrity mode refused the config; let the firmware
+            // move on to the next BootOrder entry rather than run without it
+            println!("Fatal: configuration failed integrity check, aborting");
+            boot::safemode::settle();
+            if boot::rescue::available() {
+                println!("Booting built-in rescue image instead");
+                return cli::Command::BootRescue.execute().map_or_else(|e| e.status(), |_| Status::SUCCESS);
+            }
+            return Status::ABORTED;
         }
         Err(e) => {
-            println!("Error: {}", e);
+            println!("Warning: Could not load config: {}", e);
+            if boot::rescue::available() {
+                println!("Rescue image available: boot {}", boot::rescue::NAME);
+            }
             util::logger::log_entry(
-                log::Level::Error,
-                &alloc::format!("Bootloader error: {}", e),
+                log::Level::Warn,
+                &alloc::format!("Config load failed: {}, using empty config", e),
             );
+            storage::Config::new()
+        }
+    };
+    cli::keymap::set_layout(config.keymap);
+    util::ui::set_mode(config.ui);
+    util::logger::set_echo(config.echo);
+    util::beep::set_mode(config.beep);
+    util::logger::set_persist(config.boot_log);
+    storage::init_config(config);
+    if storage::with_config(|config| config.image_cache).unwrap_or(false) {
+        storage::cache::init();
+    }
+    util::logger::log_entry(log::Level::Info, "Configuration loaded");
+    storage::with_config(|config| {
+        if let Err(e) = storage::integrity::measure(config) {
+            println!("Warning: Could not measure configuration: {}", e);
+        }
+
+        // Point out likely mistakes once, before anything is booted
+        for lint in config.lint(util::caps::has(util::caps::Capability::Tls)) {
+            println!("Warning: {}", lint);
+            util::logger::log_entry(log::Level::Warn, &alloc::format!("Config: {}", lint));
+        }
+    });
+
+    // Learn whether the OS confirmed the previous boot
+    match boot::health::resolve_pending() {
+        Some(boot::health::Health::Good) => {
+            say!("Previous boot was confirmed by the OS");
+            util::logger::log_entry(log::Level::Info, "Previous boot confirmed");
+            if let Some(floor) = boot::rollback::commit_pending() {
+                say!("Minimum image version raised to {}", floor);
+            }
         }
+        Some(_) => {
+            boot::rollback::clear_pending();
+            println!("Warning: Previous boot was never confirmed, entry marked as failed");
+            println!("Run 'boot' without an index to fall back to a last-known-good entry");
+            util::logger::log_entry(log::Level::Warn, "Previous boot not confirmed");
+        }
+        None => {}
+    }
+
+    // Startup (and anything that went wrong so far) goes to the boot log
+    util::logger::flush();
+
+    // Arguments (e.g. `uefipxe.efi boot 0` in startup.nsh) run one command
+    // and report its result as our exit status instead of starting the CLI
+    match cli::args::command_line() {
+        Ok(args) if !args.is_empty() && safe_mode => println!("Safe mode: ignoring the command line"),
+        Ok(args) if !args.is_empty() => {
+            util::splash::set_unattended(true);
+            let status = cli::args::execute(&args);
+            boot::safemode::settle();
+            network::manager::teardown();
+            storage::secrets::clear();
+            util::logger::flush();
+            return status;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            println!("Error: Invalid command line: {}", e);
+            return e.status();
+        }
+    }
+
+    if !safe_mode {
+        // In an existing PXE setup, the boot file its servers name is an entry
+        if storage::with_config(|config| config.pxe_discover).unwrap_or(false)
+            && cli::Command::Net(cli::commands::NetAction::Pxe).execute().is_err()
+        {
+            println!("Continuing without a PXE entry");
+        }
+
+        // Let the default entry download while the technician is at the prompt
+        boot::prefetch::start();
+
+        // Startup script (e.g. ask for a site code, then boot)
+        util::splash::set_unattended(true);
+        let autoexec = cli::script::run_autoexec();
+        util::splash::set_unattended(false);
+        match autoexec {
+            Ok(cli::ControlFlow::Exit(status)) => {
+                boot::safemode::settle();
+                network::manager::teardown();
+                storage::secrets::clear();
+                return status;
+            }
+            Ok(cli::ControlFlow::Continue) => {}
+            Err(e) => println!("Startup script failed: {}, starting CLI", e),
+        }
+
+        // Boot menu for picking an entry without typing indexes
+        if let Some(timeout) = storage::with_config(|config| config.menu_timeout).flatten() {
+            if let Err(e) = cli::menu::run(Some(timeout)) {
+                println!("Boot menu failed: {}, starting CLI", e);
+            }
+        }
+    }
+
+    // Run CLI REPL
+    let status = cli::run();
+    network::manager::teardown();
+    storage::secrets::clear();
+    if status.is_success() {
+        say!("Exiting normally");
+        util::logger::log_entry(log::Level::Info, "Bootloader exiting normally");
+    } else {
+        println!("Exiting with status {:?}", status);
+        util::logger::log_entry(
+            log::Level::Warn,
+            &alloc::format!("Bootloader exiting with status {:?}", status),
+        );
     }
+    util::logger::flush();
 
-    Status::SUCCESS
+    status
 }
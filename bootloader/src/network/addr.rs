@@ -0,0 +1,323 @@
+use crate::util::{Error, Result};
+use core::fmt;
+
+/// IPv4 address with dotted-quad formatting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Ipv4(pub [u8; 4]);
+
+impl Ipv4 {
+    /// Whether this is 0.0.0.0
+    pub fn is_unspecified(&self) -> bool {
+        self.0 == [0; 4]
+    }
+}
+
+impl fmt::Display for Ipv4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+    }
+}
+
+/// IPv6 address, formatted as RFC 5952 recommends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Ipv6(pub [u8; 16]);
+
+impl Ipv6 {
+    /// The eight 16-bit groups
+    fn groups(&self) -> [u16; 8] {
+        let mut groups = [0u16; 8];
+        for (i, group) in groups.iter_mut().enumerate() {
+            *group = u16::from_be_bytes([self.0[2 * i], self.0[2 * i + 1]]);
+        }
+        groups
+    }
+
+    /// Whether this is `::`
+    pub fn is_unspecified(&self) -> bool {
+        self.0 == [0; 16]
+    }
+
+    /// Whether this is a link-local address (fe80::/10), which every
+    /// interface has before it is configured
+    pub fn is_link_local(&self) -> bool {
+        self.0[0] == 0xfe && self.0[1] & 0xc0 == 0x80
+    }
+}
+
+impl fmt::Display for Ipv6 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups = self.groups();
+
+        // The longest run of two or more zero groups becomes "::"
+        let mut best: Option<(usize, usize)> = None;
+        let mut i = 0;
+        while i < 8 {
+            let run = groups[i..].iter().take_while(|&&g| g == 0).count();
+            if run >= 2 && best.is_none_or(|(_, len)| run > len) {
+                best = Some((i, run));
+            }
+            i += run.max(1);
+        }
+
+        let mut i = 0;
+        while i < 8 {
+            if let Some((start, len)) = best.filter(|&(start, _)| start == i) {
+                f.write_str(if start == 0 { "::" } else { ":" })?;
+                i += len;
+                continue;
+            }
+            write!(f, "{:x}", groups[i])?;
+            if i < 7 {
+                f.write_str(":")?;
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Ethernet MAC address, formatted as colon-separated hex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Mac(pub [u8; 6]);
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", m[0], m[1], m[2], m[3], m[4], m[5])
+    }
+}
+
+/// Parse a MAC address, with `:` or `-` between the six bytes
+pub fn parse_mac(s: &str) -> Result<Mac> {
+    let s = s.trim();
+    let separator = if s.contains('-') { '-' } else { ':' };
+    let mut mac = [0u8; 6];
+    let mut count = 0;
+    for part in s.split(separator) {
+        if count == 6 || part.len() != 2 {
+            return Err(Error::Parse);
+        }
+        mac[count] = u8::from_str_radix(part, 16).map_err(|_| Error::Parse)?;
+        count += 1;
+    }
+    if count != 6 {
+        return Err(Error::Parse);
+    }
+    Ok(Mac(mac))
+}
+
+/// Parse a dotted-quad IPv4 address
+pub fn parse_ipv4(s: &str) -> Result<Ipv4> {
+    let mut octets = [0u8; 4];
+    let mut parts = s.trim().split('.');
+
+    for octet in octets.iter_mut() {
+        let part = parts.next().ok_or(Error::Parse)?;
+        // Reject empty parts, signs and leading zeros ("010" is ambiguous)
+        if part.is_empty() || part.len() > 3 || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::Parse);
+        }
+        if part.len() > 1 && part.starts_with('0') {
+            return Err(Error::Parse);
+        }
+        *octet = part.parse::<u8>().map_err(|_| Error::Parse)?;
+    }
+
+    if parts.next().is_some() {
+        return Err(Error::Parse);
+    }
+
+    Ok(Ipv4(octets))
+}
+
+/// Parse an IPv6 address (`2001:db8::1`, `::ffff:192.0.2.1`)
+///
+/// Zone identifiers (`fe80::1%eth0`) are not accepted.
+pub fn parse_ipv6(s: &str) -> Result<Ipv6> {
+    let s = s.trim();
+
+    // Groups before and after "::", if there is one
+    let (head, tail) = match s.split_once("::") {
+        Some((head, tail)) => (head, Some(tail)),
+        None => (s, None),
+    };
+    let mut groups: heapless::Vec<u16, 8> = heapless::Vec::new();
+    let mut tail_groups: heapless::Vec<u16, 8> = heapless::Vec::new();
+    parse_groups(head, &mut groups, tail.is_none())?;
+    if let Some(tail) = tail {
+        parse_groups(tail, &mut tail_groups, true)?;
+    }
+
+    let total = groups.len() + tail_groups.len();
+    match tail {
+        // "::" stands for at least one zero group
+        Some(_) if total > 7 => return Err(Error::Parse),
+        None if total != 8 => return Err(Error::Parse),
+        _ => {}
+    }
+
+    let mut octets = [0u8; 16];
+    for (i, group) in groups.iter().enumerate() {
+        octets[2 * i..2 * i + 2].copy_from_slice(&group.to_be_bytes());
+    }
+    let offset = 8 - tail_groups.len();
+    for (i, group) in tail_groups.iter().enumerate() {
+        octets[2 * (offset + i)..2 * (offset + i) + 2].copy_from_slice(&group.to_be_bytes());
+    }
+    Ok(Ipv6(octets))
+}
+
+/// Parse colon-separated hex groups; the last may be a dotted IPv4 address
+/// when `last` (the groups end the address)
+fn parse_groups(s: &str, groups: &mut heapless::Vec<u16, 8>, last: bool) -> Result<()> {
+    if s.is_empty() {
+        return Ok(());
+    }
+    let mut parts = s.split(':').peekable();
+    while let Some(part) = parts.next() {
+        if last && parts.peek().is_none() && part.contains('.') {
+            let v4 = parse_ipv4(part)?.0;
+            groups.push(u16::from_be_bytes([v4[0], v4[1]])).map_err(|_| Error::Parse)?;
+            groups.push(u16::from_be_bytes([v4[2], v4[3]])).map_err(|_| Error::Parse)?;
+            break;
+        }
+        if part.is_empty() || part.len() > 4 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::Parse);
+        }
+        let group = u16::from_str_radix(part, 16).map_err(|_| Error::Parse)?;
+        groups.push(group).map_err(|_| Error::Parse)?;
+    }
+    Ok(())
+}
+
+/// Parse a subnet mask, either dotted (255.255.255.0) or as a prefix length (/24 or 24)
+pub fn parse_netmask(s: &str) -> Result<Ipv4> {
+    let s = s.trim();
+    let prefix = s.strip_prefix('/').unwrap_or(s);
+
+    if !prefix.contains('.') {
+        let bits = prefix.parse::<u32>().map_err(|_| Error::Parse)?;
+        if bits == 0 || bits > 32 {
+            return Err(Error::InvalidArgument);
+        }
+        let mask = u32::MAX << (32 - bits);
+        return Ok(Ipv4(mask.to_be_bytes()));
+    }
+
+    let mask = parse_ipv4(s)?;
+    if !is_valid_netmask(mask) {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(mask)
+}
+
+/// Whether a mask is non-empty and has contiguous leading one bits
+pub fn is_valid_netmask(mask: Ipv4) -> bool {
+    let bits = u32::from_be_bytes(mask.0);
+    bits != 0 && bits.leading_ones() + bits.trailing_zeros() == 32
+}
+
+/// Whether an address is usable as a host address within the given subnet
+pub fn is_host_address(addr: Ipv4, mask: Ipv4) -> bool {
+    let addr = u32::from_be_bytes(addr.0);
+    let mask = u32::from_be_bytes(mask.0);
+    let host = addr & !mask;
+
+    // Not network/broadcast (unless /31 or /32), not 0.x, multicast or reserved
+    let special_host = mask < 0xFFFF_FFFE && (host == 0 || host == !mask);
+    let first = (addr >> 24) as u8;
+    !special_host && first != 0 && first != 127 && first < 224
+}
+
+/// Whether two addresses are in the same subnet
+pub fn same_subnet(a: Ipv4, b: Ipv4, mask: Ipv4) -> bool {
+    let mask = u32::from_be_bytes(mask.0);
+    u32::from_be_bytes(a.0) & mask == u32::from_be_bytes(b.0) & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ipv4() {
+        assert_eq!(parse_ipv4("192.168.1.10").unwrap(), Ipv4([192, 168, 1, 10]));
+        assert_eq!(parse_ipv4(" 10.0.2.15 ").unwrap(), Ipv4([10, 0, 2, 15]));
+        assert!(parse_ipv4("192.168.1").is_err());
+        assert!(parse_ipv4("192.168.1.256").is_err());
+        assert!(parse_ipv4("192.168.1.1.1").is_err());
+        assert!(parse_ipv4("192.168.01.1").is_err());
+        assert!(parse_ipv4("192.168..1").is_err());
+        assert!(parse_ipv4("a.b.c.d").is_err());
+    }
+
+    #[test]
+    fn test_parse_mac() {
+        let mac = Mac([0x52, 0x54, 0x00, 0xab, 0xcd, 0xef]);
+        assert_eq!(parse_mac("52:54:00:ab:cd:ef").unwrap(), mac);
+        assert_eq!(parse_mac("52-54-00-AB-CD-EF").unwrap(), mac);
+        assert_eq!(alloc::format!("{}", mac), "52:54:00:ab:cd:ef");
+        assert!(parse_mac("52:54:00:ab:cd").is_err());
+        assert!(parse_mac("52:54:00:ab:cd:ef:01").is_err());
+        assert!(parse_mac("52:54:00:ab:cd:e").is_err());
+        assert!(parse_mac("52:54:00-ab:cd:ef").is_err());
+        assert!(parse_mac("52:54:00:ab:cd:gg").is_err());
+    }
+
+    #[test]
+    fn test_parse_ipv6() {
+        let address = parse_ipv6("2001:db8::1").unwrap();
+        assert_eq!(address.0[..4], [0x20, 0x01, 0x0d, 0xb8]);
+        assert_eq!(address.0[15], 1);
+        assert_eq!(parse_ipv6("::").unwrap(), Ipv6([0; 16]));
+        assert_eq!(parse_ipv6("::1").unwrap().0[15], 1);
+        assert_eq!(parse_ipv6("fe80::").unwrap().0[..2], [0xfe, 0x80]);
+        assert_eq!(parse_ipv6("::ffff:192.0.2.1").unwrap().0[10..], [0xff, 0xff, 192, 0, 2, 1]);
+        assert_eq!(parse_ipv6("1:2:3:4:5:6:7:8").unwrap().0[14..], [0, 8]);
+
+        assert!(parse_ipv6("1:2:3:4:5:6:7").is_err());
+        assert!(parse_ipv6("1:2:3:4:5:6:7:8:9").is_err());
+        assert!(parse_ipv6("1:2:3:4::5:6:7:8").is_err());
+        assert!(parse_ipv6("1::2::3").is_err());
+        assert!(parse_ipv6("12345::").is_err());
+        assert!(parse_ipv6("fe80::1%eth0").is_err());
+        assert!(parse_ipv6("192.0.2.1").is_err());
+        assert!(parse_ipv6("").is_err());
+    }
+
+    #[test]
+    fn test_format_ipv6() {
+        let format = |s| alloc::format!("{}", parse_ipv6(s).unwrap());
+        assert_eq!(format("2001:0DB8:0:0:0:0:0:1"), "2001:db8::1");
+        assert_eq!(format("::"), "::");
+        assert_eq!(format("::1"), "::1");
+        assert_eq!(format("fe80::"), "fe80::");
+        // The longest run wins, a single zero group stays
+        assert_eq!(format("2001:db8:0:0:1:0:0:0"), "2001:db8:0:0:1::");
+        assert_eq!(format("2001:db8:0:1:1:1:1:1"), "2001:db8:0:1:1:1:1:1");
+        assert!(parse_ipv6("fe80::1").unwrap().is_link_local());
+        assert!(!parse_ipv6("2001:db8::1").unwrap().is_link_local());
+    }
+
+    #[test]
+    fn test_parse_netmask() {
+        assert_eq!(parse_netmask("255.255.255.0").unwrap(), Ipv4([255, 255, 255, 0]));
+        assert_eq!(parse_netmask("/24").unwrap(), Ipv4([255, 255, 255, 0]));
+        assert_eq!(parse_netmask("20").unwrap(), Ipv4([255, 255, 240, 0]));
+        assert!(parse_netmask("255.0.255.0").is_err());
+        assert!(parse_netmask("0.0.0.0").is_err());
+        assert!(parse_netmask("/33").is_err());
+    }
+
+    #[test]
+    fn test_host_address() {
+        let mask = Ipv4([255, 255, 255, 0]);
+        assert!(is_host_address(Ipv4([192, 168, 1, 10]), mask));
+        assert!(!is_host_address(Ipv4([192, 168, 1, 0]), mask));
+        assert!(!is_host_address(Ipv4([192, 168, 1, 255]), mask));
+        assert!(!is_host_address(Ipv4([127, 0, 0, 1]), mask));
+        assert!(!is_host_address(Ipv4([224, 0, 0, 1]), mask));
+        assert!(same_subnet(Ipv4([10, 0, 0, 5]), Ipv4([10, 0, 0, 1]), mask));
+        assert!(!same_subnet(Ipv4([10, 0, 1, 5]), Ipv4([10, 0, 0, 1]), mask));
+    }
+}
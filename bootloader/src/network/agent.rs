@@ -0,0 +1,99 @@
+use crate::util::{Error, Result};
+use alloc::string::String;
+use core::fmt::Write;
+
+/// User-Agent sent when the config does not set `user_agent=`
+pub const DEFAULT_TEMPLATE: &str = "uefipxe/{version} ({arch}; {uuid}; {mac})";
+
+/// Placeholders a `user_agent=` template may use
+const PLACEHOLDERS: [&str; 4] = ["version", "arch", "uuid", "mac"];
+
+/// What the placeholders stand for on this machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identity<'a> {
+    pub version: &'a str,
+    pub arch: Option<&'a str>,
+    /// SMBIOS system UUID
+    pub uuid: Option<&'a str>,
+    /// Address of the interface the session uses
+    pub mac: Option<[u8; 6]>,
+}
+
+/// Check a template: printable ASCII, and only known placeholders
+pub fn validate(template: &str) -> Result<()> {
+    if template.is_empty() || !template.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+        return Err(Error::Parse);
+    }
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let (name, after) = rest[start + 1..].split_once('}').ok_or(Error::Parse)?;
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(Error::Parse);
+        }
+        rest = after;
+    }
+    Ok(())
+}
+
+/// Fill in a template; values the machine does not have read "unknown"
+pub fn expand(template: &str, identity: &Identity) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some((name, after)) = rest[start + 1..].split_once('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        match name {
+            "version" => out.push_str(identity.version),
+            "arch" => out.push_str(identity.arch.unwrap_or("unknown")),
+            "uuid" => out.push_str(identity.uuid.unwrap_or("unknown")),
+            "mac" => match identity.mac {
+                Some(m) => {
+                    let _ = write!(out, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", m[0], m[1], m[2], m[3], m[4], m[5]);
+                }
+                None => out.push_str("unknown"),
+            },
+            _ => {
+                out.push('{');
+                out.push_str(name);
+                out.push('}');
+            }
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let identity = Identity {
+            version: "0.4.0",
+            arch: Some("x64"),
+            uuid: Some("00112233-4455-6677-8899-AABBCCDDEEFF"),
+            mac: Some([0x52, 0x54, 0x00, 0x12, 0x34, 0x5a]),
+        };
+        assert_eq!(
+            expand(DEFAULT_TEMPLATE, &identity),
+            "uefipxe/0.4.0 (x64; 00112233-4455-6677-8899-AABBCCDDEEFF; 52:54:00:12:34:5a)"
+        );
+        assert_eq!(expand("lab-{mac}", &Identity { mac: None, ..identity }), "lab-unknown");
+        assert_eq!(expand("a {b} {c", &identity), "a {b} {c");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(DEFAULT_TEMPLATE).is_ok());
+        assert!(validate("uefipxe").is_ok());
+        assert!(validate("uefipxe/{version} {serial}").is_err());
+        assert!(validate("uefipxe/{version").is_err());
+        assert!(validate("").is_err());
+        assert!(validate("caf\u{e9}").is_err());
+    }
+}
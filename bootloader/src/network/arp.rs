@@ -0,0 +1,129 @@
+use crate::network::child::{RawProtocol, ServiceChild};
+use crate::network::client::Completion;
+use crate::util::{Error, Result};
+use core::ffi::c_void;
+use core::time::Duration;
+use uefi::boot;
+use uefi::{Guid, Handle, Status};
+
+/// ARP Service Binding Protocol GUID
+/// {F44C00EE-1F2C-4A00-AA09-1C9F3E0800A3}
+const ARP_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0xee, 0x00, 0x4c, 0xf4, 0x2c, 0x1f, 0x00, 0x4a,
+    0xaa, 0x09, 0x1c, 0x9f, 0x3e, 0x08, 0x00, 0xa3,
+]);
+
+/// ARP Protocol GUID
+/// {F4B427BB-BA21-4F16-BC4E-43E416AB619C}
+const ARP_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0xbb, 0x27, 0xb4, 0xf4, 0x21, 0xba, 0x16, 0x4f,
+    0xbc, 0x4e, 0x43, 0xe4, 0x16, 0xab, 0x61, 0x9c,
+]);
+
+/// EtherType of the protocol addresses resolved (IPv4)
+const SW_ADDRESS_TYPE_IPV4: u16 = 0x0800;
+
+/// Requests sent before the driver gives up, one per second
+const RETRY_COUNT: u32 = 3;
+const RETRY_TIMEOUT_100NS: u32 = 10_000_000;
+
+/// How long to wait for an answer, a little past the driver's retries
+pub const RESOLVE_TIMEOUT: Duration = Duration::from_secs(4);
+
+#[repr(C)]
+struct ArpConfigData {
+    sw_address_type: u16,
+    sw_address_length: u8,
+    station_address: *const c_void,
+    /// 0 = the driver's default
+    entry_timeout: u32,
+    retry_count: u32,
+    retry_timeout: u32,
+}
+
+#[repr(C)]
+struct ArpProtocol {
+    configure: unsafe extern "efiapi" fn(this: *mut ArpProtocol, config: *const ArpConfigData) -> Status,
+    _add: unsafe extern "efiapi" fn() -> Status,
+    _find: unsafe extern "efiapi" fn() -> Status,
+    _delete: unsafe extern "efiapi" fn() -> Status,
+    _flush: unsafe extern "efiapi" fn() -> Status,
+    request: unsafe extern "efiapi" fn(
+        this: *mut ArpProtocol,
+        target_sw_address: *const c_void,
+        resolved_event: uefi_raw::Event,
+        target_hw_address: *mut c_void,
+    ) -> Status,
+    cancel: unsafe extern "efiapi" fn(
+        this: *mut ArpProtocol,
+        target_sw_address: *const c_void,
+        resolved_event: uefi_raw::Event,
+    ) -> Status,
+}
+
+/// Hardware address of `target` on the link, asked for from `station`
+///
+/// An answer from the ARP cache comes back at once; otherwise requests go
+/// out until the target answers or `timeout` passes (Error::Uefi(TIMEOUT)).
+pub fn resolve(nic_handle: Handle, station: [u8; 4], target: [u8; 4], timeout: Duration) -> Result<[u8; 6]> {
+    let child = ServiceChild::create(nic_handle, &ARP_SERVICE_BINDING_GUID)?;
+    let arp = RawProtocol::<ArpProtocol>::open(child.handle(), &ARP_PROTOCOL_GUID)?;
+    let arp_ptr = arp.as_ptr();
+
+    let config = ArpConfigData {
+        sw_address_type: SW_ADDRESS_TYPE_IPV4,
+        sw_address_length: 4,
+        station_address: station.as_ptr() as *const c_void,
+        entry_timeout: 0,
+        retry_count: RETRY_COUNT,
+        retry_timeout: RETRY_TIMEOUT_100NS,
+    };
+    let status = unsafe { ((*arp_ptr).configure)(arp_ptr, &config) };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+
+    // The driver writes up to its hardware address length (6 on Ethernet)
+    let mut hw_address = [0u8; 32];
+    let completion = Completion::new()?;
+    let status = unsafe {
+        ((*arp_ptr).request)(
+            arp_ptr,
+            target.as_ptr() as *const c_void,
+            completion.0.as_ptr(),
+            hw_address.as_mut_ptr() as *mut c_void,
+        )
+    };
+
+    let result = match status {
+        Status::SUCCESS => Ok(()),
+        Status::NOT_READY => wait(&completion, timeout),
+        status => Err(Error::Uefi(status)),
+    };
+    if result.is_err() {
+        unsafe {
+            let _ = ((*arp_ptr).cancel)(arp_ptr, target.as_ptr() as *const c_void, completion.0.as_ptr());
+        }
+    }
+    result?;
+
+    // The event is also signaled when the retries run out unanswered
+    let mac: [u8; 6] = hw_address[..6].try_into().map_err(|_| Error::Unknown)?;
+    if mac == [0; 6] {
+        return Err(Error::Uefi(Status::TIMEOUT));
+    }
+    Ok(mac)
+}
+
+/// Wait for a request to complete
+fn wait(completion: &Completion, timeout: Duration) -> Result<()> {
+    let mut waited = Duration::ZERO;
+    while !completion.is_signaled() {
+        if waited >= timeout {
+            return Err(Error::Uefi(Status::TIMEOUT));
+        }
+        boot::stall(Duration::from_millis(10));
+        waited += Duration::from_millis(10);
+    }
+    Ok(())
+}
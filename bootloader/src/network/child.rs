@@ -0,0 +1,127 @@
+use crate::network::manager;
+use crate::util::{Error, Result};
+use core::ptr;
+use uefi::boot;
+use uefi::{Guid, Handle};
+use uefi_raw::protocol::driver::ServiceBindingProtocol;
+use uefi_raw::table::boot::BootServices;
+
+/// Get the raw boot services table
+fn boot_services() -> Result<*mut BootServices> {
+    let system_table = uefi::table::system_table_raw().ok_or(Error::Unknown)?;
+    Ok(unsafe { (*system_table.as_ptr()).boot_services })
+}
+
+/// A protocol interface opened with OpenProtocol, closed again on drop
+pub struct RawProtocol<T> {
+    handle: Handle,
+    guid: &'static Guid,
+    ptr: *mut T,
+}
+
+impl<T> RawProtocol<T> {
+    /// Open a protocol on a handle (GET_PROTOCOL, agent = our image)
+    pub fn open(handle: Handle, guid: &'static Guid) -> Result<Self> {
+        let mut ptr: *mut T = ptr::null_mut();
+
+        let status = unsafe {
+            let bs = boot_services()?;
+            ((*bs).open_protocol)(
+                handle.as_ptr(),
+                guid as *const Guid as *const uefi_raw::Guid,
+                &mut ptr as *mut *mut T as *mut *mut core::ffi::c_void,
+                boot::image_handle().as_ptr(),
+                ptr::null_mut(),
+                0x02, // GET_PROTOCOL
+            )
+        };
+
+        if status.is_error() {
+            return Err(Error::Uefi(status));
+        }
+        if ptr.is_null() {
+            return Err(Error::NotFound);
+        }
+
+        Ok(RawProtocol { handle, guid, ptr })
+    }
+
+    /// Raw interface pointer
+    pub fn as_ptr(&self) -> *mut T {
+        self.ptr
+    }
+}
+
+impl<T> Drop for RawProtocol<T> {
+    fn drop(&mut self) {
+        if let Ok(bs) = boot_services() {
+            unsafe {
+                let _ = ((*bs).close_protocol)(
+                    self.handle.as_ptr(),
+                    self.guid as *const Guid as *const uefi_raw::Guid,
+                    boot::image_handle().as_ptr(),
+                    ptr::null_mut(),
+                );
+            }
+        }
+    }
+}
+
+/// A child created through a Service Binding Protocol, destroyed on drop
+///
+/// Protocols opened on the child must be dropped before the child itself,
+/// so declare the `RawProtocol` after the `ServiceChild` it belongs to.
+/// Children are recorded with `manager`, which destroys any still alive
+/// before an image is started.
+pub struct ServiceChild {
+    binding: RawProtocol<ServiceBindingProtocol>,
+    handle: Handle,
+}
+
+impl ServiceChild {
+    /// Create a child on the given service binding handle
+    pub fn create(service_handle: Handle, binding_guid: &'static Guid) -> Result<Self> {
+        let binding = RawProtocol::<ServiceBindingProtocol>::open(service_handle, binding_guid)?;
+
+        let mut child_handle_raw: uefi_raw::Handle = ptr::null_mut();
+        let status = unsafe {
+            ((*binding.as_ptr()).create_child)(
+                binding.as_ptr(),
+                &mut child_handle_raw as *mut uefi_raw::Handle as *mut *mut core::ffi::c_void,
+            )
+        };
+
+        if status.is_error() {
+            return Err(Error::Uefi(status));
+        }
+
+        let handle = unsafe { Handle::from_ptr(child_handle_raw) }.ok_or(Error::Unknown)?;
+        manager::register(service_handle, binding_guid, handle);
+        Ok(ServiceChild { binding, handle })
+    }
+
+    /// Run `stop` on the child if teardown has to destroy it
+    pub fn on_teardown(&self, stop: fn(Handle)) {
+        manager::set_stop(self.handle, stop);
+    }
+
+    /// Handle of the child instance
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+}
+
+impl Drop for ServiceChild {
+    fn drop(&mut self) {
+        // Already destroyed by teardown
+        if !manager::unregister(self.handle) {
+            return;
+        }
+        unsafe {
+            let _ = ((*self.binding.as_ptr()).destroy_child)(
+                self.binding.as_ptr(),
+                self.handle.as_ptr(),
+            );
+        }
+    }
+}
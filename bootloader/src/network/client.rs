@@ -0,0 +1,724 @@
+use crate::network::child::{RawProtocol, ServiceChild};
+use crate::util::wipe::Wiped;
+use crate::util::{ucs2, Error, Result};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ptr;
+use core::time::Duration;
+use uefi::boot::{self, EventType, Tpl};
+use uefi::{Event, Guid, Handle, Status};
+use uefi_raw::protocol::network::http::{
+    HttpAccessPoint, HttpConfigData, HttpHeader, HttpMessage, HttpMethod, HttpProtocol,
+    HttpRequestData, HttpRequestOrResponse, HttpResponseData, HttpStatusCode, HttpToken,
+    HttpV4AccessPoint, HttpV6AccessPoint, HttpVersion,
+};
+use uefi_raw::{Boolean, Ipv4Address, Ipv6Address};
+
+/// HTTP Service Binding Protocol GUID
+/// {BDC8E6AF-D9BC-4379-A72A-E0C4E75DAE1C}
+const HTTP_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0xaf, 0xe6, 0xc8, 0xbd, 0xbc, 0xd9, 0x79, 0x43,
+    0xa7, 0x2a, 0xe0, 0xc4, 0xe7, 0x5d, 0xae, 0x1c,
+]);
+
+/// HTTP Protocol GUID
+/// {7A59B29B-910B-4171-8242-A85A0DF25B5B}
+const HTTP_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0x9b, 0xb2, 0x59, 0x7a, 0x0b, 0x91, 0x71, 0x41,
+    0x82, 0x42, 0xa8, 0x5a, 0x0d, 0xf2, 0x5b, 0x5b,
+]);
+
+/// Connection timeout handed to the firmware
+const CONNECT_TIMEOUT_MS: u32 = 10_000;
+
+/// Give up on a request or receive call that makes no progress for this long
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Pause between polls of the HTTP driver
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Receive buffer size at the start of a transfer
+pub const MIN_CHUNK: usize = 16 * 1024;
+
+/// Largest receive buffer the chunk size grows to
+pub const MAX_CHUNK: usize = 1024 * 1024;
+
+/// Receive buffer size for the next call, given how much the last one got
+///
+/// A completely filled buffer means the driver had more data queued than we
+/// asked for, so the buffer doubles. Less than half a buffer means we are
+/// ahead of the link and it halves again. Either way it stays between
+/// MIN_CHUNK and MAX_CHUNK.
+pub fn next_chunk_size(current: usize, received: usize) -> usize {
+    if received >= current {
+        (current * 2).min(MAX_CHUNK)
+    } else if received < current / 2 {
+        (current / 2).max(MIN_CHUNK)
+    } else {
+        current
+    }
+}
+
+/// Value of a Content-Length header, if this is one
+pub fn content_length(name: &[u8], value: &[u8]) -> Option<usize> {
+    if !name.eq_ignore_ascii_case(b"Content-Length") {
+        return None;
+    }
+    core::str::from_utf8(value).ok()?.trim().parse().ok()
+}
+
+/// Whether this is a `header` header listing `token`, e.g. `Connection:
+/// close` or `Transfer-Encoding: gzip, chunked`
+pub fn lists_token(name: &[u8], value: &[u8], header: &[u8], token: &str) -> bool {
+    name.eq_ignore_ascii_case(header)
+        && core::str::from_utf8(value)
+            .is_ok_and(|value| value.split(',').any(|item| item.trim().eq_ignore_ascii_case(token)))
+}
+
+/// Byte range of a partial response (206), from its Content-Range header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    /// Offset of the first byte sent
+    pub start: usize,
+    /// Length of the whole file (None if the server does not know it)
+    pub total: Option<usize>,
+}
+
+/// Value of a Content-Range header (`bytes <start>-<end>/<total>`), if this
+/// is one
+pub fn content_range(name: &[u8], value: &[u8]) -> Option<ContentRange> {
+    if !name.eq_ignore_ascii_case(b"Content-Range") {
+        return None;
+    }
+    let value = core::str::from_utf8(value).ok()?.trim();
+    let (unit, range) = value.split_once(' ')?;
+    if !unit.eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    let (range, total) = range.trim().split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let start: usize = start.parse().ok()?;
+    if end.parse::<usize>().ok()? < start {
+        return None;
+    }
+    let total = match total {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some(ContentRange { start, total })
+}
+
+/// Numeric status codes in the order of the firmware's HttpStatusCode
+/// values (308 was added to the end of the list later)
+const STATUS_NUMBERS: [u16; 42] = [
+    0, 100, 101, 200, 201, 202, 203, 204, 205, 206, 300, 301, 302, 303, 304, 305, 307,
+    400, 401, 402, 403, 404, 405, 406, 407, 408, 409, 410, 411, 412, 413, 414, 415, 416, 417,
+    500, 501, 502, 503, 504, 505, 308,
+];
+
+/// Numeric HTTP status code, e.g. 404 (0 if the firmware could not map it)
+pub fn status_number(status: HttpStatusCode) -> u16 {
+    usize::try_from(status.0)
+        .ok()
+        .and_then(|i| STATUS_NUMBERS.get(i).copied())
+        .unwrap_or(0)
+}
+
+/// Whether a receive failed because the server closed the connection
+/// cleanly, which is how a body sent without a length ends; a reset or a
+/// timeout leaves it cut short
+pub fn is_closed(error: Error) -> bool {
+    error == Error::Uefi(Status::CONNECTION_FIN)
+}
+
+/// Body of a PUT or POST request
+pub struct RequestBody<'a> {
+    /// Content-Type header value
+    pub content_type: &'a str,
+    /// Total length, sent as Content-Length
+    pub length: usize,
+    /// Part of the body sent along with the headers
+    pub first: &'a [u8],
+}
+
+/// Status line and headers of a response
+#[derive(Debug, Clone)]
+pub struct ResponseHead {
+    /// HTTP status code
+    pub status: HttpStatusCode,
+    /// Body length announced by the server
+    pub content_length: Option<usize>,
+    /// Body sent in chunks (`Transfer-Encoding: chunked`), which the driver
+    /// decodes; the last chunk ends it
+    pub chunked: bool,
+    /// Server closes the connection after this response (`Connection: close`)
+    pub close: bool,
+    /// Part of the file a partial response carries
+    pub content_range: Option<ContentRange>,
+    /// Redirect target (Location header)
+    pub location: Option<String>,
+    /// Version of the resource (ETag header)
+    pub etag: Option<String>,
+    /// Set-Cookie header values
+    pub set_cookies: Vec<String>,
+}
+
+impl ResponseHead {
+    /// Whether the body ends by itself, with a length or a last chunk,
+    /// rather than with the server closing the connection
+    pub fn is_delimited(&self) -> bool {
+        self.content_length.is_some() || self.chunked
+    }
+
+    /// Whether the connection can take the next request once the body has
+    /// been read to its end
+    pub fn keeps_alive(&self) -> bool {
+        self.is_delimited() && !self.close
+    }
+}
+
+/// Completion event of one request or receive call, closed on drop
+pub(crate) struct Completion(pub(crate) Event);
+
+impl Completion {
+    pub(crate) fn new() -> Result<Self> {
+        let event = unsafe { boot::create_event(EventType::empty(), Tpl::CALLBACK, None, None) }
+            .map_err(|e| Error::Uefi(e.status()))?;
+        Ok(Completion(event))
+    }
+
+    pub(crate) fn is_signaled(&self) -> bool {
+        boot::check_event(unsafe { self.0.unsafe_clone() }).unwrap_or(false)
+    }
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        let _ = boot::close_event(unsafe { self.0.unsafe_clone() });
+    }
+}
+
+/// Token, message and response of a call, boxed so that they stay put while
+/// the driver holds pointers to them
+struct CallSlot {
+    token: HttpToken,
+    message: HttpMessage,
+    response: HttpResponseData,
+}
+
+/// Kind of call handed to the driver
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Call {
+    /// Send a request
+    Request,
+    /// First receive of a response (status line, headers, start of body)
+    Head,
+    /// Further body data
+    Body,
+}
+
+/// A call the driver is still working on
+struct Pending {
+    completion: Completion,
+    slot: Box<CallSlot>,
+}
+
+/// A receive call in flight
+///
+/// The body is written straight into the caller's vector, which must not be
+/// touched until the call is finished with `end_receive` or `cancel_receive`.
+pub struct Receive {
+    pending: Pending,
+    call: Call,
+    start: usize,
+    max: usize,
+}
+
+/// What a finished receive call delivered
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// Status and headers (first receive of a response only)
+    pub head: Option<ResponseHead>,
+    /// Body bytes appended to the vector
+    pub received: usize,
+}
+
+/// HTTP client driving the firmware's HTTP protocol directly
+///
+/// Unlike `HttpHelper` this receives into caller-provided buffers of any
+/// size, so large downloads are not cut into thousands of small calls, and
+/// receive calls can be left running while the caller does something else.
+pub struct HttpClient {
+    // Declared before `child` so the protocol is closed first
+    http: RawProtocol<HttpProtocol>,
+    _child: ServiceChild,
+}
+
+impl HttpClient {
+    /// Create an HTTP instance on a NIC and configure it (HTTP/1.1, over
+    /// IPv6 when `ipv6`)
+    ///
+    /// HTTP/1.1 keeps the connection open, so the firmware sends the next
+    /// request to the same server over it. An IPv6 instance resolves names
+    /// to AAAA records.
+    pub fn new(nic_handle: Handle, ipv6: bool) -> Result<Self> {
+        let child = ServiceChild::create(nic_handle, &HTTP_SERVICE_BINDING_GUID)?;
+        let http = RawProtocol::<HttpProtocol>::open(child.handle(), &HTTP_PROTOCOL_GUID)?;
+
+        let access_point = HttpV4AccessPoint {
+            use_default_addr: Boolean::TRUE,
+            local_address: Ipv4Address([0; 4]),
+            local_subnet: Ipv4Address([0; 4]),
+            local_port: 0,
+        };
+        // The unspecified address lets the driver pick the source address
+        let access_point6 = HttpV6AccessPoint {
+            local_address: Ipv6Address([0; 16]),
+            local_port: 0,
+        };
+        let config = HttpConfigData {
+            http_version: HttpVersion::HTTP_VERSION_11,
+            time_out_millisec: CONNECT_TIMEOUT_MS,
+            local_addr_is_ipv6: if ipv6 { Boolean::TRUE } else { Boolean::FALSE },
+            access_point: if ipv6 {
+                HttpAccessPoint { ipv6_node: &access_point6 }
+            } else {
+                HttpAccessPoint { ipv4_node: &access_point }
+            },
+        };
+
+        let http_ptr = http.as_ptr();
+        let status = unsafe { ((*http_ptr).configure)(http_ptr, &config) };
+        if status.is_error() {
+            return Err(Error::Uefi(status));
+        }
+
+        Ok(HttpClient { http, _child: child })
+    }
+
+    /// Send a GET request; `host` is the Host header value (host[:port])
+    ///
+    /// With `from` set, only the body from that offset on is asked for (a
+    /// Range request); servers that don't do ranges send all of it.
+    pub fn get(
+        &mut self,
+        url: &str,
+        host: &str,
+        authorization: Option<&str>,
+        cookie: Option<&str>,
+        from: Option<usize>,
+    ) -> Result<()> {
+        let range = from.map(|from| alloc::format!("bytes={}-", from));
+        let extra: Vec<(&[u8], &str)> = cookie
+            .map(|cookie| (&b"Cookie\0"[..], cookie))
+            .into_iter()
+            .chain(range.as_deref().map(|range| (&b"Range\0"[..], range)))
+            .collect();
+        self.request(HttpMethod::GET, url, host, authorization, &extra, None)
+    }
+
+    /// Send a HEAD request: the status and headers a GET would get
+    pub fn head(&mut self, url: &str, host: &str, authorization: Option<&str>, cookie: Option<&str>) -> Result<()> {
+        let extra: Vec<(&[u8], &str)> = cookie.map(|cookie| (&b"Cookie\0"[..], cookie)).into_iter().collect();
+        self.request(HttpMethod::HEAD, url, host, authorization, &extra, None)
+    }
+
+    /// Send a request with a body
+    ///
+    /// The rest of the body, if `body.first` is not all of it, follows with
+    /// `send_body`.
+    pub fn send(
+        &mut self,
+        method: HttpMethod,
+        url: &str,
+        host: &str,
+        authorization: Option<&str>,
+        body: &RequestBody,
+    ) -> Result<()> {
+        self.request(method, url, host, authorization, &[], Some(body))
+    }
+
+    /// Send the next part of a request body
+    ///
+    /// Firmware whose HTTP driver only takes the body along with the
+    /// headers fails this with INVALID_PARAMETER.
+    pub fn send_body(&mut self, chunk: &[u8]) -> Result<()> {
+        let message = HttpMessage {
+            // No request data: more body for the request already sent
+            data: HttpRequestOrResponse { request: ptr::null() },
+            header_count: 0,
+            header: ptr::null_mut(),
+            body_length: chunk.len(),
+            body: chunk.as_ptr() as *mut core::ffi::c_void,
+        };
+
+        let pending = self.start(message, Call::Request)?;
+        if let Err(e) = self.wait(&pending) {
+            self.cancel(&pending);
+            return Err(e);
+        }
+        token_status(&pending)
+    }
+
+    fn request(
+        &mut self,
+        method: HttpMethod,
+        url: &str,
+        host: &str,
+        authorization: Option<&str>,
+        extra: &[(&[u8], &str)],
+        body: Option<&RequestBody>,
+    ) -> Result<()> {
+        let url16 = ucs2::to_cstring16(url)?;
+        let request = HttpRequestData {
+            method,
+            url: url16.as_ptr().cast(),
+        };
+
+        // Header strings are NUL-terminated ASCII
+        let mut host_value: Vec<u8> = Vec::with_capacity(host.len() + 1);
+        host_value.extend_from_slice(host.as_bytes());
+        host_value.push(0);
+        let mut agent_value = crate::network::session::user_agent().into_bytes();
+        agent_value.push(0);
+        let mut headers = alloc::vec![
+            HttpHeader { field_name: b"Host\0".as_ptr(), field_value: host_value.as_ptr() },
+            HttpHeader { field_name: b"Accept\0".as_ptr(), field_value: b"*/*\0".as_ptr() },
+            HttpHeader { field_name: b"User-Agent\0".as_ptr(), field_value: agent_value.as_ptr() },
+        ];
+        let mut auth_value = Wiped::default();
+        if let Some(value) = authorization {
+            auth_value.extend_from_slice(value.as_bytes());
+            auth_value.push(0);
+            headers.push(HttpHeader { field_name: b"Authorization\0".as_ptr(), field_value: auth_value.as_ptr() });
+        }
+        // Further headers, each name NUL-terminated already
+        let extra_values: Vec<Vec<u8>> = extra
+            .iter()
+            .map(|(_, value)| {
+                let mut bytes = Vec::from(value.as_bytes());
+                bytes.push(0);
+                bytes
+            })
+            .collect();
+        for ((name, _), value) in extra.iter().zip(extra_values.iter()) {
+            headers.push(HttpHeader { field_name: name.as_ptr(), field_value: value.as_ptr() });
+        }
+        let (mut type_value, mut length_value) = (Vec::new(), Vec::new());
+        let first = match body {
+            Some(body) => {
+                type_value.extend_from_slice(body.content_type.as_bytes());
+                type_value.push(0);
+                length_value.extend_from_slice(alloc::format!("{}\0", body.length).as_bytes());
+                headers.push(HttpHeader { field_name: b"Content-Type\0".as_ptr(), field_value: type_value.as_ptr() });
+                headers.push(HttpHeader { field_name: b"Content-Length\0".as_ptr(), field_value: length_value.as_ptr() });
+                body.first
+            }
+            None => &[],
+        };
+
+        let message = HttpMessage {
+            data: HttpRequestOrResponse { request: &request },
+            header_count: headers.len(),
+            header: headers.as_mut_ptr(),
+            body_length: first.len(),
+            body: if first.is_empty() { ptr::null_mut() } else { first.as_ptr() as *mut core::ffi::c_void },
+        };
+
+        // The request, URL and headers live on this stack frame, so wait here
+        let pending = self.start(message, Call::Request)?;
+        if let Err(e) = self.wait(&pending) {
+            self.cancel(&pending);
+            return Err(e);
+        }
+        token_status(&pending)
+    }
+
+    /// Receive the status line, headers and the start of the body
+    ///
+    /// Up to `max` body bytes are appended to `data`.
+    pub fn response_head(&mut self, data: &mut Wiped, max: usize) -> Result<ResponseHead> {
+        let chunk = self.receive(data, max, true)?;
+        chunk.head.ok_or(Error::Io)
+    }
+
+    /// Receive more of the body, appending up to `max` bytes to `data`
+    ///
+    /// Returns the number of bytes received (0 once the body is complete).
+    pub fn response_body(&mut self, data: &mut Wiped, max: usize) -> Result<usize> {
+        self.receive(data, max, false).map(|chunk| chunk.received)
+    }
+
+    fn receive(&mut self, data: &mut Wiped, max: usize, head: bool) -> Result<Chunk> {
+        let rx = self.begin_receive(data, max, head)?;
+        if let Err(e) = self.wait(&rx.pending) {
+            self.cancel_receive(rx, data);
+            return Err(e);
+        }
+        self.end_receive(rx, data)
+    }
+
+    /// Start receiving up to `max` body bytes into `data` without waiting
+    ///
+    /// With `head` set this is the first receive of a response and also
+    /// collects the status line and headers.
+    pub fn begin_receive(&mut self, data: &mut Wiped, max: usize, head: bool) -> Result<Receive> {
+        let start = data.len();
+        data.try_reserve(max).map_err(|_| Error::OutOfMemory)?;
+        data.resize(start + max, 0);
+
+        let message = HttpMessage {
+            // Pointed at the slot's response by `start` for the head
+            data: HttpRequestOrResponse { response: ptr::null() },
+            header_count: 0,
+            header: ptr::null_mut(),
+            body_length: max,
+            body: data[start..].as_mut_ptr().cast(),
+        };
+
+        let call = if head { Call::Head } else { Call::Body };
+        match self.start(message, call) {
+            Ok(pending) => Ok(Receive { pending, call, start, max }),
+            Err(e) => {
+                data.truncate(start);
+                Err(e)
+            }
+        }
+    }
+
+    /// Drive the driver once and report whether the receive call is done
+    pub fn is_done(&mut self, rx: &Receive) -> bool {
+        self.poll_once(&rx.pending)
+    }
+
+    /// Block until a receive call completes (TIMEOUT if it stalls)
+    pub fn wait_receive(&mut self, rx: &Receive) -> Result<()> {
+        self.wait(&rx.pending)
+    }
+
+    /// Collect the result of a completed receive call
+    pub fn end_receive(&mut self, rx: Receive, data: &mut Wiped) -> Result<Chunk> {
+        let slot = &rx.pending.slot;
+        let result = token_status(&rx.pending);
+        let received = if result.is_ok() { slot.message.body_length.min(rx.max) } else { 0 };
+        data.truncate(rx.start + received);
+
+        let headers = unsafe { take_headers(&slot.message) };
+        result?;
+
+        let head = if rx.call == Call::Head {
+            Some(ResponseHead {
+                status: slot.response.status_code,
+                ..headers
+            })
+        } else {
+            None
+        };
+        Ok(Chunk { head, received })
+    }
+
+    /// Abandon a receive call and drop whatever it had received
+    pub fn cancel_receive(&mut self, rx: Receive, data: &mut Wiped) {
+        self.cancel(&rx.pending);
+        data.truncate(rx.start);
+    }
+
+    /// Hand a message to the driver
+    fn start(&mut self, message: HttpMessage, call: Call) -> Result<Pending> {
+        let completion = Completion::new()?;
+
+        let mut slot = Box::new(CallSlot {
+            token: HttpToken {
+                event: completion.0.as_ptr(),
+                status: Status::NOT_READY,
+                message: ptr::null_mut(),
+            },
+            message,
+            response: HttpResponseData {
+                status_code: HttpStatusCode::STATUS_UNSUPPORTED,
+            },
+        });
+        if call == Call::Head {
+            slot.message.data = HttpRequestOrResponse { response: &slot.response };
+        }
+        slot.token.message = &mut slot.message;
+
+        let http_ptr = self.http.as_ptr();
+        let status = unsafe {
+            if call == Call::Request {
+                ((*http_ptr).request)(http_ptr, &mut slot.token)
+            } else {
+                ((*http_ptr).response)(http_ptr, &mut slot.token)
+            }
+        };
+        if status.is_error() {
+            return Err(Error::Uefi(status));
+        }
+
+        Ok(Pending { completion, slot })
+    }
+
+    fn poll_once(&mut self, pending: &Pending) -> bool {
+        if pending.completion.is_signaled() {
+            return true;
+        }
+        let http_ptr = self.http.as_ptr();
+        unsafe {
+            let _ = ((*http_ptr).poll)(http_ptr);
+        }
+        pending.completion.is_signaled()
+    }
+
+    /// Poll until a call completes or makes no progress for CALL_TIMEOUT
+    fn wait(&mut self, pending: &Pending) -> Result<()> {
+        let polls = CALL_TIMEOUT.as_micros() / POLL_INTERVAL.as_micros();
+        for _ in 0..polls {
+            if self.poll_once(pending) {
+                return Ok(());
+            }
+            boot::stall(POLL_INTERVAL);
+        }
+        Err(Error::Uefi(Status::TIMEOUT))
+    }
+
+    /// Cancel a call; the driver must not touch its buffers afterwards
+    fn cancel(&mut self, pending: &Pending) {
+        let http_ptr = self.http.as_ptr();
+        unsafe {
+            let _ = ((*http_ptr).cancel)(http_ptr, &pending.slot.token);
+        }
+    }
+}
+
+/// Final status the driver stored in a completed call's token
+fn token_status(pending: &Pending) -> Result<()> {
+    let status = unsafe { ptr::read_volatile(&pending.slot.token.status) };
+    if status.is_error() {
+        Err(Error::Uefi(status))
+    } else {
+        Ok(())
+    }
+}
+
+/// Collect the headers we use and free the header array the driver
+/// allocated (the status is left for the caller to fill in)
+unsafe fn take_headers(message: &HttpMessage) -> ResponseHead {
+    let mut head = ResponseHead {
+        status: HttpStatusCode::STATUS_UNSUPPORTED,
+        content_length: None,
+        chunked: false,
+        close: false,
+        content_range: None,
+        location: None,
+        etag: None,
+        set_cookies: Vec::new(),
+    };
+    if message.header.is_null() {
+        return head;
+    }
+
+    let headers = core::slice::from_raw_parts(message.header, message.header_count);
+    for header in headers {
+        let name = cstr_bytes(header.field_name);
+        let value = cstr_bytes(header.field_value);
+        head.content_length = head.content_length.or_else(|| content_length(name, value));
+        head.content_range = head.content_range.or_else(|| content_range(name, value));
+        head.chunked |= lists_token(name, value, b"Transfer-Encoding", "chunked");
+        head.close |= lists_token(name, value, b"Connection", "close");
+        if let Ok(value) = core::str::from_utf8(value) {
+            if name.eq_ignore_ascii_case(b"Location") {
+                head.location = Some(String::from(value.trim()));
+            } else if name.eq_ignore_ascii_case(b"ETag") {
+                head.etag = Some(String::from(value.trim()));
+            } else if name.eq_ignore_ascii_case(b"Set-Cookie") {
+                head.set_cookies.push(String::from(value));
+            }
+        }
+
+        for field in [header.field_name, header.field_value] {
+            if let Some(p) = ptr::NonNull::new(field as *mut u8) {
+                let _ = boot::free_pool(p);
+            }
+        }
+    }
+
+    let _ = boot::free_pool(ptr::NonNull::new_unchecked(message.header.cast()));
+    head
+}
+
+/// Bytes of a NUL-terminated string (empty for null)
+unsafe fn cstr_bytes<'a>(p: *const u8) -> &'a [u8] {
+    if p.is_null() {
+        return &[];
+    }
+    let mut len = 0;
+    while *p.add(len) != 0 {
+        len += 1;
+    }
+    core::slice::from_raw_parts(p, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_growth() {
+        let mut size = MIN_CHUNK;
+        for _ in 0..10 {
+            size = next_chunk_size(size, size);
+        }
+        assert_eq!(size, MAX_CHUNK);
+
+        // Short reads shrink it again, never below the minimum
+        assert_eq!(next_chunk_size(MAX_CHUNK, 4096), MAX_CHUNK / 2);
+        assert_eq!(next_chunk_size(MIN_CHUNK, 0), MIN_CHUNK);
+        assert_eq!(next_chunk_size(64 * 1024, 40 * 1024), 64 * 1024);
+    }
+
+    #[test]
+    fn test_status_number() {
+        assert_eq!(status_number(HttpStatusCode::STATUS_200_OK), 200);
+        assert_eq!(status_number(HttpStatusCode::STATUS_307_TEMPORARY_REDIRECT), 307);
+        assert_eq!(status_number(HttpStatusCode::STATUS_413_REQUEST_ENTITY_TOO_LARGE), 413);
+        assert_eq!(status_number(HttpStatusCode::STATUS_505_HTTP_VERSION_NOT_SUPPORTED), 505);
+        assert_eq!(status_number(HttpStatusCode::STATUS_308_PERMANENT_REDIRECT), 308);
+        assert_eq!(status_number(HttpStatusCode::STATUS_UNSUPPORTED), 0);
+    }
+
+    #[test]
+    fn test_is_closed() {
+        assert!(is_closed(Error::Uefi(Status::CONNECTION_FIN)));
+        assert!(!is_closed(Error::Uefi(Status::CONNECTION_RESET)));
+        assert!(!is_closed(Error::Uefi(Status::TIMEOUT)));
+    }
+
+    #[test]
+    fn test_content_length() {
+        assert_eq!(content_length(b"content-length", b" 1234 "), Some(1234));
+        assert_eq!(content_length(b"Content-Type", b"1234"), None);
+        assert_eq!(content_length(b"Content-Length", b"lots"), None);
+    }
+
+    #[test]
+    fn test_lists_token() {
+        assert!(lists_token(b"connection", b"Close", b"Connection", "close"));
+        assert!(lists_token(b"Transfer-Encoding", b"gzip, chunked", b"Transfer-Encoding", "chunked"));
+        assert!(!lists_token(b"Connection", b"keep-alive", b"Connection", "close"));
+        assert!(!lists_token(b"X-Connection", b"close", b"Connection", "close"));
+    }
+
+    #[test]
+    fn test_content_range() {
+        assert_eq!(
+            content_range(b"content-range", b"bytes 100-999/1000"),
+            Some(ContentRange { start: 100, total: Some(1000) })
+        );
+        assert_eq!(content_range(b"Content-Range", b"bytes 0-9/*"), Some(ContentRange { start: 0, total: None }));
+        assert_eq!(content_range(b"Content-Range", b"bytes */1000"), None);
+        assert_eq!(content_range(b"Content-Range", b"bytes 10-5/1000"), None);
+        assert_eq!(content_range(b"Content-Range", b"items 0-9/10"), None);
+        assert_eq!(content_range(b"Content-Length", b"bytes 0-9/10"), None);
+    }
+}
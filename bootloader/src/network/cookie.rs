@@ -0,0 +1,226 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Cookies kept per session; the oldest goes when a new one does not fit
+const MAX_COOKIES: usize = 32;
+
+/// Longest name=value pair kept (RFC 6265 asks for at least 4096 bytes)
+const MAX_COOKIE_LEN: usize = 4096;
+
+/// A cookie set by a server (RFC 6265)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Host, or domain with its subdomains, the cookie goes back to
+    pub domain: String,
+    /// No Domain attribute: sent to exactly `domain`
+    pub host_only: bool,
+    pub path: String,
+    /// Sent over HTTPS only
+    pub secure: bool,
+}
+
+impl Cookie {
+    /// Whether the cookie goes along with a request to `host` and `path`
+    fn matches(&self, host: &str, path: &str, https: bool) -> bool {
+        let domain_ok = host == self.domain
+            || (!self.host_only && host.strip_suffix(self.domain.as_str()).is_some_and(|rest| rest.ends_with('.')));
+        let path_ok = path == self.path
+            || (path.starts_with(self.path.as_str())
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        domain_ok && path_ok && (https || !self.secure)
+    }
+}
+
+/// Parse a Set-Cookie header received from `host` for `path`
+///
+/// Returns the cookie and whether it is already expired (Max-Age of zero
+/// or less, or an Expires date before `this_year`, which is how servers
+/// delete cookies). None for malformed cookies and for a Domain the host
+/// does not belong to.
+pub fn parse(header: &str, host: &str, path: &str, this_year: u16) -> Option<(Cookie, bool)> {
+    let host = host.to_ascii_lowercase();
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let (name, value) = (name.trim(), value.trim());
+    if name.is_empty() || name.len() + value.len() > MAX_COOKIE_LEN {
+        return None;
+    }
+
+    let mut cookie = Cookie {
+        name: String::from(name),
+        value: String::from(value),
+        domain: host.clone(),
+        host_only: true,
+        path: default_path(path),
+        secure: false,
+    };
+    let mut max_age = None;
+    let mut expires_year = None;
+
+    for attribute in parts {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let (key, value) = (key.trim(), value.trim());
+        if key.eq_ignore_ascii_case("domain") {
+            let domain = value.trim_start_matches('.').to_ascii_lowercase();
+            if domain.is_empty() {
+                continue;
+            }
+            // A parent domain of the host, never a bare top-level one
+            let parent = host.strip_suffix(domain.as_str()).is_some_and(|rest| rest.ends_with('.'));
+            if domain != host && !(parent && domain.contains('.')) {
+                return None;
+            }
+            cookie.host_only = domain == host;
+            cookie.domain = domain;
+        } else if key.eq_ignore_ascii_case("path") {
+            if value.starts_with('/') {
+                cookie.path = String::from(value);
+            }
+        } else if key.eq_ignore_ascii_case("secure") {
+            cookie.secure = true;
+        } else if key.eq_ignore_ascii_case("max-age") {
+            max_age = value.parse::<i64>().ok();
+        } else if key.eq_ignore_ascii_case("expires") {
+            expires_year = value
+                .split([' ', '-'])
+                .find(|part| part.len() == 4 && part.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|year| year.parse::<u16>().ok());
+        }
+    }
+
+    // Max-Age wins over Expires when both are given
+    let expired = match max_age {
+        Some(seconds) => seconds <= 0,
+        None => expires_year.is_some_and(|year| year < this_year),
+    };
+    Some((cookie, expired))
+}
+
+/// Directory of the request path, where a cookie without Path applies
+fn default_path(path: &str) -> String {
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    match path.rfind('/') {
+        Some(0) | None => String::from("/"),
+        Some(i) => String::from(&path[..i]),
+    }
+}
+
+/// Cookies received during a session
+#[derive(Debug, Default)]
+pub struct Jar {
+    cookies: Vec<Cookie>,
+}
+
+impl Jar {
+    /// Take in a Set-Cookie header from a response to `host` and `path`
+    pub fn store(&mut self, header: &str, host: &str, path: &str, this_year: u16) {
+        let Some((cookie, expired)) = parse(header, host, path, this_year) else {
+            return;
+        };
+
+        // A cookie with the same name, domain and path is replaced in place,
+        // keeping its position among cookies with equally long paths
+        let existing = self
+            .cookies
+            .iter()
+            .position(|c| c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path);
+        match (existing, expired) {
+            (Some(i), true) => {
+                self.cookies.remove(i);
+                return;
+            }
+            (Some(i), false) => {
+                self.cookies[i] = cookie;
+                return;
+            }
+            (None, true) => return,
+            (None, false) => {}
+        }
+        if self.cookies.len() >= MAX_COOKIES {
+            self.cookies.remove(0);
+        }
+        self.cookies.push(cookie);
+    }
+
+    /// Cookie header value for a request, longest paths first
+    pub fn header(&self, host: &str, path: &str, https: bool) -> Option<String> {
+        let host = host.to_ascii_lowercase();
+        let path = path.split(['?', '#']).next().unwrap_or("/");
+
+        let mut matching: Vec<&Cookie> = self.cookies.iter().filter(|c| c.matches(&host, path, https)).collect();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by_key(|c| core::cmp::Reverse(c.path.len()));
+
+        let pairs: Vec<String> = matching.iter().map(|c| alloc::format!("{}={}", c.name, c.value)).collect();
+        Some(pairs.join("; "))
+    }
+
+    /// Number of cookies held
+    pub fn count(&self) -> usize {
+        self.cookies.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let (cookie, expired) = parse("SESSION=abc123; Path=/; HttpOnly; Secure", "Store.example.com", "/a/b", 2026).unwrap();
+        assert_eq!(cookie.name, "SESSION");
+        assert_eq!(cookie.value, "abc123");
+        assert_eq!(cookie.domain, "store.example.com");
+        assert!(cookie.host_only && cookie.secure && !expired);
+        assert_eq!(cookie.path, "/");
+
+        // Without Path, the directory of the request
+        assert_eq!(parse("a=1", "h", "/dl/images/x.efi?t=1", 2026).unwrap().0.path, "/dl/images");
+        assert_eq!(parse("a=1", "h", "/x.efi", 2026).unwrap().0.path, "/");
+
+        let (cookie, _) = parse("a=1; Domain=.example.com", "store.example.com", "/", 2026).unwrap();
+        assert_eq!(cookie.domain, "example.com");
+        assert!(!cookie.host_only);
+
+        // Domains the host is not in, and top-level domains, are refused
+        assert!(parse("a=1; Domain=other.com", "store.example.com", "/", 2026).is_none());
+        assert!(parse("a=1; Domain=com", "store.example.com", "/", 2026).is_none());
+        assert!(parse("a=1; Domain=ample.com", "example.com", "/", 2026).is_none());
+        assert!(parse("=1", "h", "/", 2026).is_none());
+        assert!(parse("novalue", "h", "/", 2026).is_none());
+
+        assert!(parse("a=; Max-Age=0", "h", "/", 2026).unwrap().1);
+        assert!(parse("a=; Expires=Thu, 01 Jan 1970 00:00:00 GMT", "h", "/", 2026).unwrap().1);
+        assert!(!parse("a=1; Expires=Fri, 01-Jan-2038 00:00:00 GMT", "h", "/", 2026).unwrap().1);
+        assert!(!parse("a=1; Max-Age=600; Expires=Thu, 01 Jan 1970 00:00:00 GMT", "h", "/", 2026).unwrap().1);
+    }
+
+    #[test]
+    fn test_jar() {
+        let mut jar = Jar::default();
+        jar.store("SESSION=one; Path=/", "sso.example.com", "/login", 2026);
+        jar.store("wide=1; Domain=example.com; Path=/", "sso.example.com", "/login", 2026);
+        jar.store("deep=2; Path=/artifacts", "store.example.com", "/artifacts/x", 2026);
+        jar.store("tls=3; Secure; Path=/", "store.example.com", "/", 2026);
+
+        assert_eq!(jar.header("sso.example.com", "/anything", true).as_deref(), Some("SESSION=one; wide=1"));
+        assert_eq!(
+            jar.header("STORE.example.com", "/artifacts/os/x.efi?sig=1", true).as_deref(),
+            Some("deep=2; wide=1; tls=3")
+        );
+        // Secure cookies stay off plain HTTP, paths must match on a boundary
+        assert_eq!(jar.header("store.example.com", "/artifactsX", false).as_deref(), Some("wide=1"));
+        assert_eq!(jar.header("other.org", "/", true), None);
+
+        // Replaced, then deleted
+        jar.store("SESSION=two; Path=/", "sso.example.com", "/", 2026);
+        assert_eq!(jar.header("sso.example.com", "/", false).as_deref(), Some("SESSION=two; wide=1"));
+        jar.store("SESSION=; Path=/; Max-Age=0", "sso.example.com", "/", 2026);
+        assert_eq!(jar.header("sso.example.com", "/", false).as_deref(), Some("wide=1"));
+        assert_eq!(jar.count(), 3);
+    }
+}
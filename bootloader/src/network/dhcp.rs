@@ -1,12 +1,20 @@
+use crate::network::addr::{self, Ipv4, Ipv6};
+use crate::network::child::{RawProtocol, ServiceChild};
+use crate::network::ip6config;
+use crate::util::caps::{self, Capability};
+use crate::{detail, say};
+use crate::util::global::Global;
+use crate::util::logger::log_entry;
 use crate::util::{Error, Result};
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::fmt;
 use core::ptr;
 use core::time::Duration;
-use uefi::boot::{self, SearchType};
+use uefi::boot;
 use uefi::{println, Guid, Handle, Status};
-use uefi_raw::protocol::driver::ServiceBindingProtocol;
-use uefi_raw::protocol::network::dhcp4::{
-    Dhcp4ConfigData, Dhcp4ModeData, Dhcp4Protocol, Dhcp4State,
-};
+use uefi_raw::protocol::network::dhcp4::{self, Dhcp4ModeData, Dhcp4Protocol, Dhcp4State};
+use uefi_raw::Ipv4Address;
 
 /// DHCP4 Service Binding Protocol GUID
 const DHCP4_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
@@ -20,96 +28,411 @@ const DHCP4_PROTOCOL_GUID: Guid = Guid::from_bytes([
     0x91, 0xc8, 0xc0, 0xf0, 0x4b, 0xda, 0x9e, 0x56,
 ]);
 
-/// Configure DHCP on a network interface
-pub fn configure_dhcp(nic_handle: Handle) -> Result<()> {
-    println!("  Configuring DHCP...");
+/// Called by the driver at every step of the exchange; an error aborts it
+type Dhcp4Callback = unsafe extern "efiapi" fn(
+    this: *mut Dhcp4Protocol,
+    context: *mut c_void,
+    current_state: Dhcp4State,
+    event: u32,
+    packet: *const u8,
+    new_packet: *mut *mut u8,
+) -> Status;
+
+/// EFI_DHCP4_CONFIG_DATA, with the callback typed for `dhcp_callback`
+#[repr(C)]
+struct Dhcp4ConfigData {
+    discover_try_count: u32,
+    discover_timeout: *mut u32,
+    request_try_count: u32,
+    request_timeout: *mut u32,
+    client_address: Ipv4Address,
+    callback: Option<Dhcp4Callback>,
+    callback_context: *mut c_void,
+    option_count: u32,
+    option_list: *mut *mut c_void,
+}
 
-    // Step 1: Locate DHCP4 Service Binding Protocol handles
-    let service_handles = boot::locate_handle_buffer(SearchType::ByProtocol(&DHCP4_SERVICE_BINDING_GUID))
-        .map_err(|e| {
-            println!("    Failed to locate DHCP4 Service Binding: {:?}", e.status());
-            Error::Uefi(e.status())
-        })?;
+/// EFI_DHCP4_EVENT values the callback reports on
+const EVENT_RCVD_OFFER: u32 = 0x02;
+const EVENT_SELECT_OFFER: u32 = 0x03;
+const EVENT_RCVD_ACK: u32 = 0x05;
+const EVENT_RCVD_NAK: u32 = 0x06;
+const EVENT_SEND_DECLINE: u32 = 0x07;
+const EVENT_FAIL: u32 = 0x0c;
+
+/// Where the DHCP message starts in an EFI_DHCP4_PACKET (after Size and
+/// Length)
+const PACKET_HEADER_LEN: usize = 8;
+
+/// Offset of the options in a DHCP message: fixed header and magic cookie
+const OPTIONS_OFFSET: usize = 240;
+
+/// DHCP options looked at in offers (RFC 2132)
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_VENDOR_CLASS: u8 = 60;
+const OPTION_BOOT_FILE: u8 = 67;
+
+/// Boot server (siaddr) and boot file fields of a DHCP message
+const SERVER_ADDRESS: core::ops::Range<usize> = 20..24;
+const BOOT_FILE: core::ops::Range<usize> = 108..236;
+
+/// Score of an offer that is taken without waiting for others
+const BEST_SCORE: u8 = 2;
+
+/// Which offer to take when several DHCP servers answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OfferPolicy {
+    /// Whichever the firmware sees first
+    #[default]
+    First,
+    /// One with PXE boot information: a "PXEClient" vendor class, or else
+    /// a boot server or boot file
+    Pxe,
+    /// The one from this server
+    Server(Ipv4),
+}
 
-    if service_handles.is_empty() {
-        println!("    No DHCP4 Service Binding found");
-        return Err(Error::NotFound);
+impl OfferPolicy {
+    /// Parse a `dhcp_offer=` value: `first`, `pxe` or a server address
+    pub fn parse(value: &str) -> crate::util::Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "first" => Ok(OfferPolicy::First),
+            "pxe" => Ok(OfferPolicy::Pxe),
+            _ => addr::parse_ipv4(value).map(OfferPolicy::Server),
+        }
     }
+}
 
-    println!("    Found {} DHCP4 Service Binding instance(s)", service_handles.len());
+impl fmt::Display for OfferPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OfferPolicy::First => write!(f, "first"),
+            OfferPolicy::Pxe => write!(f, "pxe"),
+            OfferPolicy::Server(server) => write!(f, "{}", server),
+        }
+    }
+}
 
-    // Use the first service binding handle
-    let service_handle = service_handles[0];
+/// What the callback saw of an exchange
+#[derive(Debug, Default)]
+struct Progress {
+    /// State at the last callback
+    state: Option<Dhcp4State>,
+    /// Offers received
+    offers: u32,
+    /// NAKs received
+    naks: u32,
+    /// Which offer to take
+    policy: OfferPolicy,
+    /// Best offer so far with its score, as a whole EFI_DHCP4_PACKET
+    best: Option<(u8, Vec<u8>)>,
+}
 
-    // Step 2: Get Service Binding Protocol interface
-    let mut service_binding_ptr: *mut ServiceBindingProtocol = ptr::null_mut();
+impl Progress {
+    /// Keep an offer that suits the policy better than those before it
+    ///
+    /// SUCCESS tells the driver to take the offer at once; NOT_READY makes
+    /// it collect offers until the discover timeout and then ask which one
+    /// to select (EVENT_SELECT_OFFER), where the kept one is handed back.
+    fn consider(&mut self, packet: &[u8]) -> Status {
+        let message = &packet[PACKET_HEADER_LEN..];
+        // A proxyDHCP offer only carries boot information, not an address
+        if offered_address(message).is_none_or(|a| a == [0; 4]) {
+            return Status::NOT_READY;
+        }
+        let score = offer_score(self.policy, message);
+        if self.best.as_ref().is_none_or(|(best, _)| score > *best) {
+            let mut copy = Vec::from(packet);
+            // Size is the buffer the driver allocated; ours is exactly this
+            copy[..4].copy_from_slice(&(packet.len() as u32).to_ne_bytes());
+            self.best = Some((score, copy));
+        }
+        if score >= BEST_SCORE {
+            Status::SUCCESS
+        } else {
+            Status::NOT_READY
+        }
+    }
+}
 
-    let status = unsafe {
-        let system_table = uefi::table::system_table_raw().unwrap();
-        let boot_services = (*system_table.as_ptr()).boot_services;
-        ((*boot_services).open_protocol)(
-            service_handle.as_ptr(),
-            &DHCP4_SERVICE_BINDING_GUID as *const Guid as *const uefi_raw::Guid,
-            &mut service_binding_ptr as *mut *mut ServiceBindingProtocol as *mut *mut core::ffi::c_void,
-            boot::image_handle().as_ptr(),
-            ptr::null_mut(),
-            0x02, // GET_PROTOCOL
-        )
-    };
+/// Address offered to the client (yiaddr) in a DHCP message
+fn offered_address(message: &[u8]) -> Option<[u8; 4]> {
+    message.get(16..20)?.try_into().ok()
+}
 
-    if status.is_error() {
-        println!("    Failed to open Service Binding Protocol: {:?}", status);
-        return Err(Error::Uefi(status));
+/// Value of an option of a DHCP message
+fn find_option(message: &[u8], wanted: u8) -> Option<&[u8]> {
+    let mut options = message.get(OPTIONS_OFFSET..)?;
+    while let Some((&code, rest)) = options.split_first() {
+        match code {
+            0 => options = rest,
+            255 => return None,
+            _ => {
+                let (&len, rest) = rest.split_first()?;
+                let value = rest.get(..len as usize)?;
+                if code == wanted {
+                    return Some(value);
+                }
+                options = &rest[len as usize..];
+            }
+        }
     }
+    None
+}
+
+/// Server identifier option of a DHCP message
+fn server_id(message: &[u8]) -> Option<[u8; 4]> {
+    find_option(message, OPTION_SERVER_ID)?.try_into().ok()
+}
 
-    println!("    Opened Service Binding Protocol");
+/// How well an offer suits `policy`, up to BEST_SCORE
+fn offer_score(policy: OfferPolicy, message: &[u8]) -> u8 {
+    match policy {
+        OfferPolicy::First => BEST_SCORE,
+        OfferPolicy::Pxe => {
+            let pxe_client = find_option(message, OPTION_VENDOR_CLASS).is_some_and(|v| v.starts_with(b"PXEClient"));
+            let boot_server = message.get(SERVER_ADDRESS).is_some_and(|a| a != [0; 4]);
+            let boot_file = message.get(BOOT_FILE).is_some_and(|f| f[0] != 0)
+                || find_option(message, OPTION_BOOT_FILE).is_some();
+            if pxe_client {
+                BEST_SCORE
+            } else if boot_server || boot_file {
+                1
+            } else {
+                0
+            }
+        }
+        OfferPolicy::Server(server) => {
+            if server_id(message) == Some(server.0) {
+                BEST_SCORE
+            } else {
+                0
+            }
+        }
+    }
+}
 
-    // Step 3: Create DHCP4 child instance
-    let mut child_handle_raw: uefi_raw::Handle = ptr::null_mut();
+/// Dotted form of an address, or "?" when it is missing
+fn dotted(address: Option<[u8; 4]>) -> alloc::string::String {
+    match address {
+        Some(a) => alloc::format!("{}.{}.{}.{}", a[0], a[1], a[2], a[3]),
+        None => alloc::string::String::from("?"),
+    }
+}
 
-    let status = unsafe {
-        ((*service_binding_ptr).create_child)(
-            service_binding_ptr,
-            &mut child_handle_raw as *mut uefi_raw::Handle as *mut *mut core::ffi::c_void
-        )
+/// Log state changes and the offers, NAKs and failures of an exchange, and
+/// pick among offers by the configured policy
+///
+/// Otherwise lets the driver carry on as it would without a callback.
+unsafe extern "efiapi" fn dhcp_callback(
+    _this: *mut Dhcp4Protocol,
+    context: *mut c_void,
+    current_state: Dhcp4State,
+    event: u32,
+    packet: *const u8,
+    new_packet: *mut *mut u8,
+) -> Status {
+    let progress = unsafe { &mut *(context as *mut Progress) };
+    match progress.state {
+        Some(previous) if previous != current_state => {
+            log_entry(log::Level::Info, &alloc::format!("DHCP: {:?} -> {:?}", previous, current_state));
+        }
+        None => log_entry(log::Level::Info, &alloc::format!("DHCP: {:?}", current_state)),
+        Some(_) => {}
+    }
+    progress.state = Some(current_state);
+
+    // The packet, as far as the driver filled it in, and its DHCP message
+    let packet: &[u8] = if packet.is_null() {
+        &[]
+    } else {
+        unsafe {
+            let length = ptr::read_unaligned(packet.add(4) as *const u32) as usize;
+            core::slice::from_raw_parts(packet, PACKET_HEADER_LEN + length)
+        }
     };
+    let message = packet.get(PACKET_HEADER_LEN..).unwrap_or(&[]);
+    let server = dotted(server_id(message));
+    match event {
+        EVENT_RCVD_OFFER => {
+            progress.offers += 1;
+            let offer = dotted(offered_address(message));
+            log_entry(log::Level::Info, &alloc::format!("DHCP: offer of {} from server {}", offer, server));
+            if progress.policy != OfferPolicy::First && !message.is_empty() {
+                return progress.consider(packet);
+            }
+        }
+        EVENT_SELECT_OFFER => {
+            let mut server = server;
+            if let Some((_, best)) = progress.best.as_mut() {
+                if !new_packet.is_null() {
+                    // The driver copies the packet before this returns
+                    unsafe { *new_packet = best.as_mut_ptr() };
+                    server = dotted(server_id(&best[PACKET_HEADER_LEN..]));
+                }
+            }
+            log_entry(log::Level::Info, &alloc::format!("DHCP: selected offer from server {}", server));
+        }
+        EVENT_RCVD_ACK => log_entry(log::Level::Info, &alloc::format!("DHCP: ACK from server {}", server)),
+        EVENT_RCVD_NAK => {
+            progress.naks += 1;
+            log_entry(log::Level::Warn, &alloc::format!("DHCP: NAK from server {}", server));
+        }
+        EVENT_SEND_DECLINE => {
+            log_entry(log::Level::Warn, "DHCP: declined the offered address, it is already in use");
+        }
+        EVENT_FAIL => log_entry(log::Level::Warn, &alloc::format!("DHCP: failed in {:?}", current_state)),
+        _ => {}
+    }
+    Status::SUCCESS
+}
 
-    if status.is_error() {
-        println!("    Failed to create DHCP4 child: {:?}", status);
-        return Err(Error::Uefi(status));
+/// Where a lease was obtained from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseSource {
+    /// Our own DHCP4 exchange
+    Dhcp,
+    /// Reused from the firmware's PXE Base Code protocol
+    Pxe,
+}
+
+/// Network parameters learned from a DHCP exchange
+#[derive(Debug, Clone)]
+pub struct Lease {
+    /// Where the lease came from
+    pub source: LeaseSource,
+    /// Address assigned to this machine
+    pub client_ip: [u8; 4],
+    /// Subnet mask
+    pub subnet_mask: [u8; 4],
+    /// DHCP or boot server that answered
+    pub server_ip: [u8; 4],
+    /// Default gateway, if offered
+    pub router: Option<[u8; 4]>,
+    /// Boot file name from the reply (may be empty)
+    pub boot_file: heapless::String<128>,
+}
+
+/// Most recent lease, shared with diagnostics
+static mut LAST_LEASE: Option<Lease> = None;
+
+/// Remember the lease currently in use
+pub fn record_lease(lease: Lease) {
+    unsafe {
+        LAST_LEASE = Some(lease);
     }
+}
 
-    let child_handle = unsafe { Handle::from_ptr(child_handle_raw) }.ok_or(Error::Unknown)?;
-    println!("    Created DHCP4 child instance");
+/// Get the lease currently in use, if any
+pub fn last_lease() -> Option<&'static Lease> {
+    unsafe { LAST_LEASE.as_ref() }
+}
 
-    // Step 4: Open DHCP4 Protocol on child handle
-    let mut dhcp4_ptr: *mut Dhcp4Protocol = ptr::null_mut();
+/// IPv6 address obtained through router advertisements or DHCPv6
+#[derive(Debug, Clone, Copy)]
+pub struct Lease6 {
+    pub address: Ipv6,
+    pub prefix_length: u8,
+}
 
-    let status = unsafe {
-        let system_table = uefi::table::system_table_raw().unwrap();
-        let boot_services = (*system_table.as_ptr()).boot_services;
-        ((*boot_services).open_protocol)(
-            child_handle.as_ptr(),
-            &DHCP4_PROTOCOL_GUID as *const Guid as *const uefi_raw::Guid,
-            &mut dhcp4_ptr as *mut *mut Dhcp4Protocol as *mut *mut core::ffi::c_void,
-            boot::image_handle().as_ptr(),
-            ptr::null_mut(),
-            0x02, // GET_PROTOCOL
-        )
+/// Most recent IPv6 address, shared with diagnostics
+static LAST_LEASE6: Global<Lease6> = Global::new();
+
+/// Get the IPv6 address currently in use, if any
+pub fn last_lease6() -> Option<Lease6> {
+    LAST_LEASE6.with(|lease| *lease)
+}
+
+/// Discovery runs before DHCP gives up, for NAKs and unanswered requests
+const MAX_DISCOVERY_ATTEMPTS: u32 = 3;
+
+/// How long to wait for IPv6 autoconfiguration
+const DHCP6_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// First global (not link-local) address of an interface
+pub fn global_address(nic_handle: Handle) -> Option<Lease6> {
+    let addresses = ip6config::query(nic_handle).ok()?;
+    addresses
+        .iter()
+        .find(|a| !a.address.is_unspecified() && !a.address.is_link_local())
+        .map(|a| Lease6 { address: a.address, prefix_length: a.prefix_length })
+}
+
+/// Configure IPv6 on a network interface
+///
+/// The firmware's IP6 driver runs SLAAC and, when the routers announce
+/// managed addresses, DHCPv6 under its automatic policy; this selects that
+/// policy and waits for a global address.
+pub fn configure_dhcp6(nic_handle: Handle) -> Result<Lease6> {
+    say!("  Configuring IPv6 (SLAAC/DHCPv6)...");
+    let lease = match global_address(nic_handle) {
+        Some(lease) => lease,
+        None => {
+            ip6config::set_automatic(nic_handle)?;
+            let mut waited = Duration::ZERO;
+            loop {
+                if let Some(lease) = global_address(nic_handle) {
+                    break lease;
+                }
+                if waited >= DHCP6_TIMEOUT {
+                    println!("    No IPv6 address after {} seconds (no router or DHCPv6 server?)", waited.as_secs());
+                    log_entry(log::Level::Warn, "DHCPv6: no global address");
+                    return Err(Error::Uefi(Status::TIMEOUT));
+                }
+                boot::stall(Duration::from_millis(100));
+                waited += Duration::from_millis(100);
+            }
+        }
     };
 
-    if status.is_error() {
-        println!("    Failed to open DHCP4 Protocol: {:?}", status);
-        return Err(Error::Uefi(status));
+    say!("    IPv6 address: {}/{}", lease.address, lease.prefix_length);
+    log_entry(log::Level::Info, &alloc::format!("IPv6 address {}/{}", lease.address, lease.prefix_length));
+    LAST_LEASE6.set(lease);
+    Ok(lease)
+}
+
+/// Configure DHCP on a network interface
+pub fn configure_dhcp(nic_handle: Handle) -> Result<()> {
+    say!("  Configuring DHCP...");
+
+    // Step 1: DHCP4 Service Binding Protocol handles, found at startup
+    let service_handles = caps::handles(Capability::Dhcp4);
+
+    if service_handles.is_empty() {
+        println!("    No DHCP4 Service Binding found");
+        return Err(Error::NotFound);
     }
 
-    println!("    Opened DHCP4 Protocol");
+    detail!("    Found {} DHCP4 Service Binding instance(s)", service_handles.len());
+
+    // Use the first service binding handle
+    let service_handle = service_handles[0];
+
+    // Step 2: Create DHCP4 child instance (destroyed when `child` drops)
+    let child = ServiceChild::create(service_handle, &DHCP4_SERVICE_BINDING_GUID).map_err(|e| {
+        println!("    Failed to create DHCP4 child: {}", e);
+        e
+    })?;
 
-    // Step 5: Configure DHCP4
-    let config = create_default_dhcp_config();
+    detail!("    Created DHCP4 child instance");
+    child.on_teardown(stop_child);
+
+    // Step 3: Open DHCP4 Protocol on child handle (closed before the child is destroyed)
+    let dhcp4 = RawProtocol::<Dhcp4Protocol>::open(child.handle(), &DHCP4_PROTOCOL_GUID).map_err(|e| {
+        println!("    Failed to open DHCP4 Protocol: {}", e);
+        e
+    })?;
+    let dhcp4_ptr = dhcp4.as_ptr();
+
+    detail!("    Opened DHCP4 Protocol");
+
+    // Step 4: Configure DHCP4; the callback records progress until `stop`
+    let policy = crate::storage::with_config(|c| c.dhcp_offer).unwrap_or(OfferPolicy::First);
+    let mut progress = Progress { policy, ..Progress::default() };
+    let config = create_default_dhcp_config(&mut progress);
 
     let status = unsafe {
-        ((*dhcp4_ptr).configure)(dhcp4_ptr, &config)
+        ((*dhcp4_ptr).configure)(dhcp4_ptr, &config as *const Dhcp4ConfigData as *const dhcp4::Dhcp4ConfigData)
     };
 
     if status.is_error() {
@@ -117,57 +440,122 @@ pub fn configure_dhcp(nic_handle: Handle) -> Result<()> {
         return Err(Error::Uefi(status));
     }
 
-    println!("    DHCP4 configured");
+    detail!("    DHCP4 configured");
+
+    // Steps 5 and 6: run discovery (synchronous, no event) and wait for a
+    // lease, starting over when a server NAKs or the session ends
+    let mut result = Err(Error::Unknown);
+    for attempt in 1..=MAX_DISCOVERY_ATTEMPTS {
+        if attempt > 1 {
+            say!("    Restarting DHCP discovery (attempt {} of {})",
+                attempt, MAX_DISCOVERY_ATTEMPTS);
+            log_entry(log::Level::Info, &alloc::format!("DHCP: restarting discovery, attempt {}", attempt));
+            unsafe {
+                let _ = ((*dhcp4_ptr).stop)(dhcp4_ptr);
+            }
+            progress.best = None;
+        }
 
-    // Step 6: Start DHCP discovery (synchronous, no event)
-    let status = unsafe {
-        ((*dhcp4_ptr).start)(dhcp4_ptr, ptr::null_mut())
-    };
+        let status = unsafe {
+            ((*dhcp4_ptr).start)(dhcp4_ptr, ptr::null_mut())
+        };
 
-    if status.is_error() {
-        println!("    Failed to start DHCP4: {:?}", status);
-        return Err(Error::Uefi(status));
-    }
+        // Another DHCP4 instance on the interface already runs the exchange
+        // (e.g. the IP4 driver's DHCP policy); its lease shows up below
+        if status.is_error() && status != Status::ALREADY_STARTED {
+            println!("    DHCP4 discovery failed: {:?}", status);
+            result = Err(Error::Uefi(status));
+            // ABORTED follows a NAK; the others mean nobody answered in time
+            if matches!(status, Status::ABORTED | Status::NO_RESPONSE | Status::TIMEOUT) {
+                continue;
+            }
+            break;
+        }
 
-    println!("    DHCP4 discovery started");
+        detail!("    DHCP4 discovery started");
+        result = match poll_dhcp_completion(dhcp4_ptr, Duration::from_secs(30)) {
+            Ok(Poll::Bound(lease)) => Ok(lease),
+            Ok(Poll::Ended(state)) => {
+                println!("    DHCP session ended in state {:?}", state);
+                Err(Error::Uefi(Status::ABORTED))
+            }
+            Err(e) => Err(e),
+        };
+        if !matches!(result, Err(Error::Uefi(Status::ABORTED))) {
+            break;
+        }
+    }
 
-    // Step 7: Poll for DHCP completion
-    let result = poll_dhcp_completion(dhcp4_ptr, Duration::from_secs(30));
+    // Step 7: Stop the instance; `dhcp4` and `child` are released on return
+    unsafe {
+        let _ = ((*dhcp4_ptr).stop)(dhcp4_ptr);
+    }
 
     match result {
-        Ok(ip_addr) => {
-            println!("    DHCP completed successfully");
-            println!("    Assigned IP: {}.{}.{}.{}",
+        Ok(lease) => {
+            let ip_addr = lease.client_ip;
+            detail!("    DHCP completed successfully");
+            say!("    Assigned IP: {}.{}.{}.{}",
                 ip_addr[0], ip_addr[1], ip_addr[2], ip_addr[3]);
+            record_lease(lease);
             Ok(())
         }
         Err(e) => {
             println!("    DHCP failed: {}", e);
+            // Say which phase it got stuck in
+            match progress.state {
+                Some(state) => println!(
+                    "    Last state {:?}, {} offer(s) and {} NAK(s) received (see 'logs')",
+                    state, progress.offers, progress.naks
+                ),
+                None => println!("    The DHCP driver reported no progress"),
+            }
             Err(e)
         }
     }
 }
 
-/// Create default DHCP configuration
-fn create_default_dhcp_config() -> Dhcp4ConfigData {
+/// Stop the exchange on a DHCP4 child that teardown is about to destroy
+fn stop_child(child: Handle) {
+    if let Ok(dhcp4) = RawProtocol::<Dhcp4Protocol>::open(child, &DHCP4_PROTOCOL_GUID) {
+        unsafe {
+            let _ = ((*dhcp4.as_ptr()).stop)(dhcp4.as_ptr());
+        }
+    }
+}
+
+/// Create default DHCP configuration, reporting to `progress`
+fn create_default_dhcp_config(progress: &mut Progress) -> Dhcp4ConfigData {
     Dhcp4ConfigData {
         discover_try_count: 4,
         discover_timeout: ptr::null_mut(),
         request_try_count: 4,
         request_timeout: ptr::null_mut(),
-        client_address: uefi_raw::Ipv4Address([0, 0, 0, 0]),
-        callback: None,
-        callback_context: ptr::null_mut(),
+        client_address: Ipv4Address([0, 0, 0, 0]),
+        callback: Some(dhcp_callback),
+        callback_context: progress as *mut Progress as *mut c_void,
         option_count: 0,
         option_list: ptr::null_mut(),
     }
 }
 
-/// Poll DHCP state until BOUND or timeout
+/// How a DHCP exchange stands
+enum Poll {
+    /// A lease is held
+    Bound(Lease),
+    /// The driver ended the session without one (e.g. after a NAK)
+    Ended(Dhcp4State),
+}
+
+/// Poll DHCP state until a lease is held, the session ends, or timeout
+///
+/// RENEWING and REBINDING still hold a valid lease (the driver is only
+/// extending it), and INIT-REBOOT/REBOOTING are the driver confirming an
+/// earlier address, which falls back to a full discovery if refused.
 fn poll_dhcp_completion(
     dhcp4_ptr: *mut Dhcp4Protocol,
     timeout: Duration,
-) -> Result<[u8; 4]> {
+) -> Result<Poll> {
     let timeout_ms = timeout.as_millis() as u64;
     let poll_interval_ms = 100;
     let max_polls = timeout_ms / poll_interval_ms;
@@ -187,21 +575,90 @@ fn poll_dhcp_completion(
 
         // Check state
         match mode_data.state {
-            Dhcp4State::BOUND => {
+            Dhcp4State::BOUND | Dhcp4State::RENEWING | Dhcp4State::REBINDING => {
                 // Success!
-                return Ok(mode_data.client_address.0);
+                let router = mode_data.router_address.0;
+                return Ok(Poll::Bound(Lease {
+                    source: LeaseSource::Dhcp,
+                    client_ip: mode_data.client_address.0,
+                    subnet_mask: mode_data.subnet_mask.0,
+                    server_ip: mode_data.server_address.0,
+                    router: if router == [0; 4] { None } else { Some(router) },
+                    boot_file: heapless::String::new(),
+                }));
             }
-            Dhcp4State::INIT | Dhcp4State::SELECTING | Dhcp4State::REQUESTING => {
+            Dhcp4State::INIT
+            | Dhcp4State::SELECTING
+            | Dhcp4State::REQUESTING
+            | Dhcp4State::INIT_REBOOT
+            | Dhcp4State::REBOOTING => {
                 // Still in progress
                 boot::stall(Duration::from_millis(poll_interval_ms));
             }
-            _ => {
-                println!("    DHCP entered unexpected state: {:?}", mode_data.state);
-                return Err(Error::Unknown);
-            }
+            state => return Ok(Poll::Ended(state)),
         }
     }
 
     println!("    DHCP timeout after {} seconds", timeout.as_secs());
-    Err(Error::Unknown)
+    Err(Error::Uefi(Status::TIMEOUT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A DHCPOFFER of 10.0.0.50 from server 10.0.0.1
+    fn offer() -> alloc::vec::Vec<u8> {
+        let mut message = alloc::vec![0u8; OPTIONS_OFFSET];
+        message[0] = 2;
+        message[16..20].copy_from_slice(&[10, 0, 0, 50]);
+        message[236..240].copy_from_slice(&[99, 130, 83, 99]);
+        message.extend_from_slice(&[53, 1, 2, 0, 1, 4, 255, 255, 255, 0, 54, 4, 10, 0, 0, 1, 255]);
+        message
+    }
+
+    #[test]
+    fn test_offer_score() {
+        let plain = offer();
+        let server = OfferPolicy::Server(Ipv4([10, 0, 0, 1]));
+        assert_eq!(offer_score(OfferPolicy::First, &plain), BEST_SCORE);
+        assert_eq!(offer_score(OfferPolicy::Pxe, &plain), 0);
+        assert_eq!(offer_score(server, &plain), BEST_SCORE);
+        assert_eq!(offer_score(OfferPolicy::Server(Ipv4([10, 0, 0, 2])), &plain), 0);
+
+        // A boot server counts for something, a PXEClient vendor class wins
+        let mut boot_server = offer();
+        boot_server[SERVER_ADDRESS].copy_from_slice(&[10, 0, 0, 9]);
+        assert_eq!(offer_score(OfferPolicy::Pxe, &boot_server), 1);
+        let mut pxe = offer();
+        pxe.pop();
+        pxe.extend_from_slice(&[OPTION_VENDOR_CLASS, 9]);
+        pxe.extend_from_slice(b"PXEClient");
+        pxe.push(255);
+        assert_eq!(offer_score(OfferPolicy::Pxe, &pxe), BEST_SCORE);
+    }
+
+    #[test]
+    fn test_offer_policy() {
+        assert_eq!(OfferPolicy::parse("first"), Ok(OfferPolicy::First));
+        assert_eq!(OfferPolicy::parse("PXE"), Ok(OfferPolicy::Pxe));
+        assert_eq!(OfferPolicy::parse("10.0.0.1"), Ok(OfferPolicy::Server(Ipv4([10, 0, 0, 1]))));
+        assert!(OfferPolicy::parse("any").is_err());
+        assert_eq!(alloc::format!("{}", OfferPolicy::Server(Ipv4([10, 0, 0, 1]))), "10.0.0.1");
+    }
+
+    #[test]
+    fn test_offer_details() {
+        let message = offer();
+        assert_eq!(offered_address(&message), Some([10, 0, 0, 50]));
+        assert_eq!(server_id(&message), Some([10, 0, 0, 1]));
+
+        // No server identifier before the end option, or cut short
+        let mut ended = offer();
+        ended[OPTIONS_OFFSET + 3] = 255;
+        assert_eq!(server_id(&ended), None);
+        assert_eq!(server_id(&message[..message.len() - 3]), None);
+        assert_eq!(server_id(&[]), None);
+        assert_eq!(offered_address(&[]), None);
+    }
 }
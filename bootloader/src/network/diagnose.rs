@@ -0,0 +1,249 @@
+use crate::network::addr::{self, Ipv4};
+use crate::network::client::status_number;
+use crate::network::url::{self, Scheme};
+use crate::network::{dns, http, init, ip4config, session, tcp};
+use crate::util::{Error, Result};
+use alloc::format;
+use alloc::string::String;
+use core::time::Duration;
+use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams};
+use uefi::proto::network::snp::SimpleNetwork;
+use uefi::{println, Handle, Status};
+
+/// How long each network check waits for an answer
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Layers checked, from the bottom up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Link,
+    Address,
+    Dns,
+    Connect,
+    Http,
+}
+
+impl Layer {
+    /// Label in the check's output, padded so the results line up
+    fn label(&self) -> &'static str {
+        match self {
+            Layer::Link => "Link ........",
+            Layer::Address => "Address .....",
+            Layer::Dns => "DNS .........",
+            Layer::Connect => "TCP connect .",
+            Layer::Http => "HTTP HEAD ...",
+        }
+    }
+}
+
+/// What to look at when `layer` failed with `error`
+fn advice(layer: Layer, error: Error) -> &'static str {
+    match (layer, error) {
+        (Layer::Link, _) => {
+            "Check the cable and the switch port; on machines with several interfaces, select the cabled one with 'net use <index>'"
+        }
+        (Layer::Address, Error::Unsupported) => "The firmware has no DHCP client: set a static address with 'netsetup'",
+        (Layer::Address, _) => {
+            "No DHCP server answered: check the VLAN of the switch port and the DHCP server, or set a static address with 'netsetup'"
+        }
+        (Layer::Dns, Error::NotFound) => {
+            "The DNS server does not know this name: check the host in the entry URL, or use the server's IP address"
+        }
+        (Layer::Dns, Error::Unsupported) => "The firmware has no DNS client: use the server's IP address in the URL",
+        (Layer::Dns, _) => "No DNS server answered: check dns= in the config, or the DNS servers the DHCP server hands out",
+        (Layer::Connect, Error::Uefi(Status::CONNECTION_REFUSED)) => {
+            "Nothing accepts connections on that port: check that the server is running and the port in the URL"
+        }
+        (Layer::Connect, Error::Uefi(Status::NETWORK_UNREACHABLE | Status::HOST_UNREACHABLE)) => {
+            "No route to the server: check the gateway (see Network Status above) and the server's address"
+        }
+        (Layer::Connect, _) => "No answer from the server: a firewall may drop the connection, or the gateway cannot route to it",
+        (Layer::Http, Error::Unsupported) => "The firmware cannot make this request (no HTTP boot or TLS support)",
+        (Layer::Http, _) => {
+            "The request failed after connecting: for https:// URLs, check the server certificate against the CAs the firmware trusts, and its clock ('date')"
+        }
+    }
+}
+
+/// What to look at when the server answered the HEAD request with `code`
+/// (None when the answer is fine)
+fn status_advice(code: u16) -> Option<&'static str> {
+    match code {
+        200..=299 => None,
+        300..=399 => Some("The server redirects: boot follows up to 5 redirects, fine if the target is allowed"),
+        401 | 403 => Some("The server refuses access: seal credentials with 'auth seal <host>', or check its rules"),
+        404 | 410 => Some("The file is not on the server: check the path in the entry URL"),
+        405 | 501 => Some("The server does not answer HEAD requests; downloads may still work"),
+        500..=599 => Some("The server failed to answer: check its logs"),
+        _ => Some("Unexpected answer: check the server configuration"),
+    }
+}
+
+/// Print the result of one check; returns whether to go on
+fn report(layer: Layer, result: Result<String>) -> bool {
+    match result {
+        Ok(detail) => {
+            println!("  {} {}", layer.label(), detail);
+            true
+        }
+        Err(e) => {
+            println!("  {} FAILED: {}", layer.label(), e);
+            println!();
+            println!("  Suggestion: {}", advice(layer, e));
+            false
+        }
+    }
+}
+
+/// Check each layer between this machine and the default entry, stopping
+/// at the first one that fails with a suggestion what to look at
+pub fn run() -> Result<()> {
+    crate::util::ui::heading("Connectivity Check:");
+
+    let nic = init::select_interface()?;
+    if !report(Layer::Link, check_link(nic)) {
+        return Err(Error::NotFound);
+    }
+    if !report(Layer::Address, check_address()) {
+        return Err(Error::NotFound);
+    }
+
+    let Some(entry) = default_url() else {
+        println!("  No entries configured; add one to check the way to its server");
+        return Ok(());
+    };
+    println!("  Default entry: {}", entry);
+    let parsed = url::parse(&entry)?;
+    if matches!(parsed.scheme, Scheme::File) {
+        println!("  Local file, no server to check");
+        return Ok(());
+    }
+    if parsed.ipv6().is_some() || session::ipv6(&parsed) {
+        println!("  IPv6 server: DNS and TCP checks skipped, see the HTTP check");
+    } else {
+        let address = match addr::parse_ipv4(parsed.host) {
+            Ok(address) => address,
+            Err(_) => {
+                let resolved = dns::resolve(parsed.host, CHECK_TIMEOUT);
+                let result = resolved.map(|address| format!("{} is {}", parsed.host, address));
+                if !report(Layer::Dns, result) {
+                    return Err(Error::NotFound);
+                }
+                resolved?
+            }
+        };
+
+        // TFTP and NFS run over UDP, there is no connection to open
+        if matches!(parsed.scheme, Scheme::Http | Scheme::Https | Scheme::Iscsi) {
+            let result = match tcp::probe(session::nic()?, address.0, parsed.port, CHECK_TIMEOUT) {
+                Ok(()) => Ok(format!("{}:{} accepts connections", address, parsed.port)),
+                Err(Error::Unsupported) => Ok(String::from("not checked (the firmware has no TCP4 driver)")),
+                Err(e) => Err(e),
+            };
+            if !report(Layer::Connect, result) {
+                return Err(Error::Io);
+            }
+        }
+    }
+
+    if !matches!(parsed.scheme, Scheme::Http | Scheme::Https) {
+        println!("  Not an http(s):// entry, no HTTP check");
+        return Ok(());
+    }
+    let head = match http::head(&entry) {
+        Ok(head) => head,
+        Err(e) => {
+            report(Layer::Http, Err(e));
+            return Err(e);
+        }
+    };
+    let code = status_number(head.status);
+    let size = match head.content_length {
+        Some(length) => format!(", {} bytes", length),
+        None => String::new(),
+    };
+    println!("  {} HTTP {}{}", Layer::Http.label(), code, size);
+    if let Some(location) = head.location {
+        println!("                Redirected to {}", location);
+    }
+    if let Some(advice) = status_advice(code) {
+        println!();
+        println!("  Suggestion: {}", advice);
+        return if code >= 400 { Err(Error::Io) } else { Ok(()) };
+    }
+
+    println!();
+    println!("All checks passed");
+    Ok(())
+}
+
+/// URL of the entry a plain `boot` starts with
+fn default_url() -> Option<String> {
+    crate::storage::with_config(|config| {
+        let index = config.default_index.unwrap_or(0);
+        config.urls.get(index).map(|url| String::from(crate::storage::entry_url(url)))
+    })
+    .flatten()
+}
+
+/// Whether the interface sees a link, and its MAC address
+fn check_link(nic: Handle) -> Result<String> {
+    let snp = unsafe {
+        boot::open_protocol::<SimpleNetwork>(
+            OpenProtocolParams { handle: nic, agent: boot::image_handle(), controller: None },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+    .map_err(|e| Error::Uefi(e.status()))?;
+
+    let mode = snp.mode();
+    let m = mode.current_address.0;
+    let mac = format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", m[0], m[1], m[2], m[3], m[4], m[5]);
+    if !bool::from(mode.media_present_supported) {
+        return Ok(format!("{} (link state not reported by the driver)", mac));
+    }
+    if !bool::from(mode.media_present) {
+        return Err(Error::Uefi(Status::NO_MEDIA));
+    }
+    Ok(format!("{} up", mac))
+}
+
+/// Bring the network up and check that it got an address
+fn check_address() -> Result<String> {
+    let nic = session::nic()?;
+    let current = ip4config::query(nic)?;
+    if !current.is_configured() {
+        return Err(Error::Uefi(Status::NO_MAPPING));
+    }
+    let how = match current.policy {
+        ip4config::Policy::Static => "static",
+        ip4config::Policy::Dhcp => "DHCP",
+    };
+    Ok(format!("{} ({})", Ipv4(current.address), how))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advice() {
+        let refused = advice(Layer::Connect, Error::Uefi(Status::CONNECTION_REFUSED));
+        assert!(refused.contains("server is running"));
+        assert!(advice(Layer::Connect, Error::Uefi(Status::TIMEOUT)).contains("firewall"));
+        assert!(advice(Layer::Dns, Error::NotFound).contains("does not know"));
+        assert!(advice(Layer::Dns, Error::Uefi(Status::TIMEOUT)).contains("No DNS server answered"));
+        assert!(advice(Layer::Address, Error::Unsupported).contains("netsetup"));
+    }
+
+    #[test]
+    fn test_status_advice() {
+        assert_eq!(status_advice(200), None);
+        assert_eq!(status_advice(206), None);
+        assert!(status_advice(302).unwrap().contains("redirects"));
+        assert!(status_advice(403).unwrap().contains("auth seal"));
+        assert!(status_advice(404).unwrap().contains("path"));
+        assert!(status_advice(503).unwrap().contains("logs"));
+        assert!(status_advice(418).is_some());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::network::addr::Ipv4;
+use crate::network::child::{RawProtocol, ServiceChild};
+use crate::network::client::Completion;
+use crate::network::session;
+use crate::util::caps::{self, Capability};
+use crate::util::{ucs2, Error, Result};
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::ptr;
+use core::time::Duration;
+use uefi::boot;
+use uefi::{Guid, Handle, Status};
+use uefi_raw::{Boolean, Ipv4Address};
+
+/// DNS4 Service Binding Protocol GUID
+/// {B625B186-E063-44F7-8905-6A74DC6F52B4}
+const DNS4_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0x86, 0xb1, 0x25, 0xb6, 0x63, 0xe0, 0xf7, 0x44,
+    0x89, 0x05, 0x6a, 0x74, 0xdc, 0x6f, 0x52, 0xb4,
+]);
+
+/// DNS4 Protocol GUID
+/// {AE3D28CC-E05B-4FA1-A011-7EB55A3F1401}
+const DNS4_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0xcc, 0x28, 0x3d, 0xae, 0x5b, 0xe0, 0xa1, 0x4f,
+    0xa0, 0x11, 0x7e, 0xb5, 0x5a, 0x3f, 0x14, 0x01,
+]);
+
+#[repr(C)]
+struct Dns4CacheEntry {
+    host_name: *mut u16,
+    ip_address: *mut Ipv4Address,
+    /// Seconds until the entry expires
+    timeout: u32,
+}
+
+/// Transport protocol of the queries (UDP)
+const PROTOCOL_UDP: u8 = 17;
+
+/// Queries sent per server before a lookup fails, and the wait for each
+const RETRY_COUNT: u32 = 2;
+const RETRY_INTERVAL_SECS: u32 = 2;
+
+#[repr(C)]
+struct Dns4ConfigData {
+    dns_server_list_count: usize,
+    dns_server_list: *const Ipv4Address,
+    use_default_setting: Boolean,
+    enable_dns_cache: Boolean,
+    protocol: u8,
+    station_ip: Ipv4Address,
+    subnet_mask: Ipv4Address,
+    local_port: u16,
+    retry_count: u32,
+    retry_interval: u32,
+}
+
+#[repr(C)]
+struct HostToAddrData {
+    ip_count: u32,
+    /// Allocated by the driver, freed by the caller
+    ip_list: *mut Ipv4Address,
+}
+
+#[repr(C)]
+struct Dns4CompletionToken {
+    event: uefi_raw::Event,
+    status: Status,
+    retry_count: u32,
+    retry_interval: u32,
+    /// Allocated by the driver, freed by the caller
+    h2a_data: *mut HostToAddrData,
+}
+
+#[repr(C)]
+struct Dns4Protocol {
+    _get_mode_data: unsafe extern "efiapi" fn(this: *mut Dns4Protocol, mode: *mut c_void) -> Status,
+    configure: unsafe extern "efiapi" fn(this: *mut Dns4Protocol, config: *const Dns4ConfigData) -> Status,
+    host_name_to_ip:
+        unsafe extern "efiapi" fn(this: *mut Dns4Protocol, name: *const u16, token: *mut Dns4CompletionToken) -> Status,
+    _ip_to_host_name:
+        unsafe extern "efiapi" fn(this: *mut Dns4Protocol, address: Ipv4Address, token: *mut c_void) -> Status,
+    _general_lookup: unsafe extern "efiapi" fn(
+        this: *mut Dns4Protocol,
+        name: *const u8,
+        query_type: u16,
+        query_class: u16,
+        token: *mut c_void,
+    ) -> Status,
+    update_dns_cache: unsafe extern "efiapi" fn(
+        this: *mut Dns4Protocol,
+        delete: Boolean,
+        overwrite: Boolean,
+        entry: Dns4CacheEntry,
+    ) -> Status,
+    poll: unsafe extern "efiapi" fn(this: *mut Dns4Protocol) -> Status,
+    cancel: unsafe extern "efiapi" fn(this: *mut Dns4Protocol, token: *mut Dns4CompletionToken) -> Status,
+}
+
+/// How long a lookup ahead of a download waits for an answer
+pub const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the servers a lookup asks come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// `dns=` of the static setup
+    Static,
+    /// `dns=` without a static address, in place of the lease's servers
+    Config,
+    /// Whatever the interface has, usually from the DHCP server
+    Interface,
+}
+
+impl Source {
+    /// Label for the command output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Static => "static setup",
+            Source::Config => "dns= in config",
+            Source::Interface => "from DHCP",
+        }
+    }
+}
+
+/// Servers named in the config, and where; none means the interface's own
+fn configured() -> (Vec<Ipv4Address>, Source) {
+    let configured = crate::storage::with_config(|config| {
+        let (servers, source) = match config.static_ip.as_ref() {
+            Some(settings) => (&settings.dns, Source::Static),
+            None => (&config.dns_servers, Source::Config),
+        };
+        (servers.iter().map(|server| Ipv4Address(server.0)).collect::<Vec<_>>(), source)
+    });
+    match configured {
+        Some((servers, source)) if !servers.is_empty() => (servers, source),
+        _ => (Vec::new(), Source::Interface),
+    }
+}
+
+/// The servers lookups over `nic_handle` ask, and where they come from
+pub fn servers(nic_handle: Handle) -> Result<(Vec<Ipv4>, Source)> {
+    match configured() {
+        (servers, Source::Interface) => {
+            let servers = crate::network::ip4config::dns_servers(nic_handle)?;
+            Ok((servers.iter().copied().collect(), Source::Interface))
+        }
+        (servers, source) => Ok((servers.iter().map(|server| Ipv4(server.0)).collect(), source)),
+    }
+}
+
+/// Seconds a pinned name stays in the firmware's cache
+const PIN_TIMEOUT: u32 = 3600;
+
+/// Make the firmware resolve `name` to `address` without asking a server
+///
+/// The entry goes into the DNS4 driver's cache, which is consulted before
+/// any query is sent, so a URL can carry the name on a network without DNS.
+pub fn pin(name: &str, address: Ipv4) -> Result<()> {
+    caps::require(Capability::Dns4)?;
+    let child = ServiceChild::create(session::nic()?, &DNS4_SERVICE_BINDING_GUID)?;
+    let dns = RawProtocol::<Dns4Protocol>::open(child.handle(), &DNS4_PROTOCOL_GUID)?;
+
+    // The driver copies both the name and the address into its cache
+    let mut host_name = ucs2::encode_with_nul(name)?;
+    let mut ip_address = Ipv4Address(address.0);
+    let entry = Dns4CacheEntry {
+        host_name: host_name.as_mut_ptr(),
+        ip_address: &mut ip_address,
+        timeout: PIN_TIMEOUT,
+    };
+
+    let dns_ptr = dns.as_ptr();
+    let status = unsafe { ((*dns_ptr).update_dns_cache)(dns_ptr, Boolean::FALSE, Boolean::TRUE, entry) };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+    Ok(())
+}
+
+/// Look up the IPv4 address of `name`, giving up after `timeout`
+///
+/// Asks the servers of the static setup or `dns=`, or else those the DHCP
+/// lease named. A name no server knows fails with NotFound, no answer at
+/// all with Error::Uefi(TIMEOUT).
+pub fn resolve(name: &str, timeout: Duration) -> Result<Ipv4> {
+    caps::require(Capability::Dns4)?;
+    let child = ServiceChild::create(session::nic()?, &DNS4_SERVICE_BINDING_GUID)?;
+    let dns = RawProtocol::<Dns4Protocol>::open(child.handle(), &DNS4_PROTOCOL_GUID)?;
+    let dns_ptr = dns.as_ptr();
+
+    let (servers, _) = configured();
+    let config = Dns4ConfigData {
+        dns_server_list_count: servers.len(),
+        dns_server_list: if servers.is_empty() { ptr::null() } else { servers.as_ptr() },
+        use_default_setting: Boolean::TRUE,
+        enable_dns_cache: Boolean::TRUE,
+        protocol: PROTOCOL_UDP,
+        station_ip: Ipv4Address([0; 4]),
+        subnet_mask: Ipv4Address([0; 4]),
+        local_port: 0,
+        retry_count: RETRY_COUNT,
+        retry_interval: RETRY_INTERVAL_SECS,
+    };
+    let status = unsafe { ((*dns_ptr).configure)(dns_ptr, &config) };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+
+    let host_name = ucs2::encode_with_nul(name)?;
+    let completion = Completion::new()?;
+    let mut token = Dns4CompletionToken {
+        event: completion.0.as_ptr(),
+        status: Status::SUCCESS,
+        retry_count: RETRY_COUNT,
+        retry_interval: RETRY_INTERVAL_SECS,
+        h2a_data: ptr::null_mut(),
+    };
+    let status = unsafe { ((*dns_ptr).host_name_to_ip)(dns_ptr, host_name.as_ptr(), &mut token) };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+
+    let mut waited = Duration::ZERO;
+    while !completion.is_signaled() {
+        if waited >= timeout {
+            unsafe {
+                let _ = ((*dns_ptr).cancel)(dns_ptr, &mut token);
+            }
+            return Err(Error::Uefi(Status::TIMEOUT));
+        }
+        unsafe {
+            let _ = ((*dns_ptr).poll)(dns_ptr);
+        }
+        boot::stall(Duration::from_millis(10));
+        waited += Duration::from_millis(10);
+    }
+
+    let address = unsafe { take_first_address(token.h2a_data) };
+    match token.status {
+        Status::SUCCESS => address.ok_or(Error::NotFound),
+        Status::NOT_FOUND => Err(Error::NotFound),
+        status => Err(Error::Uefi(status)),
+    }
+}
+
+/// First address of a lookup answer, freeing what the driver allocated
+unsafe fn take_first_address(data: *mut HostToAddrData) -> Option<Ipv4> {
+    let data = ptr::NonNull::new(data)?;
+    let list = data.as_ref().ip_list;
+    let first = match data.as_ref().ip_count {
+        0 => None,
+        _ if list.is_null() => None,
+        _ => Some(Ipv4((*list).0)),
+    };
+    if let Some(list) = ptr::NonNull::new(list) {
+        let _ = boot::free_pool(list.cast());
+    }
+    let _ = boot::free_pool(data.cast());
+    first
+}
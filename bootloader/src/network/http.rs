@@ -1,109 +1,591 @@
+use crate::network::client::{
+    is_closed, next_chunk_size, status_number, HttpClient, RequestBody, ResponseHead, MIN_CHUNK,
+};
+use crate::network::session;
+use crate::network::url::{Scheme, Url};
+use crate::util::caps::{self, Capability};
+use crate::util::metrics::{Phase, Timer};
+use crate::util::wipe::Wiped;
 use crate::util::{Error, Result};
+use crate::{detail, say};
+use alloc::format;
+use alloc::string::String;
 use alloc::vec::Vec;
-use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams, SearchType};
-use uefi::proto::network::http::HttpHelper;
-use uefi::proto::network::snp::SimpleNetwork;
-use uefi::{println, Identify};
-use uefi_raw::protocol::network::http::HttpStatusCode;
+use core::time::Duration;
+use uefi::boot;
+use uefi::{println, Status};
+use uefi_raw::protocol::network::http::{HttpMethod, HttpStatusCode};
+
+/// Redirects followed before a download gives up
+const MAX_REDIRECTS: usize = 5;
+
+/// Times an interrupted download is resumed before it fails, unless
+/// `http_retries` says otherwise
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// Wait before the first resume, unless `http_backoff` says otherwise; it
+/// doubles with every further attempt
+pub const DEFAULT_BACKOFF_MS: u32 = 1000;
+
+/// Longest wait between two attempts
+const MAX_BACKOFF_MS: u64 = 30_000;
+
+/// Make sure the firmware can handle a request to this URL
+fn require_capabilities(url: &Url) -> Result<()> {
+    caps::require(Capability::Http)?;
+    if url.scheme == Scheme::Https {
+        caps::require(Capability::Tls)?;
+    }
+    if url.ipv6().is_some() && !crate::storage::with_config(|config| config.ip6).unwrap_or(false) {
+        println!("  IPv6 is off; set ip6=true in config.txt to reach {}", url.host);
+        return Err(Error::Unsupported);
+    }
+    if session::ipv6(url) {
+        caps::require(Capability::Ip6)?;
+    } else if crate::network::addr::parse_ipv4(url.host).is_err() {
+        caps::require(Capability::Dns4)?;
+    }
+    Ok(())
+}
+
+/// Host header value for `host` (the port only when given in the URL)
+fn host_header(url: &Url, host: &str) -> String {
+    if url.explicit_port {
+        format!("{}:{}", host, url.port)
+    } else {
+        String::from(host)
+    }
+}
+
+/// What a request asks the server for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fetch {
+    /// The body, from this offset on when set (GET)
+    Body(Option<usize>),
+    /// Only the status and headers (HEAD)
+    Head,
+}
+
+/// Validate a URL, bring up the network and send a GET request for it
+pub fn connect(url: &str) -> Result<HttpClient> {
+    open(url, Fetch::Body(None))
+}
+
+/// Validate a URL, bring up the network and send the request for it
+fn open(url: &str, fetch: Fetch) -> Result<HttpClient> {
+    // Validate the URL up front; the port is passed through to the firmware
+    // HTTP driver as part of the URL string
+    let parsed = crate::network::url::parse(url).map_err(|e| {
+        println!("  Invalid URL: {}", url);
+        e
+    })?;
+    if !matches!(parsed.scheme, Scheme::Http | Scheme::Https) {
+        println!("  Unsupported scheme for HTTP download: {}", parsed.scheme.as_str());
+        return Err(Error::InvalidArgument);
+    }
+    detail!("  Server: {}:{}", parsed.host, parsed.port);
+    require_capabilities(&parsed)?;
+    if !session::ipv6(&parsed) && crate::network::addr::parse_ipv4(parsed.host).is_err() {
+        resolve_ahead(parsed.host)?;
+    }
+
+    // The entry may name the virtual host a server reached by address expects
+    let Some(name) = crate::storage::with_config(|c| c.host_override(url).map(String::from)).flatten() else {
+        return request(url, &parsed, host_header(&parsed, parsed.host), fetch);
+    };
+    detail!("  Presenting host: {}", name);
+    let host = host_header(&parsed, &name);
+    match (parsed.scheme, crate::network::addr::parse_ipv4(parsed.host)) {
+        // Over TLS the name has to be in the URL to reach SNI and the
+        // certificate check, so the firmware's resolver learns the address
+        (Scheme::Https, Ok(address)) => {
+            crate::network::dns::pin(&name, address)?;
+            let named = format!("https://{}{}", host, parsed.path);
+            request(&named, &crate::network::url::parse(&named)?, host, fetch)
+        }
+        _ => request(url, &parsed, host, fetch),
+    }
+}
+
+/// Look the server's name up before the firmware's HTTP driver does, so a
+/// DNS failure is reported as one rather than as a failed connection
+///
+/// The address is pinned in the firmware's resolver cache, where the HTTP
+/// driver finds it even when the answer came from `dns=` servers it does
+/// not know about.
+fn resolve_ahead(host: &str) -> Result<()> {
+    use crate::network::dns;
+
+    match dns::resolve(host, dns::LOOKUP_TIMEOUT) {
+        Ok(address) => {
+            detail!("  Resolved {} to {}", host, address);
+            let _ = dns::pin(host, address);
+            Ok(())
+        }
+        Err(Error::NotFound) => {
+            println!("  DNS: no server knows {} (check the host name, or 'resolve {}')", host, host);
+            Err(Error::NotFound)
+        }
+        Err(Error::Uefi(Status::TIMEOUT)) => {
+            println!("  DNS: no answer looking up {} (check dns= or the servers DHCP hands out)", host);
+            Err(Error::Uefi(Status::TIMEOUT))
+        }
+        Err(e) => {
+            println!("  DNS lookup of {} failed: {}", host, e);
+            Err(e)
+        }
+    }
+}
+
+/// Send a request for a validated URL, presenting `host`
+fn request(url: &str, parsed: &Url, host: String, fetch: Fetch) -> Result<HttpClient> {
+    let credential = authorization(parsed);
+    let authorization = credential.as_deref().and_then(|value| core::str::from_utf8(value).ok());
+    let cookie = session::cookie_header(parsed);
+    send_request(parsed, |client| match fetch {
+        Fetch::Body(from) => client.get(url, &host, authorization, cookie.as_deref(), from),
+        Fetch::Head => client.head(url, &host, authorization, cookie.as_deref()),
+    })
+}
+
+/// Credential sealed for the server, which goes to it over TLS only
+fn authorization(url: &Url) -> Option<Wiped> {
+    match crate::storage::secrets::authorization(url.host) {
+        Some(_) if url.scheme != Scheme::Https => {
+            println!("  Not sending the sealed credential for {} over plain HTTP", url.host);
+            None
+        }
+        authorization => authorization,
+    }
+}
+
+/// Send a request with `send`, over the session's connection to the server
+/// when there is one
+fn send_request(url: &Url, send: impl Fn(&mut HttpClient) -> Result<()>) -> Result<HttpClient> {
+    // Network comes up on first use; an earlier transfer from the same
+    // server leaves its HTTP instance (and connection) in the session
+    let (mut client, reused) = session::checkout(url)?;
+    if reused {
+        detail!("  Reusing connection");
+    }
+
+    // The firmware resolves the name and connects here
+    detail!("  Sending request...");
+    let timer = Timer::start();
+    let sent = match send(&mut client) {
+        Ok(()) => Ok(client),
+        // The server may have closed the idle connection; start afresh
+        Err(_) if reused => {
+            drop(client);
+            HttpClient::new(session::nic()?, session::ipv6(url)).and_then(|mut client| {
+                send(&mut client)?;
+                Ok(client)
+            })
+        }
+        Err(e) => Err(e),
+    };
+    timer.stop(Phase::Connect);
+    sent
+}
+
+/// Give back a client whose response was read exactly to its end
+pub fn release(url: &str, client: HttpClient) {
+    if let Ok(parsed) = crate::network::url::parse(url) {
+        session::checkin(&parsed, client);
+    }
+}
+
+/// Where a redirect from `from` leads, if following it is allowed
+///
+/// The target has to pass the boot policy like any entry URL, and an HTTPS
+/// download never continues over plain HTTP.
+fn redirect_target(from: &str, location: &str) -> Result<String> {
+    let target = crate::network::url::resolve(from, location).map_err(|e| {
+        println!("  Unusable redirect target: {}", location);
+        e
+    })?;
+
+    let scheme = |url: &str| crate::network::url::parse(url).map(|parsed| parsed.scheme);
+    if scheme(from)? == Scheme::Https && scheme(&target)? != Scheme::Https {
+        println!("  Refusing redirect from HTTPS to {}", target);
+        return Err(Error::SecurityViolation);
+    }
+    if let Err(violation) = crate::storage::policy::check_url(&target) {
+        println!("  Redirect to {} refused by boot policy: {}", target, violation.as_str());
+        return Err(Error::SecurityViolation);
+    }
+    Ok(target)
+}
 
 /// Download a file over HTTP
+///
+/// Redirects are followed (up to MAX_REDIRECTS), and cookies servers set on
+/// the way are sent back for the rest of the session. A transfer of known
+/// length that breaks off is resumed from the last byte received, with a
+/// growing pause before each attempt (`http_retries`, `http_backoff`).
 pub fn download(url: &str) -> Result<Vec<u8>> {
-    println!("Downloading: {}", url);
-
-    // Initialize network (attempts DHCP configuration if available)
-    let nic_handle = crate::network::init::initialize_network()?;
+    say!("Downloading: {}", url);
+    let mut current = String::from(url);
+    let mut redirects = 0;
 
-    // Create HTTP helper
-    println!("  Initializing HTTP...");
-    let mut http_helper = HttpHelper::new(nic_handle).map_err(|e| Error::Uefi(e.status()))?;
+    let (mut client, mut data, head) = loop {
+        let mut client = connect(&current)?;
 
-    // Configure HTTP protocol with defaults (IPv4, HTTP/1.0, 10s timeout)
-    println!("  Configuring HTTP...");
-    http_helper
-        .configure()
-        .map_err(|e| Error::Uefi(e.status()))?;
+        // Receive response (status, headers and the first part of the body)
+        detail!("  Receiving response...");
+        // Whatever arrived is wiped if the transfer is abandoned
+        let mut data = Wiped::default();
+        let timer = Timer::start();
+        let head = client.response_head(&mut data, MIN_CHUNK)?;
+        timer.stop(Phase::Connect);
+        session::store_cookies(&crate::network::url::parse(&current)?, &head.set_cookies);
 
-    // Send GET request
-    println!("  Sending request...");
-    http_helper
-        .request_get(url)
-        .map_err(|e| Error::Uefi(e.status()))?;
+        let code = status_number(head.status);
+        if !matches!(code, 301 | 302 | 303 | 307 | 308) {
+            break (client, data, head);
+        }
+        let Some(location) = head.location.as_deref() else {
+            println!("  HTTP {} without a Location header", code);
+            return Err(Error::Io);
+        };
+        if redirects == MAX_REDIRECTS {
+            println!("  Too many redirects");
+            return Err(Error::Io);
+        }
+        redirects += 1;
 
-    // Receive response (expect body data)
-    println!("  Receiving response...");
-    let response = http_helper
-        .response_first(true)
-        .map_err(|e| Error::Uefi(e.status()))?;
+        let target = redirect_target(&current, location)?;
+        say!("  Redirected ({}) to {}", code, target);
+        // A redirect body read to its end leaves the connection reusable
+        if head.keeps_alive() && head.content_length == Some(data.len()) {
+            release(&current, client);
+        }
+        current = target;
+    };
 
     // Check HTTP status code
-    if response.status != HttpStatusCode::STATUS_200_OK {
-        println!("  HTTP error: status code {:?}", response.status);
+    if head.status != HttpStatusCode::STATUS_200_OK {
+        println!("  HTTP error: status code {}", status_number(head.status));
         return Err(Error::Io);
     }
 
-    // Start with initial body chunk
-    let mut data = response.body;
-    println!("  Downloaded {} bytes (initial chunk)", data.len());
-
-    // Get remaining chunks for larger files
-    // Only print progress every 10 chunks (~14KB) to reduce output
-    let mut chunk_count = 0;
-    const PROGRESS_INTERVAL: usize = 10;
+    let total = head.content_length;
+    if let Some(total) = total {
+        detail!("  Size: {} bytes", total);
+        crate::util::splash::expect(total);
+        data.try_reserve_exact(total.saturating_sub(data.len()))
+            .map_err(|_| Error::OutOfMemory)?;
+    }
 
+    // Receive the rest, growing the buffer while the link keeps it full
+    let (retries, backoff_ms) = retry_settings();
+    let mut attempts = 0;
+    let mut chunk = next_chunk_size(MIN_CHUNK, data.len());
+    let timer = Timer::start();
     loop {
-        match http_helper.response_more() {
-            Ok(chunk) => {
-                if chunk.is_empty() {
-                    break; // No more data
-                }
-                data.extend_from_slice(&chunk);
-                chunk_count += 1;
+        let want = match total {
+            Some(total) if data.len() >= total => break,
+            Some(total) => chunk.min(total - data.len()),
+            None => chunk,
+        };
+
+        let previous = data.len();
+        let mut error = match client.response_body(&mut data, want) {
+            Ok(0) if total.is_none() => break,
+            // The connection closed before the announced length
+            Ok(0) => Error::Io,
+            Ok(n) => {
+                chunk = next_chunk_size(chunk, n);
+                // Any progress earns a later break-off the full retries again
+                attempts = 0;
+                crate::util::ui::progress("Progress", data.len(), previous, total);
+                continue;
+            }
+            // Without a length or chunks, only the server closing the
+            // connection ends the body; a timeout or reset means it was cut short
+            Err(e) if !head.is_delimited() && is_closed(e) => break,
+            Err(e) => e,
+        };
+        crate::util::ui::progress_done();
+        println!("  Transfer interrupted after {} bytes: {}", data.len(), error);
 
-                // Print progress every N chunks
-                if chunk_count % PROGRESS_INTERVAL == 0 {
-                    println!("  Progress: {} bytes", data.len());
+        // Only a length to resume against gets here
+        let total = total.ok_or(error)?;
+        client = loop {
+            if attempts == retries {
+                println!("  Giving up after {} attempts to resume", retries);
+                return Err(error);
+            }
+            attempts += 1;
+            let delay = backoff(backoff_ms, attempts);
+            say!("  Resuming at byte {} in {} ms (attempt {} of {})", data.len(), delay, attempts, retries);
+            boot::stall(Duration::from_millis(delay));
+            match resume(&current, &mut data, total) {
+                Ok(client) => break client,
+                Err(e) => {
+                    println!("  Resume failed: {}", e);
+                    error = e;
                 }
             }
-            Err(_) => break, // No more data or error
+        };
+        chunk = MIN_CHUNK;
+    }
+    crate::util::ui::progress_done();
+    timer.stop(Phase::Download);
+
+    match total {
+        Some(total) if data.len() != total => {
+            println!("  Short download: {} of {} bytes", data.len(), total);
+            return Err(Error::Io);
+        }
+        // The connection is at a message boundary, keep it for the next request
+        _ if head.keeps_alive() => release(&current, client),
+        // Body ended with the connection, or the server is closing it
+        _ => drop(client),
+    }
+
+    say!("  Download complete: {} bytes total", data.len());
+    Ok(data.into_inner())
+}
+
+/// Retry count and first backoff (ms) for interrupted downloads
+fn retry_settings() -> (u32, u32) {
+    let (retries, backoff_ms) =
+        crate::storage::with_config(|config| (config.http_retries, config.http_backoff)).unwrap_or_default();
+    (retries.unwrap_or(DEFAULT_RETRIES), backoff_ms.unwrap_or(DEFAULT_BACKOFF_MS))
+}
+
+/// Pause before resume attempt `attempt` (from 1): `first_ms`, doubling
+/// each time up to MAX_BACKOFF_MS
+fn backoff(first_ms: u32, attempt: u32) -> u64 {
+    let doublings = attempt.saturating_sub(1).min(16);
+    (u64::from(first_ms) << doublings).min(MAX_BACKOFF_MS)
+}
+
+/// Ask again for the rest of `url`, of which `data` holds the start
+///
+/// Returns the client to read the remaining body from. A server that
+/// ignores the Range header sends the whole file again, which then replaces
+/// what arrived before. A file that changed size in between is not resumed.
+fn resume(url: &str, data: &mut Wiped, total: usize) -> Result<HttpClient> {
+    let from = data.len();
+    let mut client = open(url, Fetch::Body(Some(from)))?;
+    let mut fresh = Wiped::default();
+    let head = client.response_head(&mut fresh, MIN_CHUNK)?;
+
+    match (status_number(head.status), head.content_range) {
+        (206, Some(range)) if range.start == from && range.total == Some(total) => {
+            data.extend_from_slice(&fresh);
+            Ok(client)
+        }
+        (200, _) if head.content_length == Some(total) => {
+            detail!("  Server does not resume, starting over");
+            *data = fresh;
+            Ok(client)
+        }
+        (code, _) => {
+            println!("  Unexpected answer to the resume request: HTTP {}", code);
+            Err(Error::Io)
         }
     }
+}
 
-    println!("  Download complete: {} bytes total", data.len());
-    Ok(data)
+/// HTTP methods supported for uploads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadMethod {
+    Put,
+    Post,
 }
 
-/// Test if network is available
-pub fn test_network() -> Result<()> {
-    // Check if we have a network interface
-    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleNetwork::GUID))
-        .map_err(|e| Error::Uefi(e.status()))?;
+impl UploadMethod {
+    fn to_raw(self) -> HttpMethod {
+        match self {
+            UploadMethod::Put => HttpMethod::PUT,
+            UploadMethod::Post => HttpMethod::POST,
+        }
+    }
 
-    if handles.is_empty() {
-        println!("No network interfaces found");
-        return Err(Error::NotFound);
+    /// Method name as sent on the wire
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UploadMethod::Put => "PUT",
+            UploadMethod::Post => "POST",
+        }
     }
+}
 
-    println!("Found {} network interface(s)", handles.len());
+/// What an upload sends
+#[derive(Debug, Clone, Copy)]
+pub enum Body<'a> {
+    /// Text built in memory (configuration, diagnostics), sent as text/plain
+    Text(&'a [u8]),
+    /// A file on the ESP, read and sent UPLOAD_CHUNK bytes at a time
+    File(&'a str),
+}
 
-    // Get MAC address of first interface
-    if let Some(&handle) = handles.first() {
-        let snp = unsafe {
-            boot::open_protocol::<SimpleNetwork>(
-                OpenProtocolParams {
-                    handle,
-                    agent: boot::image_handle(),
-                    controller: None,
-                },
-                OpenProtocolAttributes::GetProtocol,
-            )
-            .map_err(|e| Error::Uefi(e.status()))?
-        };
+/// Bytes of an ESP file read and sent per call
+const UPLOAD_CHUNK: usize = 64 * 1024;
 
-        let mode = snp.mode();
-        let mac = mode.current_address.0;
-        println!("MAC address: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
-            mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
+/// Upload to a URL with PUT or POST
+///
+/// The URL has to pass the boot policy, and the server's sealed credential
+/// goes along as for downloads. Any 2xx status counts as success; for
+/// anything else the status and the start of the server's answer are
+/// shown.
+pub fn upload(url: &str, method: UploadMethod, body: Body) -> Result<()> {
+    let parsed = crate::network::url::parse(url)?;
+    if !matches!(parsed.scheme, Scheme::Http | Scheme::Https) {
+        println!("  Unsupported scheme for HTTP upload: {}", parsed.scheme.as_str());
+        return Err(Error::InvalidArgument);
+    }
+    if let Err(violation) = crate::storage::policy::check_url(url) {
+        println!("  Refused by boot policy: {}", violation.as_str());
+        return Err(Error::SecurityViolation);
     }
 
-    Ok(())
+    let (mut file, text) = match body {
+        Body::Text(text) => (None, text),
+        Body::File(path) => (Some(crate::storage::file::Reader::open(path)?), &[][..]),
+    };
+    let (content_type, length) = match &file {
+        Some(file) => ("application/octet-stream", usize::try_from(file.size()).map_err(|_| Error::OutOfMemory)?),
+        None => ("text/plain", text.len()),
+    };
+    say!("Uploading {} bytes ({}): {}", length, method.as_str(), url);
+    require_capabilities(&parsed)?;
+
+    // Text goes out with the headers; a file starts with its first chunk
+    let mut chunk = Vec::new();
+    let first = match file.as_mut() {
+        Some(file) => {
+            chunk.resize(UPLOAD_CHUNK.min(length), 0);
+            let read = file.read(&mut chunk)?;
+            chunk.truncate(read);
+            &chunk[..]
+        }
+        None => text,
+    };
+
+    let credential = authorization(&parsed);
+    let authorization = credential.as_deref().and_then(|value| core::str::from_utf8(value).ok());
+    let host = host_header(&parsed, parsed.host);
+    let request = RequestBody { content_type, length, first };
+    let mut client = send_request(&parsed, |client| client.send(method.to_raw(), url, &host, authorization, &request))?;
+
+    // The rest of a file follows a chunk at a time
+    if let Some(file) = file.as_mut() {
+        let mut sent = first.len();
+        chunk.resize(UPLOAD_CHUNK, 0);
+        while sent < length {
+            let read = file.read(&mut chunk)?;
+            if read == 0 {
+                crate::util::ui::progress_done();
+                println!("  File shrank while uploading: {} of {} bytes sent", sent, length);
+                return Err(Error::Io);
+            }
+            let part = read.min(length - sent);
+            client.send_body(&chunk[..part]).map_err(|e| {
+                crate::util::ui::progress_done();
+                println!("  Upload failed after {} bytes: {}", sent, e);
+                e
+            })?;
+            crate::util::ui::progress("Sent", sent + part, sent, Some(length));
+            sent += part;
+        }
+        crate::util::ui::progress_done();
+    }
+
+    detail!("  Receiving response...");
+    let mut answer = Wiped::default();
+    let head = client.response_head(&mut answer, MIN_CHUNK)?;
+    let code = status_number(head.status);
+
+    // A short answer read to its end leaves the connection reusable
+    if head.keeps_alive() && head.content_length == Some(answer.len()) {
+        release(url, client);
+    }
+
+    match upload_error(code) {
+        None => {
+            say!("  Upload complete ({})", code);
+            Ok(())
+        }
+        Some(meaning) => {
+            println!("  HTTP error {}: {}", code, meaning);
+            if let Some(line) = first_line(&answer) {
+                println!("  Server said: {}", line);
+            }
+            Err(Error::Io)
+        }
+    }
+}
+
+/// Why an upload answered with `code` failed (None for success)
+fn upload_error(code: u16) -> Option<&'static str> {
+    match code {
+        200..=299 => None,
+        300..=399 => Some("redirected; uploads are not redirected, use the final URL"),
+        400 => Some("bad request"),
+        401 | 407 => Some("authentication required (seal a credential for the server)"),
+        403 => Some("forbidden"),
+        404 => Some("not found"),
+        405 => Some("method not allowed; try the other of PUT and POST"),
+        411 => Some("the server wants a length"),
+        413 => Some("too large for the server"),
+        415 => Some("content type refused"),
+        400..=499 => Some("refused by the server"),
+        500..=599 => Some("server error"),
+        _ => Some("unexpected status"),
+    }
+}
+
+/// First line of a server's answer, if it is short text worth showing
+fn first_line(answer: &[u8]) -> Option<&str> {
+    let text = core::str::from_utf8(answer).ok()?;
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let end = line.char_indices().nth(100).map_or(line.len(), |(i, _)| i);
+    Some(&line[..end])
+}
+
+/// Status and headers the server answers a HEAD request for `url` with
+///
+/// Redirects are not followed; the Location header says where they lead.
+pub fn head(url: &str) -> Result<ResponseHead> {
+    let mut client = open(url, Fetch::Head)?;
+    let mut data = Wiped::default();
+    client.response_head(&mut data, MIN_CHUNK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1000, 1), 1000);
+        assert_eq!(backoff(1000, 2), 2000);
+        assert_eq!(backoff(1000, 4), 8000);
+        assert_eq!(backoff(1000, 10), MAX_BACKOFF_MS);
+        assert_eq!(backoff(u32::MAX, 40), MAX_BACKOFF_MS);
+        assert_eq!(backoff(0, 3), 0);
+    }
+
+    #[test]
+    fn test_upload_error() {
+        assert_eq!(upload_error(200), None);
+        assert_eq!(upload_error(204), None);
+        assert!(upload_error(301).is_some());
+        assert!(upload_error(405).unwrap().contains("PUT and POST"));
+        assert_eq!(upload_error(418), Some("refused by the server"));
+        assert_eq!(upload_error(503), Some("server error"));
+        assert_eq!(upload_error(0), Some("unexpected status"));
+    }
+
+    #[test]
+    fn test_first_line() {
+        assert_eq!(first_line(b"\r\n  Quota exceeded \r\nmore"), Some("Quota exceeded"));
+        assert_eq!(first_line(b""), None);
+        assert_eq!(first_line(&[0xff, 0xfe]), None);
+        assert_eq!(first_line("x".repeat(300).as_bytes()).map(str::len), Some(100));
+    }
 }
@@ -0,0 +1,63 @@
+use crate::util::global::Global;
+use alloc::string::String;
+use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams};
+use uefi::proto::device_path::{DeviceSubType, DeviceType, LoadedImageDevicePath};
+use uefi::proto::network::snp::SimpleNetwork;
+use uefi::Handle;
+
+/// What the firmware's HTTP Boot left behind when it started this image
+#[derive(Clone)]
+pub struct Origin {
+    /// URL this image was downloaded from
+    pub url: String,
+    /// Interface it came in over
+    pub nic: Option<Handle>,
+}
+
+/// Looked up once: unset = not yet, None = not started by HTTP Boot
+static ORIGIN: Global<Option<Origin>> = Global::new();
+
+/// The HTTP Boot that started this image, if it was one
+///
+/// The firmware appends a URI node with the download URL to the device
+/// path of the image it loads; an image from disk or TFTP has none.
+pub fn origin() -> Option<Origin> {
+    if ORIGIN.with(|_| ()).is_none() {
+        ORIGIN.set(detect());
+    }
+    ORIGIN.with(|origin| origin.clone()).flatten()
+}
+
+/// The HTTP Boot whose network setup is used as it is, with
+/// `reuse_http_boot=true` in the config
+pub fn reused() -> Option<Origin> {
+    let enabled = crate::storage::with_config(|config| config.reuse_http_boot).unwrap_or(false);
+    if enabled {
+        origin()
+    } else {
+        None
+    }
+}
+
+fn detect() -> Option<Origin> {
+    let path = unsafe {
+        boot::open_protocol::<LoadedImageDevicePath>(
+            OpenProtocolParams { handle: boot::image_handle(), agent: boot::image_handle(), controller: None },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+    .ok()?;
+
+    let url = path.node_iter().find_map(|node| {
+        let is_uri = node.device_type() == DeviceType::MESSAGING && node.sub_type() == DeviceSubType::MESSAGING_URI;
+        is_uri.then(|| core::str::from_utf8(node.data()).ok()).flatten()
+    })?;
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return None;
+    }
+
+    // The interface is the closest handle on the path with a network driver
+    let mut remaining: &uefi::proto::device_path::DevicePath = &path;
+    let nic = boot::locate_device_path::<SimpleNetwork>(&mut remaining).ok();
+    Some(Origin { url: String::from(url.trim_end_matches('\0')), nic })
+}
@@ -1,7 +1,15 @@
+use crate::util::caps::{self, Capability};
+use crate::{detail, say};
+use crate::util::metrics::{Phase, Timer};
 use crate::util::{Error, Result};
-use uefi::boot::{self, SearchType};
+use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams, SearchType};
 use uefi::proto::network::snp::SimpleNetwork;
-use uefi::{println, Guid, Handle, Identify};
+use crate::network::ip4config::{self, Policy, IP4_CONFIG2_PROTOCOL_GUID};
+use crate::network::addr::Ipv4;
+use crate::network::arp;
+use crate::network::child::RawProtocol;
+use crate::network::ip6config;
+use uefi::{print, println, CStr16, Char16, Guid, Handle, Status};
 
 /// DHCP4 Protocol GUID (from UEFI spec)
 /// {8A219718-4EF5-4761-91C8-C0F04BDA9E56}
@@ -10,32 +18,298 @@ const DHCP4_PROTOCOL_GUID: Guid = Guid::from_bytes([
     0x91, 0xc8, 0xc0, 0xf0, 0x4b, 0xda, 0x9e, 0x56,
 ]);
 
-/// IP4 Config2 Protocol GUID (from UEFI spec)
-/// {5B446ED1-E30B-4FAA-871A-3654ECA36080}
-const IP4_CONFIG2_PROTOCOL_GUID: Guid = Guid::from_bytes([
-    0xd1, 0x6e, 0x44, 0x5b, 0x0b, 0xe3, 0xaa, 0x4f,
-    0x87, 0x1a, 0x36, 0x54, 0xec, 0xa3, 0x60, 0x80,
+/// Component Name 2 Protocol GUID, installed by drivers to name themselves
+/// {6A7A5CFF-E8D9-4F70-BADA-75AB3025CE14}
+const COMPONENT_NAME2_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0xff, 0x5c, 0x7a, 0x6a, 0xd9, 0xe8, 0x70, 0x4f,
+    0xba, 0xda, 0x75, 0xab, 0x30, 0x25, 0xce, 0x14,
 ]);
 
+#[repr(C)]
+struct ComponentName2Protocol {
+    get_driver_name: unsafe extern "efiapi" fn(
+        this: *mut ComponentName2Protocol,
+        language: *const u8,
+        name: *mut *const Char16,
+    ) -> Status,
+    get_controller_name: unsafe extern "efiapi" fn(
+        this: *mut ComponentName2Protocol,
+        controller: uefi_raw::Handle,
+        child: uefi_raw::Handle,
+        language: *const u8,
+        name: *mut *const Char16,
+    ) -> Status,
+    /// RFC 4646 language codes, separated by `;`
+    supported_languages: *const u8,
+}
+
+/// A network interface and its MAC address
+pub struct Interface {
+    pub handle: Handle,
+    pub mac: [u8; 6],
+}
+
+/// List all network interfaces in firmware order
+pub fn list_interfaces() -> Result<alloc::vec::Vec<Interface>> {
+    Ok(caps::nics()
+        .iter()
+        .map(|&handle| {
+            let mut mac = [0u8; 6];
+            let snp = unsafe {
+                boot::open_protocol::<SimpleNetwork>(
+                    OpenProtocolParams {
+                        handle,
+                        agent: boot::image_handle(),
+                        controller: None,
+                    },
+                    OpenProtocolAttributes::GetProtocol,
+                )
+            };
+            if let Ok(snp) = snp {
+                mac.copy_from_slice(&snp.mode().current_address.0[..6]);
+            }
+            Interface { handle, mac }
+        })
+        .collect())
+}
+
+/// Whether an interface sees a link (None = its driver does not report it)
+pub fn link_state(handle: Handle) -> Option<bool> {
+    let snp = unsafe {
+        boot::open_protocol::<SimpleNetwork>(
+            OpenProtocolParams { handle, agent: boot::image_handle(), controller: None },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+    .ok()?;
+    let mode = snp.mode();
+    bool::from(mode.media_present_supported).then(|| bool::from(mode.media_present))
+}
+
+/// Name of the driver managing an interface
+///
+/// Asks every driver with a Component Name 2 protocol for the interface's
+/// name; the one that knows it manages the interface. Drivers that do not
+/// name their interfaces leave this None.
+pub fn driver_name(handle: Handle) -> Option<alloc::string::String> {
+    let drivers = boot::locate_handle_buffer(SearchType::ByProtocol(&COMPONENT_NAME2_PROTOCOL_GUID)).ok()?;
+    drivers.iter().find_map(|&driver| {
+        let names = RawProtocol::<ComponentName2Protocol>::open(driver, &COMPONENT_NAME2_PROTOCOL_GUID).ok()?;
+        let ptr = names.as_ptr();
+        unsafe {
+            let language = first_language((*ptr).supported_languages)?;
+            let mut controller: *const Char16 = core::ptr::null();
+            let child = core::ptr::null_mut();
+            let status = ((*ptr).get_controller_name)(ptr, handle.as_ptr(), child, language.as_ptr(), &mut controller);
+            if status.is_error() {
+                return None;
+            }
+            let mut name: *const Char16 = core::ptr::null();
+            let status = ((*ptr).get_driver_name)(ptr, language.as_ptr(), &mut name);
+            if status.is_error() || name.is_null() {
+                return None;
+            }
+            Some(alloc::format!("{}", CStr16::from_ptr(name)))
+        }
+    })
+}
+
+/// The first of a driver's `;`-separated languages, NUL-terminated
+unsafe fn first_language(languages: *const u8) -> Option<alloc::vec::Vec<u8>> {
+    if languages.is_null() {
+        return None;
+    }
+    let all = core::ffi::CStr::from_ptr(languages.cast()).to_bytes();
+    let mut language: alloc::vec::Vec<u8> = all.split(|&b| b == b';').next()?.to_vec();
+    if language.is_empty() {
+        return None;
+    }
+    language.push(0);
+    Some(language)
+}
+
+/// What checking the default gateway found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GatewayCheck {
+    /// No lease or static setup names a gateway
+    NoGateway,
+    /// The gateway answered ARP from this hardware address
+    Reachable(Ipv4, [u8; 6]),
+    /// The gateway did not answer ARP
+    Unreachable(Ipv4),
+    /// ARP could not be used to check (e.g. the firmware lacks it)
+    Unchecked(Ipv4),
+}
+
+/// Check that the gateway of the static setup or lease answers ARP
+///
+/// A lease whose gateway is down still reaches servers on the local subnet,
+/// so callers report this rather than fail.
+pub fn verify_gateway(nic_handle: Handle) -> GatewayCheck {
+    let settings = crate::storage::with_config(|c| c.static_ip.clone()).flatten();
+    let (station, gateway) = match (settings, crate::network::dhcp::last_lease()) {
+        (Some(settings), _) => (settings.address.0, settings.gateway.map(|g| g.0)),
+        (None, Some(lease)) => (lease.client_ip, lease.router),
+        (None, None) => return GatewayCheck::NoGateway,
+    };
+    let Some(gateway) = gateway else {
+        return GatewayCheck::NoGateway;
+    };
+
+    match arp::resolve(nic_handle, station, gateway, arp::RESOLVE_TIMEOUT) {
+        Ok(mac) => GatewayCheck::Reachable(Ipv4(gateway), mac),
+        Err(Error::Uefi(Status::TIMEOUT)) => {
+            crate::util::logger::log_entry(
+                log::Level::Warn,
+                &alloc::format!("Gateway {} did not answer ARP", Ipv4(gateway)),
+            );
+            GatewayCheck::Unreachable(Ipv4(gateway))
+        }
+        Err(_) => GatewayCheck::Unchecked(Ipv4(gateway)),
+    }
+}
+
+/// Pick the interface selected in the config (default: the first one)
+pub fn select_interface() -> Result<Handle> {
+    let (nic_mac, nic_index) = crate::storage::with_config(|c| (c.nic_mac, c.nic_index)).unwrap_or_default();
+    if let Some(mac) = nic_mac {
+        let interfaces = list_interfaces()?;
+        return interfaces.iter().find(|nic| nic.mac == mac.0).map(|nic| nic.handle).ok_or_else(|| {
+            println!("  Network interface {} not found ({} available, see 'net list')", mac, interfaces.len());
+            Error::NotFound
+        });
+    }
+
+    let handles = caps::nics();
+    let index = nic_index.unwrap_or(0);
+
+    handles.get(index).copied().ok_or_else(|| {
+        println!("  Network interface {} not found ({} available)", index, handles.len());
+        Error::NotFound
+    })
+}
+
 /// Initialize network interface with DHCP
 pub fn initialize_network() -> Result<Handle> {
-    println!("Initializing network...");
+    say!("Initializing network...");
+
+    // Started by HTTP Boot, the firmware already brought the network up
+    let http_boot_nic = crate::network::httpboot::reused().and_then(|origin| origin.nic);
+    if let Some(nic_handle) = http_boot_nic {
+        match ip4config::query(nic_handle) {
+            Ok(current) if current.is_configured() => {
+                say!("  Reusing HTTP Boot setup: {}", Ipv4(current.address));
+                detail!("  Network initialization complete");
+                return Ok(nic_handle);
+            }
+            _ => detail!("  HTTP Boot interface has no IPv4 address, setting it up"),
+        }
+    }
 
     // Find network interface handle
-    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleNetwork::GUID))
-        .map_err(|e| Error::Uefi(e.status()))?;
+    let nic_handle = match http_boot_nic {
+        Some(nic_handle) => nic_handle,
+        None => select_interface()?,
+    };
+
+    detail!("  Found network interface");
+
+    // IPv6 comes up next to IPv4; each URL is fetched over the family its
+    // host calls for
+    if crate::storage::with_config(|c| c.ip6).unwrap_or(false) && caps::require(Capability::Ip6).is_ok() {
+        let timer = Timer::start();
+        let result = crate::network::dhcp::configure_dhcp6(nic_handle);
+        timer.stop(Phase::Dhcp);
+        if let Err(e) = result {
+            println!("  IPv6 configuration failed: {}", e);
+        }
+    }
+
+    // A static setup from the config takes precedence over any lease
+    if let Some(settings) = crate::storage::with_config(|c| c.static_ip.clone()).flatten() {
+        let already_set = matches!(
+            ip4config::query(nic_handle),
+            Ok(current) if current.policy == Policy::Static && current.address == settings.address.0
+        );
+        if !already_set {
+            ip4config::set_static(nic_handle, &settings)?;
+        }
+        say!("  Using static address: {}", settings.address);
+        detail!("  Network initialization complete");
+        return Ok(nic_handle);
+    }
+
+    // If the firmware PXE-booted us over this interface, its DHCP lease is
+    // still valid
+    match crate::network::pxe::existing_lease(nic_handle) {
+        Ok(lease) => {
+            let ip = lease.client_ip;
+            say!("  Reusing PXE lease: {}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]);
+            if !lease.boot_file.is_empty() {
+                detail!("  PXE boot file: {}", lease.boot_file);
+            }
+            crate::network::dhcp::record_lease(lease);
+            warn_unreachable_gateway(nic_handle);
+            detail!("  Network initialization complete");
+            return Ok(nic_handle);
+        }
+        Err(Error::NotFound) => {}
+        Err(e) => println!("  Could not reuse the PXE lease: {}", e),
+    }
+
+    // Leave the interface alone if it already has a static or leased address
+    match ip4config::query(nic_handle) {
+        Ok(current) if current.is_configured() => {
+            let ip = current.address;
+            let how = match current.policy {
+                Policy::Static => "static",
+                Policy::Dhcp => "DHCP lease",
+            };
+            say!("  Using existing {} address: {}.{}.{}.{}", how, ip[0], ip[1], ip[2], ip[3]);
+            detail!("  Network initialization complete");
+            return Ok(nic_handle);
+        }
+        Ok(_) => {
+            detail!("  Interface has no address yet");
+        }
+        Err(e) => {
+            println!("  Could not read IP4 configuration: {}", e);
+        }
+    }
 
-    let nic_handle = handles
-        .first()
-        .copied()
-        .ok_or(Error::NotFound)?;
+    // Classic PXE discovery hears ProxyDHCP and boot servers too
+    if crate::storage::with_config(|c| c.pxe_discover).unwrap_or(false) {
+        let timer = Timer::start();
+        let result = crate::network::pxe::discover(nic_handle);
+        timer.stop(Phase::Dhcp);
+        match result {
+            Ok(lease) => {
+                say!("  Network configured via PXE: {}", Ipv4(lease.client_ip));
+                if !lease.boot_file.is_empty() {
+                    detail!("  PXE boot file: {} from {}", lease.boot_file, Ipv4(lease.server_ip));
+                }
+                crate::network::dhcp::record_lease(lease);
+                warn_unreachable_gateway(nic_handle);
+                detail!("  Network initialization complete");
+                return Ok(nic_handle);
+            }
+            Err(e) => println!("  PXE discovery failed: {}, trying DHCP", e),
+        }
+    }
 
-    println!("  Found network interface");
+    if !caps::has(Capability::Dhcp4) {
+        println!("  DHCP4 not available, configure a static address with 'netsetup'");
+        return Err(Error::Unsupported);
+    }
 
     // Try to configure DHCP on this interface
-    match crate::network::dhcp::configure_dhcp(nic_handle) {
+    let timer = Timer::start();
+    let result = crate::network::dhcp::configure_dhcp(nic_handle);
+    timer.stop(Phase::Dhcp);
+    match result {
         Ok(_) => {
-            println!("  Network configured successfully via DHCP");
+            say!("  Network configured successfully via DHCP");
+            warn_unreachable_gateway(nic_handle);
         }
         Err(e) => {
             println!("  DHCP configuration failed: {}", e);
@@ -43,11 +317,20 @@ pub fn initialize_network() -> Result<Handle> {
         }
     }
 
-    println!("  Network initialization complete");
+    detail!("  Network initialization complete");
 
     Ok(nic_handle)
 }
 
+/// Tell the technician when the lease's gateway does not answer, which
+/// otherwise only shows up as timeouts to off-subnet servers
+fn warn_unreachable_gateway(nic_handle: Handle) {
+    if let GatewayCheck::Unreachable(gateway) = verify_gateway(nic_handle) {
+        println!("  Warning: got a lease but the gateway {} is unreachable (no ARP reply)", gateway);
+        println!("  Only servers on the local subnet can be reached");
+    }
+}
+
 /// Simplified DHCP configuration attempt
 /// This uses the DHCP4 Service Binding to create a child instance
 fn configure_dhcp_simple(service_binding_handle: Handle) -> Result<()> {
@@ -73,18 +356,19 @@ fn configure_dhcp_simple(service_binding_handle: Handle) -> Result<()> {
 
 /// Try to get network status information
 pub fn check_network_status() -> Result<()> {
-    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleNetwork::GUID))
-        .map_err(|e| Error::Uefi(e.status()))?;
+    let handles = caps::nics();
 
     if handles.is_empty() {
         println!("No network interfaces found");
         return Err(Error::NotFound);
     }
 
-    println!("Network Status:");
-    println!("===============");
+    crate::util::ui::heading("Network Status:");
     println!("  Network interfaces: {}", handles.len());
 
+    // Everything below is about the interface `net use` or `nic=` picked
+    let nic_handle = select_interface().unwrap_or(handles[0]);
+
     // Check if DHCP protocol is available
     match boot::locate_handle_buffer(SearchType::ByProtocol(&DHCP4_PROTOCOL_GUID)) {
         Ok(dhcp_handles) => {
@@ -95,6 +379,22 @@ pub fn check_network_status() -> Result<()> {
         }
     }
 
+    // Report whether the firmware left a PXE lease we can reuse
+    match crate::network::pxe::existing_lease(nic_handle).ok() {
+        Some(lease) => {
+            let ip = lease.client_ip;
+            println!("  PXE lease: {}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]);
+        }
+        None => {
+            println!("  PXE lease: none");
+        }
+    }
+
+    match crate::network::httpboot::origin() {
+        Some(origin) => println!("  HTTP Boot: {}", origin.url),
+        None => println!("  HTTP Boot: no"),
+    }
+
     // Check if IP4 Config2 protocol is available
     match boot::locate_handle_buffer(SearchType::ByProtocol(&IP4_CONFIG2_PROTOCOL_GUID)) {
         Ok(ip_handles) => {
@@ -105,5 +405,54 @@ pub fn check_network_status() -> Result<()> {
         }
     }
 
+    // Show the current address of the selected interface
+    if let Ok(current) = ip4config::query(nic_handle) {
+        let ip = current.address;
+        println!("  IPv4 address: {}.{}.{}.{} ({:?})", ip[0], ip[1], ip[2], ip[3], current.policy);
+    }
+
+    // A lease is not much use if its gateway is down
+    match verify_gateway(nic_handle) {
+        GatewayCheck::NoGateway => println!("  Gateway: none"),
+        GatewayCheck::Reachable(gateway, m) => println!(
+            "  Gateway: {} reachable ({:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x})",
+            gateway, m[0], m[1], m[2], m[3], m[4], m[5]
+        ),
+        GatewayCheck::Unreachable(gateway) => {
+            println!("  Gateway: {} UNREACHABLE - got a lease but the gateway does not answer ARP", gateway);
+            println!("           (check the cabling, VLAN and router; on-link servers still work)");
+        }
+        GatewayCheck::Unchecked(gateway) => println!("  Gateway: {} (ARP not available, not checked)", gateway),
+    }
+
+    match crate::network::dns::servers(nic_handle) {
+        Ok((servers, _)) if servers.is_empty() => println!("  DNS servers: none"),
+        Ok((servers, source)) => {
+            print!("  DNS servers:");
+            for server in servers.iter() {
+                print!(" {}", server);
+            }
+            println!(" ({})", source.as_str());
+        }
+        Err(_) => println!("  DNS servers: unknown"),
+    }
+
+    // IPv6 is only brought up when asked for
+    if crate::storage::with_config(|c| c.ip6).unwrap_or(false) {
+        println!("  IPv6: on");
+        match ip6config::query(nic_handle) {
+            Ok(addresses) if addresses.is_empty() => println!("  IPv6 address: none"),
+            Ok(addresses) => {
+                for a in addresses.iter() {
+                    let scope = if a.address.is_link_local() { " (link-local)" } else { "" };
+                    println!("  IPv6 address: {}/{}{}", a.address, a.prefix_length, scope);
+                }
+            }
+            Err(_) => println!("  IP6Config protocol: not available"),
+        }
+    } else {
+        println!("  IPv6: off (ip6=true in config.txt enables it)");
+    }
+
     Ok(())
 }
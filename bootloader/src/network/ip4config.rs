@@ -0,0 +1,182 @@
+use crate::network::addr::Ipv4;
+use crate::network::child::RawProtocol;
+use crate::util::{Error, Result};
+use uefi::{Guid, Handle, Status};
+use crate::storage::config::StaticIp;
+use uefi_raw::protocol::network::ip4_config2::{
+    Ip4Config2DataType, Ip4Config2InterfaceInfo, Ip4Config2ManualAddress, Ip4Config2Policy,
+    Ip4Config2Protocol,
+};
+use uefi_raw::Ipv4Address;
+
+/// IP4 Config2 Protocol GUID (from UEFI spec)
+/// {5B446ED1-E30B-4FAA-871A-3654ECA36080}
+pub const IP4_CONFIG2_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0xd1, 0x6e, 0x44, 0x5b, 0x0b, 0xe3, 0xaa, 0x4f,
+    0x87, 0x1a, 0x36, 0x54, 0xec, 0xa3, 0x60, 0x80,
+]);
+
+/// Most DNS servers read back from an interface
+pub const MAX_INTERFACE_DNS_SERVERS: usize = 8;
+
+/// Address assignment policy of an interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Manually configured address
+    Static,
+    /// Address obtained by the firmware's DHCP client
+    Dhcp,
+}
+
+/// Current IPv4 configuration of an interface
+#[derive(Debug, Clone, Copy)]
+pub struct InterfaceConfig {
+    /// Address assignment policy
+    pub policy: Policy,
+    /// Station address (0.0.0.0 when unconfigured)
+    pub address: [u8; 4],
+    /// Subnet mask
+    pub subnet_mask: [u8; 4],
+}
+
+impl InterfaceConfig {
+    /// Whether the interface already has a usable address
+    pub fn is_configured(&self) -> bool {
+        self.address != [0; 4]
+    }
+}
+
+/// Open the IP4 Config2 protocol on a NIC handle
+fn open(nic_handle: Handle) -> Result<RawProtocol<Ip4Config2Protocol>> {
+    RawProtocol::open(nic_handle, &IP4_CONFIG2_PROTOCOL_GUID)
+}
+
+/// Read the current IPv4 policy and address of an interface
+pub fn query(nic_handle: Handle) -> Result<InterfaceConfig> {
+    let config = open(nic_handle)?;
+    let config_ptr = config.as_ptr();
+
+    // Policy
+    let mut policy = Ip4Config2Policy::STATIC;
+    let mut size = core::mem::size_of::<Ip4Config2Policy>();
+    let status = unsafe {
+        ((*config_ptr).get_data)(
+            config_ptr,
+            Ip4Config2DataType::POLICY,
+            &mut size,
+            &mut policy as *mut Ip4Config2Policy as *mut core::ffi::c_void,
+        )
+    };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+
+    // Interface info (variable size: the route table follows the struct)
+    let mut buf = [0u64; 128];
+    let mut size = core::mem::size_of_val(&buf);
+    let status = unsafe {
+        ((*config_ptr).get_data)(
+            config_ptr,
+            Ip4Config2DataType::INTERFACE_INFO,
+            &mut size,
+            buf.as_mut_ptr() as *mut core::ffi::c_void,
+        )
+    };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+
+    let info = unsafe { &*(buf.as_ptr() as *const Ip4Config2InterfaceInfo) };
+
+    Ok(InterfaceConfig {
+        policy: if policy == Ip4Config2Policy::DHCP {
+            Policy::Dhcp
+        } else {
+            Policy::Static
+        },
+        address: info.station_addr.0,
+        subnet_mask: info.subnet_mask.0,
+    })
+}
+
+/// DNS servers an interface uses: those of its static setup, or those the
+/// DHCP server handed out (empty when there are none)
+pub fn dns_servers(nic_handle: Handle) -> Result<heapless::Vec<Ipv4, MAX_INTERFACE_DNS_SERVERS>> {
+    let config = open(nic_handle)?;
+    let config_ptr = config.as_ptr();
+
+    let mut buf = [Ipv4Address([0; 4]); MAX_INTERFACE_DNS_SERVERS];
+    let mut size = core::mem::size_of_val(&buf);
+    let status = unsafe {
+        ((*config_ptr).get_data)(
+            config_ptr,
+            Ip4Config2DataType::DNS_SERVER,
+            &mut size,
+            buf.as_mut_ptr() as *mut core::ffi::c_void,
+        )
+    };
+    match status {
+        Status::NOT_FOUND => return Ok(heapless::Vec::new()),
+        status if status.is_error() => return Err(Error::Uefi(status)),
+        _ => {}
+    }
+
+    let count = (size / core::mem::size_of::<Ipv4Address>()).min(MAX_INTERFACE_DNS_SERVERS);
+    Ok(buf[..count].iter().map(|server| Ipv4(server.0)).collect())
+}
+
+/// Write one configuration item
+///
+/// Address changes complete asynchronously (duplicate address detection),
+/// which the driver reports as NOT_READY; that is not a failure.
+fn set_data<T>(config: &RawProtocol<Ip4Config2Protocol>, data_type: Ip4Config2DataType, data: &[T]) -> Result<()> {
+    let config_ptr = config.as_ptr();
+    let status = unsafe {
+        ((*config_ptr).set_data)(
+            config_ptr,
+            data_type,
+            core::mem::size_of_val(data),
+            data.as_ptr() as *const core::ffi::c_void,
+        )
+    };
+
+    if status.is_error() && status != Status::NOT_READY {
+        return Err(Error::Uefi(status));
+    }
+    Ok(())
+}
+
+/// Switch an interface to the firmware's DHCP client
+pub fn set_dhcp(nic_handle: Handle) -> Result<()> {
+    let config = open(nic_handle)?;
+    set_data(&config, Ip4Config2DataType::POLICY, &[Ip4Config2Policy::DHCP])
+}
+
+/// Give an interface a static address, gateway and DNS servers
+pub fn set_static(nic_handle: Handle, settings: &StaticIp) -> Result<()> {
+    let config = open(nic_handle)?;
+
+    // Switching the policy clears any previous (leased) address
+    set_data(&config, Ip4Config2DataType::POLICY, &[Ip4Config2Policy::STATIC])?;
+
+    let manual = Ip4Config2ManualAddress {
+        address: Ipv4Address(settings.address.0),
+        subnet_mask: Ipv4Address(settings.netmask.0),
+    };
+    set_data(&config, Ip4Config2DataType::MANUAL_ADDRESS, &[manual])?;
+
+    if let Some(gateway) = settings.gateway {
+        set_data(&config, Ip4Config2DataType::GATEWAY, &[Ipv4Address(gateway.0)])?;
+    }
+
+    if !settings.dns.is_empty() {
+        let mut servers: heapless::Vec<Ipv4Address, { crate::storage::config::MAX_DNS_SERVERS }> =
+            heapless::Vec::new();
+        for server in settings.dns.iter() {
+            let _ = servers.push(Ipv4Address(server.0));
+        }
+        set_data(&config, Ip4Config2DataType::DNS_SERVER, &servers)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use crate::network::addr::Ipv6;
+use crate::network::child::RawProtocol;
+use crate::util::{Error, Result};
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use uefi::{Guid, Handle, Status};
+
+/// IP6 Config Protocol GUID
+/// {937FE521-95AE-4D1A-8929-48BCD90AD31A}
+pub const IP6_CONFIG_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0x21, 0xe5, 0x7f, 0x93, 0xae, 0x95, 0x1a, 0x4d,
+    0x89, 0x29, 0x48, 0xbc, 0xd9, 0x0a, 0xd3, 0x1a,
+]);
+
+/// EFI_IP6_CONFIG_DATA_TYPE values used here
+const DATA_TYPE_INTERFACE_INFO: u32 = 0;
+const DATA_TYPE_POLICY: u32 = 2;
+
+/// EFI_IP6_CONFIG_POLICY: addresses from router advertisements and DHCPv6
+const POLICY_AUTOMATIC: u32 = 1;
+
+#[repr(C)]
+struct Ip6ConfigProtocol {
+    set_data: unsafe extern "efiapi" fn(
+        this: *mut Ip6ConfigProtocol,
+        data_type: u32,
+        data_size: usize,
+        data: *const c_void,
+    ) -> Status,
+    get_data: unsafe extern "efiapi" fn(
+        this: *mut Ip6ConfigProtocol,
+        data_type: u32,
+        data_size: *mut usize,
+        data: *mut c_void,
+    ) -> Status,
+    _register_data_notify:
+        unsafe extern "efiapi" fn(this: *mut Ip6ConfigProtocol, data_type: u32, event: uefi_raw::Event) -> Status,
+    _unregister_data_notify:
+        unsafe extern "efiapi" fn(this: *mut Ip6ConfigProtocol, data_type: u32, event: uefi_raw::Event) -> Status,
+}
+
+#[repr(C)]
+struct Ip6AddressInfo {
+    address: [u8; 16],
+    prefix_length: u8,
+}
+
+#[repr(C)]
+struct Ip6ConfigInterfaceInfo {
+    name: [u16; 32],
+    if_type: u8,
+    hw_address_size: u32,
+    hw_address: [u8; 32],
+    address_info_count: u32,
+    /// Points into the same buffer, after the struct
+    address_info: *const Ip6AddressInfo,
+    route_count: u32,
+    route_table: *const c_void,
+}
+
+/// An address assigned to an interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub address: Ipv6,
+    pub prefix_length: u8,
+}
+
+/// Open the IP6 Config protocol on a NIC handle
+fn open(nic_handle: Handle) -> Result<RawProtocol<Ip6ConfigProtocol>> {
+    RawProtocol::open(nic_handle, &IP6_CONFIG_PROTOCOL_GUID)
+}
+
+/// Addresses an interface has, link-local ones included
+pub fn query(nic_handle: Handle) -> Result<Vec<Address>> {
+    let config = open(nic_handle)?;
+    let config_ptr = config.as_ptr();
+
+    // Interface info (variable size: addresses and routes follow the struct)
+    let mut buf = [0u64; 256];
+    let mut size = core::mem::size_of_val(&buf);
+    let status = unsafe {
+        ((*config_ptr).get_data)(
+            config_ptr,
+            DATA_TYPE_INTERFACE_INFO,
+            &mut size,
+            buf.as_mut_ptr() as *mut c_void,
+        )
+    };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+
+    let info = unsafe { &*(buf.as_ptr() as *const Ip6ConfigInterfaceInfo) };
+    if info.address_info.is_null() {
+        return Ok(Vec::new());
+    }
+    let addresses = unsafe { core::slice::from_raw_parts(info.address_info, info.address_info_count as usize) };
+    Ok(addresses
+        .iter()
+        .map(|a| Address { address: Ipv6(a.address), prefix_length: a.prefix_length })
+        .collect())
+}
+
+/// Let the firmware configure IPv6 on its own: SLAAC from router
+/// advertisements, and DHCPv6 when the routers say addresses are managed
+///
+/// Selecting the policy the interface already has is refused as ABORTED;
+/// that is not a failure.
+pub fn set_automatic(nic_handle: Handle) -> Result<()> {
+    let config = open(nic_handle)?;
+    let config_ptr = config.as_ptr();
+    let policy = POLICY_AUTOMATIC;
+    let status = unsafe {
+        ((*config_ptr).set_data)(
+            config_ptr,
+            DATA_TYPE_POLICY,
+            core::mem::size_of_val(&policy),
+            &policy as *const u32 as *const c_void,
+        )
+    };
+
+    if status.is_error() && status != Status::NOT_READY && status != Status::ABORTED {
+        return Err(Error::Uefi(status));
+    }
+    Ok(())
+}
@@ -0,0 +1,450 @@
+use crate::network::addr::{self, Ipv4};
+use crate::network::child::RawProtocol;
+use crate::network::url::{self, Scheme};
+use crate::storage::config::{IscsiInitiator, StaticIp, MAX_IQN_LEN};
+use crate::storage::{self, file};
+use crate::util::global::Global;
+use crate::util::metrics::{Phase, Timer};
+use crate::util::{ucs2, Error, Result};
+use crate::say;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::ptr::{self, NonNull};
+use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams, SearchType};
+use uefi::proto::device_path::{DevicePath, DeviceSubType, DeviceType};
+use uefi::proto::media::fs::SimpleFileSystem;
+use uefi::{println, CStr16, Guid, Handle, Identify};
+use uefi_raw::{Char16, Status};
+
+/// iSCSI Initiator Name Protocol GUID (UEFI spec)
+/// {59324945-EC44-4C0D-B1CD-9DB139DF070C}
+const INITIATOR_NAME_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0x45, 0x49, 0x32, 0x59, 0x44, 0xec, 0x0d, 0x4c,
+    0xb1, 0xcd, 0x9d, 0xb1, 0x39, 0xdf, 0x07, 0x0c,
+]);
+
+/// Config Keyword Handler Protocol GUID (UEFI spec)
+/// {0A8BADD5-03B8-4D19-B128-7B8F0EDAA596}
+const KEYWORD_HANDLER_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0xd5, 0xad, 0x8b, 0x0a, 0xb8, 0x03, 0x19, 0x4d,
+    0xb1, 0x28, 0x7b, 0x8f, 0x0e, 0xda, 0xa5, 0x96,
+]);
+
+#[repr(C)]
+struct InitiatorNameProtocol {
+    _get: unsafe extern "efiapi" fn(this: *mut InitiatorNameProtocol, size: *mut usize, buffer: *mut c_void) -> Status,
+    set: unsafe extern "efiapi" fn(this: *mut InitiatorNameProtocol, size: *mut usize, buffer: *mut c_void) -> Status,
+}
+
+#[repr(C)]
+struct KeywordHandlerProtocol {
+    set_data: unsafe extern "efiapi" fn(
+        this: *mut KeywordHandlerProtocol,
+        keyword_string: *const Char16,
+        progress: *mut *const Char16,
+        progress_err: *mut u32,
+    ) -> Status,
+    get_data: unsafe extern "efiapi" fn(
+        this: *mut KeywordHandlerProtocol,
+        namespace_id: *const Char16,
+        keyword_string: *const Char16,
+        progress: *mut *const Char16,
+        progress_err: *mut u32,
+        results: *mut *mut Char16,
+    ) -> Status,
+}
+
+/// Keyword namespace of the standard HII keywords
+const NAMESPACE: &str = "x-UEFI-ns";
+
+/// Attempt slot of the firmware's iSCSI driver that uefipxe fills in
+const ATTEMPT: u8 = 1;
+
+/// Highest LUN that fits the flat addressing of the device path node
+const MAX_LUN: u16 = 0x3fff;
+
+/// An iSCSI target and LUN, from an `iscsi://` entry URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target<'a> {
+    pub address: Ipv4,
+    pub port: u16,
+    /// Target name (`iqn.`, `eui.` or `naa.` form)
+    pub name: &'a str,
+    pub lun: u16,
+}
+
+impl<'a> Target<'a> {
+    /// Parse `iscsi://<target ip>[:port]/<target name>[/<lun>]`
+    ///
+    /// The firmware's initiator connects to addresses, so the host has to be
+    /// an IPv4 address rather than a name.
+    pub fn parse(url: &'a str) -> Result<Self> {
+        let parsed = url::parse(url)?;
+        if parsed.scheme != Scheme::Iscsi {
+            return Err(Error::InvalidArgument);
+        }
+        let address = addr::parse_ipv4(parsed.host)?;
+
+        let path = &parsed.path[1..];
+        let (name, lun) = match path.split_once('/') {
+            Some((name, lun)) => (name, lun.parse::<u16>().map_err(|_| Error::InvalidArgument)?),
+            None => (path, 0),
+        };
+
+        let known_form = ["iqn.", "eui.", "naa."].iter().any(|prefix| name.starts_with(prefix));
+        if !known_form
+            || name.len() > MAX_IQN_LEN
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || ".-:".contains(c))
+            || lun > MAX_LUN
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(Target { address, port: parsed.port, name, lun })
+    }
+
+    /// LUN as the 8 bytes of an iSCSI device path node (SAM flat addressing)
+    pub fn lun_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        let [high, low] = self.lun.to_be_bytes();
+        bytes[0] = if self.lun > 0xff { 0x40 | high } else { 0 };
+        bytes[1] = low;
+        bytes
+    }
+}
+
+/// Target name and LUN of an iSCSI device path node's data
+///
+/// Layout (UEFI spec, Messaging subtype 19): protocol (2), login options
+/// (2), LUN (8), portal group tag (2), then the target name.
+pub fn parse_node(data: &[u8]) -> Option<([u8; 8], &str)> {
+    let lun: [u8; 8] = data.get(4..12)?.try_into().ok()?;
+    let name = data.get(14..)?;
+    let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    Some((lun, core::str::from_utf8(&name[..end]).ok()?))
+}
+
+/// A keyword value as the keyword handler expects it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+    /// String question: UCS-2 characters, 4 hex digits each, terminated
+    Text(&'a str),
+    /// Numeric or one-of question of `width` bytes, most significant first
+    Number(u64, usize),
+}
+
+impl Value<'_> {
+    pub fn encode(&self) -> Result<String> {
+        Ok(match *self {
+            Value::Text(text) => ucs2::encode_with_nul(text)?.iter().map(|unit| format!("{:04x}", unit)).collect(),
+            Value::Number(number, width) => format!("{:0digits$x}", number, digits = width * 2),
+        })
+    }
+}
+
+/// Keyword string for SetData, with an encoded value
+pub fn keyword_request(pathname: &str, keyword: &str, value: &str) -> String {
+    format!("NAMESPACE={}&PATHNAME={}&KEYWORD={}&VALUE={}", NAMESPACE, pathname, keyword, value)
+}
+
+/// Value of `name=` in a `&`-separated keyword response
+pub fn field<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    response.split('&').find_map(|part| part.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// Keywords and encoded values that describe `target` as attempt `ATTEMPT`
+///
+/// The initiator takes its own address from DHCP unless a static setup is
+/// configured, in which case that is used on the SAN as well.
+pub fn attempt_keywords(
+    target: &Target,
+    initiator: &IscsiInitiator,
+    mac: [u8; 6],
+    static_ip: Option<&StaticIp>,
+) -> Result<Vec<(String, String)>> {
+    let mut keywords = Vec::new();
+    let mut set = |keyword: &str, value: Value| -> Result<()> {
+        keywords.push((format!("{}:{}", keyword, ATTEMPT), value.encode()?));
+        Ok(())
+    };
+
+    let mac = format!("{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
+    set("iSCSIMacAddr", Value::Text(&mac))?;
+    set("iSCSIAttemptName", Value::Text("uefipxe"))?;
+    set("iSCSIBootEnable", Value::Number(1, 1))?;
+    set("iSCSIIpAddressType", Value::Number(0, 1))?;
+    match static_ip {
+        Some(ip) => {
+            set("iSCSIInitiatorInfoViaDHCP", Value::Number(0, 1))?;
+            set("iSCSIInitiatorIpAddress", Value::Text(&format!("{}", ip.address)))?;
+            set("iSCSIInitiatorNetmask", Value::Text(&format!("{}", ip.netmask)))?;
+            if let Some(gateway) = ip.gateway {
+                set("iSCSIInitiatorGateway", Value::Text(&format!("{}", gateway)))?;
+            }
+        }
+        None => set("iSCSIInitiatorInfoViaDHCP", Value::Number(1, 1))?,
+    }
+    set("iSCSITargetInfoViaDHCP", Value::Number(0, 1))?;
+    set("iSCSITargetName", Value::Text(target.name))?;
+    set("iSCSITargetIpAddress", Value::Text(&format!("{}", target.address)))?;
+    set("iSCSITargetTcpPort", Value::Number(u64::from(target.port), 2))?;
+    set("iSCSIBootLUN", Value::Text(&format!("{:x}", target.lun)))?;
+    if initiator.chap_user.is_empty() {
+        set("iSCSIAuthenticationMethod", Value::Number(0, 1))?;
+    } else {
+        set("iSCSIAuthenticationMethod", Value::Number(1, 1))?;
+        set("iSCSIChapType", Value::Number(1, 1))?;
+        set("iSCSIChapUsername", Value::Text(&initiator.chap_user))?;
+        set("iSCSIChapSecret", Value::Text(&initiator.chap_secret))?;
+    }
+    Ok(keywords)
+}
+
+/// Device path of the loader last read, for LoadImage
+///
+/// The loader finds the rest of the OS through its own device, so it has
+/// to be started with its real path even though it is loaded from memory.
+static LOADED_FROM: Global<(String, Box<DevicePath>)> = Global::new();
+
+/// Take the device path recorded when `url` was read
+pub fn take_device_path(url: &str) -> Option<Box<DevicePath>> {
+    match LOADED_FROM.take() {
+        Some((loaded_url, path)) if loaded_url == url => Some(path),
+        _ => None,
+    }
+}
+
+/// Attach the target of an `iscsi://` URL and read its removable-media loader
+pub fn read_loader(url: &str) -> Result<Vec<u8>> {
+    let target = Target::parse(url)?;
+    say!("Attaching iSCSI target {} LUN {} at {}:{}", target.name, target.lun, target.address, target.port);
+
+    let timer = Timer::start();
+    let mut volumes = volumes_of(&target);
+    if volumes.is_empty() {
+        if let Err(e) = configure(&target) {
+            println!("  Could not configure the firmware's initiator: {}", e);
+            println!("  Trying an attempt set up in firmware setup (iSCSI Configuration)");
+        }
+        connect_nics();
+        volumes = volumes_of(&target);
+    }
+    timer.stop(Phase::Connect);
+
+    if volumes.is_empty() {
+        println!("  Target not attached: check the address, name, LUN and CHAP settings");
+        return Err(Error::NotFound);
+    }
+
+    let loader = storage::path::removable_loader();
+    for (handle, device_path) in volumes {
+        let timer = Timer::start();
+        let data = match file::read_image_on(handle, loader) {
+            Ok(data) => data,
+            Err(Error::NotFound) => continue,
+            Err(e) => return Err(e),
+        };
+        timer.stop(Phase::Download);
+
+        say!("  Read {} ({} bytes)", loader, data.len());
+        let path = file::with_file(&device_path, loader)?;
+        LOADED_FROM.set((String::from(url), path));
+        return Ok(data);
+    }
+
+    println!("  No filesystem on the target has {}", loader);
+    Err(Error::NotFound)
+}
+
+/// Filesystems on the target's LUN, with their device paths
+fn volumes_of(target: &Target) -> Vec<(Handle, Box<DevicePath>)> {
+    let Ok(handles) = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID)) else {
+        return Vec::new();
+    };
+
+    handles
+        .iter()
+        .filter_map(|&handle| {
+            let device_path = unsafe {
+                boot::open_protocol::<DevicePath>(
+                    OpenProtocolParams {
+                        handle,
+                        agent: boot::image_handle(),
+                        controller: None,
+                    },
+                    OpenProtocolAttributes::GetProtocol,
+                )
+                .ok()?
+            };
+            let on_target = device_path.node_iter().any(|node| {
+                node.device_type() == DeviceType::MESSAGING
+                    && node.sub_type() == DeviceSubType::MESSAGING_ISCSI
+                    && parse_node(node.data()) == Some((target.lun_bytes(), target.name))
+            });
+            on_target.then(|| (handle, device_path.to_boxed()))
+        })
+        .collect()
+}
+
+/// Connect every NIC so the iSCSI driver logs in with its attempts
+fn connect_nics() {
+    for handle in crate::util::caps::nics() {
+        let _ = boot::connect_controller(handle, None, None, true);
+    }
+}
+
+/// Fill in the firmware's initiator settings for `target`
+fn configure(target: &Target) -> Result<()> {
+    let (initiator, static_ip) =
+        storage::with_config(|config| (config.iscsi.clone(), config.static_ip.clone())).ok_or(Error::Unknown)?;
+
+    if !initiator.name.is_empty() {
+        set_initiator_name(&initiator.name)?;
+    }
+
+    let nic = crate::network::init::select_interface()?;
+    let mac = crate::network::init::list_interfaces()?
+        .iter()
+        .find(|interface| interface.handle == nic)
+        .map(|interface| interface.mac)
+        .ok_or(Error::NotFound)?;
+
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&KEYWORD_HANDLER_PROTOCOL_GUID))
+        .map_err(|_| Error::Unsupported)?;
+    let handle = *handles.first().ok_or(Error::Unsupported)?;
+    let handler = RawProtocol::<KeywordHandlerProtocol>::open(handle, &KEYWORD_HANDLER_PROTOCOL_GUID)?;
+
+    let pathname = driver_pathname(handler.as_ptr())?;
+
+    // Adding fails harmlessly when the attempt exists from an earlier boot
+    let add = format!("{}", ATTEMPT);
+    let _ = set_data(handler.as_ptr(), &keyword_request(&pathname, "iSCSIAddAttempts", &Value::Text(&add).encode()?));
+    for (keyword, value) in attempt_keywords(target, &initiator, mac, static_ip.as_ref())? {
+        set_data(handler.as_ptr(), &keyword_request(&pathname, &keyword, &value)).map_err(|e| {
+            println!("  Firmware refused {}: {}", keyword, e);
+            e
+        })?;
+    }
+    Ok(())
+}
+
+fn set_initiator_name(name: &str) -> Result<()> {
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&INITIATOR_NAME_PROTOCOL_GUID))
+        .map_err(|_| Error::Unsupported)?;
+    let handle = *handles.first().ok_or(Error::Unsupported)?;
+    let protocol = RawProtocol::<InitiatorNameProtocol>::open(handle, &INITIATOR_NAME_PROTOCOL_GUID)?;
+
+    let mut buffer: Vec<u8> = name.bytes().chain(core::iter::once(0)).collect();
+    let mut size = buffer.len();
+    let status = unsafe { ((*protocol.as_ptr()).set)(protocol.as_ptr(), &mut size, buffer.as_mut_ptr().cast()) };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+    Ok(())
+}
+
+/// PATHNAME of the iSCSI driver's keyword store, asked from the handler
+/// rather than built, since the driver chooses its own device path
+fn driver_pathname(handler: *mut KeywordHandlerProtocol) -> Result<String> {
+    let namespace = ucs2::to_cstring16(NAMESPACE)?;
+    let request = ucs2::to_cstring16("KEYWORD=iSCSIInitiatorName")?;
+    let mut progress: *const Char16 = ptr::null();
+    let mut progress_err = 0u32;
+    let mut results: *mut Char16 = ptr::null_mut();
+
+    let status = unsafe {
+        ((*handler).get_data)(
+            handler,
+            namespace.as_ptr().cast(),
+            request.as_ptr().cast(),
+            &mut progress,
+            &mut progress_err,
+            &mut results,
+        )
+    };
+    if status.is_error() {
+        // NOT_FOUND here means the firmware has no iSCSI driver
+        return Err(Error::Uefi(status));
+    }
+    let results = NonNull::new(results).ok_or(Error::NotFound)?;
+
+    let text = String::from(unsafe { CStr16::from_ptr(results.as_ptr().cast()) });
+    let _ = unsafe { boot::free_pool(results.cast()) };
+    field(&text, "PATHNAME").map(String::from).ok_or(Error::Parse)
+}
+
+fn set_data(handler: *mut KeywordHandlerProtocol, request: &str) -> Result<()> {
+    let request = ucs2::to_cstring16(request)?;
+    let mut progress: *const Char16 = ptr::null();
+    let mut progress_err = 0u32;
+
+    let status = unsafe { ((*handler).set_data)(handler, request.as_ptr().cast(), &mut progress, &mut progress_err) };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let target = Target::parse("iscsi://10.0.0.5/iqn.2010-04.org.example:san").unwrap();
+        assert_eq!(target.address, Ipv4([10, 0, 0, 5]));
+        assert_eq!(target.port, 3260);
+        assert_eq!(target.name, "iqn.2010-04.org.example:san");
+        assert_eq!(target.lun, 0);
+
+        let target = Target::parse("iscsi://10.0.0.5:3261/iqn.2010-04.org.example:san/300").unwrap();
+        assert_eq!(target.port, 3261);
+        assert_eq!(target.lun, 300);
+        assert_eq!(target.lun_bytes(), [0x41, 0x2c, 0, 0, 0, 0, 0, 0]);
+
+        for bad in [
+            "http://10.0.0.5/iqn.2010-04.org.example:san",
+            "iscsi://san.example.com/iqn.2010-04.org.example:san",
+            "iscsi://10.0.0.5/san",
+            "iscsi://10.0.0.5/iqn.2010-04.org.example:san/x",
+            "iscsi://10.0.0.5/iqn.2010-04.org.example:san/16384",
+            "iscsi://10.0.0.5/iqn.2010-04.org.example:s an",
+        ] {
+            assert!(Target::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_node() {
+        let mut data = alloc::vec![0u8, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 1, 0];
+        data.extend_from_slice(b"iqn.2010-04.org.example:san\0");
+        assert_eq!(parse_node(&data), Some(([0, 7, 0, 0, 0, 0, 0, 0], "iqn.2010-04.org.example:san")));
+        assert_eq!(parse_node(&data[..10]), None);
+    }
+
+    #[test]
+    fn test_keywords() {
+        assert_eq!(Value::Text("Ab").encode().unwrap(), "004100620000");
+        assert_eq!(Value::Text("\u{1F680}").encode(), Err(Error::InvalidArgument));
+        assert_eq!(Value::Number(3260, 2).encode().unwrap(), "0cbc");
+        assert_eq!(Value::Number(1, 1).encode().unwrap(), "01");
+        assert_eq!(
+            keyword_request("0104", "iSCSIBootEnable:1", &Value::Number(1, 1).encode().unwrap()),
+            "NAMESPACE=x-UEFI-ns&PATHNAME=0104&KEYWORD=iSCSIBootEnable:1&VALUE=01"
+        );
+        assert_eq!(field("NAMESPACE=x-UEFI-ns&PATHNAME=0104&KEYWORD=k", "PATHNAME"), Some("0104"));
+        assert_eq!(field("NAMESPACE=x-UEFI-ns", "PATHNAME"), None);
+
+        let target = Target::parse("iscsi://10.0.0.5/iqn.2010-04.org.example:san/1").unwrap();
+        let keywords = attempt_keywords(&target, &IscsiInitiator::default(), [0; 6], None).unwrap();
+        let get = |name: &str| keywords.iter().find(|(keyword, _)| keyword == name).map(|(_, value)| value.as_str());
+        assert_eq!(get("iSCSITargetTcpPort:1"), Some("0cbc"));
+        assert_eq!(get("iSCSIBootLUN:1"), Some("00310000"));
+        assert_eq!(get("iSCSIInitiatorInfoViaDHCP:1"), Some("01"));
+        assert_eq!(get("iSCSIAuthenticationMethod:1"), Some("00"));
+        assert_eq!(get("iSCSIChapSecret:1"), None);
+    }
+}
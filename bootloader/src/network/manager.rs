@@ -0,0 +1,95 @@
+use crate::network::child::RawProtocol;
+use crate::util::global::Global;
+use crate::util::logger::log_entry;
+use alloc::vec::Vec;
+use uefi::{Guid, Handle};
+use uefi_raw::protocol::driver::ServiceBindingProtocol;
+
+/// A child this image created through a service binding
+struct Live {
+    service: Handle,
+    binding: &'static Guid,
+    child: Handle,
+    /// Quiesces the child's protocol (e.g. stops DHCP) before it goes
+    stop: Option<fn(Handle)>,
+}
+
+/// Children created and not yet destroyed, oldest first
+///
+/// Every `ServiceChild` is recorded here, so that nothing we set up is
+/// left running when an image starts or we exit: on some platforms a
+/// leftover DHCP child still configured on the interface breaks the next
+/// stage's own use of the UEFI network stack.
+static LIVE: Global<Vec<Live>> = Global::new();
+
+/// Run `f` with the recorded children, starting an empty list on first use
+fn with_live<R>(f: impl FnOnce(&mut Vec<Live>) -> R) -> R {
+    if LIVE.with(|_| ()).is_none() {
+        LIVE.set(Vec::new());
+    }
+    LIVE.with_mut(f).expect("list set above")
+}
+
+/// Record a child just created on `service`
+pub(crate) fn register(service: Handle, binding: &'static Guid, child: Handle) {
+    with_live(|live| live.push(Live { service, binding, child, stop: None }));
+}
+
+/// Have `stop` run on the child before teardown destroys it
+pub(crate) fn set_stop(child: Handle, stop: fn(Handle)) {
+    with_live(|live| {
+        if let Some(live) = live.iter_mut().find(|live| live.child == child) {
+            live.stop = Some(stop);
+        }
+    });
+}
+
+/// Forget a child its owner is destroying
+///
+/// Returns false when teardown has destroyed it already, in which case the
+/// handle must not be used again.
+pub(crate) fn unregister(child: Handle) -> bool {
+    with_live(|live| match live.iter().position(|live| live.child == child) {
+        Some(i) => {
+            live.remove(i);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Number of children currently alive, for status output
+pub fn live_count() -> usize {
+    LIVE.with(|live| live.len()).unwrap_or(0)
+}
+
+/// Release everything the network layer holds, before an image is started
+/// or control goes back to the firmware
+///
+/// The background download and the HTTP session let go of their instances
+/// first, through the usual drop path that closes their protocols. Children
+/// still recorded after that were leaked somewhere: they are stopped and
+/// destroyed here, newest first. The next transfer brings the interface up
+/// again if the image returns.
+pub fn teardown() {
+    crate::boot::prefetch::cancel();
+    crate::network::session::close();
+
+    let leaked = LIVE.take().unwrap_or_default();
+    for live in leaked.iter().rev() {
+        if let Some(stop) = live.stop {
+            stop(live.child);
+        }
+        if let Ok(binding) = RawProtocol::<ServiceBindingProtocol>::open(live.service, live.binding) {
+            unsafe {
+                let _ = ((*binding.as_ptr()).destroy_child)(binding.as_ptr(), live.child.as_ptr());
+            }
+        }
+    }
+    if !leaked.is_empty() {
+        log_entry(
+            log::Level::Warn,
+            &alloc::format!("Network: destroyed {} leftover child instance(s) at teardown", leaked.len()),
+        );
+    }
+}
@@ -1,6 +1,27 @@
+pub mod addr;
+pub mod agent;
+pub mod arp;
+pub mod child;
+pub mod client;
+pub mod cookie;
 pub mod dhcp;
+pub mod diagnose;
+pub mod dns;
 pub mod http;
+pub mod httpboot;
 pub mod init;
+pub mod ip4config;
+pub mod ip6config;
+pub mod iscsi;
+pub mod manager;
+pub mod nfs;
+pub mod packet;
+pub mod pxe;
+pub mod rpc;
+pub mod session;
+pub mod tcp;
+pub mod tftp;
+pub mod url;
 pub mod verify;
 
 use crate::util::Result;
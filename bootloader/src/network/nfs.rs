@@ -0,0 +1,580 @@
+use crate::network::addr::{self, Ipv4};
+use crate::network::child::{RawProtocol, ServiceChild};
+use crate::network::client::Completion;
+use crate::network::rpc::{self, Decoder, FileHandle};
+use crate::network::session;
+use crate::network::url::{self, Scheme};
+use crate::util::caps::{self, Capability, UDP4_SERVICE_BINDING_GUID};
+use crate::util::global::Global;
+use crate::util::metrics::{Phase, Timer};
+use crate::util::wipe::Wiped;
+use crate::util::{Error, Result};
+use crate::{detail, say};
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::ops::RangeInclusive;
+use core::ptr;
+use core::time::Duration;
+use uefi::boot;
+use uefi::{println, Event, Guid, Handle, Status};
+use uefi_raw::{Boolean, Ipv4Address};
+
+/// UDP4 Protocol GUID
+/// {3AD9DF29-4501-478D-B1F8-7F7FE70E50F3}
+const UDP4_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0x29, 0xdf, 0xd9, 0x3a, 0x01, 0x45, 0x8d, 0x47,
+    0xb1, 0xf8, 0x7f, 0x7f, 0xe7, 0x0e, 0x50, 0xf3,
+]);
+
+#[repr(C)]
+struct Udp4ConfigData {
+    accept_broadcast: Boolean,
+    accept_promiscuous: Boolean,
+    accept_any_port: Boolean,
+    allow_duplicate_port: Boolean,
+    type_of_service: u8,
+    time_to_live: u8,
+    do_not_fragment: Boolean,
+    receive_timeout: u32,
+    transmit_timeout: u32,
+    use_default_address: Boolean,
+    station_address: Ipv4Address,
+    subnet_mask: Ipv4Address,
+    station_port: u16,
+    remote_address: Ipv4Address,
+    remote_port: u16,
+}
+
+#[repr(C)]
+struct Udp4CompletionToken {
+    event: uefi_raw::Event,
+    status: Status,
+    /// Udp4ReceiveData or Udp4TransmitData
+    packet: *mut c_void,
+}
+
+#[repr(C)]
+struct Udp4FragmentData {
+    fragment_length: u32,
+    fragment_buffer: *mut c_void,
+}
+
+#[repr(C)]
+struct Udp4SessionData {
+    source_address: Ipv4Address,
+    source_port: u16,
+    destination_address: Ipv4Address,
+    destination_port: u16,
+}
+
+#[repr(C)]
+struct Udp4ReceiveData {
+    time_stamp: uefi_raw::time::Time,
+    recycle_signal: uefi_raw::Event,
+    udp_session: Udp4SessionData,
+    data_length: u32,
+    fragment_count: u32,
+    /// First of `fragment_count` entries
+    fragment_table: [Udp4FragmentData; 1],
+}
+
+#[repr(C)]
+struct Udp4TransmitData {
+    udp_session_data: *const Udp4SessionData,
+    gateway_address: *const Ipv4Address,
+    data_length: u32,
+    fragment_count: u32,
+    fragment_table: [Udp4FragmentData; 1],
+}
+
+#[repr(C)]
+struct Udp4Protocol {
+    _get_mode_data: unsafe extern "efiapi" fn(
+        this: *mut Udp4Protocol,
+        udp_config: *mut c_void,
+        ip_mode: *mut c_void,
+        mnp_config: *mut c_void,
+        snp_mode: *mut c_void,
+    ) -> Status,
+    configure: unsafe extern "efiapi" fn(this: *mut Udp4Protocol, config: *const Udp4ConfigData) -> Status,
+    _groups: unsafe extern "efiapi" fn(this: *mut Udp4Protocol, join: Boolean, address: *const Ipv4Address) -> Status,
+    _routes: unsafe extern "efiapi" fn(
+        this: *mut Udp4Protocol,
+        delete: Boolean,
+        subnet: *const Ipv4Address,
+        mask: *const Ipv4Address,
+        gateway: *const Ipv4Address,
+    ) -> Status,
+    transmit: unsafe extern "efiapi" fn(this: *mut Udp4Protocol, token: *mut Udp4CompletionToken) -> Status,
+    receive: unsafe extern "efiapi" fn(this: *mut Udp4Protocol, token: *mut Udp4CompletionToken) -> Status,
+    cancel: unsafe extern "efiapi" fn(this: *mut Udp4Protocol, token: *mut Udp4CompletionToken) -> Status,
+    poll: unsafe extern "efiapi" fn(this: *mut Udp4Protocol) -> Status,
+}
+
+/// Port mapper (rpcbind) port
+const PORTMAP_PORT: u16 = 111;
+
+/// Bytes asked for per READ; 8 KiB is the customary size over UDP
+const READ_SIZE: u32 = 8192;
+
+/// Wait for the first reply to a call, doubled for every retransmission
+const FIRST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Transmissions of a call before giving up (about 30 seconds in all)
+const ATTEMPTS: u32 = 6;
+
+/// Give up on a datagram the driver does not manage to send
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause between polls of the UDP driver
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Local ports calls are sent from, tried highest first
+///
+/// Exports are `secure` by default on Linux, which means the server only
+/// answers requests from ports below 1024.
+const RESERVED_PORTS: RangeInclusive<u16> = 512..=1023;
+
+/// A file on an NFS server, from an `nfs://` entry URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub server: Ipv4,
+    /// NFS port (2049 unless written out)
+    pub port: u16,
+    /// Full path of the file on the server, export included
+    pub path: &'a str,
+}
+
+impl<'a> Location<'a> {
+    /// Parse `nfs://<server ip>[:port]/<path>`
+    ///
+    /// The server has to be an IP address: host names are resolved by the
+    /// firmware's HTTP driver, which is not involved here.
+    pub fn parse(url: &'a str) -> Result<Self> {
+        let parsed = url::parse(url)?;
+        if parsed.scheme != Scheme::Nfs {
+            return Err(Error::InvalidArgument);
+        }
+        let server = addr::parse_ipv4(parsed.host).map_err(|_| Error::InvalidArgument)?;
+
+        let path = parsed.path;
+        if path.ends_with('/') || path.contains(['?', '#']) || path.split('/').any(|name| name == "." || name == "..") {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(Location { server, port: parsed.port, path })
+    }
+}
+
+/// Ways to split a path into an export and the path below it, longest
+/// export first
+///
+/// The URL does not say which directory is exported, so each is tried in
+/// turn: `/srv/boot/x.efi` is `x.efi` in `/srv/boot`, or `boot/x.efi` in
+/// `/srv`, or the whole path in `/`.
+pub fn mount_points(path: &str) -> impl Iterator<Item = (&str, &str)> {
+    path.rmatch_indices('/')
+        .map(move |(i, _)| (if i == 0 { "/" } else { &path[..i] }, &path[i + 1..]))
+}
+
+/// Download a file from an NFS export
+///
+/// Speaks NFSv3 over UDP itself: the port mapper on the server says where
+/// MOUNT listens, MOUNT hands out the export's root handle, and the file is
+/// looked up and read from the NFS port.
+pub fn download(url: &str) -> Result<Vec<u8>> {
+    say!("Downloading: {}", url);
+    let location = Location::parse(url).map_err(|e| {
+        println!("  Invalid NFS URL: {}", url);
+        println!("  Expected nfs://<server ip>[:port]/<export>/<path>");
+        e
+    })?;
+    caps::require(Capability::Udp4)?;
+    detail!("  Server: {}:{}", location.server, location.port);
+    let nic = session::nic()?;
+
+    let timer = Timer::start();
+    let opened = open_file(nic, &location);
+    timer.stop(Phase::Connect);
+    let (mut nfs, file, size) = opened?;
+    detail!("  Size: {} bytes", size);
+    crate::util::splash::expect(size);
+
+    // Whatever arrived is wiped if the transfer is abandoned
+    let mut data = Wiped::default();
+    data.try_reserve_exact(size).map_err(|_| Error::OutOfMemory)?;
+
+    let timer = Timer::start();
+    let result = read_file(&mut nfs, &file, size, &mut data);
+    crate::util::ui::progress_done();
+    timer.stop(Phase::Download);
+    if let Err(e) = result {
+        println!("  Transfer failed after {} bytes: {}", data.len(), e);
+        return Err(e);
+    }
+    if data.len() != size {
+        println!("  File changed during the transfer: read {} of {} bytes", data.len(), size);
+        return Err(Error::Io);
+    }
+
+    say!("  Download complete: {} bytes total", data.len());
+    Ok(data.into_inner())
+}
+
+/// Mount the export holding the file and look the file up in it
+///
+/// Returns the NFS client, the file's handle and its size.
+fn open_file(nic: Handle, location: &Location) -> Result<(RpcClient, FileHandle, usize)> {
+    let mount_port = {
+        let mut portmap = RpcClient::open(nic, location.server, PORTMAP_PORT, rpc::PORTMAP, rpc::PORTMAP_VERSION, "port mapper")?;
+        let args = rpc::getport_args(rpc::MOUNT, rpc::MOUNT_VERSION);
+        portmap.call(rpc::PMAPPROC_GETPORT, &args, rpc::parse_port)?.ok_or_else(|| {
+            println!("  Server does not offer MOUNT version 3 over UDP");
+            Error::NotFound
+        })?
+    };
+    let (root, below) = mount(nic, location, mount_port)?;
+
+    let mut nfs = RpcClient::open(nic, location.server, location.port, rpc::NFS, rpc::NFS_VERSION, "NFS")?;
+    let mut handle = root;
+    let mut attributes = None;
+    for name in below.split('/').filter(|name| !name.is_empty()) {
+        let args = rpc::lookup_args(&handle, name);
+        (handle, attributes) = nfs.call(rpc::NFSPROC3_LOOKUP, &args, |results| Ok(rpc::parse_lookup(results)))?.map_err(|reason| {
+            println!("  Lookup of {}: {}", name, reason);
+            Error::NotFound
+        })?;
+    }
+
+    let attributes = attributes.ok_or_else(|| {
+        println!("  Server sent no attributes for {}", location.path);
+        Error::Io
+    })?;
+    match attributes.kind {
+        rpc::NF3REG => {}
+        rpc::NF3LNK => {
+            println!("  {} is a symbolic link, use the path it points to", location.path);
+            return Err(Error::Unsupported);
+        }
+        _ => {
+            println!("  {} is not a regular file", location.path);
+            return Err(Error::InvalidArgument);
+        }
+    }
+    let size = usize::try_from(attributes.size).map_err(|_| Error::OutOfMemory)?;
+
+    Ok((nfs, handle, size))
+}
+
+/// Mount the longest exported directory above the file
+///
+/// Returns the export's root handle and the path of the file below it.
+fn mount<'a>(nic: Handle, location: &Location<'a>, port: u16) -> Result<(FileHandle, &'a str)> {
+    let mut client = RpcClient::open(nic, location.server, port, rpc::MOUNT, rpc::MOUNT_VERSION, "MOUNT")?;
+
+    // The most specific export is the one worth reporting if none works
+    let mut refusal = None;
+    for (export, below) in mount_points(location.path) {
+        let args = rpc::mount_args(export);
+        match client.call(rpc::MOUNTPROC3_MNT, &args, |results| Ok(rpc::parse_mount(results)))? {
+            Ok(root) => {
+                detail!("  Mounted {}", export);
+                return Ok((root, below));
+            }
+            Err(reason) => {
+                refusal.get_or_insert((export, reason));
+            }
+        }
+    }
+
+    if let Some((export, reason)) = refusal {
+        println!("  Mount of {} refused: {}", export, reason);
+    }
+    Err(Error::NotFound)
+}
+
+/// Read the whole file (`size` bytes, as the server announced), READ_SIZE
+/// bytes per call
+fn read_file(nfs: &mut RpcClient, file: &[u8], size: usize, data: &mut Wiped) -> Result<()> {
+    loop {
+        let previous = data.len();
+        let args = rpc::read_args(file, previous as u64, READ_SIZE);
+        let eof = nfs
+            .call(rpc::NFSPROC3_READ, &args, |results| {
+                Ok(rpc::parse_read(results).map(|(chunk, eof)| {
+                    data.extend_from_slice(chunk);
+                    eof
+                }))
+            })?
+            .map_err(|reason| {
+                println!("  Read at offset {}: {}", previous, reason);
+                Error::Io
+            })?;
+        crate::util::ui::progress("Progress", data.len(), previous, Some(size));
+
+        // A short read without EOF would make no progress
+        if eof || data.len() == previous {
+            return Ok(());
+        }
+    }
+}
+
+/// Transaction id of the last call
+///
+/// Seeded from the clock, so calls after a reboot do not reuse ids the
+/// server may still have cached replies for.
+static LAST_XID: Global<u32> = Global::new();
+
+fn next_xid() -> u32 {
+    if LAST_XID.with(|_| ()).is_none() {
+        LAST_XID.set(crate::util::rtc::now().map_or(1, |t| {
+            let seconds = (u32::from(t.day()) * 24 + u32::from(t.hour())) * 3600
+                + u32::from(t.minute()) * 60
+                + u32::from(t.second());
+            seconds.wrapping_mul(0x9e37_79b9) ^ t.nanosecond()
+        }));
+    }
+    LAST_XID
+        .with_mut(|xid| {
+            *xid = xid.wrapping_add(1);
+            *xid
+        })
+        .expect("xid seeded above")
+}
+
+/// One RPC program on the server, called over its own socket
+struct RpcClient {
+    socket: UdpSocket,
+    program: u32,
+    version: u32,
+    /// Program name for messages
+    name: &'static str,
+}
+
+impl RpcClient {
+    fn open(nic: Handle, server: Ipv4, port: u16, program: u32, version: u32, name: &'static str) -> Result<Self> {
+        let socket = UdpSocket::open(nic, server, port)?;
+        Ok(RpcClient { socket, program, version, name })
+    }
+
+    /// Call a procedure and decode its results
+    ///
+    /// Datagrams get lost, so the call is sent again with the same xid
+    /// whenever no reply comes in time, waiting twice as long each time.
+    /// Servers answer repeats from their duplicate request cache.
+    fn call<T>(
+        &mut self,
+        procedure: u32,
+        args: &[u8],
+        decode: impl FnOnce(Decoder) -> core::result::Result<T, &'static str>,
+    ) -> Result<T> {
+        let xid = next_xid();
+        let message = rpc::call(xid, self.program, self.version, procedure, args);
+
+        let mut timeout = FIRST_TIMEOUT;
+        for _ in 0..ATTEMPTS {
+            self.socket.send(&message)?;
+            // Late replies to earlier calls are skipped
+            while let Some(datagram) = self.socket.receive(timeout)? {
+                let results = match rpc::reply(xid, &datagram) {
+                    Ok(Some(results)) => decode(results),
+                    Ok(None) => continue,
+                    Err(reason) => Err(reason),
+                };
+                return results.map_err(|reason| {
+                    println!("  {} call failed: {}", self.name, reason);
+                    Error::Io
+                });
+            }
+            timeout *= 2;
+        }
+
+        println!("  No reply from the {} service", self.name);
+        Err(Error::Uefi(Status::TIMEOUT))
+    }
+}
+
+/// A UDP4 instance exchanging datagrams with one server port
+struct UdpSocket {
+    // Declared before `child` so the protocol is closed first
+    udp: RawProtocol<Udp4Protocol>,
+    _child: ServiceChild,
+}
+
+impl UdpSocket {
+    /// Create a UDP4 instance on a NIC, bound to a free reserved port and
+    /// to the server's address and port
+    fn open(nic: Handle, server: Ipv4, port: u16) -> Result<Self> {
+        let child = ServiceChild::create(nic, &UDP4_SERVICE_BINDING_GUID)?;
+        let udp = RawProtocol::<Udp4Protocol>::open(child.handle(), &UDP4_PROTOCOL_GUID)?;
+        let udp_ptr = udp.as_ptr();
+
+        for local_port in RESERVED_PORTS.rev() {
+            let config = Udp4ConfigData {
+                accept_broadcast: Boolean::FALSE,
+                accept_promiscuous: Boolean::FALSE,
+                accept_any_port: Boolean::FALSE,
+                allow_duplicate_port: Boolean::FALSE,
+                type_of_service: 0,
+                time_to_live: 64,
+                do_not_fragment: Boolean::FALSE,
+                receive_timeout: 0,
+                transmit_timeout: 0,
+                use_default_address: Boolean::TRUE,
+                station_address: Ipv4Address([0; 4]),
+                subnet_mask: Ipv4Address([0; 4]),
+                station_port: local_port,
+                remote_address: Ipv4Address(server.0),
+                remote_port: port,
+            };
+            match unsafe { ((*udp_ptr).configure)(udp_ptr, &config) } {
+                // Port taken by another instance
+                Status::ACCESS_DENIED => continue,
+                status if status.is_error() => return Err(Error::Uefi(status)),
+                _ => return Ok(UdpSocket { udp, _child: child }),
+            }
+        }
+
+        println!("  No free reserved port to send from");
+        Err(Error::Uefi(Status::ACCESS_DENIED))
+    }
+
+    /// Send one datagram to the server
+    fn send(&mut self, datagram: &[u8]) -> Result<()> {
+        let completion = Completion::new()?;
+        let length = datagram.len() as u32;
+        let mut data = Udp4TransmitData {
+            udp_session_data: ptr::null(),
+            gateway_address: ptr::null(),
+            data_length: length,
+            fragment_count: 1,
+            fragment_table: [Udp4FragmentData {
+                fragment_length: length,
+                fragment_buffer: datagram.as_ptr() as *mut c_void,
+            }],
+        };
+        let mut token = Udp4CompletionToken {
+            event: completion.0.as_ptr(),
+            status: Status::NOT_READY,
+            packet: &mut data as *mut Udp4TransmitData as *mut c_void,
+        };
+
+        // The token and buffers live on this stack frame, so wait here
+        let udp_ptr = self.udp.as_ptr();
+        let status = unsafe { ((*udp_ptr).transmit)(udp_ptr, &mut token) };
+        if status.is_error() {
+            return Err(Error::Uefi(status));
+        }
+        if !self.wait(&completion, SEND_TIMEOUT) {
+            unsafe {
+                let _ = ((*udp_ptr).cancel)(udp_ptr, &mut token);
+            }
+            return Err(Error::Uefi(Status::TIMEOUT));
+        }
+        token_status(&token)
+    }
+
+    /// Next datagram from the server, or None if none came within `timeout`
+    fn receive(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        let completion = Completion::new()?;
+        let mut token = Udp4CompletionToken {
+            event: completion.0.as_ptr(),
+            status: Status::NOT_READY,
+            packet: ptr::null_mut(),
+        };
+
+        let udp_ptr = self.udp.as_ptr();
+        let status = unsafe { ((*udp_ptr).receive)(udp_ptr, &mut token) };
+        if status.is_error() {
+            return Err(Error::Uefi(status));
+        }
+        if !self.wait(&completion, timeout) {
+            unsafe {
+                let _ = ((*udp_ptr).cancel)(udp_ptr, &mut token);
+            }
+            return Ok(None);
+        }
+        // An ICMP error (port unreachable, say) completes the token too
+        token_status(&token)?;
+
+        let rx = token.packet as *const Udp4ReceiveData;
+        if rx.is_null() {
+            return Err(Error::Io);
+        }
+        Ok(Some(unsafe { take_datagram(rx) }))
+    }
+
+    /// Poll until `completion` is signaled; false after `timeout`
+    fn wait(&mut self, completion: &Completion, timeout: Duration) -> bool {
+        let udp_ptr = self.udp.as_ptr();
+        let polls = timeout.as_micros() / POLL_INTERVAL.as_micros();
+        for _ in 0..polls {
+            if completion.is_signaled() {
+                return true;
+            }
+            unsafe {
+                let _ = ((*udp_ptr).poll)(udp_ptr);
+            }
+            boot::stall(POLL_INTERVAL);
+        }
+        completion.is_signaled()
+    }
+}
+
+/// Final status the driver stored in a completed token
+fn token_status(token: &Udp4CompletionToken) -> Result<()> {
+    let status = unsafe { ptr::read_volatile(&token.status) };
+    if status.is_error() {
+        Err(Error::Uefi(status))
+    } else {
+        Ok(())
+    }
+}
+
+/// Copy a received datagram out of the driver's buffers and give them back
+unsafe fn take_datagram(rx: *const Udp4ReceiveData) -> Vec<u8> {
+    let fragments = core::slice::from_raw_parts(
+        ptr::addr_of!((*rx).fragment_table).cast::<Udp4FragmentData>(),
+        (*rx).fragment_count as usize,
+    );
+
+    let mut datagram = Vec::with_capacity((*rx).data_length as usize);
+    for fragment in fragments {
+        datagram.extend_from_slice(core::slice::from_raw_parts(
+            fragment.fragment_buffer as *const u8,
+            fragment.fragment_length as usize,
+        ));
+    }
+
+    if let Some(recycle) = Event::from_ptr((*rx).recycle_signal) {
+        let _ = boot::signal_event(&recycle);
+    }
+    datagram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_location() {
+        let location = Location::parse("nfs://10.0.0.5/srv/boot/grubx64.efi").unwrap();
+        assert_eq!(location.server, Ipv4([10, 0, 0, 5]));
+        assert_eq!(location.port, 2049);
+        assert_eq!(location.path, "/srv/boot/grubx64.efi");
+        assert_eq!(Location::parse("nfs://10.0.0.5:20049/x.efi").unwrap().port, 20049);
+
+        // Names need DNS, which only the HTTP driver does
+        assert!(Location::parse("nfs://filer.example.com/srv/x.efi").is_err());
+        assert!(Location::parse("nfs://10.0.0.5/srv/boot/").is_err());
+        assert!(Location::parse("nfs://10.0.0.5/srv/../etc/shadow").is_err());
+        assert!(Location::parse("http://10.0.0.5/srv/x.efi").is_err());
+    }
+
+    #[test]
+    fn test_mount_points() {
+        let splits: Vec<(&str, &str)> = mount_points("/srv/boot/x.efi").collect();
+        assert_eq!(splits, [("/srv/boot", "x.efi"), ("/srv", "boot/x.efi"), ("/", "srv/boot/x.efi")]);
+        assert_eq!(mount_points("/x.efi").collect::<Vec<_>>(), [("/", "x.efi")]);
+    }
+}
@@ -0,0 +1,141 @@
+/// Offset of the client ("your") IP address in a BOOTP/DHCP packet
+const YIADDR_OFFSET: usize = 16;
+
+/// Offset of the next-server IP address in a BOOTP/DHCP packet
+const SIADDR_OFFSET: usize = 20;
+
+/// Offset of the boot file name field in a BOOTP/DHCP packet
+const FILE_OFFSET: usize = 108;
+
+/// Length of the boot file name field
+const FILE_LEN: usize = 128;
+
+/// Offset of the DHCP magic cookie
+const MAGIC_OFFSET: usize = 236;
+
+/// DHCP magic cookie (99.130.83.99)
+const MAGIC: [u8; 4] = [99, 130, 83, 99];
+
+/// DHCP option: subnet mask
+pub const OPT_SUBNET_MASK: u8 = 1;
+/// DHCP option: default gateway
+pub const OPT_ROUTER: u8 = 3;
+/// DHCP option: DNS servers
+pub const OPT_DNS: u8 = 6;
+/// DHCP option: server identifier
+pub const OPT_SERVER_ID: u8 = 54;
+/// DHCP option: boot file name
+pub const OPT_BOOTFILE: u8 = 67;
+
+/// Read a 4-byte address from a packet at the given offset
+fn read_addr(packet: &[u8], offset: usize) -> Option<[u8; 4]> {
+    let bytes = packet.get(offset..offset + 4)?;
+    Some([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Address assigned to the client (yiaddr)
+pub fn your_ip(packet: &[u8]) -> Option<[u8; 4]> {
+    read_addr(packet, YIADDR_OFFSET)
+}
+
+/// Next-server address (siaddr), typically the boot server
+pub fn server_ip(packet: &[u8]) -> Option<[u8; 4]> {
+    read_addr(packet, SIADDR_OFFSET)
+}
+
+/// Boot file name, preferring option 67 over the fixed BOOTP field
+pub fn boot_file(packet: &[u8]) -> &str {
+    let raw = match find_option(packet, OPT_BOOTFILE) {
+        Some(value) => value,
+        None => match packet.get(FILE_OFFSET..FILE_OFFSET + FILE_LEN) {
+            Some(field) => field,
+            None => return "",
+        },
+    };
+
+    // Both forms may be NUL-padded
+    let len = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    core::str::from_utf8(&raw[..len]).unwrap_or("")
+}
+
+/// Find a DHCP option by code and return its value bytes
+pub fn find_option(packet: &[u8], code: u8) -> Option<&[u8]> {
+    if packet.get(MAGIC_OFFSET..MAGIC_OFFSET + 4)? != MAGIC {
+        return None;
+    }
+
+    let mut pos = MAGIC_OFFSET + 4;
+    while pos < packet.len() {
+        match packet[pos] {
+            // End option
+            255 => return None,
+            // Pad option
+            0 => pos += 1,
+            opt => {
+                let len = *packet.get(pos + 1)? as usize;
+                let value = packet.get(pos + 2..pos + 2 + len)?;
+                if opt == code {
+                    return Some(value);
+                }
+                pos += 2 + len;
+            }
+        }
+    }
+
+    None
+}
+
+/// Find an option holding (at least) one IPv4 address and return the first
+pub fn find_addr_option(packet: &[u8], code: u8) -> Option<[u8; 4]> {
+    let value = find_option(packet, code)?;
+    read_addr(value, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_packet() -> [u8; 300] {
+        let mut packet = [0u8; 300];
+        packet[YIADDR_OFFSET..YIADDR_OFFSET + 4].copy_from_slice(&[10, 0, 2, 15]);
+        packet[SIADDR_OFFSET..SIADDR_OFFSET + 4].copy_from_slice(&[10, 0, 2, 2]);
+        packet[FILE_OFFSET..FILE_OFFSET + 9].copy_from_slice(b"pxe.efi\0\0");
+        packet[MAGIC_OFFSET..MAGIC_OFFSET + 4].copy_from_slice(&MAGIC);
+        let options = [
+            OPT_ROUTER, 4, 10, 0, 2, 1,
+            0, // pad
+            OPT_SERVER_ID, 4, 10, 0, 2, 3,
+            255,
+        ];
+        packet[240..240 + options.len()].copy_from_slice(&options);
+        packet
+    }
+
+    #[test]
+    fn test_addresses() {
+        let packet = sample_packet();
+        assert_eq!(your_ip(&packet), Some([10, 0, 2, 15]));
+        assert_eq!(server_ip(&packet), Some([10, 0, 2, 2]));
+    }
+
+    #[test]
+    fn test_find_option() {
+        let packet = sample_packet();
+        assert_eq!(find_addr_option(&packet, OPT_ROUTER), Some([10, 0, 2, 1]));
+        assert_eq!(find_addr_option(&packet, OPT_SERVER_ID), Some([10, 0, 2, 3]));
+        assert_eq!(find_option(&packet, OPT_DNS), None);
+    }
+
+    #[test]
+    fn test_boot_file_fallback() {
+        let packet = sample_packet();
+        assert_eq!(boot_file(&packet), "pxe.efi");
+    }
+
+    #[test]
+    fn test_missing_magic() {
+        let mut packet = sample_packet();
+        packet[MAGIC_OFFSET] = 0;
+        assert_eq!(find_option(&packet, OPT_ROUTER), None);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::network::addr::Ipv4;
+use crate::network::child::RawProtocol;
+use crate::network::dhcp::{Lease, LeaseSource};
+use crate::network::packet;
+use crate::util::{Error, Result};
+use alloc::format;
+use alloc::string::String;
+use uefi::{Guid, Handle, Status};
+use uefi_raw::protocol::network::pxe::{PxeBaseCodeBootType, PxeBaseCodeMode, PxeBaseCodeProtocol};
+use uefi_raw::Boolean;
+
+/// PXE Base Code Protocol GUID (from UEFI spec)
+/// {03C4E603-AC28-11D3-9A2D-0090273FC14D}
+const PXE_BASE_CODE_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0x03, 0xe6, 0xc4, 0x03, 0x28, 0xac, 0xd3, 0x11,
+    0x9a, 0x2d, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d,
+]);
+
+/// Name of the entry PXE discovery adds
+pub const ENTRY_NAME: &str = "pxe";
+
+/// Tag marking the entry as one PXE discovery added, and may update
+pub const ENTRY_TAG: &str = "pxe-discovered";
+
+/// Look for a DHCP lease the firmware's PXE client already obtained on an
+/// interface
+///
+/// When we were chainloaded from PXE, the PXE Base Code protocol is still
+/// started and holds the DHCP ACK it received. Reusing it saves a full
+/// DHCP round-trip and avoids requesting a second lease for the same NIC.
+/// Fails with `NotFound` when the interface has no PXE session with a lease.
+pub fn existing_lease(nic_handle: Handle) -> Result<Lease> {
+    let pxe = RawProtocol::<PxeBaseCodeProtocol>::open(nic_handle, &PXE_BASE_CODE_PROTOCOL_GUID)
+        .map_err(|_| Error::NotFound)?;
+    lease_from_mode(unsafe { mode_data(pxe.as_ptr()) }.ok_or(Error::NotFound)?)
+}
+
+/// Run classic PXE discovery on an interface through the firmware's PXE
+/// Base Code: DHCP with the PXEClient vendor class, which ProxyDHCP servers
+/// answer, then a boot server request on port 4011 when the offers only
+/// name boot servers
+///
+/// Leaves the PXE session started, so `existing_lease` finds the result
+/// and the network is not set up a second time.
+pub fn discover(nic_handle: Handle) -> Result<Lease> {
+    let pxe = RawProtocol::<PxeBaseCodeProtocol>::open(nic_handle, &PXE_BASE_CODE_PROTOCOL_GUID)
+        .map_err(|_| Error::Unsupported)?;
+    let pxe_ptr = pxe.as_ptr();
+    let mode = unsafe { mode_data(pxe_ptr) }.ok_or(Error::Unsupported)?;
+
+    if !bool::from(mode.started) {
+        let status = unsafe { ((*pxe_ptr).start)(pxe_ptr, Boolean::FALSE) };
+        if status.is_error() && status != Status::ALREADY_STARTED {
+            return Err(Error::Uefi(status));
+        }
+    }
+    if bool::from(mode.using_ipv6) {
+        return Err(Error::Unsupported);
+    }
+
+    // Sorted offers put the PXE ones first, with any ProxyDHCP reply kept
+    // next to the address lease
+    if !bool::from(mode.dhcp_ack_received) {
+        let status = unsafe { ((*pxe_ptr).dhcp)(pxe_ptr, Boolean::TRUE) };
+        if status.is_error() {
+            return Err(Error::Uefi(status));
+        }
+    }
+
+    // Offers that list boot servers instead of a file want the client to
+    // ask one of them
+    let mode = unsafe { mode_data(pxe_ptr) }.ok_or(Error::Unsupported)?;
+    let named_file = lease_from_mode(mode).is_ok_and(|lease| !lease.boot_file.is_empty());
+    if !named_file && bool::from(mode.pxe_discover_valid) {
+        let mut layer = 0u16;
+        let status = unsafe {
+            ((*pxe_ptr).discover)(
+                pxe_ptr,
+                PxeBaseCodeBootType::BOOTSTRAP,
+                &mut layer,
+                Boolean::FALSE,
+                core::ptr::null(),
+            )
+        };
+        if status.is_error() {
+            return Err(Error::Uefi(status));
+        }
+    }
+
+    lease_from_mode(unsafe { mode_data(pxe_ptr) }.ok_or(Error::Unsupported)?)
+}
+
+/// The entry URL for the boot file a PXE lease names, if it names one
+pub fn boot_url(lease: &Lease) -> Option<String> {
+    if lease.boot_file.is_empty() || lease.server_ip == [0; 4] {
+        return None;
+    }
+    Some(format!("tftp://{}/{}", Ipv4(lease.server_ip), lease.boot_file))
+}
+
+/// Mode data of a PXE Base Code instance
+unsafe fn mode_data<'a>(pxe_ptr: *mut PxeBaseCodeProtocol) -> Option<&'a PxeBaseCodeMode> {
+    let mode_ptr = (*pxe_ptr).mode;
+    if mode_ptr.is_null() {
+        return None;
+    }
+    Some(&*mode_ptr)
+}
+
+/// The lease a PXE session holds, with the boot server and file from the
+/// ProxyDHCP or boot server reply when there is one
+///
+/// A boot file name too long to keep fails with `BufferTooSmall` rather
+/// than being cut short.
+fn lease_from_mode(mode: &PxeBaseCodeMode) -> Result<Lease> {
+    // Only an IPv4 session that actually finished DHCP is useful to us
+    if !bool::from(mode.started) || !bool::from(mode.dhcp_ack_received) || bool::from(mode.using_ipv6) {
+        return Err(Error::NotFound);
+    }
+
+    let ack = unsafe { &mode.dhcp_ack.raw };
+    let client_ip = packet::your_ip(ack).ok_or(Error::NotFound)?;
+    if client_ip == [0; 4] {
+        return Err(Error::NotFound);
+    }
+
+    // Boot server details come from the ProxyDHCP/PXE reply when present
+    let boot_packet = unsafe {
+        if bool::from(mode.pxe_reply_received) {
+            &mode.pxe_reply.raw
+        } else if bool::from(mode.proxy_offer_received) {
+            &mode.proxy_offer.raw
+        } else {
+            ack
+        }
+    };
+
+    let server_ip = packet::server_ip(boot_packet)
+        .filter(|ip| *ip != [0; 4])
+        .or_else(|| packet::find_addr_option(ack, packet::OPT_SERVER_ID))
+        .unwrap_or([0; 4]);
+
+    let mut boot_file = heapless::String::new();
+    boot_file.push_str(packet::boot_file(boot_packet)).map_err(|_| Error::BufferTooSmall)?;
+
+    Ok(Lease {
+        source: LeaseSource::Pxe,
+        client_ip,
+        subnet_mask: packet::find_addr_option(ack, packet::OPT_SUBNET_MASK).unwrap_or([0; 4]),
+        server_ip,
+        router: packet::find_addr_option(ack, packet::OPT_ROUTER),
+        boot_file,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lease(server_ip: [u8; 4], boot_file: &str) -> Lease {
+        Lease {
+            source: LeaseSource::Pxe,
+            client_ip: [10, 0, 2, 15],
+            subnet_mask: [255, 255, 255, 0],
+            server_ip,
+            router: None,
+            boot_file: heapless::String::try_from(boot_file).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_boot_url() {
+        let url = boot_url(&lease([10, 0, 2, 4], "efi64/syslinux.efi")).unwrap();
+        assert_eq!(url, "tftp://10.0.2.4/efi64/syslinux.efi");
+        assert_eq!(crate::network::tftp::Location::parse(&url).unwrap().file, "efi64/syslinux.efi");
+        // An absolute path on the server keeps its slash
+        assert_eq!(
+            boot_url(&lease([10, 0, 2, 4], "/srv/tftp/grubx64.efi")).unwrap(),
+            "tftp://10.0.2.4//srv/tftp/grubx64.efi"
+        );
+        assert_eq!(boot_url(&lease([10, 0, 2, 4], "")), None);
+        assert_eq!(boot_url(&lease([0; 4], "grubx64.efi")), None);
+    }
+}
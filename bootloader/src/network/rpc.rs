@@ -0,0 +1,413 @@
+use crate::util::{Error, Result};
+use alloc::vec::Vec;
+
+/// Port mapper (RFC 1833), version 2
+pub const PORTMAP: u32 = 100000;
+pub const PORTMAP_VERSION: u32 = 2;
+/// NFS (RFC 1813), version 3
+pub const NFS: u32 = 100003;
+pub const NFS_VERSION: u32 = 3;
+/// MOUNT (RFC 1813 appendix I), version 3
+pub const MOUNT: u32 = 100005;
+pub const MOUNT_VERSION: u32 = 3;
+
+/// Port mapper procedure
+pub const PMAPPROC_GETPORT: u32 = 3;
+/// MOUNT procedure
+pub const MOUNTPROC3_MNT: u32 = 1;
+/// NFS procedures
+pub const NFSPROC3_LOOKUP: u32 = 3;
+pub const NFSPROC3_READ: u32 = 6;
+
+/// IP protocol number for UDP, as asked of the port mapper
+const IPPROTO_UDP: u32 = 17;
+
+/// Longest file handle in NFSv3 (FHSIZE3)
+pub const MAX_HANDLE_LEN: usize = 64;
+
+/// File types in NFSv3 attributes
+pub const NF3REG: u32 = 1;
+pub const NF3LNK: u32 = 5;
+
+/// Host name sent in AUTH_UNIX credentials
+const MACHINE_NAME: &str = "uefipxe";
+
+/// Opaque server-side identifier of a file or directory
+pub type FileHandle = heapless::Vec<u8, MAX_HANDLE_LEN>;
+
+/// XDR (RFC 4506) writer
+#[derive(Default)]
+pub struct Encoder(Vec<u8>);
+
+impl Encoder {
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Variable-length opaque data or string, padded to four bytes
+    pub fn opaque(&mut self, bytes: &[u8]) -> &mut Self {
+        self.u32(bytes.len() as u32);
+        self.0.extend_from_slice(bytes);
+        self.0.resize(self.0.len() + padding(bytes.len()), 0);
+        self
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// XDR reader; running past the end is a parse error
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Decoder { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len()).ok_or(Error::Parse)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok((u64::from(self.u32()?) << 32) | u64::from(self.u32()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        Ok(self.u32()? != 0)
+    }
+
+    /// Variable-length opaque data of at most `max` bytes
+    pub fn opaque(&mut self, max: usize) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        if len > max {
+            return Err(Error::Parse);
+        }
+        let bytes = self.take(len)?;
+        self.take(padding(len))?;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.take(len).map(|_| ())
+    }
+}
+
+/// Bytes of padding after `len` bytes of opaque data
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+/// A complete call message: header, AUTH_UNIX credentials as root, and the
+/// procedure's arguments
+///
+/// Exports are usually squashed to nobody for root anyway; sending
+/// AUTH_UNIX rather than AUTH_NONE keeps servers that refuse anonymous
+/// mounts happy.
+pub fn call(xid: u32, program: u32, version: u32, procedure: u32, args: &[u8]) -> Vec<u8> {
+    let mut credentials = Encoder::default();
+    credentials
+        .u32(0) // stamp
+        .opaque(MACHINE_NAME.as_bytes())
+        .u32(0) // uid
+        .u32(0) // gid
+        .u32(0); // no supplementary groups
+
+    let mut message = Encoder::default();
+    message
+        .u32(xid)
+        .u32(0) // CALL
+        .u32(2) // RPC version
+        .u32(program)
+        .u32(version)
+        .u32(procedure)
+        .u32(1) // AUTH_UNIX
+        .opaque(&credentials.into_inner())
+        .u32(0) // AUTH_NONE verifier
+        .u32(0);
+
+    let mut bytes = message.into_inner();
+    bytes.extend_from_slice(args);
+    bytes
+}
+
+/// Results of a successful reply to call `xid`
+///
+/// Ok(None) for datagrams that are not a reply to this call (a late answer
+/// to an earlier retransmission, say), Err with the reason when the server
+/// refused the call.
+pub fn reply(xid: u32, datagram: &[u8]) -> core::result::Result<Option<Decoder<'_>>, &'static str> {
+    let mut reply = Decoder::new(datagram);
+    match (reply.u32(), reply.u32()) {
+        (Ok(id), Ok(1)) if id == xid => {}
+        _ => return Ok(None),
+    }
+
+    let malformed = |_| "malformed reply";
+    match reply.u32().map_err(malformed)? {
+        // MSG_ACCEPTED
+        0 => {
+            reply.u32().map_err(malformed)?;
+            reply.opaque(400).map_err(malformed)?;
+            match reply.u32().map_err(malformed)? {
+                0 => Ok(Some(reply)),
+                1 => Err("program not available"),
+                2 => Err("program version not supported"),
+                3 => Err("procedure not available"),
+                4 => Err("server could not decode the call"),
+                _ => Err("server error"),
+            }
+        }
+        // MSG_DENIED
+        _ => match reply.u32().map_err(malformed)? {
+            0 => Err("RPC version mismatch"),
+            _ => Err("credentials refused"),
+        },
+    }
+}
+
+/// What an NFS or MOUNT status code means
+pub fn status_str(status: u32) -> &'static str {
+    match status {
+        1 => "not owner",
+        2 => "no such file or directory",
+        5 => "I/O error",
+        6 => "no such device",
+        13 => "permission denied",
+        20 => "not a directory",
+        21 => "is a directory",
+        22 => "invalid argument",
+        63 => "name too long",
+        70 => "stale file handle",
+        10004 => "operation not supported",
+        10008 => "server busy, try again",
+        _ => "server error",
+    }
+}
+
+/// Arguments of PMAPPROC_GETPORT: which program to find, over UDP
+pub fn getport_args(program: u32, version: u32) -> Vec<u8> {
+    let mut args = Encoder::default();
+    args.u32(program).u32(version).u32(IPPROTO_UDP).u32(0);
+    args.into_inner()
+}
+
+/// Port of the program asked for (None if it is not registered)
+pub fn parse_port(mut results: Decoder) -> core::result::Result<Option<u16>, &'static str> {
+    match results.u32() {
+        Ok(0) => Ok(None),
+        Ok(port) => u16::try_from(port).map(Some).map_err(|_| "malformed reply"),
+        Err(_) => Err("malformed reply"),
+    }
+}
+
+/// Arguments of MOUNTPROC3_MNT: the exported directory
+pub fn mount_args(export: &str) -> Vec<u8> {
+    let mut args = Encoder::default();
+    args.opaque(export.as_bytes());
+    args.into_inner()
+}
+
+/// Root file handle of a mounted export
+pub fn parse_mount(mut results: Decoder) -> core::result::Result<FileHandle, &'static str> {
+    match results.u32().map_err(|_| "malformed reply")? {
+        0 => handle(&mut results).map_err(|_| "malformed reply"),
+        status => Err(status_str(status)),
+    }
+}
+
+/// Arguments of NFSPROC3_LOOKUP: one name in a directory
+pub fn lookup_args(directory: &[u8], name: &str) -> Vec<u8> {
+    let mut args = Encoder::default();
+    args.opaque(directory).opaque(name.as_bytes());
+    args.into_inner()
+}
+
+/// The parts of a file's attributes (fattr3) that matter here
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attributes {
+    /// NF3REG, NF3LNK, ...
+    pub kind: u32,
+    pub size: u64,
+}
+
+/// Handle and, when the server sent them, attributes of the name looked up
+pub fn parse_lookup(mut results: Decoder) -> core::result::Result<(FileHandle, Option<Attributes>), &'static str> {
+    let malformed = |_| "malformed reply";
+    match results.u32().map_err(malformed)? {
+        0 => {
+            let object = handle(&mut results).map_err(malformed)?;
+            let attributes = post_op_attributes(&mut results).map_err(malformed)?;
+            Ok((object, attributes))
+        }
+        status => Err(status_str(status)),
+    }
+}
+
+/// Arguments of NFSPROC3_READ
+pub fn read_args(file: &[u8], offset: u64, count: u32) -> Vec<u8> {
+    let mut args = Encoder::default();
+    args.opaque(file).u64(offset).u32(count);
+    args.into_inner()
+}
+
+/// Data returned by a read and whether it reached the end of the file
+pub fn parse_read(mut results: Decoder<'_>) -> core::result::Result<(&[u8], bool), &'static str> {
+    let malformed = |_| "malformed reply";
+    let status = results.u32().map_err(malformed)?;
+    post_op_attributes(&mut results).map_err(malformed)?;
+    if status != 0 {
+        return Err(status_str(status));
+    }
+
+    let count = results.u32().map_err(malformed)? as usize;
+    let eof = results.bool().map_err(malformed)?;
+    let data = results.opaque(count).map_err(malformed)?;
+    Ok((data, eof))
+}
+
+fn handle(results: &mut Decoder) -> Result<FileHandle> {
+    FileHandle::from_slice(results.opaque(MAX_HANDLE_LEN)?).map_err(|_| Error::Parse)
+}
+
+/// post_op_attr: attributes if the server chose to include them
+fn post_op_attributes(results: &mut Decoder) -> Result<Option<Attributes>> {
+    if !results.bool()? {
+        return Ok(None);
+    }
+
+    let kind = results.u32()?;
+    // mode, nlink, uid, gid
+    results.skip(16)?;
+    let size = results.u64()?;
+    // used, rdev, fsid, fileid, atime, mtime, ctime
+    results.skip(56)?;
+    Ok(Some(Attributes { kind, size }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reply header for call `xid`: accepted, null verifier, SUCCESS
+    fn accepted(xid: u32) -> Encoder {
+        let mut reply = Encoder::default();
+        reply.u32(xid).u32(1).u32(0).u32(0).u32(0).u32(0);
+        reply
+    }
+
+    fn fattr3(reply: &mut Encoder, kind: u32, size: u64) {
+        reply.u32(1).u32(kind);
+        for _ in 0..4 {
+            reply.u32(0);
+        }
+        reply.u64(size);
+        for _ in 0..14 {
+            reply.u32(0);
+        }
+    }
+
+    #[test]
+    fn test_xdr() {
+        let mut out = Encoder::default();
+        out.u32(7).opaque(b"abcde").u64(1 << 40);
+        let bytes = out.into_inner();
+        assert_eq!(bytes.len(), 4 + 4 + 8 + 8);
+        assert_eq!(&bytes[8..16], b"abcde\0\0\0");
+
+        let mut input = Decoder::new(&bytes);
+        assert_eq!(input.u32(), Ok(7));
+        assert_eq!(input.opaque(5), Ok(&b"abcde"[..]));
+        assert_eq!(input.u64(), Ok(1 << 40));
+        assert!(input.u32().is_err());
+
+        // Lengths beyond the limit or the datagram are rejected
+        assert!(Decoder::new(&bytes[4..]).opaque(4).is_err());
+        assert!(Decoder::new(&[0, 0, 0, 9, 1, 2]).opaque(64).is_err());
+    }
+
+    #[test]
+    fn test_call() {
+        let message = call(0x1234, NFS, NFS_VERSION, NFSPROC3_READ, &[9, 9, 9, 9]);
+        let mut header = Decoder::new(&message);
+        for expected in [0x1234, 0, 2, NFS, NFS_VERSION, NFSPROC3_READ, 1] {
+            assert_eq!(header.u32(), Ok(expected));
+        }
+        let credentials = header.opaque(400).unwrap();
+        assert_eq!(&credentials[8..15], b"uefipxe");
+        assert_eq!((header.u32(), header.u32(), header.u32()), (Ok(0), Ok(0), Ok(0x09090909)));
+    }
+
+    #[test]
+    fn test_reply() {
+        let ok = accepted(5).into_inner();
+        assert!(matches!(reply(5, &ok), Ok(Some(_))));
+        // Someone else's reply, or garbage
+        assert!(matches!(reply(6, &ok), Ok(None)));
+        assert!(matches!(reply(5, &[1, 2]), Ok(None)));
+
+        let mut unavailable = Encoder::default();
+        unavailable.u32(5).u32(1).u32(0).u32(0).u32(0).u32(1);
+        assert_eq!(reply(5, &unavailable.into_inner()).err(), Some("program not available"));
+
+        let mut denied = Encoder::default();
+        denied.u32(5).u32(1).u32(1).u32(1).u32(2);
+        assert_eq!(reply(5, &denied.into_inner()).err(), Some("credentials refused"));
+    }
+
+    #[test]
+    fn test_results() {
+        let mut port = accepted(1);
+        port.u32(20048);
+        let port = port.into_inner();
+        assert_eq!(parse_port(reply(1, &port).unwrap().unwrap()), Ok(Some(20048)));
+
+        let mut mount = accepted(2);
+        mount.u32(0).opaque(&[0xab; 28]).u32(1).u32(1);
+        let mount = mount.into_inner();
+        assert_eq!(parse_mount(reply(2, &mount).unwrap().unwrap()).unwrap().as_slice(), &[0xab; 28]);
+
+        let mut refused = accepted(3);
+        refused.u32(13);
+        let refused = refused.into_inner();
+        assert_eq!(parse_mount(reply(3, &refused).unwrap().unwrap()), Err("permission denied"));
+
+        let mut lookup = accepted(4);
+        lookup.u32(0).opaque(&[1; 32]);
+        fattr3(&mut lookup, NF3REG, 123_456);
+        lookup.u32(0);
+        let lookup = lookup.into_inner();
+        let (object, attributes) = parse_lookup(reply(4, &lookup).unwrap().unwrap()).unwrap();
+        assert_eq!(object.len(), 32);
+        assert_eq!(attributes, Some(Attributes { kind: NF3REG, size: 123_456 }));
+
+        let mut read = accepted(5);
+        read.u32(0);
+        fattr3(&mut read, NF3REG, 3);
+        read.u32(3).u32(1).opaque(b"MZ\x90");
+        let read = read.into_inner();
+        assert_eq!(parse_read(reply(5, &read).unwrap().unwrap()), Ok((&b"MZ\x90"[..], true)));
+
+        let mut stale = accepted(6);
+        stale.u32(70).u32(0);
+        let stale = stale.into_inner();
+        assert_eq!(parse_read(reply(6, &stale).unwrap().unwrap()), Err("stale file handle"));
+    }
+}
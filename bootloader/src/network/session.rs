@@ -0,0 +1,165 @@
+use crate::network::agent::{self, Identity};
+use crate::network::client::HttpClient;
+use crate::network::cookie::Jar;
+use crate::network::url::{Scheme, Url};
+use crate::util::platform::Arch;
+use crate::util::Result;
+use alloc::format;
+use alloc::string::String;
+use heapless::Vec;
+use uefi::Handle;
+
+/// Servers whose HTTP instance is kept open at the same time
+const MAX_POOLED: usize = 4;
+
+/// An idle HTTP instance and the server its connection goes to
+struct Pooled {
+    authority: String,
+    client: HttpClient,
+}
+
+/// Network state shared by every transfer until the next chainload
+///
+/// The interface is brought up once, and HTTP instances are kept per
+/// server so that consecutive downloads from the same host reuse the open
+/// connection. Name lookups need no cache of their own: the firmware's HTTP
+/// driver resolves through DNS4, which caches answers for their TTL.
+///
+/// Cookies set by servers are kept too, so an artifact store that hands out
+/// a session cookie on a redirect gets it back on the next request.
+struct Session {
+    nic: Handle,
+    idle: Vec<Pooled, MAX_POOLED>,
+    cookies: Jar,
+    /// User-Agent for every request, filled in once for the session's NIC
+    user_agent: String,
+}
+
+/// Current session (None until the network is first used)
+static mut SESSION: Option<Session> = None;
+
+/// Pool key: scheme, host and port
+fn authority(url: &Url) -> String {
+    format!("{}://{}:{}", url.scheme.as_str(), url.host, url.port).to_ascii_lowercase()
+}
+
+/// Interface of the session, initializing the network on first use
+pub fn nic() -> Result<Handle> {
+    if let Some(session) = unsafe { SESSION.as_ref() } {
+        return Ok(session.nic);
+    }
+
+    let nic = crate::network::init::initialize_network()?;
+    unsafe {
+        SESSION = Some(Session { nic, idle: Vec::new(), cookies: Jar::default(), user_agent: user_agent_for(nic) });
+    }
+    Ok(nic)
+}
+
+/// Take an HTTP instance for this server, reusing an idle one if possible
+///
+/// Returns whether the instance was reused, since its connection may have
+/// been closed by the server in the meantime.
+pub fn checkout(url: &Url) -> Result<(HttpClient, bool)> {
+    let nic = nic()?;
+    let key = authority(url);
+
+    if let Some(session) = unsafe { SESSION.as_mut() } {
+        if let Some(i) = session.idle.iter().position(|p| p.authority == key) {
+            return Ok((session.idle.swap_remove(i).client, true));
+        }
+    }
+
+    Ok((HttpClient::new(nic, ipv6(url))?, false))
+}
+
+/// Whether a request to this URL goes over IPv6
+///
+/// IPv6 addresses in URLs always do, IPv4 addresses never; host names do
+/// when `ip6=true`, so the firmware looks up their AAAA records.
+pub fn ipv6(url: &Url) -> bool {
+    url.ipv6().is_some()
+        || (crate::network::addr::parse_ipv4(url.host).is_err()
+            && crate::storage::with_config(|config| config.ip6).unwrap_or(false))
+}
+
+/// Return an HTTP instance whose last response was read to the end
+pub fn checkin(url: &Url, client: HttpClient) {
+    let Some(session) = (unsafe { SESSION.as_mut() }) else {
+        return;
+    };
+
+    // Drop the least recently used server when full
+    if session.idle.is_full() {
+        session.idle.remove(0);
+    }
+    let _ = session.idle.push(Pooled {
+        authority: authority(url),
+        client,
+    });
+}
+
+/// Number of idle connections, for status output
+pub fn idle_count() -> usize {
+    unsafe { SESSION.as_ref() }.map_or(0, |s| s.idle.len())
+}
+
+/// Expand the configured User-Agent template for this machine and NIC
+fn user_agent_for(nic: Handle) -> String {
+    let template = crate::storage::with_config(|config| config.user_agent.as_deref().map(String::from))
+        .flatten()
+        .unwrap_or_else(|| String::from(agent::DEFAULT_TEMPLATE));
+    let system = crate::util::smbios::system_info();
+    let uuid = system.as_ref().map(|info| info.uuid_string());
+    let mac = crate::network::init::list_interfaces()
+        .ok()
+        .and_then(|interfaces| interfaces.iter().find(|i| i.handle == nic).map(|i| i.mac));
+
+    agent::expand(
+        &template,
+        &Identity {
+            version: crate::util::version::VERSION,
+            arch: Arch::CURRENT.map(|arch| arch.as_str()),
+            uuid: uuid.as_deref(),
+            mac,
+        },
+    )
+}
+
+/// User-Agent header value (just the name before the network is up)
+pub fn user_agent() -> String {
+    unsafe { SESSION.as_ref() }.map_or_else(|| String::from("uefipxe"), |s| s.user_agent.clone())
+}
+
+/// Cookie header value for a request to this URL
+pub fn cookie_header(url: &Url) -> Option<String> {
+    let session = unsafe { SESSION.as_ref() }?;
+    session.cookies.header(url.host, url.path, url.scheme == Scheme::Https)
+}
+
+/// Keep the cookies a response to this URL set
+pub fn store_cookies(url: &Url, set_cookies: &[String]) {
+    let Some(session) = (unsafe { SESSION.as_mut() }) else {
+        return;
+    };
+    // Expires dates are only compared by year
+    let this_year = crate::util::rtc::now().map_or(0, |t| t.year);
+    for header in set_cookies {
+        session.cookies.store(header, url.host, url.path, this_year);
+    }
+}
+
+/// Number of cookies held, for status output
+pub fn cookie_count() -> usize {
+    unsafe { SESSION.as_ref() }.map_or(0, |s| s.cookies.count())
+}
+
+/// End the session, destroying all HTTP instances and forgetting cookies
+///
+/// Called before an image is started and whenever the network settings
+/// change, so the next transfer brings the interface up again.
+pub fn close() {
+    unsafe {
+        SESSION = None;
+    }
+}
@@ -0,0 +1,137 @@
+use crate::network::child::{RawProtocol, ServiceChild};
+use crate::network::client::Completion;
+use crate::util::{Error, Result};
+use core::ffi::c_void;
+use core::ptr;
+use core::time::Duration;
+use uefi::boot;
+use uefi::{Guid, Handle, Status};
+use uefi_raw::{Boolean, Ipv4Address};
+
+/// TCP4 Service Binding Protocol GUID
+/// {00720665-67EB-4A99-BAF7-D3C33A1C7CC9}
+const TCP4_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0x65, 0x06, 0x72, 0x00, 0xeb, 0x67, 0x99, 0x4a,
+    0xba, 0xf7, 0xd3, 0xc3, 0x3a, 0x1c, 0x7c, 0xc9,
+]);
+
+/// TCP4 Protocol GUID
+/// {65530BC7-A359-410F-B010-5AADC7EC2B62}
+const TCP4_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0xc7, 0x0b, 0x53, 0x65, 0x59, 0xa3, 0x0f, 0x41,
+    0xb0, 0x10, 0x5a, 0xad, 0xc7, 0xec, 0x2b, 0x62,
+]);
+
+/// IP time to live of the connection's packets
+const TIME_TO_LIVE: u8 = 64;
+
+#[repr(C)]
+struct Tcp4AccessPoint {
+    use_default_address: Boolean,
+    station_address: Ipv4Address,
+    subnet_mask: Ipv4Address,
+    station_port: u16,
+    remote_address: Ipv4Address,
+    remote_port: u16,
+    active_flag: Boolean,
+}
+
+#[repr(C)]
+struct Tcp4ConfigData {
+    type_of_service: u8,
+    time_to_live: u8,
+    access_point: Tcp4AccessPoint,
+    /// Null = the driver's defaults
+    control_option: *const c_void,
+}
+
+#[repr(C)]
+struct Tcp4CompletionToken {
+    event: uefi_raw::Event,
+    status: Status,
+}
+
+#[repr(C)]
+struct Tcp4Protocol {
+    _get_mode_data: unsafe extern "efiapi" fn() -> Status,
+    configure: unsafe extern "efiapi" fn(this: *mut Tcp4Protocol, config: *const Tcp4ConfigData) -> Status,
+    _routes: unsafe extern "efiapi" fn() -> Status,
+    connect: unsafe extern "efiapi" fn(this: *mut Tcp4Protocol, token: *mut Tcp4CompletionToken) -> Status,
+    _accept: unsafe extern "efiapi" fn() -> Status,
+    _transmit: unsafe extern "efiapi" fn() -> Status,
+    _receive: unsafe extern "efiapi" fn() -> Status,
+    _close: unsafe extern "efiapi" fn() -> Status,
+    cancel: unsafe extern "efiapi" fn(this: *mut Tcp4Protocol, token: *mut Tcp4CompletionToken) -> Status,
+    poll: unsafe extern "efiapi" fn(this: *mut Tcp4Protocol) -> Status,
+}
+
+/// Whether a TCP connection to `address:port` can be opened, within
+/// `timeout`
+///
+/// The connection is reset again straight away, nothing is sent over it.
+/// A refused connection fails with Error::Uefi(CONNECTION_REFUSED), no
+/// answer at all with Error::Uefi(TIMEOUT); firmware without TCP4 with
+/// Error::Unsupported.
+pub fn probe(nic_handle: Handle, address: [u8; 4], port: u16, timeout: Duration) -> Result<()> {
+    let child = match ServiceChild::create(nic_handle, &TCP4_SERVICE_BINDING_GUID) {
+        Err(Error::Uefi(Status::UNSUPPORTED)) | Err(Error::Uefi(Status::NOT_FOUND)) => return Err(Error::Unsupported),
+        child => child?,
+    };
+    let tcp = RawProtocol::<Tcp4Protocol>::open(child.handle(), &TCP4_PROTOCOL_GUID)?;
+    let tcp_ptr = tcp.as_ptr();
+
+    let config = Tcp4ConfigData {
+        type_of_service: 0,
+        time_to_live: TIME_TO_LIVE,
+        access_point: Tcp4AccessPoint {
+            use_default_address: Boolean::TRUE,
+            station_address: Ipv4Address([0; 4]),
+            subnet_mask: Ipv4Address([0; 4]),
+            station_port: 0,
+            remote_address: Ipv4Address(address),
+            remote_port: port,
+            active_flag: Boolean::TRUE,
+        },
+        control_option: ptr::null(),
+    };
+    let status = unsafe { ((*tcp_ptr).configure)(tcp_ptr, &config) };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+
+    let completion = Completion::new()?;
+    let mut token = Tcp4CompletionToken { event: completion.0.as_ptr(), status: Status::SUCCESS };
+    let status = unsafe { ((*tcp_ptr).connect)(tcp_ptr, &mut token) };
+    let result = if status.is_error() { Err(Error::Uefi(status)) } else { wait(tcp_ptr, &completion, timeout) };
+    if result.is_err() {
+        unsafe {
+            let _ = ((*tcp_ptr).cancel)(tcp_ptr, &mut token);
+        }
+    }
+
+    // Unconfiguring resets the connection, if there is one
+    unsafe {
+        let _ = ((*tcp_ptr).configure)(tcp_ptr, ptr::null());
+    }
+    result?;
+    match token.status {
+        Status::SUCCESS => Ok(()),
+        status => Err(Error::Uefi(status)),
+    }
+}
+
+/// Wait for the connect call to complete, driving the driver meanwhile
+fn wait(tcp_ptr: *mut Tcp4Protocol, completion: &Completion, timeout: Duration) -> Result<()> {
+    let mut waited = Duration::ZERO;
+    while !completion.is_signaled() {
+        if waited >= timeout {
+            return Err(Error::Uefi(Status::TIMEOUT));
+        }
+        unsafe {
+            let _ = ((*tcp_ptr).poll)(tcp_ptr);
+        }
+        boot::stall(Duration::from_millis(10));
+        waited += Duration::from_millis(10);
+    }
+    Ok(())
+}
@@ -0,0 +1,506 @@
+use crate::network::addr::{self, Ipv4};
+use crate::network::child::{RawProtocol, ServiceChild};
+use crate::network::session;
+use crate::network::url::{self, Scheme};
+use crate::util::caps::{self, Capability};
+use crate::util::metrics::{Phase, Timer};
+use crate::util::wipe::Wiped;
+use crate::util::{Error, Result};
+use crate::{detail, say};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::ops::RangeInclusive;
+use core::ptr;
+use uefi::{println, Guid, Status};
+use uefi_raw::{Boolean, Ipv4Address};
+
+/// MTFTP4 Service Binding Protocol GUID
+/// {2FE800BE-8F01-4AA6-946B-D71388E1833F}
+const MTFTP4_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0xbe, 0x00, 0xe8, 0x2f, 0x01, 0x8f, 0xa6, 0x4a,
+    0x94, 0x6b, 0xd7, 0x13, 0x88, 0xe1, 0x83, 0x3f,
+]);
+
+/// MTFTP4 Protocol GUID
+/// {78247C57-63DB-4708-99C2-A8B4A9A61F6B}
+const MTFTP4_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0x57, 0x7c, 0x24, 0x78, 0xdb, 0x63, 0x08, 0x47,
+    0x99, 0xc2, 0xa8, 0xb4, 0xa9, 0xa6, 0x1f, 0x6b,
+]);
+
+#[repr(C)]
+struct Mtftp4ConfigData {
+    use_default_setting: Boolean,
+    station_ip: Ipv4Address,
+    subnet_mask: Ipv4Address,
+    local_port: u16,
+    gateway_ip: Ipv4Address,
+    server_ip: Ipv4Address,
+    initial_server_port: u16,
+    try_count: u16,
+    /// Seconds to wait for each packet
+    timeout_value: u16,
+}
+
+#[repr(C)]
+struct Mtftp4Option {
+    option_str: *const u8,
+    value_str: *const u8,
+}
+
+/// Called for every packet the driver accepts; an error status aborts
+type CheckPacket =
+    unsafe extern "efiapi" fn(this: *mut Mtftp4Protocol, token: *mut Mtftp4Token, length: u16, packet: *const u8) -> Status;
+
+#[repr(C)]
+struct Mtftp4Token {
+    status: Status,
+    /// Null makes ReadFile blocking
+    event: uefi_raw::Event,
+    override_data: *const c_void,
+    filename: *const u8,
+    mode_str: *const u8,
+    option_list: *const Mtftp4Option,
+    option_count: u32,
+    buffer_size: u64,
+    /// Null hands the data to `check_packet` only
+    buffer: *mut c_void,
+    context: *mut c_void,
+    check_packet: Option<CheckPacket>,
+    timeout_callback: Option<unsafe extern "efiapi" fn(this: *mut Mtftp4Protocol, token: *mut Mtftp4Token) -> Status>,
+    packet_needed: Option<
+        unsafe extern "efiapi" fn(
+            this: *mut Mtftp4Protocol,
+            token: *mut Mtftp4Token,
+            length: *mut u16,
+            buffer: *mut *mut c_void,
+        ) -> Status,
+    >,
+}
+
+#[repr(C)]
+struct Mtftp4Protocol {
+    _get_mode_data: unsafe extern "efiapi" fn(this: *mut Mtftp4Protocol, mode: *mut c_void) -> Status,
+    configure: unsafe extern "efiapi" fn(this: *mut Mtftp4Protocol, config: *const Mtftp4ConfigData) -> Status,
+    _get_info: unsafe extern "efiapi" fn(
+        this: *mut Mtftp4Protocol,
+        override_data: *const c_void,
+        filename: *const u8,
+        mode_str: *const u8,
+        option_count: u8,
+        option_list: *const Mtftp4Option,
+        packet_length: *mut u32,
+        packet: *mut *mut c_void,
+    ) -> Status,
+    _parse_options: unsafe extern "efiapi" fn(
+        this: *mut Mtftp4Protocol,
+        packet_length: u32,
+        packet: *const c_void,
+        option_count: *mut u32,
+        option_list: *mut *mut Mtftp4Option,
+    ) -> Status,
+    read_file: unsafe extern "efiapi" fn(this: *mut Mtftp4Protocol, token: *mut Mtftp4Token) -> Status,
+    _write_file: unsafe extern "efiapi" fn(this: *mut Mtftp4Protocol, token: *mut Mtftp4Token) -> Status,
+    _read_directory: unsafe extern "efiapi" fn(this: *mut Mtftp4Protocol, token: *mut Mtftp4Token) -> Status,
+    _poll: unsafe extern "efiapi" fn(this: *mut Mtftp4Protocol) -> Status,
+}
+
+/// TFTP opcodes (RFC 1350, RFC 2347)
+const OPCODE_DATA: u16 = 3;
+const OPCODE_ERROR: u16 = 5;
+const OPCODE_OACK: u16 = 6;
+
+/// Block size asked for unless `tftp_blksize=` says otherwise: the most a
+/// 1500-byte Ethernet frame carries
+pub const DEFAULT_BLOCK_SIZE: u16 = 1468;
+
+/// Block sizes RFC 2348 allows
+pub const BLOCK_SIZES: RangeInclusive<u16> = 8..=65464;
+
+/// Blocks the server may send before waiting for an acknowledgement
+/// (RFC 7440) unless `tftp_windowsize=` says otherwise
+pub const DEFAULT_WINDOW_SIZE: u16 = 16;
+
+/// Window sizes accepted in the config; 1 is plain lock-step TFTP
+pub const WINDOW_SIZES: RangeInclusive<u16> = 1..=64;
+
+/// Transmissions of each packet before giving up
+const TRY_COUNT: u16 = 5;
+
+/// Seconds to wait for an answer to each transmission
+const TIMEOUT_SECS: u16 = 3;
+
+/// A file on a TFTP server, from a `tftp://` entry URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub server: Ipv4,
+    /// Server port (69 unless written out)
+    pub port: u16,
+    /// File name sent to the server: the URL path without its leading
+    /// slash (RFC 3617), so `tftp://host//abs` asks for `/abs`
+    pub file: &'a str,
+}
+
+impl<'a> Location<'a> {
+    /// Parse `tftp://<server ip>[:port]/<file>`
+    ///
+    /// The server has to be an IP address: host names are resolved by the
+    /// firmware's HTTP driver, which is not involved here.
+    pub fn parse(url: &'a str) -> Result<Self> {
+        let parsed = url::parse(url)?;
+        if parsed.scheme != Scheme::Tftp {
+            return Err(Error::InvalidArgument);
+        }
+        let server = addr::parse_ipv4(parsed.host).map_err(|_| Error::InvalidArgument)?;
+
+        let file = &parsed.path[1..];
+        if file.is_empty() || file.ends_with('/') || file.contains(['?', '#']) {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(Location { server, port: parsed.port, file })
+    }
+}
+
+/// Value of an option in an OACK packet's `name\0value\0` list
+pub fn option_value<'p>(options: &'p [u8], name: &str) -> Option<&'p str> {
+    let mut fields = options.split(|&b| b == 0);
+    while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+        if key.eq_ignore_ascii_case(name.as_bytes()) {
+            return core::str::from_utf8(value).ok();
+        }
+    }
+    None
+}
+
+/// Multicast group a server assigned (RFC 2090 `addr,port,mc`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Group {
+    /// Group address and port; left out of OACKs after the first
+    pub address: Option<(Ipv4, u16)>,
+    /// This client acknowledges blocks for the group
+    pub master: bool,
+}
+
+impl Group {
+    /// Parse the value of a `multicast` option in an OACK
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split(',');
+        let (address, port, master) = (fields.next()?, fields.next()?, fields.next()?);
+        if fields.next().is_some() {
+            return None;
+        }
+        let address = match (address, port) {
+            ("", "") => None,
+            (address, port) => Some((addr::parse_ipv4(address).ok()?, port.parse().ok()?)),
+        };
+        let master = match master {
+            "1" => true,
+            "0" => false,
+            _ => return None,
+        };
+        Some(Group { address, master })
+    }
+}
+
+/// Number of a block counted from the start of the file, for a 16-bit
+/// block number from the wire
+///
+/// Multicast clients join transfers under way and fill in what they missed
+/// later, so blocks come out of order and past the 65535 wrap-around. The
+/// candidate closest to the highest block seen so far is taken.
+pub fn absolute_block(block: u16, highest: u64) -> u64 {
+    let wraps = highest >> 16;
+    [wraps.saturating_sub(1), wraps, wraps + 1]
+        .iter()
+        .map(|&wrap| (wrap << 16) | u64::from(block))
+        .filter(|&candidate| candidate > 0)
+        .min_by_key(|&candidate| candidate.abs_diff(highest))
+        .unwrap_or(u64::from(block))
+}
+
+/// State shared with the packet callback
+#[derive(Default)]
+struct Receive {
+    /// Whatever arrived is wiped if the transfer is abandoned
+    data: Wiped,
+    /// Size announced by the server (tsize option)
+    size: Option<usize>,
+    /// Agreed block size, for placing multicast blocks
+    block_size: usize,
+    /// Set once the server accepted a multicast transfer
+    group: Option<Group>,
+    /// Highest block placed so far
+    highest_block: u64,
+    /// Bytes placed so far (multicast blocks fill `data` out of order)
+    received: usize,
+    /// Message from an ERROR packet
+    server_error: Option<String>,
+    /// Set when the data no longer fits in memory
+    out_of_memory: bool,
+}
+
+/// Collect the file as the driver accepts its blocks, in order
+unsafe extern "efiapi" fn check_packet(
+    _this: *mut Mtftp4Protocol,
+    token: *mut Mtftp4Token,
+    length: u16,
+    packet: *const u8,
+) -> Status {
+    let receive = unsafe { &mut *((*token).context as *mut Receive) };
+    let packet = unsafe { core::slice::from_raw_parts(packet, length as usize) };
+    if packet.len() < 4 {
+        return Status::SUCCESS;
+    }
+
+    match u16::from_be_bytes([packet[0], packet[1]]) {
+        // A multicast server sends another OACK whenever this client
+        // becomes the master client
+        OPCODE_OACK if receive.group.is_some() => {
+            let group = option_value(&packet[2..], "multicast").and_then(Group::parse);
+            if group.is_some_and(|group| group.master) {
+                detail!();
+                detail!("  Now acknowledging for the group");
+            }
+        }
+        OPCODE_OACK => {
+            let options = &packet[2..];
+            detail!(
+                "  Blocks of {} bytes, window of {}",
+                option_value(options, "blksize").unwrap_or("512"),
+                option_value(options, "windowsize").unwrap_or("1")
+            );
+            receive.block_size = option_value(options, "blksize").and_then(|size| size.parse().ok()).unwrap_or(512);
+            receive.size = option_value(options, "tsize").and_then(|size| size.parse().ok());
+            receive.group = option_value(options, "multicast").and_then(Group::parse);
+            if let Some(group) = receive.group {
+                match group.address {
+                    Some((address, port)) => detail!("  Multicast group {}:{}", address, port),
+                    None => detail!("  Multicast group"),
+                }
+                if group.master {
+                    detail!("  Acknowledging for the group");
+                }
+            }
+            if let Some(size) = receive.size {
+                detail!("  Size: {} bytes", size);
+                crate::util::splash::expect(size);
+                if receive.data.try_reserve_exact(size).is_err() {
+                    receive.out_of_memory = true;
+                    return Status::ABORTED;
+                }
+            }
+            // Out-of-order blocks go to their place in a buffer of the full size
+            if receive.group.is_some() {
+                match receive.size {
+                    Some(size) => receive.data.resize(size, 0),
+                    None => {
+                        println!("  Server gave no size for a multicast transfer");
+                        return Status::ABORTED;
+                    }
+                }
+            }
+        }
+        OPCODE_DATA if receive.group.is_some() => {
+            let block = absolute_block(u16::from_be_bytes([packet[2], packet[3]]), receive.highest_block);
+            let data = &packet[4..];
+            let offset = (block - 1) as usize * receive.block_size;
+            let Some(target) = receive.data.get_mut(offset..offset + data.len()) else {
+                return Status::ABORTED;
+            };
+            target.copy_from_slice(data);
+            receive.highest_block = receive.highest_block.max(block);
+            let previous = receive.received;
+            receive.received += data.len();
+            crate::util::ui::progress("Progress", receive.received, previous, receive.size);
+        }
+        OPCODE_DATA => {
+            let block = &packet[4..];
+            if receive.data.try_reserve(block.len()).is_err() {
+                receive.out_of_memory = true;
+                return Status::ABORTED;
+            }
+            let previous = receive.data.len();
+            receive.data.extend_from_slice(block);
+            crate::util::ui::progress("Progress", receive.data.len(), previous, receive.size);
+        }
+        OPCODE_ERROR => {
+            let message = packet[4..].split(|&b| b == 0).next().unwrap_or_default();
+            receive.server_error = Some(String::from_utf8_lossy(message).into_owned());
+        }
+        _ => {}
+    }
+    Status::SUCCESS
+}
+
+/// Download a file from a TFTP server
+///
+/// The firmware's MTFTP4 driver does the transfer; blocks are collected as
+/// they come, so files whose size the server does not announce work too.
+pub fn download(url: &str) -> Result<Vec<u8>> {
+    say!("Downloading: {}", url);
+    let location = Location::parse(url).map_err(|e| {
+        println!("  Invalid TFTP URL: {}", url);
+        println!("  Expected tftp://<server ip>[:port]/<path>");
+        e
+    })?;
+    caps::require(Capability::Mtftp4)?;
+    detail!("  Server: {}:{}", location.server, location.port);
+    let nic = session::nic()?;
+
+    let child = ServiceChild::create(nic, &MTFTP4_SERVICE_BINDING_GUID)?;
+    let mtftp = RawProtocol::<Mtftp4Protocol>::open(child.handle(), &MTFTP4_PROTOCOL_GUID)?;
+    let mtftp_ptr = mtftp.as_ptr();
+
+    let config = Mtftp4ConfigData {
+        use_default_setting: Boolean::TRUE,
+        station_ip: Ipv4Address([0; 4]),
+        subnet_mask: Ipv4Address([0; 4]),
+        local_port: 0,
+        gateway_ip: Ipv4Address([0; 4]),
+        server_ip: Ipv4Address(location.server.0),
+        initial_server_port: location.port,
+        try_count: TRY_COUNT,
+        timeout_value: TIMEOUT_SECS,
+    };
+    let status = unsafe { ((*mtftp_ptr).configure)(mtftp_ptr, &config) };
+    if status.is_error() {
+        return Err(Error::Uefi(status));
+    }
+
+    let file_name: Vec<u8> = location.file.bytes().chain(core::iter::once(0)).collect();
+    let (block_size, multicast, window_size) =
+        crate::storage::with_config(|c| (c.tftp_blksize, c.tftp_multicast, c.tftp_windowsize)).unwrap_or_default();
+    let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+    // RFC 2090 transfers are lock-step; windows are for unicast only
+    let window_size = if multicast { 1 } else { window_size.unwrap_or(DEFAULT_WINDOW_SIZE) };
+    let block_value = alloc::format!("{}\0", block_size);
+    let window_value = alloc::format!("{}\0", window_size);
+    let mut options = alloc::vec![
+        Mtftp4Option { option_str: b"blksize\0".as_ptr(), value_str: block_value.as_ptr() },
+        Mtftp4Option { option_str: b"tsize\0".as_ptr(), value_str: b"0\0".as_ptr() },
+    ];
+    if window_size > 1 {
+        options.push(Mtftp4Option { option_str: b"windowsize\0".as_ptr(), value_str: window_value.as_ptr() });
+    }
+    if multicast {
+        options.push(Mtftp4Option { option_str: b"multicast\0".as_ptr(), value_str: b"\0".as_ptr() });
+    }
+    let mut receive = Receive::default();
+
+    let timer = Timer::start();
+    let mut status = read_file(mtftp_ptr, &file_name, &options, &mut receive);
+    // Drivers that do not know an option refuse it before sending
+    if status == Status::UNSUPPORTED && options.len() > 2 && receive.data.is_empty() {
+        if multicast {
+            println!("  Firmware does not support multicast, downloading on its own");
+        } else {
+            println!("  Firmware does not support windowsize, sending one block at a time");
+        }
+        options.pop();
+        status = read_file(mtftp_ptr, &file_name, &options, &mut receive);
+    }
+    if multicast && !status.is_error() && receive.group.is_none() {
+        println!("  Server does not support multicast, downloaded on its own");
+    }
+    crate::util::ui::progress_done();
+    timer.stop(Phase::Download);
+
+    if status.is_error() {
+        println!("  Transfer failed after {} bytes: {}", receive.data.len(), describe(status, &receive));
+        return Err(if receive.out_of_memory { Error::OutOfMemory } else { Error::Uefi(status) });
+    }
+    if let Some(size) = receive.size.filter(|&size| size != receive.data.len()) {
+        println!("  Short download: {} of {} bytes", receive.data.len(), size);
+        return Err(Error::Io);
+    }
+
+    say!("  Download complete: {} bytes total", receive.data.len());
+    Ok(receive.data.into_inner())
+}
+
+/// Read a file, handing every packet to `check_packet`
+///
+/// Blocking without an event: returns when the transfer is over.
+fn read_file(mtftp: *mut Mtftp4Protocol, file_name: &[u8], options: &[Mtftp4Option], receive: &mut Receive) -> Status {
+    let mut token = Mtftp4Token {
+        status: Status::SUCCESS,
+        event: ptr::null_mut(),
+        override_data: ptr::null(),
+        filename: file_name.as_ptr(),
+        mode_str: b"octet\0".as_ptr(),
+        option_list: options.as_ptr(),
+        option_count: options.len() as u32,
+        buffer_size: 0,
+        buffer: ptr::null_mut(),
+        context: receive as *mut Receive as *mut c_void,
+        check_packet: Some(check_packet),
+        timeout_callback: None,
+        packet_needed: None,
+    };
+    unsafe { ((*mtftp).read_file)(mtftp, &mut token) }
+}
+
+/// Why a transfer failed, in words
+fn describe(status: Status, receive: &Receive) -> String {
+    if receive.out_of_memory {
+        return String::from("out of memory");
+    }
+    match (&receive.server_error, status) {
+        (Some(message), _) => alloc::format!("server said \"{}\"", message),
+        (None, Status::TIMEOUT) => String::from("no answer from the server"),
+        (None, Status::ICMP_ERROR) => String::from("server unreachable"),
+        (None, status) => alloc::format!("{:?}", status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_location() {
+        let location = Location::parse("tftp://10.0.0.5/pxe/grubx64.efi").unwrap();
+        assert_eq!(location.server, Ipv4([10, 0, 0, 5]));
+        assert_eq!(location.port, 69);
+        assert_eq!(location.file, "pxe/grubx64.efi");
+        assert_eq!(Location::parse("tftp://10.0.0.5:6969//srv/x.efi").unwrap().file, "/srv/x.efi");
+
+        assert!(Location::parse("tftp://tftp.example.com/x.efi").is_err());
+        assert!(Location::parse("tftp://10.0.0.5/").is_err());
+        assert!(Location::parse("tftp://10.0.0.5/pxe/").is_err());
+        assert!(Location::parse("http://10.0.0.5/x.efi").is_err());
+    }
+
+    #[test]
+    fn test_parse_group() {
+        assert_eq!(
+            Group::parse("239.1.1.1,1758,1"),
+            Some(Group { address: Some((Ipv4([239, 1, 1, 1]), 1758)), master: true })
+        );
+        assert_eq!(Group::parse(",,0"), Some(Group { address: None, master: false }));
+        assert_eq!(Group::parse("239.1.1.1,1758"), None);
+        assert_eq!(Group::parse("239.1.1.1,port,1"), None);
+        assert_eq!(Group::parse("239.1.1.1,1758,2"), None);
+    }
+
+    #[test]
+    fn test_absolute_block() {
+        assert_eq!(absolute_block(1, 0), 1);
+        assert_eq!(absolute_block(40, 500), 40);
+        // Past the wrap-around, and a late block from just before it
+        assert_eq!(absolute_block(3, 65530), 65539);
+        assert_eq!(absolute_block(65534, 65540), 65534);
+        assert_eq!(absolute_block(0, 65535), 65536);
+    }
+
+    #[test]
+    fn test_option_value() {
+        let oack = b"blksize\x001468\x00TSIZE\x0012345\x00";
+        assert_eq!(option_value(oack, "tsize"), Some("12345"));
+        assert_eq!(option_value(oack, "blksize"), Some("1468"));
+        assert_eq!(option_value(oack, "timeout"), None);
+        assert_eq!(option_value(b"tsize", "tsize"), None);
+    }
+}
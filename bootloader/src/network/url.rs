@@ -0,0 +1,331 @@
+use crate::network::addr::{self, Ipv6};
+use crate::util::{Error, Result};
+use alloc::string::String;
+
+/// URL schemes understood by the download backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Http,
+    Https,
+    Tftp,
+    /// File on a local volume (`file://<volume>/path`, empty volume = any)
+    File,
+    /// SAN disk attached through the firmware's iSCSI initiator
+    /// (`iscsi://<target ip>[:port]/<target name>[/<lun>]`)
+    Iscsi,
+    /// File on an NFS export, read over NFSv3/UDP
+    /// (`nfs://<server ip>[:port]/<path>`)
+    Nfs,
+}
+
+impl Scheme {
+    /// Parse a scheme name (case-insensitive)
+    fn parse(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("http") {
+            Some(Scheme::Http)
+        } else if name.eq_ignore_ascii_case("https") {
+            Some(Scheme::Https)
+        } else if name.eq_ignore_ascii_case("tftp") {
+            Some(Scheme::Tftp)
+        } else if name.eq_ignore_ascii_case("file") {
+            Some(Scheme::File)
+        } else if name.eq_ignore_ascii_case("iscsi") {
+            Some(Scheme::Iscsi)
+        } else if name.eq_ignore_ascii_case("nfs") {
+            Some(Scheme::Nfs)
+        } else {
+            None
+        }
+    }
+
+    /// Well-known port used when the URL does not specify one
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Scheme::Http => 80,
+            Scheme::Https => 443,
+            Scheme::Tftp => 69,
+            Scheme::File => 0,
+            Scheme::Iscsi => 3260,
+            Scheme::Nfs => 2049,
+        }
+    }
+
+    /// Scheme name as written in URLs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+            Scheme::Tftp => "tftp",
+            Scheme::File => "file",
+            Scheme::Iscsi => "iscsi",
+            Scheme::Nfs => "nfs",
+        }
+    }
+}
+
+/// A parsed URL borrowing from the original string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Url<'a> {
+    pub scheme: Scheme,
+    /// Host name or address, IPv6 addresses in brackets (volume label or
+    /// device path for `file://`)
+    pub host: &'a str,
+    /// Effective port (explicit, or the scheme default)
+    pub port: u16,
+    /// Whether the port was written out in the URL
+    pub explicit_port: bool,
+    /// Path including query, always starting with '/'
+    pub path: &'a str,
+}
+
+impl Url<'_> {
+    /// The host, when it is an IPv6 address
+    pub fn ipv6(&self) -> Option<Ipv6> {
+        let address = self.host.strip_prefix('[')?.strip_suffix(']')?;
+        addr::parse_ipv6(address).ok()
+    }
+}
+
+/// Parse and validate a URL of the form `scheme://host[:port][/path]`
+pub fn parse(url: &str) -> Result<Url<'_>> {
+    let (scheme_str, rest) = url.split_once("://").ok_or(Error::Parse)?;
+    let scheme = Scheme::parse(scheme_str).ok_or(Error::InvalidArgument)?;
+
+    let (authority, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/"),
+    };
+
+    // The authority of a file URL selects a volume and has no port
+    if scheme == Scheme::File {
+        if path == "/" || !authority.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+            return Err(Error::InvalidArgument);
+        }
+        return Ok(Url {
+            scheme,
+            host: authority,
+            port: 0,
+            explicit_port: false,
+            path,
+        });
+    }
+
+    // An IPv6 address is written in brackets, which stay part of the host
+    let (host, port_str) = if authority.starts_with('[') {
+        let end = authority.find(']').ok_or(Error::InvalidArgument)?;
+        let (host, rest) = authority.split_at(end + 1);
+        addr::parse_ipv6(&host[1..end]).map_err(|_| Error::InvalidArgument)?;
+        match rest {
+            "" => (host, None),
+            rest => (host, Some(rest.strip_prefix(':').ok_or(Error::InvalidArgument)?)),
+        }
+    } else {
+        let (host, port_str) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        };
+        if host.is_empty() || !host.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-') {
+            return Err(Error::InvalidArgument);
+        }
+        (host, port_str)
+    };
+
+    let (port, explicit_port) = match port_str {
+        Some(p) => {
+            let port = p.parse::<u16>().map_err(|_| Error::InvalidArgument)?;
+            if port == 0 {
+                return Err(Error::InvalidArgument);
+            }
+            (port, true)
+        }
+        None => (scheme.default_port(), false),
+    };
+
+    // An iSCSI URL names a target, not a file on a server
+    if scheme == Scheme::Iscsi && path == "/" {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(Url {
+        scheme,
+        host,
+        port,
+        explicit_port,
+        path,
+    })
+}
+
+/// Absolute form of a URL given relative to `base`: a full URL, a
+/// scheme-relative one (`//host/path`), an absolute path, or a path
+/// relative to the directory of `base`
+pub fn resolve(base: &str, location: &str) -> Result<String> {
+    let location = location.trim();
+    let resolved = if location.contains("://") {
+        String::from(location)
+    } else {
+        let (scheme, rest) = base.split_once("://").ok_or(Error::Parse)?;
+        let origin = &base[..scheme.len() + 3 + rest.find('/').unwrap_or(rest.len())];
+
+        if let Some(target) = location.strip_prefix("//") {
+            alloc::format!("{}://{}", scheme, target)
+        } else if location.starts_with('/') {
+            alloc::format!("{}{}", origin, location)
+        } else {
+            let path = parse(base)?.path.split(['?', '#']).next().unwrap_or("/");
+            let directory = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+            alloc::format!("{}{}{}", origin, directory, location)
+        }
+    };
+
+    parse(&resolved)?;
+    Ok(resolved)
+}
+
+/// Placeholder for this machine's architecture in entry URLs
+pub const ARCH_PLACEHOLDER: &str = "${arch}";
+
+/// `url` with the architecture appended to its file name, before the
+/// extension, as UEFI names its loaders (`boot.efi` becomes `bootx64.efi`)
+pub fn append_arch(url: &str, arch: &str) -> String {
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, tail) = url.split_at(end);
+    let name = path.rfind('/').map_or(0, |i| i + 1);
+    let at = match path[name..].rfind('.') {
+        Some(dot) if dot > 0 => name + dot,
+        _ => path.len(),
+    };
+    alloc::format!("{}{}{}{}", &path[..at], arch, &path[at..], tail)
+}
+
+/// `url` made absolute against `base`, when it is relative and there is
+/// a base (None for a full URL, which needs nothing done)
+pub fn relative_to(base: Option<&str>, url: &str) -> Option<String> {
+    if url.contains("://") {
+        return None;
+    }
+    resolve(base?, url).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_ports() {
+        let url = parse("http://boot.example.com/image.efi").unwrap();
+        assert_eq!(url.scheme, Scheme::Http);
+        assert_eq!(url.host, "boot.example.com");
+        assert_eq!(url.port, 80);
+        assert!(!url.explicit_port);
+        assert_eq!(url.path, "/image.efi");
+
+        assert_eq!(parse("https://example.com/").unwrap().port, 443);
+        assert_eq!(parse("tftp://10.0.0.1/pxe.efi").unwrap().port, 69);
+        assert_eq!(parse("nfs://10.0.0.1/srv/pxe.efi").unwrap().port, 2049);
+    }
+
+    #[test]
+    fn test_explicit_port() {
+        let url = parse("http://10.0.2.2:8080/test.efi").unwrap();
+        assert_eq!(url.host, "10.0.2.2");
+        assert_eq!(url.port, 8080);
+        assert!(url.explicit_port);
+    }
+
+    #[test]
+    fn test_ipv6_hosts() {
+        let url = parse("http://[2001:db8::5]:8080/image.efi").unwrap();
+        assert_eq!(url.host, "[2001:db8::5]");
+        assert_eq!(url.port, 8080);
+        assert_eq!(url.path, "/image.efi");
+        assert_eq!(url.ipv6().map(|a| a.0[15]), Some(5));
+
+        let url = parse("https://[::1]/x.efi").unwrap();
+        assert_eq!((url.host, url.port), ("[::1]", 443));
+        assert_eq!(parse("http://boot.example.com/").unwrap().ipv6(), None);
+
+        assert!(parse("http://[2001:db8::5/x.efi").is_err());
+        assert!(parse("http://[2001:db8::zz]/x.efi").is_err());
+        assert!(parse("http://[::1]8080/x.efi").is_err());
+        assert!(parse("http://2001:db8::5/x.efi").is_err());
+        assert_eq!(
+            resolve("http://[2001:db8::5]:8080/os/kernel", "initrd.img").unwrap(),
+            "http://[2001:db8::5]:8080/os/initrd.img"
+        );
+    }
+
+    #[test]
+    fn test_missing_path() {
+        let url = parse("http://example.com:8000").unwrap();
+        assert_eq!(url.port, 8000);
+        assert_eq!(url.path, "/");
+    }
+
+    #[test]
+    fn test_invalid_ports() {
+        assert!(parse("http://example.com:0/").is_err());
+        assert!(parse("http://example.com:65536/").is_err());
+        assert!(parse("http://example.com:http/").is_err());
+        assert!(parse("http://example.com:/").is_err());
+    }
+
+    #[test]
+    fn test_file_urls() {
+        let url = parse("file://USB-KEY/images/rescue.efi").unwrap();
+        assert_eq!(url.scheme, Scheme::File);
+        assert_eq!(url.host, "USB-KEY");
+        assert_eq!(url.path, "/images/rescue.efi");
+
+        assert_eq!(parse("file:///EFI/rescue.efi").unwrap().host, "");
+        assert!(parse("file://USB-KEY").is_err());
+        assert!(parse("file://USB:1/x.efi").is_err());
+    }
+
+    #[test]
+    fn test_iscsi_urls() {
+        let url = parse("iscsi://10.0.0.5/iqn.2010-04.org.example:node1/1").unwrap();
+        assert_eq!(url.scheme, Scheme::Iscsi);
+        assert_eq!(url.host, "10.0.0.5");
+        assert_eq!(url.port, 3260);
+        assert_eq!(url.path, "/iqn.2010-04.org.example:node1/1");
+
+        assert!(parse("iscsi://10.0.0.5:3261").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let base = "https://store.example.com:8443/dl/os/image.efi?token=1";
+        assert_eq!(resolve(base, "https://cdn.example.net/x.efi").unwrap(), "https://cdn.example.net/x.efi");
+        assert_eq!(resolve(base, "//cdn.example.net/x.efi").unwrap(), "https://cdn.example.net/x.efi");
+        assert_eq!(resolve(base, "/login?next=/dl").unwrap(), "https://store.example.com:8443/login?next=/dl");
+        assert_eq!(resolve(base, "signed/image.efi").unwrap(), "https://store.example.com:8443/dl/os/signed/image.efi");
+        assert_eq!(resolve("http://h", "x.efi").unwrap(), "http://h/x.efi");
+        assert!(resolve(base, "ftp://elsewhere/x.efi").is_err());
+    }
+
+    #[test]
+    fn test_append_arch() {
+        assert_eq!(append_arch("http://h/boot/shim.efi", "x64"), "http://h/boot/shimx64.efi");
+        assert_eq!(append_arch("http://h/boot/grub.efi?v=2", "aa64"), "http://h/boot/grubaa64.efi?v=2");
+        assert_eq!(append_arch("http://h/v1.2/vmlinuz", "riscv64"), "http://h/v1.2/vmlinuzriscv64");
+        assert_eq!(append_arch("images/.hidden", "x64"), "images/.hiddenx64");
+    }
+
+    #[test]
+    fn test_relative_to() {
+        let base = Some("http://10.0.0.5/boot/uefipxe.efi");
+        assert_eq!(relative_to(base, "images/kernel.efi").as_deref(), Some("http://10.0.0.5/boot/images/kernel.efi"));
+        assert_eq!(relative_to(base, "/images/kernel.efi").as_deref(), Some("http://10.0.0.5/images/kernel.efi"));
+        // Full URLs are left alone, and so is everything without a base
+        assert_eq!(relative_to(base, "https://other/kernel.efi"), None);
+        assert_eq!(relative_to(None, "images/kernel.efi"), None);
+    }
+
+    #[test]
+    fn test_invalid_urls() {
+        assert!(parse("example.com/image.efi").is_err());
+        assert!(parse("ftp://example.com/image.efi").is_err());
+        assert!(parse("http:///image.efi").is_err());
+        assert!(parse("http://exa mple.com/").is_err());
+    }
+}
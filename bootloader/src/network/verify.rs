@@ -1,9 +1,178 @@
-use crate::util::{Error, Result};
-use alloc::format;
+use crate::storage::{policy, signed};
+use crate::util::blake3::Blake3;
+use crate::util::sha256::Sha256;
+use crate::util::{authenticode, Error, Result};
+use crate::{detail, say};
+use alloc::boxed::Box;
 use alloc::string::String;
-use sha2::{Digest, Sha256};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use sha2::{Digest, Sha512};
 use uefi::println;
 
+/// Config key of the verifier bare values (and `signature=`) belong to
+pub const DEFAULT: &str = "sha256";
+
+/// Running hash of an image as it arrives
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    // Its chaining value stack makes it much larger than the others
+    Blake3(Box<Blake3>),
+}
+
+impl Hasher {
+    /// Feed more data
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Finish and return the digest
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha256(hasher) => Vec::from(hasher.finalize()),
+            Hasher::Sha512(hasher) => Vec::from(hasher.finalize().as_slice()),
+            Hasher::Blake3(hasher) => Vec::from(hasher.finalize()),
+        }
+    }
+}
+
+/// How a verifier checks an image against its configured value
+pub enum Method {
+    /// Hex digest of the image bytes, which can be hashed as they arrive
+    Hash { len: usize, start: fn() -> Hasher },
+    /// Hex digest that needs the complete image
+    Digest { len: usize, compute: fn(&[u8]) -> Result<Vec<u8>> },
+    /// Signature over the image by one of the policy's trusted keys
+    Signature { valid: fn(&str) -> bool, check: fn(&[u8], &str) -> Result<()> },
+}
+
+/// A way of verifying images, named by the config key carrying its value
+///
+/// The same name is what `algorithm=` lines in policy.txt accept.
+pub struct Verifier {
+    pub key: &'static str,
+    pub method: Method,
+}
+
+/// Every supported verifier
+///
+/// Adding an algorithm only takes an entry here: config.txt, policy.txt,
+/// boot and prefetch all look verifiers up by key.
+pub static VERIFIERS: [Verifier; 5] = [
+    Verifier {
+        key: "sha256",
+        method: Method::Hash { len: 32, start: || Hasher::Sha256(Sha256::new()) },
+    },
+    Verifier {
+        key: "sha512",
+        method: Method::Hash { len: 64, start: || Hasher::Sha512(Sha512::new()) },
+    },
+    Verifier {
+        key: "blake3",
+        method: Method::Hash { len: 32, start: || Hasher::Blake3(Box::default()) },
+    },
+    Verifier {
+        key: "ed25519",
+        method: Method::Signature {
+            valid: |value| signed::parse_signature(value).is_ok(),
+            check: check_ed25519,
+        },
+    },
+    Verifier {
+        // Authenticode digest, as listed in db/dbx, so a pin survives re-signing
+        key: "authenticode",
+        method: Method::Digest {
+            len: 32,
+            compute: |data| authenticode::digest(data).map(Vec::from),
+        },
+    },
+];
+
+fn check_ed25519(data: &[u8], value: &str) -> Result<()> {
+    let signature = signed::parse_signature(value)?;
+    signed::check(data, &signature, &policy::trusted_keys()).map(|_| ())
+}
+
+impl Verifier {
+    /// Whether a configured value is well-formed for this verifier
+    pub fn accepts(&self, value: &str) -> bool {
+        match &self.method {
+            Method::Hash { len, .. } | Method::Digest { len, .. } => {
+                value.len() == len * 2 && value.bytes().all(|b| b.is_ascii_hexdigit())
+            }
+            Method::Signature { valid, .. } => valid(value),
+        }
+    }
+
+    /// Digest of the whole image, for verifiers that compare one
+    fn digest(&self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        match &self.method {
+            Method::Hash { start, .. } => {
+                let mut hasher = start();
+                hasher.update(data);
+                Ok(Some(hasher.finalize()))
+            }
+            Method::Digest { compute, .. } => compute(data).map(Some),
+            Method::Signature { .. } => Ok(None),
+        }
+    }
+}
+
+/// Verifier for a config key
+pub fn find(key: &str) -> Option<&'static Verifier> {
+    VERIFIERS.iter().find(|verifier| verifier.key == key)
+}
+
+/// Split a stored signature into its verifier and value
+///
+/// Entries keep `<key>:<value>`, except that SHA256 values are stored bare
+/// as they always have been.
+pub fn split(signature: &str) -> (&'static Verifier, &str) {
+    signature
+        .split_once(':')
+        .and_then(|(key, value)| Some((find(key)?, value)))
+        .unwrap_or_else(|| (find(DEFAULT).unwrap(), signature))
+}
+
+/// Start hashing an image for a signature whose verifier allows it
+pub fn hasher(signature: &str) -> Option<Hasher> {
+    match split(signature).0.method {
+        Method::Hash { start, .. } => Some(start()),
+        _ => None,
+    }
+}
+
+/// Whether a digest from `hasher` matches the signature
+pub fn digest_matches(signature: &str, digest: &[u8]) -> bool {
+    to_hex(digest).eq_ignore_ascii_case(split(signature).1)
+}
+
+/// Check an image against a signature without printing anything
+pub fn check(data: &[u8], signature: &str) -> Result<()> {
+    let (verifier, value) = split(signature);
+    match verifier.digest(data)? {
+        Some(digest) if to_hex(&digest).eq_ignore_ascii_case(value) => Ok(()),
+        Some(_) => Err(Error::Io),
+        None => match verifier.method {
+            Method::Signature { check, .. } => check(data, value),
+            _ => Err(Error::Unsupported),
+        },
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes.iter() {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
 /// Compute SHA256 hash of data and return as lowercase hex string
 pub fn compute_sha256(data: &[u8]) -> String {
     let mut hasher = Sha256::new();
@@ -13,30 +182,35 @@ pub fn compute_sha256(data: &[u8]) -> String {
         hasher.update(chunk);
     }
 
-    let result = hasher.finalize();
-
-    // Convert to lowercase hex string
-    format!("{:x}", result)
+    to_hex(&hasher.finalize())
 }
 
-/// Verify that downloaded data matches expected SHA256 signature
+/// Verify that downloaded data matches its configured signature
 pub fn verify_signature(data: &[u8], expected_signature: &str) -> Result<()> {
-    println!("  Verifying signature...");
+    let (verifier, value) = split(expected_signature);
+    say!("  Verifying {} signature...", verifier.key);
 
-    // Compute actual hash
-    let actual_hash = compute_sha256(data);
+    let result = match verifier.digest(data)? {
+        Some(digest) => {
+            let actual = to_hex(&digest);
+            detail!("  Expected: {}", value);
+            detail!("  Actual:   {}", actual);
 
-    println!("  Expected: {}", expected_signature);
-    println!("  Actual:   {}", actual_hash);
+            // Compare signatures (case-insensitive)
+            if actual.eq_ignore_ascii_case(value) {
+                Ok(())
+            } else {
+                Err(Error::Io) // Use Io error for signature mismatch
+            }
+        }
+        None => check(data, expected_signature),
+    };
 
-    // Compare signatures (case-insensitive)
-    if actual_hash.eq_ignore_ascii_case(expected_signature) {
-        println!("  ✓ Signature verification passed");
-        Ok(())
-    } else {
-        println!("  ✗ Signature verification FAILED");
-        Err(Error::Io) // Use Io error for signature mismatch
+    match result {
+        Ok(()) => say!("  ✓ Signature verification passed"),
+        Err(_) => println!("  ✗ Signature verification FAILED"),
     }
+    result
 }
 
 #[cfg(test)]
@@ -74,4 +248,28 @@ mod tests {
         let expected = "invalid_hash";
         assert!(verify_signature(data, expected).is_err());
     }
+
+    #[test]
+    fn test_registry() {
+        let sha512 = "sha512:9b71d224bd62f3785d96d46ad3ea3d73319bfbc2890caadae2dff72519673ca7\
+                      2323c3d99ba5c11d7c7acc6e14b8c5da0c4663475c2e5c3adef46f73bcdec043";
+        let blake3 = "blake3:ea8f163db38682925e4491c5e58d4bb3506ef8c14eb78a86e908c5624a67200f";
+        for signature in [sha512, blake3] {
+            assert!(check(b"hello", signature).is_ok());
+            assert!(check(b"hellO", signature).is_err());
+
+            let mut hasher = hasher(signature).unwrap();
+            hasher.update(b"hel");
+            hasher.update(b"lo");
+            assert!(digest_matches(signature, &hasher.finalize()));
+        }
+
+        // Bare values, and prefixes that are not verifiers, are SHA256
+        assert_eq!(split("abcd").0.key, "sha256");
+        let (verifier, value) = split("md5:abcd");
+        assert_eq!((verifier.key, value), ("sha256", "md5:abcd"));
+        assert!(find("sha512").unwrap().accepts(&sha512[7..]));
+        assert!(!find("sha256").unwrap().accepts(&sha512[7..]));
+        assert!(hasher("authenticode:00").is_none());
+    }
 }
@@ -0,0 +1,339 @@
+use crate::network::verify;
+use crate::storage::file;
+use crate::util::global::Global;
+use crate::util::{rtc, Error, Result};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Directory holding one `<sha256>.img` per cached image
+pub const CACHE_DIR: &str = "\\EFI\\uefipxe\\cache";
+
+/// What is cached for each URL, one line per URL (see `Index::serialize`)
+const INDEX_NAME: &str = "index.txt";
+
+/// Length of a SHA-256 in hex
+const HASH_LEN: usize = 64;
+
+/// Written in place of an ETag or time the index does not know
+const UNKNOWN: &str = "-";
+
+/// The index, read from the ESP once and written back on every change
+static INDEX: Global<Index> = Global::new();
+
+/// The cached image of one URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub url: String,
+    /// SHA-256 of the image, which names its file
+    pub hash: String,
+    pub size: u64,
+    /// When it was stored and last booted (`rtc::stamp` form)
+    pub stored: Option<String>,
+    pub used: Option<String>,
+    /// Boots from the cached copy
+    pub hits: u32,
+    /// ETag the server gave the image, to tell whether it is still current
+    pub etag: Option<String>,
+}
+
+impl Entry {
+    /// One index line: `<sha256> <size> <stored> <used> <hits> <etag> <url>`
+    ///
+    /// Lines of older indexes, `<sha256> <url>`, still parse, with nothing
+    /// known but the hash.
+    fn parse(line: &str) -> Option<Self> {
+        let (hash, rest) = line.trim().split_once(' ')?;
+        if !is_hash(hash) {
+            return None;
+        }
+        let known = |field: &str| Some(String::from(field)).filter(|field| field != UNKNOWN);
+        let fields: Vec<&str> = rest.splitn(6, ' ').collect();
+        let entry = match fields.as_slice() {
+            [size, stored, used, hits, etag, url] if size.parse::<u64>().is_ok() => Entry {
+                url: String::from(url.trim()),
+                hash: hash.to_ascii_lowercase(),
+                size: size.parse().ok()?,
+                stored: known(stored),
+                used: known(used),
+                hits: hits.parse().ok()?,
+                etag: known(etag),
+            },
+            _ => Entry {
+                url: String::from(rest.trim()),
+                hash: hash.to_ascii_lowercase(),
+                size: 0,
+                stored: None,
+                used: None,
+                hits: 0,
+                etag: None,
+            },
+        };
+        Some(entry).filter(|entry| !entry.url.is_empty())
+    }
+}
+
+/// URLs and what is cached for each
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Index {
+    entries: Vec<Entry>,
+}
+
+impl Index {
+    /// Parse the file form; malformed lines are dropped
+    pub fn parse(text: &str) -> Self {
+        Index { entries: text.lines().filter_map(Entry::parse).collect() }
+    }
+
+    /// What is cached for `url`
+    pub fn lookup(&self, url: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.url == url)
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Point `url` at a newly stored image, returning the hash it replaces
+    /// if no other URL still uses it (so its file can go)
+    pub fn insert(&mut self, entry: Entry) -> Option<String> {
+        let hash = entry.hash.clone();
+        let old = match self.entries.iter_mut().find(|e| e.url == entry.url) {
+            Some(existing) => Some(core::mem::replace(existing, entry).hash),
+            None => {
+                self.entries.push(entry);
+                None
+            }
+        };
+        old.filter(|old| *old != hash && !self.entries.iter().any(|e| e.hash == *old))
+    }
+
+    /// Record the ETag of the image cached for `url`; whether it changed
+    pub fn set_etag(&mut self, url: &str, etag: &str) -> bool {
+        match self.entries.iter_mut().find(|e| e.url == url) {
+            Some(entry) if entry.etag.as_deref() != Some(etag) => {
+                entry.etag = Some(String::from(etag));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Count a boot of the copy cached for `url` at `now`
+    pub fn hit(&mut self, url: &str, now: Option<String>) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.url == url) {
+            entry.hits = entry.hits.saturating_add(1);
+            entry.used = now;
+        }
+    }
+
+    /// Forget `url`, returning its hash if no other URL uses it
+    pub fn remove(&mut self, url: &str) -> Option<String> {
+        let i = self.entries.iter().position(|e| e.url == url)?;
+        let old = self.entries.remove(i).hash;
+        Some(old).filter(|old| !self.entries.iter().any(|e| e.hash == *old))
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut text = String::new();
+        for entry in self.entries.iter() {
+            let _ = writeln!(
+                text,
+                "{} {} {} {} {} {} {}",
+                entry.hash,
+                entry.size,
+                entry.stored.as_deref().unwrap_or(UNKNOWN),
+                entry.used.as_deref().unwrap_or(UNKNOWN),
+                entry.hits,
+                entry.etag.as_deref().unwrap_or(UNKNOWN),
+                entry.url
+            );
+        }
+        text
+    }
+}
+
+/// Whether `s` is a SHA-256 in hex, as used in cache file names
+fn is_hash(s: &str) -> bool {
+    s.len() == HASH_LEN && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn index_path() -> String {
+    format!("{}\\{}", CACHE_DIR, INDEX_NAME)
+}
+
+fn image_path(hash: &str) -> String {
+    format!("{}\\{}.img", CACHE_DIR, hash)
+}
+
+/// The time for the index, if the clock can be read
+fn now() -> Option<String> {
+    rtc::now().ok().map(|time| rtc::stamp(&time))
+}
+
+/// Read the index from the ESP; a missing or unreadable one is empty
+///
+/// Called at startup; the index is read on first use otherwise.
+pub fn init() {
+    let index = file::read_file(&index_path())
+        .ok()
+        .and_then(|data| core::str::from_utf8(&data).ok().map(Index::parse))
+        .unwrap_or_default();
+    INDEX.set(index);
+}
+
+/// Run `f` with the index
+fn with_index<R>(f: impl FnOnce(&mut Index) -> R) -> R {
+    if INDEX.with(|_| ()).is_none() {
+        init();
+    }
+    INDEX.with_mut(f).expect("index loaded above")
+}
+
+fn save(index: &Index) -> Result<()> {
+    file::atomic_write(&index_path(), index.serialize().as_bytes())
+}
+
+/// What is cached, for `cache list`
+pub fn entries() -> Vec<Entry> {
+    with_index(|index| index.entries().to_vec())
+}
+
+/// The ETag of the copy cached for `url`, if the server gave one
+pub fn etag(url: &str) -> Option<String> {
+    with_index(|index| index.lookup(url).and_then(|entry| entry.etag.clone()))
+}
+
+/// Keep a verified image for `url`, replacing the one cached for it before
+///
+/// `etag` is the server's ETag for the image, if it sent one.
+pub fn store(url: &str, data: &[u8], etag: Option<&str>) -> Result<()> {
+    let hash = verify::compute_sha256(data);
+
+    // Already cached by an earlier boot, though the ETag may be news
+    if with_index(|index| index.lookup(url).is_some_and(|entry| entry.hash == hash.as_str())) {
+        return match etag {
+            Some(etag) => with_index(|index| if index.set_etag(url, etag) { save(index) } else { Ok(()) }),
+            None => Ok(()),
+        };
+    }
+
+    file::atomic_write(&image_path(&hash), data)?;
+
+    let entry = Entry {
+        url: String::from(url),
+        hash: String::from(hash.as_str()),
+        size: data.len() as u64,
+        stored: now(),
+        used: None,
+        hits: 0,
+        etag: etag.map(String::from),
+    };
+    let (stale, saved) = with_index(|index| {
+        let stale = index.insert(entry);
+        (stale, save(index))
+    });
+    saved?;
+    if let Some(stale) = stale {
+        let _ = file::delete(&image_path(&stale));
+    }
+    Ok(())
+}
+
+/// The image cached for `url`, if it is there and intact; counts a hit
+///
+/// The caller still verifies it against the configured signature, which
+/// may have moved on to a newer image since it was cached.
+pub fn load(url: &str) -> Result<Vec<u8>> {
+    let hash = with_index(|index| index.lookup(url).map(|entry| entry.hash.clone())).ok_or(Error::NotFound)?;
+    let data = match file::read_image(None, &image_path(&hash)) {
+        Ok(data) if verify::compute_sha256(&data) == hash.as_str() => data,
+        result => {
+            // Gone or damaged on the ESP; don't offer it again
+            let _ = file::delete(&image_path(&hash));
+            with_index(|index| {
+                index.remove(url);
+                let _ = save(index);
+            });
+            return Err(result.err().unwrap_or(Error::Io));
+        }
+    };
+    with_index(|index| {
+        index.hit(url, now());
+        let _ = save(index);
+    });
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH_A: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const HASH_B: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn entry(url: &str, hash: &str) -> Entry {
+        Entry {
+            url: String::from(url),
+            hash: String::from(hash),
+            size: 0,
+            stored: None,
+            used: None,
+            hits: 0,
+            etag: None,
+        }
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let text = alloc::format!(
+            "{} http://a/x.efi\nnot a line\n{} 1024 20261016-093000 - 3 \"v1\" tftp://b/y.efi\n",
+            HASH_A,
+            HASH_B
+        );
+        let index = Index::parse(&text);
+        assert_eq!(index.lookup("http://a/x.efi"), Some(&entry("http://a/x.efi", HASH_A)));
+        let b = index.lookup("tftp://b/y.efi").unwrap();
+        assert_eq!((b.hash.as_str(), b.size, b.hits), (HASH_B, 1024, 3));
+        assert_eq!(
+            (b.stored.as_deref(), b.used.as_deref(), b.etag.as_deref()),
+            (Some("20261016-093000"), None, Some("\"v1\""))
+        );
+        assert_eq!(index.lookup("http://c/z.efi"), None);
+        assert_eq!(Index::parse(&index.serialize()), index);
+    }
+
+    #[test]
+    fn test_index_insert() {
+        let mut index = Index::default();
+        assert_eq!(index.insert(entry("http://a/x.efi", HASH_A)), None);
+        assert_eq!(index.insert(entry("http://b/x.efi", HASH_A)), None);
+
+        // Still used by the other URL, so the file stays
+        assert_eq!(index.insert(entry("http://a/x.efi", HASH_B)), None);
+        assert_eq!(index.lookup("http://a/x.efi").map(|e| e.hash.as_str()), Some(HASH_B));
+
+        // The last URL using it moves on
+        assert_eq!(index.insert(entry("http://b/x.efi", HASH_B)), Some(String::from(HASH_A)));
+        assert_eq!(index.insert(entry("http://b/x.efi", HASH_B)), None);
+
+        assert_eq!(index.remove("http://a/x.efi"), None);
+        assert_eq!(index.remove("http://b/x.efi"), Some(String::from(HASH_B)));
+        assert_eq!(index.remove("http://b/x.efi"), None);
+    }
+
+    #[test]
+    fn test_index_hits() {
+        let mut index = Index::default();
+        index.insert(entry("http://a/x.efi", HASH_A));
+        index.hit("http://a/x.efi", Some(String::from("20261016-093000")));
+        index.hit("http://a/x.efi", None);
+        index.hit("http://b/x.efi", None);
+        assert_eq!(index.lookup("http://a/x.efi").map(|e| e.hits), Some(2));
+
+        assert!(index.set_etag("http://a/x.efi", "\"v2\""));
+        assert!(!index.set_etag("http://a/x.efi", "\"v2\""));
+        assert!(!index.set_etag("http://b/x.efi", "\"v2\""));
+    }
+}
@@ -1,34 +1,331 @@
+use crate::boot::entry_type::EntryType;
+use crate::cli::history::MAX_ENTRIES as MAX_HISTORY;
+use crate::cli::keymap::Layout;
+use crate::network::addr::{self, Ipv4, Mac};
+use crate::network::agent;
+use crate::network::dhcp::OfferPolicy;
+use crate::network::{tftp, url, verify};
+use crate::storage::Store;
+use crate::util::beep::BeepMode;
+use crate::util::logger::Echo;
+use crate::util::password::{self, PasswordHash};
+use crate::util::platform::{Arch, Requirements, Revision};
+use crate::util::ui::UiMode;
 use crate::util::{Error, Result};
 use heapless::{String, Vec};
 use core::fmt::Write;
 
 /// Maximum number of image URLs that can be stored
-pub const MAX_URLS: usize = 16;
+pub const MAX_URLS: usize = 64;
 
 /// Maximum length of a URL
 pub const MAX_URL_LEN: usize = 256;
 
-/// Maximum length of a signature (hex-encoded SHA256 = 64 chars)
-pub const MAX_SIGNATURE_LEN: usize = 128;
+/// Maximum length of a stored signature (`ed25519:<key name> <128 hex>`)
+pub const MAX_SIGNATURE_LEN: usize = 192;
+
+/// Maximum length of an entry name
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Maximum number of tags per entry
+pub const MAX_TAGS: usize = 4;
+
+/// Maximum length of a tag
+pub const MAX_TAG_LEN: usize = 24;
+
+/// Maximum size of the serialized configuration
+pub const MAX_CONFIG_LEN: usize = 16384;
+
+/// Maximum length of a `volume=` selector
+pub const MAX_VOLUME_LEN: usize = 128;
+
+/// Maximum length of a `shim=` path
+pub const MAX_SHIM_PATH_LEN: usize = 128;
+
+/// Maximum length of a `cmdline=` value
+pub const MAX_CMDLINE_LEN: usize = 512;
+
+/// Maximum length of an entry's `pre=` or `on_fail=` lines together
+pub const MAX_HOOK_LEN: usize = 256;
+
+/// Maximum length of a `host_override=` name
+pub const MAX_HOST_LEN: usize = 128;
+
+/// Maximum length of a `user_agent=` template
+pub const MAX_AGENT_LEN: usize = 128;
+
+/// Maximum number of DNS servers for a static setup
+pub const MAX_DNS_SERVERS: usize = 2;
+
+/// Maximum length of an iSCSI name (RFC 3720)
+pub const MAX_IQN_LEN: usize = 223;
+
+/// Maximum length of a CHAP secret the firmware's initiator accepts
+pub const MAX_CHAP_SECRET_LEN: usize = 16;
+
+/// Shortest CHAP secret allowed by RFC 3720
+const MIN_CHAP_SECRET_LEN: usize = 12;
+
+/// How the firmware's iSCSI initiator logs in to `iscsi://` targets
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IscsiInitiator {
+    /// Initiator name (empty = keep the firmware's)
+    pub name: String<MAX_IQN_LEN>,
+    /// CHAP user name (empty = no authentication)
+    pub chap_user: String<MAX_IQN_LEN>,
+    /// CHAP secret, set together with the user name
+    pub chap_secret: String<MAX_CHAP_SECRET_LEN>,
+}
+
+/// Static IPv4 settings (used instead of DHCP when present)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticIp {
+    pub address: Ipv4,
+    pub netmask: Ipv4,
+    pub gateway: Option<Ipv4>,
+    pub dns: Vec<Ipv4, MAX_DNS_SERVERS>,
+}
+
+/// Descriptive metadata of an image entry
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryMeta {
+    /// Human-readable name (empty = none)
+    pub name: String<MAX_NAME_LEN>,
+    /// What the URL points at (None = implied by the other fields)
+    pub entry_type: Option<EntryType>,
+    /// Free-form tags used for filtering
+    pub tags: Vec<String<MAX_TAG_LEN>, MAX_TAGS>,
+    /// Declared image version, checked against the rollback floor
+    pub version: Option<u32>,
+    /// Left out of the fallback rotation and prefetch until enabled again
+    pub disabled: bool,
+    /// What the machine must offer before the image is downloaded
+    pub requires: Requirements,
+    /// Name presented as Host (and SNI over HTTPS) instead of the URL's
+    /// host, for name-based virtual hosts reached by address (empty = none)
+    pub host_override: String<MAX_HOST_LEN>,
+    /// Load options handed to the image, e.g. a Linux kernel command line
+    pub cmdline: String<MAX_CMDLINE_LEN>,
+    /// Initrd to download with the image, absolute or relative to its URL
+    pub initrd: String<MAX_URL_LEN>,
+    /// SHA256 the initrd must have (empty = not verified)
+    pub initrd_sha256: String<64>,
+    /// Detached Ed25519 signature of the image, absolute or relative to its
+    /// URL; downloaded alongside and checked against the trusted keys
+    pub sig: String<MAX_URL_LEN>,
+    /// Script lines run before the entry boots, one per line (empty = none)
+    pub pre: String<MAX_HOOK_LEN>,
+    /// Script lines run when the entry fails to boot, one per line
+    pub on_fail: String<MAX_HOOK_LEN>,
+}
+
+impl EntryMeta {
+    /// How the entry is booted: its `type=`, or the type implied without one
+    pub fn effective_type(&self) -> EntryType {
+        self.entry_type.unwrap_or_else(|| EntryType::implied(self))
+    }
+
+    /// Parse a comma-separated tag list
+    pub fn set_tags(&mut self, value: &str) -> Result<()> {
+        self.tags.clear();
+        for tag in value.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let mut tag_string = String::new();
+            tag_string.push_str(tag).map_err(|_| Error::BufferTooSmall)?;
+            self.tags.push(tag_string).map_err(|_| Error::OutOfMemory)?;
+        }
+        Ok(())
+    }
+
+    /// Append a line to a `pre=` or `on_fail=` hook
+    fn add_hook_line(hook: &mut String<MAX_HOOK_LEN>, line: &str) -> Result<()> {
+        if line.is_empty() {
+            return Err(Error::Parse);
+        }
+        if !hook.is_empty() {
+            hook.push('\n').map_err(|_| Error::BufferTooSmall)?;
+        }
+        hook.push_str(line).map_err(|_| Error::BufferTooSmall)
+    }
+
+    /// Set the Host override, checking it is a plain host name
+    pub fn set_host_override(&mut self, value: &str) -> Result<()> {
+        let valid = value.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        });
+        if !value.is_empty() && !valid {
+            return Err(Error::Parse);
+        }
+        self.host_override.clear();
+        self.host_override.push_str(value).map_err(|_| Error::BufferTooSmall)
+    }
+}
 
 /// Configuration for the bootloader
+///
+/// The entries, up to MAX_URLS of them, live on the heap: a full list is
+/// far more than the firmware's stack holds.
 #[derive(Debug, Clone)]
 pub struct Config {
     /// List of image URLs
-    pub urls: Vec<String<MAX_URL_LEN>, MAX_URLS>,
+    pub urls: alloc::vec::Vec<String<MAX_URL_LEN>>,
     /// List of image signatures (SHA256 hex, empty string = no verification)
-    pub signatures: Vec<String<MAX_SIGNATURE_LEN>, MAX_URLS>,
-    /// Default image index (0-based)
+    pub signatures: alloc::vec::Vec<String<MAX_SIGNATURE_LEN>>,
+    /// Names and tags of the entries
+    pub meta: alloc::vec::Vec<EntryMeta>,
+    /// Default image index (0-based, always an existing entry)
     pub default_index: Option<usize>,
+    /// Out-of-range `default=` that was ignored, kept to warn about it
+    pub dropped_default: Option<usize>,
+    /// Write the configuration to every ESP instead of just the first
+    pub mirror_esp: bool,
+    /// Where `save` keeps the configuration
+    pub storage: Store,
+    /// Download the default entry in the background while the CLI is idle
+    pub prefetch: bool,
+    /// Keep verified images on the ESP to boot when the download fails
+    pub image_cache: bool,
+    /// Keep the last verified image in RAM for booting it again without a
+    /// download, also after a warm reset
+    pub stage_image: bool,
+    /// Minutes without input at the prompt before the default boot resumes
+    pub idle_timeout: Option<u32>,
+    /// Commands kept in the history file (None: all remembered; 0: no file)
+    pub history: Option<u32>,
+    /// Show the boot menu at startup, booting the default after this many
+    /// seconds (0 = wait for a choice)
+    pub menu_timeout: Option<u32>,
+    /// PBKDF2 hash of the password the CLI asks for, which gives every
+    /// command (None = no password)
+    pub admin_password: Option<String<{ password::MAX_LEN }>>,
+    /// PBKDF2 hash of a second password that only lists, shows status
+    /// and boots; needs `admin_password`
+    pub operator_password: Option<String<{ password::MAX_LEN }>>,
+    /// What relative entry URLs are resolved against, always ending in `/`
+    pub base_url: Option<String<MAX_URL_LEN>>,
+    /// Append the architecture to entry file names (`boot.efi` is fetched
+    /// as `bootx64.efi`), for entries that do not use `${arch}`
+    pub arch_suffix: bool,
+    /// Volume (label or device path) to load the configuration from
+    pub volume: Option<String<MAX_VOLUME_LEN>>,
+    /// Configuration on a server whose entries the boot menu follows
+    pub config_url: Option<String<MAX_URL_LEN>>,
+    /// Seconds between checks of `config_url` while the menu is shown
+    pub poll_config_interval: Option<u32>,
+    /// Network interface to use (0-based, default: first)
+    pub nic_index: Option<usize>,
+    /// Network interface to use, by MAC address (takes the place of
+    /// `nic_index`, and survives the firmware reordering interfaces)
+    pub nic_mac: Option<Mac>,
+    /// DNS servers to ask instead of those the DHCP server hands out (the
+    /// static setup carries its own)
+    pub dns_servers: Vec<Ipv4, MAX_DNS_SERVERS>,
+    /// Bring up IPv6 as well, and reach host names over it
+    pub ip6: bool,
+    /// When started by the firmware's HTTP Boot, keep its interface and
+    /// address instead of setting up the network again
+    pub reuse_http_boot: bool,
+    /// Set up the network with the firmware's PXE client, so ProxyDHCP and
+    /// PXE boot servers are heard, and add the boot file they name as an
+    /// entry at startup
+    pub pxe_discover: bool,
+    /// Which DHCP offer to take when several servers answer
+    pub dhcp_offer: OfferPolicy,
+    /// Append log entries to `logger::LOG_FILE_PATH` on the ESP
+    pub boot_log: bool,
+    /// Connect the NICs to their firmware drivers again before an image starts
+    pub reconnect_nic: bool,
+    /// Clear the screen before an image starts
+    pub clear_screen: bool,
+    /// Watchdog armed for the image, in seconds (Some(0) = disabled, None =
+    /// left as the firmware set it)
+    pub watchdog: Option<u32>,
+    /// Shim on a local volume to start images through when Secure Boot
+    /// refuses them, so keys enrolled in MOK apply
+    pub shim: Option<String<MAX_SHIM_PATH_LEN>>,
+    /// Static IPv4 settings (None = DHCP)
+    pub static_ip: Option<StaticIp>,
+    /// iSCSI initiator settings
+    pub iscsi: IscsiInitiator,
+    /// Console keyboard layout
+    pub keymap: Layout,
+    /// Console output style
+    pub ui: UiMode,
+    /// How much progress output reaches the console
+    pub echo: Echo,
+    /// How finished downloads and failed boots are sounded
+    pub beep: BeepMode,
+    /// Draw a progress bar on the framebuffer instead of text during
+    /// unattended boots
+    pub splash: bool,
+    /// TFTP block size to ask for (None = `tftp::DEFAULT_BLOCK_SIZE`)
+    pub tftp_blksize: Option<u16>,
+    /// TFTP window size to ask for (None = `tftp::DEFAULT_WINDOW_SIZE`)
+    pub tftp_windowsize: Option<u16>,
+    /// Ask TFTP servers for a multicast transfer (RFC 2090)
+    pub tftp_multicast: bool,
+    /// Resume attempts for an interrupted HTTP download (None =
+    /// `http::DEFAULT_RETRIES`)
+    pub http_retries: Option<u32>,
+    /// Pause before the first resume attempt in milliseconds, doubling each
+    /// time (None = `http::DEFAULT_BACKOFF_MS`)
+    pub http_backoff: Option<u32>,
+    /// User-Agent template for HTTP requests (None = `agent::DEFAULT_TEMPLATE`)
+    pub user_agent: Option<String<MAX_AGENT_LEN>>,
 }
 
 impl Config {
     /// Create a new empty configuration
     pub fn new() -> Self {
         Config {
-            urls: Vec::new(),
-            signatures: Vec::new(),
+            urls: alloc::vec::Vec::new(),
+            signatures: alloc::vec::Vec::new(),
+            meta: alloc::vec::Vec::new(),
             default_index: None,
+            dropped_default: None,
+            mirror_esp: false,
+            storage: Store::Esp,
+            prefetch: false,
+            image_cache: false,
+            stage_image: false,
+            idle_timeout: None,
+            history: None,
+            menu_timeout: None,
+            admin_password: None,
+            operator_password: None,
+            base_url: None,
+            arch_suffix: false,
+            volume: None,
+            config_url: None,
+            poll_config_interval: None,
+            nic_index: None,
+            nic_mac: None,
+            dns_servers: Vec::new(),
+            ip6: false,
+            reuse_http_boot: false,
+            pxe_discover: false,
+            dhcp_offer: OfferPolicy::First,
+            boot_log: false,
+            reconnect_nic: false,
+            clear_screen: false,
+            watchdog: None,
+            shim: None,
+            static_ip: None,
+            iscsi: IscsiInitiator::default(),
+            keymap: Layout::Firmware,
+            ui: UiMode::Fancy,
+            echo: Echo::Normal,
+            beep: BeepMode::Off,
+            splash: false,
+            tftp_blksize: None,
+            tftp_windowsize: None,
+            tftp_multicast: false,
+            http_retries: None,
+            http_backoff: None,
+            user_agent: None,
         }
     }
 
@@ -39,7 +336,7 @@ impl Config {
 
     /// Add a URL with signature to the configuration
     pub fn add_url_with_signature(&mut self, url: &str, signature: &str) -> Result<()> {
-        if self.urls.is_full() {
+        if self.is_full() {
             return Err(Error::OutOfMemory);
         }
 
@@ -49,11 +346,17 @@ impl Config {
         let mut sig_string = String::new();
         sig_string.push_str(signature).map_err(|_| Error::BufferTooSmall)?;
 
-        self.urls.push(url_string).map_err(|_| Error::OutOfMemory)?;
-        self.signatures.push(sig_string).map_err(|_| Error::OutOfMemory)?;
+        self.urls.push(url_string);
+        self.signatures.push(sig_string);
+        self.meta.push(EntryMeta::default());
         Ok(())
     }
 
+    /// Whether MAX_URLS entries are configured already
+    pub fn is_full(&self) -> bool {
+        self.urls.len() >= MAX_URLS
+    }
+
     /// Remove a URL at the specified index
     pub fn remove_url(&mut self, index: usize) -> Result<()> {
         if index >= self.urls.len() {
@@ -62,19 +365,135 @@ impl Config {
 
         self.urls.remove(index);
         self.signatures.remove(index);
+        self.meta.remove(index);
 
         // Adjust default index if necessary
         if let Some(default) = self.default_index {
             if default == index {
                 self.default_index = None;
+                crate::util::logger::log_entry(
+                    log::Level::Warn,
+                    &alloc::format!("Default entry [{}] removed, no default set", index),
+                );
             } else if default > index {
                 self.default_index = Some(default - 1);
             }
         }
+        self.drop_invalid_default();
+
+        Ok(())
+    }
 
+    /// Entry that has this exact URL, or failing that this name
+    /// (case-insensitive)
+    pub fn find_duplicate(&self, url: &str, name: Option<&str>) -> Option<usize> {
+        self.urls.iter().position(|existing| existing == url).or_else(|| {
+            let name = name.filter(|name| !name.is_empty())?;
+            self.meta.iter().position(|meta| meta.name.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Point an entry at another URL
+    ///
+    /// The hash and declared version described the old image, so a changed
+    /// URL drops them; name and tags stay.
+    pub fn replace_url(&mut self, index: usize, url: &str) -> Result<()> {
+        let existing = self.urls.get_mut(index).ok_or(Error::NotFound)?;
+        if existing == url {
+            return Ok(());
+        }
+        let mut url_string = String::new();
+        url_string.push_str(url).map_err(|_| Error::BufferTooSmall)?;
+        *existing = url_string;
+        self.signatures[index].clear();
+        self.meta[index].version = None;
         Ok(())
     }
 
+    /// Name to present for requests to this URL, if an entry asks for one
+    ///
+    /// Relative entries match the URL they resolve to under `base_url=`.
+    pub fn host_override(&self, url: &str) -> Option<&str> {
+        let base = self.base_url.as_deref();
+        let index = self.urls.iter().position(|u| u == url || url::relative_to(base, u).as_deref() == Some(url))?;
+        self.meta.get(index).map(|meta| meta.host_override.as_str()).filter(|name| !name.is_empty())
+    }
+
+    /// Set an entry's name (empty clears it)
+    pub fn set_name(&mut self, index: usize, name: &str) -> Result<()> {
+        let meta = self.meta.get_mut(index).ok_or(Error::NotFound)?;
+        meta.name.clear();
+        meta.name.push_str(name).map_err(|_| Error::BufferTooSmall)
+    }
+
+    /// Take over the entries (and default) of `other`, keeping this
+    /// machine's own settings
+    pub fn replace_entries(&mut self, other: Config) {
+        self.urls = other.urls;
+        self.signatures = other.signatures;
+        self.meta = other.meta;
+        self.default_index = other.default_index;
+        self.dropped_default = other.dropped_default;
+    }
+
+    /// Drop what would run CLI commands, for a copy nobody vouched for:
+    /// `pre=` and `on_fail=` hooks, and `type=script` entries
+    ///
+    /// Returns how many entries were removed.
+    pub fn strip_commands(&mut self) -> usize {
+        let mut removed = 0;
+        let mut index = 0;
+        while index < self.urls.len() {
+            let Some(meta) = self.meta.get_mut(index) else {
+                break;
+            };
+            if meta.effective_type() == EntryType::Script {
+                let _ = self.remove_url(index);
+                removed += 1;
+                continue;
+            }
+            meta.pre.clear();
+            meta.on_fail.clear();
+            index += 1;
+        }
+        removed
+    }
+
+    /// Whether an entry is taken out of the fallback rotation
+    pub fn is_disabled(&self, index: usize) -> bool {
+        self.meta.get(index).is_some_and(|meta| meta.disabled)
+    }
+
+    /// Take an entry out of the fallback rotation, or put it back
+    pub fn set_disabled(&mut self, index: usize, disabled: bool) -> Result<()> {
+        self.meta.get_mut(index).ok_or(Error::NotFound)?.disabled = disabled;
+        Ok(())
+    }
+
+    /// Clear a default index past the last entry, so it is noticed when the
+    /// config is loaded rather than when booting
+    fn drop_invalid_default(&mut self) {
+        let Some(default) = self.default_index.filter(|&default| default >= self.urls.len()) else {
+            return;
+        };
+        self.default_index = None;
+        self.dropped_default = Some(default);
+        crate::util::logger::log_entry(
+            log::Level::Warn,
+            &alloc::format!("default={} is past the last entry ({} configured), ignored", default, self.urls.len()),
+        );
+    }
+
+    /// Whether an entry's URL, name or tags contain `filter` (case-insensitive)
+    pub fn entry_matches(&self, index: usize, filter: &str) -> bool {
+        let url_match = self.urls.get(index).is_some_and(|url| contains_ignore_case(url, filter));
+        let meta_match = self.meta.get(index).is_some_and(|meta| {
+            contains_ignore_case(&meta.name, filter)
+                || meta.tags.iter().any(|tag| contains_ignore_case(tag, filter))
+        });
+        url_match || meta_match
+    }
+
     /// Set the default image index
     pub fn set_default(&mut self, index: usize) -> Result<()> {
         if index >= self.urls.len() {
@@ -82,6 +501,7 @@ impl Config {
         }
 
         self.default_index = Some(index);
+        self.dropped_default = None;
         Ok(())
     }
 
@@ -89,6 +509,10 @@ impl Config {
     pub fn parse(content: &str) -> Result<Self> {
         let mut config = Config::new();
         let mut last_url_index = None;
+        let mut ip = None;
+        let mut netmask = None;
+        let mut gateway = None;
+        let mut dns = Vec::new();
 
         for line in content.lines() {
             let line = line.trim();
@@ -108,16 +532,280 @@ impl Config {
                         let index = value.parse::<usize>().map_err(|_| Error::Parse)?;
                         config.default_index = Some(index);
                     }
+                    "mirror_esp" => {
+                        config.mirror_esp = parse_bool(value)?;
+                    }
+                    "storage" => {
+                        config.storage = Store::parse(value).map_err(|_| Error::Parse)?;
+                    }
+                    "prefetch" => {
+                        config.prefetch = parse_bool(value)?;
+                    }
+                    "image_cache" => {
+                        config.image_cache = parse_bool(value)?;
+                    }
+                    "stage_image" => {
+                        config.stage_image = parse_bool(value)?;
+                    }
+                    "idle_timeout" => {
+                        let minutes = value.parse::<u32>().map_err(|_| Error::Parse)?;
+                        config.idle_timeout = Some(minutes).filter(|&m| m > 0);
+                    }
+                    "history" => {
+                        let count = value.parse::<u32>().map_err(|_| Error::Parse)?;
+                        if count as usize > MAX_HISTORY {
+                            return Err(Error::Parse);
+                        }
+                        config.history = Some(count);
+                    }
+                    "menu_timeout" => {
+                        config.menu_timeout = Some(value.parse::<u32>().map_err(|_| Error::Parse)?);
+                    }
+                    "admin_password" | "operator_password" => {
+                        PasswordHash::parse(value)?;
+                        let mut hash = String::new();
+                        hash.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        if key == "admin_password" {
+                            config.admin_password = Some(hash);
+                        } else {
+                            config.operator_password = Some(hash);
+                        }
+                    }
+                    "base_url" => {
+                        url::parse(value)?;
+                        // A directory, so entries go inside it rather than next to it
+                        let mut base = String::new();
+                        base.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        if !base.ends_with('/') {
+                            base.push('/').map_err(|_| Error::BufferTooSmall)?;
+                        }
+                        config.base_url = Some(base);
+                    }
+                    "arch_suffix" => config.arch_suffix = parse_bool(value)?,
+                    "volume" => {
+                        let mut volume = String::new();
+                        volume.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        config.volume = Some(volume);
+                    }
+                    "config_url" => {
+                        url::parse(value)?;
+                        let mut config_url = String::new();
+                        config_url.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        config.config_url = Some(config_url);
+                    }
+                    "poll_config_interval" => {
+                        let seconds = value.parse::<u32>().map_err(|_| Error::Parse)?;
+                        config.poll_config_interval = Some(seconds).filter(|&s| s > 0);
+                    }
+                    "keymap" => {
+                        config.keymap = Layout::parse(value).map_err(|_| Error::Parse)?;
+                    }
+                    "ui" => {
+                        config.ui = UiMode::parse(value).map_err(|_| Error::Parse)?;
+                    }
+                    "echo" => {
+                        config.echo = Echo::parse(value).map_err(|_| Error::Parse)?;
+                    }
+                    "beep" => {
+                        config.beep = BeepMode::parse(value).map_err(|_| Error::Parse)?;
+                    }
+                    "splash" => {
+                        config.splash = parse_bool(value)?;
+                    }
+                    "tftp_blksize" => {
+                        let size = value.parse::<u16>().map_err(|_| Error::Parse)?;
+                        if !tftp::BLOCK_SIZES.contains(&size) {
+                            return Err(Error::Parse);
+                        }
+                        config.tftp_blksize = Some(size);
+                    }
+                    "tftp_windowsize" => {
+                        let size = value.parse::<u16>().map_err(|_| Error::Parse)?;
+                        if !tftp::WINDOW_SIZES.contains(&size) {
+                            return Err(Error::Parse);
+                        }
+                        config.tftp_windowsize = Some(size);
+                    }
+                    "boot_log" => {
+                        config.boot_log = parse_bool(value)?;
+                    }
+                    "reconnect_nic" => {
+                        config.reconnect_nic = parse_bool(value)?;
+                    }
+                    "clear_screen" => {
+                        config.clear_screen = parse_bool(value)?;
+                    }
+                    "watchdog" => {
+                        config.watchdog = match value {
+                            "off" => Some(0),
+                            _ => Some(value.parse::<u32>().map_err(|_| Error::Parse)?),
+                        };
+                    }
+                    "shim" => {
+                        // Read from a volume, so a path there and not a URL
+                        if !value.starts_with('\\') {
+                            return Err(Error::Parse);
+                        }
+                        let mut path = String::new();
+                        path.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        config.shim = Some(path);
+                    }
+                    "tftp_multicast" => {
+                        config.tftp_multicast = parse_bool(value)?;
+                    }
+                    "http_retries" => {
+                        config.http_retries = Some(value.parse::<u32>().map_err(|_| Error::Parse)?);
+                    }
+                    "http_backoff" => {
+                        config.http_backoff = Some(value.parse::<u32>().map_err(|_| Error::Parse)?);
+                    }
+                    "user_agent" => {
+                        agent::validate(value)?;
+                        let mut template = String::new();
+                        template.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        config.user_agent = Some(template);
+                    }
+                    "nic" => {
+                        if value.contains(':') || value.contains('-') {
+                            config.nic_mac = Some(addr::parse_mac(value)?);
+                            config.nic_index = None;
+                        } else {
+                            config.nic_index = Some(value.parse::<usize>().map_err(|_| Error::Parse)?);
+                            config.nic_mac = None;
+                        }
+                    }
+                    "ip6" => config.ip6 = parse_bool(value)?,
+                    "reuse_http_boot" => config.reuse_http_boot = parse_bool(value)?,
+                    "pxe_discover" => config.pxe_discover = parse_bool(value)?,
+                    "dhcp_offer" => config.dhcp_offer = OfferPolicy::parse(value)?,
+                    "ip" => ip = Some(addr::parse_ipv4(value)?),
+                    "netmask" => netmask = Some(addr::parse_netmask(value)?),
+                    "gateway" => gateway = Some(addr::parse_ipv4(value)?),
+                    "dns" => {
+                        dns.push(addr::parse_ipv4(value)?).map_err(|_| Error::OutOfMemory)?;
+                    }
+                    "iscsi_initiator" => {
+                        config.iscsi.name.clear();
+                        config.iscsi.name.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                    }
+                    "iscsi_chap_user" => {
+                        config.iscsi.chap_user.clear();
+                        config.iscsi.chap_user.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                    }
+                    "iscsi_chap_secret" => {
+                        config.iscsi.chap_secret.clear();
+                        config.iscsi.chap_secret.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                    }
                     "url" => {
                         config.add_url(value)?;
                         last_url_index = Some(config.urls.len() - 1);
                     }
-                    "signature" | "sha256" => {
+                    "name" => {
+                        // Name follows the last URL
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].name.clear();
+                            config.meta[idx].name.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        }
+                    }
+                    "type" => {
+                        let entry_type = EntryType::parse(value)?;
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].entry_type = Some(entry_type);
+                        }
+                    }
+                    "tags" => {
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].set_tags(value)?;
+                        }
+                    }
+                    "version" => {
+                        let version = value.parse::<u32>().map_err(|_| Error::Parse)?;
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].version = Some(version);
+                        }
+                    }
+                    "arch" => {
+                        let arch = Arch::parse(value).map_err(|_| Error::Parse)?;
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].requires.arch = Some(arch);
+                        }
+                    }
+                    "min_fw" => {
+                        let revision = Revision::parse(value).map_err(|_| Error::Parse)?;
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].requires.min_fw = Some(revision);
+                        }
+                    }
+                    "secureboot" => {
+                        let required = match value {
+                            "required" => true,
+                            "optional" => false,
+                            _ => return Err(Error::Parse),
+                        };
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].requires.secure_boot = required;
+                        }
+                    }
+                    "cmdline" => {
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].cmdline.clear();
+                            config.meta[idx].cmdline.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        }
+                    }
+                    "initrd" => {
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].initrd.clear();
+                            config.meta[idx].initrd.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        }
+                    }
+                    "initrd_sha256" => {
+                        if !verify::find(verify::DEFAULT).is_some_and(|sha256| sha256.accepts(value)) {
+                            return Err(Error::Parse);
+                        }
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].initrd_sha256.clear();
+                            config.meta[idx].initrd_sha256.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        }
+                    }
+                    "sig" => {
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].sig.clear();
+                            config.meta[idx].sig.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                        }
+                    }
+                    "host_override" => {
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].set_host_override(value)?;
+                        }
+                    }
+                    // Repeated lines add to the hook, to run in order
+                    "pre" => {
+                        if let Some(idx) = last_url_index {
+                            EntryMeta::add_hook_line(&mut config.meta[idx].pre, value)?;
+                        }
+                    }
+                    "on_fail" => {
+                        if let Some(idx) = last_url_index {
+                            EntryMeta::add_hook_line(&mut config.meta[idx].on_fail, value)?;
+                        }
+                    }
+                    "disabled" => {
+                        let disabled = parse_bool(value)?;
+                        if let Some(idx) = last_url_index {
+                            config.meta[idx].disabled = disabled;
+                        }
+                    }
+                    key if key == "signature" || verify::find(key).is_some() => {
                         // Signature follows the last URL
                         if let Some(idx) = last_url_index {
                             if idx < config.signatures.len() {
-                                config.signatures[idx].clear();
-                                config.signatures[idx].push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                                let signature = &mut config.signatures[idx];
+                                signature.clear();
+                                if key != "signature" && key != verify::DEFAULT {
+                                    signature.push_str(key).map_err(|_| Error::BufferTooSmall)?;
+                                    signature.push(':').map_err(|_| Error::BufferTooSmall)?;
+                                }
+                                signature.push_str(value).map_err(|_| Error::BufferTooSmall)?;
                             }
                         }
                     }
@@ -128,12 +816,54 @@ impl Config {
             }
         }
 
+        // `default=` may come before the entries, so check it at the end
+        config.drop_invalid_default();
+
+        // So are the fields an entry's type needs, which may follow it
+        for (index, (url, meta)) in config.urls.iter().zip(config.meta.iter()).enumerate() {
+            if let Some(entry_type) = meta.entry_type {
+                entry_type.check(url, meta).map_err(|reason| {
+                    crate::util::logger::log_entry(
+                        log::Level::Error,
+                        &alloc::format!("Entry [{}] {}: {}", index, url, reason),
+                    );
+                    Error::Parse
+                })?;
+            }
+        }
+
+        // An operator password on its own would never be asked for
+        if config.operator_password.is_some() && config.admin_password.is_none() {
+            return Err(Error::Parse);
+        }
+
+        // CHAP needs both halves, and the initiator rejects short secrets
+        let iscsi = &config.iscsi;
+        if iscsi.chap_user.is_empty() != iscsi.chap_secret.is_empty()
+            || (!iscsi.chap_secret.is_empty() && iscsi.chap_secret.len() < MIN_CHAP_SECRET_LEN)
+        {
+            return Err(Error::Parse);
+        }
+
+        // Static settings only apply when an address is given; without
+        // one, DNS servers stand in for those of the DHCP lease
+        if let Some(address) = ip {
+            config.static_ip = Some(StaticIp {
+                address,
+                netmask: netmask.unwrap_or(Ipv4([255, 255, 255, 0])),
+                gateway,
+                dns,
+            });
+        } else {
+            config.dns_servers = dns;
+        }
+
         Ok(config)
     }
 
-    /// Serialize configuration to text format
-    pub fn serialize(&self) -> Result<String<4096>> {
-        let mut output = String::new();
+    /// Serialize configuration to text format, at most MAX_CONFIG_LEN bytes
+    pub fn serialize(&self) -> Result<alloc::string::String> {
+        let mut output = alloc::string::String::new();
 
         // Write header
         writeln!(output, "# UEFI PXE Bootloader Configuration").map_err(|_| Error::BufferTooSmall)?;
@@ -146,29 +876,423 @@ impl Config {
             writeln!(output).map_err(|_| Error::BufferTooSmall)?;
         }
 
+        if self.mirror_esp {
+            writeln!(output, "mirror_esp=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.storage != Store::Esp {
+            writeln!(output, "storage={}", self.storage.as_str()).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.prefetch {
+            writeln!(output, "prefetch=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.image_cache {
+            writeln!(output, "image_cache=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.stage_image {
+            writeln!(output, "stage_image=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(minutes) = self.idle_timeout {
+            writeln!(output, "idle_timeout={}", minutes).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(count) = self.history {
+            writeln!(output, "history={}", count).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(seconds) = self.menu_timeout {
+            writeln!(output, "menu_timeout={}", seconds).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(ref hash) = self.admin_password {
+            writeln!(output, "admin_password={}", hash).map_err(|_| Error::BufferTooSmall)?;
+            if let Some(ref hash) = self.operator_password {
+                writeln!(output, "operator_password={}", hash).map_err(|_| Error::BufferTooSmall)?;
+            }
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(ref base) = self.base_url {
+            writeln!(output, "base_url={}", base).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.arch_suffix {
+            writeln!(output, "arch_suffix=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(ref volume) = self.volume {
+            writeln!(output, "volume={}", volume).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(ref config_url) = self.config_url {
+            writeln!(output, "config_url={}", config_url).map_err(|_| Error::BufferTooSmall)?;
+            if let Some(seconds) = self.poll_config_interval {
+                writeln!(output, "poll_config_interval={}", seconds).map_err(|_| Error::BufferTooSmall)?;
+            }
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.boot_log {
+            writeln!(output, "boot_log=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.reconnect_nic {
+            writeln!(output, "reconnect_nic=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.clear_screen {
+            writeln!(output, "clear_screen=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(seconds) = self.watchdog {
+            match seconds {
+                0 => writeln!(output, "watchdog=off").map_err(|_| Error::BufferTooSmall)?,
+                _ => writeln!(output, "watchdog={}", seconds).map_err(|_| Error::BufferTooSmall)?,
+            }
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(ref shim) = self.shim {
+            writeln!(output, "shim={}", shim).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.keymap != Layout::Firmware {
+            writeln!(output, "keymap={}", self.keymap.as_str()).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.ui != UiMode::Fancy {
+            writeln!(output, "ui={}", self.ui.as_str()).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.echo != Echo::Normal {
+            writeln!(output, "echo={}", self.echo.as_str()).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.beep != BeepMode::Off {
+            writeln!(output, "beep={}", self.beep.as_str()).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.splash {
+            writeln!(output, "splash=true").map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(size) = self.tftp_blksize {
+            writeln!(output, "tftp_blksize={}", size).map_err(|_| Error::BufferTooSmall)?;
+        }
+        if let Some(size) = self.tftp_windowsize {
+            writeln!(output, "tftp_windowsize={}", size).map_err(|_| Error::BufferTooSmall)?;
+        }
+        if self.tftp_multicast {
+            writeln!(output, "tftp_multicast=true").map_err(|_| Error::BufferTooSmall)?;
+        }
+        if self.tftp_blksize.is_some() || self.tftp_windowsize.is_some() || self.tftp_multicast {
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(retries) = self.http_retries {
+            writeln!(output, "http_retries={}", retries).map_err(|_| Error::BufferTooSmall)?;
+        }
+        if let Some(ms) = self.http_backoff {
+            writeln!(output, "http_backoff={}", ms).map_err(|_| Error::BufferTooSmall)?;
+        }
+        if self.http_retries.is_some() || self.http_backoff.is_some() {
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if let Some(ref template) = self.user_agent {
+            writeln!(output, "user_agent={}", template).map_err(|_| Error::BufferTooSmall)?;
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        let dhcp_offer = self.dhcp_offer != OfferPolicy::First;
+        let nic = self.nic_index.is_some() || self.nic_mac.is_some();
+        let addressing = self.static_ip.is_some() || !self.dns_servers.is_empty();
+        if nic || addressing || self.ip6 || self.reuse_http_boot || self.pxe_discover || dhcp_offer {
+            writeln!(output, "# Network").map_err(|_| Error::BufferTooSmall)?;
+            if let Some(mac) = self.nic_mac {
+                writeln!(output, "nic={}", mac).map_err(|_| Error::BufferTooSmall)?;
+            } else if let Some(nic) = self.nic_index {
+                writeln!(output, "nic={}", nic).map_err(|_| Error::BufferTooSmall)?;
+            }
+            if self.ip6 {
+                writeln!(output, "ip6=true").map_err(|_| Error::BufferTooSmall)?;
+            }
+            if self.reuse_http_boot {
+                writeln!(output, "reuse_http_boot=true").map_err(|_| Error::BufferTooSmall)?;
+            }
+            if self.pxe_discover {
+                writeln!(output, "pxe_discover=true").map_err(|_| Error::BufferTooSmall)?;
+            }
+            if dhcp_offer {
+                writeln!(output, "dhcp_offer={}", self.dhcp_offer).map_err(|_| Error::BufferTooSmall)?;
+            }
+            if let Some(ref ip) = self.static_ip {
+                writeln!(output, "ip={}", ip.address).map_err(|_| Error::BufferTooSmall)?;
+                writeln!(output, "netmask={}", ip.netmask).map_err(|_| Error::BufferTooSmall)?;
+                if let Some(gateway) = ip.gateway {
+                    writeln!(output, "gateway={}", gateway).map_err(|_| Error::BufferTooSmall)?;
+                }
+                for server in ip.dns.iter() {
+                    writeln!(output, "dns={}", server).map_err(|_| Error::BufferTooSmall)?;
+                }
+            } else {
+                for server in self.dns_servers.iter() {
+                    writeln!(output, "dns={}", server).map_err(|_| Error::BufferTooSmall)?;
+                }
+            }
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
+        if self.iscsi != IscsiInitiator::default() {
+            writeln!(output, "# iSCSI").map_err(|_| Error::BufferTooSmall)?;
+            if !self.iscsi.name.is_empty() {
+                writeln!(output, "iscsi_initiator={}", self.iscsi.name).map_err(|_| Error::BufferTooSmall)?;
+            }
+            if !self.iscsi.chap_user.is_empty() {
+                writeln!(output, "iscsi_chap_user={}", self.iscsi.chap_user).map_err(|_| Error::BufferTooSmall)?;
+                writeln!(output, "iscsi_chap_secret={}", self.iscsi.chap_secret).map_err(|_| Error::BufferTooSmall)?;
+            }
+            writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+        }
+
         // Write URLs with signatures
-        writeln!(output, "# Image URLs with optional SHA256 signatures").map_err(|_| Error::BufferTooSmall)?;
+        writeln!(output, "# Image URLs with optional signatures").map_err(|_| Error::BufferTooSmall)?;
         for (i, url) in self.urls.iter().enumerate() {
             writeln!(output, "url={}", url).map_err(|_| Error::BufferTooSmall)?;
             if i < self.signatures.len() && !self.signatures[i].is_empty() {
-                writeln!(output, "sha256={}", self.signatures[i]).map_err(|_| Error::BufferTooSmall)?;
+                let (verifier, value) = verify::split(&self.signatures[i]);
+                writeln!(output, "{}={}", verifier.key, value).map_err(|_| Error::BufferTooSmall)?;
+            }
+            if let Some(meta) = self.meta.get(i) {
+                if !meta.name.is_empty() {
+                    writeln!(output, "name={}", meta.name).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if let Some(entry_type) = meta.entry_type {
+                    writeln!(output, "type={}", entry_type).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if !meta.tags.is_empty() {
+                    write!(output, "tags=").map_err(|_| Error::BufferTooSmall)?;
+                    for (t, tag) in meta.tags.iter().enumerate() {
+                        let sep = if t == 0 { "" } else { "," };
+                        write!(output, "{}{}", sep, tag).map_err(|_| Error::BufferTooSmall)?;
+                    }
+                    writeln!(output).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if let Some(version) = meta.version {
+                    writeln!(output, "version={}", version).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if let Some(arch) = meta.requires.arch {
+                    writeln!(output, "arch={}", arch.as_str()).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if let Some(min_fw) = meta.requires.min_fw {
+                    writeln!(output, "min_fw={}", min_fw).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if meta.requires.secure_boot {
+                    writeln!(output, "secureboot=required").map_err(|_| Error::BufferTooSmall)?;
+                }
+                if !meta.cmdline.is_empty() {
+                    writeln!(output, "cmdline={}", meta.cmdline).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if !meta.initrd.is_empty() {
+                    writeln!(output, "initrd={}", meta.initrd).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if !meta.initrd_sha256.is_empty() {
+                    writeln!(output, "initrd_sha256={}", meta.initrd_sha256).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if !meta.sig.is_empty() {
+                    writeln!(output, "sig={}", meta.sig).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if !meta.host_override.is_empty() {
+                    writeln!(output, "host_override={}", meta.host_override).map_err(|_| Error::BufferTooSmall)?;
+                }
+                for line in meta.pre.lines() {
+                    writeln!(output, "pre={}", line).map_err(|_| Error::BufferTooSmall)?;
+                }
+                for line in meta.on_fail.lines() {
+                    writeln!(output, "on_fail={}", line).map_err(|_| Error::BufferTooSmall)?;
+                }
+                if meta.disabled {
+                    writeln!(output, "disabled=true").map_err(|_| Error::BufferTooSmall)?;
+                }
             }
         }
 
+        if output.len() > MAX_CONFIG_LEN {
+            return Err(Error::BufferTooSmall);
+        }
         Ok(output)
     }
 }
 
+/// ASCII case-insensitive substring search
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    let (haystack, needle) = (haystack.as_bytes(), needle.as_bytes());
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window.eq_ignore_ascii_case(needle))
+}
+
+/// Parse a boolean setting value
+pub fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(Error::Parse),
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Something in a configuration that parses but is probably a mistake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    /// Entry is booted without any verification
+    NoHash(usize),
+    /// Entry has a hash or signature that can never match
+    MalformedHash(usize, &'static str),
+    /// Entry uses plain HTTP although the firmware could do TLS
+    PlainHttp(usize),
+    /// `default=` named an entry that does not exist and was ignored
+    DefaultOutOfRange(usize),
+}
+
+impl core::fmt::Display for Lint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Lint::NoHash(index) => write!(
+                f,
+                "Entry [{}] has no hash and boots unverified; add a sha256= line after its url=",
+                index
+            ),
+            Lint::MalformedHash(index, key) => write!(
+                f,
+                "Entry [{}] has a malformed {} value (wrong length or not hex) and will never boot",
+                index, key
+            ),
+            Lint::PlainHttp(index) => write!(
+                f,
+                "Entry [{}] uses http:// although the firmware supports TLS; consider https://",
+                index
+            ),
+            Lint::DefaultOutOfRange(index) => write!(
+                f,
+                "default={} points past the last entry and was ignored; set it again with 'default'",
+                index
+            ),
+        }
+    }
+}
+
+impl Config {
+    /// Look for likely mistakes, in entry order
+    ///
+    /// `tls` says whether the firmware can fetch https:// URLs, so plain
+    /// HTTP entries are only flagged when there is an alternative.
+    pub fn lint(&self, tls: bool) -> alloc::vec::Vec<Lint> {
+        let mut lints = alloc::vec::Vec::new();
+
+        for (index, url) in self.urls.iter().enumerate() {
+            let signature = self.signatures.get(index).map_or("", |sig| sig.as_str());
+            let detached = self.meta.get(index).is_some_and(|meta| !meta.sig.is_empty());
+            if signature.is_empty() && !detached {
+                lints.push(Lint::NoHash(index));
+            } else if !signature.is_empty() {
+                let (verifier, value) = verify::split(signature);
+                if !verifier.accepts(value) {
+                    lints.push(Lint::MalformedHash(index, verifier.key));
+                }
+            }
+
+            let resolved = url::relative_to(self.base_url.as_deref(), url);
+            let url = resolved.as_deref().unwrap_or(url);
+            if tls && url.get(..7).is_some_and(|scheme| scheme.eq_ignore_ascii_case("http://")) {
+                lints.push(Lint::PlainHttp(index));
+            }
+        }
+
+        if let Some(default) = self.dropped_default {
+            lints.push(Lint::DefaultOutOfRange(default));
+        }
+
+        lints
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_default_out_of_range() {
+        let config = Config::parse("default=99\nurl=http://a/1.efi\nurl=http://a/2.efi\n").unwrap();
+        assert_eq!((config.default_index, config.dropped_default), (None, Some(99)));
+
+        // Given before its entry is fine
+        let mut config = Config::parse("default=1\nurl=http://a/1.efi\nurl=http://a/2.efi\n").unwrap();
+        assert_eq!(config.default_index, Some(1));
+        config.remove_url(1).unwrap();
+        assert_eq!(config.default_index, None);
+        assert_eq!(config.dropped_default, None);
+    }
+
+    #[test]
+    fn test_lint() {
+        let config = Config::parse(
+            "default=3\n\
+             url=http://10.0.0.1/a.efi\n\
+             url=https://boot.example.com/b.efi\n\
+             sha256=a3b2c1d4e5f6abcd1234567890abcdef1234567890abcdef1234567890abcdef\n\
+             url=HTTP://10.0.0.1/c.efi\n\
+             sha512=abcd\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.lint(true),
+            [
+                Lint::NoHash(0),
+                Lint::PlainHttp(0),
+                Lint::MalformedHash(2, "sha512"),
+                Lint::PlainHttp(2),
+                Lint::DefaultOutOfRange(3),
+            ]
+        );
+        assert_eq!(
+            config.lint(false),
+            [Lint::NoHash(0), Lint::MalformedHash(2, "sha512"), Lint::DefaultOutOfRange(3)]
+        );
+        assert!(Config::new().lint(true).is_empty());
+    }
+
     #[test]
     fn test_empty_config() {
         let config = Config::new();
@@ -194,6 +1318,139 @@ mod tests {
         assert_eq!(config.urls[0].as_str(), "https://example.com/image2.efi");
     }
 
+    #[test]
+    fn test_find_and_replace_duplicate() {
+        let mut config =
+            Config::parse("url=http://a/x.efi\nname=Ubuntu 24.04\nversion=3\nsha256=abcd\nurl=http://a/y.efi\n").unwrap();
+
+        assert_eq!(config.find_duplicate("http://a/y.efi", None), Some(1));
+        assert_eq!(config.find_duplicate("http://a/z.efi", Some("ubuntu 24.04")), Some(0));
+        assert_eq!(config.find_duplicate("http://a/z.efi", Some("")), None);
+        assert_eq!(config.find_duplicate("http://a/z.efi", None), None);
+
+        // Same URL keeps the hash; a new one drops what described the old image
+        config.replace_url(0, "http://a/x.efi").unwrap();
+        assert_eq!(config.signatures[0].as_str(), "abcd");
+        config.replace_url(0, "http://a/z.efi").unwrap();
+        assert_eq!(config.urls[0].as_str(), "http://a/z.efi");
+        assert!(config.signatures[0].is_empty());
+        assert_eq!(config.meta[0].version, None);
+        assert_eq!(config.meta[0].name.as_str(), "Ubuntu 24.04");
+        assert_eq!(config.replace_url(2, "http://a/z.efi"), Err(Error::NotFound));
+    }
+
+    #[test]
+    fn test_disabled_round_trip() {
+        let mut config = Config::parse("url=http://a/x.efi\ndisabled=yes\nurl=http://a/y.efi\n").unwrap();
+        assert!(config.is_disabled(0));
+        assert!(!config.is_disabled(1));
+        assert!(!config.is_disabled(2));
+
+        config.set_disabled(1, true).unwrap();
+        config.set_disabled(0, false).unwrap();
+        assert_eq!(config.set_disabled(2, true), Err(Error::NotFound));
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert!(!reparsed.is_disabled(0));
+        assert!(reparsed.is_disabled(1));
+    }
+
+    #[test]
+    fn test_requirements_round_trip() {
+        let config =
+            Config::parse("url=http://a/x.efi\narch=AA64\nmin_fw=2.3.1\nsecureboot=required\nurl=http://a/y.efi\n")
+                .unwrap();
+        let requires = config.meta[0].requires;
+        assert_eq!(requires.arch, Some(Arch::Aa64));
+        assert_eq!(requires.min_fw, Some(Revision { major: 2, minor: 31 }));
+        assert!(requires.secure_boot);
+        assert!(config.meta[1].requires.is_empty());
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.meta[0].requires, requires);
+
+        assert_eq!(Config::parse("url=http://a/x.efi\narch=sparc\n").err(), Some(Error::Parse));
+        assert_eq!(Config::parse("url=http://a/x.efi\nsecureboot=yes\n").err(), Some(Error::Parse));
+    }
+
+    #[test]
+    fn test_cmdline_and_initrd() {
+        let hash = "a3b2c1d4e5f6abcd1234567890abcdef1234567890abcdef1234567890abcdef";
+        let content = alloc::format!(
+            "url=http://a/vmlinuz.efi\ncmdline=console=ttyS0 root=/dev/nfs ip=dhcp\ninitrd=initrd.img\ninitrd_sha256={}\n",
+            hash
+        );
+        let config = Config::parse(&content).unwrap();
+        assert_eq!(config.meta[0].cmdline.as_str(), "console=ttyS0 root=/dev/nfs ip=dhcp");
+        assert_eq!(config.meta[0].initrd.as_str(), "initrd.img");
+        assert_eq!(config.meta[0].initrd_sha256.as_str(), hash);
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.meta[0], config.meta[0]);
+        assert_eq!(Config::parse("url=http://a/x.efi\ninitrd_sha256=abcd\n").err(), Some(Error::Parse));
+    }
+
+    #[test]
+    fn test_detached_sig() {
+        let config = Config::parse("url=http://a/x.efi\nsig=x.efi.sig\nurl=http://a/y.efi\n").unwrap();
+        assert_eq!(config.meta[0].sig.as_str(), "x.efi.sig");
+        assert!(config.meta[1].sig.is_empty());
+
+        // A detached signature counts as verification
+        let lints = config.lint(false);
+        assert!(!lints.contains(&Lint::NoHash(0)));
+        assert!(!lints.iter().any(|lint| matches!(lint, Lint::MalformedHash(0, _))));
+        assert!(lints.contains(&Lint::NoHash(1)));
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.meta[0], config.meta[0]);
+    }
+
+    #[test]
+    fn test_host_override() {
+        let config = Config::parse(
+            "url=https://10.1.2.3/boot.efi\nhost_override=Artifacts.example.com\nurl=http://10.1.2.3/other.efi\n",
+        )
+        .unwrap();
+        assert_eq!(config.host_override("https://10.1.2.3/boot.efi"), Some("Artifacts.example.com"));
+        assert_eq!(config.host_override("http://10.1.2.3/other.efi"), None);
+        assert_eq!(config.host_override("http://10.9.9.9/boot.efi"), None);
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.meta[0].host_override, config.meta[0].host_override);
+
+        // A host name only: no port, path, scheme or empty labels
+        for bad in ["a.example.com:8443", "a.example.com/x", "http://a", "a..b", "-a.b", "a_b.c"] {
+            let content = alloc::format!("url=http://10.1.2.3/x.efi\nhost_override={}\n", bad);
+            assert_eq!(Config::parse(&content).err(), Some(Error::Parse), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_iscsi_settings() {
+        let content = "iscsi_initiator=iqn.2026-01.org.example:node7\n\
+                       iscsi_chap_user=node7\n\
+                       iscsi_chap_secret=0123456789abcdef\n\
+                       url=iscsi://10.0.0.5/iqn.2010-04.org.example:san/1\n";
+        let config = Config::parse(content).unwrap();
+        assert_eq!(config.iscsi.name.as_str(), "iqn.2026-01.org.example:node7");
+        assert_eq!(config.iscsi.chap_user.as_str(), "node7");
+        assert_eq!(config.iscsi.chap_secret.as_str(), "0123456789abcdef");
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.iscsi, config.iscsi);
+        assert!(!Config::new().serialize().unwrap().contains("iscsi"));
+
+        // Half a CHAP setup, or a secret the initiator would refuse
+        assert_eq!(Config::parse("iscsi_chap_user=node7\n").err(), Some(Error::Parse));
+        assert_eq!(Config::parse("iscsi_chap_secret=0123456789ab\n").err(), Some(Error::Parse));
+        assert_eq!(Config::parse("iscsi_chap_user=a\niscsi_chap_secret=short\n").err(), Some(Error::Parse));
+        assert_eq!(
+            Config::parse("iscsi_chap_user=a\niscsi_chap_secret=0123456789abcdefg\n").err(),
+            Some(Error::BufferTooSmall)
+        );
+    }
+
     #[test]
     fn test_set_default() {
         let mut config = Config::new();
@@ -224,6 +1481,463 @@ url=https://example.com/image2.efi
         assert_eq!(config.default_index, Some(0));
     }
 
+    #[test]
+    fn test_parse_mirror_esp() {
+        let config = Config::parse("mirror_esp=yes\n").unwrap();
+        assert!(config.mirror_esp);
+        assert!(Config::parse("mirror_esp=maybe\n").is_err());
+
+        let serialized = config.serialize().unwrap();
+        assert!(serialized.contains("mirror_esp=true"));
+    }
+
+    #[test]
+    fn test_parse_storage() {
+        assert_eq!(Config::new().storage, Store::Esp);
+        assert!(!Config::new().serialize().unwrap().contains("storage="));
+
+        let config = Config::parse("storage=NVRAM\n").unwrap();
+        assert_eq!(config.storage, Store::Nvram);
+        assert!(config.serialize().unwrap().contains("storage=nvram"));
+        assert_eq!(Config::parse("storage=esp\n").unwrap().storage, Store::Esp);
+        assert!(Config::parse("storage=usb\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_prefetch() {
+        assert!(!Config::new().prefetch);
+        let config = Config::parse("prefetch=on\ndefault=0\nurl=http://example.com/a.efi\n").unwrap();
+        assert!(config.prefetch);
+        assert!(config.serialize().unwrap().contains("prefetch=true"));
+    }
+
+    #[test]
+    fn test_parse_user_agent() {
+        assert_eq!(Config::new().user_agent, None);
+        let config = Config::parse("user_agent=lab-boot/{version} {mac}\n").unwrap();
+        assert_eq!(config.user_agent.as_deref(), Some("lab-boot/{version} {mac}"));
+        assert!(config.serialize().unwrap().contains("user_agent=lab-boot/{version} {mac}"));
+        assert_eq!(Config::parse("user_agent=x {serial}\n").err(), Some(Error::Parse));
+    }
+
+    #[test]
+    fn test_parse_tftp_sizes() {
+        let config = Config::parse("tftp_blksize=8192\ntftp_windowsize=1\n").unwrap();
+        assert_eq!(config.tftp_blksize, Some(8192));
+        assert_eq!(config.tftp_windowsize, Some(1));
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!((reparsed.tftp_blksize, reparsed.tftp_windowsize), (Some(8192), Some(1)));
+        assert!(!reparsed.tftp_multicast);
+        let multicast = Config::parse("tftp_multicast=true\n").unwrap();
+        assert!(multicast.tftp_multicast);
+        assert!(multicast.serialize().unwrap().contains("tftp_multicast=true"));
+
+        assert_eq!(Config::parse("tftp_blksize=4\n").err(), Some(Error::Parse));
+        assert_eq!(Config::parse("tftp_blksize=65465\n").err(), Some(Error::Parse));
+        assert_eq!(Config::parse("tftp_windowsize=0\n").err(), Some(Error::Parse));
+    }
+
+    #[test]
+    fn test_parse_http_retries() {
+        let config = Config::new();
+        assert_eq!((config.http_retries, config.http_backoff), (None, None));
+        let config = Config::parse("http_retries=0\nhttp_backoff=250\n").unwrap();
+        assert_eq!((config.http_retries, config.http_backoff), (Some(0), Some(250)));
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!((reparsed.http_retries, reparsed.http_backoff), (Some(0), Some(250)));
+        assert_eq!(Config::parse("http_retries=many\n").err(), Some(Error::Parse));
+    }
+
+    #[test]
+    fn test_parse_idle_timeout() {
+        assert_eq!(Config::new().idle_timeout, None);
+        let config = Config::parse("idle_timeout=30\n").unwrap();
+        assert_eq!(config.idle_timeout, Some(30));
+        assert!(config.serialize().unwrap().contains("idle_timeout=30"));
+        assert_eq!(Config::parse("idle_timeout=0\n").unwrap().idle_timeout, None);
+        assert!(Config::parse("idle_timeout=soon\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_history() {
+        assert_eq!(Config::new().history, None);
+        let config = Config::parse("history=0\n").unwrap();
+        assert_eq!(config.history, Some(0));
+        assert!(config.serialize().unwrap().contains("history=0"));
+        assert_eq!(Config::parse("history=100\n").unwrap().history, Some(100));
+        assert!(Config::parse("history=101\n").is_err());
+        assert!(Config::parse("history=all\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_menu_timeout() {
+        assert_eq!(Config::new().menu_timeout, None);
+        let config = Config::parse("menu_timeout=5\n").unwrap();
+        assert_eq!(config.menu_timeout, Some(5));
+        assert!(config.serialize().unwrap().contains("menu_timeout=5"));
+        // Zero still shows the menu, without a countdown
+        assert_eq!(Config::parse("menu_timeout=0\n").unwrap().menu_timeout, Some(0));
+        assert!(Config::parse("menu_timeout=-1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_passwords() {
+        let admin =
+            "pbkdf2-sha256$10000$a1b2c3d4e5f60718$9af95e08f8b55543e29ad7bcfdcec9cdcbc80e54fcf7ece830b268f51eb53e33";
+        let operator =
+            "pbkdf2-sha256$10000$0f1e2d3c4b5a6978$94b265f1108c5bd14e294ba9054b6acaec889fe2560375d1838a2de828515015";
+        assert_eq!(Config::new().admin_password, None);
+        let text = alloc::format!("admin_password={}\noperator_password={}\n", admin, operator);
+        let config = Config::parse(&text).unwrap();
+        assert_eq!(config.admin_password.as_deref(), Some(admin));
+        assert_eq!(config.operator_password.as_deref(), Some(operator));
+        assert!(config.serialize().unwrap().contains(&text));
+        assert!(Config::parse("admin_password=admin\n").is_err());
+
+        // Unsalted SHA-256 is no longer accepted, nor an operator without an admin
+        assert!(Config::parse(&alloc::format!("admin_password={}\n", &admin[admin.len() - 64..])).is_err());
+        assert!(Config::parse(&alloc::format!("operator_password={}\n", operator)).is_err());
+    }
+
+    #[test]
+    fn test_strip_commands() {
+        let mut config = Config::parse(
+            "url=http://a/x.efi\npre=set echo quiet\non_fail=logs save\n\
+             url=http://a/site.txt\ntype=script\n\
+             url=http://a/y.efi\ndefault=2\n",
+        )
+        .unwrap();
+        assert_eq!(config.strip_commands(), 1);
+        assert_eq!(config.urls.len(), 2);
+        assert_eq!(config.urls[1].as_str(), "http://a/y.efi");
+        assert!(config.meta[0].pre.is_empty() && config.meta[0].on_fail.is_empty());
+        assert_eq!(config.default_index, Some(1));
+    }
+
+    #[test]
+    fn test_parse_volume() {
+        let config = Config::parse("volume=USB-KEY\n").unwrap();
+        assert_eq!(config.volume.as_deref(), Some("USB-KEY"));
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.volume, config.volume);
+        assert!(Config::new().volume.is_none());
+    }
+
+    #[test]
+    fn test_parse_config_url() {
+        let config = Config::parse("config_url=http://10.0.0.5/menu.txt\npoll_config_interval=30\n").unwrap();
+        assert_eq!(config.config_url.as_deref(), Some("http://10.0.0.5/menu.txt"));
+        assert_eq!(config.poll_config_interval, Some(30));
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.config_url, config.config_url);
+        assert_eq!(reparsed.poll_config_interval, Some(30));
+
+        // Zero turns watching off
+        assert_eq!(Config::parse("poll_config_interval=0\n").unwrap().poll_config_interval, None);
+        assert!(Config::parse("config_url=menu.txt\n").is_err());
+    }
+
+    #[test]
+    fn test_replace_entries() {
+        let mut local = Config::parse("url=http://10.0.0.1/old.efi\nname=Old\nmenu_timeout=5\n").unwrap();
+        let remote =
+            Config::parse("url=http://10.0.0.2/a.efi\nname=A\nurl=http://10.0.0.2/b.efi\ndefault=1\nmenu_timeout=0\n")
+                .unwrap();
+        local.replace_entries(remote);
+        assert_eq!(local.urls.len(), 2);
+        assert_eq!(local.meta[0].name, "A");
+        assert_eq!(local.default_index, Some(1));
+        // Settings stay the machine's own
+        assert_eq!(local.menu_timeout, Some(5));
+    }
+
+    #[test]
+    fn test_parse_names_and_tags() {
+        let content = "url=http://a/x.efi\nname=Ubuntu 24.04\ntags=linux, lts\nurl=http://a/y.efi\n";
+        let config = Config::parse(content).unwrap();
+        assert_eq!(config.meta.len(), 2);
+        assert_eq!(config.meta[0].name.as_str(), "Ubuntu 24.04");
+        assert_eq!(config.meta[0].tags.len(), 2);
+        assert_eq!(config.meta[0].tags[1].as_str(), "lts");
+        assert!(config.meta[1].name.is_empty());
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.meta, config.meta);
+
+        assert!(config.entry_matches(0, "ubuntu"));
+        assert!(config.entry_matches(0, "LTS"));
+        assert!(config.entry_matches(1, "y.efi"));
+        assert!(!config.entry_matches(1, "ubuntu"));
+
+        let mut config = reparsed;
+        config.remove_url(0).unwrap();
+        assert_eq!(config.meta.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let config = Config::parse("url=http://a/x.efi\nversion=42\nurl=http://a/y.efi\n").unwrap();
+        assert_eq!(config.meta[0].version, Some(42));
+        assert_eq!(config.meta[1].version, None);
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.meta, config.meta);
+
+        assert!(Config::parse("url=http://a/x.efi\nversion=-1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_keymap() {
+        let config = Config::parse("keymap=de\n").unwrap();
+        assert_eq!(config.keymap, Layout::De);
+        assert!(config.serialize().unwrap().contains("keymap=de"));
+        assert!(Config::parse("keymap=klingon\n").is_err());
+        assert!(!Config::new().serialize().unwrap().contains("keymap="));
+    }
+
+    #[test]
+    fn test_parse_static_ip() {
+        let content = "nic=1\nip=192.168.1.10\nnetmask=/24\ngateway=192.168.1.1\ndns=1.1.1.1\n";
+        let config = Config::parse(content).unwrap();
+        assert_eq!(config.nic_index, Some(1));
+        let ip = config.static_ip.as_ref().unwrap();
+        assert_eq!(ip.address, Ipv4([192, 168, 1, 10]));
+        assert_eq!(ip.netmask, Ipv4([255, 255, 255, 0]));
+        assert_eq!(ip.gateway, Some(Ipv4([192, 168, 1, 1])));
+        assert_eq!(ip.dns.len(), 1);
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.static_ip, config.static_ip);
+        assert_eq!(reparsed.nic_index, Some(1));
+
+        assert!(Config::parse("gateway=192.168.1.1\n").unwrap().static_ip.is_none());
+        assert!(!reparsed.ip6);
+        assert!(Config::parse("ip=192.168.1.300\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_ip6() {
+        assert!(!Config::new().ip6);
+        let config = Config::parse("ip6=on\n").unwrap();
+        assert!(config.ip6);
+        assert!(config.serialize().unwrap().contains("ip6=true"));
+        assert!(Config::parse("ip6=maybe\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_reuse_http_boot() {
+        assert!(!Config::new().reuse_http_boot);
+        let config = Config::parse("reuse_http_boot=yes\n").unwrap();
+        assert!(config.reuse_http_boot);
+        assert!(config.serialize().unwrap().contains("reuse_http_boot=true"));
+        assert!(!Config::new().serialize().unwrap().contains("reuse_http_boot"));
+    }
+
+    #[test]
+    fn test_parse_entry_type() {
+        let config = Config::parse(
+            "url=http://10.0.0.5/vmlinuz\n\
+             type=linux\n\
+             initrd=initrd.img\n\
+             url=file://WINDOWS/\n\
+             type=localdisk\n\
+             url=http://10.0.0.5/shell.efi\n\
+             url=http://10.0.0.5/vmlinuz\n\
+             initrd=initrd.img\n",
+        )
+        .unwrap();
+        assert_eq!(config.meta[0].entry_type, Some(EntryType::Linux));
+        assert_eq!(config.meta[1].effective_type(), EntryType::LocalDisk);
+        assert_eq!((config.meta[2].entry_type, config.meta[2].effective_type()), (None, EntryType::Efi));
+        assert_eq!(config.meta[3].effective_type(), EntryType::Linux);
+
+        let serialized = config.serialize().unwrap();
+        assert!(serialized.contains("url=file://WINDOWS/\ntype=localdisk\n"));
+        assert_eq!(serialized.matches("type=").count(), 2);
+        assert_eq!(Config::parse(&serialized).unwrap().meta, config.meta);
+
+        // Required fields are checked once the entry is complete
+        assert!(Config::parse("url=http://10.0.0.5/vmlinuz\ntype=efi\ninitrd=initrd.img\n").is_err());
+        assert!(Config::parse("url=http://10.0.0.5/x.efi\ntype=localdisk\n").is_err());
+        assert!(Config::parse("url=http://10.0.0.5/x.efi\ntype=pe\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_pxe_discover() {
+        assert!(!Config::new().pxe_discover);
+        let config = Config::parse("pxe_discover=true\n").unwrap();
+        assert!(config.pxe_discover);
+        assert!(config.serialize().unwrap().contains("# Network\npxe_discover=true"));
+        assert!(Config::parse("pxe_discover=maybe\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_hooks() {
+        let content =
+            "url=http://a/1.efi\npre=set echo verbose\npre=logs save\non_fail=diag export\nurl=http://a/2.efi\n";
+        let config = Config::parse(content).unwrap();
+        assert_eq!(config.meta[0].pre.as_str(), "set echo verbose\nlogs save");
+        assert_eq!(config.meta[0].on_fail.as_str(), "diag export");
+        assert!(config.meta[1].pre.is_empty() && config.meta[1].on_fail.is_empty());
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.meta[0], config.meta[0]);
+        assert!(Config::parse("url=http://a/1.efi\npre=\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_dns_servers() {
+        // Without an address the servers go with DHCP
+        let config = Config::parse("dns=1.1.1.1\ndns=9.9.9.9\n").unwrap();
+        assert!(config.static_ip.is_none());
+        assert_eq!(config.dns_servers.as_slice(), &[Ipv4([1, 1, 1, 1]), Ipv4([9, 9, 9, 9])]);
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.dns_servers, config.dns_servers);
+
+        let config = Config::parse("ip=192.168.1.10\ndns=1.1.1.1\n").unwrap();
+        assert!(config.dns_servers.is_empty());
+        assert_eq!(config.static_ip.unwrap().dns.len(), 1);
+        assert!(Config::parse("dns=1.1.1.1\ndns=8.8.8.8\ndns=9.9.9.9\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_nic_mac() {
+        let config = Config::parse("nic=52:54:00:AB:CD:EF\n").unwrap();
+        assert_eq!(config.nic_mac, Some(Mac([0x52, 0x54, 0x00, 0xab, 0xcd, 0xef])));
+        assert_eq!(config.nic_index, None);
+        assert!(config.serialize().unwrap().contains("nic=52:54:00:ab:cd:ef"));
+
+        // The later line wins, whichever form it takes
+        let config = Config::parse("nic=52-54-00-ab-cd-ef\nnic=2\n").unwrap();
+        assert_eq!((config.nic_index, config.nic_mac), (Some(2), None));
+        assert!(Config::parse("nic=52:54:00:ab:cd\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_image_cache() {
+        assert!(!Config::new().image_cache);
+        let config = Config::parse("image_cache=true\n").unwrap();
+        assert!(config.image_cache);
+        assert!(config.serialize().unwrap().contains("image_cache=true"));
+    }
+
+    #[test]
+    fn test_parse_stage_image() {
+        assert!(!Config::new().stage_image);
+        let config = Config::parse("stage_image=true\n").unwrap();
+        assert!(config.stage_image);
+        assert!(config.serialize().unwrap().contains("stage_image=true"));
+        assert!(Config::parse("stage_image=maybe\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_cleanup() {
+        let config = Config::new();
+        assert!(!config.reconnect_nic && !config.clear_screen);
+        assert_eq!(config.watchdog, None);
+
+        let config = Config::parse("reconnect_nic=true\nclear_screen=yes\nwatchdog=300\n").unwrap();
+        assert!(config.reconnect_nic && config.clear_screen);
+        assert_eq!(config.watchdog, Some(300));
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert!(reparsed.reconnect_nic && reparsed.clear_screen);
+        assert_eq!(reparsed.watchdog, Some(300));
+
+        let config = Config::parse("watchdog=off\n").unwrap();
+        assert_eq!(config.watchdog, Some(0));
+        assert!(config.serialize().unwrap().contains("watchdog=off"));
+        assert!(Config::parse("watchdog=soon\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_base_url() {
+        let config = Config::parse("base_url=http://mirror.example.com/boot\nurl=images/rescue.efi\n").unwrap();
+        let base = config.base_url.as_deref();
+        assert_eq!(base, Some("http://mirror.example.com/boot/"));
+        let resolved = url::relative_to(base, &config.urls[0]);
+        assert_eq!(resolved.as_deref(), Some("http://mirror.example.com/boot/images/rescue.efi"));
+        assert_eq!(config.lint(true), [Lint::NoHash(0), Lint::PlainHttp(0)]);
+
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.base_url, config.base_url);
+
+        let config =
+            Config::parse("base_url=http://10.1.2.3/\nurl=boot.efi\nhost_override=boot.example.com\n").unwrap();
+        assert_eq!(config.host_override("http://10.1.2.3/boot.efi"), Some("boot.example.com"));
+        assert!(Config::parse("base_url=mirror.example.com/boot\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_arch_suffix() {
+        assert!(!Config::new().arch_suffix);
+        let config = Config::parse("arch_suffix=true\nurl=http://h/${arch}/boot.efi\n").unwrap();
+        assert!(config.arch_suffix);
+        assert_eq!(config.urls[0], "http://h/${arch}/boot.efi");
+        assert!(config.serialize().unwrap().contains("arch_suffix=true"));
+        assert!(Config::parse("arch_suffix=x64\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_shim() {
+        assert!(Config::new().shim.is_none());
+        let config = Config::parse("shim=\\EFI\\uefipxe\\shimx64.efi\n").unwrap();
+        assert_eq!(config.shim.as_deref(), Some("\\EFI\\uefipxe\\shimx64.efi"));
+        let reparsed = Config::parse(&config.serialize().unwrap()).unwrap();
+        assert_eq!(reparsed.shim, config.shim);
+        assert!(Config::parse("shim=http://boot/shimx64.efi\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_echo() {
+        assert_eq!(Config::new().echo, Echo::Normal);
+        assert!(!Config::new().serialize().unwrap().contains("echo="));
+        let config = Config::parse("echo=quiet\n").unwrap();
+        assert_eq!(config.echo, Echo::Quiet);
+        assert!(config.serialize().unwrap().contains("echo=quiet"));
+        assert!(Config::parse("echo=loud\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_beep() {
+        assert_eq!(Config::new().beep, BeepMode::Off);
+        assert!(!Config::new().serialize().unwrap().contains("beep="));
+        let config = Config::parse("beep=speaker\n").unwrap();
+        assert_eq!(config.beep, BeepMode::Speaker);
+        assert!(config.serialize().unwrap().contains("beep=speaker"));
+        assert!(Config::parse("beep=loud\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_splash() {
+        assert!(!Config::new().splash);
+        let config = Config::parse("splash=true\n").unwrap();
+        assert!(config.splash);
+        assert!(config.serialize().unwrap().contains("splash=true"));
+        assert!(Config::parse("splash=maybe\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_boot_log() {
+        assert!(!Config::new().boot_log);
+        assert!(!Config::new().serialize().unwrap().contains("boot_log"));
+        let config = Config::parse("boot_log=true\n").unwrap();
+        assert!(config.boot_log);
+        assert!(config.serialize().unwrap().contains("boot_log=true"));
+        assert!(!Config::parse("boot_log=false\n").unwrap().boot_log);
+    }
+
+    #[test]
+    fn test_parse_dhcp_offer() {
+        assert_eq!(Config::new().dhcp_offer, OfferPolicy::First);
+        let config = Config::parse("dhcp_offer=10.0.0.5\n").unwrap();
+        assert_eq!(config.dhcp_offer, OfferPolicy::Server(Ipv4([10, 0, 0, 5])));
+        assert!(config.serialize().unwrap().contains("dhcp_offer=10.0.0.5"));
+        assert_eq!(Config::parse("dhcp_offer=pxe\n").unwrap().dhcp_offer, OfferPolicy::Pxe);
+        assert!(Config::parse("dhcp_offer=fastest\n").is_err());
+    }
+
     #[test]
     fn test_serialize() {
         let mut config = Config::new();
@@ -1,57 +1,650 @@
-use crate::util::{Error, Result};
+use crate::storage::naming::{self, Sibling};
+use crate::util::sha256::{self, Sha256};
+use crate::util::{ucs2, Error, Result};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams, SearchType};
-use uefi::proto::media::file::{File, FileAttribute, FileInfo, FileMode};
+use uefi::proto::device_path::build::{self, DevicePathBuilder};
+use uefi::proto::device_path::text::{AllowShortcuts, DisplayOnly};
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::proto::media::file::{
+    Directory, File, FileAttribute, FileInfo, FileMode, FileSystemInfo, FileType, RegularFile,
+};
 use uefi::proto::media::fs::SimpleFileSystem;
-use uefi::{CStr16, Identify};
-use heapless::Vec;
+use uefi::proto::media::partition::PartitionInfo;
+use uefi::{CString16, Identify};
 
 /// Path to the configuration file on the ESP
 pub const CONFIG_PATH: &str = "\\EFI\\uefipxe\\config.txt";
 
-/// Read a file from the ESP
-pub fn read_file(path: &str) -> Result<Vec<u8, 8192>> {
-    // Convert path to UCS-2
-    let mut path_buf = [0u16; 256];
-    let path_ucs2 = str_to_ucs2(path, &mut path_buf)?;
+/// Largest file we read into memory
+pub const MAX_FILE_SIZE: usize = 16384;
+
+/// Writes and read-back verification happen in chunks of this size
+const WRITE_CHUNK: usize = 64 * 1024;
+
+/// Writes at least this large report progress
+const PROGRESS_THRESHOLD: usize = 1024 * 1024;
+
+/// Free space kept in reserve beyond the bytes being written, for
+/// directory entries and FAT cluster rounding
+const SPACE_MARGIN: u64 = 64 * 1024;
+
+/// Size and free space of one filesystem
+pub struct FsSpace {
+    /// Volume label (may be empty)
+    pub label: heapless::String<32>,
+    /// Volume size in bytes
+    pub total: u64,
+    /// Free space in bytes
+    pub free: u64,
+    /// Whether the volume is read-only
+    pub read_only: bool,
+    /// Whether the firmware flags the partition as an ESP
+    pub esp: bool,
+}
+
+impl FsSpace {
+    /// Whether a write of `size` bytes fits with room to spare
+    pub fn fits(&self, size: usize) -> bool {
+        !self.read_only && self.free >= size as u64 + SPACE_MARGIN
+    }
+}
+
+/// Report size and free space of every detected filesystem
+pub fn filesystems() -> Result<alloc::vec::Vec<FsSpace>> {
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .map_err(|e| Error::Uefi(e.status()))?;
+
+    Ok(handles
+        .iter()
+        .filter_map(|handle| {
+            let mut space = space_of_handle(*handle).ok()?;
+            space.esp = is_esp(*handle);
+            Some(space)
+        })
+        .collect())
+}
+
+/// Find the filesystem selected by a `volume=` style selector
+///
+/// See `path::volume_matches` for how labels and device paths match.
+pub fn find_volume(selector: &str) -> Result<uefi::Handle> {
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .map_err(|e| Error::Uefi(e.status()))?;
+
+    handles
+        .iter()
+        .copied()
+        .find(|handle| {
+            let label = space_of_handle(*handle).map(|space| space.label).unwrap_or_default();
+            let device_path = device_path_text(*handle).unwrap_or_default();
+            super::path::volume_matches(selector, &label, &device_path)
+        })
+        .ok_or(Error::NotFound)
+}
+
+/// Text form of a handle's device path
+fn device_path_text(handle: uefi::Handle) -> Option<alloc::string::String> {
+    let device_path = unsafe {
+        boot::open_protocol::<DevicePath>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+        .ok()?
+    };
+
+    let text = device_path
+        .to_string(DisplayOnly(false), AllowShortcuts(false))
+        .ok()?;
+    Some(alloc::string::String::from(&*text))
+}
+
+/// Read a file from one specific volume
+pub fn read_file_from(volume: &str, path: &str) -> Result<Vec<u8>> {
+    let handle = find_volume(volume)?;
+    try_read_from_handle(handle, path).map(|copy| copy.data)
+}
+
+/// Read a whole file of any size (e.g. an image behind a `file://` URL)
+///
+/// With no volume given, every filesystem is searched in firmware order.
+pub fn read_image(volume: Option<&str>, path: &str) -> Result<alloc::vec::Vec<u8>> {
+    let handles = match volume {
+        Some(volume) => alloc::vec![find_volume(volume)?],
+        None => boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+            .map_err(|e| Error::Uefi(e.status()))?
+            .to_vec(),
+    };
+
+    for handle in handles {
+        if let Some(data) = read_whole(handle, path)? {
+            return Ok(data);
+        }
+    }
+
+    Err(Error::NotFound)
+}
+
+/// Read a whole file from one specific filesystem handle
+pub fn read_image_on(handle: uefi::Handle, path: &str) -> Result<alloc::vec::Vec<u8>> {
+    read_whole(handle, path)?.ok_or(Error::NotFound)
+}
+
+/// The first filesystem, in firmware order, with a file at `path`
+pub fn volume_with(path: &str) -> Result<uefi::Handle> {
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .map_err(|e| Error::Uefi(e.status()))?;
+
+    handles.iter().copied().find(|&handle| has_file(handle, path)).ok_or(Error::NotFound)
+}
+
+fn has_file(handle: uefi::Handle, path: &str) -> bool {
+    let fs = unsafe {
+        boot::open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    };
+    let Ok(mut fs) = fs else {
+        return false;
+    };
+    match fs.open_volume() {
+        Ok(mut root) => matches!(open_regular(&mut root, path, FileMode::Read), Ok(Some(_))),
+        Err(_) => false,
+    }
+}
+
+/// Write a file to one specific filesystem, replacing it as `atomic_write`
+/// does
+pub fn write_on(handle: uefi::Handle, path: &str, data: &[u8]) -> Result<()> {
+    check_target(path)?;
+    try_write_to_handle(handle, path, data)
+}
+
+/// Delete a file from one specific filesystem; whether it was there
+pub fn delete_on(handle: uefi::Handle, path: &str) -> Result<bool> {
+    try_delete_from_handle(handle, path)
+}
+
+/// Device path of the file at `path` on a filesystem handle, for loading
+/// an image so that it knows where it came from
+pub fn device_path_on(handle: uefi::Handle, path: &str) -> Result<Box<DevicePath>> {
+    let volume = unsafe {
+        boot::open_protocol::<DevicePath>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+        .map_err(|e| Error::Uefi(e.status()))?
+    };
+    with_file(&volume, path)
+}
+
+/// `volume` followed by a file path node for `path`
+pub fn with_file(volume: &DevicePath, path: &str) -> Result<Box<DevicePath>> {
+    let path_name = ucs2::to_cstring16(path)?;
+    let mut buf = alloc::vec::Vec::new();
+    let mut builder = DevicePathBuilder::with_vec(&mut buf);
+    for node in volume.node_iter() {
+        builder = builder.push(&node).map_err(|_| Error::BufferTooSmall)?;
+    }
+    let full = builder
+        .push(&build::media::FilePath { path_name: &path_name })
+        .and_then(|builder| builder.finalize())
+        .map_err(|_| Error::BufferTooSmall)?;
+    Ok(full.to_boxed())
+}
 
+/// A file on the ESP read a piece at a time, for files too large to keep
+/// in memory
+pub struct Reader {
+    file: RegularFile,
+    size: u64,
+}
+
+impl Reader {
+    /// Open a file on the first filesystem that has it
+    pub fn open(path: &str) -> Result<Self> {
+        let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+            .map_err(|e| Error::Uefi(e.status()))?;
+
+        for &handle in handles.iter() {
+            let mut fs = unsafe {
+                boot::open_protocol::<SimpleFileSystem>(
+                    OpenProtocolParams {
+                        handle,
+                        agent: boot::image_handle(),
+                        controller: None,
+                    },
+                    OpenProtocolAttributes::GetProtocol,
+                )
+                .map_err(|e| Error::Uefi(e.status()))?
+            };
+            let mut root = fs.open_volume().map_err(|e| Error::Uefi(e.status()))?;
+
+            if let Some(mut file) = open_regular(&mut root, path, FileMode::Read)? {
+                let mut info_buf = [0u8; 256];
+                let size = file
+                    .get_info::<FileInfo>(&mut info_buf)
+                    .map_err(|e| Error::Uefi(e.status()))?
+                    .file_size();
+                return Ok(Reader { file, size });
+            }
+        }
+
+        Err(Error::NotFound)
+    }
+
+    /// File size in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Read the next part of the file into `buf`; 0 at the end
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.file.read(buf).map_err(|e| Error::Uefi(e.status()))
+    }
+}
+
+/// Whole file from a filesystem, None when it is not there
+fn read_whole(handle: uefi::Handle, path: &str) -> Result<Option<alloc::vec::Vec<u8>>> {
+    let mut fs = unsafe {
+        boot::open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+        .map_err(|e| Error::Uefi(e.status()))?
+    };
+    let mut root = fs.open_volume().map_err(|e| Error::Uefi(e.status()))?;
+
+    let mut file = match open_regular(&mut root, path, FileMode::Read)? {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+
+    let mut info_buf = [0u8; 256];
+    let size = file
+        .get_info::<FileInfo>(&mut info_buf)
+        .map_err(|e| Error::Uefi(e.status()))?
+        .file_size() as usize;
+
+    let mut data = crate::util::wipe::Wiped::default();
+    data.try_reserve_exact(size).map_err(|_| Error::OutOfMemory)?;
+    data.resize(size, 0);
+    let read = file.read(&mut data).map_err(|e| Error::Uefi(e.status()))?;
+    if read != size {
+        return Err(Error::Io);
+    }
+    Ok(Some(data.into_inner()))
+}
+
+/// Query size and free space through the filesystem's root directory
+fn space_of_handle(handle: uefi::Handle) -> Result<FsSpace> {
+    let mut fs = unsafe {
+        boot::open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+        .map_err(|e| Error::Uefi(e.status()))?
+    };
+
+    let mut root = fs.open_volume().map_err(|e| Error::Uefi(e.status()))?;
+    space_of(&mut root)
+}
+
+fn space_of(root: &mut Directory) -> Result<FsSpace> {
+    let mut info_buf = [0u8; 256];
+    let info = root
+        .get_info::<FileSystemInfo>(&mut info_buf)
+        .map_err(|e| Error::Uefi(e.status()))?;
+
+    let mut label = heapless::String::new();
+    for c in info.volume_label().iter() {
+        if label.push(char::from(*c)).is_err() {
+            break;
+        }
+    }
+
+    Ok(FsSpace {
+        label,
+        total: info.volume_size(),
+        free: info.free_space(),
+        read_only: info.read_only(),
+        esp: false,
+    })
+}
+
+/// Refuse a write that would not fit, instead of leaving a truncated file
+///
+/// `replaced` bytes, the size of the file the write replaces, count as
+/// free. Should the new copy then not fit next to the old one, the write
+/// fails before the old version is touched. Firmware that cannot report
+/// free space is trusted to have enough.
+fn check_space(root: &mut Directory, size: usize, replaced: u64) -> Result<()> {
+    let mut space = match space_of(root) {
+        Ok(space) => space,
+        Err(_) => return Ok(()),
+    };
+    space.free = space.free.saturating_add(replaced);
+
+    if space.fits(size) {
+        return Ok(());
+    }
+
+    uefi::println!(
+        "Warning: not enough space on {} ({} bytes free, {} needed)",
+        if space.label.is_empty() { "filesystem" } else { space.label.as_str() },
+        space.free,
+        size as u64 + SPACE_MARGIN
+    );
+    crate::util::logger::log_entry(
+        log::Level::Warn,
+        &alloc::format!("Write of {} bytes refused: only {} bytes free", size, space.free),
+    );
+    Err(Error::NoSpace)
+}
+
+/// Read a file from the ESP
+pub fn read_file(path: &str) -> Result<Vec<u8>> {
     // Locate the SimpleFileSystem protocol
     let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
         .map_err(|e| Error::Uefi(e.status()))?;
 
     // Try each handle until we find one that works
     for handle in &*handles {
-        let result = try_read_from_handle(*handle, path_ucs2);
-        if result.is_ok() {
-            return result;
+        if let Ok(copy) = try_read_from_handle(*handle, path) {
+            return Ok(copy.data);
         }
     }
 
     Err(Error::NotFound)
 }
 
-/// Write a file to the ESP
-pub fn write_file(path: &str, data: &[u8]) -> Result<()> {
-    // Convert path to UCS-2
-    let mut path_buf = [0u16; 256];
-    let path_ucs2 = str_to_ucs2(path, &mut path_buf)?;
+/// A copy of a file read from one filesystem
+pub struct FileCopy {
+    /// File contents
+    pub data: Vec<u8>,
+    /// Modification time, packed so that later times compare greater
+    pub modified: u64,
+}
+
+/// Read every copy of a file found on the ESPs, in firmware order
+///
+/// Other volumes are never read: whoever plugs in a USB stick must not get
+/// to replace the file.
+pub fn read_file_copies(path: &str) -> Result<alloc::vec::Vec<FileCopy>> {
+    Ok(esp_handles()?
+        .into_iter()
+        .filter_map(|handle| try_read_from_handle(handle, path).ok())
+        .collect())
+}
+
+/// Read a file from the first ESP that has it
+///
+/// For files that decide what may boot, which no other volume gets to
+/// provide.
+pub fn read_esp_file(path: &str) -> Result<Vec<u8>> {
+    esp_handles()?
+        .into_iter()
+        .find_map(|handle| try_read_from_handle(handle, path).ok())
+        .map(|copy| copy.data)
+        .ok_or(Error::NotFound)
+}
+
+/// Filesystems the firmware flags as ESPs, or the volume we were started
+/// from when it flags none
+pub fn esp_handles() -> Result<alloc::vec::Vec<uefi::Handle>> {
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .map_err(|e| Error::Uefi(e.status()))?;
+
+    let esps: alloc::vec::Vec<uefi::Handle> = handles.iter().copied().filter(|h| is_esp(*h)).collect();
+    if !esps.is_empty() {
+        return Ok(esps);
+    }
+    let image =
+        boot::open_protocol_exclusive::<LoadedImage>(boot::image_handle()).map_err(|e| Error::Uefi(e.status()))?;
+    let device = image.device().ok_or(Error::NotFound)?;
+    Ok(handles.iter().copied().filter(|h| *h == device).collect())
+}
+
+/// Write a file to every ESP, returning how many copies were written
+///
+/// ESPs are identified through the Partition Info protocol. If the firmware
+/// does not flag any partition as an ESP, this behaves like `atomic_write`.
+pub fn write_file_mirrored(path: &str, data: &[u8]) -> Result<usize> {
+    check_target(path)?;
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .map_err(|e| Error::Uefi(e.status()))?;
+
+    let mut esp_count = 0;
+    let mut written = 0;
+    let mut last_error = Error::Io;
+    for handle in handles.iter().filter(|h| is_esp(**h)) {
+        esp_count += 1;
+        match try_write_to_handle(*handle, path, data) {
+            Ok(()) => written += 1,
+            Err(Error::NoSpace) => last_error = Error::NoSpace,
+            Err(_) => {}
+        }
+    }
+
+    if esp_count == 0 {
+        atomic_write(path, data)?;
+        return Ok(1);
+    }
+
+    if written == 0 {
+        return Err(last_error);
+    }
+
+    Ok(written)
+}
+
+/// Check whether a filesystem handle is an EFI System Partition
+fn is_esp(handle: uefi::Handle) -> bool {
+    let info = unsafe {
+        boot::open_protocol::<PartitionInfo>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    };
+
+    matches!(info, Ok(info) if info.is_system())
+}
+
+/// Write a file to the ESP so that it is either fully replaced or untouched
+///
+/// The data goes to `<path>.tmp` first and is flushed. The old file is then
+/// renamed to `<path>.bak`, the new one renamed into place, and the backup
+/// deleted. Power loss at any point leaves either the old or the new file
+/// readable, since reads fall back to the backup while the target is missing.
+pub fn atomic_write(path: &str, data: &[u8]) -> Result<()> {
+    check_target(path)?;
 
     // Locate the SimpleFileSystem protocol
     let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
         .map_err(|e| Error::Uefi(e.status()))?;
 
     // Try each handle until we find one that works
+    let mut last_error = Error::NotFound;
     for handle in &*handles {
-        let result = try_write_to_handle(*handle, path_ucs2, data);
-        if result.is_ok() {
-            return result;
+        match try_write_to_handle(*handle, path, data) {
+            Ok(()) => return Ok(()),
+            Err(Error::NoSpace) => last_error = Error::NoSpace,
+            Err(_) => {}
         }
     }
 
-    Err(Error::NotFound)
+    Err(last_error)
+}
+
+/// Append to a file on the first ESP that takes it, creating the file
+///
+/// Only ESPs are written, never whatever other volume happens to be
+/// writable (a USB key, an OS partition). A file that would grow past
+/// `rotate_at` bytes is first renamed with its `Sibling::Rotated` name,
+/// replacing the older part, so it stays bounded.
+pub fn append(path: &str, data: &[u8], rotate_at: u64) -> Result<()> {
+    check_target(path)?;
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .map_err(|e| Error::Uefi(e.status()))?;
+
+    let mut last_error = Error::NotFound;
+    for handle in handles.iter().copied().filter(|h| is_esp(*h)) {
+        match try_append_to_handle(handle, path, data, rotate_at) {
+            Ok(()) => return Ok(()),
+            Err(Error::NoSpace) => last_error = Error::NoSpace,
+            Err(_) => {}
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Append to a file on a specific filesystem handle, rotating it if needed
+fn try_append_to_handle(handle: uefi::Handle, path: &str, data: &[u8], rotate_at: u64) -> Result<()> {
+    let mut fs = unsafe {
+        boot::open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+        .map_err(|e| Error::Uefi(e.status()))?
+    };
+    let mut root = fs.open_volume().map_err(|e| Error::Uefi(e.status()))?;
+
+    check_space(&mut root, data.len(), 0)?;
+    create_parent_dirs(&mut root, path)?;
+
+    let mut file = open_regular(&mut root, path, FileMode::CreateReadWrite)?.ok_or(Error::Io)?;
+    let mut info_buf = [0u8; 256];
+    let size = file
+        .get_info::<FileInfo>(&mut info_buf)
+        .map_err(|e| Error::Uefi(e.status()))?
+        .file_size();
+
+    if size > 0 && size + data.len() as u64 > rotate_at {
+        let rotated = naming::sibling(path, Sibling::Rotated);
+        delete_file(&mut root, &rotated)?;
+        rename(file, &rotated)?;
+        file = open_regular(&mut root, path, FileMode::CreateReadWrite)?.ok_or(Error::Io)?;
+    } else {
+        file.set_position(RegularFile::END_OF_FILE).map_err(|e| Error::Uefi(e.status()))?;
+    }
+
+    file.write(data).map_err(|e| Error::Uefi(e.status()))?;
+    file.flush().map_err(|e| Error::Uefi(e.status()))
+}
+
+/// Delete a file (and any backup of it) from every filesystem
+///
+/// Returns how many copies were removed.
+pub fn delete(path: &str) -> Result<usize> {
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID))
+        .map_err(|e| Error::Uefi(e.status()))?;
+
+    let mut deleted = 0;
+    for handle in handles.iter() {
+        if let Ok(true) = try_delete_from_handle(*handle, path) {
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// Whether a file (or the backup an interrupted write left of it) is on
+/// any filesystem
+///
+/// A volume that cannot be read counts as not having it, so one broken
+/// disk does not make every name look taken.
+pub fn exists(path: &str) -> bool {
+    let Ok(handles) = boot::locate_handle_buffer(SearchType::ByProtocol(&SimpleFileSystem::GUID)) else {
+        return false;
+    };
+    handles.iter().any(|handle| try_exists_on_handle(*handle, path).unwrap_or(false))
+}
+
+fn try_exists_on_handle(handle: uefi::Handle, path: &str) -> Result<bool> {
+    let mut fs = unsafe {
+        boot::open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+        .map_err(|e| Error::Uefi(e.status()))?
+    };
+    let mut root = fs.open_volume().map_err(|e| Error::Uefi(e.status()))?;
+    Ok(open_regular(&mut root, path, FileMode::Read)?.is_some()
+        || open_regular(&mut root, &naming::sibling(path, Sibling::Backup), FileMode::Read)?.is_some())
+}
+
+/// A path for a new file in `dir` that no filesystem has yet: stamped with
+/// the current time, and numbered when that is taken too (see
+/// `naming::numbered`)
+pub fn unique_path(dir: &str, stem: &str, ext: &str) -> Result<alloc::string::String> {
+    let stamp = crate::util::rtc::now().ok().map(|time| crate::util::rtc::stamp(&time));
+    (0..naming::MAX_CANDIDATES)
+        .map(|n| naming::numbered(dir, stem, ext, stamp.as_deref(), n))
+        .find(|path| !exists(path))
+        .ok_or(Error::Io)
+}
+
+/// Delete a file from a specific filesystem handle, if it is there
+fn try_delete_from_handle(handle: uefi::Handle, path: &str) -> Result<bool> {
+    let mut fs = unsafe {
+        boot::open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle,
+                agent: boot::image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+        .map_err(|e| Error::Uefi(e.status()))?
+    };
+    let mut root = fs.open_volume().map_err(|e| Error::Uefi(e.status()))?;
+
+    // A backup would otherwise be read in place of the deleted file
+    delete_file(&mut root, &naming::sibling(path, Sibling::Backup))?;
+    match open_regular(&mut root, path, FileMode::ReadWrite)? {
+        Some(file) => file.delete().map(|_| true).map_err(|e| Error::Uefi(e.status())),
+        None => Ok(false),
+    }
 }
 
 /// Try to read a file from a specific filesystem handle
-fn try_read_from_handle(handle: uefi::Handle, path: &CStr16) -> Result<Vec<u8, 8192>> {
+fn try_read_from_handle(handle: uefi::Handle, path: &str) -> Result<FileCopy> {
     // Open the SimpleFileSystem protocol
     let mut fs = unsafe {
         boot::open_protocol::<SimpleFileSystem>(
@@ -68,14 +661,14 @@ fn try_read_from_handle(handle: uefi::Handle, path: &CStr16) -> Result<Vec<u8, 8
     // Open the root directory
     let mut root = fs.open_volume().map_err(|e| Error::Uefi(e.status()))?;
 
-    // Open the file
-    let file_handle = root
-        .open(path, FileMode::Read, FileAttribute::empty())
-        .map_err(|e| Error::Uefi(e.status()))?;
-
-    let mut file = match file_handle.into_type().map_err(|e| Error::Uefi(e.status()))? {
-        uefi::proto::media::file::FileType::Regular(f) => f,
-        uefi::proto::media::file::FileType::Dir(_) => return Err(Error::Io),
+    // Open the file, or the previous version if an atomic write was
+    // interrupted while the new one was being moved into place
+    let mut file = match open_regular(&mut root, path, FileMode::Read)? {
+        Some(file) => file,
+        None => {
+            let backup = naming::sibling(path, Sibling::Backup);
+            open_regular(&mut root, &backup, FileMode::Read)?.ok_or(Error::NotFound)?
+        }
     };
 
     // Get file size
@@ -85,22 +678,32 @@ fn try_read_from_handle(handle: uefi::Handle, path: &CStr16) -> Result<Vec<u8, 8
         .map_err(|e| Error::Uefi(e.status()))?;
 
     let file_size = info.file_size() as usize;
+    let modified = pack_time(info.modification_time());
 
-    if file_size > 8192 {
+    if file_size > MAX_FILE_SIZE {
         return Err(Error::BufferTooSmall);
     }
 
     // Read file contents
-    let mut buffer = Vec::new();
-    buffer.resize(file_size, 0).map_err(|_| Error::OutOfMemory)?;
+    let mut buffer = alloc::vec![0u8; file_size];
 
     file.read(&mut buffer).map_err(|e| Error::Uefi(e.status()))?;
 
-    Ok(buffer)
+    Ok(FileCopy {
+        data: buffer,
+        modified,
+    })
+}
+
+/// Pack a UEFI time into an integer that orders like the time itself
+fn pack_time(time: &uefi::runtime::Time) -> u64 {
+    let days = (time.year() as u64 * 12 + time.month() as u64) * 31 + time.day() as u64;
+    let seconds = time.hour() as u64 * 3600 + time.minute() as u64 * 60 + time.second() as u64;
+    (days * 86400 + seconds) * 1_000_000_000 + time.nanosecond() as u64
 }
 
-/// Try to write a file to a specific filesystem handle
-fn try_write_to_handle(handle: uefi::Handle, path: &CStr16, data: &[u8]) -> Result<()> {
+/// Atomically replace a file on a specific filesystem handle
+fn try_write_to_handle(handle: uefi::Handle, path: &str, data: &[u8]) -> Result<()> {
     // Open the SimpleFileSystem protocol
     let mut fs = unsafe {
         boot::open_protocol::<SimpleFileSystem>(
@@ -117,45 +720,194 @@ fn try_write_to_handle(handle: uefi::Handle, path: &CStr16, data: &[u8]) -> Resu
     // Open the root directory
     let mut root = fs.open_volume().map_err(|e| Error::Uefi(e.status()))?;
 
-    // Open/create the file
-    let file_handle = root
-        .open(
-            path,
-            FileMode::CreateReadWrite,
-            FileAttribute::empty(),
-        )
-        .map_err(|e| Error::Uefi(e.status()))?;
+    // A partial write would leave a truncated file behind
+    let replaced = size_on(&mut root, path);
+    check_space(&mut root, data.len(), replaced)?;
 
-    let mut file = match file_handle.into_type().map_err(|e| Error::Uefi(e.status()))? {
-        uefi::proto::media::file::FileType::Regular(f) => f,
-        uefi::proto::media::file::FileType::Dir(_) => return Err(Error::Io),
-    };
+    // Make sure the containing directories exist (e.g. on a fresh mirror ESP)
+    create_parent_dirs(&mut root, path)?;
 
-    // Write data
-    file.write(data).map_err(|e| Error::Uefi(e.status()))?;
+    let temp = naming::sibling(path, Sibling::Temp);
+    let backup = naming::sibling(path, Sibling::Backup);
 
-    // Flush
-    file.flush().map_err(|e| Error::Uefi(e.status()))?;
+    // Write the new contents next to the target, replacing any leftover
+    // from an interrupted write
+    delete_file(&mut root, &temp)?;
+    let mut file = open_regular(&mut root, &temp, FileMode::CreateReadWrite)?.ok_or(Error::Io)?;
+    let written = write_chunked(&mut file, data);
+    drop(file);
+    if let Err(e) = written {
+        let _ = delete_file(&mut root, &temp);
+        return Err(e);
+    }
+
+    // Some cheap media silently corrupt large writes, so only a copy that
+    // reads back intact may replace the old version
+    if let Err(e) = verify_written(&mut root, &temp, data) {
+        let _ = delete_file(&mut root, &temp);
+        return Err(e);
+    }
+    let file = open_regular(&mut root, &temp, FileMode::ReadWrite)?.ok_or(Error::Io)?;
 
+    // Keep the old version readable until the new one is in place
+    if let Some(old) = open_regular(&mut root, path, FileMode::ReadWrite)? {
+        delete_file(&mut root, &backup)?;
+        rename(old, &backup)?;
+    }
+
+    rename(file, path)?;
+    root.flush().map_err(|e| Error::Uefi(e.status()))?;
+
+    // Only now is the old version no longer needed; a leftover backup is
+    // replaced by the next write, so the new file stays a success
+    if let Err(e) = delete_file(&mut root, &backup) {
+        crate::util::logger::log_entry(
+            log::Level::Warn,
+            &alloc::format!("Could not delete {} after writing {}: {}", backup, path, e),
+        );
+    }
     Ok(())
 }
 
-/// Convert a Rust string to UCS-2 (UTF-16 without surrogates)
-fn str_to_ucs2<'a>(s: &str, buf: &'a mut [u16]) -> Result<&'a CStr16> {
-    if s.len() >= buf.len() {
-        return Err(Error::BufferTooSmall);
+/// Write data in chunks, reporting progress for large files
+fn write_chunked(file: &mut RegularFile, data: &[u8]) -> Result<()> {
+    let show_progress = data.len() >= PROGRESS_THRESHOLD;
+    let mut written = 0;
+
+    for chunk in data.chunks(WRITE_CHUNK) {
+        file.write(chunk).map_err(|e| Error::Uefi(e.status()))?;
+        if show_progress {
+            crate::util::ui::progress("Writing", written + chunk.len(), written, Some(data.len()));
+        }
+        written += chunk.len();
+    }
+    if show_progress {
+        crate::util::ui::progress_done();
     }
 
-    let mut i = 0;
-    for c in s.chars() {
-        if i >= buf.len() - 1 {
-            return Err(Error::BufferTooSmall);
+    file.flush().map_err(|e| Error::Uefi(e.status()))
+}
+
+/// Read a freshly written file back and compare its hash with the data
+fn verify_written(root: &mut Directory, path: &str, data: &[u8]) -> Result<()> {
+    let mut file = open_regular(root, path, FileMode::Read)?.ok_or(Error::Io)?;
+    let mut hasher = Sha256::new();
+    let mut chunk = alloc::vec![0u8; WRITE_CHUNK];
+    let mut total = 0;
+
+    loop {
+        let read = file.read(&mut chunk).map_err(|e| Error::Uefi(e.status()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&chunk[..read]);
+        total += read;
+    }
+
+    if total == data.len() && hasher.finalize() == sha256::digest(data) {
+        return Ok(());
+    }
+
+    uefi::println!("Error: {} did not read back correctly ({} of {} bytes), media may be faulty", path, total, data.len());
+    crate::util::logger::log_entry(
+        log::Level::Error,
+        &alloc::format!("Verify after write failed for {}", path),
+    );
+    Err(Error::Io)
+}
+
+/// Open a regular file, or `None` if it does not exist
+fn open_regular(root: &mut Directory, path: &str, mode: FileMode) -> Result<Option<RegularFile>> {
+    let path_ucs2 = str_to_ucs2(path)?;
+
+    let file_handle = match root.open(&path_ucs2, mode, FileAttribute::empty()) {
+        Ok(handle) => handle,
+        Err(e) if e.status() == uefi::Status::NOT_FOUND => return Ok(None),
+        Err(e) => return Err(Error::Uefi(e.status())),
+    };
+
+    match file_handle.into_type().map_err(|e| Error::Uefi(e.status()))? {
+        FileType::Regular(f) => Ok(Some(f)),
+        FileType::Dir(_) => Err(Error::Io),
+    }
+}
+
+/// Size of a file, 0 if it does not exist or cannot be read
+fn size_on(root: &mut Directory, path: &str) -> u64 {
+    let Ok(Some(mut file)) = open_regular(root, path, FileMode::Read) else {
+        return 0;
+    };
+    let mut info_buf = [0u8; 256];
+    file.get_info::<FileInfo>(&mut info_buf).map_or(0, |info| info.file_size())
+}
+
+/// Delete a file if it exists
+fn delete_file(root: &mut Directory, path: &str) -> Result<()> {
+    match open_regular(root, path, FileMode::ReadWrite)? {
+        Some(file) => file.delete().map_err(|e| Error::Uefi(e.status())),
+        None => Ok(()),
+    }
+}
+
+/// Rename (move) an open file, flushing the change to disk
+fn rename(mut file: RegularFile, new_path: &str) -> Result<()> {
+    let name = str_to_ucs2(new_path)?;
+
+    // Everything but the name stays as it is
+    let mut info_buf = [0u8; 1024];
+    let info = file
+        .get_info::<FileInfo>(&mut info_buf)
+        .map_err(|e| Error::Uefi(e.status()))?;
+    let size = info.file_size();
+    let physical_size = info.physical_size();
+    let created = *info.create_time();
+    let accessed = *info.last_access_time();
+    let modified = *info.modification_time();
+    let attribute = info.attribute();
+
+    let mut new_buf = [0u8; 1024];
+    let new_info = FileInfo::new(&mut new_buf, size, physical_size, created, accessed, modified, attribute, &name)
+        .map_err(|_| Error::BufferTooSmall)?;
+
+    file.set_info(new_info).map_err(|e| Error::Uefi(e.status()))?;
+    file.flush().map_err(|e| Error::Uefi(e.status()))
+}
+
+/// Refuse to write a file named like another file's temp, backup or
+/// rotated copy, which the next write of that file would clobber (or
+/// which would clobber it)
+fn check_target(path: &str) -> Result<()> {
+    if naming::is_sibling(path) {
+        crate::util::logger::log_entry(
+            log::Level::Warn,
+            &alloc::format!("Write to {} refused: the name is kept for another file's copies", path),
+        );
+        return Err(Error::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Create every directory leading up to the final path component
+fn create_parent_dirs(root: &mut Directory, path: &str) -> Result<()> {
+    let path = super::path::normalize(path)?;
+    for (i, c) in path.char_indices() {
+        if c != '\\' || i == 0 {
+            continue;
         }
-        buf[i] = c as u16;
-        i += 1;
+
+        let dir_ucs2 = str_to_ucs2(&path[..i])?;
+        root.open(&dir_ucs2, FileMode::CreateReadWrite, FileAttribute::DIRECTORY)
+            .map_err(|e| Error::Uefi(e.status()))?;
     }
-    buf[i] = 0; // Null terminator
 
-    // Safety: We just null-terminated the buffer
-    unsafe { Ok(CStr16::from_u16_with_nul_unchecked(&buf[..=i])) }
+    Ok(())
+}
+
+/// Convert a path to the UCS-2 form the firmware expects
+///
+/// The path is normalized first (see `path::normalize`), so `/` separators
+/// work and names FAT cannot store are rejected instead of mangled.
+fn str_to_ucs2(path: &str) -> Result<CString16> {
+    let path = super::path::normalize(path)?;
+    ucs2::to_cstring16(&path)
 }
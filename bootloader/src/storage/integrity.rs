@@ -0,0 +1,160 @@
+use crate::storage::{vars, Config};
+use crate::util::caps::{self, Capability};
+use crate::util::tpm;
+use crate::util::{Error, Result};
+use uefi::cstr16;
+
+/// Variable holding the integrity record of the saved configuration
+///
+/// The variable is boot-services-only, so an OS (or anything else running
+/// after ExitBootServices) can rewrite config.txt on the ESP but cannot
+/// update the matching hash.
+const RECORD_VAR: &uefi::CStr16 = cstr16!("ConfigHash");
+
+/// Record layout: flags byte followed by the SHA256 of the config file
+const RECORD_LEN: usize = 1 + 32;
+
+/// Refuse to load a config whose hash does not match
+const FLAG_STRICT: u8 = 0x01;
+/// A hash has been recorded
+const FLAG_HASHED: u8 = 0x02;
+
+/// SHA256 of the configuration last measured into the TPM
+static mut MEASURED: Option<[u8; 32]> = None;
+
+/// Outcome of comparing a config file against the stored hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// The file is the one uefipxe last saved
+    Match,
+    /// The file was modified outside uefipxe
+    Mismatch,
+    /// No hash has been recorded yet
+    NoRecord,
+}
+
+/// Stored integrity record
+#[derive(Debug, Clone, Copy, Default)]
+struct Record {
+    flags: u8,
+    hash: [u8; 32],
+}
+
+impl Record {
+    /// Read the record, falling back to an empty one
+    fn load() -> Self {
+        let mut buf = [0u8; RECORD_LEN];
+        match vars::read(RECORD_VAR, &mut buf) {
+            Ok(RECORD_LEN) => {
+                let mut hash = [0u8; 32];
+                hash.copy_from_slice(&buf[1..]);
+                Record { flags: buf[0], hash }
+            }
+            _ => Record::default(),
+        }
+    }
+
+    /// Write the record back
+    fn store(&self) -> Result<()> {
+        let mut buf = [0u8; RECORD_LEN];
+        buf[0] = self.flags;
+        buf[1..].copy_from_slice(&self.hash);
+        vars::write(RECORD_VAR, vars::BOOT_ONLY, &buf)
+    }
+}
+
+/// SHA256 of the raw config file contents
+fn digest(data: &[u8]) -> [u8; 32] {
+    crate::util::sha256::digest(data)
+}
+
+/// Record the hash of a config file that was just saved
+pub fn record(data: &[u8]) -> Result<()> {
+    let mut record = Record::load();
+    record.flags |= FLAG_HASHED;
+    record.hash = digest(data);
+    record.store()
+}
+
+/// Compare a config file against the recorded hash
+pub fn check(data: &[u8]) -> Check {
+    let record = Record::load();
+
+    if record.flags & FLAG_HASHED == 0 {
+        Check::NoRecord
+    } else if record.hash == digest(data) {
+        Check::Match
+    } else {
+        Check::Mismatch
+    }
+}
+
+/// Verify a config file before it is parsed
+///
+/// A mismatch is reported loudly; in strict mode the file is rejected.
+pub fn verify(data: &[u8]) -> Result<()> {
+    match check(data) {
+        Check::Match | Check::NoRecord => Ok(()),
+        Check::Mismatch => {
+            uefi::println!();
+            uefi::println!("!!! WARNING: config.txt was modified outside uefipxe !!!");
+            uefi::println!("!!! Its SHA256 does not match the hash recorded at the last save !!!");
+            if is_strict() {
+                uefi::println!("!!! Strict mode is enabled, refusing to load it !!!");
+                uefi::println!();
+                return Err(Error::SecurityViolation);
+            }
+            uefi::println!("!!! Run 'save' to accept the current contents !!!");
+            uefi::println!();
+            Ok(())
+        }
+    }
+}
+
+/// Whether tampered configs are refused rather than just reported
+///
+/// Either `config strict on` or `strict=true` in policy.txt enables it.
+pub fn is_strict() -> bool {
+    Record::load().flags & FLAG_STRICT != 0 || crate::storage::policy::is_strict()
+}
+
+/// Enable or disable strict mode
+pub fn set_strict(strict: bool) -> Result<()> {
+    let mut record = Record::load();
+    if strict {
+        record.flags |= FLAG_STRICT;
+    } else {
+        record.flags &= !FLAG_STRICT;
+    }
+    record.store()
+}
+
+/// Measure the effective configuration into `tpm::CONFIG_PCR`
+///
+/// What is measured is the configuration as uefipxe uses it (serialized,
+/// after any volume redirect), not the file it was read from, so a
+/// verifier can recompute the digest from the menu it expects. Nothing
+/// is extended again until the configuration changes. Returns whether a
+/// measurement was made; without a TPM there is nothing to do.
+pub fn measure(config: &Config) -> Result<bool> {
+    if !caps::has(Capability::Tcg2) {
+        return Ok(false);
+    }
+
+    let content = config.serialize()?;
+    let digest = digest(content.as_bytes());
+    if unsafe { MEASURED } == Some(digest) {
+        return Ok(false);
+    }
+
+    tpm::extend(tpm::CONFIG_PCR, content.as_bytes(), "uefipxe effective configuration")?;
+    unsafe {
+        MEASURED = Some(digest);
+    }
+    Ok(true)
+}
+
+/// SHA256 of the last measured configuration, for status output
+pub fn measured() -> Option<[u8; 32]> {
+    unsafe { MEASURED }
+}
@@ -1,56 +1,293 @@
+pub mod cache;
 pub mod config;
 pub mod file;
+pub mod integrity;
+pub mod naming;
+pub mod nvram;
+pub mod path;
+pub mod policy;
+pub mod remote;
+pub mod secrets;
+pub mod signed;
+pub mod vars;
 
+use crate::network::url;
+use crate::util::global::Global;
+use crate::util::platform::Arch;
 use crate::util::{Error, Result};
+use alloc::borrow::Cow;
 
 pub use config::Config;
 
-/// Load configuration from ESP
-pub fn load_config() -> Result<Config> {
-    match file::read_file(file::CONFIG_PATH) {
-        Ok(data) => {
-            // Convert bytes to string
-            let content = core::str::from_utf8(&data).map_err(|_| Error::Parse)?;
+/// Where the configuration is kept between boots
+pub trait Backend {
+    /// Name for messages
+    fn name(&self) -> &'static str;
+
+    /// Read the stored configuration (None = nothing stored here)
+    fn load(&self) -> Result<Option<Config>>;
+
+    /// Store `content`, the serialized `config`
+    fn save(&self, config: &Config, content: &str) -> Result<()>;
+}
+
+/// Which backend `save` writes to (`storage=` in the config)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Store {
+    /// config.txt on the ESP(s)
+    #[default]
+    Esp,
+    /// UEFI variables, for a read-only ESP
+    Nvram,
+}
+
+impl Store {
+    /// Parse a backend name as used in config.txt and `config storage`
+    pub fn parse(name: &str) -> Result<Self> {
+        if name.eq_ignore_ascii_case("esp") {
+            Ok(Store::Esp)
+        } else if name.eq_ignore_ascii_case("nvram") {
+            Ok(Store::Nvram)
+        } else {
+            Err(Error::InvalidArgument)
+        }
+    }
+
+    /// Backend name as written in config.txt
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Store::Esp => "esp",
+            Store::Nvram => "nvram",
+        }
+    }
+
+    /// The backend itself
+    pub fn backend(&self) -> &'static dyn Backend {
+        match self {
+            Store::Esp => &Esp,
+            Store::Nvram => &nvram::Nvram,
+        }
+    }
+}
+
+/// config.txt on the ESP, on every ESP with `mirror_esp=true`
+pub struct Esp;
+
+impl Backend for Esp {
+    fn name(&self) -> &'static str {
+        "ESP"
+    }
+
+    fn load(&self) -> Result<Option<Config>> {
+        load_local_config()
+    }
+
+    fn save(&self, config: &Config, content: &str) -> Result<()> {
+        // Write to file (all ESPs when mirroring)
+        if config.mirror_esp {
+            let copies = file::write_file_mirrored(file::CONFIG_PATH, content.as_bytes())?;
+            uefi::println!("Configuration written to {} ESP(s)", copies);
+        } else {
+            file::atomic_write(file::CONFIG_PATH, content.as_bytes())?;
+        }
 
-            // Parse configuration
-            Config::parse(content)
+        // Remember what we wrote so outside modifications can be detected
+        if let Err(e) = integrity::record(content.as_bytes()) {
+            uefi::println!("Warning: Could not record config hash: {}", e);
         }
-        Err(Error::NotFound) => {
-            // Config file doesn't exist, return empty config
+        Ok(())
+    }
+}
+
+/// Load the configuration
+///
+/// A configuration saved to NVRAM (`storage=nvram`) comes first, as the
+/// ESP's copy may be read-only and out of date; otherwise it is read from
+/// the ESP. If that configuration names a `volume`, the copy on that volume
+/// (e.g. a golden config on a USB stick) is used instead while the volume
+/// is present.
+pub fn load_config() -> Result<Config> {
+    let stored = match nvram::Nvram.load() {
+        Ok(Some(config)) => {
+            uefi::println!("Using configuration from NVRAM");
+            Some(config)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            uefi::println!("Configuration in NVRAM not usable ({}), using the ESP's", e);
+            None
+        }
+    };
+    let config = match stored {
+        Some(config) => config,
+        None => Esp.load()?.unwrap_or_else(|| {
             uefi::println!("Config file not found, using empty configuration");
-            Ok(Config::new())
+            Config::new()
+        }),
+    };
+
+    let volume = match config.volume {
+        Some(ref volume) => volume.clone(),
+        None => return Ok(config),
+    };
+
+    match load_volume_config(&volume) {
+        Ok(mut volume_config) => {
+            uefi::println!("Using configuration from volume {}", volume);
+            // Keep the redirect when this config is saved back to the ESP
+            volume_config.volume = Some(volume);
+            Ok(volume_config)
+        }
+        Err(e) => {
+            uefi::println!("Volume {} not usable ({}), using local configuration", volume, e);
+            Ok(config)
+        }
+    }
+}
+
+/// Load the configuration stored on a specific volume
+///
+/// With `signed_config=true` in the policy, the copy must be signed by a
+/// trusted key (inline or in `config.txt.sig`), since whoever holds the
+/// volume would otherwise control the boot menu.
+fn load_volume_config(volume: &str) -> Result<Config> {
+    let data = file::read_file_from(volume, file::CONFIG_PATH)?;
+    let content = core::str::from_utf8(&data).map_err(|_| Error::Parse)?;
+
+    let Some(keys) = policy::config_keys() else {
+        return Config::parse(content);
+    };
+
+    let sidecar_path = alloc::format!("{}{}", file::CONFIG_PATH, signed::SIDECAR_SUFFIX);
+    let sidecar = file::read_file_from(volume, &sidecar_path).ok();
+    let sidecar = sidecar.as_ref().and_then(|data| core::str::from_utf8(data).ok());
+    match signed::verify(content, sidecar, keys) {
+        Ok((body, key)) => {
+            uefi::println!("Configuration on volume {} is signed by key '{}'", volume, key);
+            Config::parse(body)
         }
-        Err(e) => Err(e),
+        Err(e) => {
+            uefi::println!("Configuration on volume {} is not signed by a trusted key", volume);
+            Err(e)
+        }
+    }
+}
+
+/// Load the configuration from the ESPs (None = no ESP has one)
+///
+/// The first ESP's copy is used unless it enables `mirror_esp`. Then, or
+/// when it is unusable, the other ESPs' copies that enable `mirror_esp`
+/// themselves are mirrors, and the most recently modified valid copy wins.
+/// Every copy is checked against the hash recorded at the last save (see
+/// `integrity`).
+fn load_local_config() -> Result<Option<Config>> {
+    let copies = file::read_file_copies(file::CONFIG_PATH)?;
+
+    let mut parsed: alloc::vec::Vec<(u64, Result<Config>)> = copies
+        .iter()
+        .map(|copy| {
+            let config = integrity::verify(&copy.data).and_then(|()| {
+                core::str::from_utf8(&copy.data).map_err(|_| Error::Parse).and_then(Config::parse)
+            });
+            (copy.modified, config)
+        })
+        .collect();
+
+    match parsed.first() {
+        None => return Ok(None),
+        Some((_, Ok(config))) if !config.mirror_esp => return Ok(Some(config.clone())),
+        _ => {}
+    }
+
+    let mirrors = parsed.iter().skip(1).filter(|(_, config)| config.as_ref().is_ok_and(|c| c.mirror_esp)).count();
+    if mirrors > 0 {
+        uefi::println!("Found {} mirrored copies of the configuration, using the newest valid one", mirrors + 1);
     }
+
+    let first_error = match parsed.first() {
+        Some((_, Err(e))) => *e,
+        _ => Error::Parse,
+    };
+    let mut candidates: alloc::vec::Vec<(u64, Config)> = parsed
+        .drain(..)
+        .enumerate()
+        .filter_map(|(i, (modified, config))| config.ok().filter(|c| i == 0 || c.mirror_esp).map(|c| (modified, c)))
+        .collect();
+
+    // Newest first
+    candidates.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    candidates.into_iter().next().map(|(_, config)| Some(config)).ok_or(first_error)
 }
 
-/// Save configuration to ESP
+/// Save configuration to the backend `storage=` selects
 pub fn save_config(config: &Config) -> Result<()> {
     // Serialize configuration
     let content = config.serialize()?;
+    config.storage.backend().save(config, &content)?;
 
-    // Write to file
-    file::write_file(file::CONFIG_PATH, content.as_bytes())?;
-
+    // NVRAM is read first at startup, where a copy would hide the ESP's
+    if config.storage != Store::Nvram {
+        nvram::clear()?;
+    }
     Ok(())
 }
 
 /// Global configuration state
-static mut GLOBAL_CONFIG: Option<Config> = None;
+static GLOBAL_CONFIG: Global<Config> = Global::new();
 
 /// Initialize global configuration
 pub fn init_config(config: Config) {
-    unsafe {
-        GLOBAL_CONFIG = Some(config);
-    }
+    GLOBAL_CONFIG.set(config);
+}
+
+/// Run `f` with the global configuration (None before `init_config`)
+///
+/// Keep `f` short: copy out what a download or a command needs rather than
+/// running them inside, as they may change the configuration.
+pub fn with_config<R>(f: impl FnOnce(&Config) -> R) -> Option<R> {
+    GLOBAL_CONFIG.with(f)
 }
 
-/// Get a reference to the global configuration
-pub fn get_config() -> Option<&'static Config> {
-    unsafe { GLOBAL_CONFIG.as_ref() }
+/// Run `f` with the global configuration, mutably (None before
+/// `init_config`)
+///
+/// `f` must not read the configuration again through `with_config`, which
+/// would panic.
+pub fn with_config_mut<R>(f: impl FnOnce(&mut Config) -> R) -> Option<R> {
+    GLOBAL_CONFIG.with_mut(f)
 }
 
-/// Get a mutable reference to the global configuration
-pub fn get_config_mut() -> Option<&'static mut Config> {
-    unsafe { GLOBAL_CONFIG.as_mut() }
+/// What an entry's `url=` points at
+///
+/// `${arch}` is filled in with this machine's architecture, or with
+/// `arch_suffix=true` the architecture is appended to the file name. A
+/// relative URL (e.g. `images/rescue.efi` or `/rescue.efi`) is then
+/// resolved against `base_url=`, or without one against the URL HTTP Boot
+/// loaded us from. Without either it stays relative, and fails to parse.
+pub fn entry_url(url: &str) -> Cow<'_, str> {
+    let (arch_suffix, base_url) = with_config(|c| (c.arch_suffix, c.base_url.clone())).unwrap_or_default();
+    let url = match Arch::CURRENT {
+        Some(arch) if !url.contains(url::ARCH_PLACEHOLDER) && arch_suffix => {
+            Cow::Owned(url::append_arch(url, arch.as_str()))
+        }
+        _ => with_arch(url),
+    };
+
+    let base = base_url.as_deref().map(with_arch);
+    let base = base.or_else(|| crate::network::httpboot::origin().map(|origin| Cow::Owned(origin.url)));
+    match url::relative_to(base.as_deref(), &url) {
+        Some(resolved) => Cow::Owned(resolved),
+        None => url,
+    }
+}
+
+/// `text` with `${arch}` replaced by this machine's architecture, named as
+/// in UEFI (`x64`, `aa64`, `riscv64`, ...)
+pub fn with_arch(text: &str) -> Cow<'_, str> {
+    match Arch::CURRENT {
+        Some(arch) if text.contains(url::ARCH_PLACEHOLDER) => {
+            Cow::Owned(text.replace(url::ARCH_PLACEHOLDER, arch.as_str()))
+        }
+        _ => Cow::Borrowed(text),
+    }
 }
@@ -0,0 +1,94 @@
+use alloc::format;
+use alloc::string::String;
+
+/// Most numbered candidates `file::unique_path` tries before giving up
+pub const MAX_CANDIDATES: u32 = 100;
+
+/// A file kept next to another one, named after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sibling {
+    /// The new contents while `atomic_write` writes them
+    Temp,
+    /// The previous version while `atomic_write` replaces it
+    Backup,
+    /// The older part of a file rotated by `append`
+    Rotated,
+}
+
+impl Sibling {
+    pub const ALL: [Sibling; 3] = [Sibling::Temp, Sibling::Backup, Sibling::Rotated];
+
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Sibling::Temp => ".tmp",
+            Sibling::Backup => ".bak",
+            Sibling::Rotated => ".old",
+        }
+    }
+}
+
+/// The `kind` sibling of the file at `path`
+pub fn sibling(path: &str, kind: Sibling) -> String {
+    format!("{}{}", path, kind.suffix())
+}
+
+/// Whether `path` is named like another file's sibling
+///
+/// Writing to such a path would replace, or later be replaced by, the
+/// temp, backup or rotated copy of that other file.
+pub fn is_sibling(path: &str) -> bool {
+    let name = path.rsplit('\\').next().unwrap_or(path);
+    Sibling::ALL.iter().any(|kind| {
+        name.len() > kind.suffix().len()
+            && name.get(name.len() - kind.suffix().len()..).is_some_and(|end| end.eq_ignore_ascii_case(kind.suffix()))
+    })
+}
+
+/// Candidate `n` for a new file in `dir`: `<stem>-<stamp><ext>`, with
+/// `-<n>` before the extension from the second candidate on
+///
+/// Without a clock the stamp is left out and the count alone tells files
+/// apart.
+pub fn numbered(dir: &str, stem: &str, ext: &str, stamp: Option<&str>, n: u32) -> String {
+    let mut name = String::from(stem);
+    if let Some(stamp) = stamp {
+        name.push('-');
+        name.push_str(stamp);
+    }
+    if n > 0 || stamp.is_none() {
+        name.push_str(&format!("-{}", n + 1));
+    }
+    format!("{}\\{}{}", dir.trim_end_matches('\\'), name, ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sibling() {
+        assert_eq!(sibling("\\EFI\\uefipxe\\config.txt", Sibling::Backup), "\\EFI\\uefipxe\\config.txt.bak");
+        assert!(is_sibling("\\EFI\\uefipxe\\config.txt.bak"));
+        assert!(is_sibling("\\EFI\\uefipxe\\boot.log.OLD"));
+        assert!(is_sibling("selftest.tmp"));
+        assert!(!is_sibling("\\EFI\\uefipxe\\config.txt"));
+        // A bare suffix is a name of its own, and directories do not count
+        assert!(!is_sibling("\\EFI\\.tmp"));
+        assert!(!is_sibling("\\EFI\\a.tmp\\image.efi"));
+    }
+
+    #[test]
+    fn test_numbered() {
+        let stamp = Some("20261016-093000");
+        assert_eq!(numbered("\\EFI\\uefipxe", "diag", ".txt", stamp, 0), "\\EFI\\uefipxe\\diag-20261016-093000.txt");
+        assert_eq!(
+            numbered("\\EFI\\uefipxe\\", "diag", ".txt", stamp, 1),
+            "\\EFI\\uefipxe\\diag-20261016-093000-2.txt"
+        );
+        assert_eq!(numbered("\\EFI\\uefipxe", "selftest", ".dat", None, 0), "\\EFI\\uefipxe\\selftest-1.dat");
+        assert_eq!(numbered("\\EFI\\uefipxe", "selftest", ".dat", None, 4), "\\EFI\\uefipxe\\selftest-5.dat");
+        for n in 0..MAX_CANDIDATES {
+            assert!(!is_sibling(&numbered("\\EFI", "x", ".txt", stamp, n)));
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::storage::config::MAX_CONFIG_LEN;
+use crate::storage::{vars, Backend, Config};
+use crate::util::{ucs2, Error, Result};
+use alloc::vec::Vec;
+use uefi::{CString16, Status};
+
+/// Bytes of the record per variable
+///
+/// Firmware limits the size of a single variable, in the smallest builds
+/// to 1 KiB including its name and header, so the record is split over
+/// `Config0`, `Config1`, ...
+const CHUNK_LEN: usize = 768;
+
+/// Record header: length of the text (u32 LE), then its SHA256
+const HEADER_LEN: usize = 4 + 32;
+
+/// Most variables a record can take
+const MAX_CHUNKS: usize = (HEADER_LEN + MAX_CONFIG_LEN).div_ceil(CHUNK_LEN);
+
+/// The configuration kept in boot-services-only UEFI variables, for
+/// machines whose ESP is read-only
+///
+/// Like the ESP integrity record, the variables cannot be changed from
+/// the running OS.
+pub struct Nvram;
+
+impl Backend for Nvram {
+    fn name(&self) -> &'static str {
+        "NVRAM"
+    }
+
+    fn load(&self) -> Result<Option<Config>> {
+        let mut record = Vec::new();
+        let mut buf = [0u8; CHUNK_LEN];
+        for i in 0..MAX_CHUNKS {
+            match vars::read(&chunk_name(i)?, &mut buf) {
+                Ok(len) => record.extend_from_slice(&buf[..len]),
+                Err(Error::NotFound) if i == 0 => return Ok(None),
+                Err(Error::NotFound) => break,
+                Err(e) => return Err(e),
+            }
+            // Chunks after the end are left over from a longer record
+            if declared_len(&record).is_some_and(|len| record.len() >= HEADER_LEN + len) {
+                break;
+            }
+        }
+
+        let text = core::str::from_utf8(decode(&record)?).map_err(|_| Error::Parse)?;
+        Config::parse(text).map(Some)
+    }
+
+    fn save(&self, _config: &Config, content: &str) -> Result<()> {
+        let record = encode(content.as_bytes());
+        for (i, chunk) in record.chunks(CHUNK_LEN).enumerate() {
+            vars::write(&chunk_name(i)?, vars::BOOT_ONLY, chunk).map_err(|e| match e {
+                Error::Uefi(Status::OUT_OF_RESOURCES) => Error::NoSpace,
+                e => e,
+            })?;
+        }
+        delete_from(record.len().div_ceil(CHUNK_LEN))
+    }
+}
+
+/// Remove the configuration from NVRAM, so the ESP's is used again
+pub fn clear() -> Result<()> {
+    delete_from(0)
+}
+
+/// Delete the record's variables from chunk `first` on
+fn delete_from(first: usize) -> Result<()> {
+    for i in first..MAX_CHUNKS {
+        vars::delete(&chunk_name(i)?)?;
+    }
+    Ok(())
+}
+
+/// Name of the variable holding chunk `index`
+fn chunk_name(index: usize) -> Result<CString16> {
+    ucs2::to_cstring16(&alloc::format!("Config{}", index))
+}
+
+/// Length of the text a record announces, once its header is complete
+fn declared_len(record: &[u8]) -> Option<usize> {
+    let len = record.get(..4)?;
+    Some(u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+}
+
+/// The record stored for configuration text `content`
+fn encode(content: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_LEN + content.len());
+    record.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crate::util::sha256::digest(content));
+    record.extend_from_slice(content);
+    record
+}
+
+/// The configuration text of a record, if it is complete and matches the
+/// hash in its header (a save cut short by a reset leaves one that is not)
+fn decode(record: &[u8]) -> Result<&[u8]> {
+    let len = declared_len(record).ok_or(Error::Parse)?;
+    let content = record.get(HEADER_LEN..HEADER_LEN + len).ok_or(Error::Parse)?;
+    if crate::util::sha256::digest(content)[..] != record[4..HEADER_LEN] {
+        return Err(Error::Parse);
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let content = b"url=http://10.0.0.5/node.efi\nstorage=nvram\n";
+        let record = encode(content);
+        assert_eq!(record.len(), HEADER_LEN + content.len());
+        assert_eq!(decode(&record).unwrap(), content);
+
+        // Chunks of a longer record that are still around
+        let mut longer = record.clone();
+        longer.extend_from_slice(b"default=3\n");
+        assert_eq!(decode(&longer).unwrap(), content);
+
+        // A save that did not get to every chunk
+        assert!(decode(&record[..record.len() - 1]).is_err());
+        let mut mixed = record.clone();
+        mixed[HEADER_LEN] = b'U';
+        assert!(decode(&mixed).is_err());
+        assert!(decode(&record[..3]).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::util::platform::Arch;
+use crate::util::{sha256, ucs2};
+use crate::util::{Error, Result};
+use alloc::string::String;
+use core::fmt::Write;
+
+/// Longest file or directory name FAT long file names allow (UTF-16 units)
+pub const MAX_COMPONENT_LEN: usize = 255;
+
+/// Hex digits of the hash appended to shortened names
+const HASH_SUFFIX_LEN: usize = 16;
+
+/// Characters FAT does not allow in names (control characters aside)
+const FAT_RESERVED: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+fn is_reserved(c: char) -> bool {
+    c.is_control() || FAT_RESERVED.contains(&c)
+}
+
+/// Whether a single name is valid on FAT
+pub fn is_valid_component(name: &str) -> bool {
+    !name.is_empty()
+        && ucs2::len(name) <= MAX_COMPONENT_LEN
+        && !name.ends_with('.')
+        && !name.ends_with(' ')
+        && !name.chars().any(is_reserved)
+        && ucs2::is_representable(name)
+}
+
+/// Whether a volume matches a `volume=` selector
+///
+/// Selectors containing `(` are matched against the device path text
+/// (e.g. `USB(0x2,0x0)` or a full `PciRoot(...)/...` path), anything else
+/// against the volume label. Both comparisons ignore ASCII case.
+pub fn volume_matches(selector: &str, label: &str, device_path: &str) -> bool {
+    if selector.contains('(') {
+        device_path
+            .as_bytes()
+            .windows(selector.len())
+            .any(|window| window.eq_ignore_ascii_case(selector.as_bytes()))
+    } else {
+        !selector.is_empty() && label.eq_ignore_ascii_case(selector)
+    }
+}
+
+/// Removable-media loader path for this architecture, which firmware
+/// starts from a disk without a boot entry of its own
+pub fn removable_loader() -> &'static str {
+    match Arch::CURRENT {
+        Some(Arch::Ia32) => "\\EFI\\BOOT\\BOOTIA32.EFI",
+        Some(Arch::Aa64) => "\\EFI\\BOOT\\BOOTAA64.EFI",
+        Some(Arch::Arm) => "\\EFI\\BOOT\\BOOTARM.EFI",
+        Some(Arch::RiscV64) => "\\EFI\\BOOT\\BOOTRISCV64.EFI",
+        Some(Arch::LoongArch64) => "\\EFI\\BOOT\\BOOTLOONGARCH64.EFI",
+        Some(Arch::X64) | None => "\\EFI\\BOOT\\BOOTX64.EFI",
+    }
+}
+
+/// Normalize a path to the form the firmware expects
+///
+/// `/` becomes `\`, repeated separators collapse, `.` and `..` are
+/// resolved, and the result always starts at the volume root. Components
+/// that FAT cannot store are rejected.
+pub fn normalize(path: &str) -> Result<String> {
+    let mut out = String::with_capacity(path.len() + 1);
+
+    for component in path.split(['\\', '/']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                // Climbing above the root is always a mistake
+                let parent = out.rfind('\\').ok_or(Error::InvalidArgument)?;
+                out.truncate(parent);
+            }
+            name if is_valid_component(name) => {
+                out.push('\\');
+                out.push_str(name);
+            }
+            _ => return Err(Error::InvalidArgument),
+        }
+    }
+
+    if out.is_empty() {
+        out.push('\\');
+    }
+
+    Ok(out)
+}
+
+/// Turn arbitrary text (e.g. a URL) into a valid FAT name
+///
+/// Reserved characters, `%` and trailing dots or spaces are percent-escaped,
+/// so distinct inputs give distinct names. Names that end up too long are
+/// shortened and suffixed with a hash of the full input.
+pub fn escape_component(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+
+    let keep = name.trim_end_matches(['.', ' ']).len();
+    for (i, c) in name.char_indices() {
+        if is_reserved(c) || c == '%' || ucs2::unit(c).is_err() || i >= keep {
+            let mut buf = [0u8; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                let _ = write!(out, "%{:02X}", b);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+
+    if out.is_empty() {
+        out.push('_');
+    }
+
+    if ucs2::len(&out) > MAX_COMPONENT_LEN {
+        let digest = sha256::digest(name.as_bytes());
+
+        // Everything kept so far is BMP, so one char is one UTF-16 unit
+        let cut = out
+            .char_indices()
+            .nth(MAX_COMPONENT_LEN - HASH_SUFFIX_LEN - 1)
+            .map_or(out.len(), |(i, _)| i);
+        out.truncate(cut);
+        out.push('~');
+        for b in &digest[..HASH_SUFFIX_LEN / 2] {
+            let _ = write!(out, "{:02x}", b);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("/EFI//uefipxe/config.txt").unwrap(), "\\EFI\\uefipxe\\config.txt");
+        assert_eq!(normalize("EFI\\.\\uefipxe\\..\\boot").unwrap(), "\\EFI\\boot");
+        assert_eq!(normalize("").unwrap(), "\\");
+        assert!(normalize("\\..\\x").is_err());
+        assert!(normalize("\\EFI\\a:b").is_err());
+        assert!(normalize("\\EFI\\name.").is_err());
+    }
+
+    #[test]
+    fn test_long_path() {
+        let mut long = String::new();
+        for _ in 0..40 {
+            long.push_str("\\directory");
+        }
+        assert_eq!(normalize(&long).unwrap().len(), long.len());
+    }
+
+    #[test]
+    fn test_volume_matches() {
+        let device = "PciRoot(0x0)/Pci(0x14,0x0)/USB(0x2,0x0)/HD(1,MBR,0x1234,0x800,0x1000)";
+        assert!(volume_matches("usb-key", "USB-KEY", device));
+        assert!(volume_matches("USB(0x2,0x0)", "", device));
+        assert!(volume_matches(device, "", device));
+        assert!(!volume_matches("USB(0x3,0x0)", "USB-KEY", device));
+        assert!(!volume_matches("", "", device));
+        assert!(!volume_matches("USB", "USB-KEY", device));
+    }
+
+    #[test]
+    fn test_escape_component() {
+        assert_eq!(escape_component("plain.efi"), "plain.efi");
+        assert_eq!(
+            escape_component("http://host/a?b=1"),
+            "http%3A%2F%2Fhost%2Fa%3Fb=1"
+        );
+        assert_eq!(escape_component("100%"), "100%25");
+        assert_eq!(escape_component("name. "), "name%2E%20");
+        assert_eq!(escape_component(""), "_");
+        assert!(is_valid_component(&escape_component("a|b*c\"d")));
+    }
+
+    #[test]
+    fn test_escape_long_component() {
+        let mut a = String::new();
+        for _ in 0..300 {
+            a.push('x');
+        }
+        let mut b = a.clone();
+        b.push('y');
+
+        let escaped_a = escape_component(&a);
+        let escaped_b = escape_component(&b);
+        assert!(is_valid_component(&escaped_a));
+        assert_eq!(escaped_a.len(), MAX_COMPONENT_LEN);
+        assert_ne!(escaped_a, escaped_b);
+    }
+}
@@ -0,0 +1,573 @@
+use crate::network::addr::{self, Ipv4};
+use crate::network::verify;
+use crate::network::url::{self, Scheme};
+use crate::storage::config::MAX_URL_LEN;
+use crate::storage::{file, vars};
+use crate::util::sha256;
+use crate::util::{Error, Result};
+use heapless::{String, Vec};
+use uefi::cstr16;
+
+/// Verified boot policy, kept apart from the frequently edited config.txt
+pub const POLICY_PATH: &str = "\\EFI\\uefipxe\\policy.txt";
+
+/// Variable pinning the SHA256 of the trusted policy file
+///
+/// Boot-services-only like the config hash, so the OS cannot re-pin a
+/// policy it rewrote. Once pinned, a missing or modified policy.txt makes
+/// every boot fail until `policy trust` or `policy forget` is run here.
+const PIN_VAR: &uefi::CStr16 = cstr16!("PolicyHash");
+
+/// Maximum number of `allow=` URL patterns
+pub const MAX_PATTERNS: usize = 16;
+
+/// Maximum number of `allow_host=` rules
+pub const MAX_HOST_RULES: usize = 16;
+
+/// Maximum length of a host name in a rule
+pub const MAX_HOST_LEN: usize = 128;
+
+/// Maximum number of trusted keys
+pub const MAX_KEYS: usize = 4;
+
+/// Maximum length of a key name
+pub const MAX_KEY_NAME_LEN: usize = 32;
+
+/// Maximum size of a public key in bytes
+pub const MAX_KEY_LEN: usize = 64;
+
+/// Maximum number of `algorithm=` lines
+const MAX_ALGORITHMS: usize = 4;
+
+/// Image verification method the policy can require, by its config key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Algorithm(&'static str);
+
+impl Algorithm {
+    /// Parse an algorithm name as used in policy.txt
+    pub fn parse(name: &str) -> Result<Self> {
+        verify::find(&name.to_ascii_lowercase())
+            .map(|verifier| Algorithm(verifier.key))
+            .ok_or(Error::Unsupported)
+    }
+
+    /// Algorithm name as written in policy.txt
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+
+    /// Algorithm a configured signature uses, if its value is well-formed
+    fn of_signature(signature: &str) -> Option<Self> {
+        let (verifier, value) = verify::split(signature);
+        verifier.accepts(value).then_some(Algorithm(verifier.key))
+    }
+}
+
+/// Servers URLs may point to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostRule {
+    /// Exactly this host name or address
+    Name(String<MAX_HOST_LEN>),
+    /// Any subdomain of this name (`*.example.com`)
+    Subdomain(String<MAX_HOST_LEN>),
+    /// Any IPv4 address in this network (`10.1.0.0/16`)
+    Network { address: Ipv4, mask: Ipv4 },
+}
+
+impl HostRule {
+    /// Parse an `allow_host=` value
+    pub fn parse(value: &str) -> Result<Self> {
+        if let Some((address, prefix)) = value.split_once('/') {
+            return Ok(HostRule::Network {
+                address: addr::parse_ipv4(address)?,
+                mask: addr::parse_netmask(prefix)?,
+            });
+        }
+
+        let (name, subdomain) = match value.strip_prefix("*.") {
+            Some(suffix) => (suffix, true),
+            None => (value, false),
+        };
+        if name.is_empty() || name.contains(['*', '/', ':']) {
+            return Err(Error::Parse);
+        }
+
+        let mut stored = String::new();
+        stored.push_str(name).map_err(|_| Error::BufferTooSmall)?;
+        Ok(if subdomain { HostRule::Subdomain(stored) } else { HostRule::Name(stored) })
+    }
+
+    /// Whether a URL host satisfies the rule
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            HostRule::Name(name) => host.eq_ignore_ascii_case(name),
+            HostRule::Subdomain(suffix) => {
+                host.len() > suffix.len() + 1
+                    && host.as_bytes()[host.len() - suffix.len() - 1] == b'.'
+                    && host[host.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            }
+            HostRule::Network { address, mask } => {
+                addr::parse_ipv4(host).is_ok_and(|ip| addr::same_subnet(ip, *address, *mask))
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for HostRule {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HostRule::Name(name) => write!(f, "{}", name),
+            HostRule::Subdomain(suffix) => write!(f, "*.{}", suffix),
+            HostRule::Network { address, mask } => {
+                write!(f, "{}/{}", address, u32::from_be_bytes(mask.0).leading_ones())
+            }
+        }
+    }
+}
+
+/// Public key an operator trusts for signed content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedKey {
+    pub name: String<MAX_KEY_NAME_LEN>,
+    pub key: Vec<u8, MAX_KEY_LEN>,
+}
+
+impl TrustedKey {
+    /// Parse `<name> <hex>`
+    pub fn parse(value: &str) -> Result<Self> {
+        let (name, hex) = value.split_once(char::is_whitespace).ok_or(Error::Parse)?;
+        let mut key = TrustedKey {
+            name: String::new(),
+            key: Vec::new(),
+        };
+        key.name.push_str(name).map_err(|_| Error::BufferTooSmall)?;
+        decode_hex(hex.trim(), &mut key.key)?;
+        Ok(key)
+    }
+}
+
+/// Rules every boot has to satisfy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// Refuse entries without a signature
+    pub require_signature: bool,
+    /// Accepted signature algorithms (empty = any supported)
+    pub algorithms: Vec<Algorithm, MAX_ALGORITHMS>,
+    /// Refuse a config.txt modified outside uefipxe, whatever `config strict` says
+    pub strict: bool,
+    /// URL patterns entries must match, `*` matching anything (empty = any)
+    pub allow: Vec<String<MAX_URL_LEN>, MAX_PATTERNS>,
+    /// Servers image and config URLs may point to (empty = any)
+    pub allow_host: Vec<HostRule, MAX_HOST_RULES>,
+    /// Keys for verifying signed content
+    pub keys: Vec<TrustedKey, MAX_KEYS>,
+    /// Configs from outside this ESP must be signed by one of `keys`
+    pub signed_config: bool,
+}
+
+impl Policy {
+    /// Parse policy.txt
+    ///
+    /// Unlike config.txt, unknown keys are errors: a typo in a security
+    /// setting must not silently weaken it.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut policy = Policy::default();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or(Error::Parse)?;
+            let value = value.trim();
+            match key.trim() {
+                "require_signature" => {
+                    policy.require_signature = crate::storage::config::parse_bool(value)?;
+                }
+                "algorithm" => {
+                    let algorithm = Algorithm::parse(value)?;
+                    if !policy.algorithms.contains(&algorithm) {
+                        policy.algorithms.push(algorithm).map_err(|_| Error::OutOfMemory)?;
+                    }
+                }
+                "strict" => policy.strict = crate::storage::config::parse_bool(value)?,
+                "signed_config" => policy.signed_config = crate::storage::config::parse_bool(value)?,
+                "allow" => {
+                    let mut pattern = String::new();
+                    pattern.push_str(value).map_err(|_| Error::BufferTooSmall)?;
+                    policy.allow.push(pattern).map_err(|_| Error::OutOfMemory)?;
+                }
+                "allow_host" => {
+                    let rule = HostRule::parse(value)?;
+                    policy.allow_host.push(rule).map_err(|_| Error::OutOfMemory)?;
+                }
+                "key" => {
+                    let key = TrustedKey::parse(value)?;
+                    policy.keys.push(key).map_err(|_| Error::OutOfMemory)?;
+                }
+                _ => return Err(Error::Parse),
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Check where a URL points (`file://` URLs name a local volume, so
+    /// only the `allow=` patterns apply to them)
+    pub fn check_url(&self, url: &str) -> core::result::Result<(), Violation> {
+        if !self.allow.is_empty() && !self.allow.iter().any(|p| pattern_matches(p, url)) {
+            return Err(Violation::UrlNotAllowed);
+        }
+
+        if self.allow_host.is_empty() {
+            return Ok(());
+        }
+        match url::parse(url) {
+            Ok(parsed) if parsed.scheme == Scheme::File => Ok(()),
+            Ok(parsed) if self.allow_host.iter().any(|rule| rule.matches(parsed.host)) => Ok(()),
+            _ => Err(Violation::HostNotAllowed),
+        }
+    }
+
+    /// Check an entry against the policy before it is downloaded
+    pub fn check_entry(&self, url: &str, signature: Option<&str>) -> core::result::Result<(), Violation> {
+        self.check_url(url)?;
+
+        match signature.filter(|s| !s.is_empty()) {
+            None if self.require_signature => Err(Violation::Unsigned),
+            None => Ok(()),
+            Some(signature) => match Algorithm::of_signature(signature) {
+                Some(algorithm) if self.algorithms.is_empty() || self.algorithms.contains(&algorithm) => Ok(()),
+                _ => Err(Violation::Algorithm),
+            },
+        }
+    }
+}
+
+/// Why the policy refused an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// The policy file is pinned but missing or modified
+    Untrusted,
+    /// No `allow=` pattern matches the URL
+    UrlNotAllowed,
+    /// The URL's host is not covered by any `allow_host=` rule
+    HostNotAllowed,
+    /// The entry has no signature
+    Unsigned,
+    /// The signature uses an algorithm the policy does not accept
+    Algorithm,
+}
+
+impl Violation {
+    /// Explanation for the console
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Violation::Untrusted => "policy.txt is missing or does not match the trusted copy",
+            Violation::UrlNotAllowed => "URL does not match any allow= pattern",
+            Violation::HostNotAllowed => "server is not in the allow_host= list",
+            Violation::Unsigned => "entry has no signature but require_signature is set",
+            Violation::Algorithm => "signature algorithm is not accepted by the policy",
+        }
+    }
+}
+
+/// Whether `text` matches a pattern where `*` stands for any run of
+/// characters (ASCII case-insensitive)
+///
+/// End host patterns with `/` (e.g. `https://images.example.com/*`), or
+/// `images.example.com.evil.net` matches as well.
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and where it started matching
+    let mut star = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p].eq_ignore_ascii_case(&text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((after, start)) = star {
+            // Let the last `*` swallow one more character
+            p = after;
+            t = start + 1;
+            star = Some((after, start + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+fn decode_hex<const N: usize>(hex: &str, out: &mut Vec<u8, N>) -> Result<()> {
+    if hex.len() % 2 != 0 {
+        return Err(Error::Parse);
+    }
+    for pair in hex.as_bytes().chunks(2) {
+        let pair = core::str::from_utf8(pair).map_err(|_| Error::Parse)?;
+        let byte = u8::from_str_radix(pair, 16).map_err(|_| Error::Parse)?;
+        out.push(byte).map_err(|_| Error::BufferTooSmall)?;
+    }
+    Ok(())
+}
+
+/// Policy in force for this session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    /// No policy file: only the per-entry signatures apply
+    None,
+    /// Policy loaded; `pinned` tells whether its hash is recorded
+    Active { policy: Policy, pinned: bool },
+    /// Pinned policy missing, modified or unparsable: refuse every boot
+    Broken,
+}
+
+/// Policy loaded at startup (None until `load` runs)
+static mut STATE: Option<State> = None;
+
+/// Current policy state
+pub fn state() -> &'static State {
+    unsafe { STATE.as_ref() }.unwrap_or(&State::None)
+}
+
+fn pinned_hash() -> Option<[u8; 32]> {
+    let mut hash = [0u8; 32];
+    match vars::read(PIN_VAR, &mut hash) {
+        Ok(32) => Some(hash),
+        _ => None,
+    }
+}
+
+/// Load policy.txt from the ESP at startup (before the config, which it
+/// may make strict)
+pub fn load() -> &'static State {
+    let state = match file::read_esp_file(POLICY_PATH) {
+        Ok(data) => evaluate(&data, pinned_hash()),
+        Err(_) if pinned_hash().is_some() => {
+            uefi::println!("!!! policy.txt is pinned but could not be read, refusing to boot !!!");
+            State::Broken
+        }
+        Err(_) => State::None,
+    };
+
+    unsafe {
+        STATE = Some(state);
+    }
+    self::state()
+}
+
+fn evaluate(data: &[u8], pin: Option<[u8; 32]>) -> State {
+    if pin.is_some_and(|pin| pin != sha256::digest(data)) {
+        uefi::println!("!!! policy.txt does not match the pinned hash, refusing to boot !!!");
+        uefi::println!("!!! Run 'policy trust' after reviewing it, or restore the old copy !!!");
+        return State::Broken;
+    }
+
+    match core::str::from_utf8(data).map_err(|_| Error::Parse).and_then(Policy::parse) {
+        Ok(policy) => {
+            if pin.is_none() {
+                uefi::println!("Warning: policy.txt is not pinned; run 'policy trust' to protect it");
+            }
+            State::Active { policy, pinned: pin.is_some() }
+        }
+        Err(e) => {
+            uefi::println!("!!! policy.txt is invalid ({}), refusing to boot !!!", e);
+            State::Broken
+        }
+    }
+}
+
+/// Pin the current policy.txt and start enforcing it
+pub fn trust() -> Result<&'static State> {
+    let data = file::read_esp_file(POLICY_PATH)?;
+    let hash = sha256::digest(&data);
+
+    // Only pin what actually parses
+    core::str::from_utf8(&data).map_err(|_| Error::Parse).and_then(Policy::parse)?;
+    vars::write(PIN_VAR, vars::BOOT_ONLY, &hash)?;
+    Ok(load())
+}
+
+/// Remove the pin (the file, if still present, applies unprotected)
+pub fn forget() -> Result<&'static State> {
+    vars::delete(PIN_VAR)?;
+    Ok(load())
+}
+
+/// Whether the policy enforces strict config integrity
+pub fn is_strict() -> bool {
+    match state() {
+        State::Active { policy, .. } => policy.strict,
+        // A broken policy cannot vouch for anything
+        State::Broken => true,
+        State::None => false,
+    }
+}
+
+/// Keys a config from outside this ESP has to be signed with, or None
+/// when such configs are accepted unsigned
+///
+/// A broken policy trusts no key at all.
+pub fn config_keys() -> Option<&'static [TrustedKey]> {
+    match state() {
+        State::None => None,
+        State::Active { policy, .. } if policy.signed_config => Some(policy.keys.as_slice()),
+        State::Active { .. } => None,
+        State::Broken => Some(&[]),
+    }
+}
+
+/// Ed25519 public key compiled in from `UEFIPXE_ED25519_KEY`, as
+/// `[name] <64 hex digits>`
+///
+/// Trusted for images even without a policy.txt, and cannot be swapped
+/// by whoever can write the ESP.
+pub const EMBEDDED_KEY: Option<&str> = option_env!("UEFIPXE_ED25519_KEY");
+
+/// Name of the embedded key when the build gives none
+const EMBEDDED_KEY_NAME: &str = "builtin";
+
+/// The embedded key, if one was built in and is well-formed
+pub fn embedded_key() -> Option<TrustedKey> {
+    parse_embedded(EMBEDDED_KEY?)
+}
+
+fn parse_embedded(text: &str) -> Option<TrustedKey> {
+    let text = text.trim();
+    let key = if text.contains(char::is_whitespace) {
+        TrustedKey::parse(text)
+    } else {
+        TrustedKey::parse(&alloc::format!("{} {}", EMBEDDED_KEY_NAME, text))
+    };
+    key.ok().filter(|key| !key.key.is_empty())
+}
+
+/// Keys images signed with `ed25519=` or `sig=` are checked against: the
+/// embedded key and those of an active policy
+pub fn trusted_keys() -> alloc::vec::Vec<TrustedKey> {
+    let mut keys: alloc::vec::Vec<TrustedKey> = embedded_key().into_iter().collect();
+    if let State::Active { policy, .. } = state() {
+        keys.extend(policy.keys.iter().cloned());
+    }
+    keys
+}
+
+/// Check a URL (image or config) against the policy in force
+pub fn check_url(url: &str) -> core::result::Result<(), Violation> {
+    match state() {
+        State::None => Ok(()),
+        State::Active { policy, .. } => policy.check_url(url),
+        State::Broken => Err(Violation::Untrusted),
+    }
+}
+
+/// Check an entry against the policy in force
+pub fn check_entry(url: &str, signature: Option<&str>) -> core::result::Result<(), Violation> {
+    match state() {
+        State::None => Ok(()),
+        State::Active { policy, .. } => policy.check_entry(url, signature),
+        State::Broken => Err(Violation::Untrusted),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = Policy::parse(
+            "# trust\nrequire_signature=yes\nalgorithm=SHA256\nstrict=on\n\
+             allow=https://images.example.com/*\nkey=ops 00ff10\nsigned_config=true\n",
+        )
+        .unwrap();
+        assert!(policy.require_signature);
+        assert!(policy.strict);
+        assert!(policy.signed_config);
+        assert_eq!(policy.algorithms.as_slice(), &[Algorithm("sha256")]);
+        assert_eq!(policy.allow.len(), 1);
+        assert_eq!(policy.keys[0].name.as_str(), "ops");
+        assert_eq!(policy.keys[0].key.as_slice(), &[0x00, 0xff, 0x10]);
+
+        assert!(Policy::parse("require_signatur=yes\n").is_err());
+        assert!(Policy::parse("algorithm=md5\n").is_err());
+        assert!(Policy::parse("key=ops 0g\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_embedded_key() {
+        let hex = "0eff9efffd57f4c4fb2600693db24a97b01f1b9c354dcd733d426c327aabeada";
+        let named = parse_embedded(&alloc::format!("release {}\n", hex)).unwrap();
+        assert_eq!(named.name.as_str(), "release");
+        assert_eq!(named.key.len(), 32);
+
+        let unnamed = parse_embedded(hex).unwrap();
+        assert_eq!(unnamed.name.as_str(), EMBEDDED_KEY_NAME);
+        assert_eq!(unnamed.key, named.key);
+
+        assert_eq!(parse_embedded("release 0g"), None);
+        assert_eq!(parse_embedded(""), None);
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(pattern_matches("https://images.example.com/*", "https://IMAGES.example.com/a/b.efi"));
+        assert!(pattern_matches("http://*/boot/*.efi", "http://10.0.0.1/boot/x.efi"));
+        assert!(pattern_matches("*", "anything"));
+        assert!(!pattern_matches("https://images.example.com/*", "https://images.example.com.evil.net/x"));
+        assert!(!pattern_matches("http://*/boot/*.efi", "http://10.0.0.1/boot/x.iso"));
+    }
+
+    #[test]
+    fn test_host_rules() {
+        let name = HostRule::parse("Images.example.com").unwrap();
+        assert!(name.matches("images.EXAMPLE.com"));
+        assert!(!name.matches("evil.images.example.com"));
+
+        let sub = HostRule::parse("*.example.com").unwrap();
+        assert!(sub.matches("a.b.example.com"));
+        assert!(!sub.matches("example.com"));
+        assert!(!sub.matches("badexample.com"));
+
+        let net = HostRule::parse("10.1.0.0/16").unwrap();
+        assert!(net.matches("10.1.200.3"));
+        assert!(!net.matches("10.2.0.1"));
+        assert!(!net.matches("10.1.example.com"));
+        assert_eq!(alloc::format!("{}", net), "10.1.0.0/16");
+
+        assert!(HostRule::parse("*").is_err());
+        assert!(HostRule::parse("10.0.0.0/33").is_err());
+    }
+
+    #[test]
+    fn test_check_url_hosts() {
+        let policy = Policy::parse("allow_host=*.example.com\nallow_host=192.168.0.0/24\n").unwrap();
+        assert_eq!(policy.check_url("https://mirror.example.com/a.efi"), Ok(()));
+        assert_eq!(policy.check_url("http://192.168.0.9:8080/a.efi"), Ok(()));
+        assert_eq!(policy.check_url("file:///EFI/rescue.efi"), Ok(()));
+        assert_eq!(policy.check_url("http://attacker.net/a.efi"), Err(Violation::HostNotAllowed));
+        assert_eq!(policy.check_url("http://192.168.1.9/a.efi"), Err(Violation::HostNotAllowed));
+        assert_eq!(policy.check_url("not a url"), Err(Violation::HostNotAllowed));
+    }
+
+    #[test]
+    fn test_check_entry() {
+        let sha = "a3b2c1d4e5f6abcd1234567890abcdef1234567890abcdef1234567890abcdef";
+        let policy = Policy::parse("require_signature=true\nallow=https://good/*\n").unwrap();
+        assert_eq!(policy.check_entry("https://good/a.efi", Some(sha)), Ok(()));
+        assert_eq!(policy.check_entry("https://good/a.efi", Some("")), Err(Violation::Unsigned));
+        assert_eq!(policy.check_entry("https://bad/a.efi", Some(sha)), Err(Violation::UrlNotAllowed));
+        assert_eq!(policy.check_entry("https://good/a.efi", Some("abcd")), Err(Violation::Algorithm));
+
+        let only_blake3 = Policy::parse("algorithm=blake3\n").unwrap();
+        let blake3 = "blake3:ea8f163db38682925e4491c5e58d4bb3506ef8c14eb78a86e908c5624a67200f";
+        assert_eq!(only_blake3.check_entry("https://good/a.efi", Some(blake3)), Ok(()));
+        assert_eq!(only_blake3.check_entry("https://good/a.efi", Some(sha)), Err(Violation::Algorithm));
+        assert_eq!(Policy::default().check_entry("http://any/x", None), Ok(()));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::network::client::status_number;
+use crate::network::http;
+use crate::storage::{self, policy, signed, Config};
+use crate::util::global::Global;
+use crate::util::{Error, Result};
+use alloc::string::String;
+use uefi::println;
+
+/// The copy of `config_url` whose entries are in use
+struct Seen {
+    /// ETag the server gave it, if any
+    etag: Option<String>,
+    /// SHA256 of its text, for servers without ETags
+    digest: [u8; 32],
+}
+
+/// Unset until the first copy was taken over
+static SEEN: Global<Seen> = Global::new();
+
+/// Seconds between checks while the menu waits, None when there is
+/// nothing to watch (or in safe mode)
+pub fn interval() -> Option<u32> {
+    if crate::boot::safemode::active() {
+        return None;
+    }
+    storage::with_config(|config| config.poll_config_interval.filter(|_| config.config_url.is_some())).flatten()
+}
+
+/// Check `config_url` for a new copy and take over its entries; returns
+/// whether they changed
+///
+/// A HEAD request comes first, and when the server's ETag is still the
+/// one of the copy in use nothing is downloaded. Only the entries and the
+/// default are taken over; every other setting stays this machine's own,
+/// and a copy without entries is refused.
+/// With `signed_config=true` in the policy the copy must be signed by a
+/// trusted key, as for `volume=`. Entries the boot policy does not allow
+/// are dropped, and so are hooks and script entries of an unsigned copy,
+/// which would run commands on the word of whoever answers on the network.
+pub fn poll() -> Result<bool> {
+    let Some(url) = storage::with_config(|config| config.config_url.clone()).flatten() else {
+        return Ok(false);
+    };
+
+    // Servers that do not answer HEAD (or redirect it) are downloaded from
+    let head = http::head(&url)?;
+    let etag = head.etag.filter(|_| status_number(head.status) == 200);
+    if etag.is_some() && SEEN.with(|seen| seen.etag == etag).unwrap_or(false) {
+        return Ok(false);
+    }
+
+    let data = http::download(&url)?;
+    let digest = crate::util::sha256::digest(&data);
+    if SEEN.with(|seen| seen.digest == digest).unwrap_or(false) {
+        SEEN.set(Seen { etag, digest });
+        return Ok(false);
+    }
+
+    let (mut remote, signed) = parse(&url, &data)?;
+    if !signed {
+        let scripts = remote.strip_commands();
+        if scripts > 0 {
+            crate::util::logger::log_entry(
+                log::Level::Warn,
+                &alloc::format!("Dropped {} script entries of the unsigned copy at {}", scripts, url),
+            );
+        }
+    }
+    allowed_only(&mut remote, &url);
+    // Rather a stale menu than none, e.g. from a file truncated on upload
+    if remote.urls.is_empty() {
+        return Err(Error::NotFound);
+    }
+    storage::with_config_mut(|config| config.replace_entries(remote)).ok_or(Error::Unknown)?;
+    SEEN.set(Seen { etag, digest });
+    crate::util::logger::log_entry(log::Level::Info, &alloc::format!("Entries updated from {}", url));
+    Ok(true)
+}
+
+/// Remove the entries of `remote` (from `url`) whose URLs the boot policy
+/// does not allow
+fn allowed_only(remote: &mut Config, url: &str) {
+    let mut index = 0;
+    while index < remote.urls.len() {
+        let absolute = storage::entry_url(&remote.urls[index]).into_owned();
+        match policy::check_url(&absolute) {
+            Ok(()) => index += 1,
+            Err(violation) => {
+                crate::util::logger::log_entry(
+                    log::Level::Warn,
+                    &alloc::format!("Dropped {} from {}: {}", absolute, url, violation.as_str()),
+                );
+                let _ = remote.remove_url(index);
+            }
+        }
+    }
+}
+
+/// Parse a copy of the configuration from `url`, checking its signature
+/// when the policy asks for one; true when it was signed
+fn parse(url: &str, data: &[u8]) -> Result<(Config, bool)> {
+    let content = core::str::from_utf8(data).map_err(|_| Error::Parse)?;
+    let Some(keys) = policy::config_keys() else {
+        return Config::parse(content).map(|config| (config, false));
+    };
+
+    let sidecar = http::download(&alloc::format!("{}{}", url, signed::SIDECAR_SUFFIX)).ok();
+    let sidecar = sidecar.as_ref().and_then(|data| core::str::from_utf8(data).ok());
+    match signed::verify(content, sidecar, keys) {
+        Ok((body, _)) => Config::parse(body).map(|config| (config, true)),
+        Err(e) => {
+            println!("Configuration at {} is not signed by a trusted key", url);
+            Err(e)
+        }
+    }
+}
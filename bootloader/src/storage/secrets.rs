@@ -0,0 +1,195 @@
+use crate::storage::{file, path};
+use crate::util::global::Global;
+use crate::util::tpm;
+use crate::util::wipe::{self, Wiped};
+use crate::util::{Error, Result};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Directory holding one sealed credential per server
+pub const SECRETS_DIR: &str = "\\EFI\\uefipxe\\secrets";
+
+/// PCRs credentials are sealed to unless told otherwise: firmware code (0),
+/// option ROM code (2), boot manager code, i.e. uefipxe itself (4), and the
+/// Secure Boot policy (7)
+pub const DEFAULT_PCRS: [u8; 4] = [0, 2, 4, 7];
+
+/// File layout: magic, format version, PCR mask (little-endian), sealed object
+const MAGIC: &[u8; 4] = b"UPXS";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = 9;
+
+/// What we know about a server's credential this session
+enum Cached {
+    /// No credential is stored for the server
+    Missing,
+    /// Unsealed Authorization header value
+    Value(String),
+    /// Stored but could not be unsealed
+    Failed,
+}
+
+/// Credentials looked up so far, by lower-case host
+///
+/// Each file is unsealed at most once per session; the values are wiped
+/// before control passes to an image or back to the firmware.
+static CACHE: Global<Vec<(String, Cached)>> = Global::new();
+
+/// Run `f` with the cache, starting an empty one on first use
+fn with_cache<R>(f: impl FnOnce(&mut Vec<(String, Cached)>) -> R) -> R {
+    if CACHE.with(|_| ()).is_none() {
+        CACHE.set(Vec::new());
+    }
+    CACHE.with_mut(f).expect("cache was just set")
+}
+
+/// File holding the sealed credential for a server
+fn secret_path(host: &str) -> String {
+    format!("{}\\{}.tpm", SECRETS_DIR, path::escape_component(host))
+}
+
+/// Frame a sealed object for storage
+pub fn encode(pcrs: u32, sealed: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + sealed.len());
+    out.extend_from_slice(MAGIC);
+    out.push(FORMAT_VERSION);
+    out.extend_from_slice(&pcrs.to_le_bytes());
+    out.extend_from_slice(sealed);
+    out
+}
+
+/// Split a stored file into PCR mask and sealed object
+pub fn decode(data: &[u8]) -> Result<(u32, &[u8])> {
+    if data.len() <= HEADER_LEN || &data[..4] != MAGIC || data[4] != FORMAT_VERSION {
+        return Err(Error::Parse);
+    }
+    let pcrs = u32::from_le_bytes([data[5], data[6], data[7], data[8]]);
+    Ok((pcrs, &data[HEADER_LEN..]))
+}
+
+/// Whether a value can go into a header line as it is
+pub fn is_valid_value(value: &str) -> bool {
+    !value.is_empty() && value.len() <= tpm::MAX_SECRET_LEN && value.bytes().all(|b| (0x20..0x7f).contains(&b))
+}
+
+/// Seal an Authorization header value for a server to the current PCRs
+pub fn seal(host: &str, value: &str, pcrs: u32) -> Result<()> {
+    if !is_valid_value(value) {
+        return Err(Error::InvalidArgument);
+    }
+
+    let host = host.to_ascii_lowercase();
+    let sealed = tpm::seal(value.as_bytes(), pcrs)?;
+    file::atomic_write(&secret_path(&host), &encode(pcrs, &sealed))?;
+
+    forget_cached(&host);
+    with_cache(|cache| cache.push((host, Cached::Value(String::from(value)))));
+    Ok(())
+}
+
+/// Authorization header value for a server, unsealing it on first use
+///
+/// None when no credential is stored for the server, or when it no longer
+/// unseals (a firmware, bootloader or Secure Boot change moved the PCRs).
+/// The value is a copy that wipes itself when dropped, so `clear` cannot
+/// pull it out from under the caller.
+pub fn authorization(host: &str) -> Option<Wiped> {
+    let host = host.to_ascii_lowercase();
+    if !with_cache(|cache| cache.iter().any(|(h, _)| *h == host)) {
+        let entry = load(&host);
+        with_cache(|cache| cache.push((host.clone(), entry)));
+    }
+
+    with_cache(|cache| match cache.iter().find(|(h, _)| *h == host) {
+        Some((_, Cached::Value(value))) => Some(Wiped::new(Vec::from(value.as_bytes()))),
+        _ => None,
+    })
+}
+
+fn load(host: &str) -> Cached {
+    let data = match file::read_file(&secret_path(host)) {
+        Ok(data) => data,
+        Err(_) => return Cached::Missing,
+    };
+
+    let unsealed = decode(&data).and_then(|(pcrs, sealed)| tpm::unseal(sealed, pcrs)).and_then(|secret| {
+        String::from_utf8(secret).map_err(|e| {
+            wipe::wipe_vec(&mut e.into_bytes());
+            Error::Parse
+        })
+    });
+    match unsealed {
+        Ok(value) if is_valid_value(&value) => Cached::Value(value),
+        Ok(value) => {
+            wipe::wipe_vec(&mut value.into_bytes());
+            Cached::Failed
+        }
+        Err(e) => {
+            uefi::println!("  Sealed credential for {} did not unseal: {}", host, e);
+            uefi::println!("  Firmware, bootloader or Secure Boot state changed? Seal it again with 'auth seal'");
+            crate::util::logger::log_entry(
+                log::Level::Warn,
+                &format!("Credential for {} did not unseal: {}", host, e),
+            );
+            Cached::Failed
+        }
+    }
+}
+
+/// Delete the stored credential for a server
+///
+/// Returns whether one was stored.
+pub fn forget(host: &str) -> Result<bool> {
+    let host = host.to_ascii_lowercase();
+    forget_cached(&host);
+    Ok(file::delete(&secret_path(&host))? > 0)
+}
+
+fn forget_cached(host: &str) {
+    with_cache(|cache| {
+        if let Some(i) = cache.iter().position(|(h, _)| h == host) {
+            if let (_, Cached::Value(value)) = &mut cache[i] {
+                wipe::wipe(unsafe { value.as_bytes_mut() });
+            }
+            cache.swap_remove(i);
+        }
+    });
+}
+
+/// Wipe every unsealed value from memory
+pub fn clear() {
+    CACHE.with_mut(|cache| {
+        for (_, entry) in cache.iter_mut() {
+            if let Cached::Value(value) = entry {
+                wipe::wipe(unsafe { value.as_bytes_mut() });
+            }
+        }
+        cache.clear();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let framed = encode(0x95, b"\x00\x02ab");
+        let (pcrs, sealed) = decode(&framed).unwrap();
+        assert_eq!(pcrs, 0x95);
+        assert_eq!(sealed, b"\x00\x02ab");
+
+        assert!(decode(&framed[..HEADER_LEN]).is_err());
+        let mut wrong = framed.clone();
+        wrong[4] = 2;
+        assert!(decode(&wrong).is_err());
+    }
+
+    #[test]
+    fn test_valid_value() {
+        assert!(is_valid_value("Bearer abc.def-123"));
+        assert!(!is_valid_value(""));
+        assert!(!is_valid_value("Bearer abc\r\nX-Injected: 1"));
+    }
+}
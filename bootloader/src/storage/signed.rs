@@ -0,0 +1,158 @@
+use crate::storage::policy::TrustedKey;
+use crate::util::ed25519;
+use crate::util::{Error, Result};
+
+/// Last line of a file carrying its own signature:
+/// `# ed25519-signature: [key name] <128 hex digits>`
+///
+/// The signature covers everything before that line. Being a comment, the
+/// line is ignored by parsers that do not check it.
+pub const INLINE_PREFIX: &str = "# ed25519-signature:";
+
+/// Suffix of a detached signature next to the signed file (same
+/// `[key name] <hex>` text, covering the whole file)
+pub const SIDECAR_SUFFIX: &str = ".sig";
+
+/// A parsed signature and the key it names, if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature<'a> {
+    pub key_name: Option<&'a str>,
+    pub bytes: [u8; ed25519::SIGNATURE_LEN],
+}
+
+/// Parse `[key name] <hex>`
+pub fn parse_signature(text: &str) -> Result<Signature<'_>> {
+    let mut words = text.split_whitespace();
+    let (key_name, hex) = match (words.next(), words.next(), words.next()) {
+        (Some(hex), None, None) => (None, hex),
+        (Some(name), Some(hex), None) => (Some(name), hex),
+        _ => return Err(Error::Parse),
+    };
+
+    if hex.len() != ed25519::SIGNATURE_LEN * 2 {
+        return Err(Error::Parse);
+    }
+    let mut bytes = [0u8; ed25519::SIGNATURE_LEN];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let pair = hex.get(2 * i..2 * i + 2).ok_or(Error::Parse)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| Error::Parse)?;
+    }
+    Ok(Signature { key_name, bytes })
+}
+
+/// Normalize a downloaded detached signature to `[key name] <hex>`
+///
+/// Accepts the text form used by sidecars or the 64 raw bytes written by
+/// `openssl pkeyutl -sign -rawin`.
+pub fn parse_detached(data: &[u8]) -> Result<alloc::string::String> {
+    if let Some(text) = core::str::from_utf8(data).ok().map(str::trim) {
+        if parse_signature(text).is_ok() {
+            return Ok(alloc::string::String::from(text));
+        }
+    }
+    if data.len() != ed25519::SIGNATURE_LEN {
+        return Err(Error::Parse);
+    }
+    Ok(data.iter().map(|byte| alloc::format!("{:02x}", byte)).collect())
+}
+
+/// Split a file into the signed part and its inline signature text
+pub fn split_inline(content: &str) -> Option<(&str, &str)> {
+    let trimmed = content.trim_end_matches(['\r', '\n']);
+    let start = trimmed.rfind('\n').map_or(0, |i| i + 1);
+    let signature = trimmed[start..].strip_prefix(INLINE_PREFIX)?;
+    Some((&content[..start], signature))
+}
+
+/// Check a signature against the trusted keys
+///
+/// A named signature is only checked against the key of that name; an
+/// unnamed one against every Ed25519 key. Returns the name of the key.
+pub fn check<'k>(data: &[u8], signature: &Signature, keys: &'k [TrustedKey]) -> Result<&'k str> {
+    keys.iter()
+        .filter(|key| signature.key_name.is_none_or(|name| key.name.as_str() == name))
+        .find(|key| {
+            <&[u8; ed25519::PUBLIC_KEY_LEN]>::try_from(key.key.as_slice())
+                .is_ok_and(|public| ed25519::verify(public, data, &signature.bytes))
+        })
+        .map(|key| key.name.as_str())
+        .ok_or(Error::SecurityViolation)
+}
+
+/// Verify a file signed inline or by a sidecar, returning the signed part
+/// (without the signature line) and the name of the key that signed it
+///
+/// Unsigned files and bad signatures are both security violations.
+pub fn verify<'c, 'k>(
+    content: &'c str,
+    sidecar: Option<&str>,
+    keys: &'k [TrustedKey],
+) -> Result<(&'c str, &'k str)> {
+    if let Some((body, text)) = split_inline(content) {
+        let signature = parse_signature(text).map_err(|_| Error::SecurityViolation)?;
+        return check(body.as_bytes(), &signature, keys).map(|key| (body, key));
+    }
+
+    let text = sidecar.ok_or(Error::SecurityViolation)?;
+    let signature = parse_signature(text).map_err(|_| Error::SecurityViolation)?;
+    check(content.as_bytes(), &signature, keys).map(|key| (content, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::{String, Vec};
+
+    // openssl pkeyutl -sign -rawin over "hello menu\n"
+    const KEY: &str = "0eff9efffd57f4c4fb2600693db24a97b01f1b9c354dcd733d426c327aabeada";
+    const SIG: &str = "f2ac8106c606fcbb0ea7fe9727d46939c1648e52fe06e26573516fab6c1a6325\
+                       3d2a984b93cf0b6dc2cf8797a26976f5b179212b7bc5c1a30bbf8411c12bfa03";
+
+    fn keys() -> [TrustedKey; 1] {
+        let mut key = Vec::new();
+        for i in 0..32 {
+            key.push(u8::from_str_radix(&KEY[2 * i..2 * i + 2], 16).unwrap()).unwrap();
+        }
+        let mut name = String::new();
+        name.push_str("ops").unwrap();
+        [TrustedKey { name, key }]
+    }
+
+    #[test]
+    fn test_inline_signature() {
+        let signed = alloc::format!("hello menu\n{} ops {}\n", INLINE_PREFIX, SIG);
+        assert_eq!(verify(&signed, None, &keys()).unwrap(), ("hello menu\n", "ops"));
+
+        let unnamed = alloc::format!("hello menu\n{} {}", INLINE_PREFIX, SIG);
+        assert!(verify(&unnamed, None, &keys()).is_ok());
+
+        let wrong_key = alloc::format!("hello menu\n{} dev {}\n", INLINE_PREFIX, SIG);
+        assert_eq!(verify(&wrong_key, None, &keys()), Err(Error::SecurityViolation));
+
+        let tampered = alloc::format!("hello menu!\n{} ops {}\n", INLINE_PREFIX, SIG);
+        assert_eq!(verify(&tampered, None, &keys()), Err(Error::SecurityViolation));
+    }
+
+    #[test]
+    fn test_sidecar_signature() {
+        let sidecar = alloc::format!("ops {}\n", SIG);
+        assert_eq!(verify("hello menu\n", Some(&sidecar), &keys()).unwrap().1, "ops");
+        assert_eq!(verify("hello menu\n", None, &keys()), Err(Error::SecurityViolation));
+        assert_eq!(verify("hello menu\n", Some("ops 00"), &keys()), Err(Error::SecurityViolation));
+    }
+
+    #[test]
+    fn test_parse_detached() {
+        let text = alloc::format!("ops {}\r\n", SIG);
+        assert_eq!(parse_detached(text.as_bytes()).unwrap(), text.trim());
+
+        let raw: alloc::vec::Vec<u8> =
+            (0..64).map(|i| u8::from_str_radix(&SIG[2 * i..2 * i + 2], 16).unwrap()).collect();
+        let hex = parse_detached(&raw).unwrap();
+        assert_eq!(hex, SIG);
+        assert!(check(b"hello menu\n", &parse_signature(&hex).unwrap(), &keys()).is_ok());
+
+        assert_eq!(parse_detached(b"ops 00"), Err(Error::Parse));
+        assert_eq!(parse_detached(b""), Err(Error::Parse));
+    }
+}
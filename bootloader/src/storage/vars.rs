@@ -0,0 +1,46 @@
+use crate::util::{Error, Result};
+use uefi::runtime::{self, VariableAttributes, VariableVendor};
+use uefi::{CStr16, Guid, Status};
+
+/// Vendor GUID for all uefipxe UEFI variables
+/// {DFC613B2-B549-42F4-AF57-86F8E24A1795}
+pub const VENDOR: VariableVendor = VariableVendor(Guid::from_bytes([
+    0xb2, 0x13, 0xc6, 0xdf, 0x49, 0xb5, 0xf4, 0x42,
+    0xaf, 0x57, 0x86, 0xf8, 0xe2, 0x4a, 0x17, 0x95,
+]));
+
+/// Persistent variable that only boot-time code (i.e. us) can read or write
+///
+/// Without RUNTIME_ACCESS the OS cannot see or modify the variable, which
+/// makes it a reasonable anchor for integrity data about files on the ESP.
+pub const BOOT_ONLY: VariableAttributes =
+    VariableAttributes::NON_VOLATILE.union(VariableAttributes::BOOTSERVICE_ACCESS);
+
+/// Persistent variable that the running OS may also read and write
+pub const OS_VISIBLE: VariableAttributes = VariableAttributes::NON_VOLATILE
+    .union(VariableAttributes::BOOTSERVICE_ACCESS)
+    .union(VariableAttributes::RUNTIME_ACCESS);
+
+/// Read one of our variables into `buf`, returning its length
+pub fn read(name: &CStr16, buf: &mut [u8]) -> Result<usize> {
+    match runtime::get_variable(name, &VENDOR, buf) {
+        Ok((data, _attributes)) => Ok(data.len()),
+        Err(e) if e.status() == Status::NOT_FOUND => Err(Error::NotFound),
+        Err(e) if e.status() == Status::BUFFER_TOO_SMALL => Err(Error::BufferTooSmall),
+        Err(e) => Err(Error::Uefi(e.status())),
+    }
+}
+
+/// Create or replace one of our variables
+pub fn write(name: &CStr16, attributes: VariableAttributes, data: &[u8]) -> Result<()> {
+    runtime::set_variable(name, &VENDOR, attributes, data).map_err(|e| Error::Uefi(e.status()))
+}
+
+/// Delete one of our variables (missing variables are not an error)
+pub fn delete(name: &CStr16) -> Result<()> {
+    match runtime::delete_variable(name, &VENDOR) {
+        Ok(()) => Ok(()),
+        Err(e) if e.status() == Status::NOT_FOUND => Ok(()),
+        Err(e) => Err(Error::Uefi(e.status())),
+    }
+}
@@ -0,0 +1,239 @@
+use crate::util::sha256::{self, Sha256};
+use crate::util::{Error, Result};
+use alloc::vec::Vec;
+
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+/// Index of the certificate table among the data directories
+const SECURITY_DIRECTORY: u32 = 4;
+
+const SECTION_HEADER_LEN: usize = 40;
+
+/// WIN_CERTIFICATE type of an Authenticode (PKCS#7 SignedData) signature
+const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 0x0002;
+
+/// WIN_CERTIFICATE header: length, revision, type
+const WIN_CERT_HEADER_LEN: usize = 8;
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..offset + 2).ok_or(Error::Parse)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or(Error::Parse)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn range(data: &[u8], start: usize, end: usize) -> Result<&[u8]> {
+    data.get(start..end).ok_or(Error::Parse)
+}
+
+/// Where the parts Authenticode treats specially sit in a PE/COFF image
+struct Layout {
+    /// Optional header and its length
+    optional: usize,
+    optional_len: usize,
+    sections: usize,
+    checksum: usize,
+    header_len: usize,
+    /// Certificate table entry among the data directories, when there is one
+    security_entry: Option<usize>,
+}
+
+fn layout(image: &[u8]) -> Result<Layout> {
+    if range(image, 0, 2)? != b"MZ" {
+        return Err(Error::Parse);
+    }
+    let pe = u32_at(image, 0x3c)? as usize;
+    if range(image, pe, pe + 4)? != b"PE\0\0" {
+        return Err(Error::Parse);
+    }
+
+    let coff = pe + 4;
+    let sections = u16_at(image, coff + 2)? as usize;
+    let optional = coff + 20;
+    let optional_len = u16_at(image, coff + 16)? as usize;
+
+    // Data directories sit further in on PE32+ for its wider fields
+    let (rva_count_offset, directories) = match u16_at(image, optional)? {
+        PE32_MAGIC => (optional + 92, optional + 96),
+        PE32_PLUS_MAGIC => (optional + 108, optional + 112),
+        _ => return Err(Error::Parse),
+    };
+    let checksum = optional + 64;
+    let header_len = u32_at(image, optional + 60)? as usize;
+    if header_len > image.len() || header_len < checksum + 4 {
+        return Err(Error::Parse);
+    }
+
+    let security_entry = match u32_at(image, rva_count_offset)? > SECURITY_DIRECTORY {
+        true => Some(directories + 8 * SECURITY_DIRECTORY as usize),
+        false => None,
+    };
+    if security_entry.is_some_and(|entry| entry + 8 > header_len) {
+        return Err(Error::Parse);
+    }
+
+    Ok(Layout { optional, optional_len, sections, checksum, header_len, security_entry })
+}
+
+/// SHA-256 Authenticode digest of a PE/COFF image
+///
+/// This is the hash a signature on the image signs, and what Secure Boot
+/// lists in db/dbx: the file with the checksum field, the certificate table
+/// entry and the certificates themselves left out, so it does not change
+/// when the image is (re)signed.
+pub fn digest(image: &[u8]) -> Result<[u8; sha256::DIGEST_LEN]> {
+    let Layout { optional, optional_len, sections, checksum, header_len, security_entry } = layout(image)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(range(image, 0, checksum)?);
+
+    let mut cert_len = 0;
+    match security_entry {
+        Some(entry) => {
+            cert_len = u32_at(image, entry + 4)? as usize;
+            hasher.update(range(image, checksum + 4, entry)?);
+            hasher.update(range(image, entry + 8, header_len)?);
+        }
+        None => hasher.update(range(image, checksum + 4, header_len)?),
+    }
+
+    // Section data in file order
+    let table = optional + optional_len;
+    let mut raw: Vec<(usize, usize)> = Vec::with_capacity(sections);
+    for i in 0..sections {
+        let header = table + i * SECTION_HEADER_LEN;
+        let size = u32_at(image, header + 16)? as usize;
+        let offset = u32_at(image, header + 20)? as usize;
+        if size > 0 {
+            raw.push((offset, size));
+        }
+    }
+    raw.sort_unstable();
+
+    let mut hashed = header_len;
+    for (offset, size) in raw {
+        hasher.update(range(image, offset, offset.checked_add(size).ok_or(Error::Parse)?)?);
+        hashed += size;
+    }
+
+    // Anything after the sections except the certificates
+    let end = image.len().checked_sub(cert_len).ok_or(Error::Parse)?;
+    if end > hashed {
+        hasher.update(&image[hashed..end]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+/// Whether a PE/COFF image carries an Authenticode signature
+///
+/// This only says a signature is there, not that any key trusts it: that
+/// is for the firmware (db) or shim (MOK) to decide when it is loaded.
+pub fn is_signed(image: &[u8]) -> Result<bool> {
+    let Some(entry) = layout(image)?.security_entry else {
+        return Ok(false);
+    };
+    // Unlike the other directories, this one holds a file offset
+    let start = u32_at(image, entry)? as usize;
+    let end = start.checked_add(u32_at(image, entry + 4)? as usize).ok_or(Error::Parse)?;
+    if end > image.len() {
+        return Err(Error::Parse);
+    }
+
+    // WIN_CERTIFICATE entries, each padded to 8 bytes
+    let mut at = start;
+    while at + WIN_CERT_HEADER_LEN <= end {
+        if u16_at(image, at + 6)? == WIN_CERT_TYPE_PKCS_SIGNED_DATA {
+            return Ok(true);
+        }
+        let len = u32_at(image, at)? as usize;
+        if len < WIN_CERT_HEADER_LEN {
+            break;
+        }
+        at = at.checked_add((len + 7) & !7).ok_or(Error::Parse)?;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal PE32+ image: headers, one 16-byte section, 8 bytes of
+    /// certificates
+    fn image() -> Vec<u8> {
+        let mut image = alloc::vec![0u8; 0x200 + 16 + 8];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c] = 0x40;
+        image[0x40..0x44].copy_from_slice(b"PE\0\0");
+        image[0x46] = 1; // one section
+        image[0x54] = 240; // optional header length
+        let optional = 0x58;
+        image[optional..optional + 2].copy_from_slice(&PE32_PLUS_MAGIC.to_le_bytes());
+        image[optional + 60..optional + 64].copy_from_slice(&0x200u32.to_le_bytes());
+        image[optional + 108] = 16; // data directories
+        let security = optional + 112 + 32;
+        image[security..security + 4].copy_from_slice(&0x210u32.to_le_bytes());
+        image[security + 4] = 8;
+        let section = optional + 240;
+        image[section + 16] = 16;
+        image[section + 20..section + 24].copy_from_slice(&0x200u32.to_le_bytes());
+        for (i, byte) in image[0x200..0x210].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        image
+    }
+
+    #[test]
+    fn test_excluded_fields() {
+        let original = digest(&image()).unwrap();
+
+        // Checksum, certificate table entry and certificates are not covered
+        let mut signed = image();
+        signed[0x58 + 64] = 0xaa;
+        signed[0x58 + 112 + 32 + 4] = 9;
+        signed.push(0xcc);
+        signed[0x212] = 0xbb;
+        assert_eq!(digest(&signed).unwrap(), original);
+
+        // Section data is
+        let mut patched = image();
+        patched[0x205] ^= 1;
+        assert_ne!(digest(&patched).unwrap(), original);
+    }
+
+    #[test]
+    fn test_is_signed() {
+        // The certificate table of the test image is all zeros
+        assert_eq!(is_signed(&image()), Ok(false));
+
+        let mut signed = image();
+        signed[0x210] = 8;
+        signed[0x214..0x216].copy_from_slice(&0x0200u16.to_le_bytes());
+        signed[0x216..0x218].copy_from_slice(&WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+        assert_eq!(is_signed(&signed), Ok(true));
+
+        // An empty certificate table
+        let mut unsigned = image();
+        unsigned[0x58 + 112 + 32 + 4] = 0;
+        assert_eq!(is_signed(&unsigned), Ok(false));
+
+        // A table past the end of the file
+        let mut truncated = signed;
+        truncated.truncate(0x214);
+        assert_eq!(is_signed(&truncated), Err(Error::Parse));
+        assert_eq!(is_signed(b"hello"), Err(Error::Parse));
+    }
+
+    #[test]
+    fn test_not_pe() {
+        assert_eq!(digest(b"hello"), Err(Error::Parse));
+        let mut truncated = image();
+        truncated.truncate(0x100);
+        assert_eq!(digest(&truncated), Err(Error::Parse));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::util::global::Global;
+use crate::util::{Error, Result};
+use core::time::Duration;
+use uefi::{boot, print};
+
+/// How state changes are sounded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BeepMode {
+    /// Silent
+    #[default]
+    Off,
+    /// BEL characters on the console (serial terminals, some firmware)
+    Console,
+    /// The PC speaker, or BEL where there is none
+    Speaker,
+}
+
+impl BeepMode {
+    /// Parse a mode name as used in config.txt and `set beep`
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Ok(BeepMode::Off),
+            "console" | "bell" => Ok(BeepMode::Console),
+            "speaker" => Ok(BeepMode::Speaker),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Mode name as written in config.txt
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BeepMode::Off => "off",
+            BeepMode::Console => "console",
+            BeepMode::Speaker => "speaker",
+        }
+    }
+}
+
+/// State changes worth telling someone who is not looking at the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// An image finished downloading
+    Downloaded,
+    /// An image (or its initrd or signature) failed verification
+    VerifyFailed,
+    /// A boot attempt failed
+    BootFailed,
+}
+
+impl Signal {
+    /// Tones to play, as (frequency in Hz, length in ms)
+    ///
+    /// Told apart by count and pitch, as a BEL only carries the count.
+    fn tones(&self) -> &'static [(u32, u64)] {
+        match self {
+            Signal::Downloaded => &[(1000, 100)],
+            Signal::VerifyFailed => &[(880, 150), (880, 150), (880, 150)],
+            Signal::BootFailed => &[(440, 400), (440, 400)],
+        }
+    }
+}
+
+/// Pause between two tones
+const GAP: Duration = Duration::from_millis(120);
+
+/// Current mode
+static MODE: Global<BeepMode> = Global::new();
+
+/// Select how signals are sounded
+pub fn set_mode(mode: BeepMode) {
+    MODE.set(mode);
+}
+
+/// Current mode
+pub fn mode() -> BeepMode {
+    MODE.with(|mode| *mode).unwrap_or_default()
+}
+
+/// Sound `signal` the way `beep=` asks for
+pub fn signal(signal: Signal) {
+    let mode = mode();
+    if mode == BeepMode::Off {
+        return;
+    }
+
+    for (i, &(frequency, ms)) in signal.tones().iter().enumerate() {
+        if i > 0 {
+            boot::stall(GAP);
+        }
+        if mode == BeepMode::Speaker && speaker::AVAILABLE {
+            speaker::tone(frequency, Duration::from_millis(ms));
+        } else {
+            print!("\x07");
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod speaker {
+    use core::arch::asm;
+    use core::time::Duration;
+
+    pub const AVAILABLE: bool = true;
+
+    /// Input clock of the programmable interval timer
+    const PIT_HZ: u32 = 1_193_182;
+
+    /// PIT channel 2 data and mode ports, and the port gating it to the
+    /// speaker (bit 0: timer gate, bit 1: speaker data)
+    const PIT_CHANNEL2: u16 = 0x42;
+    const PIT_COMMAND: u16 = 0x43;
+    const SPEAKER_GATE: u16 = 0x61;
+
+    unsafe fn outb(port: u16, value: u8) {
+        asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+    }
+
+    unsafe fn inb(port: u16) -> u8 {
+        let value: u8;
+        asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+        value
+    }
+
+    /// Play `frequency` for `length`, as GRUB's `play` does
+    pub fn tone(frequency: u32, length: Duration) {
+        let divisor = (PIT_HZ / frequency.max(20)).min(u32::from(u16::MAX)) as u16;
+        unsafe {
+            // Channel 2, low then high byte, square wave
+            outb(PIT_COMMAND, 0xb6);
+            outb(PIT_CHANNEL2, divisor as u8);
+            outb(PIT_CHANNEL2, (divisor >> 8) as u8);
+            let gate = inb(SPEAKER_GATE);
+            outb(SPEAKER_GATE, gate | 0x03);
+            uefi::boot::stall(length);
+            outb(SPEAKER_GATE, gate & !0x03);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod speaker {
+    use core::time::Duration;
+
+    /// No PC speaker outside x86
+    pub const AVAILABLE: bool = false;
+
+    pub fn tone(_frequency: u32, _length: Duration) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(BeepMode::parse("off").unwrap(), BeepMode::Off);
+        assert_eq!(BeepMode::parse("Bell").unwrap(), BeepMode::Console);
+        assert_eq!(BeepMode::parse("speaker").unwrap(), BeepMode::Speaker);
+        assert!(BeepMode::parse("loud").is_err());
+        for mode in [BeepMode::Off, BeepMode::Console, BeepMode::Speaker] {
+            assert_eq!(BeepMode::parse(mode.as_str()).unwrap(), mode);
+        }
+    }
+
+    #[test]
+    fn test_signals_differ_in_count() {
+        let counts = [Signal::Downloaded, Signal::VerifyFailed, Signal::BootFailed].map(|s| s.tones().len());
+        assert_eq!(counts, [1, 3, 2]);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::util::{Error, Result};
+use alloc::vec::Vec;
+use uefi::boot::{self, SearchType};
+use uefi::proto::network::snp::SimpleNetwork;
+use uefi::{println, Guid, Handle, Identify};
+
+/// HTTP Service Binding Protocol GUID
+/// {BDC8E6AF-D9BC-4379-A72A-E0C4E75DAE1C}
+const HTTP_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0xaf, 0xe6, 0xc8, 0xbd, 0xbc, 0xd9, 0x79, 0x43,
+    0xa7, 0x2a, 0xe0, 0xc4, 0xe7, 0x5d, 0xae, 0x1c,
+]);
+
+/// DHCP4 Service Binding Protocol GUID
+/// {9D9A39D8-BD42-4A73-A4D5-8EE94BE11380}
+const DHCP4_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0xd8, 0x39, 0x9a, 0x9d, 0x42, 0xbd, 0x73, 0x4a,
+    0xa4, 0xd5, 0x8e, 0xe9, 0x4b, 0xe1, 0x13, 0x80,
+]);
+
+/// DNS4 Service Binding Protocol GUID
+/// {B625B186-E063-44F7-8905-6A74DC6F52B4}
+const DNS4_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0x86, 0xb1, 0x25, 0xb6, 0x63, 0xe0, 0xf7, 0x44,
+    0x89, 0x05, 0x6a, 0x74, 0xdc, 0x6f, 0x52, 0xb4,
+]);
+
+/// TLS Service Binding Protocol GUID
+/// {952CB795-FF36-48CF-A249-4DF486D6AB8D}
+const TLS_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0x95, 0xb7, 0x2c, 0x95, 0x36, 0xff, 0xcf, 0x48,
+    0xa2, 0x49, 0x4d, 0xf4, 0x86, 0xd6, 0xab, 0x8d,
+]);
+
+/// TCG2 (TPM 2.0) Protocol GUID
+/// {607F766C-7455-42BE-930B-E4D76DB2720F}
+const TCG2_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0x6c, 0x76, 0x7f, 0x60, 0x55, 0x74, 0xbe, 0x42,
+    0x93, 0x0b, 0xe4, 0xd7, 0x6d, 0xb2, 0x72, 0x0f,
+]);
+
+/// UDP4 Service Binding Protocol GUID
+/// {83F01464-99BD-45E5-B383-AF6305D8E9E6}
+pub const UDP4_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0x64, 0x14, 0xf0, 0x83, 0xbd, 0x99, 0xe5, 0x45,
+    0xb3, 0x83, 0xaf, 0x63, 0x05, 0xd8, 0xe9, 0xe6,
+]);
+
+/// MTFTP4 Service Binding Protocol GUID
+/// {2FE800BE-8F01-4AA6-946B-D71388E1833F}
+const MTFTP4_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0xbe, 0x00, 0xe8, 0x2f, 0x01, 0x8f, 0xa6, 0x4a,
+    0x94, 0x6b, 0xd7, 0x13, 0x88, 0xe1, 0x83, 0x3f,
+]);
+
+/// IP6 Service Binding Protocol GUID
+/// {EC835DD3-FE0F-617B-A621-B350C3E13388}
+const IP6_SERVICE_BINDING_GUID: Guid = Guid::from_bytes([
+    0xd3, 0x5d, 0x83, 0xec, 0x0f, 0xfe, 0x7b, 0x61,
+    0xa6, 0x21, 0xb3, 0x50, 0xc3, 0xe1, 0x33, 0x88,
+]);
+
+/// RamDisk Protocol GUID
+/// {AB38A0DF-6873-44A9-87E6-D4EB56148449}
+pub const RAM_DISK_PROTOCOL_GUID: Guid = Guid::from_bytes([
+    0xdf, 0xa0, 0x38, 0xab, 0x73, 0x68, 0xa9, 0x44,
+    0x87, 0xe6, 0xd4, 0xeb, 0x56, 0x14, 0x84, 0x49,
+]);
+
+/// Firmware features we depend on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// HTTP boot stack (downloads and uploads)
+    Http,
+    /// DHCP4 client
+    Dhcp4,
+    /// DNS4 resolver (host names in URLs)
+    Dns4,
+    /// TLS (https:// URLs)
+    Tls,
+    /// UDP4 (nfs:// URLs)
+    Udp4,
+    /// MTFTP4 client (tftp:// URLs)
+    Mtftp4,
+    /// IPv6 stack (`ip6=true`, IPv6 addresses in URLs)
+    Ip6,
+    /// TPM 2.0 measurements
+    Tcg2,
+    /// RAM disk registration
+    RamDisk,
+}
+
+impl Capability {
+    /// All capabilities, in display order
+    pub const ALL: [Capability; 9] = [
+        Capability::Http,
+        Capability::Dhcp4,
+        Capability::Dns4,
+        Capability::Tls,
+        Capability::Udp4,
+        Capability::Mtftp4,
+        Capability::Ip6,
+        Capability::Tcg2,
+        Capability::RamDisk,
+    ];
+
+    /// Protocol whose presence provides the capability
+    fn guid(&self) -> &'static Guid {
+        match self {
+            Capability::Http => &HTTP_SERVICE_BINDING_GUID,
+            Capability::Dhcp4 => &DHCP4_SERVICE_BINDING_GUID,
+            Capability::Dns4 => &DNS4_SERVICE_BINDING_GUID,
+            Capability::Tls => &TLS_SERVICE_BINDING_GUID,
+            Capability::Udp4 => &UDP4_SERVICE_BINDING_GUID,
+            Capability::Mtftp4 => &MTFTP4_SERVICE_BINDING_GUID,
+            Capability::Ip6 => &IP6_SERVICE_BINDING_GUID,
+            Capability::Tcg2 => &TCG2_PROTOCOL_GUID,
+            Capability::RamDisk => &RAM_DISK_PROTOCOL_GUID,
+        }
+    }
+
+    /// Short protocol name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Http => "HTTP",
+            Capability::Dhcp4 => "DHCP4",
+            Capability::Dns4 => "DNS4",
+            Capability::Tls => "TLS",
+            Capability::Udp4 => "UDP4",
+            Capability::Mtftp4 => "MTFTP4",
+            Capability::Ip6 => "IP6",
+            Capability::Tcg2 => "TCG2",
+            Capability::RamDisk => "RamDisk",
+        }
+    }
+
+    /// What stops working without it
+    pub fn impact(&self) -> &'static str {
+        match self {
+            Capability::Http => "image downloads, config push and diag upload disabled",
+            Capability::Dhcp4 => "no automatic addressing, use a static setup (netsetup)",
+            Capability::Dns4 => "URLs must use IP addresses instead of host names",
+            Capability::Tls => "https:// URLs disabled",
+            Capability::Udp4 => "nfs:// URLs disabled",
+            Capability::Mtftp4 => "tftp:// URLs disabled",
+            Capability::Ip6 => "IPv4 only",
+            Capability::Tcg2 => "no TPM measurements or sealed credentials",
+            Capability::RamDisk => "no RAM disk images",
+        }
+    }
+
+}
+
+/// What the firmware provides, found once at startup
+///
+/// Enumerating handles is slow on firmware with hundreds of them, so
+/// commands ask here instead of locating protocols each time. The handles
+/// only change when drivers are connected or unloaded; filesystems, which
+/// come and go with removable media, are not cached.
+struct Probe {
+    /// Handles providing each capability, in `Capability::ALL` (and
+    /// declaration) order
+    handles: [Vec<Handle>; Capability::ALL.len()],
+    /// Network interfaces (Simple Network Protocol), in firmware order
+    nics: Vec<Handle>,
+}
+
+/// Cached probe, filled in by `probe`
+static mut PROBE: Option<Probe> = None;
+
+fn locate(guid: &Guid) -> Vec<Handle> {
+    boot::locate_handle_buffer(SearchType::ByProtocol(guid))
+        .map(|handles| handles.to_vec())
+        .unwrap_or_default()
+}
+
+fn run_probe() -> Probe {
+    Probe {
+        handles: Capability::ALL.map(|cap| locate(cap.guid())),
+        nics: locate(&SimpleNetwork::GUID),
+    }
+}
+
+/// Check which protocols the firmware provides, replacing cached results
+pub fn probe() {
+    unsafe {
+        PROBE = Some(run_probe());
+    }
+}
+
+/// Cached probe results (probes on first use)
+fn cached() -> &'static Probe {
+    unsafe { PROBE.get_or_insert_with(run_probe) }
+}
+
+/// Whether a capability is available (probes on first use)
+pub fn has(cap: Capability) -> bool {
+    !cached().handles[cap as usize].is_empty()
+}
+
+/// Handles providing a capability, e.g. the DHCP4 service bindings
+pub fn handles(cap: Capability) -> Vec<Handle> {
+    cached().handles[cap as usize].clone()
+}
+
+/// Network interfaces in firmware order
+pub fn nics() -> Vec<Handle> {
+    cached().nics.clone()
+}
+
+/// Fail cleanly when a feature depends on a missing capability
+pub fn require(cap: Capability) -> Result<()> {
+    if has(cap) {
+        return Ok(());
+    }
+
+    println!("  Firmware does not provide {}: {}", cap.name(), cap.impact());
+    Err(Error::Unsupported)
+}
+
+/// Print a warning for every missing capability
+pub fn warn_missing() {
+    for cap in Capability::ALL.iter().filter(|cap| !has(**cap)) {
+        println!("Warning: {} not available ({})", cap.name(), cap.impact());
+        crate::util::logger::log_entry(
+            log::Level::Warn,
+            &alloc::format!("{} protocol missing", cap.name()),
+        );
+    }
+}
+
+/// Print the full capability matrix
+pub fn print_matrix() {
+    crate::util::ui::heading("Firmware Capabilities:");
+    for cap in Capability::ALL.iter() {
+        if has(*cap) {
+            println!("  {:8} available", cap.name());
+        } else {
+            println!("  {:8} MISSING - {}", cap.name(), cap.impact());
+        }
+    }
+}
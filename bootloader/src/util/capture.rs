@@ -0,0 +1,150 @@
+use crate::util::{Error, Result};
+use alloc::string::String;
+use uefi_raw::protocol::console::SimpleTextOutputProtocol;
+use uefi_raw::{Boolean, Char16, Status};
+
+/// Most text kept from one command; the rest is dropped
+const MAX_CAPTURE_LEN: usize = 1024 * 1024;
+
+/// Console output being collected instead of shown
+struct Capture {
+    /// Stand-in installed as the system table's ConOut
+    protocol: SimpleTextOutputProtocol,
+    /// Firmware console to put back afterwards
+    original: *mut SimpleTextOutputProtocol,
+    text: String,
+}
+
+/// Capture in progress, if any
+///
+/// Lives in a static so the protocol handed to the firmware never moves.
+static mut CAPTURE: Option<Capture> = None;
+
+/// Whether console output is currently being captured
+pub fn active() -> bool {
+    unsafe { CAPTURE.is_some() }
+}
+
+/// Start collecting everything printed to the console
+///
+/// Works like redirection in the UEFI Shell: ConOut in the system table is
+/// swapped for a protocol that appends to a buffer, so every `println!`
+/// lands there until `stop` is called.
+pub fn start() -> Result<()> {
+    if active() {
+        return Err(Error::InvalidArgument);
+    }
+    let table = uefi::table::system_table_raw().ok_or(Error::Unsupported)?;
+
+    unsafe {
+        let table = table.as_ptr();
+        let original = (*table).stdout;
+        if original.is_null() {
+            return Err(Error::Unsupported);
+        }
+
+        let capture = CAPTURE.insert(Capture {
+            protocol: SimpleTextOutputProtocol {
+                reset,
+                output_string,
+                test_string,
+                query_mode,
+                set_mode,
+                set_attribute,
+                clear_screen,
+                set_cursor_position,
+                enable_cursor,
+                mode: (*original).mode,
+            },
+            original,
+            text: String::new(),
+        });
+        (*table).stdout = &mut capture.protocol;
+    }
+    Ok(())
+}
+
+/// Put the firmware console back and return what was captured
+pub fn stop() -> String {
+    let Some(capture) = (unsafe { CAPTURE.take() }) else {
+        return String::new();
+    };
+
+    if let Some(table) = uefi::table::system_table_raw() {
+        unsafe { (*table.as_ptr()).stdout = capture.original };
+    }
+    capture.text
+}
+
+unsafe extern "efiapi" fn output_string(_this: *mut SimpleTextOutputProtocol, string: *const Char16) -> Status {
+    let Some(capture) = CAPTURE.as_mut() else {
+        return Status::DEVICE_ERROR;
+    };
+
+    let mut next = string;
+    let units = core::iter::from_fn(|| {
+        let unit = *next;
+        if unit == 0 {
+            return None;
+        }
+        next = next.add(1);
+        Some(unit)
+    });
+
+    for c in char::decode_utf16(units) {
+        match c.unwrap_or(char::REPLACEMENT_CHARACTER) {
+            // Files get plain newlines, and progress lines that rewrite
+            // themselves collapse into one
+            '\r' => {}
+            '\x08' => {
+                capture.text.pop();
+            }
+            c if capture.text.len() < MAX_CAPTURE_LEN => capture.text.push(c),
+            _ => {}
+        }
+    }
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn test_string(_this: *mut SimpleTextOutputProtocol, _string: *const Char16) -> Status {
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn reset(_this: *mut SimpleTextOutputProtocol, _extended: Boolean) -> Status {
+    Status::SUCCESS
+}
+
+/// Screen size questions still go to the real console
+unsafe extern "efiapi" fn query_mode(
+    _this: *mut SimpleTextOutputProtocol,
+    mode: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> Status {
+    match CAPTURE.as_ref() {
+        Some(capture) => ((*capture.original).query_mode)(capture.original, mode, columns, rows),
+        None => Status::DEVICE_ERROR,
+    }
+}
+
+// Colors, cursor and screen changes mean nothing in a file
+
+unsafe extern "efiapi" fn set_mode(_this: *mut SimpleTextOutputProtocol, _mode: usize) -> Status {
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_attribute(_this: *mut SimpleTextOutputProtocol, _attribute: usize) -> Status {
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn clear_screen(_this: *mut SimpleTextOutputProtocol) -> Status {
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn set_cursor_position(_this: *mut SimpleTextOutputProtocol, _column: usize, _row: usize) -> Status {
+    Status::SUCCESS
+}
+
+unsafe extern "efiapi" fn enable_cursor(_this: *mut SimpleTextOutputProtocol, _visible: Boolean) -> Status {
+    Status::SUCCESS
+}
@@ -0,0 +1,190 @@
+use crate::network::addr::Ipv4;
+use crate::network::dhcp::{self, LeaseSource};
+use crate::network::http::{self, Body, UploadMethod};
+use crate::storage;
+use crate::util::{logger, smbios, version, Result};
+use alloc::string::String;
+use core::fmt::Write;
+use uefi::boot::{self, MemoryType};
+use uefi::mem::memory_map::MemoryMap;
+
+/// Directory bundles go to on the ESP when no path is given, each under a
+/// new `diag-<date>-<time>.txt` name so that earlier ones are kept
+pub const DIAG_DIR: &str = "\\EFI\\uefipxe";
+
+/// Build the diagnostics bundle as plain text
+pub fn build_bundle() -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# uefipxe diagnostics bundle");
+    let _ = writeln!(out, "version={}", version::VERSION);
+    let _ = writeln!(out, "commit={}", version::COMMIT);
+    let _ = writeln!(out, "built={}", version::BUILD_DATE);
+    if let Ok(t) = uefi::runtime::get_time() {
+        let _ = writeln!(
+            out,
+            "time={:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            t.year(),
+            t.month(),
+            t.day(),
+            t.hour(),
+            t.minute(),
+            t.second()
+        );
+    }
+
+    write_identity(&mut out);
+    write_memory_map(&mut out);
+    write_lease(&mut out);
+    write_boot_report(&mut out);
+    write_config(&mut out);
+    write_logs(&mut out);
+
+    out
+}
+
+/// SMBIOS system identity
+fn write_identity(out: &mut String) {
+    let _ = writeln!(out, "\n[system]");
+    match smbios::system_info() {
+        Some(info) => {
+            let _ = writeln!(out, "manufacturer={}", info.manufacturer);
+            let _ = writeln!(out, "product={}", info.product);
+            let _ = writeln!(out, "serial={}", info.serial);
+            let _ = writeln!(out, "uuid={}", info.uuid_string());
+        }
+        None => {
+            let _ = writeln!(out, "(no SMBIOS system information)");
+        }
+    }
+}
+
+/// Memory map totals by broad category
+fn write_memory_map(out: &mut String) {
+    let _ = writeln!(out, "\n[memory]");
+
+    let map = match boot::memory_map(MemoryType::LOADER_DATA) {
+        Ok(map) => map,
+        Err(e) => {
+            let _ = writeln!(out, "(memory map unavailable: {:?})", e.status());
+            return;
+        }
+    };
+
+    let mut total_pages = 0u64;
+    let mut free_pages = 0u64;
+    let mut boot_pages = 0u64;
+    let mut runtime_pages = 0u64;
+    for desc in map.entries() {
+        total_pages += desc.page_count;
+        match desc.ty {
+            MemoryType::CONVENTIONAL => free_pages += desc.page_count,
+            MemoryType::BOOT_SERVICES_CODE | MemoryType::BOOT_SERVICES_DATA => {
+                boot_pages += desc.page_count
+            }
+            MemoryType::RUNTIME_SERVICES_CODE | MemoryType::RUNTIME_SERVICES_DATA => {
+                runtime_pages += desc.page_count
+            }
+            _ => {}
+        }
+    }
+
+    let _ = writeln!(out, "entries={}", map.len());
+    let _ = writeln!(out, "total_mib={}", total_pages * 4 / 1024);
+    let _ = writeln!(out, "conventional_mib={}", free_pages * 4 / 1024);
+    let _ = writeln!(out, "boot_services_mib={}", boot_pages * 4 / 1024);
+    let _ = writeln!(out, "runtime_services_mib={}", runtime_pages * 4 / 1024);
+}
+
+/// Current DHCP/PXE lease
+fn write_lease(out: &mut String) {
+    let _ = writeln!(out, "\n[lease]");
+    match dhcp::last_lease() {
+        Some(lease) => {
+            let source = match lease.source {
+                LeaseSource::Dhcp => "dhcp",
+                LeaseSource::Pxe => "pxe",
+            };
+            let _ = writeln!(out, "source={}", source);
+            let _ = writeln!(out, "address={}", Ipv4(lease.client_ip));
+            let _ = writeln!(out, "netmask={}", Ipv4(lease.subnet_mask));
+            let _ = writeln!(out, "server={}", Ipv4(lease.server_ip));
+            if let Some(router) = lease.router {
+                let _ = writeln!(out, "router={}", Ipv4(router));
+            }
+            if !lease.boot_file.is_empty() {
+                let _ = writeln!(out, "boot_file={}", lease.boot_file);
+            }
+        }
+        None => {
+            let _ = writeln!(out, "(no lease recorded this session)");
+        }
+    }
+    if let Some(lease) = dhcp::last_lease6() {
+        let _ = writeln!(out, "address6={}/{}", lease.address, lease.prefix_length);
+    }
+}
+
+/// Outcome of the most recent boot attempt
+fn write_boot_report(out: &mut String) {
+    let _ = writeln!(out, "\n[last-boot]");
+    match crate::boot::report::last() {
+        Some(report) => out.push_str(&report),
+        None => {
+            let _ = writeln!(out, "(no boot attempt recorded)");
+        }
+    }
+}
+
+/// Current in-memory configuration
+fn write_config(out: &mut String) {
+    let _ = writeln!(out, "\n[config]");
+    match storage::with_config(|c| c.serialize()) {
+        Some(Ok(content)) => {
+            // The bundle may be uploaded, so secrets stay behind
+            for line in content.lines() {
+                match line.split_once('=') {
+                    Some(("iscsi_chap_secret", _)) => {
+                        let _ = writeln!(out, "iscsi_chap_secret=(redacted)");
+                    }
+                    _ => {
+                        let _ = writeln!(out, "{}", line);
+                    }
+                }
+            }
+        }
+        _ => {
+            let _ = writeln!(out, "(configuration unavailable)");
+        }
+    }
+}
+
+/// Buffered log messages
+fn write_logs(out: &mut String) {
+    let _ = writeln!(out, "\n[logs]");
+    for entry in logger::get_logs().iter() {
+        let _ = writeln!(out, "[{:5}] {}", entry.level, entry.message);
+    }
+}
+
+/// Write the bundle to the ESP, or POST it when `target` is a URL
+pub fn export(target: Option<&str>) -> Result<()> {
+    let bundle = build_bundle();
+
+    match target {
+        Some(url) if url.contains("://") => {
+            http::upload(url, UploadMethod::Post, Body::Text(bundle.as_bytes()))?;
+            uefi::println!("Diagnostics bundle ({} bytes) posted to {}", bundle.len(), url);
+        }
+        target => {
+            let path = match target {
+                Some(path) => String::from(path),
+                None => storage::file::unique_path(DIAG_DIR, "diag", ".txt")?,
+            };
+            storage::file::atomic_write(&path, bundle.as_bytes())?;
+            uefi::println!("Diagnostics bundle ({} bytes) written to {}", bundle.len(), path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,381 @@
+use sha2::{Digest, Sha512};
+
+// Ed25519 signature verification (RFC 8032)
+//
+// Field elements are 16 signed limbs of 16 bits, as in TweetNaCl. This is
+// not constant time, which is fine for verification: everything it handles
+// is public.
+
+/// Public key length in bytes
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Signature length in bytes
+pub const SIGNATURE_LEN: usize = 64;
+
+type Gf = [i64; 16];
+
+const GF0: Gf = [0; 16];
+const GF1: Gf = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// Curve constant d
+const D: Gf = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070,
+    0xe898, 0x7779, 0x4079, 0x8cc7, 0xfe73, 0x2b6f, 0x6cee, 0x5203,
+];
+
+/// 2 * d
+const D2: Gf = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0,
+    0xd130, 0xeef3, 0x80f2, 0x198e, 0xfce7, 0x56df, 0xd9dc, 0x2406,
+];
+
+/// Base point coordinates
+const X: Gf = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c,
+    0xdc5c, 0xfdd6, 0xe231, 0xc0a4, 0x53fe, 0xcd6e, 0x36d3, 0x2169,
+];
+const Y: Gf = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+];
+
+/// sqrt(-1)
+const I: Gf = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43,
+    0xd7a7, 0x3dfb, 0x0099, 0x2b4d, 0xdf0b, 0x4fc1, 0x2480, 0x2b83,
+];
+
+/// Group order, little-endian
+const L: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+fn carry(o: &mut Gf) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+    }
+}
+
+/// Swap p and q if b is 1
+fn select(p: &mut Gf, q: &mut Gf, b: i64) {
+    let c = !(b - 1);
+    for i in 0..16 {
+        let t = c & (p[i] ^ q[i]);
+        p[i] ^= t;
+        q[i] ^= t;
+    }
+}
+
+fn pack25519(n: &Gf) -> [u8; 32] {
+    let mut t = *n;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+    let mut m = GF0;
+    for _ in 0..2 {
+        m[0] = t[0] - 0xffed;
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let b = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        select(&mut t, &mut m, 1 - b);
+    }
+
+    let mut out = [0u8; 32];
+    for i in 0..16 {
+        out[2 * i] = (t[i] & 0xff) as u8;
+        out[2 * i + 1] = (t[i] >> 8) as u8;
+    }
+    out
+}
+
+fn unpack25519(n: &[u8; 32]) -> Gf {
+    let mut o = GF0;
+    for i in 0..16 {
+        o[i] = n[2 * i] as i64 + ((n[2 * i + 1] as i64) << 8);
+    }
+    o[15] &= 0x7fff;
+    o
+}
+
+fn neq(a: &Gf, b: &Gf) -> bool {
+    pack25519(a) != pack25519(b)
+}
+
+fn parity(a: &Gf) -> u8 {
+    pack25519(a)[0] & 1
+}
+
+fn add(a: &Gf, b: &Gf) -> Gf {
+    core::array::from_fn(|i| a[i] + b[i])
+}
+
+fn sub(a: &Gf, b: &Gf) -> Gf {
+    core::array::from_fn(|i| a[i] - b[i])
+}
+
+fn mul(a: &Gf, b: &Gf) -> Gf {
+    let mut t = [0i64; 31];
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+    let mut o = GF0;
+    o.copy_from_slice(&t[..16]);
+    carry(&mut o);
+    carry(&mut o);
+    o
+}
+
+fn square(a: &Gf) -> Gf {
+    mul(a, a)
+}
+
+fn invert(i: &Gf) -> Gf {
+    let mut c = *i;
+    for a in (0..=253).rev() {
+        c = square(&c);
+        if a != 2 && a != 4 {
+            c = mul(&c, i);
+        }
+    }
+    c
+}
+
+/// i^((p - 5) / 8), for square roots
+fn pow2523(i: &Gf) -> Gf {
+    let mut c = *i;
+    for a in (0..=250).rev() {
+        c = square(&c);
+        if a != 1 {
+            c = mul(&c, i);
+        }
+    }
+    c
+}
+
+/// Point in extended coordinates (X, Y, Z, T)
+type Point = [Gf; 4];
+
+fn point_add(p: &mut Point, q: &Point) {
+    let a = mul(&sub(&p[1], &p[0]), &sub(&q[1], &q[0]));
+    let b = mul(&add(&p[0], &p[1]), &add(&q[0], &q[1]));
+    let c = mul(&mul(&p[3], &q[3]), &D2);
+    let d = mul(&p[2], &q[2]);
+    let d = add(&d, &d);
+    let e = sub(&b, &a);
+    let f = sub(&d, &c);
+    let g = add(&d, &c);
+    let h = add(&b, &a);
+
+    p[0] = mul(&e, &f);
+    p[1] = mul(&h, &g);
+    p[2] = mul(&g, &f);
+    p[3] = mul(&e, &h);
+}
+
+fn point_swap(p: &mut Point, q: &mut Point, b: i64) {
+    for i in 0..4 {
+        select(&mut p[i], &mut q[i], b);
+    }
+}
+
+fn point_pack(p: &Point) -> [u8; 32] {
+    let zi = invert(&p[2]);
+    let tx = mul(&p[0], &zi);
+    let ty = mul(&p[1], &zi);
+    let mut r = pack25519(&ty);
+    r[31] ^= parity(&tx) << 7;
+    r
+}
+
+/// s * q (s little-endian)
+fn scalar_mult(q: &Point, s: &[u8; 32]) -> Point {
+    let mut p: Point = [GF0, GF1, GF1, GF0];
+    let mut q = *q;
+    for i in (0..256).rev() {
+        let b = ((s[i / 8] >> (i & 7)) & 1) as i64;
+        point_swap(&mut p, &mut q, b);
+        point_add(&mut q, &p);
+        let p2 = p;
+        point_add(&mut p, &p2);
+        point_swap(&mut p, &mut q, b);
+    }
+    p
+}
+
+fn scalar_base(s: &[u8; 32]) -> Point {
+    scalar_mult(&[X, Y, GF1, mul(&X, &Y)], s)
+}
+
+/// Reduce a 512-bit little-endian number modulo L
+fn reduce(h: &[u8; 64]) -> [u8; 32] {
+    let mut x = [0i64; 64];
+    for i in 0..64 {
+        x[i] = h[i] as i64;
+    }
+
+    for i in (32..64).rev() {
+        let mut carry = 0;
+        let mut j = i - 32;
+        while j < i - 12 {
+            x[j] += carry - 16 * x[i] * L[j - (i - 32)];
+            carry = (x[j] + 128) >> 8;
+            x[j] -= carry << 8;
+            j += 1;
+        }
+        x[j] += carry;
+        x[i] = 0;
+    }
+
+    let mut carry = 0;
+    for j in 0..32 {
+        x[j] += carry - (x[31] >> 4) * L[j];
+        carry = x[j] >> 8;
+        x[j] &= 255;
+    }
+    for j in 0..32 {
+        x[j] -= carry * L[j];
+    }
+
+    let mut r = [0u8; 32];
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        r[i] = (x[i] & 255) as u8;
+    }
+    r
+}
+
+/// Decode a public key as the negated point -A
+fn unpack_negated(key: &[u8; 32]) -> Option<Point> {
+    let y = unpack25519(key);
+    let num = square(&y);
+    let den = mul(&num, &D);
+    let num = sub(&num, &GF1);
+    let den = add(&GF1, &den);
+
+    // x = sqrt(num / den), computed as num * den^3 * (num * den^7)^((p-5)/8)
+    let den2 = square(&den);
+    let den4 = square(&den2);
+    let den6 = mul(&den4, &den2);
+    let t = mul(&mul(&den6, &num), &den);
+    let t = mul(&mul(&mul(&pow2523(&t), &num), &den), &den);
+    let mut x = mul(&t, &den);
+
+    if neq(&mul(&square(&x), &den), &num) {
+        x = mul(&x, &I);
+    }
+    if neq(&mul(&square(&x), &den), &num) {
+        return None;
+    }
+
+    if parity(&x) == key[31] >> 7 {
+        x = sub(&GF0, &x);
+    }
+
+    Some([x, y, GF1, mul(&x, &y)])
+}
+
+/// Whether the little-endian scalar is below the group order
+fn is_canonical(s: &[u8]) -> bool {
+    for i in (0..32).rev() {
+        let l = L[i] as u8;
+        if s[i] != l {
+            return s[i] < l;
+        }
+    }
+    false
+}
+
+/// Verify an Ed25519 signature over `message`
+pub fn verify(public_key: &[u8; PUBLIC_KEY_LEN], message: &[u8], signature: &[u8; SIGNATURE_LEN]) -> bool {
+    let (r, s) = signature.split_at(32);
+    // Non-canonical S values would make signatures malleable
+    if !is_canonical(s) {
+        return false;
+    }
+    let Some(neg_a) = unpack_negated(public_key) else {
+        return false;
+    };
+
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(message);
+    let mut digest = [0u8; 64];
+    digest.copy_from_slice(&hasher.finalize());
+    let k = reduce(&digest);
+
+    // R must equal [S]B - [k]A
+    let mut s_bytes = [0u8; 32];
+    s_bytes.copy_from_slice(s);
+    let mut p = scalar_mult(&neg_a, &k);
+    point_add(&mut p, &scalar_base(&s_bytes));
+    point_pack(&p) == r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex<const N: usize>(s: &str) -> [u8; N] {
+        let mut out = [0u8; N];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_rfc8032_vectors() {
+        // RFC 8032 section 7.1, tests 1 and 2
+        let key = hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let sig = hex(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        );
+        assert!(verify(&key, b"", &sig));
+        assert!(!verify(&key, b"x", &sig));
+
+        let key = hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let sig = hex(
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        );
+        assert!(verify(&key, &[0x72], &sig));
+    }
+
+    #[test]
+    fn test_openssl_signature() {
+        // openssl pkeyutl -sign -rawin over "hello menu\n"
+        let key = hex("0eff9efffd57f4c4fb2600693db24a97b01f1b9c354dcd733d426c327aabeada");
+        let mut sig = hex(
+            "f2ac8106c606fcbb0ea7fe9727d46939c1648e52fe06e26573516fab6c1a63253d2a984b93cf0b6dc2cf8797a26976f5b179212b7bc5c1a30bbf8411c12bfa03",
+        );
+        assert!(verify(&key, b"hello menu\n", &sig));
+
+        sig[10] ^= 1;
+        assert!(!verify(&key, b"hello menu\n", &sig));
+    }
+
+    #[test]
+    fn test_non_canonical_s() {
+        let key = hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let mut sig = [0u8; 64];
+        sig[32..].copy_from_slice(&[0xff; 32]);
+        assert!(!verify(&key, b"", &sig));
+    }
+}
@@ -19,6 +19,14 @@ pub enum Error {
     OutOfMemory,
     /// Buffer too small
     BufferTooSmall,
+    /// Required firmware protocol not available
+    Unsupported,
+    /// Integrity or security check failed
+    SecurityViolation,
+    /// Not enough free space on the target filesystem
+    NoSpace,
+    /// TPM command failed with this response code
+    Tpm(u32),
     /// Unknown error
     Unknown,
 }
@@ -34,11 +42,36 @@ impl fmt::Display for Error {
             Error::NotFound => write!(f, "Not found"),
             Error::OutOfMemory => write!(f, "Out of memory"),
             Error::BufferTooSmall => write!(f, "Buffer too small"),
+            Error::Unsupported => write!(f, "Not supported by firmware"),
+            Error::SecurityViolation => write!(f, "Security violation"),
+            Error::NoSpace => write!(f, "Not enough free space"),
+            Error::Tpm(code) => write!(f, "TPM error 0x{:03x}", code),
             Error::Unknown => write!(f, "Unknown error"),
         }
     }
 }
 
+impl Error {
+    /// UEFI status code reported to our caller (e.g. the shell's %lasterror%)
+    pub fn status(&self) -> uefi::Status {
+        match self {
+            Error::Uefi(status) => *status,
+            Error::Io => uefi::Status::DEVICE_ERROR,
+            Error::Parse | Error::InvalidCommand | Error::InvalidArgument => {
+                uefi::Status::INVALID_PARAMETER
+            }
+            Error::NotFound => uefi::Status::NOT_FOUND,
+            Error::OutOfMemory => uefi::Status::OUT_OF_RESOURCES,
+            Error::BufferTooSmall => uefi::Status::BUFFER_TOO_SMALL,
+            Error::Unsupported => uefi::Status::UNSUPPORTED,
+            Error::SecurityViolation => uefi::Status::SECURITY_VIOLATION,
+            Error::NoSpace => uefi::Status::VOLUME_FULL,
+            Error::Tpm(_) => uefi::Status::DEVICE_ERROR,
+            Error::Unknown => uefi::Status::ABORTED,
+        }
+    }
+}
+
 impl From<uefi::Status> for Error {
     fn from(status: uefi::Status) -> Self {
         Error::Uefi(status)
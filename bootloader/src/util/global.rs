@@ -0,0 +1,83 @@
+use core::cell::RefCell;
+
+/// State shared by the whole bootloader, set once at startup
+///
+/// Access goes through closures, and a mutable borrow that overlaps any
+/// other borrow panics instead of handing out aliasing references.
+/// Boot services run everything on one CPU, so this is a RefCell rather
+/// than a spin lock: an event notification that interrupted a holder
+/// would spin forever where it now panics with a clear message.
+pub struct Global<T>(RefCell<Option<T>>);
+
+// Boot services never run code on another CPU (see above)
+unsafe impl<T> Sync for Global<T> {}
+
+impl<T> Global<T> {
+    /// An empty slot, for a `static`
+    pub const fn new() -> Self {
+        Global(RefCell::new(None))
+    }
+
+    /// Put `value` in place of whatever was there
+    pub fn set(&self, value: T) {
+        *self.0.borrow_mut() = Some(value);
+    }
+
+    /// Empty the slot again
+    pub fn clear(&self) {
+        *self.0.borrow_mut() = None;
+    }
+
+    /// Move the value out, leaving the slot empty
+    pub fn take(&self) -> Option<T> {
+        self.0.borrow_mut().take()
+    }
+
+    /// Run `f` with the value (None while it is unset)
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.0.borrow().as_ref().map(f)
+    }
+
+    /// Run `f` with the value, mutably (None while it is unset)
+    ///
+    /// `f` must not reach the value again through `with` or `with_mut`.
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.0.borrow_mut().as_mut().map(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global() {
+        let global: Global<u32> = Global::new();
+        assert_eq!(global.with(|value| *value), None);
+
+        global.set(1);
+        assert_eq!(
+            global.with_mut(|value| {
+                *value += 1;
+                *value
+            }),
+            Some(2)
+        );
+        // Shared access nests
+        assert_eq!(global.with(|outer| global.with(|inner| outer + inner)), Some(Some(4)));
+
+        assert_eq!(global.take(), Some(2));
+        assert_eq!(global.take(), None);
+        global.set(3);
+        global.clear();
+        assert_eq!(global.with(|value| *value), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_overlapping_mutation_panics() {
+        let global: Global<u32> = Global::new();
+        global.set(1);
+        global.with(|_| global.with_mut(|value| *value = 2));
+    }
+}
@@ -1,9 +1,21 @@
+use crate::storage::naming::{self, Sibling};
+use crate::util::{Error, Result};
+use alloc::string::String;
 use heapless::Deque;
-use core::fmt::Write;
+use core::fmt::{self, Write};
 
 const LOG_BUFFER_SIZE: usize = 100;
 const MAX_LOG_ENTRY_LEN: usize = 128;
 
+/// Boot log on the ESP, kept across reboots
+pub const LOG_FILE_PATH: &str = "\\EFI\\uefipxe\\boot.log";
+
+/// The boot log is rotated to `boot.log.old` before growing past this
+const MAX_LOG_FILE_LEN: u64 = 64 * 1024;
+
+/// Most text held back for the boot log; the oldest lines go first
+const MAX_PENDING_LEN: usize = 16 * 1024;
+
 /// A single log entry
 #[derive(Debug, Clone)]
 pub struct LogEntry {
@@ -14,10 +26,147 @@ pub struct LogEntry {
 /// Global log buffer using a circular buffer
 static mut LOG_BUFFER: Option<Deque<LogEntry, LOG_BUFFER_SIZE>> = None;
 
+/// Lines not yet appended to the boot log
+static mut PENDING: Option<String> = None;
+
+/// Whether `flush` writes to the boot log (`boot_log=` in the config)
+static mut PERSIST: bool = false;
+
+/// Whether this session has written to the boot log yet
+static mut STARTED: bool = false;
+
+/// How much progress output reaches the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Echo {
+    /// Warnings and errors only, for unattended boots
+    Quiet,
+    /// What each step is doing
+    #[default]
+    Normal,
+    /// Step-by-step detail from the network and boot code
+    Verbose,
+    /// Everything, also kept in the log for `logs` and the boot log
+    Debug,
+}
+
+impl Echo {
+    /// Parse a level name as used in config.txt and `set echo`
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "quiet" => Ok(Echo::Quiet),
+            "normal" => Ok(Echo::Normal),
+            "verbose" => Ok(Echo::Verbose),
+            "debug" => Ok(Echo::Debug),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Level name as written in config.txt
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Echo::Quiet => "quiet",
+            Echo::Normal => "normal",
+            Echo::Verbose => "verbose",
+            Echo::Debug => "debug",
+        }
+    }
+
+    /// Least severe message level shown
+    fn max_level(&self) -> log::Level {
+        match self {
+            Echo::Quiet => log::Level::Warn,
+            Echo::Normal => log::Level::Info,
+            Echo::Verbose => log::Level::Debug,
+            Echo::Debug => log::Level::Trace,
+        }
+    }
+}
+
+/// Console echo level (`echo=` in the config)
+static mut ECHO: Echo = Echo::Normal;
+
+/// Select the console echo level
+pub fn set_echo(echo: Echo) {
+    unsafe {
+        ECHO = echo;
+    }
+}
+
+/// Current console echo level
+pub fn echo() -> Echo {
+    unsafe { ECHO }
+}
+
+/// Whether messages at `level` reach the console
+pub fn echoes(level: log::Level) -> bool {
+    level <= echo().max_level()
+}
+
+/// Print a progress message if the echo level shows `level`
+///
+/// With `echo=debug` the detail is also logged, so that `logs save` or the
+/// boot log captures a troubleshooting session in full.
+pub fn echo_line(level: log::Level, args: fmt::Arguments) {
+    if !echoes(level) {
+        return;
+    }
+    uefi::println!("{}", args);
+    if echo() == Echo::Debug && level >= log::Level::Debug {
+        let mut line = String::new();
+        let _ = line.write_fmt(args);
+        if !line.trim().is_empty() {
+            log_entry(level, line.trim());
+        }
+    }
+}
+
+/// Print a progress message at a given `log::Level`, subject to the echo
+/// level; errors and anything asked for interactively use `println!`
+#[macro_export]
+macro_rules! echo {
+    ($level:expr) => {
+        $crate::util::logger::echo_line($level, format_args!(""))
+    };
+    ($level:expr, $($arg:tt)*) => {
+        $crate::util::logger::echo_line($level, format_args!($($arg)*))
+    };
+}
+
+/// What a step is doing (hidden by `echo=quiet`)
+#[macro_export]
+macro_rules! say {
+    () => {
+        $crate::echo!(log::Level::Info)
+    };
+    ($($arg:tt)*) => {
+        $crate::echo!(log::Level::Info, $($arg)*)
+    };
+}
+
+/// Step-by-step detail (shown from `echo=verbose` on)
+#[macro_export]
+macro_rules! detail {
+    () => {
+        $crate::echo!(log::Level::Debug)
+    };
+    ($($arg:tt)*) => {
+        $crate::echo!(log::Level::Debug, $($arg)*)
+    };
+}
+
 /// Initialize the log buffer
 pub fn init() {
     unsafe {
         LOG_BUFFER = Some(Deque::new());
+        PENDING = Some(String::new());
+    }
+}
+
+/// Turn writing the boot log on or off; entries logged before this are
+/// kept, so the first flush still has them
+pub fn set_persist(persist: bool) {
+    unsafe {
+        PERSIST = persist;
     }
 }
 
@@ -48,11 +197,77 @@ pub fn log_entry(level: log::Level, message: &str) {
                 buffer.pop_front();
             }
 
+            if let Some(ref mut pending) = PENDING {
+                let _ = writeln!(pending, "[{:5}] {}", entry.level, entry.message);
+                trim_front(pending, MAX_PENDING_LEN);
+            }
+
             let _ = buffer.push_back(entry);
         }
     }
 }
 
+/// Drop whole lines from the start of `text` until it fits in `max` bytes
+fn trim_front(text: &mut String, max: usize) {
+    if text.len() <= max {
+        return;
+    }
+    let cut = text.len() - max;
+    let start = match text[cut..].find('\n') {
+        Some(newline) => cut + newline + 1,
+        None => text.len(),
+    };
+    text.drain(..start);
+}
+
+/// Append the entries logged since the last flush to the boot log, if it
+/// is turned on
+///
+/// Called at points where the firmware is not in a callback: after each
+/// command and before an image is started.
+pub fn flush() {
+    if unsafe { PERSIST } {
+        // A missing or read-only ESP is not worth failing anything over
+        let _ = save();
+    }
+}
+
+/// Append the entries logged since the last flush to the boot log
+pub fn save() -> Result<()> {
+    let text = match unsafe { PENDING.as_mut() } {
+        Some(pending) if !pending.is_empty() => core::mem::take(pending),
+        _ => return Ok(()),
+    };
+
+    // The first write of a session says when it started
+    let mut out = String::new();
+    if !unsafe { STARTED } {
+        let now = crate::util::rtc::now().map(|time| crate::util::rtc::format(&time));
+        let now = now.as_deref().unwrap_or("(clock unavailable)");
+        let _ = writeln!(out, "=== uefipxe {} started {} ===", env!("CARGO_PKG_VERSION"), now);
+    }
+    out.push_str(&text);
+
+    if let Err(e) = crate::storage::file::append(LOG_FILE_PATH, out.as_bytes(), MAX_LOG_FILE_LEN) {
+        // Keep the lines for the next attempt, ahead of anything newer
+        if let Some(pending) = unsafe { PENDING.as_mut() } {
+            pending.insert_str(0, &text);
+            trim_front(pending, MAX_PENDING_LEN);
+        }
+        return Err(e);
+    }
+    unsafe {
+        STARTED = true;
+    }
+    Ok(())
+}
+
+/// Delete the boot log and its rotated part; returns how many files went
+pub fn clear_file() -> Result<usize> {
+    let rotated = naming::sibling(LOG_FILE_PATH, Sibling::Rotated);
+    Ok(crate::storage::file::delete(LOG_FILE_PATH)? + crate::storage::file::delete(&rotated)?)
+}
+
 /// Get all log entries
 pub fn get_logs() -> heapless::Vec<LogEntry, LOG_BUFFER_SIZE> {
     unsafe {
@@ -72,3 +287,33 @@ pub fn clear_logs() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_levels() {
+        assert_eq!(Echo::parse("Verbose"), Ok(Echo::Verbose));
+        assert!(Echo::parse("loud").is_err());
+        assert_eq!(Echo::parse(Echo::Quiet.as_str()), Ok(Echo::Quiet));
+
+        assert!(log::Level::Warn <= Echo::Quiet.max_level());
+        assert!(log::Level::Info > Echo::Quiet.max_level());
+        assert!(log::Level::Info <= Echo::Normal.max_level());
+        assert!(log::Level::Debug > Echo::Normal.max_level());
+        assert!(log::Level::Debug <= Echo::Verbose.max_level());
+        assert!(log::Level::Trace <= Echo::Debug.max_level());
+    }
+
+    #[test]
+    fn test_trim_front() {
+        let mut text = String::from("one\ntwo\nthree\n");
+        trim_front(&mut text, 64);
+        assert_eq!(text, "one\ntwo\nthree\n");
+        trim_front(&mut text, 10);
+        assert_eq!(text, "three\n");
+        trim_front(&mut text, 3);
+        assert_eq!(text, "");
+    }
+}
@@ -0,0 +1,167 @@
+use alloc::format;
+use alloc::string::String;
+
+/// Steps of a network boot that are timed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Getting a DHCP lease when the network comes up
+    Dhcp,
+    /// Request until the response headers: name lookup, TCP and TLS
+    /// handshake all happen inside the firmware's HTTP driver here
+    Connect,
+    /// Receiving the image body
+    Download,
+    /// Checking the image against its signature
+    Verify,
+    /// LoadImage of the verified image
+    Load,
+}
+
+impl Phase {
+    /// All phases, in the order a boot goes through them
+    pub const ALL: [Phase; 5] = [Phase::Dhcp, Phase::Connect, Phase::Download, Phase::Verify, Phase::Load];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Dhcp => "dhcp",
+            Phase::Connect => "connect",
+            Phase::Download => "download",
+            Phase::Verify => "verify",
+            Phase::Load => "load",
+        }
+    }
+}
+
+/// Time spent in each phase, in microseconds (None: not measured)
+pub type Timings = [Option<u64>; Phase::ALL.len()];
+
+/// Timings of the current boot attempt
+///
+/// DHCP happens once per session, so its timing carries over from one
+/// attempt to the next.
+static mut TIMINGS: Timings = [None; Phase::ALL.len()];
+
+/// Start a new boot attempt, forgetting everything but the DHCP time
+pub fn begin_attempt() {
+    for phase in Phase::ALL.iter().filter(|phase| **phase != Phase::Dhcp) {
+        unsafe { TIMINGS[*phase as usize] = None };
+    }
+}
+
+/// Add time spent in a phase (a retried request counts twice)
+pub fn add(phase: Phase, micros: u64) {
+    unsafe {
+        let total = TIMINGS[phase as usize].unwrap_or(0).saturating_add(micros);
+        TIMINGS[phase as usize] = Some(total);
+    }
+}
+
+/// Timings measured so far
+pub fn timings() -> Timings {
+    unsafe { TIMINGS }
+}
+
+/// Measures one stretch of a phase
+pub struct Timer {
+    start: u64,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        Timer { start: clock::now_us() }
+    }
+
+    /// Microseconds since `start`
+    pub fn elapsed_us(&self) -> u64 {
+        clock::now_us().saturating_sub(self.start)
+    }
+
+    /// Stop and add the time to `phase`
+    pub fn stop(self, phase: Phase) {
+        add(phase, self.elapsed_us());
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod clock {
+    /// TSC ticks per microsecond, measured against the firmware's stall
+    static mut TICKS_PER_US: u64 = 0;
+
+    /// Monotonic microseconds from the time stamp counter
+    ///
+    /// The first call calibrates for 10ms; the TSC runs at a constant rate
+    /// on every CPU recent enough to run UEFI firmware.
+    pub fn now_us() -> u64 {
+        use core::arch::x86_64::_rdtsc;
+
+        unsafe {
+            if TICKS_PER_US == 0 {
+                let start = _rdtsc();
+                uefi::boot::stall(core::time::Duration::from_millis(10));
+                TICKS_PER_US = ((_rdtsc() - start) / 10_000).max(1);
+            }
+            _rdtsc() / TICKS_PER_US
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod clock {
+    /// Microseconds from the real-time clock; coarse, but needs no counter
+    pub fn now_us() -> u64 {
+        match uefi::runtime::get_time() {
+            Ok(t) => {
+                let seconds = ((t.day() as u64 * 24 + t.hour() as u64) * 60 + t.minute() as u64) * 60 + t.second() as u64;
+                seconds * 1_000_000 + t.nanosecond() as u64 / 1000
+            }
+            Err(_) => 0,
+        }
+    }
+}
+
+/// Short human form: `850us`, `120ms`, `3.41s`
+pub fn format_duration(micros: u64) -> String {
+    match micros {
+        0..=999 => format!("{}us", micros),
+        1_000..=999_999 => format!("{}ms", micros / 1000),
+        _ => format!("{}.{:02}s", micros / 1_000_000, micros % 1_000_000 / 10_000),
+    }
+}
+
+/// `dhcp=1.20s connect=85ms ...` for the measured phases, or None
+pub fn summary(timings: &Timings) -> Option<String> {
+    let parts: alloc::vec::Vec<String> = Phase::ALL
+        .iter()
+        .filter_map(|phase| {
+            timings[*phase as usize].map(|micros| format!("{}={}", phase.as_str(), format_duration(micros)))
+        })
+        .collect();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0us");
+        assert_eq!(format_duration(999), "999us");
+        assert_eq!(format_duration(85_400), "85ms");
+        assert_eq!(format_duration(1_000_000), "1.00s");
+        assert_eq!(format_duration(3_419_999), "3.41s");
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut timings: Timings = [None; Phase::ALL.len()];
+        assert_eq!(summary(&timings), None);
+        timings[Phase::Dhcp as usize] = Some(1_200_000);
+        timings[Phase::Verify as usize] = Some(120_000);
+        assert_eq!(summary(&timings).as_deref(), Some("dhcp=1.20s verify=120ms"));
+    }
+}
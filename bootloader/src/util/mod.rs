@@ -1,4 +1,25 @@
+pub mod authenticode;
+pub mod beep;
+pub mod blake3;
+pub mod capture;
+pub mod caps;
+pub mod diag;
+pub mod ed25519;
 pub mod error;
+pub mod global;
 pub mod logger;
+pub mod metrics;
+pub mod password;
+pub mod platform;
+pub mod rtc;
+pub mod selftest;
+pub mod sha256;
+pub mod smbios;
+pub mod splash;
+pub mod tpm;
+pub mod ucs2;
+pub mod ui;
+pub mod version;
+pub mod wipe;
 
 pub use error::{Error, Result};
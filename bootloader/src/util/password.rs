@@ -0,0 +1,113 @@
+use crate::util::sha256::{self, DIGEST_LEN};
+use crate::util::{Error, Result};
+use heapless::Vec;
+
+/// Scheme of a stored password: `pbkdf2-sha256$<iterations>$<salt hex>$<hash hex>`
+pub const SCHEME: &str = "pbkdf2-sha256";
+
+/// Iteration counts accepted; fewer is cheap to guess, more stalls the
+/// prompt on slow firmware
+pub const MIN_ITERATIONS: u32 = 10_000;
+pub const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Salt lengths accepted, in bytes
+const MIN_SALT_LEN: usize = 8;
+const MAX_SALT_LEN: usize = 32;
+
+/// Longest stored password text
+pub const MAX_LEN: usize = SCHEME.len() + 1 + 7 + 1 + 2 * MAX_SALT_LEN + 1 + 2 * DIGEST_LEN;
+
+/// A salted, iterated password hash as kept in the configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordHash {
+    iterations: u32,
+    salt: Vec<u8, MAX_SALT_LEN>,
+    hash: [u8; DIGEST_LEN],
+}
+
+impl PasswordHash {
+    /// Parse `pbkdf2-sha256$<iterations>$<salt hex>$<hash hex>`
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut parts = value.split('$');
+        if parts.next() != Some(SCHEME) {
+            return Err(Error::Parse);
+        }
+        let iterations = parts.next().and_then(|n| n.parse::<u32>().ok()).ok_or(Error::Parse)?;
+        if !(MIN_ITERATIONS..=MAX_ITERATIONS).contains(&iterations) {
+            return Err(Error::Parse);
+        }
+
+        let mut salt = Vec::new();
+        decode_hex(parts.next().ok_or(Error::Parse)?, &mut salt)?;
+        if salt.len() < MIN_SALT_LEN {
+            return Err(Error::Parse);
+        }
+        let mut hash: Vec<u8, DIGEST_LEN> = Vec::new();
+        decode_hex(parts.next().ok_or(Error::Parse)?, &mut hash)?;
+        if parts.next().is_some() {
+            return Err(Error::Parse);
+        }
+        let hash = hash.into_array().map_err(|_| Error::Parse)?;
+
+        Ok(PasswordHash { iterations, salt, hash })
+    }
+
+    /// Whether `password` is the one hashed, compared in constant time
+    pub fn matches(&self, password: &[u8]) -> bool {
+        constant_time_eq(&sha256::pbkdf2(password, &self.salt, self.iterations), &self.hash)
+    }
+}
+
+/// Whether `a` and `b` are equal, taking as long whichever bytes differ
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    core::hint::black_box(difference) == 0
+}
+
+fn decode_hex<const N: usize>(hex: &str, out: &mut Vec<u8, N>) -> Result<()> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(Error::Parse);
+    }
+    for i in (0..hex.len()).step_by(2) {
+        let byte = u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| Error::Parse)?;
+        out.push(byte).map_err(|_| Error::Parse)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `printf %s admin | scripts/hash-password.sh` with salt a1b2c3d4e5f60718
+    const ADMIN: &str =
+        "pbkdf2-sha256$10000$a1b2c3d4e5f60718$9af95e08f8b55543e29ad7bcfdcec9cdcbc80e54fcf7ece830b268f51eb53e33";
+
+    #[test]
+    fn test_matches() {
+        let hash = PasswordHash::parse(ADMIN).unwrap();
+        assert!(hash.matches(b"admin"));
+        assert!(!hash.matches(b"Admin"));
+        assert!(!hash.matches(b""));
+    }
+
+    #[test]
+    fn test_parse_rejects() {
+        assert!(PasswordHash::parse(&ADMIN.replacen("pbkdf2-sha256", "sha256", 1)).is_err());
+        assert!(PasswordHash::parse(&ADMIN.replacen("$10000$", "$999$", 1)).is_err());
+        assert!(PasswordHash::parse(&ADMIN.replacen("$a1b2c3d4e5f60718$", "$a1b2$", 1)).is_err());
+        assert!(PasswordHash::parse(&ADMIN[..ADMIN.len() - 2]).is_err());
+        assert!(PasswordHash::parse(&alloc::format!("{}$00", ADMIN)).is_err());
+        assert!(PasswordHash::parse("8c6976e5b5410415bde908bd4dee15dfb167a9c873fc4bb8a81f6f2ab448a918").is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::util::{Error, Result};
+use core::fmt;
+use uefi::cstr16;
+use uefi::runtime::{self, VariableVendor};
+
+/// CPU architecture of an EFI image, named as in removable-media paths
+/// (`BOOTX64.EFI`, `BOOTAA64.EFI`, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X64,
+    Ia32,
+    Aa64,
+    Arm,
+    RiscV64,
+    LoongArch64,
+}
+
+impl Arch {
+    /// Architecture this bootloader was built for, which is the only one
+    /// LoadImage will start here
+    pub const CURRENT: Option<Arch> = if cfg!(target_arch = "x86_64") {
+        Some(Arch::X64)
+    } else if cfg!(target_arch = "x86") {
+        Some(Arch::Ia32)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(Arch::Aa64)
+    } else if cfg!(target_arch = "arm") {
+        Some(Arch::Arm)
+    } else if cfg!(target_arch = "riscv64") {
+        Some(Arch::RiscV64)
+    } else if cfg!(target_arch = "loongarch64") {
+        Some(Arch::LoongArch64)
+    } else {
+        None
+    };
+
+    /// Parse an `arch=` value; Rust target names are accepted too
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "x64" | "x86_64" | "amd64" => Ok(Arch::X64),
+            "ia32" | "x86" | "i386" => Ok(Arch::Ia32),
+            "aa64" | "aarch64" | "arm64" => Ok(Arch::Aa64),
+            "arm" => Ok(Arch::Arm),
+            "riscv64" => Ok(Arch::RiscV64),
+            "loongarch64" => Ok(Arch::LoongArch64),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Name as written in config.txt
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arch::X64 => "x64",
+            Arch::Ia32 => "ia32",
+            Arch::Aa64 => "aa64",
+            Arch::Arm => "arm",
+            Arch::RiscV64 => "riscv64",
+            Arch::LoongArch64 => "loongarch64",
+        }
+    }
+}
+
+/// UEFI specification revision, e.g. 2.7
+///
+/// `minor` is encoded as in the system table: tens for the minor version
+/// and units for the errata, so 2.3.1 is 31, 2.7 is 70 and 2.10 is 100.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Revision {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl Revision {
+    /// Parse `<major>[.<minor>[.<errata>]]`, e.g. `2`, `2.7` or `2.3.1`
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut parts = value.split('.');
+        let major = number(parts.next())?;
+        let minor = parts.next().map_or(Ok(0), |part| number(Some(part)))?;
+        let errata = parts.next().map_or(Ok(0), |part| number(Some(part)))?;
+        if parts.next().is_some() || minor > 999 || errata > 9 {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Revision { major, minor: minor * 10 + errata })
+    }
+}
+
+/// A version component: decimal digits only
+fn number(part: Option<&str>) -> Result<u16> {
+    match part {
+        Some(part) if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) => {
+            part.parse().map_err(|_| Error::InvalidArgument)
+        }
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+impl fmt::Display for Revision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor / 10)?;
+        if self.minor % 10 != 0 {
+            write!(f, ".{}", self.minor % 10)?;
+        }
+        Ok(())
+    }
+}
+
+/// What an entry needs from the machine before it is worth downloading
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Requirements {
+    /// Architecture the image was built for (`arch=`)
+    pub arch: Option<Arch>,
+    /// Lowest UEFI revision the firmware must implement (`min_fw=`)
+    pub min_fw: Option<Revision>,
+    /// Whether Secure Boot has to be enabled (`secureboot=required`)
+    pub secure_boot: bool,
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        *self == Requirements::default()
+    }
+
+    /// Why the image cannot run on `platform`, if it cannot
+    pub fn unmet(&self, platform: &Platform) -> Option<alloc::string::String> {
+        if let Some(arch) = self.arch.filter(|&arch| Some(arch) != platform.arch) {
+            let here = platform.arch.map_or("unknown", |arch| arch.as_str());
+            return Some(alloc::format!("image is {}, this machine runs {}", arch.as_str(), here));
+        }
+        if let Some(min_fw) = self.min_fw.filter(|&min_fw| min_fw > platform.uefi) {
+            return Some(alloc::format!("needs UEFI {}, firmware implements {}", min_fw, platform.uefi));
+        }
+        if self.secure_boot && !platform.secure_boot {
+            return Some(alloc::string::String::from("needs Secure Boot, which is off"));
+        }
+        None
+    }
+}
+
+/// What the running machine offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub arch: Option<Arch>,
+    pub uefi: Revision,
+    pub secure_boot: bool,
+}
+
+impl Platform {
+    /// Look at the live firmware
+    pub fn current() -> Self {
+        let revision = uefi::system::uefi_revision();
+        Platform {
+            arch: Arch::CURRENT,
+            uefi: Revision { major: revision.major(), minor: revision.minor() },
+            secure_boot: secure_boot_enabled(),
+        }
+    }
+}
+
+/// Whether the firmware enforces Secure Boot (the global `SecureBoot`
+/// variable; missing means a firmware without Secure Boot support)
+pub fn secure_boot_enabled() -> bool {
+    let mut buf = [0u8; 1];
+    matches!(
+        runtime::get_variable(cstr16!("SecureBoot"), &VariableVendor::GLOBAL_VARIABLE, &mut buf),
+        Ok((data, _)) if data == [1]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Arch::parse("AA64"), Ok(Arch::Aa64));
+        assert_eq!(Arch::parse("x86_64"), Ok(Arch::X64));
+        assert!(Arch::parse("sparc").is_err());
+
+        assert_eq!(Revision::parse("2.7"), Ok(Revision { major: 2, minor: 70 }));
+        assert_eq!(Revision::parse("2.3.1"), Ok(Revision { major: 2, minor: 31 }));
+        assert_eq!(Revision::parse("2"), Ok(Revision { major: 2, minor: 0 }));
+        assert!(Revision::parse("2.10").unwrap() > Revision::parse("2.9").unwrap());
+        assert_eq!(alloc::format!("{}", Revision::parse("2.10").unwrap()), "2.10");
+        assert_eq!(alloc::format!("{}", Revision::parse("2.3.1").unwrap()), "2.3.1");
+        for bad in ["", "2.", ".7", "2.x", "-2.7", "2.7.10", "2.7.1.1"] {
+            assert!(Revision::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_unmet() {
+        let platform = Platform {
+            arch: Some(Arch::X64),
+            uefi: Revision { major: 2, minor: 70 },
+            secure_boot: false,
+        };
+        assert_eq!(Requirements::default().unmet(&platform), None);
+
+        let fits = Requirements { arch: Some(Arch::X64), min_fw: Some(Revision { major: 2, minor: 70 }), secure_boot: false };
+        assert_eq!(fits.unmet(&platform), None);
+
+        let arm = Requirements { arch: Some(Arch::Aa64), ..Requirements::default() };
+        assert_eq!(arm.unmet(&platform).as_deref(), Some("image is aa64, this machine runs x64"));
+
+        let newer = Requirements { min_fw: Some(Revision { major: 2, minor: 100 }), ..Requirements::default() };
+        assert_eq!(newer.unmet(&platform).as_deref(), Some("needs UEFI 2.10, firmware implements 2.7"));
+
+        let signed = Requirements { secure_boot: true, ..Requirements::default() };
+        assert!(signed.unmet(&platform).is_some());
+        assert_eq!(signed.unmet(&Platform { secure_boot: true, ..platform }), None);
+    }
+}
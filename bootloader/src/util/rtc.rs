@@ -0,0 +1,193 @@
+use crate::util::{Error, Result};
+use core::fmt;
+use uefi::runtime::{self, Time, TimeParams};
+
+/// Date and time to the minute, as typed by `date set`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl DateTime {
+    /// Parse `YYYY-MM-DD` and `HH:MM`
+    ///
+    /// Years are limited to what the RTC can hold (1900-9999), and the day
+    /// has to exist in that month.
+    pub fn parse(date: &str, time: &str) -> Result<Self> {
+        let mut date_parts = date.split('-');
+        let year = number(date_parts.next(), 4)?;
+        let month = number(date_parts.next(), 2)?;
+        let day = number(date_parts.next(), 2)?;
+
+        let mut time_parts = time.split(':');
+        let hour = number(time_parts.next(), 2)?;
+        let minute = number(time_parts.next(), 2)?;
+
+        if date_parts.next().is_some() || time_parts.next().is_some() {
+            return Err(Error::InvalidArgument);
+        }
+        if !(1900..=9999).contains(&year)
+            || !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: hour as u8,
+            minute: minute as u8,
+        })
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute)
+    }
+}
+
+/// A field of exactly `digits` decimal digits
+fn number(part: Option<&str>, digits: usize) -> Result<u32> {
+    match part {
+        Some(part) if part.len() == digits && part.bytes().all(|b| b.is_ascii_digit()) => {
+            part.parse().map_err(|_| Error::InvalidArgument)
+        }
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Read the firmware clock
+pub fn now() -> Result<Time> {
+    runtime::get_time().map_err(|e| Error::Uefi(e.status()))
+}
+
+/// Set the firmware clock, keeping its time zone and daylight setting
+///
+/// Seconds start over at zero.
+pub fn set(to: &DateTime) -> Result<()> {
+    let current = now()?;
+    let time = Time::new(TimeParams {
+        year: to.year,
+        month: to.month,
+        day: to.day,
+        hour: to.hour,
+        minute: to.minute,
+        second: 0,
+        nanosecond: 0,
+        time_zone: current.time_zone(),
+        daylight: current.daylight(),
+    })
+    .map_err(|_| Error::InvalidArgument)?;
+
+    // Nothing else touches the clock while we run
+    unsafe { runtime::set_time(&time) }.map_err(|e| Error::Uefi(e.status()))
+}
+
+/// Seconds from 2000-01-01 00:00 to `time` on the same clock, for telling
+/// how far apart two readings are
+pub fn seconds(time: &Time) -> u64 {
+    let days = days_since_2000(u32::from(time.year()), u32::from(time.month()), u32::from(time.day()));
+    let of_day = u64::from(time.hour()) * 3600 + u64::from(time.minute()) * 60 + u64::from(time.second());
+    days * 86_400 + of_day
+}
+
+fn days_since_2000(year: u32, month: u32, day: u32) -> u64 {
+    let years = (2000..year).map(|year| if days_in_month(year, 2) == 29 { 366 } else { 365 });
+    let months = (1..month).map(|month| days_in_month(year, month));
+    (years.sum::<u32>() + months.sum::<u32>() + day.saturating_sub(1)) as u64
+}
+
+/// `YYYYMMDD-HHMMSS`, for file names that sort by time
+pub fn stamp(time: &Time) -> alloc::string::String {
+    alloc::format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        time.year(),
+        time.month(),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+/// `YYYY-MM-DD HH:MM:SS` with the zone when the firmware knows it
+pub fn format(time: &Time) -> alloc::string::String {
+    let mut out = alloc::format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        time.year(),
+        time.month(),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    );
+    // Minutes east of UTC in the spec's sign convention: local = UTC - zone
+    if let Some(zone) = time.time_zone() {
+        let east = -i32::from(zone);
+        let sign = if east < 0 { '-' } else { '+' };
+        out.push_str(&alloc::format!(" UTC{}{:02}:{:02}", sign, east.abs() / 60, east.abs() % 60));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parsed = DateTime::parse("2026-10-16", "14:05").unwrap();
+        assert_eq!(parsed, DateTime { year: 2026, month: 10, day: 16, hour: 14, minute: 5 });
+        assert_eq!(alloc::format!("{}", parsed), "2026-10-16 14:05");
+
+        assert!(DateTime::parse("2024-02-29", "00:00").is_ok());
+        assert!(DateTime::parse("2000-02-29", "23:59").is_ok());
+    }
+
+    #[test]
+    fn test_days_since_2000() {
+        assert_eq!(days_since_2000(2000, 1, 1), 0);
+        assert_eq!(days_since_2000(2000, 3, 1), 31 + 29);
+        assert_eq!(days_since_2000(2001, 1, 1), 366);
+        assert_eq!(days_since_2000(2026, 10, 16), 9785);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for (date, time) in [
+            ("2026-10-16", "24:00"),
+            ("2026-10-16", "12:60"),
+            ("2026-13-01", "12:00"),
+            ("2026-04-31", "12:00"),
+            ("2023-02-29", "12:00"),
+            ("1900-02-29", "12:00"),
+            ("1899-12-31", "12:00"),
+            ("26-10-16", "12:00"),
+            ("2026-10-16", "1:00"),
+            ("2026-10-16", "12:00:00"),
+            ("2026-10-16-01", "12:00"),
+            ("2026-+1-16", "12:00"),
+        ] {
+            assert_eq!(DateTime::parse(date, time), Err(Error::InvalidArgument), "{} {}", date, time);
+        }
+    }
+}
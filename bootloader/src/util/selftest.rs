@@ -0,0 +1,202 @@
+use crate::network::verify;
+use crate::storage::{config::Config, file, path};
+use crate::util::caps::{self, Capability};
+use crate::util::{ed25519, sha256, ucs2, ui};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use uefi::println;
+
+/// Directory of the scratch file for the ESP check, removed again
+/// afterwards
+const SCRATCH_DIR: &str = "\\EFI\\uefipxe";
+
+/// What a check found: a short note on success, the reason on failure
+type Outcome = core::result::Result<String, String>;
+
+/// One built-in check
+struct Check {
+    name: &'static str,
+    run: fn() -> Outcome,
+}
+
+const CHECKS: [Check; 6] = [
+    Check { name: "SHA-256 vectors", run: check_sha256 },
+    Check { name: "Verifier vectors", run: check_verifiers },
+    Check { name: "Config round-trip", run: check_config },
+    Check { name: "UCS-2 conversion", run: check_ucs2 },
+    Check { name: "ESP write/read", run: check_esp },
+    Check { name: "Firmware protocols", run: check_protocols },
+];
+
+/// Run every check and print the results
+///
+/// Meant as a first sanity check of a new build on new hardware: the hash
+/// code paths depend on the CPU, the rest on the firmware. Returns the
+/// number of failed checks.
+pub fn run() -> usize {
+    println!();
+    ui::heading("Self-test:");
+
+    let mut failed = 0;
+    for check in CHECKS.iter() {
+        match (check.run)() {
+            Ok(note) if note.is_empty() => println!("  [ OK ] {}", check.name),
+            Ok(note) => println!("  [ OK ] {} ({})", check.name, note),
+            Err(reason) => {
+                failed += 1;
+                println!("  [FAIL] {}: {}", check.name, reason);
+                crate::util::logger::log_entry(
+                    log::Level::Warn,
+                    &format!("Self-test {} failed: {}", check.name, reason),
+                );
+            }
+        }
+    }
+
+    println!();
+    match failed {
+        0 => println!("All {} checks passed", CHECKS.len()),
+        n => println!("{} of {} checks FAILED", n, CHECKS.len()),
+    }
+    println!();
+    failed
+}
+
+fn expect(what: &str, ok: bool) -> core::result::Result<(), String> {
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("{} is wrong", what))
+    }
+}
+
+fn check_sha256() -> Outcome {
+    expect(
+        "empty input",
+        verify::compute_sha256(b"") == "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+    )?;
+
+    // Many blocks, fed unevenly, so the accelerated path and the buffering
+    // between calls both run
+    let mut hasher = sha256::Sha256::new();
+    let block = [b'a'; 1000];
+    for i in 0..1000 {
+        let split = i % 97;
+        hasher.update(&block[..split]);
+        hasher.update(&block[split..]);
+    }
+    let digest: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    expect(
+        "million 'a'",
+        digest == "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+    )?;
+
+    Ok(String::from(sha256::backend()))
+}
+
+fn check_verifiers() -> Outcome {
+    let vectors = [
+        "sha512:ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+         2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+        "blake3:6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+    ];
+    for signature in vectors {
+        let (verifier, _) = verify::split(signature);
+        expect(verifier.key, verify::check(b"abc", signature).is_ok())?;
+    }
+
+    // RFC 8032 section 7.1, test 1
+    let key = unhex::<{ ed25519::PUBLIC_KEY_LEN }>("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+    let signature = unhex::<{ ed25519::SIGNATURE_LEN }>(
+        "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+    );
+    expect("ed25519", ed25519::verify(&key, b"", &signature) && !ed25519::verify(&key, b"x", &signature))?;
+
+    Ok(String::new())
+}
+
+fn unhex<const N: usize>(hex: &str) -> [u8; N] {
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap_or(0);
+    }
+    out
+}
+
+fn check_config() -> Outcome {
+    const SAMPLE: &str = "ip=10.0.0.5\ngateway=10.0.0.1\ndefault=1\n\
+        url=http://boot.example.com/a.efi\nsha256=a3b2c1d4e5f6abcd1234567890abcdef1234567890abcdef1234567890abcdef\n\
+        name=Production\ntags=prod,x86\nversion=7\n\
+        url=file://USB-KEY/rescue.efi\nblake3=6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85\n";
+
+    let config = Config::parse(SAMPLE).map_err(|e| format!("sample did not parse: {}", e))?;
+    expect("entry count", config.urls.len() == 2)?;
+    expect("default entry", config.default_index == Some(1))?;
+    expect("entry version", config.meta.first().and_then(|meta| meta.version) == Some(7))?;
+
+    let first = config.serialize().map_err(|e| format!("serialize: {}", e))?;
+    let reparsed = Config::parse(&first).map_err(|e| format!("serialized form did not parse: {}", e))?;
+    let second = reparsed.serialize().map_err(|e| format!("serialize: {}", e))?;
+    expect("round-trip", first == second)?;
+
+    Ok(format!("{} bytes", first.len()))
+}
+
+fn check_ucs2() -> Outcome {
+    // Plain and non-ASCII BMP text survives the trip to UCS-2 and back
+    for text in ["\\EFI\\uefipxe\\config.txt", "Entrée ünïcode"] {
+        let wide = ucs2::to_cstring16(text).map_err(|_| format!("'{}' did not convert", text))?;
+        let bytes: Vec<u8> = wide.as_slice_with_nul().iter().flat_map(|c| u16::from(*c).to_le_bytes()).collect();
+        expect(text, ucs2::decode_le_bytes(&bytes).as_deref() == Ok(text))?;
+    }
+
+    // Characters outside the BMP have no UCS-2 form
+    expect("non-BMP rejection", ucs2::to_cstring16("image-\u{1F680}.efi").is_err())?;
+
+    // Odd lengths and lone surrogates are not text
+    expect("odd length", ucs2::decode_le_bytes(&[b'a', 0, b'b']).is_err())?;
+    expect("lone surrogate", ucs2::decode_le_bytes(&[0x00, 0xd8, b'a', 0]).is_err())?;
+    expect("empty options", ucs2::decode_le_bytes(&[0, 0]).as_deref() == Ok(""))?;
+
+    // Paths are normalized before conversion
+    expect("path separators", path::normalize("/EFI//uefipxe/./a.txt").as_deref() == Ok("\\EFI\\uefipxe\\a.txt"))?;
+    expect("invalid path name", path::normalize("\\EFI\\a:b").is_err())?;
+
+    Ok(String::new())
+}
+
+fn check_esp() -> Outcome {
+    let pattern: Vec<u8> = (0..4096u32).map(|i| (i * 7 + i / 256) as u8).collect();
+    let scratch = file::unique_path(SCRATCH_DIR, "selftest", ".dat").map_err(|e| format!("scratch name: {}", e))?;
+    file::atomic_write(&scratch, &pattern).map_err(|e| format!("write {}: {}", scratch, e))?;
+
+    let read = file::read_file(&scratch);
+    let removed = file::delete(&scratch);
+
+    let data = read.map_err(|e| format!("read back: {}", e))?;
+    expect("data read back", data.as_slice() == pattern.as_slice())?;
+    removed.map_err(|e| format!("delete: {}", e))?;
+
+    Ok(format!("{} bytes", pattern.len()))
+}
+
+fn check_protocols() -> Outcome {
+    caps::probe();
+
+    // Without HTTP nothing can be booted; the rest only limits features
+    if !caps::has(Capability::Http) {
+        return Err(format!("{} missing: {}", Capability::Http.name(), Capability::Http.impact()));
+    }
+
+    let missing: Vec<&str> = Capability::ALL
+        .iter()
+        .filter(|cap| !caps::has(**cap))
+        .map(|cap| cap.name())
+        .collect();
+    if missing.is_empty() {
+        Ok(String::from("all available"))
+    } else {
+        Ok(format!("missing: {}", missing.join(", ")))
+    }
+}
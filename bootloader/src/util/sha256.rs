@@ -0,0 +1,385 @@
+use sha2::digest::generic_array::GenericArray;
+
+/// Size of a SHA-256 digest in bytes
+pub const DIGEST_LEN: usize = 32;
+
+const BLOCK_LEN: usize = 64;
+
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+    0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256 that uses the CPU's SHA extensions when present
+///
+/// The sha2 crate detects SHA-NI through `cpufeatures`, which reports no SIMD
+/// features at all on UEFI targets, so on its own it always hashes in
+/// software. UEFI firmware has SSE enabled on x86_64, so we probe CPUID
+/// ourselves and only hand blocks to sha2 when the extensions are missing.
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: [u8; BLOCK_LEN],
+    buffered: usize,
+    length: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    /// Start a new hash
+    pub fn new() -> Self {
+        Sha256 {
+            state: INITIAL_STATE,
+            buffer: [0; BLOCK_LEN],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    /// Feed more data
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+
+        // Top up a partial block first
+        if self.buffered > 0 {
+            let take = (BLOCK_LEN - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+
+            if self.buffered < BLOCK_LEN {
+                return;
+            }
+            let block = self.buffer;
+            compress(&mut self.state, core::slice::from_ref(&block));
+            self.buffered = 0;
+        }
+
+        // Whole blocks straight from the input
+        let (blocks, rest) = data.split_at(data.len() - data.len() % BLOCK_LEN);
+        if !blocks.is_empty() {
+            // SAFETY: [u8; 64] has the same layout as 64 bytes of a slice
+            let blocks = unsafe {
+                core::slice::from_raw_parts(blocks.as_ptr() as *const [u8; BLOCK_LEN], blocks.len() / BLOCK_LEN)
+            };
+            compress(&mut self.state, blocks);
+        }
+
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    /// Finish and return the digest
+    pub fn finalize(mut self) -> [u8; DIGEST_LEN] {
+        let bit_length = self.length.wrapping_mul(8);
+
+        // Padding: 0x80, zeros, then the length in bits (big-endian)
+        let mut block = [0u8; BLOCK_LEN];
+        block[..self.buffered].copy_from_slice(&self.buffer[..self.buffered]);
+        block[self.buffered] = 0x80;
+        if self.buffered >= BLOCK_LEN - 8 {
+            compress(&mut self.state, core::slice::from_ref(&block));
+            block = [0u8; BLOCK_LEN];
+        }
+        block[BLOCK_LEN - 8..].copy_from_slice(&bit_length.to_be_bytes());
+        compress(&mut self.state, core::slice::from_ref(&block));
+
+        let mut digest = [0u8; DIGEST_LEN];
+        for (out, word) in digest.chunks_exact_mut(4).zip(self.state.iter()) {
+            out.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+/// Hash data in one go
+pub fn digest(data: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// HMAC-SHA256 with a key, prepared once for many messages
+struct Hmac {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl Hmac {
+    fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            block[..DIGEST_LEN].copy_from_slice(&digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        let mut inner = Sha256::new();
+        inner.update(&block.map(|byte| byte ^ 0x36));
+        let mut outer = Sha256::new();
+        outer.update(&block.map(|byte| byte ^ 0x5c));
+        crate::util::wipe::wipe(&mut block);
+        Hmac { inner, outer }
+    }
+
+    fn mac(&self, message: &[u8]) -> [u8; DIGEST_LEN] {
+        let mut inner = self.inner.clone();
+        inner.update(message);
+        let mut outer = self.outer.clone();
+        outer.update(&inner.finalize());
+        outer.finalize()
+    }
+}
+
+/// HMAC-SHA256 of `message` (RFC 2104)
+pub fn hmac(key: &[u8], message: &[u8]) -> [u8; DIGEST_LEN] {
+    Hmac::new(key).mac(message)
+}
+
+/// PBKDF2-HMAC-SHA256 (RFC 8018) of `password`, one digest long
+pub fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> [u8; DIGEST_LEN] {
+    let hmac = Hmac::new(password);
+
+    // Block 1: the salt followed by the block index, big-endian
+    let mut first = alloc::vec::Vec::with_capacity(salt.len() + 4);
+    first.extend_from_slice(salt);
+    first.extend_from_slice(&1u32.to_be_bytes());
+
+    let mut u = hmac.mac(&first);
+    let mut key = u;
+    for _ in 1..iterations {
+        u = hmac.mac(&u);
+        for (out, byte) in key.iter_mut().zip(u.iter()) {
+            *out ^= byte;
+        }
+    }
+    key
+}
+
+/// Name of the implementation in use, for status output
+pub fn backend() -> &'static str {
+    if accelerated() {
+        "SHA-NI"
+    } else {
+        "software"
+    }
+}
+
+fn compress(state: &mut [u32; 8], blocks: &[[u8; BLOCK_LEN]]) {
+    #[cfg(target_arch = "x86_64")]
+    if accelerated() {
+        // SAFETY: the CPU supports every feature the function is built for
+        unsafe { shani::compress(state, blocks) };
+        return;
+    }
+
+    for block in blocks {
+        sha2::compress256(state, core::slice::from_ref(GenericArray::from_slice(block)));
+    }
+}
+
+/// Result of the CPU feature probe (None = not probed yet)
+static mut ACCELERATED: Option<bool> = None;
+
+/// Whether SHA-NI can be used
+pub fn accelerated() -> bool {
+    if let Some(accelerated) = unsafe { ACCELERATED } {
+        return accelerated;
+    }
+
+    let accelerated = detect();
+    unsafe {
+        ACCELERATED = Some(accelerated);
+    }
+    accelerated
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> bool {
+    use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+    if __cpuid(0).eax < 7 {
+        return false;
+    }
+    let (basic, extended) = (__cpuid(1), __cpuid_count(7, 0));
+
+    let sse2 = basic.edx & (1 << 26) != 0;
+    let ssse3 = basic.ecx & (1 << 9) != 0;
+    let sse41 = basic.ecx & (1 << 19) != 0;
+    let sha = extended.ebx & (1 << 29) != 0;
+    sse2 && ssse3 && sse41 && sha
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn detect() -> bool {
+    false
+}
+
+#[cfg(target_arch = "x86_64")]
+mod shani {
+    use core::arch::x86_64::*;
+
+    /// Round constants, four per row in the order the SHA-NI rounds take them
+    const K: [[u32; 4]; 16] = [
+        [0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5],
+        [0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5],
+        [0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3],
+        [0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174],
+        [0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc],
+        [0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da],
+        [0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7],
+        [0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967],
+        [0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13],
+        [0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85],
+        [0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3],
+        [0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070],
+        [0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5],
+        [0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3],
+        [0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208],
+        [0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2],
+    ];
+
+    /// Four rounds: two `sha256rnds2` steps on the message words plus constants
+    #[inline]
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    unsafe fn rounds4(abef: &mut __m128i, cdgh: &mut __m128i, w: __m128i, i: usize) {
+        let k = _mm_loadu_si128(K[i].as_ptr() as *const __m128i);
+        let wk = _mm_add_epi32(w, k);
+        *cdgh = _mm_sha256rnds2_epu32(*cdgh, *abef, wk);
+        *abef = _mm_sha256rnds2_epu32(*abef, *cdgh, _mm_shuffle_epi32(wk, 0x0E));
+    }
+
+    /// Next four message schedule words from the previous sixteen
+    #[inline]
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    unsafe fn schedule(w0: __m128i, w1: __m128i, w2: __m128i, w3: __m128i) -> __m128i {
+        let t = _mm_add_epi32(_mm_sha256msg1_epu32(w0, w1), _mm_alignr_epi8(w3, w2, 4));
+        _mm_sha256msg2_epu32(t, w3)
+    }
+
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    pub unsafe fn compress(state: &mut [u32; 8], blocks: &[[u8; 64]]) {
+        // Byte swap of each 32-bit word (message words are big-endian)
+        let bswap = _mm_set_epi64x(0x0c0d0e0f08090a0b, 0x0405060700010203);
+
+        // SHA-NI keeps the state as ABEF/CDGH rather than ABCD/EFGH
+        let dcba = _mm_loadu_si128(state.as_ptr() as *const __m128i);
+        let hgfe = _mm_loadu_si128(state.as_ptr().add(4) as *const __m128i);
+        let cdab = _mm_shuffle_epi32(dcba, 0xB1);
+        let efgh = _mm_shuffle_epi32(hgfe, 0x1B);
+        let mut abef = _mm_alignr_epi8(cdab, efgh, 8);
+        let mut cdgh = _mm_blend_epi16(efgh, cdab, 0xF0);
+
+        for block in blocks {
+            let (abef_start, cdgh_start) = (abef, cdgh);
+            let p = block.as_ptr() as *const __m128i;
+            let mut w = [
+                _mm_shuffle_epi8(_mm_loadu_si128(p), bswap),
+                _mm_shuffle_epi8(_mm_loadu_si128(p.add(1)), bswap),
+                _mm_shuffle_epi8(_mm_loadu_si128(p.add(2)), bswap),
+                _mm_shuffle_epi8(_mm_loadu_si128(p.add(3)), bswap),
+            ];
+
+            for i in 0..16 {
+                if i >= 4 {
+                    w[i % 4] = schedule(w[i % 4], w[(i + 1) % 4], w[(i + 2) % 4], w[(i + 3) % 4]);
+                }
+                rounds4(&mut abef, &mut cdgh, w[i % 4], i);
+            }
+
+            abef = _mm_add_epi32(abef, abef_start);
+            cdgh = _mm_add_epi32(cdgh, cdgh_start);
+        }
+
+        let feba = _mm_shuffle_epi32(abef, 0x1B);
+        let dchg = _mm_shuffle_epi32(cdgh, 0xB1);
+        let dcba = _mm_blend_epi16(feba, dchg, 0xF0);
+        let hgef = _mm_alignr_epi8(dchg, feba, 8);
+        _mm_storeu_si128(state.as_mut_ptr() as *mut __m128i, dcba);
+        _mm_storeu_si128(state.as_mut_ptr().add(4) as *mut __m128i, hgef);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+
+    fn reference(data: &[u8]) -> [u8; DIGEST_LEN] {
+        sha2::Sha256::digest(data).into()
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        let empty = digest(b"");
+        assert_eq!(empty[..4], [0xe3, 0xb0, 0xc4, 0x42]);
+        assert_eq!(digest(b"hello"), reference(b"hello"));
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        // Lengths around the padding boundaries and several blocks
+        let data: alloc::vec::Vec<u8> = (0..1000u32).map(|i| (i * 7 + 3) as u8).collect();
+        for len in [1, 55, 56, 63, 64, 65, 119, 128, 1000] {
+            assert_eq!(digest(&data[..len]), reference(&data[..len]), "length {}", len);
+        }
+    }
+
+    fn hex(digest: &[u8]) -> alloc::string::String {
+        digest.iter().map(|byte| alloc::format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hex(&hmac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than a block are hashed first (test case 6)
+        assert_eq!(
+            hex(&hmac(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2() {
+        assert_eq!(
+            hex(&pbkdf2(b"password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex(&pbkdf2(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let data: alloc::vec::Vec<u8> = (0..300u32).map(|i| i as u8).collect();
+        let mut hasher = Sha256::new();
+        for chunk in data.chunks(17) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize(), reference(&data));
+    }
+
+    #[test]
+    fn test_software_fallback() {
+        let data = [0x5au8; 200];
+        let mut state = INITIAL_STATE;
+        let mut soft = INITIAL_STATE;
+        let blocks = [[0x5au8; 64], [0x5au8; 64]];
+        compress(&mut state, &blocks);
+        for block in blocks.iter() {
+            sha2::compress256(&mut soft, core::slice::from_ref(GenericArray::from_slice(block)));
+        }
+        assert_eq!(state, soft);
+        assert_eq!(digest(&data), reference(&data));
+    }
+}
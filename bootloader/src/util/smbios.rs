@@ -0,0 +1,171 @@
+use heapless::String;
+use uefi::table::cfg::ConfigTableEntry;
+
+/// Maximum length kept for SMBIOS strings
+const MAX_FIELD_LEN: usize = 64;
+
+/// SMBIOS structure type of the System Information table
+const TYPE_SYSTEM_INFO: u8 = 1;
+/// SMBIOS end-of-table structure type
+const TYPE_END: u8 = 127;
+
+/// Machine identity from the SMBIOS System Information (type 1) table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemInfo {
+    pub manufacturer: String<MAX_FIELD_LEN>,
+    pub product: String<MAX_FIELD_LEN>,
+    pub serial: String<MAX_FIELD_LEN>,
+    /// System UUID in wire order (first three fields little-endian)
+    pub uuid: [u8; 16],
+}
+
+impl SystemInfo {
+    /// Format the UUID in its canonical text form
+    pub fn uuid_string(&self) -> String<36> {
+        use core::fmt::Write;
+
+        let u = &self.uuid;
+        let mut s = String::new();
+        let _ = write!(
+            s,
+            "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+            u[3], u[2], u[1], u[0], u[5], u[4], u[7], u[6],
+            u[8], u[9], u[10], u[11], u[12], u[13], u[14], u[15]
+        );
+        s
+    }
+}
+
+/// Find the SMBIOS structure table through the system configuration table
+///
+/// Prefers the 64-bit SMBIOS 3 entry point and falls back to SMBIOS 2.
+fn structure_table() -> Option<&'static [u8]> {
+    let (addr, len) = uefi::system::with_config_table(|entries| {
+        let smbios3 = entries.iter().find(|e| e.guid == ConfigTableEntry::SMBIOS3_GUID);
+        if let Some(entry) = smbios3 {
+            // SMBIOS 3 entry point: "_SM3_", max size @0x0C, address @0x10
+            let ep = unsafe { core::slice::from_raw_parts(entry.address as *const u8, 0x18) };
+            if &ep[..5] == b"_SM3_" {
+                let len = u32::from_le_bytes([ep[0x0C], ep[0x0D], ep[0x0E], ep[0x0F]]) as usize;
+                let mut addr = [0u8; 8];
+                addr.copy_from_slice(&ep[0x10..0x18]);
+                return Some((u64::from_le_bytes(addr) as usize, len));
+            }
+        }
+
+        let smbios = entries.iter().find(|e| e.guid == ConfigTableEntry::SMBIOS_GUID)?;
+        // SMBIOS 2 entry point: "_SM_", length @0x16, address @0x18
+        let ep = unsafe { core::slice::from_raw_parts(smbios.address as *const u8, 0x1F) };
+        if &ep[..4] != b"_SM_" {
+            return None;
+        }
+        let len = u16::from_le_bytes([ep[0x16], ep[0x17]]) as usize;
+        let addr = u32::from_le_bytes([ep[0x18], ep[0x19], ep[0x1A], ep[0x1B]]) as usize;
+        Some((addr, len))
+    })?;
+
+    if addr == 0 || len == 0 {
+        return None;
+    }
+
+    Some(unsafe { core::slice::from_raw_parts(addr as *const u8, len) })
+}
+
+/// Read the System Information table of this machine
+pub fn system_info() -> Option<SystemInfo> {
+    parse_system_info(structure_table()?)
+}
+
+/// Extract the System Information table from a raw structure table
+pub fn parse_system_info(table: &[u8]) -> Option<SystemInfo> {
+    let mut offset = 0;
+
+    while offset + 4 <= table.len() {
+        let kind = table[offset];
+        let formatted_len = table[offset + 1] as usize;
+        if formatted_len < 4 || offset + formatted_len > table.len() {
+            return None;
+        }
+
+        let formatted = &table[offset..offset + formatted_len];
+        let strings_start = offset + formatted_len;
+
+        // The string set ends with a double NUL
+        let strings_len = table[strings_start..]
+            .windows(2)
+            .position(|w| w == [0, 0])?;
+        let strings = &table[strings_start..strings_start + strings_len];
+
+        if kind == TYPE_SYSTEM_INFO && formatted_len >= 0x19 {
+            let mut info = SystemInfo::default();
+            copy_string(&mut info.manufacturer, strings, formatted[4]);
+            copy_string(&mut info.product, strings, formatted[5]);
+            copy_string(&mut info.serial, strings, formatted[7]);
+            info.uuid.copy_from_slice(&formatted[8..24]);
+            return Some(info);
+        }
+
+        if kind == TYPE_END {
+            return None;
+        }
+
+        offset = strings_start + strings_len + 2;
+    }
+
+    None
+}
+
+/// Copy the 1-based string `index` of a structure's string set
+fn copy_string(dst: &mut String<MAX_FIELD_LEN>, strings: &[u8], index: u8) {
+    if index == 0 {
+        return;
+    }
+
+    if let Some(raw) = strings.split(|&b| b == 0).nth(index as usize - 1) {
+        for &b in raw.iter().take(MAX_FIELD_LEN) {
+            let c = if b.is_ascii_graphic() || b == b' ' { b as char } else { '?' };
+            let _ = dst.push(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BIOS (type 0) followed by System Information (type 1) and end marker
+    fn build_table() -> heapless::Vec<u8, 256> {
+        let mut t = heapless::Vec::new();
+        // Type 0, length 4, handle 0, one string
+        t.extend_from_slice(&[0, 4, 0, 0]).unwrap();
+        t.extend_from_slice(b"Vendor BIOS\0\0").unwrap();
+        // Type 1, length 0x1B, handle 1
+        t.extend_from_slice(&[1, 0x1B, 1, 0, 1, 2, 0, 3]).unwrap();
+        t.extend_from_slice(&[
+            0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66,
+            0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF,
+        ]).unwrap();
+        t.extend_from_slice(&[6, 0, 0]).unwrap();
+        t.extend_from_slice(b"Acme\0Server 9000\0SN123\0\0").unwrap();
+        // End of table
+        t.extend_from_slice(&[127, 4, 2, 0, 0, 0]).unwrap();
+        t
+    }
+
+    #[test]
+    fn test_parse_system_info() {
+        let table = build_table();
+        let info = parse_system_info(&table).unwrap();
+        assert_eq!(info.manufacturer.as_str(), "Acme");
+        assert_eq!(info.product.as_str(), "Server 9000");
+        assert_eq!(info.serial.as_str(), "SN123");
+        assert_eq!(info.uuid_string().as_str(), "00112233-4455-6677-8899-AABBCCDDEEFF");
+    }
+
+    #[test]
+    fn test_parse_missing_system_info() {
+        let table = [0u8, 4, 0, 0, 0, 0, 127, 4, 1, 0, 0, 0];
+        assert!(parse_system_info(&table).is_none());
+        assert!(parse_system_info(&[1, 2]).is_none());
+    }
+}
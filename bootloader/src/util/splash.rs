@@ -0,0 +1,232 @@
+use crate::util::global::Global;
+use crate::util::logger::{self, Echo};
+use crate::util::ui;
+use core::fmt::Write;
+use uefi::boot::{self, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol};
+use uefi::proto::console::gop::{BltOp, BltPixel, GraphicsOutput};
+use uefi::proto::console::text::Color;
+
+/// Bar width, as a percentage of the screen width
+const BAR_WIDTH_PERCENT: usize = 60;
+
+/// Bar height and border, in pixels
+const BAR_HEIGHT: usize = 16;
+const BORDER: usize = 2;
+
+/// Text rows between the title or stage line and the bar, at mid-screen
+const TEXT_GAP_ROWS: usize = 3;
+
+/// With no size announced, the marker moves one step per this many bytes
+const UNKNOWN_SIZE_STEP: usize = 64 * 1024;
+
+/// Marker width with no size announced, as a fraction of the bar
+const UNKNOWN_SIZE_MARKER: usize = 5;
+
+/// The progress screen while it is up
+struct Splash {
+    gop: ScopedProtocol<GraphicsOutput>,
+    /// Inside of the bar, in pixels
+    x: usize,
+    y: usize,
+    width: usize,
+    /// Text mode size, for placing the title and stage lines
+    columns: usize,
+    rows: usize,
+    /// Size announced for the current transfer
+    total: Option<usize>,
+    /// Echo level to go back to when the screen is taken down
+    echo: Echo,
+}
+
+/// Set while a boot runs without anyone at the keyboard
+static UNATTENDED: Global<bool> = Global::new();
+
+static SPLASH: Global<Splash> = Global::new();
+
+/// Shows the progress screen while alive; dropping it goes back to text
+pub struct Shown(());
+
+impl Drop for Shown {
+    fn drop(&mut self) {
+        hide();
+    }
+}
+
+/// Mark the boots that follow as unattended (menu timeout, idle prompt,
+/// startup script) or not
+pub fn set_unattended(unattended: bool) {
+    UNATTENDED.set(unattended);
+}
+
+/// Replace the boot output with a progress screen titled `title`
+///
+/// Only for unattended boots with `splash=true`, and never in plain mode,
+/// which is meant for serial capture. Without a usable framebuffer this
+/// does nothing and the text output carries on as usual.
+pub fn show(title: &str) -> Option<Shown> {
+    let enabled = crate::storage::with_config(|config| config.splash).unwrap_or(false);
+    let unattended = UNATTENDED.with(|unattended| *unattended).unwrap_or(false);
+    if !unattended || !enabled || ui::is_plain() || is_shown() {
+        return None;
+    }
+
+    // Shared access: an exclusive open would disconnect the text console
+    let handle = boot::get_handle_for_protocol::<GraphicsOutput>().ok()?;
+    let mut gop = unsafe {
+        boot::open_protocol::<GraphicsOutput>(
+            OpenProtocolParams { handle, agent: boot::image_handle(), controller: None },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+    .ok()?;
+
+    let (screen_width, screen_height) = gop.current_mode_info().resolution();
+    let width = screen_width * BAR_WIDTH_PERCENT / 100;
+    let x = (screen_width - width) / 2;
+    let y = screen_height.saturating_sub(BAR_HEIGHT) / 2;
+
+    let (columns, rows) = uefi::system::with_stdout(|stdout| {
+        let _ = stdout.set_color(Color::LightGray, Color::Black);
+        let _ = stdout.clear();
+        let _ = stdout.enable_cursor(false);
+        match stdout.current_mode() {
+            Ok(Some(mode)) => (mode.columns(), mode.rows()),
+            _ => (80, 25),
+        }
+    });
+
+    // Border, then the empty inside
+    let _ = gop.blt(BltOp::VideoFill {
+        color: BltPixel::new(0xa0, 0xa0, 0xa0),
+        dest: (x.saturating_sub(BORDER), y.saturating_sub(BORDER)),
+        dims: (width + 2 * BORDER, BAR_HEIGHT + 2 * BORDER),
+    });
+    let _ = gop.blt(BltOp::VideoFill { color: BltPixel::new(0, 0, 0), dest: (x, y), dims: (width, BAR_HEIGHT) });
+
+    let echo = logger::echo();
+    logger::set_echo(Echo::Quiet);
+    SPLASH.set(Splash { gop, x, y, width, columns, rows, total: None, echo });
+    write_centered(columns, (rows / 2).saturating_sub(TEXT_GAP_ROWS), title);
+    Some(Shown(()))
+}
+
+/// Whether the progress screen is up
+pub fn is_shown() -> bool {
+    SPLASH.with(|_| ()).is_some()
+}
+
+/// Take the progress screen down, leaving a clear text console
+fn hide() {
+    let Some(splash) = SPLASH.take() else {
+        return;
+    };
+    logger::set_echo(splash.echo);
+    uefi::system::with_stdout(|stdout| {
+        let _ = stdout.clear();
+        let _ = stdout.enable_cursor(true);
+    });
+}
+
+/// A transfer of `total` bytes starts
+pub fn expect(total: usize) {
+    SPLASH.with_mut(|splash| {
+        splash.total = Some(total).filter(|&total| total > 0);
+        splash.fill(0, splash.width, BltPixel::new(0, 0, 0));
+    });
+}
+
+/// `bytes` of the current transfer have arrived
+pub fn update(bytes: usize) {
+    SPLASH.with_mut(|splash| {
+        let (start, len) = match splash.total {
+            Some(total) => (0, filled(bytes, total, splash.width)),
+            None => marker(bytes, splash.width),
+        };
+        if splash.total.is_none() {
+            splash.fill(0, splash.width, BltPixel::new(0, 0, 0));
+        }
+        splash.fill(start, len, BltPixel::new(0x30, 0x80, 0xe0));
+    });
+}
+
+/// The boot moved on to `stage` (e.g. "Verifying"), shown under the bar
+pub fn stage(stage: &str) {
+    let placed = SPLASH.with_mut(|splash| {
+        splash.fill(0, splash.width, BltPixel::new(0x30, 0xa0, 0x50));
+        (splash.columns, splash.rows / 2 + TEXT_GAP_ROWS)
+    });
+    if let Some((columns, row)) = placed {
+        write_centered(columns, row, stage);
+    }
+}
+
+impl Splash {
+    /// Paint `len` pixels of the bar from `start`
+    fn fill(&mut self, start: usize, len: usize, color: BltPixel) {
+        if len == 0 {
+            return;
+        }
+        let _ = self.gop.blt(BltOp::VideoFill { color, dest: (self.x + start, self.y), dims: (len, BAR_HEIGHT) });
+    }
+}
+
+/// Write `text` centered on text row `row` of a `columns` wide console,
+/// replacing what was there
+fn write_centered(columns: usize, row: usize, text: &str) {
+    let text: alloc::string::String = text.chars().take(columns.saturating_sub(2)).collect();
+    let column = (columns - text.chars().count()) / 2;
+    uefi::system::with_stdout(|stdout| {
+        let _ = stdout.set_cursor_position(0, row);
+        let _ = write!(stdout, "{:width$}", "", width = columns - 1);
+        let _ = stdout.set_cursor_position(column, row);
+        let _ = write!(stdout, "{}", text);
+    });
+}
+
+/// Pixels of a `width` bar filled after `bytes` of `total`
+fn filled(bytes: usize, total: usize, width: usize) -> usize {
+    if total == 0 {
+        return 0;
+    }
+    (bytes.min(total) as u64 * width as u64 / total as u64) as usize
+}
+
+/// Where the moving marker is after `bytes` of a transfer of unknown size,
+/// as start and length in a `width` bar
+fn marker(bytes: usize, width: usize) -> (usize, usize) {
+    let len = (width / UNKNOWN_SIZE_MARKER).max(1);
+    let travel = width.saturating_sub(len);
+    if travel == 0 {
+        return (0, width);
+    }
+    // Back and forth, one pixel per step
+    let step = (bytes / UNKNOWN_SIZE_STEP) % (2 * travel);
+    let start = if step < travel { step } else { 2 * travel - step };
+    (start, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filled() {
+        assert_eq!(filled(0, 1000, 600), 0);
+        assert_eq!(filled(500, 1000, 600), 300);
+        assert_eq!(filled(1000, 1000, 600), 600);
+        // A server that sends more than it announced
+        assert_eq!(filled(2000, 1000, 600), 600);
+        assert_eq!(filled(10, 0, 600), 0);
+    }
+
+    #[test]
+    fn test_marker() {
+        assert_eq!(marker(0, 500), (0, 100));
+        assert_eq!(marker(UNKNOWN_SIZE_STEP * 10, 500), (10, 100));
+        // Turns around at the end of the bar and comes back
+        assert_eq!(marker(UNKNOWN_SIZE_STEP * 400, 500), (400, 100));
+        assert_eq!(marker(UNKNOWN_SIZE_STEP * 410, 500), (390, 100));
+        assert_eq!(marker(UNKNOWN_SIZE_STEP * 800, 500), (0, 100));
+        assert_eq!(marker(123, 0), (0, 0));
+    }
+}
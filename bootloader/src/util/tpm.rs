@@ -0,0 +1,442 @@
+use crate::util::caps::{self, Capability};
+use crate::util::{Error, Result};
+use alloc::vec::Vec;
+use uefi::boot;
+use uefi::proto::tcg::v2::{HashLogExtendEventFlags, PcrEventInputs, Tcg};
+use uefi::proto::tcg::{EventType, PcrIndex};
+
+// TPM 2.0 structure tags, handles, command codes and algorithms used here
+// (TPM 2.0 Library, Part 2)
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_ST_SESSIONS: u16 = 0x8002;
+const TPM_RH_OWNER: u32 = 0x4000_0001;
+const TPM_RH_NULL: u32 = 0x4000_0007;
+const TPM_RS_PW: u32 = 0x4000_0009;
+const TPM_CC_CREATE_PRIMARY: u32 = 0x0131;
+const TPM_CC_CREATE: u32 = 0x0153;
+const TPM_CC_LOAD: u32 = 0x0157;
+const TPM_CC_UNSEAL: u32 = 0x015E;
+const TPM_CC_FLUSH_CONTEXT: u32 = 0x0165;
+const TPM_CC_START_AUTH_SESSION: u32 = 0x0176;
+const TPM_CC_POLICY_PCR: u32 = 0x017F;
+const TPM_CC_POLICY_GET_DIGEST: u32 = 0x0189;
+const TPM_ALG_AES: u16 = 0x0006;
+const TPM_ALG_KEYEDHASH: u16 = 0x0008;
+const TPM_ALG_SHA256: u16 = 0x000B;
+const TPM_ALG_NULL: u16 = 0x0010;
+const TPM_ALG_ECC: u16 = 0x0023;
+const TPM_ALG_CFB: u16 = 0x0043;
+const TPM_ECC_NIST_P256: u16 = 0x0003;
+const TPM_SE_POLICY: u8 = 0x01;
+const TPM_SE_TRIAL: u8 = 0x03;
+
+// Object attributes
+const FIXED_TPM: u32 = 1 << 1;
+const FIXED_PARENT: u32 = 1 << 4;
+const SENSITIVE_DATA_ORIGIN: u32 = 1 << 5;
+const USER_WITH_AUTH: u32 = 1 << 6;
+const NO_DA: u32 = 1 << 10;
+const RESTRICTED: u32 = 1 << 16;
+const DECRYPT: u32 = 1 << 17;
+
+/// PCRs in the SHA256 bank a sealed object can be bound to
+pub const MAX_PCR: u8 = 23;
+
+/// Largest secret a sealed data object holds (MAX_SYM_DATA)
+pub const MAX_SECRET_LEN: usize = 128;
+
+/// PCR the effective configuration is measured into (the first PCR the
+/// PC Client spec leaves to the OS loader)
+pub const CONFIG_PCR: u32 = 8;
+
+/// Largest command or response we exchange with the TPM
+const BUFFER_SIZE: usize = 4096;
+
+/// A TPM command being marshalled (big-endian, as on the wire)
+struct Command {
+    buf: Vec<u8>,
+}
+
+impl Command {
+    fn new(tag: u16, code: u32) -> Self {
+        let mut cmd = Command { buf: Vec::with_capacity(256) };
+        cmd.u16(tag).u32(0).u32(code);
+        cmd
+    }
+
+    fn u8(&mut self, v: u8) -> &mut Self {
+        self.buf.push(v);
+        self
+    }
+
+    fn u16(&mut self, v: u16) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    /// Size-prefixed byte buffer (TPM2B_*)
+    fn sized(&mut self, data: &[u8]) -> &mut Self {
+        self.u16(data.len() as u16);
+        self.buf.extend_from_slice(data);
+        self
+    }
+
+    /// Authorization area with a single session and empty nonce and HMAC
+    ///
+    /// `TPM_RS_PW` with an empty password, or a policy session whose policy
+    /// does not involve an auth value.
+    fn auth(&mut self, session: u32) -> &mut Self {
+        self.u32(9).u32(session).u16(0).u8(0).u16(0)
+    }
+
+    /// Start of a nested size-prefixed structure (TPM2B_PUBLIC and friends)
+    fn begin_sized(&mut self) -> usize {
+        self.u16(0);
+        self.buf.len()
+    }
+
+    fn end_sized(&mut self, start: usize) -> &mut Self {
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start - 2..start].copy_from_slice(&len.to_be_bytes());
+        self
+    }
+
+    /// Selection of SHA256 PCRs (TPML_PCR_SELECTION with one bank)
+    fn pcr_selection(&mut self, pcrs: u32) -> &mut Self {
+        let select = pcrs.to_le_bytes();
+        self.u32(1).u16(TPM_ALG_SHA256).u8(3).u8(select[0]).u8(select[1]).u8(select[2])
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[2..6].copy_from_slice(&len.to_be_bytes());
+        self.buf
+    }
+}
+
+/// Reads a TPM response
+struct Response<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Response<'a> {
+    /// Check the header and skip to the handle area
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        let mut rsp = Response { data, pos: 0 };
+        let _tag = rsp.u16()?;
+        let size = rsp.u32()? as usize;
+        let code = rsp.u32()?;
+        if size != data.len() {
+            return Err(Error::Parse);
+        }
+        if code != 0 {
+            return Err(Error::Tpm(code));
+        }
+        Ok(rsp)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let out = self.data.get(self.pos..self.pos + n).ok_or(Error::Parse)?;
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// TPM2B contents, without the size
+    fn sized(&mut self) -> Result<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+
+    /// TPM2B including its size, as it is passed back in a later command
+    fn sized_raw(&mut self) -> Result<&'a [u8]> {
+        let start = self.pos;
+        self.sized()?;
+        Ok(&self.data[start..self.pos])
+    }
+}
+
+/// Open TPM, through the firmware's TCG2 protocol
+struct Tpm {
+    tcg: boot::ScopedProtocol<Tcg>,
+    response: Vec<u8>,
+}
+
+impl Tpm {
+    fn open() -> Result<Self> {
+        caps::require(Capability::Tcg2)?;
+        let handle = *caps::handles(Capability::Tcg2).first().ok_or(Error::Unsupported)?;
+        let tcg = boot::open_protocol_exclusive::<Tcg>(handle).map_err(|e| Error::Uefi(e.status()))?;
+        Ok(Tpm { tcg, response: alloc::vec![0; BUFFER_SIZE] })
+    }
+
+    /// Send a command and return the response, checked for errors
+    fn submit(&mut self, cmd: Command) -> Result<Response<'_>> {
+        let input = cmd.finish();
+        self.response.fill(0);
+        self.tcg
+            .submit_command(&input, &mut self.response)
+            .map_err(|e| Error::Uefi(e.status()))?;
+
+        let len = self.response.get(2..6).map_or(0, |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+        let data = self.response.get(..len).ok_or(Error::Parse)?;
+        Response::parse(data)
+    }
+
+    fn flush(&mut self, handle: u32) {
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_FLUSH_CONTEXT);
+        cmd.u32(handle);
+        let _ = self.submit(cmd);
+    }
+
+    /// Storage root key under the owner hierarchy
+    ///
+    /// The template is fixed, so the TPM derives the same key from its seed
+    /// every time and nothing about it needs to be stored.
+    fn create_primary(&mut self) -> Result<u32> {
+        let mut cmd = Command::new(TPM_ST_SESSIONS, TPM_CC_CREATE_PRIMARY);
+        cmd.u32(TPM_RH_OWNER).auth(TPM_RS_PW);
+
+        // Sensitive: empty auth value and data
+        cmd.u16(4).u16(0).u16(0);
+
+        // Public: ECC P-256 restricted decryption key with AES-128-CFB
+        let public = cmd.begin_sized();
+        cmd.u16(TPM_ALG_ECC)
+            .u16(TPM_ALG_SHA256)
+            .u32(FIXED_TPM | FIXED_PARENT | SENSITIVE_DATA_ORIGIN | USER_WITH_AUTH | NO_DA | RESTRICTED | DECRYPT)
+            .sized(&[])
+            .u16(TPM_ALG_AES)
+            .u16(128)
+            .u16(TPM_ALG_CFB)
+            .u16(TPM_ALG_NULL)
+            .u16(TPM_ECC_NIST_P256)
+            .u16(TPM_ALG_NULL)
+            .sized(&[])
+            .sized(&[]);
+        cmd.end_sized(public);
+
+        // Outside info and creation PCRs
+        cmd.u16(0).u32(0);
+
+        self.submit(cmd)?.u32()
+    }
+
+    /// Start a policy (or trial) session and bind it to the PCRs
+    fn policy_pcr(&mut self, session_type: u8, pcrs: u32) -> Result<u32> {
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_START_AUTH_SESSION);
+        // Unsalted and unbound: the session only evaluates a policy, it
+        // never carries an HMAC
+        cmd.u32(TPM_RH_NULL)
+            .u32(TPM_RH_NULL)
+            .sized(&[0; 16])
+            .sized(&[])
+            .u8(session_type)
+            .u16(TPM_ALG_NULL)
+            .u16(TPM_ALG_SHA256);
+        let session = self.submit(cmd)?.u32()?;
+
+        // Empty digest: the TPM uses the current PCR values
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_POLICY_PCR);
+        cmd.u32(session).sized(&[]).pcr_selection(pcrs);
+        if let Err(e) = self.submit(cmd) {
+            self.flush(session);
+            return Err(e);
+        }
+        Ok(session)
+    }
+
+    /// Policy digest requiring the current values of these PCRs
+    fn pcr_policy_digest(&mut self, pcrs: u32) -> Result<Vec<u8>> {
+        let session = self.policy_pcr(TPM_SE_TRIAL, pcrs)?;
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_POLICY_GET_DIGEST);
+        cmd.u32(session);
+        let digest = self.submit(cmd).and_then(|mut rsp| rsp.sized().map(Vec::from));
+        self.flush(session);
+        digest
+    }
+}
+
+/// Measure data into a PCR and the TCG event log
+///
+/// The firmware hashes `data` into every active PCR bank and logs an
+/// EV_IPL event carrying `description`.
+pub fn extend(pcr: u32, data: &[u8], description: &str) -> Result<()> {
+    let mut tpm = Tpm::open()?;
+    let mut event_buf = [0u8; 128];
+    let event = PcrEventInputs::new_in_buffer(&mut event_buf, PcrIndex(pcr), EventType::IPL, description.as_bytes())
+        .map_err(|_| Error::BufferTooSmall)?;
+    tpm.tcg
+        .hash_log_extend_event(HashLogExtendEventFlags::empty(), data, event)
+        .map_err(|e| Error::Uefi(e.status()))
+}
+
+/// Bitmask of PCR indices
+pub fn pcr_mask(pcrs: &[u8]) -> Result<u32> {
+    pcrs.iter().try_fold(0u32, |mask, &pcr| {
+        if pcr > MAX_PCR {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(mask | (1 << pcr))
+    })
+}
+
+/// Parse a comma-separated PCR list (`0,2,4,7`) into a bitmask
+pub fn parse_pcrs(list: &str) -> Result<u32> {
+    let mut mask = 0;
+    for item in list.split(',') {
+        let pcr = item.trim().parse::<u8>().map_err(|_| Error::Parse)?;
+        mask |= pcr_mask(&[pcr])?;
+    }
+    Ok(mask)
+}
+
+/// Seal a secret to the current values of the PCRs in `pcrs`
+///
+/// Returns the sealed object (TPM2B_PRIVATE followed by TPM2B_PUBLIC). It
+/// is encrypted by a key that never leaves the TPM, so it may be stored on
+/// the ESP, and it only unseals while the PCRs hold the same values.
+pub fn seal(secret: &[u8], pcrs: u32) -> Result<Vec<u8>> {
+    if secret.is_empty() || secret.len() > MAX_SECRET_LEN || pcrs == 0 {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut tpm = Tpm::open()?;
+    let policy = tpm.pcr_policy_digest(pcrs)?;
+    let primary = tpm.create_primary()?;
+
+    let mut cmd = Command::new(TPM_ST_SESSIONS, TPM_CC_CREATE);
+    cmd.u32(primary).auth(TPM_RS_PW);
+
+    // Sensitive: no auth value, the secret as data
+    let sensitive = cmd.begin_sized();
+    cmd.sized(&[]).sized(secret);
+    cmd.end_sized(sensitive);
+
+    // Public: sealed data object usable only through the PCR policy
+    let public = cmd.begin_sized();
+    cmd.u16(TPM_ALG_KEYEDHASH)
+        .u16(TPM_ALG_SHA256)
+        .u32(FIXED_TPM | FIXED_PARENT)
+        .sized(&policy)
+        .u16(TPM_ALG_NULL)
+        .sized(&[]);
+    cmd.end_sized(public);
+    cmd.u16(0).u32(0);
+
+    let sealed = tpm.submit(cmd).and_then(|mut rsp| {
+        let _parameter_size = rsp.u32()?;
+        let private = rsp.sized_raw()?;
+        let public = rsp.sized_raw()?;
+        let mut sealed = Vec::with_capacity(private.len() + public.len());
+        sealed.extend_from_slice(private);
+        sealed.extend_from_slice(public);
+        Ok(sealed)
+    });
+    tpm.flush(primary);
+    sealed
+}
+
+/// Unseal a secret sealed by `seal` to the same PCRs
+///
+/// Fails with a TPM error (policy check failed) once any of the PCRs
+/// differs from its value at sealing time.
+pub fn unseal(sealed: &[u8], pcrs: u32) -> Result<Vec<u8>> {
+    let mut parts = Response { data: sealed, pos: 0 };
+    let private = parts.sized_raw()?;
+    let public = parts.sized_raw()?;
+    if parts.pos != sealed.len() {
+        return Err(Error::Parse);
+    }
+
+    let mut tpm = Tpm::open()?;
+    let primary = tpm.create_primary()?;
+
+    let mut cmd = Command::new(TPM_ST_SESSIONS, TPM_CC_LOAD);
+    cmd.u32(primary).auth(TPM_RS_PW);
+    cmd.buf.extend_from_slice(private);
+    cmd.buf.extend_from_slice(public);
+    let object = tpm.submit(cmd).and_then(|mut rsp| rsp.u32());
+    tpm.flush(primary);
+    let object = object?;
+
+    let secret = tpm.policy_pcr(TPM_SE_POLICY, pcrs).and_then(|session| {
+        // The session ends with the command (continueSession is clear)
+        let mut cmd = Command::new(TPM_ST_SESSIONS, TPM_CC_UNSEAL);
+        cmd.u32(object).auth(session);
+        let secret = tpm.submit(cmd).and_then(|mut rsp| {
+            let _parameter_size = rsp.u32()?;
+            rsp.sized().map(Vec::from)
+        });
+        if secret.is_err() {
+            tpm.flush(session);
+        }
+        secret
+    });
+    tpm.flush(object);
+    secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_marshalling() {
+        let mut cmd = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_POLICY_PCR);
+        cmd.u32(0x0300_0000).sized(&[]).pcr_selection(pcr_mask(&[0, 7, 16]).unwrap());
+        let bytes = cmd.finish();
+        assert_eq!(
+            bytes,
+            [
+                0x80, 0x01, 0x00, 0x00, 0x00, 0x1a, 0x00, 0x00, 0x01, 0x7f, // header
+                0x03, 0x00, 0x00, 0x00, // session
+                0x00, 0x00, // empty digest
+                0x00, 0x00, 0x00, 0x01, 0x00, 0x0b, 0x03, 0x81, 0x00, 0x01, // PCRs 0, 7, 16
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_size() {
+        let mut cmd = Command::new(TPM_ST_SESSIONS, TPM_CC_CREATE);
+        let start = cmd.begin_sized();
+        cmd.sized(&[]).sized(b"abc");
+        cmd.end_sized(start);
+        assert_eq!(&cmd.finish()[10..], &[0x00, 0x07, 0x00, 0x00, 0x00, 0x03, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn test_response_errors() {
+        let ok = [0x80, 0x01, 0x00, 0x00, 0x00, 0x0e, 0, 0, 0, 0, 0x80, 0x00, 0x00, 0x01];
+        assert_eq!(Response::parse(&ok).unwrap().u32().unwrap(), 0x8000_0001);
+
+        let policy_fail = [0x80, 0x01, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x09, 0x9d];
+        assert_eq!(Response::parse(&policy_fail).err(), Some(Error::Tpm(0x099d)));
+        assert!(Response::parse(&ok[..12]).is_err());
+    }
+
+    #[test]
+    fn test_pcr_mask() {
+        assert_eq!(pcr_mask(&[0, 2, 4, 7]).unwrap(), 0x95);
+        assert!(pcr_mask(&[24]).is_err());
+        assert_eq!(parse_pcrs("0, 2,4,7").unwrap(), 0x95);
+        assert!(parse_pcrs("0,,7").is_err());
+        assert!(parse_pcrs("31").is_err());
+    }
+}
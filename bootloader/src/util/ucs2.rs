@@ -0,0 +1,139 @@
+use crate::util::{Error, Result};
+use alloc::string::String;
+use alloc::vec::Vec;
+use uefi::{CStr16, CString16};
+
+/// The UCS-2 unit of `c`
+///
+/// UCS-2 is UTF-16 without surrogate pairs: characters past U+FFFF have no
+/// unit of their own, and NUL would end a firmware string early, so both
+/// are refused rather than turned into something the firmware reads
+/// differently.
+pub fn unit(c: char) -> Result<u16> {
+    match u32::from(c) {
+        0 => Err(Error::InvalidArgument),
+        // Rust chars are never surrogates, so this is the character itself
+        code @ 1..=0xFFFF => Ok(code as u16),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+/// Whether all of `text` can be handed to the firmware
+pub fn is_representable(text: &str) -> bool {
+    text.chars().all(|c| unit(c).is_ok())
+}
+
+/// Length of `text` in UTF-16 units, as FAT and the firmware count names
+///
+/// Characters past U+FFFF take two, though only FAT stores them.
+pub fn len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// UCS-2 units of `text`, without a terminating NUL
+pub fn encode(text: &str) -> Result<Vec<u16>> {
+    text.chars().map(unit).collect()
+}
+
+/// UCS-2 units of `text` with the NUL firmware strings end in
+pub fn encode_with_nul(text: &str) -> Result<Vec<u16>> {
+    let mut units = encode(text)?;
+    units.push(0);
+    Ok(units)
+}
+
+/// `text` as a firmware string
+pub fn to_cstring16(text: &str) -> Result<CString16> {
+    let units = encode_with_nul(text)?;
+    CStr16::from_u16_with_nul(&units).map(CString16::from).map_err(|_| Error::InvalidArgument)
+}
+
+/// Text of UCS-2 `units`, up to the first NUL
+///
+/// Surrogate pairs, which some firmware and loaders write although the
+/// spec says UCS-2, decode to the character they stand for; a lone
+/// surrogate is not text.
+pub fn decode(units: &[u16]) -> Result<String> {
+    let end = units.iter().position(|&unit| unit == 0).unwrap_or(units.len());
+    char::decode_utf16(units[..end].iter().copied()).collect::<core::result::Result<_, _>>().map_err(|_| Error::Parse)
+}
+
+/// Text of little-endian UCS-2 `bytes` (e.g. LoadOptions), up to the
+/// first NUL
+pub fn decode_le_bytes(bytes: &[u8]) -> Result<String> {
+    if bytes.len() % 2 != 0 {
+        return Err(Error::Parse);
+    }
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+    decode(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Characters from every range that matters: ASCII, Latin-1, the rest
+    /// of the BMP up to and past the surrogates, and beyond the BMP
+    fn samples() -> impl Iterator<Item = char> {
+        (0..=0x11_0000u32)
+            .step_by(97)
+            .chain([0x7f, 0xd7ff, 0xe000, 0xfffd, 0xffff, 0x1_0000, 0x10_ffff])
+            .filter_map(char::from_u32)
+    }
+
+    /// A deterministic string of `len` sample characters, starting at `seed`
+    fn text(seed: usize, len: usize) -> String {
+        let chars: Vec<char> = samples().collect();
+        (0..len).map(|i| chars[(seed * 7919 + i * 104_729) % chars.len()]).collect()
+    }
+
+    #[test]
+    fn test_unit_matches_utf16() {
+        for c in samples() {
+            let mut buf = [0u16; 2];
+            let utf16 = c.encode_utf16(&mut buf);
+            match unit(c) {
+                Ok(unit) => assert_eq!(utf16, [unit], "{:?}", c),
+                Err(_) => assert!(c == '\0' || utf16.len() == 2, "{:?}", c),
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for seed in 0..200 {
+            let text = text(seed, seed % 23);
+            match encode_with_nul(&text) {
+                Ok(units) => {
+                    assert_eq!(units.len(), text.chars().count() + 1);
+                    assert_eq!(units.len() - 1, len(&text));
+                    assert_eq!(decode(&units).unwrap(), text);
+                    let bytes: Vec<u8> = units.iter().flat_map(|unit| unit.to_le_bytes()).collect();
+                    assert_eq!(decode_le_bytes(&bytes).unwrap(), text);
+                }
+                Err(_) => assert!(!is_representable(&text)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unrepresentable() {
+        assert_eq!(encode("image-\u{1F680}.efi"), Err(Error::InvalidArgument));
+        assert_eq!(encode("a\0b"), Err(Error::InvalidArgument));
+        assert_eq!(len("\u{1F680}"), 2);
+        assert_eq!(encode("Entrée ünïcode").unwrap().len(), 14);
+        assert!(is_representable("\\EFI\\uefipxe\\config.txt"));
+    }
+
+    #[test]
+    fn test_decode() {
+        // Stops at the first NUL; pairs decode, lone surrogates do not
+        assert_eq!(decode(&[0x41, 0, 0x42]).unwrap(), "A");
+        assert_eq!(decode(&[0xd83d, 0xde80]).unwrap(), "\u{1F680}");
+        assert_eq!(decode(&[0xd800, 0x61, 0]), Err(Error::Parse));
+        assert_eq!(decode(&[0xdc00]), Err(Error::Parse));
+        assert_eq!(decode_le_bytes(&[b'a', 0, b'b']), Err(Error::Parse));
+        assert_eq!(decode_le_bytes(&[0, 0]).unwrap(), "");
+        assert_eq!(decode_le_bytes(&[]).unwrap(), "");
+    }
+}
@@ -0,0 +1,156 @@
+use crate::util::logger;
+use crate::util::splash;
+use crate::util::{Error, Result};
+use uefi::{print, println};
+
+/// Console output style
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UiMode {
+    /// Underlined headings, in-place progress and backspace erasing
+    #[default]
+    Fancy,
+    /// Append-only text for serial capture and screen readers
+    Plain,
+}
+
+impl UiMode {
+    /// Parse a mode name as used in config.txt and `set ui`
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "fancy" | "default" => Ok(UiMode::Fancy),
+            "plain" => Ok(UiMode::Plain),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    /// Mode name as written in config.txt
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UiMode::Fancy => "fancy",
+            UiMode::Plain => "plain",
+        }
+    }
+}
+
+/// Current output style
+static mut MODE: UiMode = UiMode::Fancy;
+
+/// Fancy mode redraws the progress line at most once per this many bytes
+const FANCY_PROGRESS_STEP: usize = 64 * 1024;
+
+/// Plain mode prints a progress line at most once per this many bytes
+const PLAIN_PROGRESS_STEP: usize = 1024 * 1024;
+
+/// Select the output style
+pub fn set_mode(mode: UiMode) {
+    unsafe {
+        MODE = mode;
+    }
+}
+
+/// Current output style
+pub fn mode() -> UiMode {
+    unsafe { MODE }
+}
+
+/// Whether append-only output is requested
+pub fn is_plain() -> bool {
+    mode() == UiMode::Plain
+}
+
+/// Print a section heading, underlined unless in plain mode
+pub fn heading(title: &str) {
+    println!("{}", title);
+    if !is_plain() {
+        for _ in title.chars() {
+            print!("=");
+        }
+        println!();
+    }
+}
+
+/// Print a prominent one-line banner
+pub fn banner(text: &str) {
+    if is_plain() {
+        println!("{}", text);
+    } else {
+        println!("===========================================");
+        println!("{}", text);
+        println!("===========================================");
+    }
+}
+
+/// Report transfer progress
+///
+/// `previous` is the count at the last call, `total` the size when it is
+/// known, shown as a percentage. Output depends only on how many bytes went
+/// by, not on how many calls it took: fancy mode rewrites a single line
+/// every 64 KiB, plain mode appends a line every MiB. Nothing is shown with
+/// `echo=quiet`, though the splash screen's bar still moves.
+pub fn progress(label: &str, bytes: usize, previous: usize, total: Option<usize>) {
+    if bytes / FANCY_PROGRESS_STEP != previous / FANCY_PROGRESS_STEP {
+        splash::update(bytes);
+    }
+    if !logger::echoes(log::Level::Info) {
+        return;
+    }
+    if is_plain() {
+        if bytes / PLAIN_PROGRESS_STEP != previous / PLAIN_PROGRESS_STEP {
+            println!("  {}: {}", label, Amount { bytes, total });
+        }
+    } else if bytes / FANCY_PROGRESS_STEP != previous / FANCY_PROGRESS_STEP {
+        print!("\r  {}: {}", label, Amount { bytes, total });
+    }
+}
+
+/// Bytes transferred, as "<n> bytes" or "<n> of <total> bytes (<p>%)"
+struct Amount {
+    bytes: usize,
+    total: Option<usize>,
+}
+
+impl core::fmt::Display for Amount {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.total {
+            Some(total) if total > 0 => {
+                write!(f, "{} of {} bytes ({}%)", self.bytes, total, percent(self.bytes, total))
+            }
+            _ => write!(f, "{} bytes", self.bytes),
+        }
+    }
+}
+
+/// Share of `total` that `bytes` is, rounded down (and at most 100)
+fn percent(bytes: usize, total: usize) -> u64 {
+    (bytes.min(total) as u64 * 100) / total as u64
+}
+
+/// Finish a progress line started with `progress`
+pub fn progress_done() {
+    if !is_plain() && logger::echoes(log::Level::Info) {
+        println!();
+    }
+}
+
+/// Echo the removal of the last typed character
+///
+/// Plain mode never moves the cursor backwards, so nothing is printed;
+/// the corrected line is echoed once Enter is pressed.
+pub fn erase_char() {
+    if !is_plain() {
+        print!("\x08 \x08");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount() {
+        assert_eq!(alloc::format!("{}", Amount { bytes: 512, total: None }), "512 bytes");
+        assert_eq!(alloc::format!("{}", Amount { bytes: 250, total: Some(1000) }), "250 of 1000 bytes (25%)");
+        assert_eq!(alloc::format!("{}", Amount { bytes: 999, total: Some(1000) }), "999 of 1000 bytes (99%)");
+        assert_eq!(alloc::format!("{}", Amount { bytes: 7, total: Some(0) }), "7 bytes");
+    }
+}
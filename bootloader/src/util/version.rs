@@ -0,0 +1,19 @@
+// Build metadata, embedded by build.rs
+
+/// Crate version from Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git commit, with `-dirty` if the tree had local changes
+pub const COMMIT: &str = env!("UEFIPXE_GIT_COMMIT");
+
+/// When the build ran (or `SOURCE_DATE_EPOCH`), in UTC
+pub const BUILD_DATE: &str = env!("UEFIPXE_BUILD_DATE");
+
+/// Enabled cargo features, comma separated, or `none`
+pub const FEATURES: &str = env!("UEFIPXE_FEATURES");
+
+/// Target triple the image was built for
+pub const TARGET: &str = env!("UEFIPXE_TARGET");
+
+/// Cargo profile (`release` or `debug`)
+pub const PROFILE: &str = env!("UEFIPXE_PROFILE");
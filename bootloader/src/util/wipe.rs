@@ -0,0 +1,178 @@
+use crate::util::{Error, Result};
+use alloc::vec::Vec;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Overwrite a buffer with zeros
+///
+/// The stores are volatile, so they are not removed as dead even when the
+/// buffer is freed right after.
+pub fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Zero a vector's whole allocation, spare capacity included, and empty it
+pub fn wipe_vec(data: &mut Vec<u8>) {
+    let base = data.as_mut_ptr();
+    for i in 0..data.capacity() {
+        unsafe { ptr::write_volatile(base.add(i), 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+    data.clear();
+}
+
+/// Byte buffer that is zeroed when dropped
+///
+/// Image data and credentials live in one of these until they are handed
+/// on, so every early return (failed verification, aborted transfer) wipes
+/// them and nothing is left in memory the next image is given.
+///
+/// Only the methods here change its length. They move the bytes to a
+/// larger allocation themselves and wipe the one they leave, which a `Vec`
+/// growing in place of them would free as it is.
+#[derive(Default)]
+pub struct Wiped(Vec<u8>);
+
+impl Wiped {
+    pub fn new(data: Vec<u8>) -> Self {
+        Wiped(data)
+    }
+
+    /// An empty buffer with room for `capacity` bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Wiped(Vec::with_capacity(capacity))
+    }
+
+    /// Take the buffer out without wiping it
+    pub fn into_inner(self) -> Vec<u8> {
+        let mut this = ManuallyDrop::new(self);
+        core::mem::take(&mut this.0)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    /// Make room for `additional` more bytes, at least doubling the
+    /// allocation when it has to move
+    pub fn try_reserve(&mut self, additional: usize) -> Result<()> {
+        let needed = self.0.len().checked_add(additional).ok_or(Error::OutOfMemory)?;
+        if needed <= self.0.capacity() {
+            return Ok(());
+        }
+        self.move_to(needed.max(self.0.capacity().saturating_mul(2)))
+    }
+
+    /// Make room for exactly `additional` more bytes
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<()> {
+        let needed = self.0.len().checked_add(additional).ok_or(Error::OutOfMemory)?;
+        if needed <= self.0.capacity() {
+            return Ok(());
+        }
+        self.move_to(needed)
+    }
+
+    /// Copy the bytes into an allocation of `capacity` and wipe the old one
+    fn move_to(&mut self, capacity: usize) -> Result<()> {
+        let mut moved = Vec::new();
+        moved.try_reserve_exact(capacity).map_err(|_| Error::OutOfMemory)?;
+        moved.extend_from_slice(&self.0);
+        wipe_vec(&mut self.0);
+        self.0 = moved;
+        Ok(())
+    }
+
+    /// Append bytes (panics when memory runs out, like `Vec`)
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.try_reserve(bytes.len()).expect("out of memory");
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn push(&mut self, byte: u8) {
+        self.extend_from_slice(&[byte]);
+    }
+
+    /// Grow to `len` with copies of `value`, or shorten to it
+    pub fn resize(&mut self, len: usize, value: u8) {
+        if len <= self.0.len() {
+            return self.truncate(len);
+        }
+        self.try_reserve_exact(len - self.0.len()).expect("out of memory");
+        self.0.resize(len, value);
+    }
+
+    /// Shorten to `len`, wiping the bytes cut off
+    pub fn truncate(&mut self, len: usize) {
+        if let Some(cut) = self.0.get_mut(len..) {
+            wipe(cut);
+        }
+        self.0.truncate(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl Deref for Wiped {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for Wiped {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl Drop for Wiped {
+    fn drop(&mut self) {
+        wipe_vec(&mut self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wipe_vec() {
+        let mut data = Vec::with_capacity(16);
+        data.extend_from_slice(b"secret");
+        wipe_vec(&mut data);
+        assert!(data.is_empty());
+        assert_eq!(data.capacity(), 16);
+        unsafe { data.set_len(6) };
+        assert_eq!(data.as_slice(), &[0; 6]);
+    }
+
+    #[test]
+    fn test_growth_moves_data() {
+        let mut wiped = Wiped::with_capacity(4);
+        wiped.extend_from_slice(b"abcd");
+        wiped.push(b'e');
+        assert!(wiped.capacity() >= 8);
+        assert_eq!(&wiped[..], b"abcde");
+
+        wiped.resize(7, b'!');
+        assert_eq!(&wiped[..], b"abcde!!");
+        wiped.truncate(2);
+        assert_eq!(&wiped[..], b"ab");
+        assert!(wiped.try_reserve(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_into_inner_keeps_data() {
+        let wiped = Wiped::new(Vec::from(&b"image"[..]));
+        assert_eq!(wiped.len(), 5);
+        assert_eq!(wiped.into_inner(), b"image");
+    }
+}
@@ -0,0 +1,30 @@
+#!/bin/bash
+set -e
+
+# Hash a CLI password for admin_password= or operator_password=
+#
+# Usage: hash-password.sh [iterations]
+#
+# Reads the password from stdin (or asks for it without echoing) and prints
+# pbkdf2-sha256$<iterations>$<salt hex>$<hash hex> with a fresh 16-byte salt.
+# Iterations must be between 10000 and 1000000; each one costs a little
+# time at the password prompt.
+
+ITERATIONS="${1:-100000}"
+
+if [ -t 0 ]; then
+    read -r -s -p "Password: " PASSWORD
+    echo >&2
+else
+    PASSWORD=$(cat)
+fi
+
+PASSWORD="$PASSWORD" python3 - "$ITERATIONS" <<'PY'
+import hashlib, os, sys
+iterations = int(sys.argv[1])
+if not 10000 <= iterations <= 1000000:
+    sys.exit("iterations must be between 10000 and 1000000")
+salt = os.urandom(16)
+digest = hashlib.pbkdf2_hmac("sha256", os.environ["PASSWORD"].encode(), salt, iterations)
+print("pbkdf2-sha256$%d$%s$%s" % (iterations, salt.hex(), digest.hex()))
+PY
@@ -0,0 +1,33 @@
+#!/bin/bash
+set -e
+
+# Sign a uefipxe configuration with an Ed25519 key
+#
+# Usage: sign-config.sh <key.pem> <config.txt> [key name]
+#
+# Appends an inline signature line to the file. The key name must match a
+# key= line in policy.txt; the public key for that line is printed by
+#
+#   openssl pkey -in key.pem -pubout -outform DER | tail -c 32 | od -An -tx1 | tr -d ' \n'
+#
+# Create a key with: openssl genpkey -algorithm ed25519 -out key.pem
+
+KEY="$1"
+CONFIG="$2"
+NAME="$3"
+
+if [ -z "$KEY" ] || [ -z "$CONFIG" ]; then
+    echo "Usage: $0 <key.pem> <config.txt> [key name]"
+    exit 1
+fi
+
+# Drop an existing signature so the file can be re-signed after edits
+BODY=$(mktemp)
+trap 'rm -f "$BODY"' EXIT
+grep -v '^# ed25519-signature:' "$CONFIG" > "$BODY" || true
+
+SIG=$(openssl pkeyutl -sign -rawin -inkey "$KEY" -in "$BODY" | od -An -tx1 | tr -d ' \n')
+
+cp "$BODY" "$CONFIG"
+echo "# ed25519-signature: ${NAME:+$NAME }$SIG" >> "$CONFIG"
+echo "Signed $CONFIG${NAME:+ with key '$NAME'}"
@@ -0,0 +1,49 @@
+#!/bin/bash
+set -e
+
+# Confirm a successful boot to uefipxe
+#
+# uefipxe sets the BootInProgress variable right before starting an image.
+# Deleting it tells uefipxe (on its next start) that the entry booted fine
+# and can be marked last-known-good. If it is left in place, the entry is
+# marked failed and `boot` without an index falls back to another entry.
+#
+# Run this late in boot, once the system is known to be healthy, e.g. from
+# the uefipxe-boot-ok.service unit shipped next to this script.
+
+# Colors for output
+RED='\033[0;31m'
+GREEN='\033[0;32m'
+YELLOW='\033[1;33m'
+NC='\033[0m' # No Color
+
+VENDOR_GUID="dfc613b2-b549-42f4-af57-86f8e24a1795"
+EFIVARS="/sys/firmware/efi/efivars"
+MARKER="$EFIVARS/BootInProgress-$VENDOR_GUID"
+
+# Check that efivarfs is available
+if [ ! -d "$EFIVARS" ]; then
+    echo -e "${RED}Error: $EFIVARS not found (not booted via UEFI or efivarfs not mounted)${NC}"
+    exit 1
+fi
+
+# --status only reports whether a confirmation is pending
+if [ "$1" = "--status" ]; then
+    if [ -e "$MARKER" ]; then
+        echo "Boot confirmation pending"
+    else
+        echo "No boot confirmation pending"
+    fi
+    exit 0
+fi
+
+if [ ! -e "$MARKER" ]; then
+    echo -e "${YELLOW}No boot confirmation pending${NC}"
+    exit 0
+fi
+
+# efivarfs marks non-standard variables immutable
+chattr -i "$MARKER" 2>/dev/null || true
+rm "$MARKER"
+
+echo -e "${GREEN}Boot confirmed to uefipxe${NC}"